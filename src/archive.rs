@@ -0,0 +1,450 @@
+//! Self-contained copies of web pages, for `ozy add --url --archive`.
+//!
+//! Stylesheets, images and icons, and the fonts and images stylesheets
+//! use, are fetched and inlined as `data:` URLs, so that the page shows
+//! offline as it appeared. Scripts are dropped, with event handler
+//! attributes and `javascript:` URLs, and so are frames, plugins and
+//! `<meta http-equiv="refresh">`: they would fetch more or lead away, and
+//! the copy is of the page as served. Links are made absolute so that they still
+//! lead somewhere.
+
+use std::collections::HashMap;
+
+use base64::Engine;
+
+use crate::attachments;
+use crate::parser::remove_elements;
+use crate::web::{resolve, Client};
+
+/// How deeply stylesheets importing stylesheets are followed.
+const MAX_IMPORT_DEPTH: usize = 3;
+
+/// Fetches what a page needs, through the shared client, remembering each
+/// resource so that it is fetched once.
+struct Archiver<'a> {
+    client: &'a Client,
+    /// `data:` URLs by the URL they were fetched from; `None` for those
+    /// that could not be.
+    fetched: HashMap<String, Option<String>>,
+}
+
+/// The page `html`, fetched from `url`, with what it needs inlined.
+/// Resources that cannot be fetched are left linked, with a warning.
+pub async fn archive(client: &Client, url: &str, html: &str) -> String {
+    let mut archiver = Archiver {
+        client,
+        fetched: HashMap::new(),
+    };
+    let mut html = html.to_string();
+    for element in ["script", "iframe", "object"] {
+        html = remove_elements(&html, element);
+    }
+    let base = base_href(&html)
+        .map(|href| resolve(url, &href))
+        .unwrap_or_else(|| url.to_string());
+    archiver.page(&html, &base).await
+}
+
+impl Archiver<'_> {
+    async fn page(&mut self, html: &str, base: &str) -> String {
+        let mut out = String::with_capacity(html.len());
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            // Comments are dropped; quotes in them mean nothing.
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+            // A `<` that starts no tag, as in `a < b`, is text.
+            let starts_tag =
+                rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+            let end = tag_end(rest).filter(|_| starts_tag);
+            let Some(end) = end else {
+                out.push('<');
+                rest = &rest[1..];
+                continue;
+            };
+            let tag = &rest[..end];
+            rest = &rest[end..];
+            let Some(mut parsed) = Tag::parse(tag) else {
+                out.push_str(tag);
+                continue;
+            };
+            parsed.attributes.retain(|(key, value)| {
+                !key.starts_with("on") && !value.as_deref().is_some_and(is_script_url)
+            });
+            if parsed.name == "style" {
+                // The stylesheet runs up to the closing tag.
+                let close = rest
+                    .to_ascii_lowercase()
+                    .find("</style")
+                    .unwrap_or(rest.len());
+                out.push_str(&parsed.render());
+                out.push_str(&self.css(&rest[..close], base, 0).await);
+                rest = &rest[close..];
+                continue;
+            }
+            match self.tag(&mut parsed, base).await {
+                Some(replaced) => out.push_str(&replaced),
+                None => out.push_str(&parsed.render()),
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// A tag with what it refers to inlined, or what replaces it.
+    async fn tag(&mut self, tag: &mut Tag, base: &str) -> Option<String> {
+        let rel = tag.get("rel").unwrap_or_default().to_ascii_lowercase();
+        match tag.name.as_str() {
+            "link" if rel.split_whitespace().any(|r| r == "stylesheet") => {
+                let href = resolve(base, &tag.get("href")?);
+                let css = self.text(&href).await?;
+                let media = tag
+                    .get("media")
+                    .map(|m| format!(" media=\"{}\"", escape(&m)))
+                    .unwrap_or_default();
+                return Some(format!(
+                    "<style{media}>{}</style>",
+                    self.css(&css, &href, 0).await
+                ));
+            }
+            "link" if rel.split_whitespace().any(|r| r.contains("icon")) => {
+                self.inline(tag, "href", base).await;
+            }
+            "img" | "source" | "input" | "video" | "audio" | "track" => {
+                self.inline(tag, "src", base).await;
+                if tag.name == "video" {
+                    self.inline(tag, "poster", base).await;
+                }
+                // Other sizes would be fetched from the web.
+                tag.remove("srcset");
+                tag.remove("sizes");
+            }
+            "a" | "area" | "form" => {
+                let attribute = if tag.name == "form" { "action" } else { "href" };
+                if let Some(href) = tag.get(attribute).filter(|h| !h.starts_with('#')) {
+                    tag.set(attribute, resolve(base, &href));
+                }
+            }
+            "base" | "embed" | "frame" => return Some(String::new()),
+            "meta"
+                if tag
+                    .get("http-equiv")
+                    .is_some_and(|e| e.eq_ignore_ascii_case("refresh")) =>
+            {
+                return Some(String::new());
+            }
+            _ => {}
+        }
+        if let Some(style) = tag.get("style") {
+            let style = self.css(&style, base, MAX_IMPORT_DEPTH).await;
+            tag.set("style", style);
+        }
+        None
+    }
+
+    /// Replaces the URL in `attribute` of `tag` by a `data:` URL.
+    async fn inline(&mut self, tag: &mut Tag, attribute: &str, base: &str) {
+        let Some(src) = tag.get(attribute) else {
+            return;
+        };
+        if src.starts_with("data:") {
+            return;
+        }
+        // What cannot be inlined is at least linked to where it is.
+        let url = resolve(base, &src);
+        let value = self.data(&url).await.unwrap_or(url);
+        tag.set(attribute, value);
+    }
+
+    /// A stylesheet fetched from `base`, with the stylesheets it imports
+    /// and the resources it uses inlined.
+    async fn css(&mut self, css: &str, base: &str, depth: usize) -> String {
+        let lower = css.to_ascii_lowercase();
+        let mut out = String::with_capacity(css.len());
+        let mut done = 0;
+        loop {
+            let next = [lower[done..].find("@import"), lower[done..].find("url(")]
+                .into_iter()
+                .flatten()
+                .min();
+            let Some(at) = next.map(|at| done + at) else {
+                break;
+            };
+            out.push_str(&css[done..at]);
+            if lower[at..].starts_with("@import") {
+                let end = css[at..].find(';').map_or(css.len(), |e| at + e + 1);
+                let statement = &css[at..end];
+                done = end;
+                let href = css_target(&statement["@import".len()..])
+                    .filter(|_| depth < MAX_IMPORT_DEPTH)
+                    .map(|t| resolve(base, &t));
+                let imported = match href {
+                    Some(href) => self.text(&href).await.map(|text| (text, href)),
+                    None => None,
+                };
+                match imported {
+                    // Boxed, as a stylesheet is archived like the one
+                    // importing it.
+                    Some((text, href)) => {
+                        out.push_str(&Box::pin(self.css(&text, &href, depth + 1)).await)
+                    }
+                    None => out.push_str(statement),
+                }
+                continue;
+            }
+            let end = css[at..].find(')').map_or(css.len(), |e| at + e + 1);
+            let reference = &css[at..end];
+            done = end;
+            let data = match css_target(reference).filter(|t| !t.starts_with("data:")) {
+                Some(target) => self.data(&resolve(base, &target)).await,
+                None => None,
+            };
+            match data {
+                Some(data) => out.push_str(&format!("url(\"{data}\")")),
+                None => out.push_str(reference),
+            }
+        }
+        out.push_str(&css[done..]);
+        out
+    }
+
+    /// The resource at `url` as a `data:` URL.
+    async fn data(&mut self, url: &str) -> Option<String> {
+        if let Some(known) = self.fetched.get(url) {
+            return known.clone();
+        }
+        let fetched = self.fetch(url).await.map(|(bytes, mime)| {
+            let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+            format!("data:{mime};base64,{encoded}")
+        });
+        self.fetched.insert(url.to_string(), fetched.clone());
+        fetched
+    }
+
+    /// The text of the resource at `url`, such as a stylesheet.
+    async fn text(&mut self, url: &str) -> Option<String> {
+        let (bytes, _) = self.fetch(url).await?;
+        Some(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    async fn fetch(&self, url: &str) -> Option<(Vec<u8>, String)> {
+        let result = async {
+            let response = self.client.get(url).await?;
+            let mime = response
+                .headers()
+                .get("content-type")
+                .and_then(|t| t.to_str().ok())
+                .map(|t| t.split(';').next().unwrap_or_default().trim().to_string())
+                .filter(|t| !t.is_empty())
+                .or_else(|| attachments::mime(url.split(['?', '#']).next().unwrap_or(url)))
+                .unwrap_or_else(|| "application/octet-stream".into());
+            anyhow::Ok((response.bytes().await?.to_vec(), mime))
+        }
+        .await;
+        match result {
+            Ok(fetched) => Some(fetched),
+            Err(e) => {
+                tracing::warn!("not archiving {url}: {e:#}");
+                None
+            }
+        }
+    }
+}
+
+/// The URL in `url(…)` or a quoted string, as CSS writes references.
+fn css_target(s: &str) -> Option<String> {
+    let s = s.trim().trim_end_matches(';').trim();
+    let s = match s.get(..4) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url(") => {
+            s[4..].split(')').next().unwrap_or_default()
+        }
+        _ => s.split_whitespace().next().unwrap_or_default(),
+    };
+    let target = s.trim().trim_matches(['"', '\'']).trim();
+    (!target.is_empty()).then(|| target.to_string())
+}
+
+/// Where a tag starting `html` ends, past its `>`, minding quotes.
+fn tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html.char_indices().skip(1) {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// An opening tag and its attributes, in order.
+struct Tag {
+    name: String,
+    attributes: Vec<(String, Option<String>)>,
+    self_closing: bool,
+}
+
+impl Tag {
+    /// Parses `<name attr="value" …>`; closing tags, comments and
+    /// doctypes are not parsed.
+    fn parse(tag: &str) -> Option<Self> {
+        let inner = tag.strip_prefix('<')?.strip_suffix('>')?;
+        if !inner.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let (inner, self_closing) = match inner.strip_suffix('/') {
+            Some(inner) => (inner, true),
+            None => (inner, false),
+        };
+        let name_end = inner
+            .find(|c: char| c.is_whitespace())
+            .unwrap_or(inner.len());
+        let name = inner[..name_end].to_ascii_lowercase();
+        let mut attributes = Vec::new();
+        let mut rest = inner[name_end..].trim_start();
+        while !rest.is_empty() {
+            let key_end = rest
+                .find(|c: char| c.is_whitespace() || c == '=')
+                .unwrap_or(rest.len());
+            let key = rest[..key_end].to_ascii_lowercase();
+            rest = rest[key_end..].trim_start();
+            let value = match rest.strip_prefix('=') {
+                Some(after) => {
+                    let after = after.trim_start();
+                    let (value, remaining) = match after.chars().next() {
+                        Some(q @ ('"' | '\'')) => {
+                            let end = after[1..].find(q).map_or(after.len(), |e| e + 1);
+                            (&after[1..end], after.get(end + 1..).unwrap_or_default())
+                        }
+                        _ => {
+                            let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                            (&after[..end], &after[end..])
+                        }
+                    };
+                    rest = remaining.trim_start();
+                    Some(unescape(value))
+                }
+                None => None,
+            };
+            if !key.is_empty() {
+                attributes.push((key, value));
+            }
+        }
+        Some(Tag {
+            name,
+            attributes,
+            self_closing,
+        })
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, v)| v.clone())
+    }
+
+    fn set(&mut self, key: &str, value: String) {
+        match self.attributes.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = Some(value),
+            None => self.attributes.push((key.to_string(), Some(value))),
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.attributes.retain(|(k, _)| k != key);
+    }
+
+    fn render(&self) -> String {
+        let mut out = format!("<{}", self.name);
+        for (key, value) in &self.attributes {
+            match value {
+                Some(value) => out.push_str(&format!(" {key}=\"{}\"", escape(value))),
+                None => out.push_str(&format!(" {key}")),
+            }
+        }
+        out.push_str(if self.self_closing { " />" } else { ">" });
+        out
+    }
+}
+
+/// The `href` of a page's `<base>`, against which its links resolve.
+fn base_href(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<base ")?;
+    let end = start + tag_end(&html[start..])?;
+    Tag::parse(&html[start..end])?.get("href")
+}
+
+/// Whether a URL runs a script when followed, as `javascript:` ones do.
+/// Browsers ignore whitespace and control characters in the scheme.
+fn is_script_url(url: &str) -> bool {
+    let scheme = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .take("javascript:".len())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    scheme.starts_with("javascript:") || scheme.starts_with("vbscript:")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime;
+    use crate::web::WebConfig;
+
+    fn archived(html: &str) -> String {
+        let client = Client::new(&WebConfig::default());
+        runtime::block_on(archive(&client, "https://example.com/a/", html))
+    }
+
+    #[test]
+    fn scripts_and_what_runs_them_are_dropped() {
+        let page = archived(
+            "<p onclick=\"steal()\" ONLOAD='x()' class=\"lead\">Hi</p>\
+             <script src=\"t.js\"></script><SCRIPT>alert(1)</SCRIPT>\
+             <a href=\"javascript:go()\">go</a><a href=\"../b\">b</a>",
+        );
+        assert!(!page.to_ascii_lowercase().contains("script"), "{page}");
+        assert!(!page.contains("onclick") && !page.to_ascii_lowercase().contains("onload"));
+        assert!(page.contains(r#"<p class="lead">Hi</p>"#), "{page}");
+        assert!(page.contains(r#"<a>go</a>"#), "{page}");
+        assert!(
+            page.contains(r#"<a href="https://example.com/b">b</a>"#),
+            "{page}"
+        );
+    }
+
+    #[test]
+    fn frames_plugins_and_refreshes_are_dropped() {
+        let page = archived(
+            "<meta charset=\"utf-8\"><meta http-equiv=\"Refresh\" content=\"0; url=https://elsewhere.example\">\
+             <iframe src=\"https://ads.example\">no frames</iframe>\
+             <object data=\"movie.swf\"><param name=\"q\" value=\"1\">fallback</object>\
+             <embed src=\"movie.swf\" type=\"application/x-shockwave-flash\"><p>kept</p>",
+        );
+        assert_eq!(page, r#"<meta charset="utf-8"><p>kept</p>"#);
+    }
+}
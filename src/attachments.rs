@@ -0,0 +1,149 @@
+//! Files a document owns, such as images, datasets and recordings, kept
+//! in the blob store and referred to from content as `blob:<hash>`.
+//!
+//! Obsidian and Logseq exports write them under `assets/` and list them
+//! in the document's frontmatter or properties as `attachments`, which
+//! `ozy add` reads back, so that they survive a round trip.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::export::split_frontmatter;
+use crate::storage::BlobStore;
+use crate::types::{Attachment, Document};
+
+/// Frontmatter key, or Logseq property, listing a document's attachments.
+pub const KEY: &str = "attachments";
+/// Directory exports write attachments to.
+const ASSETS_DIR: &str = "assets";
+
+/// The media type of a file, by its extension.
+pub fn mime(name: &str) -> Option<String> {
+    let (_, extension) = name.rsplit_once('.')?;
+    Some(
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "svg" => "image/svg+xml",
+            "webp" => "image/webp",
+            "pdf" => "application/pdf",
+            "csv" => "text/csv",
+            "json" => "application/json",
+            "txt" => "text/plain",
+            "mp3" => "audio/mpeg",
+            "m4a" => "audio/mp4",
+            "ogg" => "audio/ogg",
+            "wav" => "audio/wav",
+            "flac" => "audio/flac",
+            "mp4" => "video/mp4",
+            "webm" => "video/webm",
+            "zip" => "application/zip",
+            _ => return None,
+        }
+        .to_string(),
+    )
+}
+
+/// Stores the file at `path` in `blobs` as an attachment named after it.
+pub fn store(blobs: &BlobStore, path: &Path) -> Result<Attachment> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let name = path
+        .file_name()
+        .map_or_else(|| "attachment".into(), |n| n.to_string_lossy().into_owned());
+    Ok(Attachment {
+        mime: mime(&name),
+        name,
+        blob: blobs.put(&bytes)?,
+        thumbnail: None,
+    })
+}
+
+/// Gives `doc` an attachment unless it has the same file under the same
+/// name already, in which case it keeps the thumbnail of either; returns
+/// whether it was added.
+pub fn add(doc: &mut Document, attachment: Attachment) -> bool {
+    match doc
+        .attachments
+        .iter_mut()
+        .find(|a| a.blob == attachment.blob && a.name == attachment.name)
+    {
+        Some(existing) => {
+            if existing.thumbnail.is_none() {
+                existing.thumbnail = attachment.thumbnail;
+            }
+            false
+        }
+        None => {
+            doc.attachments.push(attachment);
+            true
+        }
+    }
+}
+
+/// The files the Markdown `content` read from `source` lists as its
+/// attachments, stored in `blobs`. Entries are paths relative to the
+/// file, or names as `[[wikilinks]]` looked up beside it and in an
+/// `assets/` directory beside or above it. Missing files are skipped
+/// with a warning.
+pub fn from_frontmatter(
+    blobs: &BlobStore,
+    source: &Path,
+    content: &str,
+) -> Result<Vec<Attachment>> {
+    let Some(base) = source.parent() else {
+        return Ok(Vec::new());
+    };
+    let mut attachments = Vec::new();
+    for entry in listed(content) {
+        let name = entry
+            .trim_start_matches('!')
+            .trim_start_matches("[[")
+            .trim_end_matches("]]")
+            .trim();
+        let candidates: [PathBuf; 3] = [
+            base.join(name),
+            base.join(ASSETS_DIR).join(name),
+            base.join("..").join(ASSETS_DIR).join(name),
+        ];
+        match candidates.iter().find(|p| p.is_file()) {
+            Some(path) => attachments.push(store(blobs, path)?),
+            None => tracing::warn!("{}: attachment {name:?} not found", source.display()),
+        }
+    }
+    Ok(attachments)
+}
+
+/// The entries under [`KEY`] in YAML frontmatter, or in a Logseq
+/// `attachments::` property among the lines the page starts with.
+fn listed(content: &str) -> Vec<String> {
+    if let Some(yaml) = split_frontmatter(content).0 {
+        let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::from_str(yaml) else {
+            return Vec::new();
+        };
+        return match mapping.get(KEY) {
+            Some(serde_yaml::Value::Sequence(entries)) => entries
+                .iter()
+                .filter_map(|e| e.as_str().map(str::to_string))
+                .collect(),
+            Some(serde_yaml::Value::String(entry)) => vec![entry.clone()],
+            _ => Vec::new(),
+        };
+    }
+    let property = format!("{KEY}::");
+    content
+        .lines()
+        .take_while(|l| l.contains("::"))
+        .find_map(|l| l.trim().strip_prefix(property.as_str()))
+        .map(|value| {
+            value
+                .split(", ")
+                .map(str::trim)
+                .filter(|e| !e.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,121 @@
+//! Citations between documents, found when the knowledge graph is rebuilt.
+//!
+//! Papers are the documents that know their DOI or citation key, such as
+//! references imported from BibTeX or Zotero. A document cites a paper
+//! when its text holds the paper's DOI or, less surely, its full title.
+
+use std::collections::HashMap;
+
+use crate::import::zotero::CITATION_KEY;
+use crate::types::{Document, DocumentId};
+
+/// Titles shorter than this many words are too common to match on.
+const MIN_TITLE_WORDS: usize = 4;
+/// Confidence of a citation found by title rather than by DOI.
+const TITLE_CONFIDENCE: f32 = 0.8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Citation {
+    pub from: DocumentId,
+    pub to: DocumentId,
+    pub confidence: f32,
+}
+
+/// The DOI of a document, from its metadata, in lowercase and without a
+/// `doi:` or `https://doi.org/` prefix.
+pub fn doi(doc: &Document) -> Option<String> {
+    dois_in(doc.metadata.get("doi")?).into_iter().next()
+}
+
+/// The DOIs written in `text`, each once, in lowercase.
+pub fn dois_in(text: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for (at, _) in text.match_indices("10.") {
+        if text[..at]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric())
+        {
+            continue;
+        }
+        let rest = &text[at + 3..];
+        let registrant = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if !(4..=9).contains(&registrant) || !rest[registrant..].starts_with('/') {
+            continue;
+        }
+        let suffix = &rest[registrant + 1..];
+        let end = suffix
+            .find(|c: char| c.is_whitespace() || matches!(c, '"' | '<' | '>'))
+            .unwrap_or(suffix.len());
+        let suffix = suffix[..end].trim_end_matches(['.', ',', ';', ':', ')', ']', '}', '\'']);
+        if suffix.is_empty() {
+            continue;
+        }
+        let doi = format!("10.{}/{suffix}", &rest[..registrant]).to_lowercase();
+        if !out.contains(&doi) {
+            out.push(doi);
+        }
+    }
+    out
+}
+
+/// Lowercase words separated by single spaces, so that titles match
+/// however they are punctuated or broken across lines.
+fn words(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_paper(doc: &Document) -> bool {
+    doc.metadata.contains_key("doi") || doc.metadata.contains_key(CITATION_KEY)
+}
+
+/// Every citation of a paper by another document among `docs`.
+pub fn find(docs: &[Document]) -> Vec<Citation> {
+    let mut by_doi: HashMap<String, &DocumentId> = HashMap::new();
+    let mut titles: Vec<(String, &DocumentId)> = Vec::new();
+    for doc in docs.iter().filter(|d| is_paper(d)) {
+        if let Some(doi) = doi(doc) {
+            by_doi.insert(doi, &doc.id);
+        }
+        let title = words(&doc.title);
+        if title.split(' ').count() >= MIN_TITLE_WORDS {
+            titles.push((format!(" {title} "), &doc.id));
+        }
+    }
+    if by_doi.is_empty() && titles.is_empty() {
+        return Vec::new();
+    }
+
+    let mut out = Vec::new();
+    for doc in docs {
+        let mut cited: Vec<(&DocumentId, f32)> = Vec::new();
+        for doi in dois_in(&doc.content) {
+            if let Some(&to) = by_doi.get(&doi) {
+                cited.push((to, 1.0));
+            }
+        }
+        let text = format!(" {} ", words(&doc.content));
+        for (title, to) in &titles {
+            if text.contains(title.as_str()) && !cited.iter().any(|(c, _)| c == to) {
+                cited.push((to, TITLE_CONFIDENCE));
+            }
+        }
+        for (to, confidence) in cited {
+            // A paper's own content starts with its title.
+            if *to != doc.id {
+                out.push(Citation {
+                    from: doc.id.clone(),
+                    to: to.clone(),
+                    confidence,
+                });
+            }
+        }
+    }
+    out
+}
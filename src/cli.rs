@@ -0,0 +1,1145 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::{ArgValueCandidates, Shell};
+
+use crate::completion;
+
+use crate::logging::LogFormat;
+
+use crate::output::Format;
+use crate::search::SearchMode;
+use crate::sync::MergePolicy;
+
+/// Ozymandias: a personal knowledge base for the command line.
+#[derive(Debug, Parser)]
+#[command(name = "ozy", version, about)]
+pub struct Cli {
+    /// Output format; json and yaml print one stable, documented value for
+    /// scripts, and dot and graphml are for `graph export`
+    #[arg(long, global = true, value_enum, default_value_t = Format::Plain)]
+    pub format: Format,
+    /// Work on a knowledge base registered under this name in
+    /// `[knowledge_bases]` of the user's config, instead of the one in the
+    /// current directory
+    #[arg(long, global = true, env = "OZY_KB")]
+    pub kb: Option<String>,
+    /// How diagnostics are logged; filter them by level and module with
+    /// `RUST_LOG`
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+    /// Append diagnostics to this file instead of printing them on stderr
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Log more: -v for debug messages, -vv for traces
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+    /// Log less and do not report progress: -q for warnings only, -qq for
+    /// errors only, -qqq for nothing
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub quiet: u8,
+    /// Report what would change in the knowledge base and its config
+    /// without changing anything
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Override a setting for this run, such as `--set llm.model=llama3`
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+    pub settings: Vec<(String, String)>,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Add files or web pages to the knowledge base
+    Add(AddArgs),
+    /// Keep the knowledge base in sync with directories as files change
+    Watch(WatchArgs),
+    /// Write a new note in the editor, from a template in `templates/` if
+    /// one is named, and add it once saved
+    New(NewArgs),
+    /// Create or open the journal entry of a day, chained to the others
+    Journal(JournalArgs),
+    /// Full-text search with ranked results
+    Search(SearchArgs),
+    /// Fuzzy lookup of notes by title, tag or alias
+    Find(FindArgs),
+    /// List documents, optionally narrowed by a query
+    List(ListArgs),
+    /// Show a document with its metadata
+    Show(ShowArgs),
+    /// Lay documents out in order of when they were created or published,
+    /// or of the dates they mention
+    Timeline(TimelineArgs),
+    /// Edit a document in the editor, then run it through the pipeline
+    /// again
+    Edit(EditArgs),
+    /// Open the file or web page a document came from
+    Open(OpenArgs),
+    /// Store files, such as images, datasets or recordings, with a document
+    Attach(AttachArgs),
+    /// List the files attached to a document, or save copies of them
+    Attachments(AttachmentsArgs),
+    /// Browse and search interactively in a full-screen interface
+    Tui,
+    /// Serve the knowledge base over HTTP, or to LLM clients with --mcp
+    Serve(ServeArgs),
+    /// Exchange changes with another copy of the knowledge base
+    Sync(SyncArgs),
+    /// List the earlier revisions of a document
+    History(HistoryArgs),
+    /// Show what changed between revisions of a document
+    Diff(DiffArgs),
+    /// Move documents to the trash, leaving tombstones that block
+    /// re-imports
+    Rm(RmArgs),
+    /// List, restore and empty removed documents
+    #[command(subcommand)]
+    Trash(TrashCommand),
+    /// Add, remove and list tags
+    #[command(subcommand)]
+    Tag(TagCommand),
+    /// Give documents other titles they are also found and linked by
+    #[command(subcommand)]
+    Alias(AliasCommand),
+    /// Reverse the last add, rm, tag, dedupe merge or trash restore
+    Undo(UndoArgs),
+    /// List the tasks written into documents and mark them done
+    #[command(subcommand)]
+    Todo(TodoCommand),
+    /// Study flashcards and documents on a spaced-repetition schedule
+    #[command(subcommand)]
+    Review(ReviewCommand),
+    /// Record a typed relation between two documents
+    Relate(RelateArgs),
+    /// List the papers a document cites, by DOI, title or `cites` relation
+    Cites(CitesArgs),
+    /// List the documents that cite a paper
+    CitedBy(CitesArgs),
+    /// Classify documents by rules and ontology concepts
+    Classify(ClassifyArgs),
+    /// Write a short summary of documents
+    Summarize(SummarizeArgs),
+    /// Group documents into topics by embedding similarity
+    Cluster(ClusterArgs),
+    /// Answer a question from the knowledge base, citing sources
+    Ask(AskArgs),
+    /// Talk with a language model about the knowledge base
+    Chat(ChatArgs),
+    /// Manage the ontology used for classification
+    #[command(subcommand)]
+    Ontology(OntologyCommand),
+    /// Explore the knowledge graph
+    #[command(subcommand)]
+    Graph(GraphCommand),
+    /// Count documents, tags, links and the space they take up
+    Stats,
+    /// Write documents out for other note-taking tools
+    #[command(subcommand)]
+    Export(ExportCommand),
+    /// Add documents from the exports of other note-taking tools
+    #[command(subcommand)]
+    Import(ImportCommand),
+    /// Inspect links between documents and to the web
+    #[command(subcommand)]
+    Links(LinksCommand),
+    /// Find and merge duplicate documents
+    #[command(subcommand)]
+    Dedupe(DedupeCommand),
+    /// Check the knowledge base for inconsistencies and damage
+    Doctor(DoctorArgs),
+    /// Re-hash every document and blob to find damaged or missing files
+    Verify(VerifyArgs),
+    /// Remove blobs and history nothing refers to and compact the indexes
+    /// and logs
+    Gc,
+    /// Rebuild the search, link, task and graph indexes from the stored
+    /// documents
+    Reindex,
+    /// Copy the knowledge base into a zip archive
+    Backup(BackupArgs),
+    /// Bring a knowledge base made by an earlier version to the current
+    /// format, rewriting the documents stored in an earlier one
+    Migrate,
+    /// Manage embedding models
+    #[command(subcommand)]
+    Models(ModelsCommand),
+    /// Inspect and customize the prompts sent to language models
+    #[command(subcommand)]
+    Prompts(PromptsCommand),
+    /// Print a script that sets up shell completion
+    Completions(CompletionsArgs),
+    /// Read and change settings
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+fn parse_setting(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err("expected KEY=VALUE".to_string()),
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AddArgs {
+    /// Files to ingest, or directories to ingest the Markdown, text and
+    /// HTML files below
+    #[arg(required_unless_present = "urls")]
+    pub paths: Vec<PathBuf>,
+    /// Clip a web page: store its main article and a snapshot of the HTML
+    #[arg(long = "url", value_name = "URL")]
+    pub urls: Vec<String>,
+    /// Also keep a self-contained copy of each page clipped with --url,
+    /// its stylesheets and images inlined, to view offline as it appeared
+    #[arg(long, requires = "urls")]
+    pub archive: bool,
+    /// Print tags the classifier predicts for each new document
+    #[arg(long)]
+    pub suggest_tags: bool,
+    /// Process files again even if they have not changed since they were
+    /// last added, for instance after changing the pipeline
+    #[arg(long)]
+    pub force: bool,
+    /// Inputs read, fetched and embedded at once (default: one per CPU
+    /// core, at least 8)
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Directories to watch, with everything below them
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Milliseconds a file must stay unchanged before it is re-ingested
+    #[arg(long, default_value_t = 500)]
+    pub debounce: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    /// Search query: words, "quoted phrases", AND/OR/NOT (or -word),
+    /// parentheses and filters such as `tag:history`, `type:markdown`,
+    /// `type:pdf`, `source:web`, `added:2024-01..2024-06`, `published:2020..`,
+    /// `mentions:1815`, `entity:person:"Ada Lovelace"` or `meta:camera=fuji`
+    #[arg(required_unless_present_any = ["saved", "list_saved"])]
+    pub query: Option<String>,
+    /// Maximum number of hits to print
+    #[arg(long, default_value_t = 10)]
+    pub limit: usize,
+    /// Number of hits to skip, for paging through results
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+    /// How to rank results
+    #[arg(long, value_enum, default_value_t = SearchMode::Hybrid)]
+    pub mode: SearchMode,
+    /// Shorthand for `--mode semantic`
+    #[arg(long, conflicts_with = "mode")]
+    pub semantic: bool,
+    /// Save the query (and mode) under this name before running it
+    #[arg(long, value_name = "NAME", conflicts_with = "saved")]
+    pub save: Option<String>,
+    /// Run a previously saved query instead of QUERY
+    #[arg(long, value_name = "NAME", conflicts_with = "query")]
+    pub saved: Option<String>,
+    /// List saved queries
+    #[arg(
+        long,
+        conflicts_with_all = ["query", "limit", "offset", "mode", "semantic", "save", "saved", "facets"]
+    )]
+    pub list_saved: bool,
+    /// Print per-tag and per-type counts of all matches
+    #[arg(long)]
+    pub facets: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    /// Only list documents matching this query (see `ozy search --help`)
+    pub query: Option<String>,
+    /// Sort order
+    #[arg(long, value_enum, default_value_t = SortKey::Date)]
+    pub sort: SortKey,
+    /// Reverse the sort order
+    #[arg(long)]
+    pub reverse: bool,
+    /// Maximum number of documents to print
+    #[arg(long, default_value_t = 50)]
+    pub limit: usize,
+    /// Number of documents to skip, for paging
+    #[arg(long, default_value_t = 0)]
+    pub offset: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct TimelineArgs {
+    /// Only include documents matching this query (see `ozy search --help`)
+    #[arg(long)]
+    pub query: Option<String>,
+    /// Which dates to lay documents out by
+    #[arg(long, value_enum, default_value_t = TimelineKey::Created)]
+    pub by: TimelineKey,
+    /// Newest first
+    #[arg(long)]
+    pub reverse: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TimelineKey {
+    /// When documents were created, or added if they do not say
+    Created,
+    /// When documents were published; those that do not say are left out
+    Published,
+    /// Every date a document mentions, once each
+    Mentions,
+    /// When documents were added to the knowledge base
+    Added,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Alphabetically by title
+    Title,
+    /// Newest first
+    Date,
+    /// Largest first
+    Size,
+}
+
+#[derive(Debug, Args)]
+pub struct NewArgs {
+    /// Title of the note, also its file name
+    pub title: String,
+    /// Template to start from, such as `meeting` for
+    /// `templates/meeting.md`
+    #[arg(short, long)]
+    pub template: Option<String>,
+    /// Value of a template variable, asked for otherwise
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_setting)]
+    pub vars: Vec<(String, String)>,
+    /// Create and add the note without opening it in an editor
+    #[arg(long)]
+    pub no_edit: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct JournalArgs {
+    /// Day of the entry, as YYYY-MM-DD (default: today)
+    #[arg(long)]
+    pub date: Option<NaiveDate>,
+    /// Create and add the entry without opening it in an editor
+    #[arg(long)]
+    pub no_edit: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ShowArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct EditArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct OpenArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+    /// Show the file in the file manager instead of opening it
+    #[arg(long)]
+    pub reveal: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+    /// Revisions to compare, as numbered by `ozy history`: `3..5`, or `3`
+    /// for revision 3 against the current one (default: the revision
+    /// before the current one against it)
+    #[arg(long, value_name = "FROM..TO")]
+    pub rev: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct RmArgs {
+    /// Ids, or unique id prefixes, of documents to remove
+    #[arg(required = true, add = ArgValueCandidates::new(completion::document_ids))]
+    pub ids: Vec<String>,
+    /// Delete for good, bypassing the trash and leaving no tombstone, so
+    /// the source can be imported again; also clears the tombstone and
+    /// trashed copy of an already removed document
+    #[arg(long)]
+    pub purge: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrashCommand {
+    /// List removed documents, the most recently removed first
+    List,
+    /// Put removed documents back, lifting their tombstones
+    Restore {
+        /// Ids, or unique id prefixes, of documents in the trash
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    /// Delete every document in the trash for good
+    Empty,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TodoCommand {
+    /// List open tasks, those due soonest first
+    List {
+        /// Only tasks in documents matching this query (see `ozy search
+        /// --help`)
+        query: Option<String>,
+        /// Include tasks already done
+        #[arg(long)]
+        all: bool,
+        /// Only tasks due by this day: a date such as 2024-03-01, or
+        /// `today`, `friday`, `next week`, `in 3 days`
+        #[arg(long, value_name = "WHEN")]
+        due: Option<String>,
+    },
+    /// Check tasks off, in the stored documents and their source files
+    Done {
+        /// Tasks as listed: a document id or unique prefix, a colon and
+        /// the line of the task
+        #[arg(required = true)]
+        tasks: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReviewCommand {
+    /// Put documents up for review: each flashcard in them, or the
+    /// document itself when it has none
+    Add {
+        /// Document ids, or unique prefixes of them
+        #[arg(required_unless_present = "query", add = ArgValueCandidates::new(completion::document_ids))]
+        ids: Vec<String>,
+        /// Also add every document matching this query (see `ozy search
+        /// --help`)
+        #[arg(long)]
+        query: Option<String>,
+    },
+    /// Take documents out of review, forgetting their schedules
+    Remove {
+        /// Document ids, or unique prefixes of them
+        #[arg(required = true, add = ArgValueCandidates::new(completion::document_ids))]
+        ids: Vec<String>,
+    },
+    /// Review the cards that are due, grading how well each was recalled
+    Start {
+        /// Most cards to review in this session
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// List the cards under review, the soonest due first
+    List {
+        /// Only the cards due today
+        #[arg(long)]
+        due: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct UndoArgs {
+    /// List the commands that can be undone, the most recent first,
+    /// instead of undoing one
+    #[arg(long)]
+    pub list: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TagCommand {
+    /// Attach tags to a document
+    Add {
+        /// Document id or unique prefix
+        #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+        id: String,
+        #[arg(required = true, add = ArgValueCandidates::new(completion::tag_names))]
+        tags: Vec<String>,
+    },
+    /// Detach tags from a document
+    Rm {
+        /// Document id or unique prefix
+        #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+        id: String,
+        #[arg(required = true, add = ArgValueCandidates::new(completion::tag_names))]
+        tags: Vec<String>,
+    },
+    /// List all tags in use
+    List {
+        /// Show how many documents carry each tag
+        #[arg(long)]
+        counts: bool,
+        /// Show the tag hierarchy as a tree
+        #[arg(long)]
+        tree: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AliasCommand {
+    /// Add alias titles to a document, such as a name it had before
+    Add {
+        /// Document id or unique prefix
+        #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+        id: String,
+        #[arg(required = true)]
+        aliases: Vec<String>,
+    },
+    /// Remove alias titles from a document
+    Rm {
+        /// Document id or unique prefix
+        #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+        id: String,
+        #[arg(required = true)]
+        aliases: Vec<String>,
+    },
+    /// List the aliases and former ids of a document, or of all documents
+    List {
+        /// Document id or unique prefix
+        #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+        id: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct RelateArgs {
+    /// Document the relation starts at
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub from: String,
+    /// is-a, part-of, cites, contradicts, follows, or a relation from the ontology
+    pub kind: String,
+    /// Document the relation points to
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub to: String,
+    /// How sure you are, from 0 to 1
+    #[arg(long, default_value_t = 1.0)]
+    pub confidence: f32,
+    /// Remove the relation instead
+    #[arg(long, conflicts_with = "confidence")]
+    pub remove: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct AttachArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+    /// Files to attach
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct AttachmentsArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+    /// Copy the attachments into this directory, created if needed
+    #[arg(long, value_name = "DIR")]
+    pub save: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CitesArgs {
+    /// Document id, or a unique prefix of one
+    #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+    pub id: String,
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    pub addr: std::net::SocketAddr,
+    /// API token clients must send as `Authorization: Bearer <token>`
+    /// (default: the one in `.ozymandias/api-token`, created on first use)
+    #[arg(long)]
+    pub token: Option<String>,
+    /// Speak the Model Context Protocol on stdin and stdout instead, for
+    /// clients such as Claude Desktop
+    #[arg(long, conflicts_with_all = ["addr", "token"])]
+    pub mcp: bool,
+    /// Serve no knowledge base, only a relay keeping encrypted objects in
+    /// this directory for `ozy sync relay+http://...`
+    #[arg(long, value_name = "DIR", conflicts_with = "mcp")]
+    pub relay: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct AskArgs {
+    /// The question, in plain language
+    pub question: String,
+    /// Number of passages retrieved as context
+    #[arg(long, default_value_t = 6)]
+    pub passages: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ChatArgs {
+    /// Number of passages retrieved as context for each question
+    #[arg(long, default_value_t = 6)]
+    pub passages: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ClusterArgs {
+    /// Number of clusters (default: about the square root of half the
+    /// number of documents)
+    #[arg(short, long)]
+    pub k: Option<usize>,
+    /// Keywords shown per cluster
+    #[arg(long, default_value_t = 5)]
+    pub keywords: usize,
+    /// Tag members with `<PREFIX>/<top keyword>` of their cluster
+    #[arg(long, value_name = "PREFIX", num_args = 0..=1, default_missing_value = "topic")]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct SummarizeArgs {
+    /// Document ids, or unique prefixes of them
+    #[arg(required = true, add = ArgValueCandidates::new(completion::document_ids))]
+    pub ids: Vec<String>,
+    /// Replace summaries that already exist
+    #[arg(long)]
+    pub force: bool,
+    /// Documents summarized at once (default: one per CPU core, at least 8)
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct ClassifyArgs {
+    /// Document ids, or unique prefixes of them
+    #[arg(required = true, add = ArgValueCandidates::new(completion::document_ids))]
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OntologyCommand {
+    /// Import the concepts of a SKOS vocabulary into the ontology
+    Import {
+        /// A Turtle (.ttl) or RDF/XML (.rdf, .xml) file
+        file: PathBuf,
+        /// File format, if the extension does not tell
+        #[arg(long, value_enum)]
+        from: Option<RdfFormat>,
+        /// Preferred language of labels
+        #[arg(long, default_value = "en")]
+        lang: String,
+    },
+    /// Check the ontology for cycles, duplicates, orphans and unused concepts
+    Lint,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RdfFormat {
+    Turtle,
+    Rdfxml,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum GraphCommand {
+    /// Show the nodes connected to a document or tag
+    Neighbors {
+        /// Document id prefix, or `#tag` for a tag node
+        #[arg(add = ArgValueCandidates::new(completion::graph_nodes))]
+        node: String,
+    },
+    /// Find a shortest path between two nodes
+    Path {
+        /// Document id prefix, or `#tag` for a tag node
+        #[arg(add = ArgValueCandidates::new(completion::graph_nodes))]
+        from: String,
+        /// Document id prefix, or `#tag` for a tag node
+        #[arg(add = ArgValueCandidates::new(completion::graph_nodes))]
+        to: String,
+    },
+    /// Write the graph for Graphviz, Gephi or other tools, as DOT unless
+    /// `--format` asks for graphml, json or yaml
+    Export(ExportArgs),
+    /// Rank notes by centrality and list the most isolated clusters
+    Stats {
+        /// Number of notes and clusters to print
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LinksCommand {
+    /// Probe external URLs and report dead or redirected ones
+    Check {
+        /// How many URLs to probe at once
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+        /// Retries after timeouts and 429/5xx answers (default: the
+        /// `web.retries` setting)
+        #[arg(long)]
+        retries: Option<u32>,
+        /// Minimum milliseconds between requests to the same host
+        /// (default: the `web.host_delay_ms` setting)
+        #[arg(long)]
+        delay_ms: Option<u64>,
+        /// Seconds to wait for each answer (default: the
+        /// `web.timeout_secs` setting)
+        #[arg(long)]
+        timeout: Option<u64>,
+        /// Also list links that are fine
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Only include documents with this tag (or one nested below it)
+    #[arg(long, add = ArgValueCandidates::new(completion::tag_names))]
+    pub tag: Option<String>,
+    /// Only include nodes near this document id prefix or `#tag`
+    #[arg(long, add = ArgValueCandidates::new(completion::graph_nodes))]
+    pub around: Option<String>,
+    /// How many edges away from `--around` to include
+    #[arg(long, default_value_t = 1, requires = "around")]
+    pub depth: usize,
+    /// Write to a file instead of standard output
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct FindArgs {
+    /// Approximate title, tag or alias; typos and partial words are fine
+    pub query: String,
+    /// Maximum number of matches to print
+    #[arg(long, default_value_t = 10)]
+    pub limit: usize,
+    /// Minimum similarity between 0 and 1 for a match
+    #[arg(long, default_value_t = crate::fuzzy::DEFAULT_THRESHOLD)]
+    pub threshold: f64,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DedupeCommand {
+    /// List clusters of exact and near-duplicate documents
+    Report {
+        /// Minimum estimated similarity, from 0 to 1 (default: the
+        /// pipeline's near_duplicate_threshold)
+        #[arg(long)]
+        threshold: Option<f32>,
+    },
+    /// Walk through the clusters `report` lists, comparing their documents
+    /// side by side, and merge each into the one chosen to keep
+    Resolve {
+        /// Minimum estimated similarity, from 0 to 1 (default: the
+        /// pipeline's near_duplicate_threshold)
+        #[arg(long)]
+        threshold: Option<f32>,
+    },
+    /// Fold duplicates into one document and remove them
+    Merge {
+        /// Id or unique id prefix of the document to keep
+        #[arg(add = ArgValueCandidates::new(completion::document_ids))]
+        keep: String,
+        /// Documents merged into it
+        #[arg(required = true, add = ArgValueCandidates::new(completion::document_ids))]
+        duplicates: Vec<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ModelsCommand {
+    /// Download a local embedding model into the model cache
+    Pull {
+        /// Hugging Face repository of a BERT sentence-embedding model
+        #[arg(default_value = crate::ml::models::DEFAULT_EMBEDDING_MODEL)]
+        repo: String,
+    },
+    /// List downloaded models and the one the knowledge base uses
+    List,
+    /// Switch the knowledge base to a local model and re-embed it
+    Use {
+        /// `hashed`, or a repository such as
+        /// `sentence-transformers/all-MiniLM-L6-v2` to run locally
+        model: String,
+    },
+    /// Re-embed every document with the provider from the config file
+    Reembed,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PromptsCommand {
+    /// List the prompt templates, their variables and whether they are
+    /// customized
+    List,
+    /// Print the text of a template as currently used
+    Show {
+        /// Template name, such as `ask` or `summarize`
+        name: String,
+    },
+    /// Write the built-in templates to the prompts directory for editing,
+    /// keeping files that already exist
+    Init,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ExportCommand {
+    /// Write an Obsidian vault, with metadata as frontmatter, links as
+    /// wikilinks and attachments in `assets/`
+    Obsidian {
+        /// Vault directory, created if needed
+        dir: PathBuf,
+    },
+    /// Write a Logseq graph, with documents as outlines, metadata as
+    /// properties and notes named after a date as journal pages
+    Logseq {
+        /// Graph directory, created if needed
+        dir: PathBuf,
+    },
+    /// Write a static HTML site: a page per document with the pages
+    /// linking to it, a page per tag, and an index with search
+    Site {
+        /// Site directory, created if needed
+        dir: PathBuf,
+        /// Only publish documents with this tag (or one nested below it)
+        #[arg(long, add = ArgValueCandidates::new(completion::tag_names))]
+        tag: Option<String>,
+    },
+    /// Compile documents into one book with a table of contents, ordered
+    /// by a contents document's links, then by the links between them
+    Book {
+        /// The book to write: a `.pdf` or an `.epub`
+        file: PathBuf,
+        /// Only include documents matching this query (see `ozy search --help`)
+        #[arg(long)]
+        query: Option<String>,
+        /// Book format; by default, from the file's extension
+        #[arg(long, value_enum)]
+        to: Option<BookFormat>,
+        /// Title of the book; by default, the contents document's title
+        #[arg(long)]
+        title: Option<String>,
+        /// Document whose links, in order, give the first chapters (an id
+        /// prefix or `@alias`); it is left out of the book itself
+        #[arg(long, add = ArgValueCandidates::new(completion::document_ids))]
+        contents: Option<String>,
+    },
+    /// Write a flashcard deck from `Q:` / `A:` pairs and question headings,
+    /// or one card per document that has neither
+    Anki {
+        /// Deck file: an Anki package if it ends in `.apkg`, else a
+        /// tab-separated file for Anki's text import
+        file: PathBuf,
+        /// Only include documents with this tag (or one nested below it)
+        #[arg(long, add = ArgValueCandidates::new(completion::tag_names))]
+        tag: Option<String>,
+        /// Name of the deck in Anki
+        #[arg(long, default_value = "Ozymandias")]
+        deck: String,
+        /// Have the language model draft cloze deletions for documents
+        /// without questions of their own
+        #[arg(long)]
+        cloze: bool,
+    },
+    /// Write a BibTeX bibliography, keeping the citation keys and fields
+    /// of imported references
+    Bibtex {
+        /// The `.bib` file to write
+        file: PathBuf,
+        /// Only include documents with this tag (or one nested below it)
+        #[arg(long, add = ArgValueCandidates::new(completion::tag_names))]
+        tag: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BookFormat {
+    Pdf,
+    Epub,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommand {
+    /// Import the Markdown, text and HTML files below directories, as `ozy
+    /// add` does, skipping those unchanged since they were last imported
+    Dir {
+        /// The directories
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+        /// Process files again even if they have not changed
+        #[arg(long)]
+        force: bool,
+        /// Files read and embedded at once (default: one per CPU core, at
+        /// least 8)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Import a Notion "Markdown & CSV" export, with subpages namespaced
+    /// under their parents and database columns as metadata
+    Notion {
+        /// The exported zip file
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import Evernote notebooks exported as `.enex` files, with embedded
+    /// images and files as attachments
+    Enex {
+        /// The exported notebooks
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import a Zotero library, one document per reference, with citation
+    /// keys as aliases (`ozy show @smith2020`) and attached files stored
+    Zotero {
+        /// `zotero.sqlite` in Zotero's data directory, or a Better BibTeX
+        /// JSON export
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import a Roam Research graph exported as JSON or EDN, with daily
+    /// notes as journal entries
+    Roam {
+        /// The exported `.json` or `.edn` file
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Clip the pages of a Pocket export, tagging unread ones `toread` and
+    /// keeping when they were saved
+    Pocket {
+        /// `ril_export.html`, or the CSV or zip of newer exports
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Clip the pages of an Instapaper CSV export, tagging unread ones
+    /// `toread` and keeping when they were saved
+    Instapaper {
+        /// The exported CSV file
+        file: PathBuf,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import the books of a Calibre library, one document per book with
+    /// all its formats attached
+    Calibre {
+        /// The library directory, which holds `metadata.db`
+        dir: PathBuf,
+        /// Formats to read a book's text from, in order of preference
+        #[arg(long, value_delimiter = ',', default_value = "epub,pdf")]
+        prefer: Vec<String>,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import browser bookmarks, exported as HTML by Firefox or Chrome,
+    /// as a Firefox JSON backup, or as Chrome's `Bookmarks` file, with
+    /// their folders as tags
+    Bookmarks {
+        /// The exported or backed up bookmarks
+        file: PathBuf,
+        /// Clip each bookmarked page rather than keeping only its link
+        #[arg(long)]
+        clip: bool,
+        /// Pages clipped per second at most
+        #[arg(long, default_value_t = 2.0, requires = "clip")]
+        rate: f64,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Import the entries of BibTeX files, with citation keys as aliases
+    /// and fields as metadata
+    Bibtex {
+        /// The `.bib` files
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+    /// Pull an RSS or Atom feed and clip the pages of its entries, with
+    /// their categories as tags
+    Feed {
+        /// The URL of the feed
+        url: String,
+        /// Pages clipped per second at most
+        #[arg(long, default_value_t = 2.0)]
+        rate: f64,
+        #[command(flatten)]
+        options: ImportOptions,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct ImportOptions {
+    /// Process documents again even if they have not changed since they
+    /// were last imported
+    #[arg(long)]
+    pub force: bool,
+    /// Move documents an earlier import of the same export added, and
+    /// that it no longer holds, to the trash. Not for saved pages
+    #[arg(long)]
+    pub prune: bool,
+    /// Documents embedded at once (default: one per CPU core, at least 8)
+    #[arg(short, long)]
+    pub jobs: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct SyncArgs {
+    /// A remote named under `[sync.remotes]`, `ssh://host/path` or
+    /// `host:path`, the URL of `ozy serve`, or a directory
+    #[arg(required_unless_present = "serve")]
+    pub remote: Option<String>,
+    /// What to keep of documents changed on both sides (default: the
+    /// `sync.policy` setting)
+    #[arg(long, value_enum)]
+    pub policy: Option<MergePolicy>,
+    /// API token of the remote's `ozy serve`
+    #[arg(long)]
+    pub token: Option<String>,
+    /// Answer one request read from stdin, as the remote end of a sync
+    /// over SSH
+    #[arg(long, hide = true, conflicts_with_all = ["remote", "policy", "token"])]
+    pub serve: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct DoctorArgs {
+    /// Repair the problems that can be repaired without losing anything
+    #[arg(long)]
+    pub fix: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct BackupArgs {
+    /// The archive to write, replaced if it exists
+    pub file: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Record checksums for documents that have none yet, such as those
+    /// stored before checksums were kept
+    #[arg(long)]
+    pub record: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArgs {
+    /// Shell to complete in
+    pub shell: Shell,
+    /// Complete only commands and flags, without calling `ozy` for
+    /// document ids and tags as you type
+    #[arg(long = "static")]
+    pub static_only: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the value of a setting, such as `llm.model`
+    Get { key: String },
+    /// Change a setting in the knowledge base's config file
+    Set {
+        key: String,
+        /// A TOML value such as `42`, `true` or `["a", "b"]`; anything
+        /// else is taken as a string
+        value: String,
+        /// Change the user's config file, shared by all knowledge bases
+        #[arg(long)]
+        user: bool,
+    },
+    /// List all settings in effect
+    List {
+        /// Show which layer each setting comes from
+        #[arg(long)]
+        show_origin: bool,
+    },
+    /// Open a config file in $VISUAL or $EDITOR
+    Edit {
+        /// Edit the user's config file instead of the knowledge base's
+        #[arg(long)]
+        user: bool,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(args: &[&str]) -> Result<SearchArgs, clap::Error> {
+        let cli = Cli::try_parse_from(["ozy"].iter().chain(args))?;
+        match cli.command {
+            Command::Search(args) => Ok(args),
+            other => panic!("expected search, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn saved_searches_are_listed_with_global_options() {
+        let args = search(&["--kb", "work", "search", "--list-saved", "--format", "json"]).unwrap();
+        assert!(args.list_saved);
+        let cli =
+            Cli::try_parse_from(["ozy", "search", "--list-saved", "--format", "json"]).unwrap();
+        assert_eq!(cli.format, Format::Json);
+    }
+
+    #[test]
+    fn graph_export_takes_its_format_from_format() {
+        for (flag, format) in [("graphml", Format::Graphml), ("dot", Format::Dot)] {
+            let cli = Cli::try_parse_from(["ozy", "graph", "export", "--format", flag]).unwrap();
+            assert!(matches!(
+                cli.command,
+                Command::Graph(GraphCommand::Export(_))
+            ));
+            assert_eq!(cli.format, format);
+        }
+        let cli = Cli::try_parse_from(["ozy", "--format", "json", "graph", "export"]).unwrap();
+        assert_eq!(cli.format, Format::Json);
+        assert!(Format::Graphml.only_for_graphs().is_err());
+        assert!(Format::Json.only_for_graphs().is_ok());
+    }
+
+    #[test]
+    fn directories_are_imported_as_they_are_added() {
+        let cli = Cli::try_parse_from(["ozy", "import", "dir", "notes", "--force"]).unwrap();
+        let Command::Import(ImportCommand::Dir { dirs, force, .. }) = cli.command else {
+            panic!("expected import dir, got {:?}", cli.command);
+        };
+        assert_eq!(dirs, [PathBuf::from("notes")]);
+        assert!(force);
+        assert!(Cli::try_parse_from(["ozy", "import", "dir"]).is_err());
+    }
+
+    #[test]
+    fn listing_saved_searches_takes_no_query_options() {
+        assert!(search(&["search", "--list-saved", "history"]).is_err());
+        assert!(search(&["search", "--list-saved", "--saved", "weekly"]).is_err());
+        assert!(search(&["search", "--list-saved", "--limit", "5"]).is_err());
+        assert!(search(&["search"]).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+//! Fetching web pages for `ozy add --url`, through the polite client of
+//! [`crate::web`].
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::web::Client;
+
+/// A fetched page, as the server sent it.
+pub struct Page {
+    /// Where the page ended up after following redirects.
+    pub final_url: String,
+    pub html: String,
+    pub fetched: DateTime<Utc>,
+}
+
+pub async fn fetch(client: &Client, url: &str) -> Result<Page> {
+    let response = client.get(url).await?;
+    let final_url = response.url().to_string();
+    let html = response
+        .text()
+        .await
+        .with_context(|| format!("failed to read {url}"))?;
+    Ok(Page {
+        final_url,
+        html,
+        fetched: Utc::now(),
+    })
+}
+
+/// Spaces out requests made at once.
+pub struct RateLimit {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimit {
+    pub fn new(per_second: f64) -> Self {
+        RateLimit {
+            interval: Duration::from_secs_f64(1.0 / per_second.max(0.001)),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Waits until the next request may be made.
+    pub async fn wait(&self) {
+        let now = Instant::now();
+        let at = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let at = (*next).max(now);
+            *next = at + self.interval;
+            at
+        };
+        tokio::time::sleep_until(at.into()).await;
+    }
+}
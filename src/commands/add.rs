@@ -0,0 +1,695 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::archive;
+use crate::attachments;
+use crate::cli::AddArgs;
+use crate::clip;
+use crate::import::Saved;
+use crate::kb::{self, KnowledgeBase};
+use crate::links;
+use crate::media;
+use crate::ml::classifier::TagClassifier;
+use crate::output::{AddStatus, Added, Format, Suggestion};
+use crate::parallel;
+use crate::parser::{self, ArticleParser, ParsedData, Parser};
+use crate::plugins::Plugins;
+use crate::progress::Progress;
+use crate::runtime;
+use crate::storage::{sha256, BlobStore, Storage};
+use crate::tags;
+use crate::thumbnails;
+use crate::tombstones::Tombstones;
+use crate::transform::dedupe::NEAR_DUPLICATE_KEY;
+use crate::transform::{Outcome, Pipelines};
+use crate::types::{
+    Document, DocumentId, DocumentKind, ARCHIVE_KEY, FETCH_ERROR_KEY, MODIFIED_KEY, SNAPSHOT_KEY,
+    SOURCE_HASH_KEY,
+};
+use crate::undo::command;
+use crate::web;
+
+/// Extensions of the files ingested from directories.
+const EXTENSIONS: &[&str] = &["md", "markdown", "txt", "text", "html", "htm"];
+
+pub fn run(args: AddArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open_or_init(&kb::dir()?)?;
+    let jobs = args.jobs.unwrap_or_else(parallel::default_jobs);
+    let mut ingest = Ingest::new(&kb, args.suggest_tags, args.force, jobs)?;
+    if args.archive {
+        ingest.archive_pages();
+    }
+    let inputs = args.paths.iter().map(|p| p.display().to_string());
+    kb.record_as(command("add", inputs.chain(args.urls.iter().cloned())));
+    // Files named on the command line must be added; one unreadable file
+    // found in a directory should not abort a large import.
+    let mut files = Vec::new();
+    let mut named = BTreeSet::new();
+    for path in &args.paths {
+        if path.is_dir() {
+            let mut found = BTreeSet::new();
+            walk(path, kb.plugins()?, &mut found)?;
+            files.extend(found);
+        } else {
+            named.insert(path.clone());
+            files.push(path.clone());
+        }
+    }
+    // Plain output is printed as each input is done, the others once at
+    // the end.
+    let progress = Progress::new("inputs added", files.len() + args.urls.len());
+    let mut results = Vec::new();
+    let mut report = |added: Added| {
+        if format.is_plain() && added.status != AddStatus::Unchanged {
+            progress.suspend(|| print(&added));
+        }
+        progress.inc(1);
+        results.push(added);
+    };
+    ingest.files(&mut kb, &files, |path, added| {
+        match added {
+            Ok(added) => report(added),
+            Err(e) if named.contains(path) => return Err(e),
+            Err(e) => progress.suspend(|| tracing::warn!("{e:#}")),
+        }
+        Ok(())
+    })?;
+    ingest.urls(&mut kb, &args.urls, |_, added| {
+        report(added?);
+        Ok(())
+    })?;
+    drop(progress);
+    kb.commit()?;
+    let unchanged = results
+        .iter()
+        .filter(|a| a.status == AddStatus::Unchanged)
+        .count();
+    format.print(&results, |_| {
+        if unchanged > 0 {
+            println!("{unchanged} unchanged since last added (use --force to process them again)");
+        }
+    })
+}
+
+/// Inputs handled together: several read or fetched at once, run through
+/// the pipeline one after the other, so that stages such as `dedupe` see
+/// the documents before them, then several embedded at once.
+const BATCH: usize = 64;
+
+/// Turns inputs into documents: parses them, runs the pipeline, stores
+/// them, and tags them as configured.
+pub struct Ingest {
+    pipelines: Pipelines,
+    classifier: Option<TagClassifier>,
+    suggest_tags: bool,
+    /// Process files even if they have not changed.
+    force: bool,
+    /// Inputs read, fetched or embedded at once.
+    jobs: usize,
+    /// Spaces out fetching saved pages.
+    rate: Option<clip::RateLimit>,
+    /// Keep self-contained copies of clipped web pages.
+    archive: bool,
+    web: web::Client,
+}
+
+/// The parts of the knowledge base inputs are prepared with, which can
+/// be shared between threads.
+struct Shared<'a> {
+    storage: &'a dyn Storage,
+    tombstones: &'a Tombstones,
+    blobs: &'a BlobStore,
+    plugins: &'a Plugins,
+    web: &'a web::Client,
+}
+
+/// An input as read, before the pipeline.
+enum Prepared {
+    /// Nothing more to do.
+    Done(Added),
+    /// Unchanged but for its modification time, which is to be stored.
+    Touched(Document, String),
+    Parsed(Document, String),
+}
+
+impl Ingest {
+    pub fn new(kb: &KnowledgeBase, suggest_tags: bool, force: bool, jobs: usize) -> Result<Self> {
+        let classifier = if suggest_tags || kb.config.tagging.auto_threshold.is_some() {
+            Some(kb.tag_classifier()?)
+        } else {
+            None
+        };
+        Ok(Ingest {
+            pipelines: Pipelines::from_config(&kb.config.pipeline, kb.plugins()?)?,
+            classifier,
+            suggest_tags,
+            force,
+            jobs,
+            rate: None,
+            archive: false,
+            web: web::Client::new(&kb.config.web),
+        })
+    }
+
+    /// Fetches saved pages at most `per_second` times a second, however
+    /// many are fetched at once.
+    pub fn limit_rate(&mut self, per_second: f64) {
+        self.rate = Some(clip::RateLimit::new(per_second));
+    }
+
+    /// The client web pages are fetched with.
+    pub fn web(&self) -> &web::Client {
+        &self.web
+    }
+
+    /// Keeps, with each web page clipped by `urls`, a copy with its
+    /// stylesheets and images inlined, to view offline.
+    pub fn archive_pages(&mut self) {
+        self.archive = true;
+    }
+
+    /// Ingests files, calling `report` for each in order with its outcome.
+    /// Errors of single files go to `report`, which may stop by returning
+    /// them.
+    pub fn files(
+        &self,
+        kb: &mut KnowledgeBase,
+        paths: &[PathBuf],
+        report: impl FnMut(&PathBuf, Result<Added>) -> Result<()>,
+    ) -> Result<()> {
+        let (force, jobs) = (self.force, self.jobs);
+        self.batches(
+            kb,
+            paths,
+            |kb, batch| parallel::map(batch, jobs, |path| prepare_file(kb, path, force)),
+            report,
+        )
+    }
+
+    /// Clips web pages, fetching several at once, and stores their main
+    /// article and a snapshot of the HTML. Reports like `files`.
+    pub fn urls(
+        &self,
+        kb: &mut KnowledgeBase,
+        urls: &[String],
+        report: impl FnMut(&String, Result<Added>) -> Result<()>,
+    ) -> Result<()> {
+        let (keep_copy, jobs) = (self.archive, self.jobs);
+        self.batches(
+            kb,
+            urls,
+            |kb, batch| {
+                runtime::block_on(parallel::map_async(batch, jobs, |url| {
+                    prepare_url(kb, url, keep_copy)
+                }))
+            },
+            report,
+        )
+    }
+
+    /// Stores pages saved in a read-later service or as bookmarks, keeping
+    /// their tags and when they were saved, as links or, with `clip`,
+    /// clipped. Pages that cannot be fetched are kept as links. Reports
+    /// like `files`.
+    pub fn saved(
+        &self,
+        kb: &mut KnowledgeBase,
+        saved: &[Saved],
+        clip: bool,
+        report: impl FnMut(&Saved, Result<Added>) -> Result<()>,
+    ) -> Result<()> {
+        let (force, jobs) = (self.force, self.jobs);
+        let rate = self.rate.as_ref();
+        self.batches(
+            kb,
+            saved,
+            |kb, batch| {
+                runtime::block_on(parallel::map_async(batch, jobs, |saved| {
+                    prepare_saved(kb, saved, clip.then_some(rate), force)
+                }))
+            },
+            report,
+        )
+    }
+
+    /// Ingests documents read by an importer, which have their `source`
+    /// and `source_hash` set. Reports like `files`.
+    pub fn documents(
+        &self,
+        kb: &mut KnowledgeBase,
+        docs: &[Document],
+        report: impl FnMut(&Document, Result<Added>) -> Result<()>,
+    ) -> Result<()> {
+        let force = self.force;
+        self.batches(
+            kb,
+            docs,
+            |kb, batch| {
+                let prepare = |doc| prepare_document(kb, doc, force);
+                batch.iter().map(prepare).collect()
+            },
+            report,
+        )
+    }
+
+    /// Prepares inputs a batch at a time with `prepare`, which readies
+    /// several at once, runs them through the pipeline in order, embeds
+    /// them several at once and stores them.
+    fn batches<T>(
+        &self,
+        kb: &mut KnowledgeBase,
+        inputs: &[T],
+        prepare: impl Fn(&Shared, &[T]) -> Vec<Result<Prepared>>,
+        mut report: impl FnMut(&T, Result<Added>) -> Result<()>,
+    ) -> Result<()> {
+        for batch in inputs.chunks(BATCH) {
+            let shared = Shared {
+                storage: kb.storage.as_ref(),
+                tombstones: &kb.tombstones,
+                blobs: &kb.blobs,
+                plugins: kb.plugins()?,
+                web: &self.web,
+            };
+            let prepared = prepare(&shared, batch);
+            // Outcomes are reported in the order of the inputs once the
+            // batch is done.
+            let mut outcomes: Vec<Option<Result<Added>>> = Vec::new();
+            let mut ready = Vec::new();
+            for prepared in prepared {
+                let (doc, input) = match prepared {
+                    Ok(Prepared::Done(added)) => {
+                        outcomes.push(Some(Ok(added)));
+                        continue;
+                    }
+                    Ok(Prepared::Touched(doc, input)) => {
+                        kb.touch(&doc.id)?;
+                        kb.storage.put(&doc)?;
+                        outcomes.push(Some(Ok(unchanged(&doc, input))));
+                        continue;
+                    }
+                    Ok(Prepared::Parsed(doc, input)) => (doc, input),
+                    Err(e) => {
+                        outcomes.push(Some(Err(e)));
+                        continue;
+                    }
+                };
+                match self.transform(kb, doc, input) {
+                    Ok((doc, added)) if added.status != AddStatus::Skipped => {
+                        ready.push((outcomes.len(), doc, added));
+                        outcomes.push(None);
+                    }
+                    outcome => outcomes.push(Some(outcome.map(|(_, added)| added))),
+                }
+            }
+            let docs: Vec<&Document> = ready.iter().map(|(_, doc, _)| doc).collect();
+            let vectors = runtime::block_on(kb.embed_all(&docs, self.jobs))?;
+            for ((i, doc, added), vectors) in ready.into_iter().zip(vectors) {
+                outcomes[i] =
+                    Some(vectors.and_then(|vectors| self.store(kb, &doc, vectors, added)));
+            }
+            for (input, outcome) in batch.iter().zip(outcomes) {
+                report(input, outcome.expect("every input has an outcome"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a document through the pipeline. A document updated from its
+    /// source keeps what was curated by hand.
+    fn transform(
+        &self,
+        kb: &KnowledgeBase,
+        mut doc: Document,
+        input: String,
+    ) -> Result<(Document, Added)> {
+        if let Some(stored) = kb.storage.get(&doc.id)? {
+            doc.added = stored.added;
+            doc.tags = stored.tags;
+            doc.aliases = stored.aliases;
+            doc.former_ids = stored.former_ids;
+            // A renamed document is still found, and linked to, by its
+            // old title.
+            if stored.title != doc.title && !doc.aliases.contains(&stored.title) {
+                doc.aliases.push(stored.title);
+            }
+            doc.aliases.retain(|a| *a != doc.title);
+            doc.relations = stored.relations;
+            // Files attached by hand stay attached.
+            for attachment in stored.attachments {
+                attachments::add(&mut doc, attachment);
+            }
+        }
+        thumbnails::fill(
+            &kb.blobs,
+            &mut doc.attachments,
+            kb.config.thumbnails.max_dimension,
+        );
+        media::fill(&kb.blobs, &mut doc);
+        let mut added = Added {
+            input,
+            status: AddStatus::Added,
+            id: doc.id.clone(),
+            title: None,
+            reason: None,
+            near_duplicate_of: None,
+            tagged: Vec::new(),
+            suggested: Vec::new(),
+        };
+        let outcome = self.pipelines.run(&mut doc, kb)?;
+        added.title = Some(doc.title.clone());
+        if let Outcome::Skip(reason) = outcome {
+            added.status = AddStatus::Skipped;
+            added.reason = Some(reason);
+        }
+        Ok((doc, added))
+    }
+
+    fn store(
+        &self,
+        kb: &mut KnowledgeBase,
+        doc: &Document,
+        vectors: Vec<Vec<f32>>,
+        added: Added,
+    ) -> Result<Added> {
+        kb.insert_embedded(doc, vectors)?;
+        self.tag(kb, doc, added)
+    }
+
+    /// Applies or suggests predicted tags for a stored document.
+    fn tag(&self, kb: &mut KnowledgeBase, doc: &Document, mut added: Added) -> Result<Added> {
+        added.near_duplicate_of = doc.metadata.get(NEAR_DUPLICATE_KEY).cloned();
+        let Some(classifier) = self.classifier.as_ref().filter(|c| !c.is_empty()) else {
+            return Ok(added);
+        };
+        let vector = kb.vectors.get(&doc.id).unwrap_or_default();
+        let predictions = classifier.predict(vector);
+        if let Some(threshold) = kb.config.tagging.auto_threshold {
+            let confident: Vec<String> = predictions
+                .iter()
+                .filter(|(_, score)| *score >= threshold)
+                .map(|(tag, _)| tag.clone())
+                .collect();
+            if !confident.is_empty() {
+                kb.retag(&doc.id, &confident, &[])?;
+                added.tagged = confident;
+            }
+        }
+        if self.suggest_tags {
+            added.suggested = predictions
+                .into_iter()
+                .take(kb.config.tagging.suggestions)
+                .map(|(tag, score)| Suggestion { tag, score })
+                .collect();
+        }
+        Ok(added)
+    }
+}
+
+/// Reads and parses a file, unless it is tombstoned or, without `force`,
+/// unchanged. Unchanged files are recognized by their modification time,
+/// or, if only that changed, by their hash, without parsing them again.
+fn prepare_file(kb: &Shared, path: &Path, force: bool) -> Result<Prepared> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("cannot access {}", path.display()))?;
+    let source = canonical.display().to_string();
+    let id = DocumentId::derive(&source);
+    let input = path.display().to_string();
+    if let Some(skipped) = tombstoned(kb.tombstones, &id, &input) {
+        return Ok(Prepared::Done(skipped));
+    }
+    let modified = std::fs::metadata(&canonical)
+        .and_then(|m| m.modified())
+        .with_context(|| format!("cannot access {}", path.display()))?;
+    let modified = DateTime::<Utc>::from(modified).to_rfc3339();
+    let stored = match force {
+        true => None,
+        false => kb.storage.get(&id)?,
+    };
+    if let Some(stored) = &stored {
+        if stored.metadata.get(MODIFIED_KEY) == Some(&modified) {
+            return Ok(Prepared::Done(unchanged(stored, input)));
+        }
+    }
+    // Read as bytes: parser commands may take formats other than text.
+    let raw =
+        std::fs::read(&canonical).with_context(|| format!("failed to read {}", path.display()))?;
+    // Equal to the content hash for text.
+    let hash = sha256(&raw);
+    if let Some(mut stored) = stored {
+        if stored.metadata.get(SOURCE_HASH_KEY) == Some(&hash) {
+            stored.metadata.insert(MODIFIED_KEY.into(), modified);
+            return Ok(Prepared::Touched(stored, input));
+        }
+    }
+    let parsed = parser::parse_raw(&canonical, &raw, kb.plugins)?;
+    let mut doc = document(id, parsed, source);
+    if let (DocumentKind::Markdown, Ok(text)) = (doc.kind, std::str::from_utf8(&raw)) {
+        doc.attachments = attachments::from_frontmatter(kb.blobs, &canonical, text)?;
+    }
+    doc.metadata.insert(MODIFIED_KEY.into(), modified);
+    doc.metadata.insert(SOURCE_HASH_KEY.into(), hash);
+    Ok(Prepared::Parsed(doc, input))
+}
+
+/// Fetches and parses a web page, unless it is tombstoned, and with
+/// `keep_copy` keeps a self-contained copy of it.
+async fn prepare_url(kb: &Shared<'_>, url: &str, keep_copy: bool) -> Result<Prepared> {
+    let page = clip::fetch(kb.web, url).await?;
+    let id = DocumentId::derive(&page.final_url);
+    if let Some(skipped) = tombstoned(kb.tombstones, &id, url) {
+        return Ok(Prepared::Done(skipped));
+    }
+    let archived = match keep_copy {
+        true => Some(archive::archive(kb.web, &page.final_url, &page.html).await),
+        false => None,
+    };
+    let source = page.final_url.clone();
+    let mut doc = clipped(kb, id, page, source)?;
+    if let Some(archived) = archived {
+        let blob = kb.blobs.put(archived.as_bytes())?;
+        doc.metadata.insert(ARCHIVE_KEY.into(), blob);
+    }
+    Ok(Prepared::Parsed(doc, url.to_string()))
+}
+
+/// The main article of a fetched page, with a snapshot of its HTML.
+fn clipped(kb: &Shared, id: DocumentId, page: clip::Page, source: String) -> Result<Document> {
+    let parsed = ArticleParser.parse(&page.html, &page.final_url)?;
+    let snapshot = kb.blobs.put(page.html.as_bytes())?;
+    let mut doc = document(id, parsed, source);
+    doc.metadata
+        .insert("fetched".into(), page.fetched.to_rfc3339());
+    doc.metadata.insert("final_url".into(), page.final_url);
+    doc.metadata.insert(SNAPSHOT_KEY.into(), snapshot);
+    Ok(doc)
+}
+
+/// Clips a saved page, when `clip` gives the rate limit to clip it at,
+/// unless it is tombstoned or, without `force`, stored already. Stored
+/// pages are clipped again if they were only kept as links. Saved pages
+/// are identified by the URL they were saved under, wherever it
+/// redirects to, so that importing a list again finds them.
+async fn prepare_saved(
+    kb: &Shared<'_>,
+    saved: &Saved,
+    clip: Option<Option<&clip::RateLimit>>,
+    force: bool,
+) -> Result<Prepared> {
+    let id = DocumentId::derive(&saved.url);
+    let input = saved.url.clone();
+    if let Some(skipped) = tombstoned(kb.tombstones, &id, &input) {
+        return Ok(Prepared::Done(skipped));
+    }
+    if !force {
+        if let Some(stored) = kb.storage.get(&id)? {
+            if clip.is_none() || stored.metadata.contains_key(SNAPSHOT_KEY) {
+                return Ok(Prepared::Done(unchanged(&stored, input)));
+            }
+        }
+    }
+    let fetched = match clip {
+        Some(rate) => {
+            if let Some(rate) = rate {
+                rate.wait().await;
+            }
+            let page = clip::fetch(kb.web, &saved.url).await;
+            Some(page.and_then(|page| clipped(kb, id.clone(), page, saved.url.clone())))
+        }
+        None => None,
+    };
+    let mut doc = match fetched {
+        Some(Ok(doc)) => doc,
+        fetched => {
+            let title = saved.title.clone().unwrap_or_else(|| saved.url.clone());
+            let content = format!("# {title}\n\n<{}>\n", saved.url);
+            let mut doc = Document::new(
+                id,
+                title,
+                DocumentKind::Markdown,
+                content,
+                Some(saved.url.clone()),
+            );
+            doc.links = links::extract(&doc.content);
+            if let Some(Err(e)) = fetched {
+                tracing::warn!("{e:#}; keeping only the link");
+                doc.metadata
+                    .insert(FETCH_ERROR_KEY.into(), format!("{e:#}"));
+            }
+            doc
+        }
+    };
+    tags::apply(&mut doc.tags, &saved.tags, &[]);
+    if let Some(at) = saved.saved {
+        doc.added = at;
+        doc.metadata.insert("saved".into(), at.to_rfc3339());
+    }
+    doc.metadata.extend(saved.metadata.iter().cloned());
+    Ok(Prepared::Parsed(doc, input))
+}
+
+/// Passes on an imported document, unless it is tombstoned or, without
+/// `force`, the same as when it was last imported.
+fn prepare_document(kb: &Shared, doc: &Document, force: bool) -> Result<Prepared> {
+    let input = doc.source.clone().unwrap_or_else(|| doc.title.clone());
+    if let Some(skipped) = tombstoned(kb.tombstones, &doc.id, &input) {
+        return Ok(Prepared::Done(skipped));
+    }
+    if !force {
+        if let Some(stored) = kb.storage.get(&doc.id)? {
+            if stored.metadata.get(SOURCE_HASH_KEY) == doc.metadata.get(SOURCE_HASH_KEY) {
+                return Ok(Prepared::Done(unchanged(&stored, input)));
+            }
+        }
+    }
+    Ok(Prepared::Parsed(doc.clone(), input))
+}
+
+fn document(id: DocumentId, parsed: ParsedData, source: String) -> Document {
+    let mut doc = Document::new(id, parsed.title, parsed.kind, parsed.content, Some(source));
+    doc.links = parsed.links;
+    doc
+}
+
+pub fn unchanged(stored: &Document, input: String) -> Added {
+    tracing::debug!("{input} is unchanged since it was added as {}", stored.id);
+    Added {
+        input,
+        status: AddStatus::Unchanged,
+        id: stored.id.clone(),
+        title: Some(stored.title.clone()),
+        reason: None,
+        near_duplicate_of: None,
+        tagged: Vec::new(),
+        suggested: Vec::new(),
+    }
+}
+
+fn tombstoned(tombstones: &Tombstones, id: &DocumentId, input: &str) -> Option<Added> {
+    let tombstone = tombstones.get(id)?;
+    Some(Added {
+        input: input.to_string(),
+        status: AddStatus::Skipped,
+        id: id.clone(),
+        title: None,
+        reason: Some(format!(
+            "removed on {} (run `ozy rm --purge {id}` to allow re-adding)",
+            tombstone.deleted.format("%Y-%m-%d")
+        )),
+        near_duplicate_of: None,
+        tagged: Vec::new(),
+        suggested: Vec::new(),
+    })
+}
+
+/// Ingests a file written by a command, such as a new note, failing if
+/// it is skipped.
+pub fn file(kb: &mut KnowledgeBase, path: &Path) -> Result<Added> {
+    let ingest = Ingest::new(kb, false, false, 1)?;
+    let mut added = None;
+    ingest.files(kb, &[path.to_path_buf()], |_, result| {
+        added = Some(result?);
+        Ok(())
+    })?;
+    let added = added.with_context(|| format!("{} was not added", path.display()))?;
+    if added.status == AddStatus::Skipped {
+        let reason = added.reason.as_deref().unwrap_or_default();
+        bail!("{} was skipped: {reason}", path.display());
+    }
+    Ok(added)
+}
+
+pub fn print(added: &Added) {
+    let title = added.title.as_deref().unwrap_or(&added.input);
+    match added.status {
+        AddStatus::Skipped => println!(
+            "skipped {title}: {}",
+            added.reason.as_deref().unwrap_or_default()
+        ),
+        AddStatus::Added => println!("added {}  {title}", added.id),
+        AddStatus::Unchanged => println!("unchanged {}  {title}", added.id),
+        AddStatus::Pruned => println!("pruned {}  {title}", added.id),
+    }
+    if let Some(original) = &added.near_duplicate_of {
+        println!("  near duplicate of {original} (see `ozy dedupe report`)");
+    }
+    if !added.tagged.is_empty() {
+        println!("  tagged: {}", added.tagged.join(", "));
+    }
+    for s in &added.suggested {
+        println!("  suggested: {} ({:.2})", s.tag, s.score);
+    }
+}
+
+/// Whether `path` is to be ingested as part of the directory `root`:
+/// hidden files and directories, such as `.git`, the knowledge base itself
+/// or editor swap files, are skipped, and so are file types that neither
+/// ozy nor a parser plugin reads.
+pub fn included(root: &Path, path: &Path, plugins: &Plugins) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    if relative
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return false;
+    }
+    if path.is_dir() {
+        return true;
+    }
+    let supported = path.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+        EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()) || plugins.handles(e)
+    });
+    // Deleted directories have no extension and must still be handled.
+    supported || (!path.exists() && path.extension().is_none())
+}
+
+/// Collects the files below `dir` to ingest. Symlinks to files are
+/// followed, symlinks to directories are not: one leading back up the
+/// tree would be walked forever.
+pub fn walk(dir: &Path, plugins: &Plugins, files: &mut BTreeSet<PathBuf>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("cannot read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if !included(dir, &path, plugins) {
+            continue;
+        }
+        let kind = entry
+            .file_type()
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        if kind.is_dir() {
+            walk(&path, plugins, files)?;
+        } else if kind.is_symlink() && path.is_dir() {
+            tracing::debug!("not following the symlinked directory {}", path.display());
+        } else {
+            files.insert(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use anyhow::{bail, Result};
+
+use crate::cli::AliasCommand;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Aliases, Format};
+use crate::types::Document;
+use crate::undo::command;
+
+pub fn run(cmd: AliasCommand, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let listed = match cmd {
+        AliasCommand::Add { id, aliases } => {
+            let id = kb.resolve(&id)?;
+            let aliases = trim_all(&aliases)?;
+            kb.record_as(command(
+                "alias add",
+                std::iter::once(id.to_string()).chain(aliases.clone()),
+            ));
+            kb.realias(&id, &aliases, &[])?;
+            kb.commit()?;
+            vec![entry(&kb.get(&id)?)]
+        }
+        AliasCommand::Rm { id, aliases } => {
+            let id = kb.resolve(&id)?;
+            let aliases = trim_all(&aliases)?;
+            kb.record_as(command(
+                "alias rm",
+                std::iter::once(id.to_string()).chain(aliases.clone()),
+            ));
+            kb.realias(&id, &[], &aliases)?;
+            kb.commit()?;
+            vec![entry(&kb.get(&id)?)]
+        }
+        AliasCommand::List { id: Some(id) } => vec![entry(&kb.get(&kb.resolve(&id)?)?)],
+        AliasCommand::List { id: None } => {
+            let mut docs: Vec<Document> = kb
+                .storage
+                .all()?
+                .into_iter()
+                .filter(|d| !d.aliases.is_empty() || !d.former_ids.is_empty())
+                .collect();
+            docs.sort_by_key(|d| d.title.to_lowercase());
+            docs.iter().map(entry).collect()
+        }
+    };
+    format.print(&listed, |listed| {
+        for a in listed {
+            println!("{}  {}", a.id, a.title);
+            for alias in &a.aliases {
+                println!("  alias: {alias}");
+            }
+            for former in &a.former_ids {
+                println!("  former id: {former}");
+            }
+        }
+    })
+}
+
+fn trim_all(raw: &[String]) -> Result<Vec<String>> {
+    raw.iter()
+        .map(|a| match a.trim() {
+            "" => bail!("an alias cannot be empty"),
+            a => Ok(a.to_string()),
+        })
+        .collect()
+}
+
+fn entry(doc: &Document) -> Aliases {
+    Aliases {
+        id: doc.id.clone(),
+        title: doc.title.clone(),
+        aliases: doc.aliases.clone(),
+        former_ids: doc.former_ids.clone(),
+    }
+}
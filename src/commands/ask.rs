@@ -0,0 +1,70 @@
+use anyhow::Result;
+
+use crate::cli::AskArgs;
+use crate::index::snippet;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::llm_from_config;
+use crate::ml::rag::{answer, retrieve};
+use crate::output::{Answer, Format, Source};
+use crate::runtime;
+
+const SNIPPET_WIDTH: usize = 120;
+
+pub fn run(args: AskArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let passages = retrieve(&mut kb, &args.question, args.passages)?;
+    let llm = llm_from_config(&kb.config.llm);
+    let reply = match &llm {
+        Some(llm) if !passages.is_empty() => Some(runtime::block_on(answer(
+            llm.as_ref(),
+            &kb.prompts(),
+            &args.question,
+            &passages,
+        ))?),
+        _ => None,
+    };
+    let output = Answer {
+        question: args.question,
+        answer: reply,
+        sources: passages
+            .into_iter()
+            .map(|p| Source {
+                citation: p.citation(),
+                id: p.doc,
+                title: p.title,
+                start: p.start,
+                end: p.end,
+                text: p.text,
+            })
+            .collect(),
+    };
+    format.print(&output, |output| {
+        if output.sources.is_empty() {
+            println!(
+                "nothing in the knowledge base matches {:?}",
+                output.question
+            );
+            return;
+        }
+        match &output.answer {
+            Some(answer) => {
+                println!("{answer}");
+                println!();
+                println!("sources:");
+                for (n, source) in output.sources.iter().enumerate() {
+                    println!("  [{}] {}  {}", n + 1, source.citation, source.title);
+                }
+            }
+            None => {
+                println!("no language model is configured (set `provider` in [llm]); the most relevant passages are:");
+                for (n, source) in output.sources.iter().enumerate() {
+                    println!("  [{}] {}  {}", n + 1, source.citation, source.title);
+                    println!(
+                        "      {}",
+                        snippet(&source.text, &output.question, SNIPPET_WIDTH)
+                    );
+                }
+            }
+        }
+    })
+}
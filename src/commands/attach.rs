@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::attachments;
+use crate::cli::{AttachArgs, AttachmentsArgs};
+use crate::commands::list::human_size;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{AttachmentEntry, Format};
+use crate::types::Attachment;
+use crate::undo::command;
+
+pub fn run(args: AttachArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let id = kb.resolve(&args.id)?;
+    kb.record_as(command(
+        "attach",
+        std::iter::once(id.to_string()).chain(args.files.iter().map(|f| f.display().to_string())),
+    ));
+    let files = args
+        .files
+        .iter()
+        .map(|f| attachments::store(&kb.blobs, f))
+        .collect::<Result<Vec<_>>>()?;
+    let all = kb.attach(&id, files)?;
+    kb.commit()?;
+    let entries: Vec<AttachmentEntry> = all.iter().map(|a| entry(&kb, a, None)).collect();
+    format.print(entries.as_slice(), print)
+}
+
+pub fn list(args: AttachmentsArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let doc = kb.get(&kb.resolve(&args.id)?)?;
+    let mut entries = Vec::new();
+    for attachment in &doc.attachments {
+        let saved = match &args.save {
+            Some(dir) => save(&kb, attachment, dir)?,
+            None => None,
+        };
+        entries.push(entry(&kb, attachment, saved.as_deref()));
+    }
+    format.print(entries.as_slice(), print)
+}
+
+/// Copies an attachment into `dir` under its name, unless its blob is
+/// missing, and returns where it went.
+fn save(kb: &KnowledgeBase, attachment: &Attachment, dir: &Path) -> Result<Option<PathBuf>> {
+    let Some(bytes) = kb.blobs.get(&attachment.blob)? else {
+        tracing::warn!("the blob of {} is missing", attachment.name);
+        return Ok(None);
+    };
+    let path = dir.join(&attachment.name);
+    if !kb::dry_run() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        std::fs::write(&path, bytes)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(Some(path))
+}
+
+fn entry(kb: &KnowledgeBase, attachment: &Attachment, saved: Option<&Path>) -> AttachmentEntry {
+    let stored = kb.blobs.path(&attachment.blob);
+    AttachmentEntry {
+        name: attachment.name.clone(),
+        mime: attachment.mime.clone(),
+        blob: attachment.blob.clone(),
+        thumbnail: attachment.thumbnail.clone(),
+        size: std::fs::metadata(&stored).ok().map(|m| m.len()),
+        path: saved.map_or(stored, Path::to_path_buf),
+    }
+}
+
+fn print(entries: &[AttachmentEntry]) {
+    for e in entries {
+        let size = e
+            .size
+            .map_or_else(|| "missing".into(), |s| human_size(s as usize));
+        println!("{:>8}  {}  {}", size, e.name, e.path.display());
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::cli::BackupArgs;
+use crate::commands::stats::human_bytes;
+use crate::kb::{self, KnowledgeBase, KB_DIR};
+use crate::output::{BackedUp, Format};
+use crate::progress::Progress;
+
+pub fn run(args: BackupArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let backed_up = backup(&kb, args.file)?;
+    format.print(&backed_up, |b| {
+        println!(
+            "backed up {} files ({}) to {}",
+            b.files,
+            human_bytes(b.bytes),
+            b.file.display()
+        );
+    })
+}
+
+/// Zips the knowledge base into `file`, replacing it only once the
+/// archive is complete.
+pub fn backup(kb: &KnowledgeBase, file: PathBuf) -> Result<BackedUp> {
+    let mut files = Vec::new();
+    collect(&kb.root, &mut files)?;
+    files.sort();
+
+    // Written next to the archive first, so that an interrupted backup
+    // leaves the previous one in place.
+    let tmp = file.with_extension("zip.tmp");
+    let written = write(&kb.root, &files, &tmp);
+    let bytes = match written {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(e.context(format!("failed to write {}", file.display())));
+        }
+    };
+    std::fs::rename(&tmp, &file).with_context(|| format!("failed to write {}", file.display()))?;
+    Ok(BackedUp {
+        file,
+        files: files.len(),
+        bytes,
+    })
+}
+
+/// Collects the files below `dir`, leaving out the locks of running
+/// commands and the leftovers of interrupted writes.
+fn collect(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(&path, files)?;
+            continue;
+        }
+        let skipped = path.extension().is_some_and(|e| e == "tmp" || e == "lock")
+            || path.file_name().is_some_and(|n| n == ".lock");
+        if !skipped {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Zips `files` under the knowledge base directory's own name, so that
+/// unpacking the archive in a directory restores the knowledge base
+/// there. Returns the bytes backed up.
+fn write(root: &Path, files: &[PathBuf], path: &Path) -> Result<u64> {
+    let file =
+        File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    let progress = Progress::new("files backed up", files.len());
+    let mut total = 0;
+    for file in files {
+        let relative = file.strip_prefix(root).unwrap_or(file);
+        let name = Path::new(KB_DIR).join(relative);
+        let name = name.to_string_lossy().replace('\\', "/");
+        let bytes =
+            std::fs::read(file).with_context(|| format!("failed to read {}", file.display()))?;
+        zip.start_file(name, options)?;
+        zip.write_all(&bytes)?;
+        total += bytes.len() as u64;
+        progress.inc(1);
+    }
+    zip.finish()?;
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+    use crate::kb::scratch::Scratch;
+    use crate::types::{Document, DocumentId, DocumentKind};
+
+    #[test]
+    fn the_archive_holds_every_file_under_the_knowledge_base_directory() {
+        let mut scratch = Scratch::new("backup");
+        let doc = Document::new(
+            DocumentId::derive("notes"),
+            "Notes".into(),
+            DocumentKind::Markdown,
+            "Some notes.".into(),
+            None,
+        );
+        scratch.kb.insert(&doc).unwrap();
+        let blob = scratch.kb.blobs.put(b"attached").unwrap();
+        scratch.kb.commit().unwrap();
+        std::fs::write(scratch.kb.root.join("index.tmp"), "interrupted").unwrap();
+
+        let file = scratch.dir.join("backup.zip");
+        let backed_up = backup(&scratch.kb, file.clone()).unwrap();
+        assert!(!file.with_extension("zip.tmp").exists());
+
+        let mut zip = zip::ZipArchive::new(File::open(&file).unwrap()).unwrap();
+        assert_eq!(zip.len(), backed_up.files);
+        let names: Vec<String> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().unwrap().into_owned())
+            .collect();
+        assert!(names.iter().all(|n| n.starts_with(&format!("{KB_DIR}/"))));
+        assert!(!names.iter().any(|n| n.ends_with(".tmp")));
+        let mut total = 0;
+        for name in &names {
+            let mut bytes = Vec::new();
+            zip.by_name(name).unwrap().read_to_end(&mut bytes).unwrap();
+            let relative = name.strip_prefix(&format!("{KB_DIR}/")).unwrap();
+            assert_eq!(
+                bytes,
+                std::fs::read(scratch.kb.root.join(relative)).unwrap()
+            );
+            total += bytes.len() as u64;
+        }
+        assert_eq!(total, backed_up.bytes);
+        let document = format!("{KB_DIR}/documents/{}.json", doc.id);
+        assert!(names.contains(&document), "{names:?}");
+        assert!(names.iter().any(|n| n.ends_with(&blob)), "{names:?}");
+    }
+}
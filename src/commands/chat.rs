@@ -0,0 +1,114 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{Context, Result};
+
+use crate::cli::ChatArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::llm_from_config;
+use crate::ml::rag::{prompt, retrieve, Passage, Turn};
+use crate::runtime;
+use crate::types::DocumentId;
+
+/// Earlier exchanges sent along with each question.
+const MEMORY: usize = 6;
+
+const HELP: &str = "\
+/open <n|id>  show source n of the last answer, or a document
+/sources      list the sources of the last answer
+/reset        forget the conversation
+/quit         leave (or press Ctrl-D)";
+
+pub fn run(args: ChatArgs) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let llm = llm_from_config(&kb.config.llm)
+        .context("ozy chat needs a language model; set `provider` in the [llm] config section")?;
+    println!(
+        "chatting with {} about this knowledge base; /help lists commands",
+        llm.name()
+    );
+
+    let prompts = kb.prompts();
+    let mut history: Vec<Turn> = Vec::new();
+    let mut sources: Vec<Passage> = Vec::new();
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            println!();
+            return Ok(());
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(command) = line.strip_prefix('/') {
+            let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+            match command {
+                "open" => open(&kb, &sources, arg.trim())?,
+                "sources" => print_sources(&sources),
+                "reset" => {
+                    history.clear();
+                    sources.clear();
+                    println!("conversation forgotten");
+                }
+                "quit" | "exit" => return Ok(()),
+                "help" => println!("{HELP}"),
+                other => println!("unknown command /{other}; /help lists commands"),
+            }
+            continue;
+        }
+
+        // Follow-up questions often lean on the previous one ("and when
+        // was she born?"), so retrieval sees both.
+        let search = match history.last() {
+            Some(turn) => format!("{} {line}", turn.question),
+            None => line.to_string(),
+        };
+        sources = retrieve(&mut kb, &search, args.passages)?;
+        let prompt = prompt(&prompts, line, &sources, &history)?;
+        let answer = runtime::block_on(llm.stream(&prompt, &mut |token| {
+            print!("{token}");
+            let _ = io::stdout().flush();
+        }))?;
+        println!();
+        history.push(Turn {
+            question: line.to_string(),
+            answer,
+        });
+        if history.len() > MEMORY {
+            history.remove(0);
+        }
+    }
+}
+
+fn print_sources(sources: &[Passage]) {
+    if sources.is_empty() {
+        println!("no sources yet");
+    }
+    for (n, passage) in sources.iter().enumerate() {
+        println!("  [{}] {}  {}", n + 1, passage.citation(), passage.title);
+    }
+}
+
+fn open(kb: &KnowledgeBase, sources: &[Passage], arg: &str) -> Result<()> {
+    let id = match arg.parse::<usize>() {
+        Ok(n) if (1..=sources.len()).contains(&n) => sources[n - 1].doc.clone(),
+        _ => match kb.resolve(arg) {
+            Ok(id) => id,
+            Err(e) => {
+                println!("{e:#}");
+                return Ok(());
+            }
+        },
+    };
+    print_document(kb, &id)
+}
+
+fn print_document(kb: &KnowledgeBase, id: &DocumentId) -> Result<()> {
+    let doc = kb.get(id)?;
+    println!("{}  {}", doc.id, doc.title);
+    println!("{}", doc.content.trim_end());
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use anyhow::Result;
+
+use crate::cli::CitesArgs;
+use crate::graph::{EdgeKind, NodeId};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{CitationRef, Format};
+use crate::relations::RelationKind;
+
+/// `ozy cites`: the papers a document cites.
+pub fn run(args: CitesArgs, format: Format) -> Result<()> {
+    citations(args, format, true)
+}
+
+/// `ozy cited-by`: the documents citing a paper.
+pub fn cited_by(args: CitesArgs, format: Format) -> Result<()> {
+    citations(args, format, false)
+}
+
+fn citations(args: CitesArgs, format: Format, outgoing: bool) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let id = kb.resolve(&args.id)?;
+    let cites = EdgeKind::Related(RelationKind::Cites);
+    let mut refs = Vec::new();
+    for n in kb.graph.neighbors(&NodeId::Document(id)) {
+        if n.outgoing != outgoing || n.edge.kind != cites {
+            continue;
+        }
+        let NodeId::Document(other) = n.node else {
+            continue;
+        };
+        refs.push(CitationRef {
+            id: other.clone(),
+            title: kb
+                .graph
+                .node(n.node)
+                .map(|node| node.label.clone())
+                .unwrap_or_default(),
+            confidence: n.edge.confidence,
+        });
+    }
+    refs.sort_by_key(|r| r.title.to_lowercase());
+    format.print(&refs, |refs| {
+        for r in refs {
+            match r.confidence.filter(|&c| c < 1.0) {
+                Some(c) => println!("{}  {}  ({c:.2})", r.id, r.title),
+                None => println!("{}  {}", r.id, r.title),
+            }
+        }
+    })
+}
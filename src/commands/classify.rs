@@ -0,0 +1,70 @@
+use anyhow::{bail, Result};
+
+use crate::cli::ClassifyArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::ontology::{Evidence, Ontology, ONTOLOGY_FILE};
+use crate::output::{Classified, ConceptMatch, Format};
+
+pub fn run(args: ClassifyArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let rules = &kb.config.classification.rules;
+    let default = Ontology::default();
+    let ontology = match &kb.ontology {
+        Some(ontology) => ontology,
+        None if !rules.is_empty() => &default,
+        None => bail!(
+            "no ontology or classification rules defined (create {})",
+            kb.root.join(ONTOLOGY_FILE).display()
+        ),
+    };
+    let mut classified = Vec::new();
+    for arg in &args.ids {
+        let doc = kb.get(&kb.resolve(arg)?)?;
+        let concepts = ontology
+            .classify(&doc, rules)
+            .into_iter()
+            .map(|c| {
+                let (rule, mentions) = match c.evidence {
+                    Evidence::Rule(i) => (Some(i + 1), None),
+                    Evidence::Mentions(n) => (None, Some(n)),
+                };
+                ConceptMatch {
+                    broader: ontology
+                        .ancestors(&c.concept)
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                    concept: c.concept,
+                    rule,
+                    mentions,
+                }
+            })
+            .collect();
+        classified.push(Classified {
+            id: doc.id,
+            title: doc.title,
+            concepts,
+        });
+    }
+    format.print(&classified, |classified| {
+        for doc in classified {
+            println!("{}  {}", doc.id, doc.title);
+            if doc.concepts.is_empty() {
+                println!("  no matching concepts");
+            }
+            for c in &doc.concepts {
+                let broader = if c.broader.is_empty() {
+                    String::new()
+                } else {
+                    format!("  (within {})", c.broader.join(", "))
+                };
+                let evidence = match (c.rule, c.mentions) {
+                    (Some(rule), _) => format!("rule {rule}"),
+                    (None, n) => format!("{} mentions", n.unwrap_or_default()),
+                };
+                let line = format!("  {:<24} {evidence:<12}{broader}", c.concept);
+                println!("{}", line.trim_end());
+            }
+        }
+    })
+}
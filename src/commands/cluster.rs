@@ -0,0 +1,76 @@
+use anyhow::{bail, Result};
+
+use crate::cli::ClusterArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::cluster::{kmeans, label};
+use crate::output::{Clusters, DocumentRef, Format, Topic};
+use crate::tags;
+use crate::types::Document;
+
+pub fn run(args: ClusterArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let docs = kb.storage.all()?;
+    let embedded: Vec<(&Document, &[f32])> = docs
+        .iter()
+        .filter_map(|d| Some((d, kb.vectors.get(&d.id)?)))
+        .collect();
+    if embedded.len() < 2 {
+        bail!("clustering needs at least two documents with embeddings");
+    }
+    let k = args
+        .k
+        .unwrap_or_else(|| ((embedded.len() as f64 / 2.0).sqrt().round() as usize).max(2));
+    if k == 0 {
+        bail!("-k must be at least 1");
+    }
+    let vectors: Vec<&[f32]> = embedded.iter().map(|(_, v)| *v).collect();
+    let assignment = kmeans(&vectors, k);
+
+    let mut clusters: Vec<Vec<&Document>> = vec![Vec::new(); k.min(embedded.len())];
+    for ((doc, _), c) in embedded.iter().zip(assignment) {
+        clusters[c].push(doc);
+    }
+    clusters.retain(|c| !c.is_empty());
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.len()));
+
+    let analyzer = kb.index.analyzer().clone();
+    let mut topics = Vec::new();
+    let mut tagging = Vec::new();
+    for members in &clusters {
+        let keywords = label(members, &docs, &analyzer, args.keywords);
+        if let (Some(prefix), Some(top)) = (&args.tag, keywords.first()) {
+            let tag = tags::normalize(&format!("{prefix}/{top}"))?;
+            tagging.extend(members.iter().map(|d| (d.id.clone(), tag.clone())));
+        }
+        topics.push(Topic {
+            keywords,
+            documents: members.iter().map(|d| DocumentRef::from(*d)).collect(),
+        });
+    }
+    for (id, tag) in &tagging {
+        kb.retag(id, std::slice::from_ref(tag), &[])?;
+    }
+    if !tagging.is_empty() {
+        kb.commit()?;
+    }
+    let output = Clusters {
+        clusters: topics,
+        tagged: tagging.len(),
+    };
+    format.print(&output, |output| {
+        for (n, topic) in output.clusters.iter().enumerate() {
+            println!(
+                "cluster {}: {} ({} documents)",
+                n + 1,
+                topic.keywords.join(", "),
+                topic.documents.len()
+            );
+            for doc in &topic.documents {
+                println!("  {}  {}", doc.id, doc.title);
+            }
+        }
+        if output.tagged > 0 {
+            println!("tagged {} documents", output.tagged);
+        }
+    })
+}
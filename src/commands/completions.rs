@@ -0,0 +1,8 @@
+use anyhow::Result;
+
+use crate::cli::CompletionsArgs;
+use crate::completion;
+
+pub fn run(args: CompletionsArgs) -> Result<()> {
+    completion::write_script(args.shell, !args.static_only, &mut std::io::stdout())
+}
@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::ConfigCommand;
+use crate::config::{self, Config, Layers, Origin};
+use crate::error::OzymandiasError;
+use crate::kb::{self, KB_DIR};
+use crate::output::{Format, Setting, SettingChanged};
+
+pub fn run(cmd: ConfigCommand, format: Format) -> Result<()> {
+    // The knowledge base is not opened, so that a broken config can still
+    // be read and fixed.
+    let kb_root = kb::dir()?.join(KB_DIR);
+    let kb_root = kb_root.is_dir().then_some(kb_root);
+    match cmd {
+        ConfigCommand::Get { key } => {
+            let layers = Layers::load(kb_root.as_deref())?;
+            let merged = layers.merged();
+            let value = config::get(&merged, &key)
+                .with_context(|| format!("{key} is not set"))?
+                .clone();
+            // A table comes from the highest layer setting any key in it.
+            let origin = layers
+                .settings()
+                .into_iter()
+                .filter(|(k, _)| *k == key || k.starts_with(&format!("{key}.")))
+                .map(|(_, (_, origin))| origin)
+                .max()
+                .unwrap_or(Origin::Default);
+            let setting = Setting { key, value, origin };
+            format.print(&setting, |s| match &s.value {
+                toml::Value::String(s) => println!("{s}"),
+                toml::Value::Table(table) => print!("{table}"),
+                value => println!("{value}"),
+            })?;
+        }
+        ConfigCommand::Set { key, value, user } => {
+            let file = file(kb_root, user)?;
+            let value = config::parse_value(&value);
+            config::edit_file(&file, |table| {
+                config::set(table, &key, value.clone())?;
+                known(table, &key)
+            })?;
+            let changed = SettingChanged { file, key, value };
+            format.print(&changed, |c| {
+                println!("set {} = {} in {}", c.key, c.value, c.file.display())
+            })?;
+        }
+        ConfigCommand::List { show_origin } => {
+            let settings: Vec<Setting> = Layers::load(kb_root.as_deref())?
+                .settings()
+                .into_iter()
+                .map(|(key, (value, origin))| Setting { key, value, origin })
+                .collect();
+            format.print(&settings, |settings| {
+                for s in settings {
+                    if show_origin {
+                        print!("{:<15} ", s.origin.name());
+                    }
+                    println!("{} = {}", s.key, s.value);
+                }
+            })?;
+        }
+        ConfigCommand::Edit { user } => {
+            let file = file(kb_root, user)?;
+            if let Some(dir) = file.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+            }
+            open_editor(&file)?;
+            config::read_file(&file)
+                .context("the file was saved, but run `ozy config edit` again to fix it")?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens `file` in `$VISUAL`, `$EDITOR` or vi, and waits for it to close.
+pub fn open_editor(file: &Path) -> Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("the editor command is empty")?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(file)
+        .status()
+        .with_context(|| format!("failed to run {editor}"))?;
+    if !status.success() {
+        bail!("{editor} exited with {status}");
+    }
+    Ok(())
+}
+
+/// The file `set` and `edit` change.
+fn file(kb_root: Option<PathBuf>, user: bool) -> Result<PathBuf> {
+    if user {
+        return config::user_path();
+    }
+    match kb_root {
+        Some(root) => Ok(Config::path(&root)),
+        None => bail!("no knowledge base found here; pass --user to change the user's config"),
+    }
+}
+
+/// Fails for keys no setting answers to, which would be silently ignored.
+fn known(table: &toml::Table, key: &str) -> Result<()> {
+    let config: Config = toml::Value::Table(table.clone())
+        .try_into()
+        .with_context(|| OzymandiasError::ConfigInvalid(format!("invalid value for {key}")))?;
+    let understood = toml::Table::try_from(config)?;
+    if config::get(&understood, key).is_none() {
+        bail!(OzymandiasError::NotFound(format!("unknown setting {key}")));
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::{bail, Result};
+
+use crate::cli::DedupeCommand;
+use crate::fingerprint;
+use crate::history;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{DocumentSummary, DuplicateCluster, Format, Merged};
+use crate::types::{Document, DocumentId};
+use crate::undo::command;
+
+/// Width of the terminal when it cannot be told.
+const DEFAULT_COLUMNS: usize = 80;
+
+pub fn run(cmd: DedupeCommand, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        DedupeCommand::Report { threshold } => {
+            let threshold = check_threshold(&kb, threshold)?;
+            let docs = kb.storage.all()?;
+            let clusters: Vec<DuplicateCluster> = fingerprint::clusters(&docs, threshold)
+                .into_iter()
+                .map(|cluster| DuplicateCluster {
+                    similarity: cluster.similarity,
+                    documents: cluster
+                        .members
+                        .iter()
+                        .map(|id| {
+                            let doc = docs.iter().find(|d| d.id == *id).expect("clustered");
+                            DocumentSummary::from(doc)
+                        })
+                        .collect(),
+                })
+                .collect();
+            format.print(&clusters, |clusters| {
+                if clusters.is_empty() {
+                    println!("no duplicates above {threshold:.2}");
+                    return;
+                }
+                for (n, cluster) in clusters.iter().enumerate() {
+                    println!(
+                        "cluster {} ({} documents, similarity {:.2})",
+                        n + 1,
+                        cluster.documents.len(),
+                        cluster.similarity
+                    );
+                    for doc in &cluster.documents {
+                        println!(
+                            "  {}  {}  {}",
+                            doc.id,
+                            doc.added.format("%Y-%m-%d"),
+                            doc.title
+                        );
+                    }
+                }
+                println!(
+                    "merge with `ozy dedupe merge <keep> <duplicates>...`, keeping the oldest \
+                     listed first"
+                );
+            })?;
+        }
+        DedupeCommand::Resolve { threshold } => {
+            let threshold = check_threshold(&kb, threshold)?;
+            let merged = resolve(&mut kb, threshold)?;
+            format.print(&merged, |merged| {
+                for m in merged {
+                    println!("merged {} into {}", m.duplicate, m.into);
+                }
+            })?;
+        }
+        DedupeCommand::Merge { keep, duplicates } => {
+            let keep = kb.resolve(&keep)?;
+            let duplicates = duplicates
+                .iter()
+                .map(|d| kb.resolve(d))
+                .collect::<Result<Vec<_>>>()?;
+            let ids = std::iter::once(&keep)
+                .chain(&duplicates)
+                .map(|id| id.to_string());
+            kb.record_as(command("dedupe merge", ids));
+            let mut merged = Vec::new();
+            for duplicate in duplicates {
+                kb.merge(&keep, &duplicate)?;
+                merged.push(Merged {
+                    duplicate,
+                    into: keep.clone(),
+                });
+            }
+            kb.commit()?;
+            format.print(&merged, |merged| {
+                for m in merged {
+                    println!("merged {} into {}", m.duplicate, m.into);
+                }
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn check_threshold(kb: &KnowledgeBase, threshold: Option<f32>) -> Result<f32> {
+    let threshold = threshold.unwrap_or(kb.config.pipeline.near_duplicate_threshold);
+    if !(0.0..=1.0).contains(&threshold) {
+        bail!("--threshold must be between 0 and 1");
+    }
+    Ok(threshold)
+}
+
+/// Shows each cluster with every document compared to the oldest, and
+/// merges the others into the one picked, one undoable merge per cluster.
+fn resolve(kb: &mut KnowledgeBase, threshold: f32) -> Result<Vec<Merged>> {
+    let docs = kb.storage.all()?;
+    let clusters = fingerprint::clusters(&docs, threshold);
+    let columns = ratatui::crossterm::terminal::size()
+        .map_or(DEFAULT_COLUMNS, |(width, _)| usize::from(width));
+    let width = (columns.saturating_sub(3) / 2).max(20);
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut merged = Vec::new();
+    for (n, cluster) in clusters.iter().enumerate() {
+        let members: Vec<&Document> = cluster
+            .members
+            .iter()
+            .filter_map(|id| docs.iter().find(|d| d.id == *id))
+            .collect();
+        println!(
+            "\ncluster {} of {} (similarity {:.2})",
+            n + 1,
+            clusters.len(),
+            cluster.similarity
+        );
+        for (i, doc) in members.iter().enumerate() {
+            println!(
+                "  {})  {}  {}  {}{}",
+                i + 1,
+                doc.id,
+                doc.added.format("%Y-%m-%d"),
+                doc.title,
+                describe(doc)
+            );
+        }
+        let first = members[0];
+        for (i, doc) in members.iter().enumerate().skip(1) {
+            println!("\n1) {}  vs  {}) {}", first.id, i + 1, doc.id);
+            if doc.content == first.content {
+                println!("(the same content)");
+            } else {
+                print!(
+                    "{}",
+                    history::side_by_side(&first.content, &doc.content, width)
+                );
+            }
+        }
+        let Some(keep) = ask_keep(&mut lines, members.len())? else {
+            break;
+        };
+        let Some(keep) = keep else {
+            continue;
+        };
+        let keep: &DocumentId = &members[keep].id;
+        let duplicates: Vec<&DocumentId> = members
+            .iter()
+            .map(|d| &d.id)
+            .filter(|id| *id != keep)
+            .collect();
+        let ids = std::iter::once(keep)
+            .chain(duplicates.iter().copied())
+            .map(|id| id.to_string());
+        kb.record_as(command("dedupe merge", ids));
+        for duplicate in duplicates {
+            kb.merge(keep, duplicate)?;
+            merged.push(Merged {
+                duplicate: duplicate.clone(),
+                into: keep.clone(),
+            });
+        }
+        kb.commit()?;
+    }
+    Ok(merged)
+}
+
+/// The tags and metadata a document would bring to a merge.
+fn describe(doc: &Document) -> String {
+    let mut parts = Vec::new();
+    if !doc.tags.is_empty() {
+        parts.push(
+            doc.tags
+                .iter()
+                .map(|t| format!("#{t}"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+    }
+    if !doc.metadata.is_empty() {
+        let keys: Vec<&str> = doc.metadata.keys().map(String::as_str).collect();
+        parts.push(format!("metadata: {}", keys.join(", ")));
+    }
+    match parts.is_empty() {
+        true => String::new(),
+        false => format!("  [{}]", parts.join("; ")),
+    }
+}
+
+/// The index of the document to keep, `Some(None)` to leave the cluster as
+/// it is, or `None` to stop.
+fn ask_keep(
+    lines: &mut impl Iterator<Item = io::Result<String>>,
+    count: usize,
+) -> Result<Option<Option<usize>>> {
+    loop {
+        print!("keep which (1-{count}), merging the others into it; s to skip, q to stop: ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(None);
+        };
+        match line.trim() {
+            "q" | "quit" => return Ok(None),
+            "s" | "skip" | "" => return Ok(Some(None)),
+            answer => match answer.parse::<usize>() {
+                Ok(n) if (1..=count).contains(&n) => return Ok(Some(Some(n - 1))),
+                _ => println!("expected a number from 1 to {count}"),
+            },
+        }
+    }
+}
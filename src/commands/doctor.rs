@@ -0,0 +1,377 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+
+use crate::cli::DoctorArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, Problem};
+use crate::storage;
+use crate::types::{Document, DocumentId};
+
+pub fn run(args: DoctorArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let problems = check(&mut kb, args.fix)?;
+    let left = problems.iter().filter(|p| !p.fixed).count();
+    format.print(&problems, |problems| {
+        for p in problems {
+            let id =
+                p.id.as_ref()
+                    .map(|id| format!("{id}  "))
+                    .unwrap_or_default();
+            let state = match (p.fixed, p.fixable) {
+                (true, _) => "  (fixed)",
+                (false, true) => "  (fixable with --fix)",
+                (false, false) => "",
+            };
+            println!("{:<16} {id}{}{state}", p.check, p.message);
+        }
+        match problems.len() {
+            0 => println!("no problems found"),
+            n => println!("{n} problems, {} fixed", n - left),
+        }
+    })?;
+    if left > 0 {
+        bail!("{left} problems remain");
+    }
+    Ok(())
+}
+
+/// How `--fix` repairs a problem.
+enum Repair {
+    /// Moves an unreadable document aside, to `corrupt/`.
+    SetAside(DocumentId),
+    /// Embeds every document again with the configured model.
+    Reembed,
+    Insert(Box<Document>),
+    DropIndexEntry(DocumentId),
+    DropEmbedding(DocumentId),
+    RemoveBlob(String),
+}
+
+impl Repair {
+    /// Repairs are made in this order: unreadable documents are set
+    /// aside before committing rebuilds links and the graph from every
+    /// document, and documents are only embedded one by one once the
+    /// vectors are of the configured model.
+    fn rank(&self) -> u8 {
+        match self {
+            Repair::SetAside(_) => 0,
+            Repair::Reembed => 1,
+            _ => 2,
+        }
+    }
+
+    fn make(self, kb: &mut KnowledgeBase) -> Result<()> {
+        match self {
+            Repair::SetAside(id) => {
+                if !kb::dry_run() {
+                    let to = storage::quarantine(&kb.root, &id)?;
+                    tracing::info!("moved {id} to {}", to.display());
+                }
+                kb.index.remove(&id);
+                kb.vectors.remove(&id);
+            }
+            Repair::Reembed => {
+                kb.reembed()?;
+            }
+            Repair::Insert(doc) => kb.insert(&doc)?,
+            Repair::DropIndexEntry(id) => kb.index.remove(&id),
+            Repair::DropEmbedding(id) => kb.vectors.remove(&id),
+            Repair::RemoveBlob(hash) => kb.blobs.remove(&hash)?,
+        }
+        Ok(())
+    }
+}
+
+/// The problems of the knowledge base, repaired where possible if `fix`
+/// is set.
+pub fn check(kb: &mut KnowledgeBase, fix: bool) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    let mut repairs = Vec::new();
+    let mut problem = |check, id: Option<&DocumentId>, message: String, repair: Option<Repair>| {
+        let fixable = repair.is_some();
+        repairs.extend(repair.map(|repair| (problems.len(), repair)));
+        problems.push(Problem {
+            check,
+            id: id.cloned(),
+            message,
+            fixable,
+            fixed: false,
+        });
+    };
+
+    let version = kb.format_version()?;
+    if version < kb::FORMAT_VERSION {
+        problem(
+            "format-version",
+            None,
+            format!(
+                "the knowledge base is in format {version}, older than format {} \
+                 (run `ozy migrate`)",
+                kb::FORMAT_VERSION
+            ),
+            None,
+        );
+    } else if version > kb::FORMAT_VERSION {
+        problem(
+            "format-version",
+            None,
+            format!(
+                "the knowledge base is in format {version}, newer than this version of ozy reads \
+                 (format {})",
+                kb::FORMAT_VERSION
+            ),
+            None,
+        );
+    }
+
+    // Documents are read one by one, so that one unreadable file does not
+    // hide the state of the others.
+    let mut docs: Vec<Document> = Vec::new();
+    for id in kb.storage.ids()? {
+        match kb.storage.get(&id) {
+            Ok(Some(doc)) => docs.push(doc),
+            Ok(None) => {}
+            Err(e) => {
+                let message = format!("{e:#} (fixing moves it to corrupt/)");
+                problem(
+                    "unreadable",
+                    Some(&id),
+                    message,
+                    Some(Repair::SetAside(id.clone())),
+                );
+            }
+        }
+    }
+    let stored: BTreeSet<&DocumentId> = docs.iter().map(|d| &d.id).collect();
+
+    for doc in &docs {
+        if !kb.index.contains(&doc.id) {
+            problem(
+                "unindexed",
+                Some(&doc.id),
+                "missing from the full-text index".into(),
+                Some(Repair::Insert(Box::new(doc.clone()))),
+            );
+        } else if kb.vectors.get(&doc.id).is_none() {
+            problem(
+                "unembedded",
+                Some(&doc.id),
+                "has no embedding".into(),
+                Some(Repair::Insert(Box::new(doc.clone()))),
+            );
+        }
+        if let Some(source) = local_source(doc) {
+            if !source.exists() {
+                problem(
+                    "missing-source",
+                    Some(&doc.id),
+                    format!("source file {} no longer exists", source.display()),
+                    None,
+                );
+            }
+        }
+    }
+    for id in kb.index.ids().filter(|id| !stored.contains(id)) {
+        problem(
+            "stale-index",
+            Some(id),
+            "indexed, but no such document is stored".into(),
+            Some(Repair::DropIndexEntry(id.clone())),
+        );
+    }
+    for id in kb
+        .vectors
+        .ids()
+        .into_iter()
+        .filter(|id| !stored.contains(id))
+    {
+        problem(
+            "stale-embedding",
+            Some(id),
+            "embedded, but no such document is stored".into(),
+            Some(Repair::DropEmbedding(id.clone())),
+        );
+    }
+
+    let model = kb.embedder()?.name();
+    if let Some(used) = kb.vectors.model().filter(|m| *m != model) {
+        problem(
+            "embedding-model",
+            None,
+            format!("vectors were made with {used}, but {model} is configured"),
+            Some(Repair::Reembed),
+        );
+    }
+
+    let referenced = kb.referenced_blobs(&docs)?;
+    for hash in kb.blobs.hashes()? {
+        if !referenced.contains(&hash) {
+            problem(
+                "orphaned-blob",
+                None,
+                format!("blob {hash} belongs to no document"),
+                Some(Repair::RemoveBlob(hash)),
+            );
+        }
+    }
+
+    if fix {
+        repairs.sort_by_key(|(_, repair)| repair.rank());
+        let mut made = Vec::new();
+        for (i, repair) in repairs {
+            match repair.make(kb) {
+                Ok(()) => made.push(i),
+                Err(e) => tracing::warn!(
+                    "could not fix {} {}: {e:#}",
+                    problems[i].check,
+                    problems[i].message
+                ),
+            }
+        }
+        kb.commit()?;
+        for i in made {
+            problems[i].fixed = true;
+        }
+    }
+    Ok(problems)
+}
+
+/// The file a document was added from, unless it came from the web or
+/// was imported from an export, such as `notion:/Page.md`.
+fn local_source(doc: &Document) -> Option<&Path> {
+    let source = doc.source.as_deref()?;
+    let scheme = source.split_once(':').map_or("", |(scheme, _)| scheme);
+    // A single letter is a Windows drive.
+    let is_url = scheme.len() > 1 && scheme.chars().all(|c| c.is_ascii_alphanumeric());
+    (!is_url).then(|| Path::new(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::{trash, undo};
+    use crate::kb::scratch::Scratch;
+    use crate::types::{Attachment, DocumentKind};
+
+    const BYTES: &[u8] = b"attached bytes";
+
+    /// Stores a document with an attachment, returning its id and the hash
+    /// of the attachment's blob.
+    fn attached(kb: &mut KnowledgeBase) -> (DocumentId, String) {
+        let doc = Document::new(
+            DocumentId::derive("notes"),
+            "Notes".into(),
+            DocumentKind::Markdown,
+            "Some notes.".into(),
+            None,
+        );
+        kb.insert(&doc).unwrap();
+        let blob = kb.blobs.put(BYTES).unwrap();
+        let file = Attachment {
+            name: "notes.txt".into(),
+            mime: None,
+            blob: blob.clone(),
+            thumbnail: None,
+        };
+        kb.attach(&doc.id, vec![file]).unwrap();
+        kb.commit().unwrap();
+        (doc.id, blob)
+    }
+
+    fn orphans(problems: &[Problem]) -> usize {
+        problems
+            .iter()
+            .filter(|p| p.check == "orphaned-blob")
+            .count()
+    }
+
+    #[test]
+    fn blobs_no_document_needs_are_removed() {
+        let mut scratch = Scratch::new("doctor-orphan");
+        let (id, blob) = attached(&mut scratch.kb);
+        scratch.kb.remove(&id, true).unwrap();
+        scratch.kb.commit().unwrap();
+
+        let problems = check(&mut scratch.kb, true).unwrap();
+        assert_eq!(orphans(&problems), 1);
+        assert!(problems.iter().all(|p| p.fixed));
+        assert_eq!(scratch.kb.blobs.get(&blob).unwrap(), None);
+    }
+
+    #[test]
+    fn a_trashed_document_is_restored_with_its_blobs_after_a_fix() {
+        let mut scratch = Scratch::new("doctor-trash");
+        let (id, blob) = attached(&mut scratch.kb);
+        scratch.kb.remove(&id, false).unwrap();
+        scratch.kb.commit().unwrap();
+
+        let problems = check(&mut scratch.kb, true).unwrap();
+        assert_eq!(orphans(&problems), 0);
+        scratch.reopen();
+        trash::restore(&mut scratch.kb, vec![id.clone()]).unwrap();
+        assert_eq!(scratch.kb.get(&id).unwrap().attachments[0].blob, blob);
+        assert_eq!(scratch.kb.blobs.get(&blob).unwrap().as_deref(), Some(BYTES));
+    }
+
+    #[test]
+    fn a_purge_is_undone_with_its_blobs_after_a_fix() {
+        let mut scratch = Scratch::new("doctor-undo");
+        let (id, blob) = attached(&mut scratch.kb);
+        scratch.kb.record_as("rm --purge".into());
+        scratch.kb.remove(&id, true).unwrap();
+        scratch.kb.commit().unwrap();
+
+        let problems = check(&mut scratch.kb, true).unwrap();
+        assert_eq!(orphans(&problems), 0);
+        scratch.reopen();
+        let undone = undo::undo_last(&mut scratch.kb).unwrap();
+        assert_eq!(undone.restored, std::slice::from_ref(&id));
+        assert_eq!(scratch.kb.get(&id).unwrap().attachments[0].blob, blob);
+        assert_eq!(scratch.kb.blobs.get(&blob).unwrap().as_deref(), Some(BYTES));
+    }
+
+    #[test]
+    fn a_new_knowledge_base_is_in_the_current_format() {
+        let mut scratch = Scratch::new("doctor-new");
+        assert_eq!(scratch.kb.format_version().unwrap(), kb::FORMAT_VERSION);
+        assert!(check(&mut scratch.kb, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn an_earlier_format_is_reported_until_migrated() {
+        let mut scratch = Scratch::new("doctor-format");
+        std::fs::remove_file(scratch.kb.root.join(kb::FORMAT_FILE)).unwrap();
+
+        let problems = check(&mut scratch.kb, true).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].check, "format-version");
+        assert!(!problems[0].fixable && !problems[0].fixed);
+
+        let migrated = crate::commands::migrate::migrate(&mut scratch.kb).unwrap();
+        assert_eq!(
+            (migrated.from_format, migrated.format),
+            (0, kb::FORMAT_VERSION)
+        );
+        assert!(check(&mut scratch.kb, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn only_repairs_that_succeed_are_fixed() {
+        let mut scratch = Scratch::new("doctor-failed-fix");
+        let id = DocumentId::derive("broken");
+        let documents = scratch.kb.root.join("documents");
+        std::fs::create_dir_all(&documents).unwrap();
+        std::fs::write(documents.join(format!("{id}.json")), "{ not json").unwrap();
+        // Setting the document aside fails where corrupt/ cannot be made.
+        std::fs::write(scratch.kb.root.join("corrupt"), "").unwrap();
+        scratch.kb.blobs.put(BYTES).unwrap();
+        scratch.reopen();
+
+        let problems = check(&mut scratch.kb, true).unwrap();
+        let fixed = |check| problems.iter().find(|p| p.check == check).unwrap().fixed;
+        assert!(!fixed("unreadable"));
+        assert!(fixed("orphaned-blob"));
+    }
+}
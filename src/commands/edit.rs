@@ -0,0 +1,64 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+
+use crate::cli::EditArgs;
+use crate::commands::add::{self, Ingest};
+use crate::commands::config::open_editor;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{AddStatus, Added, Format};
+use crate::parser::{MarkdownParser, Parser, TextParser};
+use crate::types::{Document, DocumentKind};
+use crate::undo::command;
+
+pub fn run(args: EditArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let id = kb.resolve(&args.id)?;
+    let doc = kb.get(&id)?;
+    let extension = match doc.kind {
+        DocumentKind::Markdown => "md",
+        DocumentKind::Text | DocumentKind::Html => "txt",
+    };
+    let path = std::env::temp_dir().join(format!("ozy-{id}.{extension}"));
+    fs::write(&path, &doc.content)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    let edited = open_editor(&path).and_then(|()| {
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))
+    });
+    let _ = fs::remove_file(&path);
+    let edited = edited?;
+    let edited = if edited == doc.content {
+        add::unchanged(&doc, id.to_string())
+    } else {
+        update(&mut kb, doc, &edited)?
+    };
+    format.print(&edited, |e| match e.status {
+        AddStatus::Skipped | AddStatus::Pruned => add::print(e),
+        AddStatus::Unchanged => println!("{} is unchanged", e.id),
+        AddStatus::Added => println!(
+            "edited {}  {}",
+            e.id,
+            e.title.as_deref().unwrap_or_default()
+        ),
+    })
+}
+
+/// Stores the edited content of `doc`, run through the pipeline again.
+fn update(kb: &mut KnowledgeBase, mut doc: Document, edited: &str) -> Result<Added> {
+    // HTML is edited as the text it was reduced to.
+    let parsed = match doc.kind {
+        DocumentKind::Markdown => MarkdownParser.parse(edited, &doc.title)?,
+        DocumentKind::Text | DocumentKind::Html => TextParser.parse(edited, &doc.title)?,
+    };
+    doc.title = parsed.title;
+    doc.content = parsed.content;
+    doc.links = parsed.links;
+    kb.record_as(command("edit", [doc.id.to_string()]));
+    let mut result = None;
+    Ingest::new(kb, false, true, 1)?.documents(kb, &[doc], |_, added| {
+        result = Some(added?);
+        Ok(())
+    })?;
+    kb.commit()?;
+    result.context("the document was not stored")
+}
@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::cli::{BookFormat, ExportCommand};
+use crate::error::OzymandiasError;
+use crate::export::{anki, bibtex, book, logseq, obsidian, site};
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::llm_from_config;
+use crate::output::{
+    DocumentSummary, Exported, ExportedBibliography, ExportedBook, ExportedDeck, Format,
+};
+use crate::types::Document;
+use crate::{parallel, runtime, tags};
+
+pub fn run(cmd: ExportCommand, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let mut docs = kb.storage.all()?;
+    let (dir, attachments) = match cmd {
+        ExportCommand::Obsidian { dir } => {
+            let attachments = obsidian::export(&docs, &kb.blobs, &dir)?;
+            (dir, attachments)
+        }
+        ExportCommand::Logseq { dir } => {
+            let attachments = logseq::export(&docs, &kb.blobs, &dir)?;
+            (dir, attachments)
+        }
+        ExportCommand::Site { dir, tag } => {
+            docs = tagged(docs, tag)?;
+            let attachments = site::export(&docs, &kb.blobs, &dir)?;
+            (dir, attachments)
+        }
+        ExportCommand::Anki {
+            file,
+            tag,
+            deck,
+            cloze,
+        } => return export_anki(&kb, docs, file, tag, &deck, cloze, format),
+        ExportCommand::Book {
+            file,
+            query,
+            to,
+            title,
+            contents,
+        } => {
+            let exported = export_book(&kb, docs, file, query, to, title, contents)?;
+            return format.print(&exported, |e| {
+                print!("exported {} chapters", e.chapters.len());
+                if let Some(pages) = e.pages {
+                    print!(" ({pages} pages)");
+                }
+                println!(" to {}", e.file.display());
+                for (i, chapter) in e.chapters.iter().enumerate() {
+                    println!("{:>4}. {}", i + 1, chapter.title);
+                }
+            });
+        }
+        ExportCommand::Bibtex { file, tag } => {
+            let docs = tagged(docs, tag)?;
+            std::fs::write(&file, bibtex::write(&docs))
+                .with_context(|| format!("failed to write {}", file.display()))?;
+            let exported = ExportedBibliography {
+                file,
+                references: docs.len(),
+            };
+            return format.print(&exported, |e| {
+                println!(
+                    "exported {} references to {}",
+                    e.references,
+                    e.file.display()
+                )
+            });
+        }
+    };
+    let exported = Exported {
+        dir,
+        documents: docs.len(),
+        attachments,
+    };
+    format.print(&exported, |e| {
+        println!(
+            "exported {} documents and {} attachments to {}",
+            e.documents,
+            e.attachments,
+            e.dir.display()
+        )
+    })
+}
+
+/// The documents with `tag` or one nested below it, if a tag is given.
+fn tagged(mut docs: Vec<Document>, tag: Option<String>) -> Result<Vec<Document>> {
+    if let Some(tag) = tag {
+        let tag = tags::normalize(&tag)?;
+        docs.retain(|d| d.tags.iter().any(|t| tags::is_within(t, &tag)));
+        if docs.is_empty() {
+            return Err(OzymandiasError::NotFound(format!("no documents are tagged {tag}")).into());
+        }
+    }
+    Ok(docs)
+}
+
+/// Writes the documents matching `query` as a book, in chapter order.
+fn export_book(
+    kb: &KnowledgeBase,
+    docs: Vec<Document>,
+    file: PathBuf,
+    query: Option<String>,
+    to: Option<BookFormat>,
+    title: Option<String>,
+    contents: Option<String>,
+) -> Result<ExportedBook> {
+    let to = match to {
+        Some(to) => to,
+        None => match file.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("pdf") => BookFormat::Pdf,
+            Some(e) if e.eq_ignore_ascii_case("epub") => BookFormat::Epub,
+            _ => anyhow::bail!(
+                "cannot tell the book format from {}; pass --to pdf or --to epub",
+                file.display()
+            ),
+        },
+    };
+    let contents = match contents {
+        Some(contents) => {
+            let id = kb.resolve(&contents)?;
+            docs.iter().find(|d| d.id == id).cloned()
+        }
+        None => None,
+    };
+    let query = kb.parse_query(query.as_deref().unwrap_or_default())?;
+    let selected: Vec<Document> = docs
+        .into_iter()
+        .filter(|doc| query.matches(doc, true))
+        .collect();
+    let chapters = book::order(selected, contents.as_ref());
+    if chapters.is_empty() {
+        return Err(OzymandiasError::NotFound("no documents match the query".into()).into());
+    }
+    let title = title
+        .or_else(|| contents.as_ref().map(|c| c.title.clone()))
+        .unwrap_or_else(|| "Ozymandias".to_string());
+    let (pages, attachments) = match to {
+        BookFormat::Pdf => (Some(book::write_pdf(&title, &chapters, &file)?), 0),
+        BookFormat::Epub => (None, book::write_epub(&title, &chapters, &kb.blobs, &file)?),
+    };
+    Ok(ExportedBook {
+        file,
+        chapters: chapters.iter().map(DocumentSummary::from).collect(),
+        pages,
+        attachments,
+    })
+}
+
+fn export_anki(
+    kb: &KnowledgeBase,
+    docs: Vec<Document>,
+    file: PathBuf,
+    tag: Option<String>,
+    deck: &str,
+    cloze: bool,
+    format: Format,
+) -> Result<()> {
+    let docs = tagged(docs, tag)?;
+    let notes = match cloze {
+        false => docs.iter().flat_map(|d| anki::notes(d, true)).collect(),
+        true => {
+            let llm = llm_from_config(&kb.config.llm).context(
+                "--cloze needs a language model; set `provider` in the [llm] config section",
+            )?;
+            let prompts = kb.prompts();
+            // Language models are slow to answer, so several are asked at once.
+            let drafted = runtime::block_on(parallel::map_async(
+                &docs,
+                parallel::default_jobs(),
+                |doc| async {
+                    let mut notes = anki::notes(doc, false);
+                    if notes.is_empty() {
+                        notes = anki::cloze(doc, llm.as_ref(), &prompts).await?;
+                    }
+                    anyhow::Ok(notes)
+                },
+            ));
+            let mut notes = Vec::new();
+            for drafted in drafted {
+                notes.extend(drafted?);
+            }
+            notes
+        }
+    };
+    let exported = ExportedDeck {
+        cards: anki::write(&notes, deck, &file)?,
+        file,
+        documents: docs.len(),
+    };
+    format.print(&exported, |e| {
+        println!(
+            "exported {} cards from {} documents to {}",
+            e.cards,
+            e.documents,
+            e.file.display()
+        )
+    })
+}
@@ -0,0 +1,52 @@
+use anyhow::Result;
+
+use crate::cli::FindArgs;
+use crate::fuzzy;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{FindMatch, Format};
+
+pub fn run(args: FindArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let mut matches = Vec::new();
+    for doc in kb.storage.all()? {
+        let fields = std::iter::once(("title", &doc.title))
+            .chain(doc.tags.iter().map(|t| ("tag", t)))
+            .chain(doc.aliases.iter().map(|a| ("alias", a)));
+        let best = fields
+            .map(|(field, value)| (fuzzy::score(&args.query, value), field, value.clone()))
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        if let Some((score, field, value)) = best {
+            if score >= args.threshold {
+                matches.push(FindMatch {
+                    id: doc.id.clone(),
+                    title: doc.title.clone(),
+                    score,
+                    field,
+                    value,
+                });
+            }
+        }
+    }
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.title.cmp(&b.title))
+    });
+    matches.truncate(args.limit);
+
+    format.print(&matches, |matches| {
+        if matches.is_empty() {
+            println!("nothing matches {:?}", args.query);
+        }
+        for m in matches {
+            if m.field == "title" {
+                println!("{}  {}  ({:.2})", m.id, m.title, m.score);
+            } else {
+                println!(
+                    "{}  {}  [{}: {}]  ({:.2})",
+                    m.id, m.title, m.field, m.value, m.score
+                );
+            }
+        }
+    })
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::commands::stats::human_bytes;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Collected, Format};
+use crate::storage::{self, CHECKSUMS_FILE};
+use crate::types::DocumentId;
+use crate::undo;
+
+/// Files of the indexes and logs, which are rewritten without what is no
+/// longer needed.
+const COMPACTED: [&str; 5] = [
+    "index.json",
+    "vectors.json",
+    CHECKSUMS_FILE,
+    "tombstones.json",
+    "undo.json",
+];
+
+pub fn run(format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let collected = collect(&mut kb)?;
+    format.print(&collected, |c| {
+        println!(
+            "removed {} blobs and the history of {} documents",
+            c.blobs, c.history
+        );
+        println!(
+            "dropped {} index entries, {} embeddings, {} checksums and {} tombstones",
+            c.index_entries, c.embeddings, c.checksums, c.tombstones
+        );
+        println!(
+            "forgot {} operations that can no longer be undone",
+            c.undo_operations
+        );
+        println!("reclaimed {}", human_bytes(c.reclaimed));
+    })
+}
+
+/// Removes what nothing needs any more and compacts the indexes and logs.
+pub fn collect(kb: &mut KnowledgeBase) -> Result<Collected> {
+    let before: u64 = COMPACTED.iter().map(|f| size(&kb.root.join(f))).sum();
+    let docs = kb.storage.all()?;
+    let trashed = kb.trash.all()?;
+    let stored: BTreeSet<&DocumentId> = docs.iter().map(|d| &d.id).collect();
+    let kept: BTreeSet<&DocumentId> = stored
+        .iter()
+        .copied()
+        .chain(trashed.iter().map(|t| &t.document.id))
+        .collect();
+    let mut collected = Collected {
+        blobs: 0,
+        history: 0,
+        index_entries: 0,
+        embeddings: 0,
+        checksums: 0,
+        tombstones: 0,
+        undo_operations: 0,
+        reclaimed: 0,
+    };
+
+    let referenced = kb.referenced_blobs(&docs)?;
+    for hash in kb.blobs.hashes()? {
+        if !referenced.contains(&hash) {
+            collected.reclaimed += size(&kb.blobs.path(&hash));
+            kb.blobs.remove(&hash)?;
+            collected.blobs += 1;
+        }
+    }
+    // Leftovers of interrupted writes go too.
+    for name in kb.blobs.leftovers()? {
+        collected.reclaimed += size(&kb.blobs.path(&name));
+        kb.blobs.remove(&name)?;
+    }
+
+    for id in kb.history.ids()? {
+        if !kept.contains(&id) {
+            collected.reclaimed += size(&kb.history.path(&id));
+            kb.history.remove(&id)?;
+            collected.history += 1;
+        }
+    }
+
+    collected.index_entries = kb.index.ids().filter(|id| !stored.contains(id)).count();
+    let unembedded: Vec<DocumentId> = kb
+        .vectors
+        .ids()
+        .into_iter()
+        .filter(|id| !stored.contains(id))
+        .cloned()
+        .collect();
+    collected.embeddings = unembedded.len();
+    for id in &unembedded {
+        kb.vectors.remove(id);
+    }
+    // Rebuilt rather than pruned, so that the statistics of removed
+    // documents go with them.
+    kb.index.rebuild(&docs);
+    collected.tombstones = kb.tombstones.retain(|id| !stored.contains(id));
+    kb.commit()?;
+
+    let storage = &kb.storage;
+    collected.undo_operations = kb
+        .undo
+        .compact(|id| undo::state(storage.get(id)?.as_ref()))?;
+    collected.checksums = storage::compact_checksums(&kb.root, |id| stored.contains(id))?;
+    if !kb::dry_run() {
+        if collected.undo_operations > 0 {
+            kb.undo.save()?;
+        }
+        let after: u64 = COMPACTED.iter().map(|f| size(&kb.root.join(f))).sum();
+        collected.reclaimed += before.saturating_sub(after);
+    }
+    Ok(collected)
+}
+
+fn size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::commands::undo::undo_last;
+    use crate::kb::scratch::Scratch;
+    use crate::kb::set_dry_run;
+    use crate::storage::write_json;
+    use crate::tombstones::Tombstone;
+    use crate::types::{Document, DocumentKind};
+
+    fn note(key: &str) -> Document {
+        Document::new(
+            DocumentId::derive(key),
+            key.into(),
+            DocumentKind::Markdown,
+            format!("About {key}."),
+            None,
+        )
+    }
+
+    /// A checksum of no document, a tombstone of a stored one, and an
+    /// operation undone by an edit that was not logged.
+    fn litter(kb: &mut KnowledgeBase) -> (Document, Document) {
+        let (edited, kept) = (note("edited"), note("kept"));
+        for doc in [&edited, &kept] {
+            kb.record_as(format!("add {}", doc.title));
+            kb.insert(doc).unwrap();
+            kb.commit().unwrap();
+        }
+        let mut changed = edited.clone();
+        changed.content.push_str(" And more.");
+        kb.insert(&changed).unwrap();
+        kb.tombstones.insert(
+            kept.id.clone(),
+            Tombstone {
+                title: kept.title.clone(),
+                source: None,
+                deleted: Utc::now(),
+            },
+        );
+        kb.commit().unwrap();
+        let mut checksums = storage::checksums(&kb.root).unwrap();
+        checksums.insert(DocumentId::derive("gone"), "0".repeat(64));
+        write_json(&kb.root.join(CHECKSUMS_FILE), &checksums).unwrap();
+        (edited, kept)
+    }
+
+    #[test]
+    fn stale_checksums_tombstones_and_undo_operations_are_compacted() {
+        let mut scratch = Scratch::new("gc-compact");
+        let (_, kept) = litter(&mut scratch.kb);
+
+        set_dry_run(true);
+        let collected = collect(&mut scratch.kb).unwrap();
+        assert_eq!(
+            (
+                collected.checksums,
+                collected.tombstones,
+                collected.undo_operations
+            ),
+            (1, 1, 1)
+        );
+        set_dry_run(false);
+        scratch.reopen();
+        assert_eq!(scratch.kb.undo.operations().len(), 2);
+        assert_eq!(storage::checksums(&scratch.kb.root).unwrap().len(), 3);
+        assert!(scratch.kb.tombstones.get(&kept.id).is_some());
+
+        let collected = collect(&mut scratch.kb).unwrap();
+        assert_eq!(
+            (
+                collected.checksums,
+                collected.tombstones,
+                collected.undo_operations
+            ),
+            (1, 1, 1)
+        );
+        assert!(collected.reclaimed > 0);
+        scratch.reopen();
+        let commands: Vec<&str> = scratch
+            .kb
+            .undo
+            .operations()
+            .iter()
+            .map(|op| op.command.as_str())
+            .collect();
+        assert_eq!(commands, ["add kept"]);
+        assert_eq!(storage::checksums(&scratch.kb.root).unwrap().len(), 2);
+        assert!(scratch.kb.tombstones.get(&kept.id).is_none());
+
+        // What is left can still be undone.
+        assert_eq!(undo_last(&mut scratch.kb).unwrap().removed, [kept.id]);
+    }
+}
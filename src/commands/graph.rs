@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::{ExportArgs, GraphCommand};
+use crate::entities::EntityKind;
+use crate::graph::NodeId;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Centrality, Format, GraphNeighbor, GraphNode, GraphStats};
+use crate::tags;
+
+pub fn run(cmd: GraphCommand, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        GraphCommand::Neighbors { node } => {
+            let node = parse_node(&kb, &node)?;
+            let neighbors: Vec<GraphNeighbor> = kb
+                .graph
+                .neighbors(&node)
+                .into_iter()
+                .map(|n| GraphNeighbor {
+                    direction: if n.outgoing { "outgoing" } else { "incoming" },
+                    kind: n.edge.kind.name().to_string(),
+                    node: n.node.to_string(),
+                    label: label(&kb, n.node).to_string(),
+                })
+                .collect();
+            format.print(&neighbors, |neighbors| {
+                for n in neighbors {
+                    let arrow = if n.direction == "outgoing" {
+                        "->"
+                    } else {
+                        "<-"
+                    };
+                    println!("{arrow} {:<10} {}  {}", n.kind, n.node, n.label);
+                }
+            })?;
+        }
+        GraphCommand::Path { from, to } => {
+            let (from, to) = (parse_node(&kb, &from)?, parse_node(&kb, &to)?);
+            let path: Vec<GraphNode> = kb
+                .graph
+                .path_between(&from, &to)
+                .unwrap_or_default()
+                .iter()
+                .map(|node| graph_node(&kb, node))
+                .collect();
+            format.print(&path, |path| {
+                if path.is_empty() {
+                    println!("no path between {from} and {to}");
+                }
+                for step in path {
+                    println!("{}  {}", step.node, step.label);
+                }
+            })?;
+        }
+        GraphCommand::Export(args) => export(&kb, args, format)?,
+        GraphCommand::Stats { limit } => format.print(&stats(&kb, limit), print_stats)?,
+    }
+    Ok(())
+}
+
+fn stats(kb: &KnowledgeBase, limit: usize) -> GraphStats {
+    let graph = &kb.graph;
+    let pagerank = graph.pagerank();
+    let betweenness = graph.betweenness();
+    let mut docs: Vec<&NodeId> = graph
+        .nodes()
+        .iter()
+        .map(|n| &n.id)
+        .filter(|id| matches!(id, NodeId::Document(_)))
+        .collect();
+    docs.sort_by(|a, b| {
+        pagerank[b]
+            .total_cmp(&pagerank[a])
+            .then(betweenness[b].total_cmp(&betweenness[a]))
+    });
+    let ranking = docs
+        .iter()
+        .take(limit)
+        .enumerate()
+        .map(|(rank, id)| Centrality {
+            rank: rank + 1,
+            id: id.to_string(),
+            title: label(kb, id).to_string(),
+            pagerank: pagerank[id],
+            betweenness: betweenness[id],
+            degree: graph.degree(id),
+        })
+        .collect();
+    let components = graph.components();
+    GraphStats {
+        nodes: graph.nodes().len(),
+        edges: graph.edges().len(),
+        documents: docs.len(),
+        ranking,
+        component_count: components.len(),
+        components: components
+            .iter()
+            .take(limit)
+            .map(|c| c.iter().map(|id| graph_node(kb, id)).collect())
+            .collect(),
+    }
+}
+
+fn print_stats(stats: &GraphStats) {
+    println!(
+        "{} nodes, {} edges, {} documents",
+        stats.nodes, stats.edges, stats.documents
+    );
+    println!();
+    println!(
+        "{:>4}  {:>8}  {:>8}  {:>6}  {:<12}  title",
+        "rank", "pagerank", "between", "degree", "id"
+    );
+    for c in &stats.ranking {
+        println!(
+            "{:>4}  {:>8.4}  {:>8.4}  {:>6}  {:<12}  {}",
+            c.rank, c.pagerank, c.betweenness, c.degree, c.id, c.title
+        );
+    }
+
+    if stats.component_count > 1 {
+        println!();
+        println!(
+            "most isolated clusters ({} in total):",
+            stats.component_count
+        );
+        for component in &stats.components {
+            let titles: Vec<String> = component
+                .iter()
+                .take(3)
+                .map(|n| format!("{} {}", n.node, n.label))
+                .collect();
+            let more = component.len().saturating_sub(3);
+            let more = if more > 0 {
+                format!(", +{more} more")
+            } else {
+                String::new()
+            };
+            println!("  {:>4}  {}{more}", component.len(), titles.join(", "));
+        }
+    }
+}
+
+fn export(kb: &KnowledgeBase, args: ExportArgs, format: Format) -> Result<()> {
+    let mut keep: Option<HashSet<NodeId>> = None;
+    if let Some(tag) = &args.tag {
+        keep = Some(kb.graph.tagged(&tags::normalize(tag)?));
+    }
+    if let Some(around) = &args.around {
+        let near = kb.graph.neighborhood(&parse_node(kb, around)?, args.depth);
+        keep = Some(match keep {
+            Some(keep) => keep.intersection(&near).cloned().collect(),
+            None => near,
+        });
+    }
+    let subgraph;
+    let graph = match &keep {
+        Some(keep) => {
+            subgraph = kb.graph.subgraph(keep);
+            &subgraph
+        }
+        None => &kb.graph,
+    };
+    let rendered = match format {
+        Format::Plain | Format::Dot => graph.to_dot(),
+        Format::Graphml => graph.to_graphml(),
+        Format::Json => serde_json::to_string_pretty(graph)? + "\n",
+        Format::Yaml => serde_yaml::to_string(graph)?,
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, rendered)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => print!("{rendered}"),
+    }
+    Ok(())
+}
+
+/// Reads `#tag` as a tag node, `@kind:name` as an entity and anything
+/// else as a document id prefix.
+pub fn parse_node(kb: &KnowledgeBase, input: &str) -> Result<NodeId> {
+    let node = if let Some(tag) = input.strip_prefix('#') {
+        NodeId::Tag(tags::normalize(tag)?)
+    } else if let Some(entity) = input.strip_prefix('@') {
+        let Some((kind, name)) = entity.split_once(':') else {
+            bail!("expected an entity as @kind:name, such as @person:Ada Lovelace");
+        };
+        NodeId::Entity(format!("{}:{name}", EntityKind::parse(kind)?.name()))
+    } else {
+        NodeId::Document(kb.resolve(input)?)
+    };
+    if kb.graph.node(&node).is_none() {
+        bail!("{node} is not in the knowledge graph");
+    }
+    Ok(node)
+}
+
+fn label<'a>(kb: &'a KnowledgeBase, node: &NodeId) -> &'a str {
+    kb.graph.node(node).map_or("", |n| n.label.as_str())
+}
+
+fn graph_node(kb: &KnowledgeBase, node: &NodeId) -> GraphNode {
+    GraphNode {
+        node: node.to_string(),
+        label: label(kb, node).to_string(),
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{bail, Result};
+
+use crate::cli::{DiffArgs, HistoryArgs};
+use crate::commands::list::human_size;
+use crate::error::OzymandiasError;
+use crate::history::{self, Revision};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{DocumentDiff, DocumentHistory, Format, RevisionEntry};
+use crate::types::DocumentId;
+
+pub fn run(args: HistoryArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let id = kb.resolve(&args.id)?;
+    let revisions = revisions(&kb, &id)?;
+    let mut entries = Vec::new();
+    for (i, revision) in revisions.iter().enumerate() {
+        let before = i.checked_sub(1).map_or("", |i| revisions[i].content.as_str());
+        let (added, removed) = history::changes(before, &revision.content);
+        entries.push(RevisionEntry {
+            number: i + 1,
+            saved: revision.saved,
+            title: revision.title.clone(),
+            size: revision.content.len(),
+            added,
+            removed,
+        });
+    }
+    let history = DocumentHistory {
+        title: revisions.last().expect("the current revision").title.clone(),
+        id,
+        revisions: entries,
+    };
+    format.print(&history, |h| {
+        for (i, r) in h.revisions.iter().enumerate() {
+            let current = if i + 1 == h.revisions.len() {
+                "  (current)"
+            } else {
+                ""
+            };
+            println!(
+                "{:>4}  {}  {:>8}  +{:<5} -{:<5} {}{current}",
+                r.number,
+                r.saved.format("%Y-%m-%d %H:%M"),
+                human_size(r.size),
+                r.added,
+                r.removed,
+                r.title
+            );
+        }
+    })
+}
+
+pub fn diff(args: DiffArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let id = kb.resolve(&args.id)?;
+    let revisions = revisions(&kb, &id)?;
+    let current = revisions.len();
+    let (from, to) = match args.rev.as_deref() {
+        None if current == 1 => bail!(OzymandiasError::NotFound(format!(
+            "{id} has no earlier revisions"
+        ))),
+        None => (current - 1, current),
+        Some(range) => match range.split_once("..") {
+            Some((from, "")) => (number(from)?, current),
+            Some((from, to)) => (number(from)?, number(to)?),
+            None => (number(range)?, current),
+        },
+    };
+    for n in [from, to] {
+        if n == 0 || n > current {
+            bail!(OzymandiasError::NotFound(format!(
+                "{id} has no revision {n} (it has 1 to {current})"
+            )));
+        }
+    }
+    let label = |n: usize| {
+        let revision = &revisions[n - 1];
+        match n == current {
+            true => format!("{} (revision {n}, current)", revision.title),
+            false => format!("{} (revision {n})", revision.title),
+        }
+    };
+    let diff = DocumentDiff {
+        diff: history::unified(
+            &revisions[from - 1].content,
+            &revisions[to - 1].content,
+            &label(from),
+            &label(to),
+        ),
+        id,
+        from,
+        to,
+    };
+    format.print(&diff, |d| match d.diff.is_empty() {
+        true => println!("no changes to the content from revision {} to {}", d.from, d.to),
+        false => print!("{}", d.diff),
+    })
+}
+
+/// Every revision of a document, the current one last.
+fn revisions(kb: &KnowledgeBase, id: &DocumentId) -> Result<Vec<Revision>> {
+    let doc = kb.get(id)?;
+    let mut revisions = kb.history.revisions(id)?;
+    revisions.push(Revision {
+        saved: kb.storage.modified(id)?.unwrap_or(doc.added),
+        title: doc.title,
+        content: doc.content,
+    });
+    Ok(revisions)
+}
+
+fn number(raw: &str) -> Result<usize> {
+    raw.trim().parse().map_err(|_| {
+        OzymandiasError::ParseFailed(format!("invalid revision {raw:?}; expected a number")).into()
+    })
+}
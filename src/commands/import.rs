@@ -0,0 +1,259 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::cli::{AddArgs, ImportCommand};
+use crate::commands::add::{self, Ingest};
+use crate::error::OzymandiasError;
+use crate::import::{
+    bibtex, bookmarks, calibre, enex, feed, instapaper, notion, pocket, roam, zotero, Saved,
+};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{AddStatus, Added, Format};
+use crate::parallel;
+use crate::progress::Progress;
+use crate::runtime;
+use crate::types::Document;
+
+pub fn run(cmd: ImportCommand, format: Format) -> Result<()> {
+    if let ImportCommand::Dir { dirs, force, jobs } = cmd {
+        return dir(dirs, force, jobs, format);
+    }
+    let mut kb = KnowledgeBase::open_or_init(&kb::dir()?)?;
+    let mut rate_limit = None;
+    // Feeds are pulled through the client pages are clipped with.
+    let mut feed_url = None;
+    // Prefixes of the sources of the documents the export holds all of,
+    // for --prune.
+    let mut scopes = Vec::new();
+    let (mut inputs, options) = match cmd {
+        ImportCommand::Dir { .. } => unreachable!("imported as added"),
+        ImportCommand::Notion { file, options } => {
+            scopes.push(notion::scope());
+            (Inputs::Documents(notion::read(&file)?), options)
+        }
+        ImportCommand::Enex { files, options } => {
+            let mut docs = Vec::new();
+            for file in &files {
+                docs.extend(enex::read(file, &kb.blobs)?);
+                scopes.push(enex::scope(file));
+            }
+            (Inputs::Documents(docs), options)
+        }
+        ImportCommand::Zotero { file, options } => {
+            scopes.push(zotero::scope());
+            (Inputs::Documents(zotero::read(&file, &kb.blobs)?), options)
+        }
+        ImportCommand::Roam { file, options } => {
+            scopes.push(roam::scope(&file));
+            (Inputs::Documents(roam::read(&file)?), options)
+        }
+        ImportCommand::Pocket { file, options } => (Inputs::clipped(pocket::read(&file)?), options),
+        ImportCommand::Instapaper { file, options } => {
+            (Inputs::clipped(instapaper::read(&file)?), options)
+        }
+        ImportCommand::Bookmarks {
+            file,
+            clip,
+            rate,
+            options,
+        } => {
+            rate_limit = clip.then_some(rate);
+            (Inputs::Saved(bookmarks::read(&file)?, clip), options)
+        }
+        ImportCommand::Calibre {
+            dir,
+            prefer,
+            options,
+        } => {
+            scopes.push(calibre::scope());
+            (
+                Inputs::Documents(calibre::read(&dir, &prefer, &kb.blobs)?),
+                options,
+            )
+        }
+        ImportCommand::Bibtex { files, options } => {
+            let mut docs = Vec::new();
+            for file in &files {
+                docs.extend(bibtex::read(file)?);
+                scopes.push(bibtex::scope(file));
+            }
+            (Inputs::Documents(docs), options)
+        }
+        ImportCommand::Feed { url, rate, options } => {
+            rate_limit = Some(rate);
+            feed_url = Some(url);
+            (Inputs::clipped(Vec::new()), options)
+        }
+    };
+    if options.prune && scopes.is_empty() {
+        bail!("--prune is for exports of documents; saved pages are never pruned");
+    }
+    let jobs = options.jobs.unwrap_or_else(parallel::default_jobs);
+    let mut ingest = Ingest::new(&kb, false, options.force, jobs)?;
+    if let Some(rate) = rate_limit {
+        ingest.limit_rate(rate);
+    }
+    if let Some(url) = feed_url {
+        inputs = Inputs::clipped(runtime::block_on(feed::read(ingest.web(), &url))?);
+    }
+    let progress = Progress::new("documents imported", inputs.len());
+    let mut results = Vec::new();
+    // One document the pipeline fails on should not abort the import.
+    let mut report = |added: Result<Added>| {
+        match added {
+            Ok(added) => {
+                if format.is_plain() && added.status != AddStatus::Unchanged {
+                    progress.suspend(|| add::print(&added));
+                }
+                results.push(added);
+            }
+            Err(e) => progress.suspend(|| tracing::warn!("{e:#}")),
+        }
+        progress.inc(1);
+        Ok(())
+    };
+    match &inputs {
+        Inputs::Documents(docs) => ingest.documents(&mut kb, docs, |_, added| report(added))?,
+        Inputs::Saved(saved, clip) => {
+            ingest.saved(&mut kb, saved, *clip, |_, added| report(added))?
+        }
+    }
+    drop(progress);
+    if let (true, Inputs::Documents(docs)) = (options.prune, &inputs) {
+        results.extend(prune(&mut kb, docs, &scopes)?);
+    }
+    kb.commit()?;
+    let unchanged = results
+        .iter()
+        .filter(|a| a.status == AddStatus::Unchanged)
+        .count();
+    format.print(&results, |results| {
+        for pruned in results.iter().filter(|a| a.status == AddStatus::Pruned) {
+            add::print(pruned);
+        }
+        if unchanged > 0 {
+            println!(
+                "{unchanged} unchanged since last imported (use --force to process them again)"
+            );
+        }
+    })
+}
+
+/// `ozy import dir`, which is `ozy add` of directories only.
+fn dir(dirs: Vec<PathBuf>, force: bool, jobs: Option<usize>, format: Format) -> Result<()> {
+    if let Some(path) = dirs.iter().find(|d| !d.is_dir()) {
+        bail!(OzymandiasError::NotFound(format!(
+            "{} is not a directory",
+            path.display()
+        )));
+    }
+    let args = AddArgs {
+        paths: dirs,
+        urls: Vec::new(),
+        archive: false,
+        suggest_tags: false,
+        force,
+        jobs,
+    };
+    add::run(args, format)
+}
+
+/// Moves the documents under `scopes` that are not among `docs`, so gone
+/// from the export, to the trash. They leave no tombstone, so that they
+/// are imported again should the export hold them once more.
+fn prune(kb: &mut KnowledgeBase, docs: &[Document], scopes: &[String]) -> Result<Vec<Added>> {
+    let read: HashSet<&str> = docs.iter().filter_map(|d| d.source.as_deref()).collect();
+    let mut pruned = Vec::new();
+    for doc in kb.storage.all()? {
+        let Some(source) = &doc.source else {
+            continue;
+        };
+        let gone = scopes.iter().any(|s| source.starts_with(s.as_str()))
+            && !read.contains(source.as_str());
+        if !gone {
+            continue;
+        }
+        kb.remove(&doc.id, false)?;
+        kb.tombstones.remove(&doc.id);
+        pruned.push(Added {
+            input: source.clone(),
+            status: AddStatus::Pruned,
+            id: doc.id,
+            title: Some(doc.title),
+            reason: None,
+            near_duplicate_of: None,
+            tagged: Vec::new(),
+            suggested: Vec::new(),
+        });
+    }
+    Ok(pruned)
+}
+
+/// What an importer reads: documents, or saved web pages, which are
+/// clipped or kept as links.
+enum Inputs {
+    Documents(Vec<Document>),
+    Saved(Vec<Saved>, bool),
+}
+
+impl Inputs {
+    fn clipped(saved: Vec<Saved>) -> Self {
+        Inputs::Saved(saved, true)
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Inputs::Documents(docs) => docs.len(),
+            Inputs::Saved(saved, _) => saved.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kb::scratch::Scratch;
+    use crate::kb::set_dry_run;
+    use crate::types::{DocumentId, DocumentKind};
+
+    fn exported(name: &str) -> Document {
+        let source = format!("export:/{name}");
+        Document::new(
+            DocumentId::derive(&source),
+            name.into(),
+            DocumentKind::Markdown,
+            format!("About {name}."),
+            Some(source),
+        )
+    }
+
+    #[test]
+    fn a_dry_run_prunes_nothing() {
+        let mut scratch = Scratch::new("import-prune");
+        let (kept, gone) = (exported("kept"), exported("gone"));
+        for doc in [&kept, &gone] {
+            scratch.kb.insert(doc).unwrap();
+        }
+        scratch.kb.commit().unwrap();
+        let scopes = ["export:/".to_string()];
+
+        let files = scratch.files();
+        set_dry_run(true);
+        scratch.reopen();
+        let pruned = prune(&mut scratch.kb, std::slice::from_ref(&kept), &scopes).unwrap();
+        scratch.kb.commit().unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].id, gone.id);
+        assert_eq!(scratch.files(), files);
+
+        set_dry_run(false);
+        scratch.reopen();
+        prune(&mut scratch.kb, std::slice::from_ref(&kept), &scopes).unwrap();
+        scratch.kb.commit().unwrap();
+        assert!(scratch.kb.storage.get(&gone.id).unwrap().is_none());
+        assert!(scratch.kb.storage.get(&kept.id).unwrap().is_some());
+        assert!(scratch.kb.tombstones.get(&gone.id).is_none());
+    }
+}
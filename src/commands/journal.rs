@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, NaiveDate};
+
+use crate::cli::JournalArgs;
+use crate::commands::add;
+use crate::commands::config::open_editor;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, JournalEntry};
+use crate::relations::{Relation, RelationKind};
+use crate::storage::Storage;
+use crate::templates;
+use crate::types::DocumentId;
+use crate::undo::command;
+
+pub fn run(args: JournalArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open_or_init(&kb::dir()?)?;
+    let date = args.date.unwrap_or_else(|| Local::now().date_naive());
+    let dir = kb.workspace_path(&kb.config.journal.dir);
+    let path = dir.join(format!("{}.md", date.format("%Y-%m-%d")));
+    let created = !path.exists();
+    if created {
+        if kb::dry_run() {
+            bail!(
+                "{} does not exist yet; --dry-run cannot create it",
+                path.display()
+            );
+        }
+        let template = templates::load(&kb.root, &kb.config.journal.template)?;
+        let vars = templates::builtins(&date.format("%Y-%m-%d").to_string(), date);
+        let text = templates::render(template.as_deref().unwrap_or(templates::DEFAULT), &vars);
+        fs::create_dir_all(&dir).with_context(|| format!("cannot create {}", dir.display()))?;
+        fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    if !args.no_edit {
+        open_editor(&path)?;
+    }
+
+    kb.record_as(command("journal", [path.display().to_string()]));
+    let added = add::file(&mut kb, &path)?;
+
+    let entries = entries(kb.storage.as_ref(), &dir)?;
+    let i = entries
+        .iter()
+        .position(|(_, id)| *id == added.id)
+        .context("the entry is not below the journal directory")?;
+    let previous = i.checked_sub(1).map(|j| entries[j].1.clone());
+    let next = entries.get(i + 1).map(|(_, id)| id.clone());
+    chain(&mut kb, &added.id, previous.as_ref())?;
+    if let Some(next) = &next {
+        chain(&mut kb, next, Some(&added.id))?;
+    }
+    kb.commit()?;
+
+    let entry = JournalEntry {
+        id: added.id,
+        title: added.title.unwrap_or_default(),
+        path,
+        created,
+        previous,
+        next,
+    };
+    format.print(&entry, |e| {
+        let created = if e.created { "created " } else { "" };
+        println!("{created}{}  {}", e.id, e.path.display());
+    })
+}
+
+/// The documents added from files in `dir` named after a date, oldest
+/// first.
+fn entries(storage: &dyn Storage, dir: &Path) -> Result<Vec<(NaiveDate, DocumentId)>> {
+    let Ok(dir) = dir.canonicalize() else {
+        return Ok(Vec::new());
+    };
+    let mut entries: Vec<_> = storage
+        .all()?
+        .into_iter()
+        .filter_map(|doc| {
+            let source = PathBuf::from(doc.source.as_deref()?);
+            if source.parent() != Some(dir.as_path()) {
+                return None;
+            }
+            let stem = source.file_stem()?.to_str()?;
+            let date = NaiveDate::parse_from_str(stem, "%Y-%m-%d").ok()?;
+            Some((date, doc.id))
+        })
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Makes `previous` the only entry `id` follows.
+fn chain(kb: &mut KnowledgeBase, id: &DocumentId, previous: Option<&DocumentId>) -> Result<()> {
+    let follows: Vec<DocumentId> = kb
+        .get(id)?
+        .relations
+        .into_iter()
+        .filter(|r| r.kind == RelationKind::Follows)
+        .map(|r| r.target)
+        .collect();
+    for target in follows.iter().filter(|t| Some(*t) != previous) {
+        kb.unrelate(id, &RelationKind::Follows, target)?;
+    }
+    if let Some(previous) = previous.filter(|p| !follows.contains(p)) {
+        kb.relate(
+            id,
+            Relation {
+                kind: RelationKind::Follows,
+                target: previous.clone(),
+                confidence: 1.0,
+            },
+        )?;
+    }
+    Ok(())
+}
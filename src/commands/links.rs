@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::cli::LinksCommand;
+use crate::kb::{self, KnowledgeBase};
+use crate::linkcheck::{self, Status};
+use crate::links::Link;
+use crate::output::{DocumentRef, Format, LinkStatus};
+use crate::runtime;
+use crate::types::Document;
+use crate::web::{self, WebConfig};
+
+pub fn run(cmd: LinksCommand, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        LinksCommand::Check {
+            concurrency,
+            retries,
+            delay_ms,
+            timeout,
+            all,
+        } => {
+            let docs = kb.storage.all()?;
+            let mut referrers: BTreeMap<&str, Vec<&Document>> = BTreeMap::new();
+            for doc in &docs {
+                for link in &doc.links {
+                    if let Link::Href(url) = link {
+                        if url.starts_with("http://") || url.starts_with("https://") {
+                            referrers.entry(url).or_default().push(doc);
+                        }
+                    }
+                }
+            }
+            let urls: Vec<String> = referrers.keys().map(|u| u.to_string()).collect();
+            tracing::info!("checking {} external links", urls.len());
+            let defaults = &kb.config.web;
+            let client = web::Client::new(&WebConfig {
+                retries: retries.unwrap_or(defaults.retries),
+                host_delay_ms: delay_ms.unwrap_or(defaults.host_delay_ms),
+                timeout_secs: timeout.unwrap_or(defaults.timeout_secs),
+                ..defaults.clone()
+            });
+            let statuses = runtime::block_on(linkcheck::check(&urls, &client, concurrency));
+
+            let mut reported = Vec::new();
+            let (mut dead, mut redirected) = (0, 0);
+            for (url, status) in urls.iter().zip(statuses) {
+                let mut link = LinkStatus {
+                    url: url.clone(),
+                    status: "ok",
+                    http_status: None,
+                    location: None,
+                    reason: None,
+                    documents: referrers[url.as_str()]
+                        .iter()
+                        .map(|d| DocumentRef::from(*d))
+                        .collect(),
+                };
+                match status {
+                    Status::Ok if !all => continue,
+                    Status::Ok => {}
+                    Status::Redirected { status, location } => {
+                        redirected += 1;
+                        link.status = "redirected";
+                        link.http_status = Some(status);
+                        link.location = Some(location);
+                    }
+                    Status::Dead(reason) => {
+                        dead += 1;
+                        link.status = "dead";
+                        link.reason = Some(reason);
+                    }
+                }
+                reported.push(link);
+            }
+            format.print(&reported, |reported| {
+                for link in reported {
+                    match link.status {
+                        "redirected" => println!(
+                            "moved {}\n      {} -> {}",
+                            link.url,
+                            link.http_status.unwrap_or_default(),
+                            link.location.as_deref().unwrap_or_default()
+                        ),
+                        "dead" => println!(
+                            "dead  {}\n      {}",
+                            link.url,
+                            link.reason.as_deref().unwrap_or_default()
+                        ),
+                        _ => println!("ok    {}", link.url),
+                    }
+                    for doc in &link.documents {
+                        println!("      in {}  {}", doc.id, doc.title);
+                    }
+                }
+                println!(
+                    "{} ok, {redirected} redirected, {dead} dead",
+                    urls.len() - redirected - dead
+                );
+            })?;
+            if dead > 0 {
+                bail!("{dead} dead links");
+            }
+        }
+    }
+    Ok(())
+}
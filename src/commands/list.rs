@@ -0,0 +1,66 @@
+use anyhow::Result;
+
+use crate::cli::{ListArgs, SortKey};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{DocumentSummary, Format, Listing};
+
+pub fn run(args: ListArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let query = kb.parse_query(args.query.as_deref().unwrap_or_default())?;
+    let mut docs: Vec<_> = kb
+        .storage
+        .all()?
+        .into_iter()
+        .filter(|doc| query.matches(doc, true))
+        .collect();
+    match args.sort {
+        SortKey::Title => docs.sort_by_key(|d| d.title.to_lowercase()),
+        SortKey::Date => docs.sort_by_key(|d| std::cmp::Reverse(d.added)),
+        SortKey::Size => docs.sort_by_key(|d| std::cmp::Reverse(d.content.len())),
+    }
+    if args.reverse {
+        docs.reverse();
+    }
+
+    let listing = Listing {
+        total: docs.len(),
+        documents: docs
+            .iter()
+            .skip(args.offset)
+            .take(args.limit)
+            .map(DocumentSummary::from)
+            .collect(),
+    };
+    format.print(&listing, |listing| {
+        for doc in &listing.documents {
+            println!(
+                "{}  {}  {:<8}  {:>8}  {}",
+                doc.id,
+                doc.added.format("%Y-%m-%d"),
+                doc.kind.name(),
+                human_size(doc.size),
+                doc.title
+            );
+        }
+        println!(
+            "showing {} of {} documents",
+            listing.documents.len(),
+            listing.total
+        );
+    })
+}
+
+pub fn human_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
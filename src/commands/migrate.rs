@@ -0,0 +1,107 @@
+use anyhow::{Context, Result};
+
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, Migrated};
+use crate::progress::Progress;
+
+pub fn run(format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let migrated = migrate(&mut kb)?;
+    format.print(&migrated, |m| {
+        for id in &m.rewritten {
+            println!("rewrote {id}");
+        }
+        println!(
+            "{} of {} documents were in an earlier format",
+            m.rewritten.len(),
+            m.documents
+        );
+        if m.from_format != m.format {
+            println!(
+                "the knowledge base is now in format {} (was {})",
+                m.format, m.from_format
+            );
+        }
+    })
+}
+
+/// Rewrites the documents whose files differ from how the document would
+/// be written now, such as those missing fields added since they were
+/// stored, and records that the knowledge base is in the current format.
+/// Their content is the same, so the indexes are left alone.
+pub fn migrate(kb: &mut KnowledgeBase) -> Result<Migrated> {
+    let from_format = kb.format_version()?;
+    let ids = kb.storage.ids()?;
+    let progress = Progress::new("documents checked", ids.len());
+    let mut rewritten = Vec::new();
+    for id in &ids {
+        let path = kb.root.join("documents").join(format!("{id}.json"));
+        let stored =
+            std::fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+        if let Some(doc) = kb.storage.get(id)? {
+            if serde_json::to_vec_pretty(&doc)? != stored {
+                kb.storage.put(&doc)?;
+                rewritten.push(doc.id);
+            }
+        }
+        progress.inc(1);
+    }
+    drop(progress);
+    kb.commit()?;
+    kb.mark_format()?;
+
+    Ok(Migrated {
+        documents: ids.len(),
+        rewritten,
+        from_format,
+        format: kb::FORMAT_VERSION,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kb::scratch::Scratch;
+    use crate::kb::{set_dry_run, FORMAT_FILE};
+    use crate::types::{Document, DocumentId, DocumentKind};
+
+    #[test]
+    fn a_dry_run_migrates_nothing() {
+        let mut scratch = Scratch::new("migrate-dry-run");
+        let doc = Document::new(
+            DocumentId::derive("old"),
+            "Old".into(),
+            DocumentKind::Markdown,
+            "Written long ago.".into(),
+            None,
+        );
+        scratch.kb.insert(&doc).unwrap();
+        scratch.kb.commit().unwrap();
+        // As an earlier version wrote it: compact, and without the marker.
+        let path = scratch
+            .kb
+            .root
+            .join("documents")
+            .join(format!("{}.json", doc.id));
+        std::fs::write(&path, serde_json::to_vec(&doc).unwrap()).unwrap();
+        std::fs::remove_file(scratch.kb.root.join(FORMAT_FILE)).unwrap();
+
+        let files = scratch.files();
+        set_dry_run(true);
+        scratch.reopen();
+        let migrated = migrate(&mut scratch.kb).unwrap();
+        assert_eq!(migrated.rewritten, std::slice::from_ref(&doc.id));
+        assert_eq!(
+            (migrated.from_format, migrated.format),
+            (0, kb::FORMAT_VERSION)
+        );
+        assert_eq!(scratch.files(), files);
+
+        set_dry_run(false);
+        scratch.reopen();
+        assert_eq!(migrate(&mut scratch.kb).unwrap().rewritten, [doc.id]);
+        let migrated = migrate(&mut scratch.kb).unwrap();
+        assert!(migrated.rewritten.is_empty());
+        assert_eq!(migrated.from_format, kb::FORMAT_VERSION);
+    }
+}
@@ -0,0 +1,118 @@
+pub mod add;
+pub mod alias;
+pub mod ask;
+pub mod attach;
+pub mod backup;
+pub mod chat;
+pub mod cites;
+pub mod classify;
+pub mod cluster;
+pub mod completions;
+pub mod config;
+pub mod dedupe;
+pub mod doctor;
+pub mod edit;
+pub mod export;
+pub mod find;
+pub mod gc;
+pub mod graph;
+pub mod history;
+pub mod import;
+pub mod journal;
+pub mod links;
+pub mod list;
+pub mod migrate;
+pub mod models;
+pub mod new;
+pub mod ontology;
+pub mod open;
+pub mod prompts;
+pub mod reindex;
+pub mod relate;
+pub mod review;
+pub mod rm;
+pub mod search;
+pub mod serve;
+pub mod show;
+pub mod stats;
+pub mod summarize;
+pub mod sync;
+pub mod tag;
+pub mod timeline;
+pub mod todo;
+pub mod trash;
+pub mod tui;
+pub mod undo;
+pub mod verify;
+pub mod watch;
+
+use anyhow::Result;
+
+use crate::cli::{Cli, Command, GraphCommand};
+
+pub fn run(cli: Cli) -> Result<()> {
+    let level = crate::logging::level(cli.verbose, cli.quiet);
+    crate::logging::init(level, cli.log_format, cli.log_file.as_deref())?;
+    let format = cli.format;
+    if !matches!(cli.command, Command::Graph(GraphCommand::Export(_))) {
+        format.only_for_graphs()?;
+    }
+    crate::config::set_flag_overrides(cli.settings);
+    crate::kb::set_dry_run(cli.dry_run);
+    crate::progress::set_quiet(cli.quiet > 0);
+    if let Some(name) = cli.kb {
+        crate::kb::select(name);
+    }
+    match cli.command {
+        Command::Add(args) => add::run(args, format),
+        Command::Watch(args) => watch::run(args),
+        Command::New(args) => new::run(args, format),
+        Command::Journal(args) => journal::run(args, format),
+        Command::Search(args) => search::run(args, format),
+        Command::Find(args) => find::run(args, format),
+        Command::List(args) => list::run(args, format),
+        Command::Show(args) => show::run(args, format),
+        Command::Timeline(args) => timeline::run(args, format),
+        Command::Edit(args) => edit::run(args, format),
+        Command::Open(args) => open::run(args, format),
+        Command::Attach(args) => attach::run(args, format),
+        Command::Attachments(args) => attach::list(args, format),
+        Command::Tui => tui::run(),
+        Command::Serve(args) => serve::run(args),
+        Command::Sync(args) => sync::run(args, format),
+        Command::History(args) => history::run(args, format),
+        Command::Diff(args) => history::diff(args, format),
+        Command::Rm(args) => rm::run(args, format),
+        Command::Trash(cmd) => trash::run(cmd, format),
+        Command::Tag(cmd) => tag::run(cmd, format),
+        Command::Alias(cmd) => alias::run(cmd, format),
+        Command::Undo(args) => undo::run(args, format),
+        Command::Todo(cmd) => todo::run(cmd, format),
+        Command::Review(cmd) => review::run(cmd, format),
+        Command::Relate(args) => relate::run(args, format),
+        Command::Cites(args) => cites::run(args, format),
+        Command::CitedBy(args) => cites::cited_by(args, format),
+        Command::Classify(args) => classify::run(args, format),
+        Command::Summarize(args) => summarize::run(args, format),
+        Command::Cluster(args) => cluster::run(args, format),
+        Command::Ask(args) => ask::run(args, format),
+        Command::Chat(args) => chat::run(args),
+        Command::Ontology(cmd) => ontology::run(cmd, format),
+        Command::Graph(cmd) => graph::run(cmd, format),
+        Command::Stats => stats::run(format),
+        Command::Export(cmd) => export::run(cmd, format),
+        Command::Import(cmd) => import::run(cmd, format),
+        Command::Links(cmd) => links::run(cmd, format),
+        Command::Dedupe(cmd) => dedupe::run(cmd, format),
+        Command::Doctor(args) => doctor::run(args, format),
+        Command::Verify(args) => verify::run(args, format),
+        Command::Gc => gc::run(format),
+        Command::Reindex => reindex::run(format),
+        Command::Backup(args) => backup::run(args, format),
+        Command::Migrate => migrate::run(format),
+        Command::Models(cmd) => models::run(cmd, format),
+        Command::Prompts(cmd) => prompts::run(cmd, format),
+        Command::Completions(args) => completions::run(args),
+        Command::Config(cmd) => config::run(cmd, format),
+    }
+}
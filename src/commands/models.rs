@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+
+use crate::cli::ModelsCommand;
+use crate::config::{self, Config, ProviderKind};
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::models;
+use crate::output::{Format, Models, Pulled, Reembedded};
+
+pub fn run(cmd: ModelsCommand, format: Format) -> Result<()> {
+    match cmd {
+        ModelsCommand::Pull { repo } => {
+            let path = models::download(&repo)?;
+            format.print(&Pulled { repo, path }, |p| {
+                println!("{} is available in {}", p.repo, p.path.display())
+            })?;
+        }
+        ModelsCommand::List => {
+            let kb = KnowledgeBase::open(&kb::dir()?).ok();
+            let languages = match &kb {
+                Some(kb) => languages(kb)?,
+                None => BTreeMap::new(),
+            };
+            let list = Models {
+                vectors: kb
+                    .as_ref()
+                    .and_then(|kb| kb.vectors.model())
+                    .map(String::from),
+                languages,
+                downloaded: models::list_downloaded()?,
+            };
+            format.print(&list, |list| {
+                if let Some(model) = &list.vectors {
+                    println!("knowledge base vectors: {model}");
+                }
+                if !list.languages.is_empty() {
+                    let summary: Vec<String> = list
+                        .languages
+                        .iter()
+                        .map(|(l, n)| format!("{l} {n}"))
+                        .collect();
+                    println!("document languages: {}", summary.join(", "));
+                }
+                for repo in &list.downloaded {
+                    println!("downloaded: {repo}");
+                }
+            })?;
+        }
+        ModelsCommand::Use { model } => {
+            let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+            let embedding = &mut kb.config.embedding;
+            if model == "hashed" {
+                embedding.provider = ProviderKind::Hashed;
+                embedding.model = None;
+            } else {
+                embedding.provider = ProviderKind::Local;
+                embedding.model = Some(model);
+            }
+            embedding.url = None;
+            embedding.api_key_env = None;
+            let embedding = toml::Value::try_from(&kb.config.embedding)?;
+            config::edit_file(&Config::path(&kb.root), |table| {
+                config::insert(table, &["embedding"], embedding)
+            })?;
+            reembed(&mut kb, format)?;
+        }
+        ModelsCommand::Reembed => reembed(&mut KnowledgeBase::open(&kb::dir()?)?, format)?,
+    }
+    Ok(())
+}
+
+/// Counts the documents in each detected language, and warns about
+/// languages the configured local model was not trained on.
+fn languages(kb: &KnowledgeBase) -> Result<BTreeMap<String, usize>> {
+    let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+    for doc in kb.storage.all()? {
+        *counts
+            .entry(doc.language().unwrap_or("unknown").to_string())
+            .or_default() += 1;
+    }
+    let embedding = &kb.config.embedding;
+    if embedding.provider == ProviderKind::Local {
+        let repo = embedding
+            .model
+            .as_deref()
+            .unwrap_or(models::DEFAULT_EMBEDDING_MODEL);
+        let unsupported: Vec<&str> = counts
+            .keys()
+            .map(String::as_str)
+            .filter(|l| *l != "unknown" && !models::supports_language(repo, l))
+            .collect();
+        if !unsupported.is_empty() {
+            tracing::warn!(
+                "{repo} is English-only; documents in {} will embed poorly, \
+                 consider a multilingual model",
+                unsupported.join(", ")
+            );
+        }
+    }
+    Ok(counts)
+}
+
+fn reembed(kb: &mut KnowledgeBase, format: Format) -> Result<()> {
+    let documents = kb.reembed()?;
+    kb.commit()?;
+    let model = kb.vectors.model().unwrap_or_default().to_string();
+    format.print(&Reembedded { documents, model }, |r| {
+        println!("re-embedded {} documents with {}", r.documents, r.model)
+    })
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use serde::Deserialize;
+
+use crate::cli::NewArgs;
+use crate::commands::add;
+use crate::commands::config::open_editor;
+use crate::error::OzymandiasError;
+use crate::export::{file_name, split_frontmatter};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, NewNote};
+use crate::tags;
+use crate::templates;
+use crate::undo::command;
+
+/// What a note starts with when no template is named: a frontmatter stub
+/// whose tags are applied once the note is saved, and a heading for its
+/// title.
+const STUB: &str = "---\ntags: []\n---\n\n# {{title}}\n\n";
+
+/// The part of a note's frontmatter `ozy new` reads.
+#[derive(Deserialize, Default)]
+struct Frontmatter {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+pub fn run(args: NewArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open_or_init(&kb::dir()?)?;
+    let file = file_name(&args.title);
+    if file.is_empty() {
+        bail!("{:?} cannot be used as a file name", args.title);
+    }
+    let dir = kb.workspace_path(&kb.config.notes.dir);
+    let path = dir.join(format!("{file}.md"));
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    let template = match &args.template {
+        Some(name) => templates::load(&kb.root, name)?.ok_or_else(|| {
+            OzymandiasError::NotFound(format!(
+                "no template {}",
+                templates::path(&kb.root, name).display()
+            ))
+        })?,
+        None => STUB.to_string(),
+    };
+    let mut vars = templates::builtins(&args.title, Local::now().date_naive());
+    vars.insert("time", Local::now().format("%H:%M").to_string());
+    let given: BTreeMap<&str, &str> = args
+        .vars
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    for name in templates::variables(&template) {
+        if vars.contains_key(name) {
+            continue;
+        }
+        let value = match given.get(name) {
+            Some(value) => value.to_string(),
+            None => ask(name)?,
+        };
+        vars.insert(name, value);
+    }
+    if kb::dry_run() {
+        bail!("--dry-run cannot create {}", path.display());
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("cannot create {}", dir.display()))?;
+    let text = templates::render(&template, &vars);
+    fs::write(&path, &text).with_context(|| format!("failed to write {}", path.display()))?;
+    if !args.no_edit {
+        open_editor(&path)?;
+        let saved = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if saved == text || saved.trim().is_empty() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+            bail!("the note was not saved; nothing was added");
+        }
+    }
+
+    kb.record_as(command("new", [args.title.clone()]));
+    let added = add::file(&mut kb, &path)?;
+    let tags = frontmatter_tags(&path)?;
+    if !tags.is_empty() {
+        kb.retag(&added.id, &tags, &[])?;
+    }
+    kb.commit()?;
+    let note = NewNote {
+        id: added.id,
+        title: added.title.unwrap_or(args.title),
+        path,
+        template: args.template,
+    };
+    format.print(&note, |n| {
+        println!("created {}  {}", n.id, n.path.display());
+    })
+}
+
+/// The normalized tags listed in the frontmatter of the note at `path`.
+fn frontmatter_tags(path: &Path) -> Result<Vec<String>> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let Some(yaml) = split_frontmatter(&text).0 else {
+        return Ok(Vec::new());
+    };
+    let frontmatter: Frontmatter = serde_yaml::from_str::<Option<Frontmatter>>(yaml)
+        .with_context(|| format!("invalid frontmatter in {}", path.display()))?
+        .unwrap_or_default();
+    frontmatter
+        .tags
+        .iter()
+        .map(|t| tags::normalize(t))
+        .collect()
+}
+
+/// Asks for the value of a template variable on the terminal.
+fn ask(name: &str) -> Result<String> {
+    eprint!("{name}: ");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        bail!("no value for {{{{{name}}}}}; pass it with --var {name}=VALUE");
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
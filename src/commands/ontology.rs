@@ -0,0 +1,91 @@
+use anyhow::{bail, Context, Result};
+
+use crate::cli::{OntologyCommand, RdfFormat};
+use crate::kb::{self, KnowledgeBase, KB_DIR};
+use crate::ontology::{Ontology, Severity, ONTOLOGY_FILE};
+use crate::output::{Format, Imported};
+use crate::skos;
+use crate::storage::{FsStorage, Storage};
+
+pub fn run(cmd: OntologyCommand, format: Format) -> Result<()> {
+    if let OntologyCommand::Lint = cmd {
+        return lint(format);
+    }
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        OntologyCommand::Import { file, from, lang } => {
+            let syntax = match from {
+                Some(RdfFormat::Turtle) => skos::Format::Turtle,
+                Some(RdfFormat::Rdfxml) => skos::Format::RdfXml,
+                None => skos::Format::from_path(&file).with_context(|| {
+                    format!("cannot tell the format of {}; pass --from", file.display())
+                })?,
+            };
+            let import = skos::import(&file, syntax, &lang.to_ascii_lowercase())?;
+            let mut ontology = Ontology::load(&kb.root)?.unwrap_or_default();
+            let mut added = 0;
+            let mut skipped = import.skipped;
+            for (name, concept) in import.ontology.concepts {
+                if ontology.insert(name, concept) {
+                    added += 1;
+                } else {
+                    skipped += 1;
+                }
+            }
+            ontology.prune_parents();
+            ontology.save(&kb.root)?;
+            let imported = Imported {
+                file,
+                added,
+                skipped,
+            };
+            format.print(&imported, |i| {
+                println!("imported {} concepts from {}", i.added, i.file.display());
+                if i.skipped > 0 {
+                    println!(
+                        "skipped {} concepts whose names were already taken",
+                        i.skipped
+                    );
+                }
+            })?;
+        }
+        OntologyCommand::Lint => unreachable!("handled above"),
+    }
+    Ok(())
+}
+
+/// Works without opening the knowledge base, which refuses to load an
+/// invalid ontology.
+fn lint(format: Format) -> Result<()> {
+    let root = kb::dir()?.join(KB_DIR);
+    let path = root.join(ONTOLOGY_FILE);
+    let raw = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let docs = FsStorage::open(&root)?.all()?;
+    let issues = Ontology::lint(&raw, &path, &docs);
+    format.print(&issues, |issues| {
+        for issue in issues {
+            let severity = match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            println!(
+                "{}:{}: {severity}: {}",
+                path.display(),
+                issue.problem.line,
+                issue.problem.message
+            );
+        }
+        if issues.is_empty() {
+            println!("no problems found");
+        }
+    })?;
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        bail!("{errors} errors in {}", path.display());
+    }
+    Ok(())
+}
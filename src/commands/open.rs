@@ -0,0 +1,111 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::cli::OpenArgs;
+use crate::commands::config::open_editor;
+use crate::error::OzymandiasError;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, Opened};
+use crate::types::DocumentKind;
+
+pub fn run(args: OpenArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let doc = kb.get(&kb.resolve(&args.id)?)?;
+    let Some(source) = doc.source.clone() else {
+        bail!(OzymandiasError::NotFound(format!(
+            "{} has no original to open; see `ozy show` or `ozy edit`",
+            doc.id
+        )));
+    };
+    let web = source.starts_with("http://") || source.starts_with("https://");
+    let with = if web {
+        if args.reveal {
+            bail!("{} is a web page, which cannot be revealed", doc.id);
+        }
+        launch(OsStr::new(&source))?;
+        "browser"
+    } else {
+        let path = Path::new(&source);
+        if !path.exists() {
+            bail!(OzymandiasError::NotFound(format!(
+                "{source}, which {} was added from, no longer exists",
+                doc.id
+            )));
+        }
+        if args.reveal {
+            reveal(path)?;
+            "file-manager"
+        } else if matches!(doc.kind, DocumentKind::Markdown | DocumentKind::Text)
+            && !path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("pdf"))
+        {
+            open_editor(path)?;
+            "editor"
+        } else {
+            launch(path.as_os_str())?;
+            "viewer"
+        }
+    };
+    let opened = Opened {
+        id: doc.id,
+        target: source,
+        with,
+    };
+    format.print(&opened, |o| {
+        println!("opened {} in the {}", o.target, o.with)
+    })
+}
+
+/// Opens a file or URL in the application the system uses for it.
+fn launch(target: &OsStr) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    run_opener(command.arg(target))
+}
+
+/// Shows a file in the file manager, selected where the system allows.
+fn reveal(path: &Path) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    } else if cfg!(windows) {
+        let mut command = Command::new("explorer");
+        let mut select = std::ffi::OsString::from("/select,");
+        select.push(path);
+        command.arg(select);
+        command
+    } else {
+        let mut command = Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(Path::new(".")));
+        command
+    };
+    // Explorer exits with 1 even when it succeeds.
+    if cfg!(windows) {
+        command.status().context("failed to run explorer")?;
+        return Ok(());
+    }
+    run_opener(&mut command)
+}
+
+fn run_opener(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .with_context(|| format!("failed to run {program}"))?;
+    if !status.success() {
+        bail!("{program} exited with {status}");
+    }
+    Ok(())
+}
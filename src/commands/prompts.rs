@@ -0,0 +1,74 @@
+use anyhow::{Context, Result};
+
+use crate::cli::PromptsCommand;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::prompts::TEMPLATES;
+use crate::output::{Format, PromptFile, PromptTemplate, PromptText};
+
+pub fn run(cmd: PromptsCommand, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let prompts = kb.prompts();
+    match cmd {
+        PromptsCommand::List => {
+            let templates: Vec<PromptTemplate> = TEMPLATES
+                .iter()
+                .map(|template| {
+                    let path = prompts.path(template);
+                    PromptTemplate {
+                        name: template.name,
+                        path: path.exists().then_some(path),
+                        variables: template.variables.to_vec(),
+                    }
+                })
+                .collect();
+            format.print(&templates, |templates| {
+                for t in templates {
+                    let origin = match &t.path {
+                        Some(path) => path.display().to_string(),
+                        None => "built-in".to_string(),
+                    };
+                    println!(
+                        "{}  ({origin})  variables: {}",
+                        t.name,
+                        t.variables.join(", ")
+                    );
+                }
+            })?;
+        }
+        PromptsCommand::Show { name } => {
+            let template = TEMPLATES.iter().find(|t| t.name == name).with_context(|| {
+                let names: Vec<_> = TEMPLATES.iter().map(|t| t.name).collect();
+                format!(
+                    "no prompt template {name:?} (expected one of {})",
+                    names.join(", ")
+                )
+            })?;
+            let shown = PromptText {
+                name: template.name,
+                text: prompts.text(template)?,
+            };
+            format.print(&shown, |shown| println!("{}", shown.text))?;
+        }
+        PromptsCommand::Init => {
+            std::fs::create_dir_all(prompts.dir())
+                .with_context(|| format!("cannot create {}", prompts.dir().display()))?;
+            let mut files = Vec::new();
+            for template in &TEMPLATES {
+                let path = prompts.path(template);
+                let written = !path.exists();
+                if written {
+                    std::fs::write(&path, template.default)
+                        .with_context(|| format!("cannot write {}", path.display()))?;
+                }
+                files.push(PromptFile { path, written });
+            }
+            format.print(&files, |files| {
+                for f in files {
+                    let verb = if f.written { "wrote" } else { "kept" };
+                    println!("{verb} {}", f.path.display());
+                }
+            })?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anyhow::Result;
+
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, Reindexed};
+
+pub fn run(format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let documents = kb.reindex()?;
+    kb.commit()?;
+    format.print(&Reindexed { documents }, |r| {
+        println!("reindexed {} documents", r.documents);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kb::scratch::Scratch;
+    use crate::types::{Document, DocumentId, DocumentKind};
+
+    #[test]
+    fn a_lost_index_is_rebuilt_from_the_stored_documents() {
+        let mut scratch = Scratch::new("reindex");
+        for name in ["Ada Lovelace", "Charles Babbage"] {
+            let doc = Document::new(
+                DocumentId::derive(name),
+                name.into(),
+                DocumentKind::Markdown,
+                format!("Notes on {name} and the Analytical Engine."),
+                None,
+            );
+            scratch.kb.insert(&doc).unwrap();
+        }
+        scratch.kb.commit().unwrap();
+        std::fs::remove_file(scratch.kb.root.join("index.json")).unwrap();
+        scratch.reopen();
+        assert!(scratch.kb.index.search("lovelace").is_empty());
+
+        assert_eq!(scratch.kb.reindex().unwrap(), 2);
+        scratch.kb.commit().unwrap();
+        scratch.reopen();
+        assert_eq!(scratch.kb.index.len(), 2);
+        assert_eq!(scratch.kb.index.search("lovelace").len(), 1);
+        assert_eq!(scratch.kb.index.search("engine").len(), 2);
+    }
+}
@@ -0,0 +1,44 @@
+use anyhow::{bail, Result};
+
+use crate::cli::RelateArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, Related};
+use crate::relations::{Relation, RelationKind};
+
+pub fn run(args: RelateArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let kind = RelationKind::parse(&args.kind, kb.ontology.as_ref())?;
+    let from = kb.resolve(&args.from)?;
+    let to = kb.resolve(&args.to)?;
+    if args.remove {
+        if !kb.unrelate(&from, &kind, &to)? {
+            bail!("{from} has no {kind} relation to {to}");
+        }
+    } else {
+        if !(0.0..=1.0).contains(&args.confidence) {
+            bail!("confidence must be between 0 and 1");
+        }
+        if from == to {
+            bail!("a document cannot be related to itself");
+        }
+        kb.relate(
+            &from,
+            Relation {
+                kind: kind.clone(),
+                target: to.clone(),
+                confidence: args.confidence,
+            },
+        )?;
+    }
+    kb.commit()?;
+    let related = Related {
+        from,
+        kind: kind.to_string(),
+        to,
+        removed: args.remove,
+    };
+    format.print(&related, |r| {
+        let removed = if r.removed { "removed " } else { "" };
+        println!("{removed}{} {} {}", r.from, r.kind, r.to);
+    })
+}
@@ -0,0 +1,175 @@
+use std::io::{self, BufRead, Write};
+
+use anyhow::Result;
+use chrono::{Local, NaiveDate};
+
+use crate::cli::ReviewCommand;
+use crate::export::anki::{flashcards, title_card};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, ReviewCard, ReviewSession};
+use crate::review::{Card, Reviews, MAX_GRADE, PASS};
+use crate::types::Document;
+
+pub fn run(cmd: ReviewCommand, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let mut reviews = Reviews::open(&kb.root)?;
+    let today = Local::now().date_naive();
+    match cmd {
+        ReviewCommand::Add { ids, query } => {
+            let mut docs = Vec::new();
+            for id in &ids {
+                docs.push(kb.get(&kb.resolve(id)?)?);
+            }
+            if let Some(query) = query {
+                let query = kb.parse_query(&query)?;
+                docs.extend(
+                    kb.storage
+                        .all()?
+                        .into_iter()
+                        .filter(|doc| query.matches(doc, true)),
+                );
+            }
+            let mut added = Vec::new();
+            for doc in &docs {
+                let mut questions: Vec<Option<String>> =
+                    flashcards(doc).into_iter().map(|(q, _)| Some(q)).collect();
+                if questions.is_empty() {
+                    questions.push(None);
+                }
+                for question in questions {
+                    if reviews.add(&doc.id, question.clone(), today) {
+                        let card = reviews.cards().last().expect("a card was just added");
+                        added.push(entry(card, &doc.title));
+                    }
+                }
+            }
+            reviews.save()?;
+            format.print(&added, |added| {
+                println!("{} cards added for review", added.len());
+            })
+        }
+        ReviewCommand::Remove { ids } => {
+            let mut removed = Vec::new();
+            for id in &ids {
+                let id = kb.resolve(id)?;
+                let title = kb.get(&id)?.title;
+                removed.extend(reviews.remove(&id).iter().map(|c| entry(c, &title)));
+            }
+            reviews.save()?;
+            format.print(&removed, |removed| {
+                println!("{} cards taken out of review", removed.len());
+            })
+        }
+        ReviewCommand::Start { limit } => {
+            let session = study(&kb, &mut reviews, limit, today)?;
+            format.print(&session, |s| {
+                println!(
+                    "reviewed {} cards, {} forgotten; {} still due today",
+                    s.reviewed, s.forgotten, s.remaining
+                );
+            })
+        }
+        ReviewCommand::List { due } => {
+            let mut cards: Vec<&Card> = reviews
+                .cards()
+                .iter()
+                .filter(|c| !due || c.schedule.due <= today)
+                .collect();
+            cards.sort_by_key(|c| c.schedule.due);
+            let mut entries = Vec::new();
+            for card in cards {
+                let title = match kb.storage.get(&card.document)? {
+                    Some(doc) => doc.title,
+                    None => continue,
+                };
+                entries.push(entry(card, &title));
+            }
+            format.print(&entries, |entries| {
+                for e in entries {
+                    let question = e.question.as_deref().unwrap_or(&e.title);
+                    println!("{}  {}  {question}", e.due.format("%Y-%m-%d"), e.document);
+                }
+            })
+        }
+    }
+}
+
+/// Asks the due cards one by one on the terminal.
+fn study(
+    kb: &KnowledgeBase,
+    reviews: &mut Reviews,
+    limit: usize,
+    today: NaiveDate,
+) -> Result<ReviewSession> {
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut session = ReviewSession {
+        reviewed: 0,
+        forgotten: 0,
+        remaining: 0,
+    };
+    for card in reviews.due(today).into_iter().take(limit) {
+        let doc = kb.storage.get(&card.document)?;
+        let Some((front, back)) = doc.as_ref().and_then(|d| sides(d, &card)) else {
+            // The document or the flashcard is gone.
+            reviews.drop_card(&card.document, card.question.as_deref());
+            continue;
+        };
+        println!("\n{front}");
+        print!("[enter to show the answer] ");
+        io::stdout().flush()?;
+        if lines.next().transpose()?.is_none() {
+            break;
+        }
+        println!("{back}");
+        let Some(grade) = ask_grade(&mut lines)? else {
+            break;
+        };
+        reviews.grade(&card.document, card.question.as_deref(), grade, today);
+        reviews.save()?;
+        session.reviewed += 1;
+        if grade < PASS {
+            session.forgotten += 1;
+        }
+    }
+    reviews.save()?;
+    session.remaining = reviews.due(today).len();
+    Ok(session)
+}
+
+/// The question and answer of a card, if the document still has it.
+fn sides(doc: &Document, card: &Card) -> Option<(String, String)> {
+    match &card.question {
+        Some(question) => flashcards(doc).into_iter().find(|(q, _)| q == question),
+        None => Some(title_card(doc)),
+    }
+}
+
+/// Reads a grade from 0 to 5, or none when the session is to end.
+fn ask_grade(lines: &mut impl Iterator<Item = io::Result<String>>) -> Result<Option<u8>> {
+    loop {
+        print!("grade 0 (forgot) to {MAX_GRADE} (easy), or q to stop: ");
+        io::stdout().flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(None);
+        };
+        match line.trim() {
+            "q" | "quit" => return Ok(None),
+            answer => match answer.parse::<u8>() {
+                Ok(grade) if grade <= MAX_GRADE => return Ok(Some(grade)),
+                _ => println!("expected a number from 0 to {MAX_GRADE}"),
+            },
+        }
+    }
+}
+
+fn entry(card: &Card, title: &str) -> ReviewCard {
+    ReviewCard {
+        document: card.document.clone(),
+        title: title.to_string(),
+        question: card.question.clone(),
+        due: card.schedule.due,
+        interval: card.schedule.interval,
+        reviews: card.history.len(),
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{bail, Result};
+
+use crate::cli::RmArgs;
+use crate::error::OzymandiasError;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, RemoveAction, Removed};
+use crate::types::DocumentId;
+use crate::undo::command;
+
+pub fn run(args: RmArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let flags = args.purge.then(|| "--purge".to_string());
+    kb.record_as(command(
+        "rm",
+        flags.into_iter().chain(args.ids.iter().cloned()),
+    ));
+    let mut removed = Vec::new();
+    for arg in &args.ids {
+        // A tombstoned id no longer resolves, so accept it verbatim for --purge.
+        let id = match kb.resolve(arg) {
+            Ok(id) => id,
+            Err(_) if args.purge && kb.tombstones.get(&DocumentId(arg.clone())).is_some() => {
+                DocumentId(arg.clone())
+            }
+            Err(e) => return Err(e),
+        };
+        let (title, action) = match kb.remove(&id, args.purge)? {
+            Some(doc) if args.purge => (Some(doc.title), RemoveAction::Purged),
+            Some(doc) => (Some(doc.title), RemoveAction::Removed),
+            None if args.purge => (None, RemoveAction::ClearedTombstone),
+            None => bail!(OzymandiasError::NotFound(format!(
+                "no document with id {id}"
+            ))),
+        };
+        removed.push(Removed { id, title, action });
+    }
+    kb.commit()?;
+    format.print(&removed, |removed| {
+        for r in removed {
+            let title = r.title.as_deref().unwrap_or_default();
+            match r.action {
+                RemoveAction::Purged => println!("purged {}  {title}", r.id),
+                RemoveAction::Removed => println!("removed {}  {title}", r.id),
+                RemoveAction::ClearedTombstone => println!("cleared tombstone of {}", r.id),
+            }
+        }
+    })
+}
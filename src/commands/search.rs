@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+
+use crate::cli::SearchArgs;
+use crate::config::{self, Config, SavedSearch};
+use crate::error::OzymandiasError;
+use crate::index::snippet;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::summarize::SUMMARY_KEY;
+use crate::output::{Count, Facets, Format, SavedSearchEntry, SearchResult, SearchResults};
+use crate::search::{self, SearchMode};
+
+const SNIPPET_WIDTH: usize = 120;
+
+pub fn run(args: SearchArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    if args.list_saved {
+        let saved: Vec<SavedSearchEntry> = kb
+            .config
+            .saved_searches
+            .iter()
+            .map(|(name, saved)| SavedSearchEntry {
+                name: name.clone(),
+                query: saved.query.clone(),
+                mode: saved.mode,
+            })
+            .collect();
+        return format.print(&saved, |saved| {
+            for entry in saved {
+                let mode = entry.mode.to_possible_value().expect("no skipped variants");
+                println!("{}  {:?}  ({})", entry.name, entry.query, mode.get_name());
+            }
+        });
+    }
+
+    let mode = if args.semantic {
+        SearchMode::Semantic
+    } else {
+        args.mode
+    };
+    let (query, mode) = match (&args.saved, args.query) {
+        (Some(name), _) => {
+            let saved = kb.config.saved_searches.get(name).with_context(|| {
+                OzymandiasError::NotFound(format!("no saved search named {name:?}"))
+            })?;
+            (saved.query.clone(), saved.mode)
+        }
+        (None, Some(query)) => (query, mode),
+        (None, None) => unreachable!("clap requires a query or --saved"),
+    };
+    if let Some(name) = args.save {
+        let saved = SavedSearch {
+            query: query.clone(),
+            mode,
+        };
+        let value = toml::Value::try_from(&saved)?;
+        config::edit_file(&Config::path(&kb.root), |table| {
+            config::insert(table, &["saved_searches", &name], value)
+        })?;
+        kb.config.saved_searches.insert(name.clone(), saved);
+        tracing::info!("saved search {name:?}");
+    }
+
+    let parsed = kb.parse_query(&query)?;
+    let matches = search::run(&mut kb, &parsed, mode)?;
+    let results = matches
+        .iter()
+        .enumerate()
+        .skip(args.offset)
+        .take(args.limit)
+        .map(|(rank, m)| {
+            // A summary describes the whole document better than an
+            // excerpt, unless a particular chunk matched.
+            let snippet = match m
+                .doc
+                .metadata
+                .get(SUMMARY_KEY)
+                .filter(|_| m.chunk.is_none())
+            {
+                Some(summary) => snippet(summary, "", SNIPPET_WIDTH),
+                None => snippet(m.passage(), &parsed.text, SNIPPET_WIDTH),
+            };
+            SearchResult {
+                rank: rank + 1,
+                id: m.doc.id.clone(),
+                title: m.doc.title.clone(),
+                score: m.score,
+                snippet,
+            }
+        })
+        .collect();
+    let facets = args.facets.then(|| {
+        let (tags, types) = search::facets(&matches);
+        Facets {
+            types: Count::all(types),
+            tags: Count::all(tags),
+        }
+    });
+    let output = SearchResults {
+        query,
+        mode,
+        total: matches.len(),
+        results,
+        facets,
+    };
+    format.print(&output, print)
+}
+
+fn print(output: &SearchResults) {
+    if output.total == 0 {
+        println!("no results for {:?}", output.query);
+        return;
+    }
+    for result in &output.results {
+        println!(
+            "{:>3}. {}  {}  ({:.4})",
+            result.rank, result.id, result.title, result.score
+        );
+        println!("     {}", result.snippet);
+    }
+    println!(
+        "showing {} of {} results",
+        output.results.len(),
+        output.total
+    );
+    if let Some(facets) = &output.facets {
+        print_facets(facets);
+    }
+}
+
+fn print_facets(facets: &Facets) {
+    println!("\ntypes:");
+    for Count { name, count } in &facets.types {
+        println!("  {name:<20} {count}");
+    }
+    if !facets.tags.is_empty() {
+        println!("tags:");
+        for Count { name, count } in &facets.tags {
+            println!("  {name:<20} {count}");
+        }
+    }
+}
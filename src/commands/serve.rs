@@ -0,0 +1,38 @@
+use anyhow::Result;
+
+use crate::cli::ServeArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::{mcp, server};
+
+pub fn run(args: ServeArgs) -> Result<()> {
+    if let Some(dir) = args.relay {
+        let token = match args.token {
+            Some(token) => token,
+            None => {
+                std::fs::create_dir_all(&dir)?;
+                let token = server::token(&dir)?;
+                println!("API token in {}", dir.join(server::TOKEN_FILE).display());
+                token
+            }
+        };
+        println!("relaying on http://{} (Ctrl-C stops)", args.addr);
+        return server::serve_relay(&dir, args.addr, token);
+    }
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    if args.mcp {
+        return mcp::serve(kb);
+    }
+    let token = match args.token {
+        Some(token) => token,
+        None => {
+            let token = server::token(&kb.root)?;
+            println!(
+                "API token in {}",
+                kb.root.join(server::TOKEN_FILE).display()
+            );
+            token
+        }
+    };
+    println!("serving on http://{} (Ctrl-C stops)", args.addr);
+    server::serve(kb, args.addr, token)
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+
+use crate::cli::ShowArgs;
+use crate::commands::list::human_size;
+use crate::graph::NodeId;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{DocumentRef, EntityEntry, Format, RelationEntry, Shown};
+use crate::types::{Document, DocumentId};
+
+pub fn run(args: ShowArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let mut doc = kb.get(&kb.resolve(&args.id)?)?;
+    doc.entities.sort_by_key(|e| e.kind);
+    let shown = Shown {
+        links_to: documents(&kb, kb.links.links_from(&doc.id))?,
+        linked_from: documents(&kb, kb.links.links_to(&doc.id))?,
+        relations: relations(&kb, &doc)?,
+        entities: doc
+            .entities
+            .iter()
+            .map(|e| EntityEntry {
+                kind: e.kind.name(),
+                name: e.name.clone(),
+            })
+            .collect(),
+        id: doc.id,
+        title: doc.title,
+        kind: doc.kind,
+        added: doc.added,
+        size: doc.content.len(),
+        source: doc.source,
+        tags: doc.tags,
+        aliases: doc.aliases,
+        keywords: doc.keywords,
+        metadata: doc.metadata,
+        content: doc.content,
+    };
+    format.print(&shown, print)
+}
+
+fn print(doc: &Shown) {
+    println!("{}", doc.title);
+    println!("{}", "=".repeat(doc.title.chars().count()));
+    println!("id:      {}", doc.id);
+    println!("type:    {}", doc.kind.name());
+    println!("added:   {}", doc.added.format("%Y-%m-%d %H:%M:%S UTC"));
+    println!("size:    {}", human_size(doc.size));
+    if let Some(source) = &doc.source {
+        println!("source:  {source}");
+    }
+    if !doc.tags.is_empty() {
+        println!("tags:    {}", doc.tags.join(", "));
+    }
+    if !doc.aliases.is_empty() {
+        println!("aliases: {}", doc.aliases.join(", "));
+    }
+    if !doc.keywords.is_empty() {
+        println!("keywords: {}", doc.keywords.join(", "));
+    }
+    for (key, value) in &doc.metadata {
+        println!("{key}: {value}");
+    }
+    print_entities(&doc.entities);
+    print_links("links to", &doc.links_to);
+    print_links("linked from", &doc.linked_from);
+    print_relations(&doc.relations);
+    println!();
+    println!("{}", doc.content.trim_end());
+}
+
+/// Stated relations of the document, then those inferred from the
+/// ontology.
+fn relations(kb: &KnowledgeBase, doc: &Document) -> Result<Vec<RelationEntry>> {
+    let mut relations = Vec::new();
+    for relation in &doc.relations {
+        relations.push(RelationEntry {
+            kind: relation.kind.to_string(),
+            target: relation.target.to_string(),
+            title: kb.storage.get(&relation.target)?.map(|d| d.title),
+            confidence: Some(relation.confidence),
+            inferred: false,
+        });
+    }
+    let node = NodeId::Document(doc.id.clone());
+    for n in kb.graph.neighbors(&node) {
+        if !(n.outgoing && n.edge.inferred) {
+            continue;
+        }
+        relations.push(RelationEntry {
+            kind: n.edge.kind.name().to_string(),
+            target: n.node.to_string(),
+            title: kb.graph.node(n.node).map(|n| n.label.clone()),
+            confidence: n.edge.confidence,
+            inferred: true,
+        });
+    }
+    Ok(relations)
+}
+
+fn print_relations(relations: &[RelationEntry]) {
+    if relations.is_empty() {
+        return;
+    }
+    println!("relations:");
+    for r in relations {
+        if r.inferred {
+            println!(
+                "  {} {}  {}{} (inferred)",
+                r.kind,
+                r.target,
+                r.title.as_deref().unwrap_or(""),
+                confidence(r.confidence)
+            );
+        } else {
+            println!(
+                "  {} {}  {}{}",
+                r.kind,
+                r.target,
+                r.title.as_deref().unwrap_or("(removed)"),
+                confidence(r.confidence)
+            );
+        }
+    }
+}
+
+/// Prints entities grouped by kind; they arrive sorted by kind.
+fn print_entities(entities: &[EntityEntry]) {
+    if entities.is_empty() {
+        return;
+    }
+    println!("entities:");
+    for group in entities.chunk_by(|a, b| a.kind == b.kind) {
+        let names: Vec<&str> = group.iter().map(|e| e.name.as_str()).collect();
+        println!("  {}: {}", group[0].kind, names.join(", "));
+    }
+}
+
+fn confidence(c: Option<f32>) -> String {
+    match c {
+        Some(c) if c < 1.0 => format!(" ({c:.2})"),
+        _ => String::new(),
+    }
+}
+
+fn documents<'a>(
+    kb: &KnowledgeBase,
+    ids: impl Iterator<Item = &'a DocumentId>,
+) -> Result<Vec<DocumentRef>> {
+    ids.map(|id| {
+        Ok(DocumentRef {
+            id: id.clone(),
+            title: kb.storage.get(id)?.map(|d| d.title).unwrap_or_default(),
+        })
+    })
+    .collect()
+}
+
+fn print_links(label: &str, docs: &[DocumentRef]) {
+    if docs.is_empty() {
+        return;
+    }
+    println!("{label}:");
+    for doc in docs {
+        println!("  {}  {}", doc.id, doc.title);
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Count, Format, Growth, IndexSize, LinkCounts, Stats, StorageSize};
+
+/// Tags listed in plain output; JSON and YAML have all of them.
+const PLAIN_TAGS: usize = 10;
+
+pub fn run(format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let docs = kb.storage.all()?;
+
+    let mut types: HashMap<String, usize> = HashMap::new();
+    let mut tags: HashMap<String, usize> = HashMap::new();
+    let mut months: BTreeMap<String, usize> = BTreeMap::new();
+    let mut links = LinkCounts {
+        internal: 0,
+        external: 0,
+    };
+    for doc in &docs {
+        *types.entry(doc.kind.name().to_string()).or_default() += 1;
+        for tag in &doc.tags {
+            *tags.entry(tag.clone()).or_default() += 1;
+        }
+        *months
+            .entry(doc.added.format("%Y-%m").to_string())
+            .or_default() += 1;
+        links.internal += kb.links.links_from(&doc.id).count();
+        links.external += doc.links.iter().filter(|l| l.is_external()).count();
+    }
+    let mut total = 0;
+    let growth = months
+        .into_iter()
+        .map(|(month, added)| {
+            total += added;
+            Growth {
+                month,
+                added,
+                total,
+            }
+        })
+        .collect();
+
+    let stats = Stats {
+        documents: docs.len(),
+        types: most_common(types),
+        tags: most_common(tags),
+        storage: storage_size(&kb.root)?,
+        index: IndexSize {
+            documents: kb.index.len(),
+            terms: kb.index.term_count(),
+            vectors: kb.vectors.len(),
+        },
+        links,
+        growth,
+    };
+    format.print(&stats, print)
+}
+
+fn most_common(counts: HashMap<String, usize>) -> Vec<Count> {
+    let mut counts = Count::all(counts);
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    counts
+}
+
+/// Adds up the files of the knowledge base by what they hold.
+fn storage_size(root: &Path) -> Result<StorageSize> {
+    let mut size = StorageSize {
+        documents: 0,
+        blobs: 0,
+        indexes: 0,
+        other: 0,
+        total: 0,
+        compressed: 0,
+    };
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries =
+            std::fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let len = bytes.len() as u64;
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            let part = match relative.iter().next().and_then(|p| p.to_str()) {
+                Some("documents") => &mut size.documents,
+                Some("blobs") => &mut size.blobs,
+                Some(
+                    "index.json" | "vectors.json" | "links.json" | "graph.json" | "tasks.json",
+                ) => &mut size.indexes,
+                _ => &mut size.other,
+            };
+            *part += len;
+            size.total += len;
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&bytes)?;
+            size.compressed += encoder.finish()?.len() as u64;
+        }
+    }
+    Ok(size)
+}
+
+fn print(stats: &Stats) {
+    println!("{} documents", stats.documents);
+    for Count { name, count } in &stats.types {
+        println!("  {name:<20} {count}");
+    }
+    if !stats.tags.is_empty() {
+        println!("\n{} tags", stats.tags.len());
+        for Count { name, count } in stats.tags.iter().take(PLAIN_TAGS) {
+            println!("  {name:<20} {count}");
+        }
+        if stats.tags.len() > PLAIN_TAGS {
+            println!("  ... and {} more", stats.tags.len() - PLAIN_TAGS);
+        }
+    }
+    let s = &stats.storage;
+    println!("\nstorage");
+    println!("  {:<20} {}", "documents", human_bytes(s.documents));
+    println!("  {:<20} {}", "snapshots", human_bytes(s.blobs));
+    println!("  {:<20} {}", "indexes", human_bytes(s.indexes));
+    println!("  {:<20} {}", "other", human_bytes(s.other));
+    println!(
+        "  {:<20} {} ({} compressed)",
+        "total",
+        human_bytes(s.total),
+        human_bytes(s.compressed)
+    );
+    println!(
+        "\nindex: {} documents, {} terms, {} vectors",
+        stats.index.documents, stats.index.terms, stats.index.vectors
+    );
+    println!(
+        "links: {} internal, {} external",
+        stats.links.internal, stats.links.external
+    );
+    if !stats.growth.is_empty() {
+        println!("\ngrowth");
+        for g in &stats.growth {
+            println!("  {}  +{:<6} {}", g.month, g.added, g.total);
+        }
+    }
+}
+
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
@@ -0,0 +1,50 @@
+use anyhow::Result;
+
+use crate::cli::SummarizeArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::llm_from_config;
+use crate::ml::summarize::{summarize, SUMMARY_KEY};
+use crate::output::{Format, Summarized};
+use crate::parallel;
+use crate::runtime;
+
+pub fn run(args: SummarizeArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let llm = llm_from_config(&kb.config.llm);
+    let prompts = kb.prompts();
+    let docs = args
+        .ids
+        .iter()
+        .map(|arg| kb.get(&kb.resolve(arg)?))
+        .collect::<Result<Vec<_>>>()?;
+    // Language models are slow to answer, so several are asked at once.
+    let jobs = args.jobs.unwrap_or_else(parallel::default_jobs);
+    let written = runtime::block_on(parallel::map_async(&docs, jobs, |doc| async {
+        match doc.metadata.get(SUMMARY_KEY) {
+            Some(_) if !args.force => Ok(None),
+            _ => summarize(doc, llm.as_deref(), &prompts).await.map(Some),
+        }
+    }));
+    let mut summaries = Vec::new();
+    for (mut doc, written) in docs.into_iter().zip(written) {
+        let summary = match written? {
+            Some(summary) => {
+                doc.metadata.insert(SUMMARY_KEY.into(), summary.clone());
+                kb.storage.put(&doc)?;
+                summary
+            }
+            None => doc.metadata[SUMMARY_KEY].clone(),
+        };
+        summaries.push(Summarized {
+            id: doc.id,
+            title: doc.title,
+            summary,
+        });
+    }
+    format.print(&summaries, |summaries| {
+        for s in summaries {
+            println!("{}  {}", s.id, s.title);
+            println!("  {}", s.summary);
+        }
+    })
+}
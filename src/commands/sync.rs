@@ -0,0 +1,45 @@
+use std::io::{Read, Write};
+
+use anyhow::Result;
+
+use crate::cli::SyncArgs;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, Synced};
+use crate::sync::{self, Kept, Request};
+
+pub fn run(args: SyncArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    let Some(name) = args.remote else {
+        let mut raw = String::new();
+        std::io::stdin().read_to_string(&mut raw)?;
+        let request: Request = serde_json::from_str(&raw)?;
+        let response = sync::handle(&mut kb, request)?;
+        let mut stdout = std::io::stdout().lock();
+        serde_json::to_writer(&mut stdout, &response)?;
+        return Ok(stdout.flush()?);
+    };
+    let policy = args.policy.unwrap_or(kb.config.sync.policy);
+    let mut remote = sync::connect(&kb.root, &name, &kb.config.sync, args.token)?;
+    let report = sync::sync(&mut kb, remote.as_mut(), policy)?;
+    let synced = Synced {
+        remote: name,
+        pulled: report.pulled,
+        pushed: report.pushed,
+        conflicts: report.conflicts,
+    };
+    format.print(&synced, |s| {
+        for c in &s.conflicts {
+            let kept = match (c.kept, &c.copy) {
+                (Kept::Local, _) => "kept this version".to_string(),
+                (Kept::Remote, _) => "kept the remote's version".to_string(),
+                (Kept::Both, Some(copy)) => format!("kept both, the remote's as {copy}"),
+                (Kept::Both, None) => "kept both".to_string(),
+            };
+            println!("conflict  {}  {}: {kept}", c.id, c.title);
+        }
+        println!(
+            "documents changed: {} here, {} on {}",
+            s.pulled, s.pushed, s.remote
+        );
+    })
+}
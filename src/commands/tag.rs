@@ -0,0 +1,87 @@
+use anyhow::Result;
+
+use crate::cli::TagCommand;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Count, Format, TagTree, Tagged};
+use crate::tags::{self, TagNode};
+use crate::undo::command;
+
+pub fn run(cmd: TagCommand, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        TagCommand::Add { id, tags } => {
+            let id = kb.resolve(&id)?;
+            let tags = normalize_all(&tags)?;
+            kb.record_as(command(
+                "tag add",
+                std::iter::once(id.to_string()).chain(tags.clone()),
+            ));
+            let now = kb.retag(&id, &tags, &[])?;
+            kb.commit()?;
+            format.print(&Tagged { id, tags: now }, print_tagged)?;
+        }
+        TagCommand::Rm { id, tags } => {
+            let id = kb.resolve(&id)?;
+            let tags = normalize_all(&tags)?;
+            kb.record_as(command(
+                "tag rm",
+                std::iter::once(id.to_string()).chain(tags.clone()),
+            ));
+            let now = kb.retag(&id, &[], &tags)?;
+            kb.commit()?;
+            format.print(&Tagged { id, tags: now }, print_tagged)?;
+        }
+        TagCommand::List { counts, tree: true } => {
+            let tree = trees(&tags::tree(&tags::counts(kb.storage.as_ref())?));
+            format.print(&tree, |tree| print_tree(tree, 0, counts))?;
+        }
+        TagCommand::List {
+            counts,
+            tree: false,
+        } => {
+            let tags = Count::all(tags::counts(kb.storage.as_ref())?);
+            format.print(&tags, |tags| {
+                for Count { name, count } in tags {
+                    if counts {
+                        println!("{count:>5}  {name}");
+                    } else {
+                        println!("{name}");
+                    }
+                }
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn normalize_all(raw: &[String]) -> Result<Vec<String>> {
+    raw.iter().map(|t| tags::normalize(t)).collect()
+}
+
+fn print_tagged(tagged: &Tagged) {
+    println!("{}  tags: {}", tagged.id, tagged.tags.join(", "));
+}
+
+fn trees(node: &TagNode) -> Vec<TagTree> {
+    node.children
+        .iter()
+        .map(|(name, child)| TagTree {
+            name: name.clone(),
+            direct: child.direct,
+            total: child.total,
+            children: trees(child),
+        })
+        .collect()
+}
+
+fn print_tree(trees: &[TagTree], depth: usize, counts: bool) {
+    for tree in trees {
+        let indent = "  ".repeat(depth);
+        if counts {
+            println!("{indent}{} ({})", tree.name, tree.total);
+        } else {
+            println!("{indent}{}", tree.name);
+        }
+        print_tree(&tree.children, depth + 1, counts);
+    }
+}
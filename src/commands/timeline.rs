@@ -0,0 +1,51 @@
+use anyhow::Result;
+
+use crate::cli::{TimelineArgs, TimelineKey};
+use crate::dates::{self, Dated};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, TimelineEntry};
+
+pub fn run(args: TimelineArgs, format: Format) -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    let query = kb.parse_query(args.query.as_deref().unwrap_or_default())?;
+    let mut dated: Vec<(Dated, TimelineEntry)> = Vec::new();
+    for doc in kb.storage.all()? {
+        if !query.matches(&doc, true) {
+            continue;
+        }
+        let found = match args.by {
+            TimelineKey::Created => vec![dates::created(&doc)],
+            TimelineKey::Published => dates::published(&doc).into_iter().collect(),
+            TimelineKey::Mentions => dates::mentioned(&doc),
+            TimelineKey::Added => vec![Dated {
+                start: doc.added,
+                label: doc.added.format("%Y-%m-%d").to_string(),
+            }],
+        };
+        for date in found {
+            let entry = TimelineEntry {
+                date: date.label.clone(),
+                id: doc.id.clone(),
+                title: doc.title.clone(),
+            };
+            dated.push((date, entry));
+        }
+    }
+    // A year sorts before the months and days within it.
+    dated.sort_by(|(a, x), (b, y)| (a, &x.title).cmp(&(b, &y.title)));
+    if args.reverse {
+        dated.reverse();
+    }
+    let entries: Vec<TimelineEntry> = dated.into_iter().map(|(_, e)| e).collect();
+    format.print(&entries, |entries| {
+        let mut year = None;
+        for e in entries {
+            let this = e.date.get(..4).unwrap_or(&e.date);
+            if year != Some(this) {
+                println!("{this}");
+                year = Some(this);
+            }
+            println!("  {:<10}  {}  {}", e.date, e.id, e.title);
+        }
+    })
+}
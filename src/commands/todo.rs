@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+
+use crate::cli::TodoCommand;
+use crate::error::OzymandiasError;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, TodoEntry};
+use crate::tasks::{self, Task};
+use crate::undo::command;
+
+pub fn run(cmd: TodoCommand, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        TodoCommand::List { query, all, due } => {
+            if kb.tasks.is_missing() {
+                let docs = kb.storage.all()?;
+                kb.tasks.rebuild(&docs);
+            }
+            let today = Local::now().date_naive();
+            let due = match due {
+                Some(when) => {
+                    let lower = when.to_lowercase();
+                    let words: Vec<&str> = lower.split_whitespace().collect();
+                    let date = tasks::parse_date(&words, today).ok_or_else(|| {
+                        OzymandiasError::ParseFailed(format!("cannot tell what day {when:?} is"))
+                    })?;
+                    Some(date)
+                }
+                None => None,
+            };
+            let query = kb.parse_query(query.as_deref().unwrap_or_default())?;
+            let mut entries = Vec::new();
+            for task in kb.tasks.all() {
+                if (task.done && !all) || due.is_some_and(|by| task.due.is_none_or(|d| d > by)) {
+                    continue;
+                }
+                let Some(doc) = kb.storage.get(&task.document)? else {
+                    continue;
+                };
+                if query.matches(&doc, true) {
+                    entries.push(entry(task, &doc.title));
+                }
+            }
+            // Tasks without a due date come last.
+            entries.sort_by(|a, b| {
+                (a.due.is_none(), a.due, &a.title, a.line).cmp(&(
+                    b.due.is_none(),
+                    b.due,
+                    &b.title,
+                    b.line,
+                ))
+            });
+            format.print(&entries, |entries| {
+                for e in entries {
+                    let check = if e.done { "[x]" } else { "[ ]" };
+                    let due = match e.due {
+                        Some(due) => format!("  (due {})", due.format("%Y-%m-%d")),
+                        None => String::new(),
+                    };
+                    println!("{check} {}{due}  {}  {}", e.text, e.id, e.title);
+                }
+            })
+        }
+        TodoCommand::Done { tasks: names } => {
+            kb.record_as(command("todo done", names.iter().cloned()));
+            let mut done = Vec::new();
+            for name in &names {
+                let (prefix, line) = name
+                    .rsplit_once(':')
+                    .and_then(|(p, l)| Some((p, l.parse::<usize>().ok()?)))
+                    .with_context(|| {
+                        format!("{name:?} is not a task; expected <document>:<line>")
+                    })?;
+                let id = kb.resolve(prefix)?;
+                let mut doc = kb.get(&id)?;
+                let mut lines: Vec<String> = doc.content.split('\n').map(String::from).collect();
+                let Some(completed) = line
+                    .checked_sub(1)
+                    .and_then(|i| lines.get(i))
+                    .and_then(|l| tasks::complete(l))
+                else {
+                    bail!(OzymandiasError::NotFound(format!(
+                        "line {line} of {id} is not an open task"
+                    )));
+                };
+                let original = std::mem::replace(&mut lines[line - 1], completed.clone());
+                doc.content = lines.join("\n");
+                if let Some(source) = &doc.source {
+                    update_source(Path::new(source), line, &original, &completed)?;
+                }
+                kb.insert(&doc)?;
+                let task = tasks::extract(&doc)
+                    .into_iter()
+                    .find(|t| t.line == line)
+                    .expect("a completed task is still a task");
+                done.push(entry(&task, &doc.title));
+            }
+            kb.commit()?;
+            format.print(&done, |done| {
+                for e in done {
+                    println!("done: {}  {}", e.text, e.id);
+                }
+            })
+        }
+    }
+}
+
+/// Checks a task off in the file a document was added from, if the file
+/// still has it on the same line.
+fn update_source(path: &Path, line: usize, original: &str, completed: &str) -> Result<()> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Ok(());
+    };
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    if lines.get(line - 1) != Some(&original) {
+        tracing::warn!(
+            "{} has changed since it was added; check the task off there too",
+            path.display()
+        );
+        return Ok(());
+    }
+    if kb::dry_run() {
+        return Ok(());
+    }
+    lines[line - 1] = completed;
+    fs::write(path, lines.join("\n")).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn entry(task: &Task, title: &str) -> TodoEntry {
+    TodoEntry {
+        id: task.id(),
+        document: task.document.clone(),
+        title: title.to_string(),
+        line: task.line,
+        text: task.text.clone(),
+        done: task.done,
+        due: task.due,
+    }
+}
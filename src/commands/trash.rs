@@ -0,0 +1,96 @@
+use anyhow::{bail, Result};
+use chrono::Duration;
+
+use crate::cli::TrashCommand;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, TrashEntry};
+use crate::trash::Trashed;
+use crate::types::DocumentId;
+use crate::undo::command;
+
+pub fn run(cmd: TrashCommand, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    match cmd {
+        TrashCommand::List => {
+            let expiry = kb.config.trash.expire_after_days;
+            let entries: Vec<TrashEntry> = kb
+                .trash
+                .all()?
+                .iter()
+                .map(|t| TrashEntry {
+                    expires: expiry.map(|days| t.deleted + Duration::days(i64::from(days))),
+                    ..entry(t)
+                })
+                .collect();
+            format.print(&entries, |entries| {
+                for e in entries {
+                    let expires = match e.expires {
+                        Some(at) => format!("  (expires {})", at.format("%Y-%m-%d")),
+                        None => String::new(),
+                    };
+                    println!(
+                        "{}  {}  {}{expires}",
+                        e.id,
+                        e.deleted.format("%Y-%m-%d %H:%M"),
+                        e.title
+                    );
+                }
+            })
+        }
+        TrashCommand::Restore { ids } => {
+            let ids = ids
+                .iter()
+                .map(|i| kb.trash.resolve(i))
+                .collect::<Result<Vec<_>>>()?;
+            let restored = restore(&mut kb, ids)?;
+            format.print(&restored, |restored| {
+                for e in restored {
+                    println!("restored {}  {}", e.id, e.title);
+                }
+            })
+        }
+        TrashCommand::Empty => {
+            let mut emptied = Vec::new();
+            for trashed in kb.trash.all()? {
+                kb.trash.remove(&trashed.document.id)?;
+                kb.history.remove(&trashed.document.id)?;
+                emptied.push(entry(&trashed));
+            }
+            format.print(&emptied, |emptied| {
+                println!("emptied {} documents from the trash", emptied.len());
+            })
+        }
+    }
+}
+
+/// Puts documents in the trash back, as one operation `ozy undo` can
+/// reverse.
+pub fn restore(kb: &mut KnowledgeBase, ids: Vec<DocumentId>) -> Result<Vec<TrashEntry>> {
+    kb.record_as(command(
+        "trash restore",
+        ids.iter().map(|id| id.to_string()),
+    ));
+    let mut restored = Vec::new();
+    for id in ids {
+        let Some(trashed) = kb.trash.get(&id)? else {
+            continue;
+        };
+        if kb.storage.get(&id)?.is_some() {
+            bail!("{id} was added again since it was removed; remove that copy first");
+        }
+        kb.insert(&trashed.document)?;
+        kb.tombstones.remove(&id);
+        restored.push(entry(&trashed));
+    }
+    kb.commit()?;
+    Ok(restored)
+}
+
+fn entry(trashed: &Trashed) -> TrashEntry {
+    TrashEntry {
+        id: trashed.document.id.clone(),
+        title: trashed.document.title.clone(),
+        deleted: trashed.deleted,
+        expires: None,
+    }
+}
@@ -0,0 +1,8 @@
+use anyhow::Result;
+
+use crate::kb::{self, KnowledgeBase};
+
+pub fn run() -> Result<()> {
+    let kb = KnowledgeBase::open(&kb::dir()?)?;
+    crate::tui::run(kb)
+}
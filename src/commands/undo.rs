@@ -0,0 +1,99 @@
+use anyhow::{bail, Result};
+
+use crate::cli::UndoArgs;
+use crate::error::OzymandiasError;
+use crate::kb::{self, KnowledgeBase};
+use crate::output::{Format, UndoEntry, Undone};
+use crate::undo;
+
+pub fn run(args: UndoArgs, format: Format) -> Result<()> {
+    let mut kb = KnowledgeBase::open(&kb::dir()?)?;
+    if args.list {
+        let entries: Vec<UndoEntry> = kb
+            .undo
+            .operations()
+            .iter()
+            .rev()
+            .map(|op| UndoEntry {
+                command: op.command.clone(),
+                at: op.at,
+                documents: op.changes.len(),
+            })
+            .collect();
+        return format.print(&entries, |entries| {
+            for e in entries {
+                println!(
+                    "{}  {:>5} docs  ozy {}",
+                    e.at.format("%Y-%m-%d %H:%M"),
+                    e.documents,
+                    e.command
+                );
+            }
+        });
+    }
+    let undone = undo_last(&mut kb)?;
+    format.print(&undone, |u| {
+        println!(
+            "undid `ozy {}` from {}",
+            u.command,
+            u.at.format("%Y-%m-%d %H:%M")
+        );
+        for id in &u.restored {
+            println!("restored {id}");
+        }
+        for id in &u.removed {
+            println!("removed {id}");
+        }
+    })
+}
+
+/// Reverses the latest operation in the undo log.
+pub fn undo_last(kb: &mut KnowledgeBase) -> Result<Undone> {
+    let Some(operation) = kb.undo.operations().last().cloned() else {
+        bail!(OzymandiasError::NotFound("nothing to undo".into()));
+    };
+    // A document changed since by a command that is not logged would lose
+    // that change.
+    for change in &operation.changes {
+        if undo::state(kb.storage.get(&change.id)?.as_ref())? != change.after {
+            bail!(
+                "{} changed after `ozy {}`, which can no longer be undone",
+                change.id,
+                operation.command
+            );
+        }
+    }
+    let (mut restored, mut removed) = (Vec::new(), Vec::new());
+    for change in &operation.changes {
+        match &change.before {
+            Some(doc) => {
+                kb.insert(doc)?;
+                restored.push(change.id.clone());
+            }
+            None => {
+                if kb.remove(&change.id, false)?.is_some() {
+                    removed.push(change.id.clone());
+                }
+            }
+        }
+        match &change.tombstone {
+            Some(tombstone) => kb.tombstones.insert(change.id.clone(), tombstone.clone()),
+            // Without a tombstone it was not in the trash either.
+            None => {
+                kb.tombstones.remove(&change.id);
+                kb.trash.remove(&change.id)?;
+            }
+        }
+    }
+    kb.commit()?;
+    kb.undo.pop();
+    if !kb::dry_run() {
+        kb.undo.save()?;
+    }
+    Ok(Undone {
+        command: operation.command,
+        at: operation.at,
+        restored,
+        removed,
+    })
+}
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{bail, Result};
+
+use crate::cli::VerifyArgs;
+use crate::error::OzymandiasError;
+use crate::kb::{self, KB_DIR};
+use crate::output::{Format, Problem, Verified};
+use crate::progress::Progress;
+use crate::storage::{self, BlobStore, FsStorage, Storage, CHECKSUMS_FILE};
+use crate::types::{Document, DocumentId};
+
+/// Re-hashes the files of the knowledge base without opening it, so that
+/// nothing is rebuilt or written unless checksums are to be recorded.
+pub fn run(args: VerifyArgs, format: Format) -> Result<()> {
+    let dir = kb::dir()?;
+    let root = dir.join(KB_DIR);
+    if !root.is_dir() {
+        bail!(OzymandiasError::NotFound(format!(
+            "no knowledge base found in {}",
+            dir.display()
+        )));
+    }
+    let ids = FsStorage::open(&root)?.ids()?;
+    let blobs = BlobStore::open(&root)?;
+    let hashes = blobs.hashes()?;
+    let mut checksums = storage::checksums(&root)?;
+    let progress = Progress::new("files verified", ids.len() + hashes.len());
+
+    let mut problems = Vec::new();
+    let mut problem = |check, id: Option<&DocumentId>, message: String, fixable| {
+        problems.push(Problem {
+            check,
+            id: id.cloned(),
+            message,
+            fixable,
+            fixed: false,
+        });
+    };
+
+    // Blobs each is needed by, to name a document when one is missing.
+    let mut needed: BTreeMap<String, Vec<DocumentId>> = BTreeMap::new();
+    let mut unrecorded = Vec::new();
+    for id in &ids {
+        let path = root.join("documents").join(format!("{id}.json"));
+        let bytes = std::fs::read(&path)?;
+        let checksum = storage::sha256(&bytes);
+        let recorded = checksums.get(id);
+        let damaged = recorded.is_some_and(|r| *r != checksum);
+        if damaged {
+            problem(
+                "corrupt-document",
+                Some(id),
+                format!("{} does not match its checksum", path.display()),
+                false,
+            );
+        }
+        match serde_json::from_slice::<Document>(&bytes) {
+            Ok(doc) => {
+                for hash in kb::blobs_of(&doc) {
+                    needed.entry(hash.clone()).or_default().push(id.clone());
+                }
+                if recorded.is_none() {
+                    unrecorded.push((id.clone(), checksum));
+                }
+            }
+            Err(e) if !damaged => problem("unreadable", Some(id), format!("{e}"), false),
+            Err(_) => {}
+        }
+        progress.inc(1);
+    }
+    let stored: BTreeSet<&DocumentId> = ids.iter().collect();
+    for id in checksums.keys().filter(|id| !stored.contains(id)) {
+        problem(
+            "missing-document",
+            Some(id),
+            "has a checksum, but its file is gone".into(),
+            false,
+        );
+    }
+    for (id, _) in &unrecorded {
+        problem(
+            "unrecorded",
+            Some(id),
+            "has no checksum to verify against".into(),
+            true,
+        );
+    }
+
+    let mut present = BTreeSet::new();
+    for hash in &hashes {
+        if let Some(bytes) = blobs.get(hash)? {
+            if storage::sha256(&bytes) != *hash {
+                problem(
+                    "corrupt-blob",
+                    None,
+                    format!("blob {hash} does not match its name"),
+                    false,
+                );
+            }
+        }
+        present.insert(hash.as_str());
+        progress.inc(1);
+    }
+    for (hash, ids) in &needed {
+        if !present.contains(hash.as_str()) {
+            for id in ids {
+                problem(
+                    "missing-blob",
+                    Some(id),
+                    format!("blob {hash} is missing"),
+                    false,
+                );
+            }
+        }
+    }
+
+    let mut recorded = 0;
+    if args.record && !unrecorded.is_empty() {
+        recorded = unrecorded.len();
+        checksums.extend(unrecorded);
+        if !kb::dry_run() {
+            storage::write_json(&root.join(CHECKSUMS_FILE), &checksums)?;
+        }
+        for problem in &mut problems {
+            problem.fixed = problem.fixable;
+        }
+    }
+
+    let left = problems.iter().filter(|p| !p.fixed).count();
+    let verified = Verified {
+        documents: ids.len(),
+        blobs: present.len(),
+        recorded,
+        problems,
+    };
+    format.print(&verified, |v| {
+        for p in &v.problems {
+            let id =
+                p.id.as_ref()
+                    .map(|id| format!("{id}  "))
+                    .unwrap_or_default();
+            let state = match (p.fixed, p.fixable) {
+                (true, _) => "  (recorded)",
+                (false, true) => "  (record with --record)",
+                (false, false) => "",
+            };
+            println!("{:<16} {id}{}{state}", p.check, p.message);
+        }
+        println!(
+            "verified {} documents and {} blobs, {} problems",
+            v.documents,
+            v.blobs,
+            v.problems.len()
+        );
+    })?;
+    if left > 0 {
+        bail!("{left} problems found");
+    }
+    Ok(())
+}
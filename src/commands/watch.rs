@@ -0,0 +1,129 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+
+use crate::cli::WatchArgs;
+use crate::commands::add::{self, included, walk, Ingest};
+use crate::kb::{self, KnowledgeBase};
+use crate::output::AddStatus;
+use crate::parallel;
+
+pub fn run(args: WatchArgs) -> Result<()> {
+    let mut kb = KnowledgeBase::open_or_init(&kb::dir()?)?;
+    let ingest = Ingest::new(&kb, false, false, parallel::default_jobs())?;
+    let roots = args
+        .paths
+        .iter()
+        .map(|p| {
+            p.canonicalize()
+                .with_context(|| format!("cannot access {}", p.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Catch up with files changed and deleted while nobody was watching.
+    let mut changed = BTreeSet::new();
+    for root in &roots {
+        walk(root, kb.plugins()?, &mut changed)?;
+    }
+    for doc in kb.storage.all()? {
+        let Some(source) = doc.source.as_deref().map(Path::new) else {
+            continue;
+        };
+        if roots.iter().any(|r| source.starts_with(r)) && !source.exists() {
+            changed.insert(source.to_path_buf());
+        }
+    }
+    sync(&mut kb, &ingest, &roots, changed)?;
+
+    let (events, received) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events)?;
+    for root in &roots {
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .with_context(|| format!("cannot watch {}", root.display()))?;
+        tracing::info!("watching {}", root.display());
+    }
+    // Editors often write a file several times in a row, or replace it;
+    // changes are handled once they have settled for the debounce delay.
+    let debounce = Duration::from_millis(args.debounce);
+    let mut pending = BTreeSet::new();
+    loop {
+        let event = if pending.is_empty() {
+            received.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            received.recv_timeout(debounce)
+        };
+        match event {
+            // Reading files while ingesting them must not count as a change.
+            Ok(Ok(event)) => match event.kind {
+                EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => {}
+                _ => pending.extend(event.paths),
+            },
+            Ok(Err(e)) => tracing::warn!("{e}"),
+            Err(RecvTimeoutError::Timeout) => {
+                sync(&mut kb, &ingest, &roots, std::mem::take(&mut pending))?
+            }
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+/// Re-ingests changed files, and removes documents of deleted ones or of
+/// files below deleted directories. Failures are reported and skipped, so
+/// that one unreadable file does not stop the watch.
+fn sync(
+    kb: &mut KnowledgeBase,
+    ingest: &Ingest,
+    roots: &[PathBuf],
+    paths: BTreeSet<PathBuf>,
+) -> Result<()> {
+    let mut files = BTreeSet::new();
+    let mut deleted = Vec::new();
+    let plugins = kb.plugins()?;
+    for path in paths {
+        if !roots.iter().any(|root| included(root, &path, plugins)) {
+            continue;
+        }
+        // Like `walk`, symlinked directories are not followed.
+        if path.is_symlink() && path.is_dir() {
+            continue;
+        }
+        if path.is_dir() {
+            walk(&path, plugins, &mut files)?;
+        } else if path.is_file() {
+            files.insert(path);
+        } else {
+            deleted.push(path);
+        }
+    }
+    if files.is_empty() && deleted.is_empty() {
+        return Ok(());
+    }
+    let files: Vec<PathBuf> = files.into_iter().collect();
+    ingest.files(kb, &files, |_, added| {
+        match added {
+            Ok(added) if added.status == AddStatus::Unchanged => {}
+            Ok(added) => add::print(&added),
+            Err(e) => tracing::warn!("{e:#}"),
+        }
+        Ok(())
+    })?;
+    if !deleted.is_empty() {
+        for doc in kb.storage.all()? {
+            let Some(source) = doc.source.as_deref().map(Path::new) else {
+                continue;
+            };
+            if deleted.iter().any(|d| source.starts_with(d)) {
+                // The file is gone, so no tombstone is needed to keep it out.
+                kb.remove(&doc.id, true)?;
+                println!("removed {}  {}", doc.id, doc.title);
+            }
+        }
+    }
+    kb.commit()
+}
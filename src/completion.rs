@@ -0,0 +1,90 @@
+//! Shell completion. The scripts printed by `ozy completions` call back
+//! into `ozy` with `COMPLETE=<shell>` set, so that document ids and tags
+//! of the knowledge base in the current directory complete too.
+
+use std::io::Write;
+
+use anyhow::Result;
+use clap::CommandFactory;
+use clap_complete::env::{Bash, Elvish, EnvCompleter, Fish, Powershell, Zsh};
+use clap_complete::{CompleteEnv, CompletionCandidate, Shell};
+
+use crate::cli::Cli;
+use crate::kb::{self, KB_DIR};
+use crate::storage::{FsStorage, Storage};
+use crate::tags;
+
+/// Environment variable asking `ozy` for completions instead of running.
+const VAR: &str = "COMPLETE";
+
+/// Answers a completion request from a registered shell and exits, or
+/// returns if this is a normal run.
+pub fn complete() {
+    CompleteEnv::with_factory(Cli::command).var(VAR).complete();
+}
+
+/// Writes the script that registers completions with `shell`. Static
+/// scripts complete commands and flags without running `ozy`, but not
+/// ids or tags.
+pub fn write_script(shell: Shell, dynamic: bool, out: &mut dyn Write) -> Result<()> {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_string();
+    if !dynamic {
+        clap_complete::generate(shell, &mut cmd, name, out);
+        return Ok(());
+    }
+    let completer: &dyn EnvCompleter = match shell {
+        Shell::Bash => &Bash,
+        Shell::Zsh => &Zsh,
+        Shell::Fish => &Fish,
+        Shell::PowerShell => &Powershell,
+        Shell::Elvish => &Elvish,
+        other => anyhow::bail!("no dynamic completion for {other}; pass --static"),
+    };
+    completer.write_registration(VAR, &name, &name, &name, out)?;
+    Ok(())
+}
+
+/// Storage of the knowledge base in the current directory, if there is
+/// one. Completion must not create it.
+fn storage() -> Option<FsStorage> {
+    let root = kb::dir().ok()?.join(KB_DIR);
+    if !root.is_dir() {
+        return None;
+    }
+    FsStorage::open(&root).ok()
+}
+
+/// Document ids, with their titles as help. Errors only mean there is
+/// nothing to offer.
+pub fn document_ids() -> Vec<CompletionCandidate> {
+    let docs = storage().and_then(|s| s.all().ok()).unwrap_or_default();
+    docs.into_iter()
+        .map(|doc| CompletionCandidate::new(doc.id.0).help(Some(doc.title.into())))
+        .collect()
+}
+
+/// Tags in use, with how many documents carry them as help.
+pub fn tag_names() -> Vec<CompletionCandidate> {
+    let counts = storage()
+        .and_then(|s| tags::counts(&s).ok())
+        .unwrap_or_default();
+    counts
+        .into_iter()
+        .map(|(tag, n)| {
+            CompletionCandidate::new(tag).help(Some(
+                match n {
+                    1 => "1 document".to_string(),
+                    n => format!("{n} documents"),
+                }
+                .into(),
+            ))
+        })
+        .collect()
+}
+
+/// Graph nodes as `ozy graph` reads them: document ids and `#tag`.
+pub fn graph_nodes() -> Vec<CompletionCandidate> {
+    let tags = tag_names().into_iter().map(|c| c.add_prefix("#"));
+    document_ids().into_iter().chain(tags).collect()
+}
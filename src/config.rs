@@ -0,0 +1,452 @@
+//! Settings, merged from layers that override each other in this order:
+//! built-in defaults, the user's `~/.config/ozymandias/config.toml` (or
+//! below `$XDG_CONFIG_HOME`), the
+//! knowledge base's own `config.toml`, `OZY_<SECTION>__<KEY>` environment
+//! variables and `--set <KEY>=<VALUE>` flags.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::dirs;
+use crate::error::OzymandiasError;
+use crate::index::AnalysisConfig;
+use crate::rules::Rule;
+use crate::search::SearchMode;
+use crate::sync::SyncConfig;
+use crate::transform::PipelineConfig;
+use crate::web::WebConfig;
+
+pub const CONFIG_FILE: &str = "config.toml";
+/// Prefix of environment variables overriding settings; `__` separates the
+/// parts of the key, so `OZY_LLM__MODEL` sets `llm.model`.
+pub const ENV_PREFIX: &str = "OZY_";
+
+static FLAG_OVERRIDES: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Records the `--set` flags of this run, which take precedence over
+/// every file and environment variable.
+pub fn set_flag_overrides(overrides: Vec<(String, String)>) {
+    FLAG_OVERRIDES.set(overrides).ok();
+}
+
+/// `~/.config/ozymandias/config.toml`, settings shared by all knowledge
+/// bases of the user.
+pub fn user_path() -> Result<PathBuf> {
+    Ok(dirs::config_dir()?.join(CONFIG_FILE))
+}
+
+/// Settings of a knowledge base, merged from all layers.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub embedding: EmbeddingConfig,
+    pub llm: LlmConfig,
+    pub tagging: TaggingConfig,
+    pub classification: ClassificationConfig,
+    pub pipeline: PipelineConfig,
+    pub analysis: AnalysisConfig,
+    pub sync: SyncConfig,
+    pub trash: TrashConfig,
+    pub notes: NotesConfig,
+    pub journal: JournalConfig,
+    pub thumbnails: ThumbnailsConfig,
+    pub web: WebConfig,
+    /// Directories of knowledge bases selected by name with `--kb` or
+    /// `OZY_KB`. Only the user's config, the environment and flags can
+    /// register them.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub knowledge_bases: BTreeMap<String, PathBuf>,
+    /// Named queries re-run with `ozy search --saved <name>`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub saved_searches: BTreeMap<String, SavedSearch>,
+    /// Commands parsing files by extension, such as `org = "org2json"`.
+    /// They read the file on stdin and print JSON on stdout.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub parsers: BTreeMap<String, String>,
+}
+
+/// Automatic tagging of newly added documents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TaggingConfig {
+    /// Attach predicted tags whose confidence reaches this value on ingest.
+    /// Unset means tags are only ever suggested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_threshold: Option<f32>,
+    /// How many suggestions `ozy add --suggest-tags` prints per document.
+    pub suggestions: usize,
+}
+
+impl Default for TaggingConfig {
+    fn default() -> Self {
+        TaggingConfig {
+            auto_threshold: None,
+            suggestions: 5,
+        }
+    }
+}
+
+/// Rules that assign categories before any model is consulted.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClassificationConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<Rule>,
+}
+
+/// Where `ozy new` writes notes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotesConfig {
+    /// Directory of the notes, relative to the directory holding the
+    /// knowledge base.
+    pub dir: PathBuf,
+}
+
+impl Default for NotesConfig {
+    fn default() -> Self {
+        NotesConfig {
+            dir: PathBuf::from("notes"),
+        }
+    }
+}
+
+/// Where `ozy journal` keeps its daily notes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    /// Directory of the entries, relative to the directory holding the
+    /// knowledge base.
+    pub dir: PathBuf,
+    /// Template new entries start from, in `templates/`; without one they
+    /// start with the date as their heading.
+    pub template: String,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig {
+            dir: PathBuf::from("journal"),
+            template: "journal".to_string(),
+        }
+    }
+}
+
+/// Previews of image and PDF attachments.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailsConfig {
+    /// Largest width and height of a thumbnail, in pixels; 0 makes none.
+    pub max_dimension: u32,
+}
+
+impl Default for ThumbnailsConfig {
+    fn default() -> Self {
+        ThumbnailsConfig { max_dimension: 256 }
+    }
+}
+
+/// How long removed documents stay in the trash.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Empty documents from the trash this many days after their removal.
+    /// Unset keeps them until `ozy trash empty`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expire_after_days: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub query: String,
+    #[serde(default)]
+    pub mode: SearchMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// Feature-hashed vectors computed without any model.
+    #[default]
+    Hashed,
+    /// A downloaded model run in-process.
+    Local,
+    /// Any endpoint speaking the OpenAI embeddings API.
+    OpenAi,
+    /// A local Ollama server.
+    Ollama,
+    /// The embeddings endpoint of the `[llm]` provider.
+    Llm,
+}
+
+/// Which embedding provider to use and how to reach it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingConfig {
+    pub provider: ProviderKind,
+    /// Model name; a Hugging Face repository for `local`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Base URL of the API, for remote providers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Environment variable holding the API key, for remote providers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LlmKind {
+    /// No language model; features fall back to extractive methods.
+    #[default]
+    None,
+    /// Any endpoint speaking the OpenAI chat completions API.
+    OpenAi,
+    /// The Anthropic messages API.
+    Anthropic,
+    /// A local Ollama server.
+    Ollama,
+}
+
+/// Which language model generates text, and how to reach it.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LlmConfig {
+    pub provider: LlmKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Base URL of the API.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Environment variable holding the API key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+    /// Model computing embeddings, for `[embedding] provider = "llm"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+}
+
+impl Config {
+    /// The knowledge base's own config file.
+    pub fn path(kb_root: &Path) -> PathBuf {
+        kb_root.join(CONFIG_FILE)
+    }
+
+    /// Loads the settings of a knowledge base from all layers.
+    pub fn load(kb_root: &Path) -> Result<Self> {
+        Layers::load(Some(kb_root))?.config()
+    }
+
+    fn from_table(table: Table) -> Result<Self> {
+        let config: Config = Value::Table(table).try_into()?;
+        for rule in &config.classification.rules {
+            rule.validate()?;
+        }
+        Ok(config)
+    }
+}
+
+/// Where a setting comes from, lowest precedence first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    Default,
+    User,
+    KnowledgeBase,
+    Env,
+    Flag,
+}
+
+impl Origin {
+    pub fn name(self) -> &'static str {
+        match self {
+            Origin::Default => "default",
+            Origin::User => "user",
+            Origin::KnowledgeBase => "knowledge_base",
+            Origin::Env => "env",
+            Origin::Flag => "flag",
+        }
+    }
+}
+
+/// The layers settings are merged from, each checked on its own so that
+/// errors name the file or variable at fault.
+pub struct Layers(Vec<(Origin, Table)>);
+
+impl Layers {
+    /// Reads every layer; `kb_root` is `None` outside a knowledge base.
+    pub fn load(kb_root: Option<&Path>) -> Result<Self> {
+        let mut layers = vec![(Origin::Default, Table::try_from(Config::default())?)];
+        if let Ok(path) = user_path() {
+            layers.push((Origin::User, read_file(&path)?));
+        }
+        if let Some(root) = kb_root {
+            layers.push((Origin::KnowledgeBase, read_file(&Config::path(root))?));
+        }
+        let mut env = Table::new();
+        for (name, value) in std::env::vars() {
+            let Some(key) = name.strip_prefix(ENV_PREFIX).filter(|k| k.contains("__")) else {
+                continue;
+            };
+            let key = key.to_lowercase().replace("__", ".");
+            set(&mut env, &key, parse_value(&value)).with_context(|| {
+                OzymandiasError::ConfigInvalid(format!("invalid environment variable {name}"))
+            })?;
+        }
+        check(&env).context(OzymandiasError::ConfigInvalid(
+            "invalid OZY_ environment variables".into(),
+        ))?;
+        layers.push((Origin::Env, env));
+        let mut flags = Table::new();
+        for (key, value) in FLAG_OVERRIDES.get().into_iter().flatten() {
+            set(&mut flags, key, parse_value(value)).context(OzymandiasError::ConfigInvalid(
+                format!("invalid --set {key}"),
+            ))?;
+        }
+        check(&flags).context(OzymandiasError::ConfigInvalid("invalid --set flags".into()))?;
+        layers.push((Origin::Flag, flags));
+        Ok(Layers(layers))
+    }
+
+    /// All layers merged into one table.
+    pub fn merged(&self) -> Table {
+        let mut merged = Table::new();
+        for (_, table) in &self.0 {
+            merge(&mut merged, table.clone());
+        }
+        merged
+    }
+
+    pub fn config(&self) -> Result<Config> {
+        Config::from_table(self.merged())
+            .context(OzymandiasError::ConfigInvalid("invalid settings".into()))
+    }
+
+    /// Every setting in effect by dotted key, with the layer it comes from.
+    pub fn settings(&self) -> BTreeMap<String, (Value, Origin)> {
+        let mut settings = BTreeMap::new();
+        for (origin, table) in &self.0 {
+            let mut leaves = BTreeMap::new();
+            flatten("", table, &mut leaves);
+            for (key, value) in leaves {
+                settings.insert(key, (value, *origin));
+            }
+        }
+        settings
+    }
+}
+
+/// Reads a config file on its own, as an empty table if it is missing.
+pub fn read_file(path: &Path) -> Result<Table> {
+    let raw = match std::fs::read_to_string(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Table::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let table: Table = toml::from_str(&raw).with_context(|| invalid(path))?;
+    check(&table).with_context(|| invalid(path))?;
+    Ok(table)
+}
+
+/// Changes a config file, creating it if needed. Only what `edit` touches
+/// is written, so settings of lower layers stay visible.
+pub fn edit_file(path: &Path, edit: impl FnOnce(&mut Table) -> Result<()>) -> Result<()> {
+    let mut table = read_file(path)?;
+    edit(&mut table)?;
+    check(&table).with_context(|| invalid(path))?;
+    if crate::kb::dry_run() {
+        tracing::info!("dry run: would write {}", path.display());
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, toml::to_string_pretty(&table)?)
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn invalid(path: &Path) -> OzymandiasError {
+    OzymandiasError::ConfigInvalid(format!("invalid {}", path.display()))
+}
+
+/// Whether one layer on its own describes valid settings.
+fn check(table: &Table) -> Result<()> {
+    let mut merged = Table::try_from(Config::default())?;
+    merge(&mut merged, table.clone());
+    Config::from_table(merged).map(drop)
+}
+
+/// Sets a dotted key such as `llm.model`, creating the tables above it.
+pub fn set(table: &mut Table, key: &str, value: Value) -> Result<()> {
+    let path: Vec<&str> = key.split('.').collect();
+    insert(table, &path, value).with_context(|| format!("cannot set {key}"))
+}
+
+/// Sets the value at `path`, whose parts may themselves contain dots.
+pub fn insert(mut table: &mut Table, path: &[&str], value: Value) -> Result<()> {
+    let (name, parents) = path.split_last().context("empty key")?;
+    for part in parents {
+        let entry = table
+            .entry(*part)
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = entry
+            .as_table_mut()
+            .with_context(|| format!("{part} is not a table"))?;
+    }
+    table.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// The value at a dotted key, if set.
+pub fn get<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let mut parts = key.split('.');
+    let mut value = table.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_table()?.get(part)?;
+    }
+    Some(value)
+}
+
+/// Reads a value as written on the command line or in the environment:
+/// TOML such as `42`, `true` or `["a", "b"]`, or else a bare string.
+pub fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {raw}"))
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+/// Overlays `top` on `base`, merging tables key by key.
+fn merge(base: &mut Table, top: Table) {
+    for (key, value) in top {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(top)) => merge(base, top),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+fn flatten(prefix: &str, table: &Table, out: &mut BTreeMap<String, Value>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            Value::Table(table) => flatten(&key, table, out),
+            value => {
+                out.insert(key, value.clone());
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! The dates a document carries, for the `created:`, `published:`,
+//! `mentions:` and `date:` filters and for `ozy timeline`:
+//!
+//! - when it was created: `created` in its metadata or frontmatter, or
+//!   `date` in its frontmatter, or when an attached photo was taken (see
+//!   [`crate::media`]), else when it was added,
+//! - when it was published: `published` or, as importers of references
+//!   record it, `date` or `year` in its metadata,
+//! - the days, months and years its text mentions.
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use crate::entities::dates_in;
+use crate::export::split_frontmatter;
+use crate::media::TAKEN_KEY;
+use crate::types::{Document, DocumentKind};
+
+const CREATED_KEYS: [&str; 4] = ["created", "created_time", "created_at", TAKEN_KEY];
+const PUBLISHED_KEYS: [&str; 6] = [
+    "published",
+    "published_at",
+    "date_published",
+    "publication_date",
+    "date",
+    "year",
+];
+
+/// A date as precise as it was given: `1815`, `1815-12` or `1815-12-10`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Dated {
+    /// The first instant of the period.
+    pub start: DateTime<Utc>,
+    pub label: String,
+}
+
+impl Dated {
+    fn day(at: DateTime<Utc>) -> Self {
+        Dated {
+            start: at,
+            label: at.format("%Y-%m-%d").to_string(),
+        }
+    }
+}
+
+/// Reads a date written as RFC 3339, or as `YYYY-MM-DD`, `YYYY-MM` or
+/// `YYYY` followed by anything else.
+pub fn parse(value: &str) -> Option<Dated> {
+    let value = value.trim().trim_matches(['"', '\'']);
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(Dated::day(at.with_timezone(&Utc)));
+    }
+    let digits = |s: &&str| s.chars().all(|c| c.is_ascii_digit());
+    let year = value.get(..4).filter(digits)?;
+    let rest = &value[4..];
+    if rest.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let month = rest
+        .strip_prefix('-')
+        .and_then(|r| r.get(..2))
+        .filter(digits);
+    let day = month
+        .and(rest.get(3..))
+        .and_then(|r| r.strip_prefix('-'))
+        .and_then(|r| r.get(..2))
+        .filter(digits);
+    let label = match (month, day) {
+        (Some(m), Some(d)) => format!("{year}-{m}-{d}"),
+        (Some(m), None) => format!("{year}-{m}"),
+        _ => year.to_string(),
+    };
+    let date = NaiveDate::from_ymd_opt(
+        year.parse().ok()?,
+        month.map_or(Some(1), |m| m.parse().ok())?,
+        day.map_or(Some(1), |d| d.parse().ok())?,
+    )?;
+    Some(Dated {
+        start: Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?),
+        label,
+    })
+}
+
+/// The value of `key` in the frontmatter of a Markdown document.
+fn frontmatter(doc: &Document, key: &str) -> Option<String> {
+    if doc.kind != DocumentKind::Markdown {
+        return None;
+    }
+    let yaml = split_frontmatter(&doc.content).0?;
+    let mapping: serde_yaml::Mapping = serde_yaml::from_str(yaml).ok()?;
+    match mapping.get(key)? {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+pub fn created(doc: &Document) -> Dated {
+    CREATED_KEYS
+        .iter()
+        .find_map(|key| parse(doc.metadata.get(*key)?))
+        .or_else(|| {
+            ["created", "date"]
+                .iter()
+                .find_map(|key| parse(&frontmatter(doc, key)?))
+        })
+        .unwrap_or_else(|| Dated::day(doc.added))
+}
+
+pub fn published(doc: &Document) -> Option<Dated> {
+    PUBLISHED_KEYS
+        .iter()
+        .find_map(|key| parse(doc.metadata.get(*key)?))
+        .or_else(|| parse(&frontmatter(doc, "published")?))
+}
+
+/// The dates mentioned in a document's text, each once, in order.
+pub fn mentioned(doc: &Document) -> Vec<Dated> {
+    dates_in(&doc.content)
+        .iter()
+        .filter_map(|iso| parse(iso))
+        .collect()
+}
@@ -0,0 +1,31 @@
+//! Per-user directories, following the XDG base directory specification:
+//! `$XDG_CONFIG_HOME` and `$XDG_CACHE_HOME` when set to absolute paths,
+//! `~/.config` and `~/.cache` otherwise.
+
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+const APP: &str = "ozymandias";
+
+/// Where the user's config lives.
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(base("XDG_CONFIG_HOME", ".config")?.join(APP))
+}
+
+/// Where downloaded models and other data that can be fetched again live.
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(base("XDG_CACHE_HOME", ".cache")?.join(APP))
+}
+
+fn base(var: &str, fallback: &str) -> Result<PathBuf> {
+    // The specification says relative paths are invalid and to be ignored.
+    if let Some(dir) = std::env::var_os(var).map(PathBuf::from) {
+        if dir.is_absolute() {
+            return Ok(dir);
+        }
+    }
+    let home =
+        std::env::var_os("HOME").with_context(|| format!("neither {var} nor HOME is set"))?;
+    Ok(PathBuf::from(home).join(fallback))
+}
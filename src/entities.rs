@@ -0,0 +1,355 @@
+//! Named entities, people, organizations, places and dates, found in
+//! document text by capitalization and context rather than by a model.
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::transform::chunk::sentences;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntityKind {
+    Person,
+    Organization,
+    Place,
+    Date,
+}
+
+impl EntityKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntityKind::Person => "person",
+            EntityKind::Organization => "organization",
+            EntityKind::Place => "place",
+            EntityKind::Date => "date",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "person" | "people" => EntityKind::Person,
+            "organization" | "organisation" | "org" => EntityKind::Organization,
+            "place" | "location" => EntityKind::Place,
+            "date" => EntityKind::Date,
+            other => {
+                bail!(
+                    "unknown entity kind {other:?} (expected person, organization, place or date)"
+                )
+            }
+        })
+    }
+}
+
+/// A mention of something named, normalized so that repeated mentions
+/// compare equal. Dates are ISO 8601 (`1815-12-10` or `1815-12`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub name: String,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind.name(), self.name)
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+const HONORIFICS: [&str; 14] = [
+    "mr",
+    "mrs",
+    "ms",
+    "miss",
+    "dr",
+    "prof",
+    "professor",
+    "sir",
+    "lady",
+    "lord",
+    "king",
+    "queen",
+    "president",
+    "saint",
+];
+/// Lowercase words allowed inside a name, as in "Bank of England".
+const CONNECTORS: [&str; 8] = ["of", "de", "del", "van", "von", "der", "la", "du"];
+const ORGANIZATION_WORDS: [&str; 20] = [
+    "inc",
+    "ltd",
+    "llc",
+    "gmbh",
+    "corp",
+    "corporation",
+    "company",
+    "university",
+    "college",
+    "institute",
+    "society",
+    "association",
+    "foundation",
+    "bank",
+    "museum",
+    "council",
+    "ministry",
+    "party",
+    "group",
+    "agency",
+];
+const PLACE_WORDS: [&str; 18] = [
+    "empire",
+    "kingdom",
+    "republic",
+    "river",
+    "sea",
+    "ocean",
+    "lake",
+    "mountains",
+    "mount",
+    "island",
+    "islands",
+    "city",
+    "street",
+    "valley",
+    "county",
+    "province",
+    "bay",
+    "gulf",
+];
+/// Words after which a capitalized name is most likely a place.
+const PLACE_PREPOSITIONS: [&str; 9] = [
+    "in", "at", "from", "near", "to", "across", "through", "into", "around",
+];
+/// Capitalized only because they start a sentence.
+const SENTENCE_STARTERS: [&str; 24] = [
+    "the", "a", "an", "this", "that", "these", "those", "it", "its", "in", "on", "at", "after",
+    "before", "when", "while", "but", "and", "or", "if", "as", "we", "he", "she",
+];
+
+/// Finds entities in `text`, each once, in order of first mention.
+pub fn extract(text: &str) -> Vec<Entity> {
+    let mut out: Vec<Entity> = Vec::new();
+    for (start, end) in sentences(text) {
+        let words: Vec<Word> = text[start..end].split_whitespace().map(Word::new).collect();
+        for entity in dates(&words).into_iter().chain(names(&words)) {
+            if !out.contains(&entity) {
+                out.push(entity);
+            }
+        }
+    }
+    out
+}
+
+/// The dates mentioned in `text`, as ISO 8601, each once, in order of
+/// first mention.
+pub fn dates_in(text: &str) -> Vec<String> {
+    let mut out: Vec<String> = Vec::new();
+    for (start, end) in sentences(text) {
+        let words: Vec<Word> = text[start..end].split_whitespace().map(Word::new).collect();
+        for date in dates(&words) {
+            if !out.contains(&date.name) {
+                out.push(date.name);
+            }
+        }
+    }
+    out
+}
+
+/// A whitespace-separated word with surrounding punctuation removed.
+struct Word<'a> {
+    text: &'a str,
+    /// Whether punctuation such as a comma ends a phrase after the word.
+    breaks: bool,
+}
+
+impl<'a> Word<'a> {
+    fn new(raw: &'a str) -> Self {
+        let end = raw.trim_end_matches(|c: char| !c.is_alphanumeric());
+        Word {
+            text: end.trim_start_matches(|c: char| !c.is_alphanumeric()),
+            breaks: end.len() < raw.len(),
+        }
+    }
+
+    fn lower(&self) -> String {
+        self.text.to_lowercase()
+    }
+
+    fn is_capitalized(&self) -> bool {
+        let mut chars = self.text.chars();
+        chars.next().is_some_and(char::is_uppercase)
+            && self
+                .text
+                .chars()
+                .all(|c| c.is_alphabetic() || "'-.".contains(c))
+    }
+
+    fn month(&self) -> Option<usize> {
+        let lower = self.lower();
+        MONTHS
+            .iter()
+            .position(|m| *m == lower || (lower.len() == 3 && m.starts_with(&lower)))
+            .map(|i| i + 1)
+    }
+}
+
+fn number(word: &Word, range: std::ops::RangeInclusive<u32>) -> Option<u32> {
+    // Ordinals such as "5th" count too.
+    let digits = word
+        .text
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    digits.parse().ok().filter(|n| range.contains(n))
+}
+
+fn dates(words: &[Word]) -> Vec<Entity> {
+    let mut out = Vec::new();
+    let year = |i: usize| words.get(i).and_then(|w| number(w, 1000..=2999));
+    let month = |i: usize| {
+        words
+            .get(i)
+            .filter(|w| w.is_capitalized())
+            .and_then(Word::month)
+    };
+    let mut i = 0;
+    while i < words.len() {
+        let (name, len) = if is_iso_date(words[i].text) {
+            (words[i].text.to_string(), 1)
+        } else if let (Some(day), Some(m), Some(y)) =
+            (number(&words[i], 1..=31), month(i + 1), year(i + 2))
+        {
+            // "10 December 1815"
+            (format!("{y}-{m:02}-{day:02}"), 3)
+        } else if let Some(m) = month(i) {
+            // "December 10, 1815" or "December 1815"
+            let day = words.get(i + 1).and_then(|w| number(w, 1..=31));
+            match (day, year(i + 2), year(i + 1)) {
+                (Some(day), Some(y), _) => (format!("{y}-{m:02}-{day:02}"), 3),
+                (_, _, Some(y)) => (format!("{y}-{m:02}"), 2),
+                _ => {
+                    i += 1;
+                    continue;
+                }
+            }
+        } else {
+            i += 1;
+            continue;
+        };
+        out.push(Entity {
+            kind: EntityKind::Date,
+            name,
+        });
+        i += len;
+    }
+    out
+}
+
+fn is_iso_date(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('-').collect();
+    matches!(parts.as_slice(), [y, m, d] if y.len() == 4 && m.len() == 2 && d.len() == 2
+        && parts.iter().all(|p| p.chars().all(|c| c.is_ascii_digit())))
+}
+
+fn names(words: &[Word]) -> Vec<Entity> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        if !is_name_word(&words[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i + 1;
+        while end < words.len() && !words[end - 1].breaks {
+            if is_name_word(&words[end]) {
+                end += 1;
+            } else if CONNECTORS.contains(&words[end].text)
+                && words.get(end + 1).is_some_and(is_name_word)
+                && !words[end].breaks
+            {
+                end += 2;
+            } else {
+                break;
+            }
+        }
+        i = end;
+        if let Some(entity) = classify(words, start, end) {
+            out.push(entity);
+        }
+    }
+    out
+}
+
+fn is_name_word(word: &Word) -> bool {
+    word.is_capitalized()
+        && word.text.chars().count() > 1
+        && word.month().is_none()
+        && !WEEKDAYS.contains(&word.lower().as_str())
+}
+
+fn classify(words: &[Word], mut start: usize, end: usize) -> Option<Entity> {
+    if start == 0 && SENTENCE_STARTERS.contains(&words[0].lower().as_str()) {
+        start += 1;
+    }
+    let honorific = HONORIFICS.contains(&words[start].lower().trim_end_matches('.'));
+    if honorific {
+        start += 1;
+    }
+    if start >= end {
+        return None;
+    }
+    let parts: Vec<&str> = words[start..end].iter().map(|w| w.text).collect();
+    let first = parts[0].to_lowercase();
+    let last = parts[parts.len() - 1].to_lowercase();
+    let has_connector = parts.iter().any(|p| CONNECTORS.contains(p));
+    let previous = start.checked_sub(1).map(|p| words[p].lower());
+    let after_preposition = previous
+        .as_deref()
+        .is_some_and(|p| PLACE_PREPOSITIONS.contains(&p));
+    // "the Analytical Engine" names a thing, not a person.
+    let after_article = matches!(previous.as_deref(), Some("the" | "a" | "an"));
+
+    let kind = if ORGANIZATION_WORDS.contains(&last.as_str())
+        || (has_connector && ORGANIZATION_WORDS.contains(&first.as_str()))
+    {
+        EntityKind::Organization
+    } else if PLACE_WORDS.contains(&last.as_str())
+        || (has_connector && PLACE_WORDS.contains(&first.as_str()))
+    {
+        EntityKind::Place
+    } else if honorific {
+        EntityKind::Person
+    } else if after_preposition {
+        EntityKind::Place
+    } else if (2..=3).contains(&parts.len()) && !has_connector && !after_article {
+        EntityKind::Person
+    } else {
+        return None;
+    };
+    Some(Entity {
+        kind,
+        name: parts.join(" "),
+    })
+}
@@ -0,0 +1,73 @@
+//! Kinds of failure that scripts wrapping `ozy` can tell apart by its exit
+//! status. Errors are still passed around as `anyhow::Error`; an
+//! [`OzymandiasError`] anywhere in the context chain decides the status.
+
+use std::fmt;
+
+use crate::query::SyntaxError;
+
+/// A failure of a known kind. Each maps to an exit status:
+///
+/// | status | kind |
+/// |-------:|------|
+/// | 0 | success |
+/// | 1 | any other error, such as an unreadable file |
+/// | 2 | invalid command line |
+/// | 3 | [`NotFound`](Self::NotFound) |
+/// | 4 | [`ParseFailed`](Self::ParseFailed) |
+/// | 5 | [`StorageCorrupt`](Self::StorageCorrupt) |
+/// | 6 | [`ConfigInvalid`](Self::ConfigInvalid) |
+/// | 7 | [`NetworkError`](Self::NetworkError) |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OzymandiasError {
+    /// No knowledge base, document or other named thing to work on.
+    NotFound(String),
+    /// A query or an input that could not be understood.
+    ParseFailed(String),
+    /// Files in the knowledge base that cannot be read back.
+    StorageCorrupt(String),
+    /// A config file or setting with a value ozy does not accept.
+    ConfigInvalid(String),
+    /// A server that could not be reached or gave no usable answer.
+    NetworkError(String),
+}
+
+impl OzymandiasError {
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            OzymandiasError::NotFound(_) => 3,
+            OzymandiasError::ParseFailed(_) => 4,
+            OzymandiasError::StorageCorrupt(_) => 5,
+            OzymandiasError::ConfigInvalid(_) => 6,
+            OzymandiasError::NetworkError(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for OzymandiasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OzymandiasError::NotFound(message)
+            | OzymandiasError::ParseFailed(message)
+            | OzymandiasError::StorageCorrupt(message)
+            | OzymandiasError::ConfigInvalid(message)
+            | OzymandiasError::NetworkError(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for OzymandiasError {}
+
+/// The exit status for `error`. Errors of other crates that are
+/// unambiguous, such as failed HTTP requests, count as their kind too.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    if let Some(e) = error.downcast_ref::<OzymandiasError>() {
+        e.exit_code()
+    } else if error.downcast_ref::<SyntaxError>().is_some() {
+        OzymandiasError::ParseFailed(String::new()).exit_code()
+    } else if error.downcast_ref::<reqwest::Error>().is_some() {
+        OzymandiasError::NetworkError(String::new()).exit_code()
+    } else {
+        1
+    }
+}
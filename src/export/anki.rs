@@ -0,0 +1,458 @@
+//! Flashcard decks for Anki, as an `.apkg` package or as a tab-separated
+//! file for Anki's text import.
+//!
+//! Cards come from `Q:` / `A:` pairs and from headings phrased as
+//! questions. A document with neither becomes one card asking for its
+//! title, unless a language model drafts cloze deletions for it instead.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use super::split_frontmatter;
+use crate::ml::prompts::{Prompts, CLOZE};
+use crate::ml::summarize::SUMMARY_KEY;
+use crate::ml::LlmProvider;
+use crate::parser::strip_tags;
+use crate::types::{Document, DocumentKind};
+
+/// Longest excerpt of a document sent to a language model.
+const PROMPT_CHARS: usize = 12_000;
+const BASIC_MODEL: i64 = 1_718_000_000_001;
+const CLOZE_MODEL: i64 = 1_718_000_000_002;
+/// Separates the fields of a note in the collection.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A note of Anki's stock note types, with its fields as HTML.
+#[derive(Debug, Clone)]
+pub struct Note {
+    pub kind: NoteKind,
+    /// Front and back, or text and extra for a cloze.
+    pub fields: [String; 2],
+    pub tags: Vec<String>,
+    /// Stays the same between exports, so Anki updates the note rather
+    /// than adding it again.
+    pub guid: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteKind {
+    Basic,
+    Cloze,
+}
+
+impl NoteKind {
+    /// The name of the stock note type in Anki.
+    fn name(self) -> &'static str {
+        match self {
+            NoteKind::Basic => "Basic",
+            NoteKind::Cloze => "Cloze",
+        }
+    }
+}
+
+impl Note {
+    fn new(doc: &Document, kind: NoteKind, fields: [String; 2]) -> Self {
+        let mut hash = Sha256::new();
+        hash.update(doc.id.to_string());
+        hash.update([0]);
+        hash.update(&fields[0]);
+        let guid = hash
+            .finalize()
+            .iter()
+            .take(8)
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let tags = doc
+            .tags
+            .iter()
+            .map(|t| t.replace('/', "::").replace(char::is_whitespace, "_"))
+            .collect();
+        Note {
+            kind,
+            fields,
+            tags,
+            guid,
+        }
+    }
+
+    /// How many cards Anki makes of the note: one per cloze number.
+    fn cards(&self) -> Vec<u32> {
+        match self.kind {
+            NoteKind::Basic => vec![0],
+            NoteKind::Cloze => {
+                let mut numbers: Vec<u32> = self.fields[0]
+                    .split("{{c")
+                    .skip(1)
+                    .filter_map(|s| s.split_once("::")?.0.parse().ok())
+                    .filter(|n| *n > 0)
+                    .map(|n: u32| n - 1)
+                    .collect();
+                numbers.sort_unstable();
+                numbers.dedup();
+                numbers
+            }
+        }
+    }
+}
+
+/// The question and answer pairs written into `doc`, as plain text.
+pub fn flashcards(doc: &Document) -> Vec<(String, String)> {
+    let body = body(doc);
+    let mut pairs = questions_and_answers(body);
+    if doc.kind == DocumentKind::Markdown {
+        pairs.extend(question_headings(body));
+    }
+    pairs
+}
+
+/// The content of `doc` without its frontmatter.
+fn body(doc: &Document) -> &str {
+    match doc.kind {
+        DocumentKind::Markdown => split_frontmatter(&doc.content).1,
+        DocumentKind::Text | DocumentKind::Html => &doc.content,
+    }
+}
+
+/// A card asking for the title of `doc`, answered by its summary or else
+/// its text.
+pub fn title_card(doc: &Document) -> (String, String) {
+    let back = match doc.metadata.get(SUMMARY_KEY) {
+        Some(summary) => summary.clone(),
+        None => body(doc)
+            .lines()
+            .skip_while(|l| l.trim().is_empty() || l.trim() == format!("# {}", doc.title))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    (doc.title.clone(), back.trim().to_string())
+}
+
+/// The question and answer cards written into `doc`, or a card asking for
+/// its title when it has none and `whole` is set.
+pub fn notes(doc: &Document, whole: bool) -> Vec<Note> {
+    let mut pairs = flashcards(doc);
+    if pairs.is_empty() && whole {
+        pairs.push(title_card(doc));
+    }
+    pairs
+        .into_iter()
+        .map(|(front, back)| Note::new(doc, NoteKind::Basic, [html(&front), html(&back)]))
+        .collect()
+}
+
+/// Cloze cards for `doc` drafted by a language model.
+pub async fn cloze(doc: &Document, llm: &dyn LlmProvider, prompts: &Prompts) -> Result<Vec<Note>> {
+    let excerpt: String = doc.content.chars().take(PROMPT_CHARS).collect();
+    let prompt = prompts.render(&CLOZE, &[("title", &doc.title), ("content", &excerpt)])?;
+    let reply = llm.complete(&prompt).await?;
+    Ok(reply
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches(['-', '*', ' ']);
+            let text = deletions(&html(line))?;
+            Some(Note::new(doc, NoteKind::Cloze, [text, html(&doc.title)]))
+        })
+        .collect())
+}
+
+/// `[hidden]` words as numbered cloze deletions, or nothing if the line
+/// hides none.
+fn deletions(line: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = line;
+    let mut number = 0;
+    while let Some(start) = rest.find('[') {
+        let Some(len) = rest[start..].find(']') else {
+            break;
+        };
+        number += 1;
+        out.push_str(&rest[..start]);
+        out.push_str(&format!(
+            "{{{{c{number}::{}}}}}",
+            &rest[start + 1..start + len]
+        ));
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    (number > 0).then_some(out)
+}
+
+/// Pairs written as a `Q:` line followed by an `A:` line. Either may go on
+/// over several lines; a blank line ends the answer.
+fn questions_and_answers(body: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut question: Option<Vec<&str>> = None;
+    let mut answer: Option<Vec<&str>> = None;
+    let mut finish = |question: &mut Option<Vec<&str>>, answer: &mut Option<Vec<&str>>| {
+        if let (Some(q), Some(a)) = (question.take(), answer.take()) {
+            pairs.push((q.join("\n"), a.join("\n")));
+        }
+    };
+    for line in body.lines() {
+        let trimmed = line.trim().trim_start_matches("- ");
+        if let Some(q) = marked(trimmed, "Q") {
+            finish(&mut question, &mut answer);
+            question = Some(vec![q]);
+        } else if let (Some(a), Some(_)) = (marked(trimmed, "A"), &question) {
+            answer = Some(vec![a]);
+        } else if trimmed.is_empty() {
+            if answer.is_some() {
+                finish(&mut question, &mut answer);
+            }
+        } else if let Some(a) = &mut answer {
+            a.push(trimmed);
+        } else if let Some(q) = &mut question {
+            q.push(trimmed);
+        }
+    }
+    finish(&mut question, &mut answer);
+    pairs
+}
+
+/// The text after a `Q:` or `A:` marker, also in bold.
+fn marked<'a>(line: &'a str, marker: &str) -> Option<&'a str> {
+    [format!("{marker}:"), format!("**{marker}:**")]
+        .iter()
+        .find_map(|m| line.strip_prefix(m.as_str()))
+        .map(str::trim)
+}
+
+/// Headings ending in a question mark, answered by the section below them.
+fn question_headings(body: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut open: Option<(usize, &str, Vec<&str>)> = None;
+    let mut fenced = false;
+    for line in body.lines() {
+        if line.trim_start().starts_with("```") {
+            fenced = !fenced;
+        }
+        let level = line.chars().take_while(|c| *c == '#').count();
+        let heading = (!fenced && level > 0)
+            .then(|| line[level..].strip_prefix(' '))
+            .flatten();
+        if let Some(heading) = heading {
+            if open.as_ref().is_some_and(|(l, _, _)| level <= *l) {
+                let (_, question, answer) = open.take().unwrap();
+                pairs.push((question.to_string(), answer.join("\n")));
+            }
+            if open.is_none() && heading.trim_end().ends_with('?') {
+                open = Some((level, heading.trim(), Vec::new()));
+                continue;
+            }
+        }
+        if let Some((_, _, answer)) = &mut open {
+            answer.push(line);
+        }
+    }
+    if let Some((_, question, answer)) = open {
+        pairs.push((question.to_string(), answer.join("\n")));
+    }
+    pairs
+        .into_iter()
+        .map(|(q, a)| (q, a.trim().to_string()))
+        .filter(|(_, a)| !a.is_empty())
+        .collect()
+}
+
+/// Plain text as an HTML field.
+fn html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\t', " ")
+        .replace('\n', "<br>")
+}
+
+/// Writes `notes` into the deck `deck` at `path`: an Anki package if it
+/// ends in `.apkg`, else a tab-separated file. Returns the number of cards.
+pub fn write(notes: &[Note], deck: &str, path: &Path) -> Result<usize> {
+    let cards = notes.iter().map(|n| n.cards().len()).sum();
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("apkg") => write_package(notes, deck, path)?,
+        _ => write_tsv(notes, deck, path)?,
+    }
+    Ok(cards)
+}
+
+fn write_tsv(notes: &[Note], deck: &str, path: &Path) -> Result<()> {
+    let mut out = format!(
+        "#separator:tab\n#html:true\n#guid column:1\n#notetype column:2\n#deck:{deck}\n#tags column:5\n"
+    );
+    for note in notes {
+        out.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            note.guid,
+            note.kind.name(),
+            note.fields[0],
+            note.fields[1],
+            note.tags.join(" ")
+        ));
+    }
+    std::fs::write(path, out).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// An `.apkg` is a zip of a SQLite collection holding the notes, their
+/// cards, the two note types and the deck, plus an empty media list.
+fn write_package(notes: &[Note], deck: &str, path: &Path) -> Result<()> {
+    let collection = path.with_extension("anki2.tmp");
+    let _ = std::fs::remove_file(&collection);
+    let written = write_collection(notes, deck, &collection).and_then(|()| {
+        let bytes = std::fs::read(&collection)
+            .with_context(|| format!("failed to read {}", collection.display()))?;
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let mut zip = zip::ZipWriter::new(file);
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("collection.anki2", options)?;
+        zip.write_all(&bytes)?;
+        zip.start_file("media", options)?;
+        zip.write_all(b"{}")?;
+        zip.finish()?;
+        Ok(())
+    });
+    let _ = std::fs::remove_file(&collection);
+    written.with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn write_collection(notes: &[Note], deck: &str, path: &Path) -> Result<()> {
+    let now = Utc::now();
+    let (secs, millis) = (now.timestamp(), now.timestamp_millis());
+    // Named the same way each time, so a new export updates the deck.
+    let deck_id = i64::from_be_bytes(Sha256::digest(deck)[..8].try_into().unwrap()) >> 12;
+    let deck_id = deck_id.abs();
+
+    let db = Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+    let field = |name: &str, ord: u32| {
+        json!({"name": name, "ord": ord, "sticky": false, "rtl": false,
+               "font": "Arial", "size": 20, "media": []})
+    };
+    let model = |id: i64, kind: NoteKind, names: [&str; 2], qfmt: &str, afmt: &str| {
+        json!({
+            "id": id, "name": format!("Ozymandias {}", kind.name()), "type": kind as u8,
+            "mod": secs, "usn": -1, "sortf": 0, "did": deck_id,
+            "tmpls": [{"name": "Card 1", "ord": 0, "qfmt": qfmt, "afmt": afmt,
+                       "did": null, "bqfmt": "", "bafmt": ""}],
+            "flds": [field(names[0], 0), field(names[1], 1)],
+            "css": CSS, "latexPre": LATEX_PRE, "latexPost": "\\end{document}",
+            "latexsvg": false, "tags": [], "vers": [], "req": [[0, "any", [0]]],
+        })
+    };
+    let models = json!({
+        BASIC_MODEL.to_string(): model(BASIC_MODEL, NoteKind::Basic, ["Front", "Back"],
+            "{{Front}}", "{{FrontSide}}<hr id=answer>{{Back}}"),
+        CLOZE_MODEL.to_string(): model(CLOZE_MODEL, NoteKind::Cloze, ["Text", "Back Extra"],
+            "{{cloze:Text}}", "{{cloze:Text}}<br>{{Back Extra}}"),
+    });
+    let deck_json = |id: i64, name: &str| {
+        json!({"id": id, "name": name, "desc": "", "mod": secs, "usn": -1,
+               "collapsed": false, "browserCollapsed": false, "newToday": [0, 0],
+               "revToday": [0, 0], "lrnToday": [0, 0], "timeToday": [0, 0],
+               "dyn": 0, "conf": 1, "extendNew": 10, "extendRev": 50})
+    };
+    let decks =
+        json!({"1": deck_json(1, "Default"), deck_id.to_string(): deck_json(deck_id, deck)});
+    let conf = json!({"activeDecks": [1], "curDeck": 1, "newSpread": 0, "collapseTime": 1200,
+                      "timeLim": 0, "estTimes": true, "dueCounts": true, "curModel": null,
+                      "nextPos": 1, "sortType": "noteFld", "sortBackwards": false,
+                      "addToCur": true});
+    db.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        params![
+            secs,
+            millis,
+            conf.to_string(),
+            models.to_string(),
+            decks.to_string(),
+            DECK_CONFIG
+        ],
+    )?;
+
+    let mut card_id = millis;
+    for (i, note) in notes.iter().enumerate() {
+        let note_id = millis + i as i64;
+        let sort_field = strip_tags(&note.fields[0].replace("<br>", " "));
+        let checksum = Sha1::digest(&sort_field);
+        let checksum = i64::from(u32::from_be_bytes(checksum[..4].try_into().unwrap()));
+        let model = match note.kind {
+            NoteKind::Basic => BASIC_MODEL,
+            NoteKind::Cloze => CLOZE_MODEL,
+        };
+        let tags = match note.tags.is_empty() {
+            true => String::new(),
+            false => format!(" {} ", note.tags.join(" ")),
+        };
+        db.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, ?8, 0, '')",
+            params![
+                note_id,
+                note.guid,
+                model,
+                secs,
+                tags,
+                note.fields.join(&FIELD_SEPARATOR.to_string()),
+                sort_field,
+                checksum
+            ],
+        )?;
+        for ord in note.cards() {
+            db.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, ?5, -1, 0, 0, ?6, 0, 0, 0, 0, 0, 0, 0, 0, '')",
+                params![card_id, note_id, deck_id, ord, secs, i as i64 + 1],
+            )?;
+            card_id += 1;
+        }
+    }
+    Ok(())
+}
+
+/// The tables of a version 11 Anki collection.
+const SCHEMA: &str = "
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null,
+    scm integer not null, ver integer not null, dty integer not null, usn integer not null,
+    ls integer not null, conf text not null, models text not null, decks text not null,
+    dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null,
+    mod integer not null, usn integer not null, tags text not null, flds text not null,
+    sfld integer not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null,
+    ord integer not null, mod integer not null, usn integer not null, type integer not null,
+    queue integer not null, due integer not null, ivl integer not null, factor integer not null,
+    reps integer not null, lapses integer not null, left integer not null, odue integer not null,
+    odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null,
+    ease integer not null, ivl integer not null, lastIvl integer not null,
+    factor integer not null, time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+CREATE INDEX ix_notes_usn ON notes (usn);
+CREATE INDEX ix_cards_usn ON cards (usn);
+CREATE INDEX ix_revlog_usn ON revlog (usn);
+CREATE INDEX ix_cards_nid ON cards (nid);
+CREATE INDEX ix_cards_sched ON cards (did, queue, due);
+CREATE INDEX ix_revlog_cid ON revlog (cid);
+CREATE INDEX ix_notes_csum ON notes (csum);
+";
+
+const CSS: &str = ".card { font-family: arial; font-size: 20px; text-align: center; \
+color: black; background-color: white; } .cloze { font-weight: bold; color: blue; }";
+
+const LATEX_PRE: &str = "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\
+\\usepackage[utf8]{inputenc}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\
+\\setlength{\\parindent}{0in}\n\\begin{document}\n";
+
+/// Anki's default scheduling options.
+const DECK_CONFIG: &str = r#"{"1": {"id": 1, "name": "Default", "mod": 0, "usn": 0,
+"maxTaken": 60, "autoplay": true, "timer": 0, "replayq": true, "dyn": false,
+"new": {"delays": [1, 10], "ints": [1, 4, 7], "initialFactor": 2500, "order": 1,
+"perDay": 20, "bury": false},
+"lapse": {"delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 0},
+"rev": {"perDay": 200, "ease4": 1.3, "fuzz": 0.05, "maxIvl": 36500, "ivlFct": 1,
+"bury": false, "hardFactor": 1.2}}}"#;
@@ -0,0 +1,328 @@
+//! A bibliography in BibTeX. References imported from `.bib` files are
+//! written back with their own entry type, key and fields; those from
+//! Zotero have their fields mapped to BibTeX's; other documents become
+//! `@misc` entries with a key made from their title.
+
+use std::collections::HashSet;
+
+use crate::import::bibtex::{plain, AUTHORS_KEY, EDITORS_KEY, ENTRY_TYPE_KEY};
+use crate::import::zotero::CITATION_KEY;
+use crate::types::Document;
+
+/// Zotero item types and the BibTeX entry types they correspond to.
+const ENTRY_TYPES: &[(&str, &str)] = &[
+    ("journalArticle", "article"),
+    ("magazineArticle", "article"),
+    ("newspaperArticle", "article"),
+    ("book", "book"),
+    ("bookSection", "incollection"),
+    ("conferencePaper", "inproceedings"),
+    ("thesis", "phdthesis"),
+    ("report", "techreport"),
+    ("manuscript", "unpublished"),
+];
+
+/// Zotero fields, as metadata keys, and the BibTeX fields they become.
+/// `publication_title` depends on the entry type.
+const RENAMED: &[(&str, &str)] = &[
+    ("issue", "number"),
+    ("place", "address"),
+    ("university", "school"),
+    ("series_title", "series"),
+    ("book_title", "booktitle"),
+    ("proceedings_title", "booktitle"),
+    ("extra", "note"),
+];
+
+/// Fields BibTeX styles know, written in this order after the author,
+/// editor, title and year.
+const FIELDS: &[&str] = &[
+    "journal",
+    "booktitle",
+    "edition",
+    "series",
+    "volume",
+    "number",
+    "chapter",
+    "pages",
+    "month",
+    "publisher",
+    "organization",
+    "institution",
+    "school",
+    "address",
+    "howpublished",
+    "type",
+    "isbn",
+    "issn",
+    "doi",
+    "url",
+    "eprint",
+    "archiveprefix",
+    "primaryclass",
+    "urldate",
+    "note",
+];
+
+/// The bibliography of `docs`, one entry each, with unique keys.
+pub fn write(docs: &[Document]) -> String {
+    let mut keys = HashSet::new();
+    let mut out = String::new();
+    for doc in docs {
+        let key = unique_key(doc, &mut keys);
+        out.push_str(&entry(doc, &key));
+        out.push('\n');
+    }
+    out
+}
+
+fn entry(doc: &Document, key: &str) -> String {
+    let meta = |name: &str| doc.metadata.get(name).map(String::as_str);
+    let kind = match (meta(ENTRY_TYPE_KEY), meta("item_type")) {
+        (Some(kind), _) => kind.to_string(),
+        (None, Some(item)) => ENTRY_TYPES
+            .iter()
+            .find(|(zotero, _)| *zotero == item)
+            .map_or("misc", |(_, bibtex)| bibtex)
+            .to_string(),
+        (None, None) => "misc".to_string(),
+    };
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    if let Some(authors) = meta(AUTHORS_KEY) {
+        fields.push((
+            "author",
+            authors.split("; ").collect::<Vec<_>>().join(" and "),
+        ));
+    }
+    if let Some(editors) = meta(EDITORS_KEY) {
+        fields.push((
+            "editor",
+            editors.split("; ").collect::<Vec<_>>().join(" and "),
+        ));
+    }
+    // A title read from a `.bib` file keeps its LaTeX; otherwise the
+    // document's title is plain text.
+    let title = match meta("title") {
+        Some(title) if plain(title) == doc.title => title.to_string(),
+        _ => escape(&doc.title),
+    };
+    fields.push(("title", title));
+    let year = meta("year").map(str::to_string).or_else(|| {
+        let date = meta("date")?;
+        date.split(|c: char| !c.is_ascii_digit())
+            .find(|part| part.len() == 4)
+            .map(str::to_string)
+    });
+    fields.extend(year.map(|year| ("year", year)));
+
+    let mut named: Vec<(&str, &str)> = Vec::new();
+    for (key, value) in &doc.metadata {
+        let field = match key.as_str() {
+            "publication_title" if kind == "article" => "journal",
+            "publication_title" => "booktitle",
+            key => RENAMED
+                .iter()
+                .find(|(from, _)| *from == key)
+                .map_or(key, |(_, to)| to),
+        };
+        named.push((field, value));
+    }
+    if !named.iter().any(|(f, _)| *f == "url") {
+        if let Some(url) = doc.source.as_deref().filter(|s| s.contains("://")) {
+            named.push(("url", url));
+        }
+    }
+    for field in FIELDS {
+        if let Some((_, value)) = named.iter().find(|(f, _)| f == field) {
+            let from_bib = meta(ENTRY_TYPE_KEY).is_some();
+            let value = match from_bib {
+                true => value.to_string(),
+                false => escape(value),
+            };
+            fields.push((field, value));
+        }
+    }
+    if !doc.tags.is_empty() {
+        fields.push(("keywords", doc.tags.join(", ")));
+    }
+    if let Some(abstract_text) = section(&doc.content, "Abstract") {
+        fields.push(("abstract", escape(abstract_text)));
+    }
+
+    let mut out = format!("@{kind}{{{key},\n");
+    for (name, value) in fields {
+        out.push_str(&format!("  {name} = {{{value}}},\n"));
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// The citation key of `doc`, or one made of its first author's surname,
+/// its year and the first word of its title, made unique among `taken`.
+fn unique_key(doc: &Document, taken: &mut HashSet<String>) -> String {
+    let base = match doc.metadata.get(CITATION_KEY) {
+        Some(key) => key.clone(),
+        None => {
+            let author = doc
+                .metadata
+                .get(AUTHORS_KEY)
+                .and_then(|a| a.split("; ").next())
+                .and_then(|a| a.split(',').next())
+                .map(|a| a.split_whitespace().last().unwrap_or(a).to_string());
+            let year = doc
+                .metadata
+                .get("year")
+                .or_else(|| doc.metadata.get("date"))
+                .and_then(|d| {
+                    d.split(|c: char| !c.is_ascii_digit())
+                        .find(|p| p.len() == 4)
+                        .map(str::to_string)
+                });
+            let word = doc
+                .title
+                .split_whitespace()
+                .find(|w| w.len() > 3)
+                .or_else(|| doc.title.split_whitespace().next());
+            let key: String = [author.as_deref(), year.as_deref(), word]
+                .into_iter()
+                .flatten()
+                .collect::<String>()
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .to_lowercase();
+            match key.is_empty() {
+                true => doc.id.to_string(),
+                false => key,
+            }
+        }
+    };
+    let mut key = base.clone();
+    let mut suffix = b'a';
+    while !taken.insert(key.clone()) {
+        key = format!("{base}{}", suffix as char);
+        suffix += 1;
+    }
+    key
+}
+
+/// The text under a `## heading` of markdown, up to the next heading.
+fn section<'a>(content: &'a str, heading: &str) -> Option<&'a str> {
+    let marker = format!("## {heading}\n");
+    let start = content.find(&marker)? + marker.len();
+    let rest = &content[start..];
+    let end = rest.find("\n#").unwrap_or(rest.len());
+    Some(rest[..end].trim()).filter(|s| !s.is_empty())
+}
+
+/// Plain text made safe inside a BibTeX value: LaTeX's special characters
+/// escaped, unless they already are, and braces balanced.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut previous = ' ';
+    for c in text.chars() {
+        match c {
+            '&' | '%' | '#' | '_' | '$' if previous != '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '{' | '}' => {}
+            c => out.push(c),
+        }
+        previous = c;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DocumentId, DocumentKind};
+
+    fn doc(title: &str, metadata: &[(&str, &str)]) -> Document {
+        let mut doc = Document::new(
+            DocumentId::derive(title),
+            title.to_string(),
+            DocumentKind::Markdown,
+            format!("# {title}\n"),
+            None,
+        );
+        for (key, value) in metadata {
+            doc.metadata.insert(key.to_string(), value.to_string());
+        }
+        doc
+    }
+
+    #[test]
+    fn zotero_fields_map_to_bibtex() {
+        let doc = doc(
+            "Growth & Form",
+            &[
+                ("item_type", "journalArticle"),
+                (CITATION_KEY, "thompson17"),
+                (AUTHORS_KEY, "D'Arcy Thompson; Someone Else"),
+                ("publication_title", "Nature_Journal"),
+                ("date", "1917-05-01"),
+                ("issue", "3"),
+                ("unknown_field", "dropped"),
+            ],
+        );
+        assert_eq!(
+            write(&[doc]),
+            "@article{thompson17,\n  \
+             author = {D'Arcy Thompson and Someone Else},\n  \
+             title = {Growth \\& Form},\n  \
+             year = {1917},\n  \
+             journal = {Nature\\_Journal},\n  \
+             number = {3},\n\
+             }\n\n"
+        );
+    }
+
+    #[test]
+    fn other_documents_are_misc_with_made_up_keys() {
+        let mut web = doc("A Page", &[]);
+        web.source = Some("https://example.com/page".into());
+        web.tags = vec!["web".into(), "reading".into()];
+        let written = write(&[web]);
+        assert!(written.starts_with("@misc{page,\n"), "{written}");
+        assert!(written.contains("  url = {https://example.com/page},\n"));
+        assert!(written.contains("  keywords = {web, reading},\n"));
+    }
+
+    #[test]
+    fn keys_are_made_unique() {
+        let meta = [(AUTHORS_KEY, "Ada Lovelace"), ("year", "1843")];
+        let docs = [
+            doc("Sketch of the Engine", &meta),
+            doc("Sketch of a Loom", &meta),
+            doc("Sketch", &meta),
+        ];
+        let written = write(&docs);
+        let keys: Vec<&str> = written
+            .lines()
+            .filter_map(|l| l.strip_prefix("@misc{"))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "lovelace1843sketch,",
+                "lovelace1843sketcha,",
+                "lovelace1843sketchb,"
+            ]
+        );
+    }
+
+    #[test]
+    fn escape_leaves_escaped_characters_alone() {
+        assert_eq!(escape("50% & more_{x}"), "50\\% \\& more\\_x");
+        assert_eq!(escape("already \\& escaped"), "already \\& escaped");
+    }
+
+    #[test]
+    fn section_reads_up_to_the_next_heading() {
+        let content = "# T\n\n## Abstract\n\nThe gist.\n\n## Notes\n\nMore.\n";
+        assert_eq!(section(content, "Abstract"), Some("The gist."));
+        assert_eq!(section(content, "Missing"), None);
+    }
+}
@@ -0,0 +1,404 @@
+//! One book compiled from several documents, as a PDF or an EPUB with a
+//! table of contents. Chapters follow a contents document's links, if
+//! one is given, then the links between the documents: a document comes
+//! before those it links to.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use pulldown_cmark::{html::push_html, Options, Parser};
+
+use super::pdf::{self, Block, Section};
+use super::{split_frontmatter, Assets, Found, Names, Targets};
+use crate::fingerprint::content_hash;
+use crate::links::{Link, Resolver};
+use crate::storage::BlobStore;
+use crate::types::{Document, DocumentId, DocumentKind};
+
+/// Orders `docs` as chapters: first those `contents` links to, in its
+/// order, then the rest, each followed by the ones it links to that have
+/// not come yet. Documents nothing links to start, oldest first.
+pub fn order(docs: Vec<Document>, contents: Option<&Document>) -> Vec<Document> {
+    let mut docs: Vec<Document> = docs
+        .into_iter()
+        .filter(|d| Some(&d.id) != contents.map(|c| &c.id))
+        .collect();
+    docs.sort_by_key(|d| d.added);
+    let resolver = Resolver::new(&docs);
+    let linked = |doc: &Document| -> Vec<DocumentId> {
+        written_links(doc)
+            .iter()
+            .filter_map(|link| resolver.resolve(doc, link))
+            .filter(|id| *id != doc.id)
+            .collect()
+    };
+    let mut by_id: HashMap<DocumentId, Document> = HashMap::new();
+    let mut ids = Vec::new();
+    let mut incoming = HashSet::new();
+    for doc in docs {
+        incoming.extend(linked(&doc));
+        ids.push(doc.id.clone());
+        by_id.insert(doc.id.clone(), doc);
+    }
+
+    let mut ordered = Vec::new();
+    let mut seen = HashSet::new();
+    if let Some(contents) = contents {
+        for id in linked(contents) {
+            if by_id.contains_key(&id) && seen.insert(id.clone()) {
+                ordered.push(id);
+            }
+        }
+    }
+    // Documents nothing links to first, then any left in cycles.
+    let (roots, rest): (Vec<_>, Vec<_>) = ids.iter().partition(|id| !incoming.contains(*id));
+    for root in roots.into_iter().chain(rest) {
+        let mut stack = vec![root.clone()];
+        while let Some(id) = stack.pop() {
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let children = linked(&by_id[&id]);
+            stack.extend(children.into_iter().rev().filter(|c| by_id.contains_key(c)));
+            ordered.push(id);
+        }
+    }
+    ordered
+        .into_iter()
+        .filter_map(|id| by_id.remove(&id))
+        .collect()
+}
+
+/// The links in `doc` in the order they are written; `doc.links` is sorted.
+fn written_links(doc: &Document) -> Vec<Link> {
+    let mut links = Vec::new();
+    super::rewrite_links(&doc.content, |found| {
+        links.push(match found {
+            Found::Wiki { target, .. } => Link::Wiki(target.trim().to_string()),
+            Found::Markdown { target, .. } => {
+                let target = target.split_whitespace().next().unwrap_or_default();
+                Link::Href(target.trim_matches(|c| c == '<' || c == '>').to_string())
+            }
+        });
+        None
+    });
+    links
+}
+
+/// Where a link in a chapter goes.
+enum Target<'a> {
+    /// The chapter with this name.
+    Chapter(&'a str),
+    /// A blob, with its name.
+    Blob(&'a str, &'a str),
+    File(std::path::PathBuf),
+}
+
+/// A chapter's markdown, without its title heading, and with each link
+/// `replace` returns markup for rewritten.
+fn chapter_markdown(
+    doc: &Document,
+    targets: &Targets,
+    mut replace: impl FnMut(Target, &str, bool) -> Option<String>,
+) -> String {
+    let body = match doc.kind {
+        DocumentKind::Markdown => split_frontmatter(&doc.content).1.to_string(),
+        // Plain text is kept as paragraphs.
+        DocumentKind::Text | DocumentKind::Html => doc
+            .content
+            .split("\n\n")
+            .map(|p| p.replace(['*', '_', '#', '`', '[', '<'], " "))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    };
+    let body = body.trim_start();
+    let body = body
+        .strip_prefix(&format!("# {}", doc.title))
+        .filter(|rest| rest.is_empty() || rest.starts_with('\n'))
+        .unwrap_or(body);
+    super::rewrite_links(body, |found| match found {
+        Found::Wiki { target, rest } => {
+            let label = rest
+                .split_once('|')
+                .map_or_else(|| format!("{target}{rest}"), |(_, l)| l.to_string());
+            let name = targets.name(doc, Link::Wiki(target.trim().to_string()));
+            Some(
+                match name.and_then(|n| replace(Target::Chapter(n), &label, false)) {
+                    Some(markup) => markup,
+                    None => label,
+                },
+            )
+        }
+        Found::Markdown {
+            text,
+            target,
+            embed,
+        } => {
+            let target = target.split_whitespace().next()?;
+            let target = target.trim_matches(|c| c == '<' || c == '>');
+            if let Some(hash) = target.strip_prefix("blob:") {
+                let name = doc
+                    .attachments
+                    .iter()
+                    .find(|a| a.blob == hash)
+                    .map_or(hash, |a| a.name.as_str());
+                return replace(Target::Blob(hash, name), text, embed);
+            }
+            if let Some(name) = targets.name(doc, Link::Href(target.to_string())) {
+                return Some(replace(Target::Chapter(name), text, embed).unwrap_or(text.into()));
+            }
+            replace(Target::File(Assets::local_file(doc, target)?), text, embed)
+        }
+    })
+}
+
+/// Chapter names, `chapter-001` and on, in order.
+fn chapter_names(docs: &[Document]) -> Names {
+    let mut names = Names::new(docs);
+    for (i, doc) in docs.iter().enumerate() {
+        names.rename(&doc.id, format!("chapter-{:03}", i + 1));
+    }
+    names
+}
+
+/// Writes `docs`, in order, as a PDF book. Links become their text and
+/// images their descriptions. Returns the number of pages.
+pub fn write_pdf(title: &str, docs: &[Document], path: &Path) -> Result<usize> {
+    let names = chapter_names(docs);
+    let targets = Targets::new(docs, &names);
+    let sections: Vec<Section> = docs
+        .iter()
+        .map(|doc| {
+            let markdown =
+                chapter_markdown(doc, &targets, |target, text, embed| match (target, embed) {
+                    (_, true) if text.is_empty() => Some(String::new()),
+                    (_, true) => Some(format!("[{}]", text.replace(['[', ']'], ""))),
+                    _ => Some(text.to_string()),
+                });
+            let mut blocks = pdf::blocks(&markdown);
+            if blocks.first() == Some(&Block::Heading(1, doc.title.clone())) {
+                blocks.remove(0);
+            }
+            Section {
+                title: doc.title.clone(),
+                blocks,
+            }
+        })
+        .collect();
+    pdf::write(title, &sections, path)
+}
+
+const EPUB_STYLE: &str = "\
+body{font-family:serif;line-height:1.5}
+h1,h2,h3,h4{font-family:sans-serif;line-height:1.2}
+pre{white-space:pre-wrap;font-size:.85em}
+img{max-width:100%}
+table{border-collapse:collapse}td,th{border:1px solid #999;padding:.2em .4em}
+";
+
+/// Writes `docs`, in order, as an EPUB 3 book, with the images and files
+/// they refer to inside it. Returns the number of files added.
+pub fn write_epub(title: &str, docs: &[Document], blobs: &BlobStore, path: &Path) -> Result<usize> {
+    let names = chapter_names(docs);
+    let targets = Targets::new(docs, &names);
+    // Files in the book, by what they were read from, with their names.
+    let mut resources: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut stored: HashMap<String, String> = HashMap::new();
+    let mut failed = None;
+    let mut chapters = Vec::new();
+    for doc in docs {
+        let markdown = chapter_markdown(doc, &targets, |target, text, embed| {
+            let bang = if embed { "!" } else { "" };
+            let (key, name) = match target {
+                Target::Chapter(name) => return Some(format!("[{text}]({name}.xhtml)")),
+                Target::Blob(hash, name) => (format!("blob:{hash}"), name.to_string()),
+                Target::File(path) => (
+                    path.display().to_string(),
+                    path.file_name()?.to_string_lossy().into_owned(),
+                ),
+            };
+            if let Some(file) = stored.get(&key) {
+                return Some(format!("{bang}[{text}](<{file}>)"));
+            }
+            let bytes = match key.strip_prefix("blob:") {
+                Some(hash) => blobs.get(hash).transpose()?,
+                None => std::fs::read(&key).context("failed to read an attachment"),
+            };
+            let bytes = match bytes {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    failed.get_or_insert(e);
+                    return None;
+                }
+            };
+            let file = format!("files/{}-{}", resources.len() + 1, file_name(&name));
+            stored.insert(key, file.clone());
+            resources.push((file.clone(), bytes));
+            Some(format!("{bang}[{text}](<{file}>)"))
+        });
+        if let Some(e) = failed.take() {
+            return Err(e);
+        }
+        let mut html = String::new();
+        let options = Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_FOOTNOTES;
+        push_html(&mut html, Parser::new_ext(&markdown, options));
+        chapters.push((
+            names.get(&doc.id).expect("every chapter is named"),
+            doc,
+            html,
+        ));
+    }
+
+    let identifier = format!(
+        "urn:ozymandias:{}",
+        &content_hash(
+            &docs
+                .iter()
+                .map(|d| d.id.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        )[..32]
+    );
+    let modified = Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    let mut manifest = String::from(
+        "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+         <item id=\"ncx\" href=\"toc.ncx\" media-type=\"application/x-dtbncx+xml\"/>\n\
+         <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+    );
+    let mut spine = String::new();
+    let mut nav = String::new();
+    let mut points = String::new();
+    for (i, (name, doc, _)) in chapters.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"{name}\" href=\"{name}.xhtml\" media-type=\"application/xhtml+xml\"/>\n"
+        ));
+        spine.push_str(&format!("<itemref idref=\"{name}\"/>\n"));
+        nav.push_str(&format!(
+            "<li><a href=\"{name}.xhtml\">{}</a></li>\n",
+            escape(&doc.title)
+        ));
+        points.push_str(&format!(
+            "<navPoint id=\"p{n}\" playOrder=\"{n}\"><navLabel><text>{}</text></navLabel>\
+             <content src=\"{name}.xhtml\"/></navPoint>\n",
+            escape(&doc.title),
+            n = i + 1
+        ));
+    }
+    for (i, (file, _)) in resources.iter().enumerate() {
+        manifest.push_str(&format!(
+            "<item id=\"file{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            i + 1,
+            escape(file),
+            media_type(file)
+        ));
+    }
+    let opf = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"id\">\n\
+         <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n\
+         <dc:identifier id=\"id\">{identifier}</dc:identifier>\n<dc:title>{}</dc:title>\n\
+         <dc:language>en</dc:language>\n<meta property=\"dcterms:modified\">{modified}</meta>\n\
+         </metadata>\n<manifest>\n{manifest}</manifest>\n<spine toc=\"ncx\">\n{spine}</spine>\n</package>\n",
+        escape(title)
+    );
+    let nav = xhtml(
+        "Contents",
+        &format!("<nav epub:type=\"toc\" id=\"toc\"><h1>Contents</h1>\n<ol>\n{nav}</ol></nav>\n"),
+    );
+    let ncx = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <ncx xmlns=\"http://www.daisy.org/z3986/2005/ncx/\" version=\"2005-1\">\n\
+         <head><meta name=\"dtb:uid\" content=\"{identifier}\"/></head>\n\
+         <docTitle><text>{}</text></docTitle>\n<navMap>\n{points}</navMap>\n</ncx>\n",
+        escape(title)
+    );
+
+    let file = std::fs::File::create(path)
+        .with_context(|| format!("failed to create {}", path.display()))?;
+    let mut zip = zip::ZipWriter::new(file);
+    // Readers recognize EPUBs by an uncompressed `mimetype` first.
+    let stored_options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    let options = zip::write::SimpleFileOptions::default();
+    zip.start_file("mimetype", stored_options)?;
+    zip.write_all(b"application/epub+zip")?;
+    zip.start_file("META-INF/container.xml", options)?;
+    zip.write_all(
+        b"<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+          <container version=\"1.0\" xmlns=\"urn:oasis:names:tc:opendocument:xmlns:container\">\n\
+          <rootfiles><rootfile full-path=\"OEBPS/content.opf\" media-type=\"application/oebps-package+xml\"/></rootfiles>\n\
+          </container>\n",
+    )?;
+    let mut add = |name: &str, bytes: &[u8]| -> Result<()> {
+        zip.start_file(format!("OEBPS/{name}"), options)?;
+        zip.write_all(bytes)?;
+        Ok(())
+    };
+    add("content.opf", opf.as_bytes())?;
+    add("nav.xhtml", nav.as_bytes())?;
+    add("toc.ncx", ncx.as_bytes())?;
+    add("style.css", EPUB_STYLE.as_bytes())?;
+    for (name, doc, html) in &chapters {
+        let body = format!("<h1>{}</h1>\n{html}", escape(&doc.title));
+        add(
+            &format!("{name}.xhtml"),
+            xhtml(&doc.title, &body).as_bytes(),
+        )?;
+    }
+    for (name, bytes) in &resources {
+        add(name, bytes)?;
+    }
+    zip.finish()?;
+    Ok(resources.len())
+}
+
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<!DOCTYPE html>\n\
+         <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n\
+         <head><title>{}</title><link rel=\"stylesheet\" href=\"style.css\"/></head>\n\
+         <body>\n{body}</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+/// `name` with only characters that are safe in URLs and zip files.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn media_type(file: &str) -> &'static str {
+    let extension = file
+        .rsplit('.')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "css" => "text/css",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
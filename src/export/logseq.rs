@@ -0,0 +1,333 @@
+//! A Logseq graph: pages as outlines of `- ` blocks under `pages/`, with
+//! metadata as `property:: value` lines, date-stamped notes as journal
+//! pages under `journals/`, and attachments under `assets/`.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate};
+use serde_yaml::Value;
+
+use super::{split_frontmatter, Assets, Found, Names, Targets};
+use crate::attachments;
+use crate::links::Link;
+use crate::storage::BlobStore;
+use crate::types::{
+    Document, DocumentKind, ARCHIVE_KEY, MODIFIED_KEY, SNAPSHOT_KEY, SOURCE_HASH_KEY,
+};
+
+pub const PAGES_DIR: &str = "pages";
+pub const JOURNALS_DIR: &str = "journals";
+pub const ASSETS_DIR: &str = "assets";
+
+/// Writes `docs` into the graph `dir`, and returns how many attachments
+/// were written with them.
+pub fn export(docs: &[Document], blobs: &BlobStore, dir: &Path) -> Result<usize> {
+    let mut names = Names::new(docs);
+    // The first note of a day becomes its journal page, named the way
+    // Logseq names journal pages by default.
+    let mut journals = HashSet::new();
+    for doc in docs {
+        if let Some(date) = journal_date(doc) {
+            if journals.insert(date) {
+                names.rename(&doc.id, journal_name(date));
+            }
+        }
+    }
+    let targets = Targets::new(docs, &names);
+    let mut assets = Assets::new(dir.join(ASSETS_DIR));
+    for doc in docs {
+        let name = names.get(&doc.id).expect("every document is named");
+        let mut properties = Vec::new();
+        let body = match doc.kind {
+            DocumentKind::Markdown => {
+                let (existing, body) = split_frontmatter(&doc.content);
+                if let Some(Ok(Value::Mapping(existing))) = existing.map(serde_yaml::from_str) {
+                    for (key, value) in existing {
+                        if let (Some(key), Some(value)) = (key.as_str(), property_value(&value)) {
+                            properties.push((key.to_string(), value));
+                        }
+                    }
+                }
+                outline(&markdown(doc, body, &targets, blobs, &mut assets)?)
+            }
+            DocumentKind::Text | DocumentKind::Html => plain(doc, &targets),
+        };
+        known_properties(doc, &names, &mut properties);
+        let mut files = Vec::new();
+        for attachment in &doc.attachments {
+            if let Some(file) = assets.blob(blobs, &attachment.blob, &attachment.name)? {
+                files.push(format!("../{ASSETS_DIR}/{file}"));
+            }
+        }
+        if !files.is_empty() {
+            properties.retain(|(k, _)| k != attachments::KEY);
+            properties.push((attachments::KEY.into(), files.join(", ")));
+        }
+        for (key, suffix) in [(SNAPSHOT_KEY, "html"), (ARCHIVE_KEY, "archive.html")] {
+            let Some(blob) = doc.metadata.get(key) else {
+                continue;
+            };
+            if let Some(html) = blobs.get(blob)? {
+                let file = assets.add(format!("{name}.{suffix}"), &html)?;
+                properties.push((key.into(), format!("../{ASSETS_DIR}/{file}")));
+            }
+        }
+
+        let path = match journal_date(doc).filter(|d| journal_name(*d) == name) {
+            Some(date) => dir
+                .join(JOURNALS_DIR)
+                .join(format!("{}.md", date.format("%Y_%m_%d"))),
+            None => dir.join(PAGES_DIR).join(format!("{name}.md")),
+        };
+        let mut page = String::new();
+        for (key, value) in properties {
+            page.push_str(&format!("{}:: {value}\n", property_key(&key)));
+        }
+        page.push('\n');
+        page.push_str(&body);
+        let parent = path.parent().expect("pages are in a directory");
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+        std::fs::write(&path, page)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(assets.len())
+}
+
+/// The day a note is about, if its title or file name is a date.
+fn journal_date(doc: &Document) -> Option<NaiveDate> {
+    let stem = doc
+        .source
+        .as_deref()
+        .and_then(|s| Path::new(s).file_stem())
+        .and_then(|s| s.to_str());
+    [Some(doc.title.as_str()), stem]
+        .into_iter()
+        .flatten()
+        .find_map(|s| {
+            ["%Y-%m-%d", "%Y_%m_%d", "%Y.%m.%d"]
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(s.trim(), f).ok())
+        })
+}
+
+/// Logseq's default journal title, such as `Jan 5th, 2024`.
+fn journal_name(date: NaiveDate) -> String {
+    let day = date.day();
+    let suffix = match (day % 10, day) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{} {day}{suffix}, {}", date.format("%b"), date.year())
+}
+
+fn known_properties(doc: &Document, names: &Names, properties: &mut Vec<(String, String)>) {
+    let mut set = |key: &str, value: String| {
+        properties.retain(|(k, _)| k != key);
+        properties.push((key.to_string(), value));
+    };
+    // `id::` is Logseq's own block reference.
+    set("ozy-id", doc.id.to_string());
+    if !doc.aliases.is_empty() {
+        set("alias", doc.aliases.join(", "));
+    }
+    if !doc.tags.is_empty() {
+        set("tags", doc.tags.join(", "));
+    }
+    set("type", doc.kind.name().to_string());
+    if let Some(source) = &doc.source {
+        set("source", source.clone());
+    }
+    set("added", doc.added.format("%Y-%m-%d").to_string());
+    let mut relations: Vec<(&str, Vec<String>)> = Vec::new();
+    for relation in &doc.relations {
+        let Some(name) = names.get(&relation.target) else {
+            continue;
+        };
+        let link = format!("[[{name}]]");
+        match relations
+            .iter_mut()
+            .find(|(k, _)| *k == relation.kind.name())
+        {
+            Some((_, links)) => links.push(link),
+            None => relations.push((relation.kind.name(), vec![link])),
+        }
+    }
+    for (kind, links) in relations {
+        set(kind, links.join(", "));
+    }
+    let internal = [SNAPSHOT_KEY, ARCHIVE_KEY, MODIFIED_KEY, SOURCE_HASH_KEY];
+    for (key, value) in &doc.metadata {
+        if !internal.contains(&key.as_str()) && !value.contains('\n') {
+            set(key, value.clone());
+        }
+    }
+}
+
+/// Property names are lowercase, with dashes for other separators.
+fn property_key(key: &str) -> String {
+    key.chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' => c.to_ascii_lowercase(),
+            _ => '-',
+        })
+        .collect()
+}
+
+/// A frontmatter value on one line, lists as comma-separated items.
+fn property_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.contains('\n') => Some(s.clone()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Sequence(items) => {
+            let items: Option<Vec<String>> = items.iter().map(property_value).collect();
+            items.map(|items| items.join(", "))
+        }
+        _ => None,
+    }
+}
+
+/// Markdown with links to other documents as page references, and
+/// attachments and local files it refers to copied into the assets.
+fn markdown(
+    doc: &Document,
+    body: &str,
+    targets: &Targets,
+    blobs: &BlobStore,
+    assets: &mut Assets,
+) -> Result<String> {
+    let mut failed = None;
+    let body = super::rewrite_links(body, |found| match found {
+        Found::Wiki { target, rest } => {
+            let name = targets.name(doc, Link::Wiki(target.trim().to_string()))?;
+            // Logseq has no heading links; keep a label if there is one.
+            match rest.split_once('|') {
+                Some((_, label)) => Some(format!("[{label}]([[{name}]])")),
+                None => Some(format!("[[{name}]]")),
+            }
+        }
+        Found::Markdown {
+            text,
+            target,
+            embed,
+        } => {
+            let target = target.split_whitespace().next()?;
+            let target = target.trim_matches(|c| c == '<' || c == '>');
+            if let Some(name) = targets.name(doc, Link::Href(target.to_string())) {
+                return Some(match text.is_empty() {
+                    true => format!("[[{name}]]"),
+                    false => format!("[{text}]([[{name}]])"),
+                });
+            }
+            let copied = match target.strip_prefix("blob:") {
+                Some(hash) => {
+                    let name = doc
+                        .attachments
+                        .iter()
+                        .find(|a| a.blob == hash)
+                        .map_or(hash, |a| a.name.as_str());
+                    assets.blob(blobs, hash, name).transpose()?
+                }
+                None => assets.copy(&Assets::local_file(doc, target)?),
+            };
+            match copied {
+                Ok(name) => {
+                    let bang = if embed { "!" } else { "" };
+                    Some(format!("{bang}[{text}](../{ASSETS_DIR}/{name})"))
+                }
+                Err(e) => {
+                    failed.get_or_insert(e);
+                    None
+                }
+            }
+        }
+    });
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(body),
+    }
+}
+
+/// Text and web pages as one block per paragraph, followed by a block
+/// linking the documents they link to.
+fn plain(doc: &Document, targets: &Targets) -> String {
+    let mut body = outline(&doc.content);
+    let linked: Vec<String> = doc
+        .links
+        .iter()
+        .filter_map(|link| targets.name(doc, link.clone()))
+        .map(|name| format!("[[{name}]]"))
+        .collect();
+    if !linked.is_empty() {
+        body.push_str(&format!("- Links: {}\n", linked.join(", ")));
+    }
+    body
+}
+
+/// Turns markdown into Logseq's outline: each paragraph, heading or code
+/// block becomes a `- ` block, and list items become blocks nested by
+/// their indentation.
+fn outline(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut fence: Option<Vec<&str>> = None;
+    let flush = |paragraph: &mut Vec<&str>, out: &mut String| {
+        if let Some((first, rest)) = paragraph.split_first() {
+            out.push_str(&format!("- {first}\n"));
+            for line in rest {
+                out.push_str(&format!("  {line}\n"));
+            }
+        }
+        paragraph.clear();
+    };
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        if let Some(code) = &mut fence {
+            code.push(line);
+            if trimmed.starts_with("```") {
+                let mut code = fence.take().unwrap();
+                flush(&mut code, &mut out);
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") {
+            flush(&mut paragraph, &mut out);
+            fence = Some(vec![line]);
+        } else if trimmed.is_empty() {
+            flush(&mut paragraph, &mut out);
+        } else if trimmed.starts_with('#') {
+            flush(&mut paragraph, &mut out);
+            out.push_str(&format!("- {trimmed}\n"));
+        } else if let Some(item) = list_item(trimmed) {
+            flush(&mut paragraph, &mut out);
+            let indent = line.len() - trimmed.len();
+            out.push_str(&"\t".repeat(indent / 2));
+            out.push_str(&format!("- {item}\n"));
+        } else {
+            paragraph.push(line);
+        }
+    }
+    if let Some(mut code) = fence {
+        flush(&mut code, &mut out);
+    }
+    flush(&mut paragraph, &mut out);
+    out
+}
+
+/// The text of a `-`, `*`, `+` or numbered list item.
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(item) = line.strip_prefix(['-', '*', '+']) {
+        return item.strip_prefix(' ');
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    if digits == 0 {
+        return None;
+    }
+    line[digits..].strip_prefix(". ")
+}
@@ -0,0 +1,286 @@
+//! Writing documents out as files for other note-taking tools, with links
+//! between documents rewritten to point at the exported files.
+
+pub mod anki;
+pub mod bibtex;
+pub mod book;
+pub mod logseq;
+pub mod obsidian;
+pub mod pdf;
+pub mod site;
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::links::{self, Link, Resolver};
+use crate::storage::BlobStore;
+use crate::types::{Document, DocumentId};
+
+/// Longest file name, in characters, derived from a title.
+const MAX_NAME: usize = 100;
+
+/// The name each exported document is written under: its title, made safe
+/// for file systems and unique, case-insensitively, within the export.
+pub struct Names(HashMap<DocumentId, String>);
+
+impl Names {
+    pub fn new(docs: &[Document]) -> Self {
+        let mut taken = HashSet::new();
+        let mut names = HashMap::new();
+        for doc in docs {
+            let base = match file_name(&doc.title) {
+                name if name.is_empty() => doc.id.to_string(),
+                name => name,
+            };
+            let mut name = base.clone();
+            let mut n = 1;
+            while !taken.insert(name.to_lowercase()) {
+                n += 1;
+                name = format!("{base} ({n})");
+            }
+            names.insert(doc.id.clone(), name);
+        }
+        Names(names)
+    }
+
+    pub fn get(&self, id: &DocumentId) -> Option<&str> {
+        self.0.get(id).map(String::as_str)
+    }
+
+    /// Names a document after something other than its title. The name
+    /// must not be taken already.
+    pub fn rename(&mut self, id: &DocumentId, name: String) {
+        self.0.insert(id.clone(), name);
+    }
+}
+
+/// `title` without the characters file systems or wiki links reject.
+pub fn file_name(title: &str) -> String {
+    let cleaned: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | '#' | '^' | '[' | ']' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    let name = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    let name: String = name.chars().take(MAX_NAME).collect();
+    name.trim_matches(|c: char| c == '.' || c.is_whitespace())
+        .to_string()
+}
+
+/// A link found in markdown by [`rewrite_links`].
+pub enum Found<'a> {
+    /// `[[target#heading|label]]`; `rest` is what follows the target.
+    Wiki { target: &'a str, rest: &'a str },
+    /// `[text](target)`, or `![text](target)` when `embed`.
+    Markdown {
+        text: &'a str,
+        target: &'a str,
+        embed: bool,
+    },
+}
+
+/// Replaces the links in markdown `content` for which `replace` returns
+/// new markup, keeping the others as written.
+pub fn rewrite_links(content: &str, mut replace: impl FnMut(Found) -> Option<String>) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find('[') {
+        let (before, from) = rest.split_at(start);
+        let embed = before.ends_with('!');
+        if let Some((found, len)) = link_at(from, embed) {
+            if let Some(markup) = replace(found) {
+                out.push_str(if embed {
+                    &before[..before.len() - 1]
+                } else {
+                    before
+                });
+                out.push_str(&markup);
+                rest = &from[len..];
+                continue;
+            }
+            out.push_str(before);
+            out.push_str(&from[..len]);
+            rest = &from[len..];
+            continue;
+        }
+        out.push_str(before);
+        out.push('[');
+        rest = &from[1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The link starting at the `[` that `s` begins with, and its length.
+fn link_at(s: &str, embed: bool) -> Option<(Found<'_>, usize)> {
+    if let Some(inner) = s.strip_prefix("[[") {
+        let end = inner.find("]]")?;
+        let body = &inner[..end];
+        if body.contains('\n') {
+            return None;
+        }
+        let split = body.find(['#', '|']).unwrap_or(body.len());
+        let (target, rest) = body.split_at(split);
+        return Some((Found::Wiki { target, rest }, end + 4));
+    }
+    let close = s.find(']')?;
+    let text = &s[1..close];
+    let after = s[close + 1..].strip_prefix('(')?;
+    let end = after.find(')')?;
+    let target = &after[..end];
+    if text.contains('\n') || target.contains('\n') {
+        return None;
+    }
+    Some((
+        Found::Markdown {
+            text,
+            target,
+            embed,
+        },
+        close + 2 + end + 1,
+    ))
+}
+
+/// Splits markdown into its YAML frontmatter, if any, and the rest.
+pub fn split_frontmatter(content: &str) -> (Option<&str>, &str) {
+    let Some(rest) = content.strip_prefix("---\n") else {
+        return (None, content);
+    };
+    match rest.find("\n---\n") {
+        Some(end) => (Some(&rest[..end + 1]), &rest[end + 5..]),
+        None => (None, content),
+    }
+}
+
+/// Copies files referred to by documents, such as images, into one
+/// directory, each once and under a unique name.
+pub struct Assets {
+    dir: PathBuf,
+    copied: HashMap<PathBuf, String>,
+    taken: HashSet<String>,
+}
+
+impl Assets {
+    pub fn new(dir: PathBuf) -> Self {
+        Assets {
+            dir,
+            copied: HashMap::new(),
+            taken: HashSet::new(),
+        }
+    }
+
+    /// Number of files copied.
+    pub fn len(&self) -> usize {
+        self.copied.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.copied.is_empty()
+    }
+
+    /// The file `target` links to from `doc`, relative to its source
+    /// file, if it exists.
+    pub fn local_file(doc: &Document, target: &str) -> Option<PathBuf> {
+        if (Link::Href(target.to_string())).is_external() {
+            return None;
+        }
+        let target = target.split('#').next()?.replace("%20", " ");
+        let base = Path::new(doc.source.as_deref()?).parent()?;
+        let path = links::normalize(&base.join(target));
+        path.is_file().then_some(path)
+    }
+
+    /// Copies `path` unless it was already, and returns its name in the
+    /// assets directory.
+    pub fn copy(&mut self, path: &Path) -> Result<String> {
+        if let Some(name) = self.copied.get(path) {
+            return Ok(name.clone());
+        }
+        let file_name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("asset")
+            .to_string();
+        let name = self.unique(file_name);
+        self.write(
+            &name,
+            &std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?,
+        )?;
+        self.copied.insert(path.to_path_buf(), name.clone());
+        Ok(name)
+    }
+
+    /// Writes `bytes` under a unique name based on `name`, and returns it.
+    pub fn add(&mut self, name: String, bytes: &[u8]) -> Result<String> {
+        let name = self.unique(name);
+        self.write(&name, bytes)?;
+        self.copied.insert(self.dir.join(&name), name.clone());
+        Ok(name)
+    }
+
+    /// Writes the blob `hash` unless it was already, under a unique name
+    /// based on `name`, and returns that name, or `None` if there is no
+    /// such blob.
+    pub fn blob(&mut self, blobs: &BlobStore, hash: &str, name: &str) -> Result<Option<String>> {
+        let key = PathBuf::from(format!("blob:{hash}"));
+        if let Some(name) = self.copied.get(&key) {
+            return Ok(Some(name.clone()));
+        }
+        let Some(bytes) = blobs.get(hash)? else {
+            return Ok(None);
+        };
+        let name = self.unique(name.to_string());
+        self.write(&name, &bytes)?;
+        self.copied.insert(key, name.clone());
+        Ok(Some(name))
+    }
+
+    fn unique(&mut self, name: String) -> String {
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => {
+                (stem.to_string(), format!(".{extension}"))
+            }
+            _ => (name.clone(), String::new()),
+        };
+        let mut candidate = name;
+        let mut n = 1;
+        while !self.taken.insert(candidate.to_lowercase()) {
+            n += 1;
+            candidate = format!("{stem} ({n}){extension}");
+        }
+        candidate
+    }
+
+    fn write(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let path = self.dir.join(name);
+        std::fs::write(&path, bytes).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Resolves links between exported documents to their names.
+pub struct Targets<'a> {
+    resolver: Resolver,
+    names: &'a Names,
+}
+
+impl<'a> Targets<'a> {
+    pub fn new(docs: &[Document], names: &'a Names) -> Self {
+        Targets {
+            resolver: Resolver::new(docs),
+            names,
+        }
+    }
+
+    /// The name of the exported document `link` in `from` points to.
+    pub fn name(&self, from: &Document, link: Link) -> Option<&'a str> {
+        let id = self.resolver.resolve(from, &link)?;
+        self.names.get(&id)
+    }
+}
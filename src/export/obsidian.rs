@@ -0,0 +1,194 @@
+//! An Obsidian vault: one markdown file per document with its metadata as
+//! YAML frontmatter, `[[wikilinks]]` between documents, and referenced
+//! files and page snapshots under `assets/`.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_yaml::{Mapping, Value};
+
+use super::{split_frontmatter, Assets, Found, Names, Targets};
+use crate::attachments;
+use crate::links::Link;
+use crate::storage::BlobStore;
+use crate::types::{
+    Document, DocumentKind, ARCHIVE_KEY, MODIFIED_KEY, SNAPSHOT_KEY, SOURCE_HASH_KEY,
+};
+
+pub const ASSETS_DIR: &str = "assets";
+
+/// Writes `docs` into the vault `dir`, and returns how many attachments
+/// were written with them.
+pub fn export(docs: &[Document], blobs: &BlobStore, dir: &Path) -> Result<usize> {
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let names = Names::new(docs);
+    let targets = Targets::new(docs, &names);
+    let mut assets = Assets::new(dir.join(ASSETS_DIR));
+    for doc in docs {
+        let name = names.get(&doc.id).expect("every document is named");
+        let mut frontmatter = Mapping::new();
+        let mut body = match doc.kind {
+            DocumentKind::Markdown => {
+                let (existing, body) = split_frontmatter(&doc.content);
+                // Keep what the author wrote there, under what we know.
+                if let Some(Ok(Value::Mapping(existing))) = existing.map(serde_yaml::from_str) {
+                    frontmatter = existing;
+                }
+                markdown(doc, body, &targets, blobs, &mut assets)?
+            }
+            DocumentKind::Text | DocumentKind::Html => plain(doc, &targets),
+        };
+        properties(doc, &names, &mut frontmatter);
+        let mut files = Vec::new();
+        for attachment in &doc.attachments {
+            if let Some(file) = assets.blob(blobs, &attachment.blob, &attachment.name)? {
+                files.push(format!("[[{file}]]"));
+            }
+        }
+        if !files.is_empty() {
+            frontmatter.insert(attachments::KEY.into(), strings(files.into_iter()));
+        }
+        // The HTML a page was clipped from, and its self-contained copy.
+        for (key, suffix) in [(SNAPSHOT_KEY, "html"), (ARCHIVE_KEY, "archive.html")] {
+            let Some(blob) = doc.metadata.get(key) else {
+                continue;
+            };
+            if let Some(html) = blobs.get(blob)? {
+                let file = assets.add(format!("{name}.{suffix}"), &html)?;
+                frontmatter.insert(key.into(), format!("[[{file}]]").into());
+            }
+        }
+        if !body.ends_with('\n') {
+            body.push('\n');
+        }
+        let path = dir.join(format!("{name}.md"));
+        let yaml = serde_yaml::to_string(&frontmatter)?;
+        std::fs::write(&path, format!("---\n{yaml}---\n{body}"))
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(assets.len())
+}
+
+/// Obsidian's own properties, then relations and the rest of the metadata.
+fn properties(doc: &Document, names: &Names, frontmatter: &mut Mapping) {
+    let mut set = |key: &str, value: Value| {
+        frontmatter.insert(key.into(), value);
+    };
+    set("id", doc.id.to_string().into());
+    if !doc.aliases.is_empty() {
+        set("aliases", strings(doc.aliases.iter().cloned()));
+    }
+    if !doc.tags.is_empty() {
+        // Obsidian tags cannot contain spaces; `/` nests them as here.
+        set(
+            "tags",
+            strings(doc.tags.iter().map(|t| t.replace(' ', "-"))),
+        );
+    }
+    set("type", doc.kind.name().into());
+    if let Some(source) = &doc.source {
+        set("source", source.clone().into());
+    }
+    set("added", doc.added.to_rfc3339().into());
+    for relation in &doc.relations {
+        let Some(name) = names.get(&relation.target) else {
+            continue;
+        };
+        let key = Value::from(relation.kind.name());
+        let entry = frontmatter
+            .entry(key)
+            .or_insert_with(|| Value::Sequence(Vec::new()));
+        if let Value::Sequence(links) = entry {
+            links.push(format!("[[{name}]]").into());
+        }
+    }
+    // What `ozy add` records to notice changed sources means nothing
+    // outside the knowledge base.
+    let internal = [SNAPSHOT_KEY, ARCHIVE_KEY, MODIFIED_KEY, SOURCE_HASH_KEY];
+    for (key, value) in &doc.metadata {
+        if !internal.contains(&key.as_str()) && !frontmatter.contains_key(key.as_str()) {
+            frontmatter.insert(key.as_str().into(), value.clone().into());
+        }
+    }
+}
+
+fn strings(values: impl Iterator<Item = String>) -> Value {
+    Value::Sequence(values.map(Value::from).collect())
+}
+
+/// Markdown with links to other documents as wikilinks, and attachments
+/// and local files it refers to copied into the assets.
+fn markdown(
+    doc: &Document,
+    body: &str,
+    targets: &Targets,
+    blobs: &BlobStore,
+    assets: &mut Assets,
+) -> Result<String> {
+    let mut failed = None;
+    let body = super::rewrite_links(body, |found| match found {
+        Found::Wiki { target, rest } => {
+            let name = targets.name(doc, Link::Wiki(target.trim().to_string()))?;
+            Some(format!("[[{name}{rest}]]"))
+        }
+        Found::Markdown {
+            text,
+            target,
+            embed,
+        } => {
+            let target = target.split_whitespace().next()?;
+            let target = target.trim_matches(|c| c == '<' || c == '>');
+            let name = if let Some(hash) = target.strip_prefix("blob:") {
+                let name = doc
+                    .attachments
+                    .iter()
+                    .find(|a| a.blob == hash)
+                    .map_or(hash, |a| a.name.as_str());
+                match assets.blob(blobs, hash, name) {
+                    Ok(file) => file?,
+                    Err(e) => {
+                        failed.get_or_insert(e);
+                        return None;
+                    }
+                }
+            } else if let Some(name) = targets.name(doc, Link::Href(target.to_string())) {
+                name.to_string()
+            } else {
+                let file = Assets::local_file(doc, target)?;
+                match assets.copy(&file) {
+                    Ok(name) => name,
+                    Err(e) => {
+                        failed.get_or_insert(e);
+                        return None;
+                    }
+                }
+            };
+            Some(match (embed, text.is_empty()) {
+                (true, _) => format!("![[{name}]]"),
+                (false, true) => format!("[[{name}]]"),
+                (false, false) => format!("[[{name}|{text}]]"),
+            })
+        }
+    });
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(body),
+    }
+}
+
+/// Text and web pages as they are, followed by the documents they link to.
+fn plain(doc: &Document, targets: &Targets) -> String {
+    let mut body = doc.content.clone();
+    let linked: Vec<&str> = doc
+        .links
+        .iter()
+        .filter_map(|link| targets.name(doc, link.clone()))
+        .collect();
+    if !linked.is_empty() {
+        body.push_str("\n\n## Links\n\n");
+        for name in linked {
+            body.push_str(&format!("- [[{name}]]\n"));
+        }
+    }
+    body
+}
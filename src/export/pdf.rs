@@ -0,0 +1,624 @@
+//! A PDF writer for books: markdown laid out as text in PDF's standard
+//! Helvetica and Courier fonts, which readers have built in, so nothing
+//! needs embedding. Characters outside the Windows-1252 set those fonts
+//! cover are printed as `?`, and images as their alternative text.
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::parser::strip_tags;
+
+/// A4, in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const MARGIN: f32 = 72.0;
+const TEXT_WIDTH: f32 = PAGE_WIDTH - 2.0 * MARGIN;
+const BODY_SIZE: f32 = 11.0;
+const CODE_SIZE: f32 = 9.5;
+/// Indentation of each level of lists and of quotes.
+const INDENT: f32 = 18.0;
+
+/// Widths of the printable ASCII characters, from ` ` to `~`, in
+/// thousandths of the font size.
+const HELVETICA: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, 1015, 667, 667, 722, 722, 667,
+    611, 778, 722, 278, 500, 667, 556, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 278, 278, 278, 469, 556, 333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500,
+    222, 833, 556, 556, 556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+const HELVETICA_BOLD: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278, 556, 556, 556,
+    556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611, 975, 722, 722, 722, 722, 667,
+    611, 778, 722, 278, 556, 722, 611, 833, 722, 778, 667, 778, 722, 667, 611, 722, 667, 944, 667,
+    667, 611, 333, 278, 333, 584, 556, 333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556,
+    278, 889, 611, 611, 611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+
+/// A part of a chapter, as laid out.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading(u8, String),
+    Paragraph(String),
+    /// A list item at a depth from 0, with its bullet or number.
+    Item(usize, String, String),
+    Code(String),
+    Quote(String),
+    Rule,
+}
+
+/// A chapter of the book.
+pub struct Section {
+    pub title: String,
+    pub blocks: Vec<Block>,
+}
+
+/// The blocks of markdown. Inline formatting is dropped.
+pub fn blocks(markdown: &str) -> Vec<Block> {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut blocks = Vec::new();
+    let mut text = String::new();
+    // The block the text belongs to, once it ends.
+    let mut heading = None;
+    let mut code = false;
+    let mut quotes = 0;
+    // Next number of each open list; `None` for bullets.
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut item: Option<(usize, String)> = None;
+    let flush = |text: &mut String,
+                 item: &mut Option<(usize, String)>,
+                 quotes: usize,
+                 blocks: &mut Vec<Block>| {
+        let trimmed = text.trim();
+        if let Some((depth, marker)) = item.take() {
+            blocks.push(Block::Item(depth, marker, trimmed.to_string()));
+        } else if trimmed.is_empty() {
+        } else if quotes > 0 {
+            blocks.push(Block::Quote(trimmed.to_string()));
+        } else {
+            blocks.push(Block::Paragraph(trimmed.to_string()));
+        }
+        text.clear();
+    };
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                heading = Some(level);
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                let level = match heading.take() {
+                    Some(HeadingLevel::H1) => 1,
+                    Some(HeadingLevel::H2) => 2,
+                    Some(HeadingLevel::H3) => 3,
+                    _ => 4,
+                };
+                blocks.push(Block::Heading(level, text.trim().to_string()));
+                text.clear();
+            }
+            Event::Start(Tag::CodeBlock(_)) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                code = true;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                blocks.push(Block::Code(text.trim_end().to_string()));
+                text.clear();
+                code = false;
+            }
+            Event::Start(Tag::BlockQuote(_)) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                quotes += 1;
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                quotes -= 1;
+            }
+            Event::Start(Tag::List(start)) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                lists.pop();
+            }
+            Event::Start(Tag::Item) => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                let depth = lists.len().saturating_sub(1);
+                let marker = match lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}.", *n - 1)
+                    }
+                    _ => "•".to_string(),
+                };
+                item = Some((depth, marker));
+            }
+            Event::End(TagEnd::Item) => flush(&mut text, &mut item, quotes, &mut blocks),
+            Event::Start(Tag::Paragraph) if item.is_none() => {
+                flush(&mut text, &mut item, quotes, &mut blocks)
+            }
+            Event::End(TagEnd::Paragraph) if item.is_none() => {
+                flush(&mut text, &mut item, quotes, &mut blocks)
+            }
+            Event::End(TagEnd::Paragraph) => text.push(' '),
+            Event::End(TagEnd::TableRow | TagEnd::TableHead) => {
+                flush(&mut text, &mut item, quotes, &mut blocks)
+            }
+            Event::End(TagEnd::TableCell) => text.push_str(" | "),
+            Event::Text(t) => text.push_str(&t),
+            Event::Code(t) => text.push_str(&t),
+            Event::InlineHtml(t) | Event::Html(t) if !code => text.push_str(&strip_tags(&t)),
+            Event::SoftBreak => text.push(' '),
+            Event::HardBreak => text.push('\n'),
+            Event::Rule => {
+                flush(&mut text, &mut item, quotes, &mut blocks);
+                blocks.push(Block::Rule);
+            }
+            Event::TaskListMarker(done) => text.push_str(if done { "[x] " } else { "[ ] " }),
+            Event::FootnoteReference(label) => text.push_str(&format!("[{label}]")),
+            _ => {}
+        }
+    }
+    flush(&mut text, &mut item, quotes, &mut blocks);
+    blocks
+}
+
+#[derive(Clone, Copy)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+    Mono,
+}
+
+impl Font {
+    fn resource(self) -> &'static str {
+        match self {
+            Font::Regular => "F1",
+            Font::Bold => "F2",
+            Font::Italic => "F3",
+            Font::Mono => "F4",
+        }
+    }
+
+    fn width(self, text: &str, size: f32) -> f32 {
+        let units: u32 = text
+            .chars()
+            .map(|c| match (self, c as u32) {
+                (Font::Mono, _) => 600,
+                (Font::Bold, n @ 32..=126) => u32::from(HELVETICA_BOLD[n as usize - 32]),
+                (_, n @ 32..=126) => u32::from(HELVETICA[n as usize - 32]),
+                _ => 556,
+            })
+            .sum();
+        units as f32 * size / 1000.0
+    }
+}
+
+#[derive(Default)]
+struct Page {
+    content: String,
+    /// Link rectangles and the page they go to.
+    links: Vec<([f32; 4], usize)>,
+}
+
+struct Layout {
+    pages: Vec<Page>,
+    y: f32,
+}
+
+impl Layout {
+    fn new() -> Self {
+        Layout {
+            pages: vec![Page::default()],
+            y: PAGE_HEIGHT - MARGIN,
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Page::default());
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Moves down by `height`, starting a new page if it does not fit.
+    fn advance(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+        self.y -= height;
+    }
+
+    fn text(&mut self, x: f32, font: Font, size: f32, text: &str) {
+        let page = self.pages.last_mut().expect("there is a page");
+        page.content.push_str(&format!(
+            "BT /{} {size} Tf {x:.2} {:.2} Td {} Tj ET\n",
+            font.resource(),
+            self.y,
+            literal(text)
+        ));
+    }
+
+    /// Wraps `text` to the lines between `x` and the right margin.
+    fn paragraph(&mut self, x: f32, font: Font, size: f32, leading: f32, text: &str) {
+        for line in wrap(text, font, size, PAGE_WIDTH - MARGIN - x) {
+            self.advance(leading);
+            self.text(x, font, size, &line);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        let leading = BODY_SIZE * 1.4;
+        match block {
+            Block::Heading(level, text) => {
+                let size = match level {
+                    1 => 20.0,
+                    2 => 16.0,
+                    3 => 13.0,
+                    _ => BODY_SIZE,
+                };
+                // Headings stay with what follows them.
+                if self.y - 4.0 * leading < MARGIN {
+                    self.new_page();
+                }
+                self.advance(size * 0.6);
+                self.paragraph(MARGIN, Font::Bold, size, size * 1.3, text);
+                self.advance(size * 0.3);
+            }
+            Block::Paragraph(text) => {
+                for line in text.split('\n') {
+                    self.paragraph(MARGIN, Font::Regular, BODY_SIZE, leading, line);
+                }
+                self.advance(leading * 0.5);
+            }
+            Block::Item(depth, marker, text) => {
+                let x = MARGIN + INDENT * (*depth as f32);
+                self.advance(leading);
+                self.text(x, Font::Regular, BODY_SIZE, marker);
+                self.y += leading;
+                self.paragraph(x + INDENT, Font::Regular, BODY_SIZE, leading, text);
+                self.advance(leading * 0.2);
+            }
+            Block::Code(text) => {
+                let leading = CODE_SIZE * 1.3;
+                for line in text.lines() {
+                    self.paragraph(MARGIN + INDENT / 2.0, Font::Mono, CODE_SIZE, leading, line);
+                }
+                self.advance(BODY_SIZE * 0.7);
+            }
+            Block::Quote(text) => {
+                self.paragraph(MARGIN + INDENT, Font::Italic, BODY_SIZE, leading, text);
+                self.advance(leading * 0.5);
+            }
+            Block::Rule => {
+                self.advance(leading);
+                let page = self.pages.last_mut().expect("there is a page");
+                page.content.push_str(&format!(
+                    "0.5 w {MARGIN} {y:.2} m {:.2} {y:.2} l S\n",
+                    PAGE_WIDTH - MARGIN,
+                    y = self.y + leading / 2.0
+                ));
+            }
+        }
+    }
+}
+
+/// `text` broken into lines at most `width` wide, at spaces where it can
+/// be.
+fn wrap(text: &str, font: Font, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split(' ').filter(|w| !w.is_empty()) {
+        let candidate = match line.is_empty() {
+            true => word.to_string(),
+            false => format!("{line} {word}"),
+        };
+        if font.width(&candidate, size) <= width {
+            line = candidate;
+            continue;
+        }
+        if !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        // A word wider than a line is broken anywhere.
+        for c in word.chars() {
+            if !line.is_empty() && font.width(&format!("{line}{c}"), size) > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            line.push(c);
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Writes a book titled `title` with a contents page, a page per chapter
+/// start, page numbers and an outline. Returns the number of pages.
+pub fn write(title: &str, sections: &[Section], path: &Path) -> Result<usize> {
+    let leading = BODY_SIZE * 1.6;
+    let toc_lines = ((PAGE_HEIGHT - 2.0 * MARGIN) / leading) as usize - 3;
+    let toc_pages = sections.len().div_ceil(toc_lines).max(1);
+
+    // The title page, the contents, then the chapters, each on a new page.
+    let mut layout = Layout::new();
+    layout.y = PAGE_HEIGHT * 0.6;
+    for line in wrap(title, Font::Bold, 28.0, TEXT_WIDTH) {
+        layout.text(MARGIN, Font::Bold, 28.0, &line);
+        layout.y -= 36.0;
+    }
+    for _ in 0..toc_pages {
+        layout.new_page();
+    }
+    let mut starts = Vec::new();
+    for section in sections {
+        layout.new_page();
+        starts.push(layout.pages.len() - 1);
+        layout.block(&Block::Heading(1, section.title.clone()));
+        for block in &section.blocks {
+            layout.block(block);
+        }
+    }
+
+    for (i, chunk) in sections.chunks(toc_lines).enumerate() {
+        let page = &mut layout.pages[1 + i];
+        let mut lines = String::new();
+        let mut y = PAGE_HEIGHT - MARGIN - 20.0;
+        if i == 0 {
+            lines.push_str(&format!(
+                "BT /F2 20 Tf {MARGIN} {y:.2} Td {} Tj ET\n",
+                literal("Contents")
+            ));
+            y -= 2.0 * leading;
+        }
+        for (j, section) in chunk.iter().enumerate() {
+            let start = starts[i * toc_lines + j];
+            let number = (start + 1).to_string();
+            let number_width = Font::Regular.width(&number, BODY_SIZE);
+            let mut entry = section.title.clone();
+            while Font::Regular.width(&entry, BODY_SIZE) > TEXT_WIDTH - number_width - 20.0 {
+                entry.pop();
+            }
+            lines.push_str(&format!(
+                "BT /F1 {BODY_SIZE} Tf {MARGIN} {y:.2} Td {} Tj ET\nBT /F1 {BODY_SIZE} Tf {:.2} {y:.2} Td {} Tj ET\n",
+                literal(&entry),
+                PAGE_WIDTH - MARGIN - number_width,
+                literal(&number)
+            ));
+            page.links
+                .push(([MARGIN, y - 3.0, PAGE_WIDTH - MARGIN, y + BODY_SIZE], start));
+            y -= leading;
+        }
+        page.content.push_str(&lines);
+    }
+    let count = layout.pages.len();
+    for (i, page) in layout.pages.iter_mut().enumerate().skip(1) {
+        let number = (i + 1).to_string();
+        let x = (PAGE_WIDTH - Font::Regular.width(&number, 9.0)) / 2.0;
+        page.content.push_str(&format!(
+            "BT /F1 9 Tf {x:.2} {:.2} Td {} Tj ET\n",
+            MARGIN / 2.0,
+            literal(&number)
+        ));
+    }
+
+    let bytes = serialize(title, &layout.pages, sections, &starts)?;
+    std::fs::write(path, bytes).with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(count)
+}
+
+/// The PDF file: catalog, page tree, fonts, outline, then each page with
+/// its content stream and link annotations.
+fn serialize(
+    title: &str,
+    pages: &[Page],
+    sections: &[Section],
+    starts: &[usize],
+) -> Result<Vec<u8>> {
+    const CATALOG: usize = 1;
+    const PAGES: usize = 2;
+    const FONTS: usize = 3;
+    const OUTLINES: usize = 7;
+    const INFO: usize = 8;
+    let first_item = 9;
+    let first_page = first_item + sections.len();
+    // Each page is followed by its content stream and annotations.
+    let mut page_ids = Vec::new();
+    let mut next = first_page;
+    for page in pages {
+        page_ids.push(next);
+        next += 2 + page.links.len();
+    }
+
+    let mut objects: Vec<(usize, Vec<u8>)> = Vec::new();
+    let mut object = |id: usize, body: String| objects.push((id, body.into_bytes()));
+    object(
+        CATALOG,
+        format!("<< /Type /Catalog /Pages {PAGES} 0 R /Outlines {OUTLINES} 0 R /PageMode /UseOutlines >>"),
+    );
+    let kids: Vec<String> = page_ids.iter().map(|id| format!("{id} 0 R")).collect();
+    object(
+        PAGES,
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        ),
+    );
+    for (i, name) in [
+        "Helvetica",
+        "Helvetica-Bold",
+        "Helvetica-Oblique",
+        "Courier",
+    ]
+    .iter()
+    .enumerate()
+    {
+        object(
+            FONTS + i,
+            format!(
+                "<< /Type /Font /Subtype /Type1 /BaseFont /{name} /Encoding /WinAnsiEncoding >>"
+            ),
+        );
+    }
+    match sections.len() {
+        0 => object(OUTLINES, "<< /Type /Outlines /Count 0 >>".into()),
+        n => object(
+            OUTLINES,
+            format!(
+                "<< /Type /Outlines /First {first_item} 0 R /Last {} 0 R /Count {n} >>",
+                first_item + n - 1
+            ),
+        ),
+    }
+    object(
+        INFO,
+        format!(
+            "<< /Title {} /Producer (ozymandias {}) >>",
+            text_string(title),
+            env!("CARGO_PKG_VERSION")
+        ),
+    );
+    for (i, section) in sections.iter().enumerate() {
+        let id = first_item + i;
+        let mut item = format!(
+            "<< /Title {} /Parent {OUTLINES} 0 R /Dest [{} 0 R /Fit]",
+            text_string(&section.title),
+            page_ids[starts[i]]
+        );
+        if i > 0 {
+            item.push_str(&format!(" /Prev {} 0 R", id - 1));
+        }
+        if i + 1 < sections.len() {
+            item.push_str(&format!(" /Next {} 0 R", id + 1));
+        }
+        item.push_str(" >>");
+        object(id, item);
+    }
+    let fonts = "/Font << /F1 3 0 R /F2 4 0 R /F3 5 0 R /F4 6 0 R >>";
+    let mut streams = Vec::new();
+    for (page, &id) in pages.iter().zip(&page_ids) {
+        let annots: Vec<String> = (0..page.links.len())
+            .map(|i| format!("{} 0 R", id + 2 + i))
+            .collect();
+        object(
+            id,
+            format!(
+                "<< /Type /Page /Parent {PAGES} 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                 /Resources << {fonts} >> /Contents {} 0 R /Annots [{}] >>",
+                id + 1,
+                annots.join(" ")
+            ),
+        );
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&encode(&page.content))?;
+        streams.push((id + 1, encoder.finish()?));
+        for (i, (rect, target)) in page.links.iter().enumerate() {
+            object(
+                id + 2 + i,
+                format!(
+                    "<< /Type /Annot /Subtype /Link /Rect [{:.2} {:.2} {:.2} {:.2}] /Border [0 0 0] \
+                     /Dest [{} 0 R /Fit] >>",
+                    rect[0], rect[1], rect[2], rect[3], page_ids[*target]
+                ),
+            );
+        }
+    }
+    for (id, data) in streams {
+        let mut body = format!(
+            "<< /Length {} /Filter /FlateDecode >>\nstream\n",
+            data.len()
+        )
+        .into_bytes();
+        body.extend(data);
+        body.extend(b"\nendstream");
+        objects.push((id, body));
+    }
+    objects.sort_by_key(|(id, _)| *id);
+
+    let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+    for (id, body) in &objects {
+        offsets.push(out.len());
+        out.extend(format!("{id} 0 obj\n").into_bytes());
+        out.extend(body);
+        out.extend(b"\nendobj\n");
+    }
+    let xref = out.len();
+    out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+    for offset in offsets {
+        out.extend(format!("{offset:010} 00000 n \n").into_bytes());
+    }
+    out.extend(
+        format!(
+            "trailer\n<< /Size {} /Root {CATALOG} 0 R /Info {INFO} 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .into_bytes(),
+    );
+    Ok(out)
+}
+
+/// `text` as a PDF string. It is encoded as Windows-1252 when the content
+/// is written out.
+fn literal(text: &str) -> String {
+    let mut out = String::from("(");
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\t' => out.push_str("    "),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out.push(')');
+    out
+}
+
+/// `text` as a string outside content streams, such as an outline's
+/// titles: UTF-16, in hex.
+fn text_string(text: &str) -> String {
+    let hex: String = text.encode_utf16().map(|u| format!("{u:04X}")).collect();
+    format!("<FEFF{hex}>")
+}
+
+/// Windows-1252, which the fonts are encoded in; other characters become
+/// `?`.
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c as u32 {
+            n @ (0..=0x7f | 0xa0..=0xff) => n as u8,
+            _ => match c {
+                '€' => 0x80,
+                '‚' => 0x82,
+                '„' => 0x84,
+                '…' => 0x85,
+                '†' => 0x86,
+                '‡' => 0x87,
+                '‰' => 0x89,
+                '‹' => 0x8b,
+                '‘' => 0x91,
+                '’' => 0x92,
+                '“' => 0x93,
+                '”' => 0x94,
+                '•' => 0x95,
+                '–' => 0x96,
+                '—' => 0x97,
+                '™' => 0x99,
+                '›' => 0x9b,
+                _ => b'?',
+            },
+        })
+        .collect()
+}
@@ -0,0 +1,417 @@
+//! A static HTML site, to publish documents as a digital garden: a page
+//! per document with the pages linking to it, a page per tag, an index,
+//! and a search index that runs in the browser, also from `file://`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use pulldown_cmark::{html::push_html, Options, Parser};
+use serde_json::json;
+
+use super::{split_frontmatter, Assets, Found, Names, Targets};
+use crate::links::{Link, Resolver};
+use crate::parser::strip_tags;
+use crate::storage::BlobStore;
+use crate::tags;
+use crate::types::{Document, DocumentId, DocumentKind};
+
+pub const ASSETS_DIR: &str = "assets";
+pub const TAGS_DIR: &str = "tags";
+/// Characters of each document's text in the search index.
+const MAX_INDEXED: usize = 20_000;
+
+const STYLE: &str = "\
+body{max-width:46rem;margin:2rem auto;padding:0 1rem;font:17px/1.6 system-ui,sans-serif;color:#222}
+header{display:flex;gap:1rem;align-items:baseline;border-bottom:1px solid #ddd;margin-bottom:1.5rem}
+header a.home{font-weight:bold;text-decoration:none}
+a{color:#1a5fb4}a.missing{color:#999}
+.meta{color:#666;font-size:.9em}
+.tag{display:inline-block;background:#eef;border-radius:3px;padding:0 .4em;margin:0 .2em .2em 0;text-decoration:none}
+pre{background:#f6f6f6;padding:.8em;overflow:auto}code{font-size:.9em}
+img{max-width:100%}
+table{border-collapse:collapse}td,th{border:1px solid #ddd;padding:.2em .5em}
+aside{border-top:1px solid #ddd;margin-top:2rem}
+#search{width:100%;font-size:1em;padding:.4em}
+#results li small{color:#666}
+";
+
+/// Finds pages whose title or text contains every word typed, titles
+/// first. The index is `SEARCH_INDEX`, set by `search-index.js`.
+const SEARCH: &str = "\
+const input = document.getElementById('search');
+const results = document.getElementById('results');
+input.addEventListener('input', () => {
+  const words = input.value.toLowerCase().split(/\\s+/).filter(w => w);
+  results.innerHTML = '';
+  if (!words.length) return;
+  const hits = [];
+  for (const page of SEARCH_INDEX) {
+    const title = page.title.toLowerCase();
+    const text = title + ' ' + page.tags.join(' ') + ' ' + page.text.toLowerCase();
+    if (!words.every(w => text.includes(w))) continue;
+    hits.push([words.filter(w => title.includes(w)).length, page]);
+  }
+  hits.sort((a, b) => b[0] - a[0]);
+  for (const [, page] of hits.slice(0, 50)) {
+    const li = document.createElement('li');
+    const a = document.createElement('a');
+    a.href = page.url;
+    a.textContent = page.title;
+    li.append(a);
+    if (page.tags.length) {
+      const small = document.createElement('small');
+      small.textContent = ' ' + page.tags.join(', ');
+      li.append(small);
+    }
+    results.append(li);
+  }
+});
+";
+
+/// Writes `docs` as a site into `dir`, and returns how many attachments
+/// were written with them.
+pub fn export(docs: &[Document], blobs: &BlobStore, dir: &Path) -> Result<usize> {
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let mut names = Names::new(docs);
+    let mut taken = HashSet::new();
+    for doc in docs {
+        let base = match slug(&doc.title) {
+            s if s.is_empty() => doc.id.to_string(),
+            s => s,
+        };
+        let mut name = base.clone();
+        let mut n = 1;
+        // `index` is the site's own page.
+        while name == "index" || !taken.insert(name.clone()) {
+            n += 1;
+            name = format!("{base}-{n}");
+        }
+        names.rename(&doc.id, name);
+    }
+    let targets = Targets::new(docs, &names);
+    let resolver = Resolver::new(docs);
+    let titles: HashMap<&DocumentId, &str> =
+        docs.iter().map(|d| (&d.id, d.title.as_str())).collect();
+    let mut backlinks: HashMap<DocumentId, Vec<&Document>> = HashMap::new();
+    for doc in docs {
+        let linked: HashSet<DocumentId> = doc
+            .links
+            .iter()
+            .filter_map(|link| resolver.resolve(doc, link))
+            .filter(|id| *id != doc.id)
+            .collect();
+        for id in linked {
+            backlinks.entry(id).or_default().push(doc);
+        }
+    }
+
+    let mut assets = Assets::new(dir.join(ASSETS_DIR));
+    let mut tagged: BTreeMap<String, Vec<&Document>> = BTreeMap::new();
+    let mut index = Vec::new();
+    for doc in docs {
+        let name = names.get(&doc.id).expect("every document is named");
+        let body = match doc.kind {
+            DocumentKind::Markdown => {
+                let (_, body) = split_frontmatter(&doc.content);
+                render(&markdown(doc, body, &targets, blobs, &mut assets)?)
+            }
+            DocumentKind::Text | DocumentKind::Html => paragraphs(&doc.content),
+        };
+        let mut page = format!(
+            "<h1>{}</h1>\n<p class=\"meta\">{}",
+            escape(&doc.title),
+            doc.added.format("%Y-%m-%d")
+        );
+        if let Some(source) = doc.source.as_deref().filter(|s| s.starts_with("http")) {
+            page.push_str(&format!(" · <a href=\"{}\">source</a>", escape(source)));
+        }
+        page.push_str("</p>\n");
+        if !doc.tags.is_empty() {
+            page.push_str(&format!("<p>{}</p>\n", tag_links(&doc.tags, "")));
+        }
+        // A body starting with the title repeats it.
+        let heading = format!("<h1>{}</h1>", escape(&doc.title));
+        page.push_str(body.trim_start().strip_prefix(&heading).unwrap_or(&body));
+
+        let mut related = Vec::new();
+        for relation in &doc.relations {
+            if let Some(target) = names.get(&relation.target) {
+                let title = titles.get(&relation.target).copied().unwrap_or(target);
+                related.push(format!(
+                    "<li>{}: <a href=\"{}.html\">{}</a></li>",
+                    relation.kind.name(),
+                    href(target),
+                    escape(title)
+                ));
+            }
+        }
+        if !related.is_empty() {
+            page.push_str(&format!(
+                "<aside><h2>Related</h2><ul>{}</ul></aside>\n",
+                related.join("")
+            ));
+        }
+        if let Some(linking) = backlinks.get(&doc.id) {
+            page.push_str("<aside><h2>Linked from</h2><ul>");
+            for from in linking {
+                let from_name = names.get(&from.id).expect("every document is named");
+                page.push_str(&format!(
+                    "<li><a href=\"{}.html\">{}</a></li>",
+                    href(from_name),
+                    escape(&from.title)
+                ));
+            }
+            page.push_str("</ul></aside>\n");
+        }
+        write(dir, &format!("{name}.html"), &html(&doc.title, "", &page))?;
+
+        for tag in &doc.tags {
+            for tag in tags::ancestors(tag) {
+                let docs = tagged.entry(tag.to_string()).or_default();
+                if !docs.iter().any(|d| d.id == doc.id) {
+                    docs.push(doc);
+                }
+            }
+        }
+        let text: String = strip_tags(&body).chars().take(MAX_INDEXED).collect();
+        index.push(json!({
+            "title": doc.title,
+            "url": format!("{}.html", href(name)),
+            "tags": doc.tags,
+            "text": text,
+        }));
+    }
+
+    for (tag, docs) in &tagged {
+        let root = "../".repeat(tag.split('/').count());
+        let mut page = format!("<h1>#{}</h1>\n", escape(tag));
+        let narrower: Vec<&String> = tagged
+            .keys()
+            .filter(|t| tags::parent(t) == Some(tag.as_str()))
+            .collect();
+        if !narrower.is_empty() {
+            let narrower: Vec<String> = narrower.into_iter().cloned().collect();
+            page.push_str(&format!("<p>{}</p>\n", tag_links(&narrower, &root)));
+        }
+        page.push_str(&document_list(docs, &names, &root));
+        write(
+            dir,
+            &format!("{}.html", tag_path(tag)),
+            &html(tag, &root, &page),
+        )?;
+    }
+
+    let mut sorted: Vec<&Document> = docs.iter().collect();
+    sorted.sort_by_key(|d| d.title.to_lowercase());
+    let mut page = String::from(
+        "<input id=\"search\" type=\"search\" placeholder=\"Search\" autofocus>\n<ul id=\"results\"></ul>\n",
+    );
+    if !tagged.is_empty() {
+        let top: Vec<String> = tagged
+            .keys()
+            .filter(|t| tags::parent(t).is_none())
+            .cloned()
+            .collect();
+        page.push_str(&format!("<h2>Tags</h2>\n<p>{}</p>\n", tag_links(&top, "")));
+    }
+    page.push_str(&format!(
+        "<h2>Documents</h2>\n{}",
+        document_list(&sorted, &names, "")
+    ));
+    page.push_str(
+        "<script src=\"search-index.js\"></script>\n<script src=\"search.js\"></script>\n",
+    );
+    write(dir, "index.html", &html("Index", "", &page))?;
+    write(
+        dir,
+        "search-index.js",
+        &format!("const SEARCH_INDEX = {};\n", serde_json::to_string(&index)?),
+    )?;
+    write(dir, "search.js", SEARCH)?;
+    write(dir, "style.css", STYLE)?;
+    Ok(assets.len())
+}
+
+/// Markdown with links to other documents pointing at their pages, links
+/// to documents outside the site as plain text, and attachments and local
+/// files it refers to copied into the assets.
+fn markdown(
+    doc: &Document,
+    body: &str,
+    targets: &Targets,
+    blobs: &BlobStore,
+    assets: &mut Assets,
+) -> Result<String> {
+    let mut failed = None;
+    let body = super::rewrite_links(body, |found| match found {
+        Found::Wiki { target, rest } => {
+            let label = rest
+                .split_once('|')
+                .map_or_else(|| format!("{target}{rest}"), |(_, l)| l.to_string());
+            Some(
+                match targets.name(doc, Link::Wiki(target.trim().to_string())) {
+                    Some(name) => format!("[{label}]({}.html)", href(name)),
+                    None => format!("<span class=\"missing\">{}</span>", escape(&label)),
+                },
+            )
+        }
+        Found::Markdown {
+            text,
+            target,
+            embed,
+        } => {
+            let target = target.split_whitespace().next()?;
+            let target = target.trim_matches(|c| c == '<' || c == '>');
+            let url = if let Some(hash) = target.strip_prefix("blob:") {
+                let name = doc
+                    .attachments
+                    .iter()
+                    .find(|a| a.blob == hash)
+                    .map_or(hash, |a| a.name.as_str());
+                match assets.blob(blobs, hash, name) {
+                    Ok(file) => format!("{ASSETS_DIR}/{}", href(&file?)),
+                    Err(e) => {
+                        failed.get_or_insert(e);
+                        return None;
+                    }
+                }
+            } else if let Some(name) = targets.name(doc, Link::Href(target.to_string())) {
+                format!("{}.html", href(name))
+            } else {
+                let file = Assets::local_file(doc, target)?;
+                match assets.copy(&file) {
+                    Ok(name) => format!("{ASSETS_DIR}/{}", href(&name)),
+                    Err(e) => {
+                        failed.get_or_insert(e);
+                        return None;
+                    }
+                }
+            };
+            let bang = if embed { "!" } else { "" };
+            Some(format!("{bang}[{text}](<{url}>)"))
+        }
+    });
+    match failed {
+        Some(e) => Err(e),
+        None => Ok(body),
+    }
+}
+
+fn render(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_FOOTNOTES;
+    let mut out = String::new();
+    push_html(&mut out, Parser::new_ext(markdown, options));
+    out
+}
+
+/// Plain text, such as clipped pages, as paragraphs.
+fn paragraphs(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>\n", escape(p).replace('\n', "<br>")))
+        .collect()
+}
+
+/// A page of the site, at `root` relative to the site's root.
+fn html(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<link rel=\"stylesheet\" href=\"{root}style.css\">\n</head>\n<body>\n\
+         <header><a class=\"home\" href=\"{root}index.html\">Index</a></header>\n<main>\n{body}</main>\n</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+fn document_list(docs: &[&Document], names: &Names, root: &str) -> String {
+    let mut out = String::from("<ul>\n");
+    for doc in docs {
+        let name = names.get(&doc.id).expect("every document is named");
+        out.push_str(&format!(
+            "<li><a href=\"{root}{}.html\">{}</a> <span class=\"meta\">{}</span></li>\n",
+            href(name),
+            escape(&doc.title),
+            doc.added.format("%Y-%m-%d")
+        ));
+    }
+    out.push_str("</ul>\n");
+    out
+}
+
+fn tag_links(tags: &[String], root: &str) -> String {
+    tags.iter()
+        .map(|tag| {
+            format!(
+                "<a class=\"tag\" href=\"{root}{}.html\">#{}</a>",
+                href(&tag_path(tag)),
+                escape(tag)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Where a tag's page is, below the site's root: `tags/project/design`
+/// for `project/design`.
+fn tag_path(tag: &str) -> String {
+    let segments: Vec<String> = tag
+        .split('/')
+        .map(|s| match slug(s) {
+            s if s.is_empty() => "-".to_string(),
+            s => s,
+        })
+        .collect();
+    format!("{TAGS_DIR}/{}", segments.join("/"))
+}
+
+/// `title` as a page name: lowercase letters and digits, with runs of
+/// anything else as a single `-`.
+fn slug(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').chars().take(80).collect()
+}
+
+/// A path as a URL, percent-encoding what URLs do not allow as is.
+fn href(path: &str) -> String {
+    let mut out = String::new();
+    for c in path.chars() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '~' | '/' => out.push(c),
+            c => {
+                let mut bytes = [0; 4];
+                for b in c.encode_utf8(&mut bytes).bytes() {
+                    out.push_str(&format!("%{b:02X}"));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn write(dir: &Path, name: &str, contents: &str) -> Result<()> {
+    let path = dir.join(name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    std::fs::write(&path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
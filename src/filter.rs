@@ -0,0 +1,470 @@
+//! Field filters such as `tag:history` or `added:2024-01..2024-06` that
+//! narrow down any query. Besides `added:`, dates can be filtered by when
+//! documents were `created:` or `published:`, by the dates they
+//! `mentions:`, or by any of these with `date:` (see [`crate::dates`]).
+//! `meta:key` keeps documents with that metadata and `meta:key=value`
+//! those whose value for it contains `value`. `type:` takes a kind of
+//! document, such as `markdown`, or a file type, such as `pdf`.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Months, NaiveDate, TimeZone, Utc};
+
+use crate::dates;
+use crate::entities::EntityKind;
+use crate::tags;
+use crate::types::{Document, DocumentKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    Tag(String),
+    Type(DocumentKind),
+    /// A file type, as [`Document::file_type`] gives it.
+    FileType(String),
+    Source(String),
+    Added(DateRange),
+    Date(DateField, DateRange),
+    /// A mentioned entity by name, of any kind when `kind` is unset.
+    Entity(Option<EntityKind>, String),
+    /// A metadata key, with part of its value when set, in lowercase.
+    Meta(String, Option<String>),
+}
+
+/// Which of a document's dates a `Date` filter looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateField {
+    Created,
+    Published,
+    Mentioned,
+    /// Any of the others.
+    Any,
+}
+
+/// A half-open `[start, end)` interval of time; either side may be open.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl Filter {
+    /// Parses a single filter; unknown fields yield `None` so that words
+    /// which merely contain a colon, such as URLs, stay searchable text.
+    pub fn parse(field: &str, value: &str) -> Result<Option<Self>> {
+        let field = field.to_ascii_lowercase();
+        // `entity:person:"Ada Lovelace"` arrives as field `entity:person`,
+        // `entity:person:ada` as value `person:ada`.
+        if let Some(rest) = field.strip_prefix("entity") {
+            let (kind, name) = match rest.strip_prefix(':') {
+                Some(kind) => (Some(kind), value),
+                None if rest.is_empty() => match value.split_once(':') {
+                    Some((kind, name)) if EntityKind::parse(kind).is_ok() => (Some(kind), name),
+                    _ => (None, value),
+                },
+                None => return Ok(None),
+            };
+            let kind = kind.map(EntityKind::parse).transpose()?;
+            return Ok(Some(Filter::Entity(kind, name.to_lowercase())));
+        }
+        Ok(Some(match field.as_str() {
+            "tag" => Filter::Tag(tags::normalize(value)?),
+            "type" => parse_type(value)?,
+            "source" => Filter::Source(value.to_lowercase()),
+            "added" => Filter::Added(
+                DateRange::parse(value, Utc::now())
+                    .with_context(|| format!("invalid date filter added:{value}"))?,
+            ),
+            "created" | "published" | "mentions" | "date" => {
+                let date_field = match field.as_str() {
+                    "created" => DateField::Created,
+                    "published" => DateField::Published,
+                    "mentions" => DateField::Mentioned,
+                    _ => DateField::Any,
+                };
+                let range = DateRange::parse(value, Utc::now())
+                    .with_context(|| format!("invalid date filter {field}:{value}"))?;
+                Filter::Date(date_field, range)
+            }
+            "meta" => match value.split_once('=') {
+                Some((key, part)) => Filter::Meta(key.to_string(), Some(part.to_lowercase())),
+                None => Filter::Meta(value.to_string(), None),
+            },
+            _ => return Ok(None),
+        }))
+    }
+
+    pub fn matches(&self, doc: &Document) -> bool {
+        match self {
+            Filter::Tag(tag) => doc.tags.iter().any(|t| tags::is_within(t, tag)),
+            Filter::Type(kind) => doc.kind == *kind,
+            Filter::FileType(extension) => doc.file_type().as_ref() == Some(extension),
+            Filter::Source(source) => {
+                let Some(actual) = doc.source.as_deref() else {
+                    return false;
+                };
+                let is_web = actual.starts_with("http://") || actual.starts_with("https://");
+                match source.as_str() {
+                    "web" => is_web,
+                    "file" => !is_web,
+                    needle => actual.to_lowercase().contains(needle),
+                }
+            }
+            Filter::Added(range) => range.contains(doc.added),
+            Filter::Date(field, range) => {
+                let created = || range.contains(dates::created(doc).start);
+                let published = || dates::published(doc).is_some_and(|d| range.contains(d.start));
+                let mentioned = || {
+                    dates::mentioned(doc)
+                        .iter()
+                        .any(|d| range.contains(d.start))
+                };
+                match field {
+                    DateField::Created => created(),
+                    DateField::Published => published(),
+                    DateField::Mentioned => mentioned(),
+                    DateField::Any => created() || published() || mentioned(),
+                }
+            }
+            Filter::Entity(kind, name) => doc
+                .entities
+                .iter()
+                .any(|e| kind.is_none_or(|k| e.kind == k) && e.name.to_lowercase() == *name),
+            Filter::Meta(key, part) => doc.metadata.get(key).is_some_and(|value| {
+                part.as_ref()
+                    .is_none_or(|part| value.to_lowercase().contains(part.as_str()))
+            }),
+        }
+    }
+}
+
+fn parse_type(value: &str) -> Result<Filter> {
+    let value = value.to_ascii_lowercase();
+    Ok(match value.as_str() {
+        "markdown" | "md" => Filter::Type(DocumentKind::Markdown),
+        "text" | "txt" => Filter::Type(DocumentKind::Text),
+        "html" | "htm" => Filter::Type(DocumentKind::Html),
+        extension if !extension.is_empty() && extension.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            Filter::FileType(value)
+        }
+        other => bail!(
+            "unknown document type {other:?} (expected markdown, text, html or a file type such as pdf)"
+        ),
+    })
+}
+
+impl DateRange {
+    /// Accepts `A..B`, `A..`, `..B`, `>A`, `<A` or a single period `A`, where
+    /// each bound is `YYYY`, `YYYY-MM`, `YYYY-MM-DD` or a relative age such
+    /// as `7d`, `2w`, `3m` or `1y` counted back from `now`.
+    pub fn parse(value: &str, now: DateTime<Utc>) -> Result<Self> {
+        if let Some((a, b)) = value.split_once("..") {
+            return Ok(DateRange {
+                start: (!a.is_empty())
+                    .then(|| bound(a, now))
+                    .transpose()?
+                    .map(|r| r.0),
+                end: (!b.is_empty())
+                    .then(|| bound(b, now))
+                    .transpose()?
+                    .map(|r| r.1),
+            });
+        }
+        if let Some(a) = value.strip_prefix('>') {
+            return Ok(DateRange {
+                start: Some(bound(a, now)?.0),
+                end: None,
+            });
+        }
+        if let Some(a) = value.strip_prefix('<') {
+            return Ok(DateRange {
+                start: None,
+                end: Some(bound(a, now)?.0),
+            });
+        }
+        let (start, end) = bound(value, now)?;
+        Ok(DateRange {
+            start: Some(start),
+            end: Some(end),
+        })
+    }
+
+    pub fn contains(&self, t: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|s| t >= s) && self.end.is_none_or(|e| t < e)
+    }
+}
+
+/// Resolves a bound to the period it names. A relative age names the
+/// instant `now - age`, so it starts and ends there.
+fn bound(s: &str, now: DateTime<Utc>) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+    if let Some(age) = relative_age(s) {
+        let t = now
+            .checked_sub_signed(age?)
+            .with_context(|| format!("age {s:?} reaches too far back"))?;
+        return Ok((t, t));
+    }
+    let parts: Vec<&str> = s.split('-').collect();
+    let num = |p: &str| p.parse::<u32>().with_context(|| format!("bad date {s:?}"));
+    let (start, len) = match parts.as_slice() {
+        [y] => (
+            NaiveDate::from_ymd_opt(num(y)? as i32, 1, 1),
+            Months::new(12),
+        ),
+        [y, m] => (
+            NaiveDate::from_ymd_opt(num(y)? as i32, num(m)?, 1),
+            Months::new(1),
+        ),
+        [y, m, d] => {
+            let day = NaiveDate::from_ymd_opt(num(y)? as i32, num(m)?, num(d)?)
+                .with_context(|| format!("bad date {s:?}"))?;
+            let start = midnight(day);
+            let end = start
+                .checked_add_signed(Duration::days(1))
+                .with_context(|| format!("bad date {s:?}"))?;
+            return Ok((start, end));
+        }
+        _ => bail!("bad date {s:?} (expected YYYY, YYYY-MM, YYYY-MM-DD or an age like 7d)"),
+    };
+    let start = start.with_context(|| format!("bad date {s:?}"))?;
+    let end = start
+        .checked_add_months(len)
+        .with_context(|| format!("bad date {s:?}"))?;
+    Ok((midnight(start), midnight(end)))
+}
+
+fn relative_age(s: &str) -> Option<Result<Duration>> {
+    let unit = s.chars().last().filter(char::is_ascii_alphabetic)?;
+    let n: i64 = s[..s.len() - unit.len_utf8()].parse().ok()?;
+    let days = match unit {
+        'd' => Some(n),
+        'w' => n.checked_mul(7),
+        'm' => n.checked_mul(30),
+        'y' => n.checked_mul(365),
+        _ => return Some(Err(anyhow::anyhow!("unknown unit {unit:?} in {s:?}"))),
+    };
+    Some(
+        days.and_then(Duration::try_days)
+            .with_context(|| format!("age {s:?} is too long")),
+    )
+}
+
+fn midnight(day: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&day.and_hms_opt(0, 0, 0).expect("midnight exists"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Entity;
+    use crate::types::DocumentId;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 15, 12, 0, 0).unwrap()
+    }
+
+    fn day(y: i32, m: u32, d: u32) -> DateTime<Utc> {
+        midnight(NaiveDate::from_ymd_opt(y, m, d).unwrap())
+    }
+
+    fn range(value: &str) -> DateRange {
+        DateRange::parse(value, now()).unwrap()
+    }
+
+    fn doc() -> Document {
+        Document::new(
+            DocumentId::derive("doc"),
+            "Doc".to_string(),
+            DocumentKind::Markdown,
+            String::new(),
+            Some("https://example.com/page".to_string()),
+        )
+    }
+
+    #[test]
+    fn periods_span_their_whole_length() {
+        assert_eq!(range("2024").start, Some(day(2024, 1, 1)));
+        assert_eq!(range("2024").end, Some(day(2025, 1, 1)));
+        assert_eq!(range("2024-02").end, Some(day(2024, 3, 1)));
+        assert_eq!(range("2024-02-29").end, Some(day(2024, 3, 1)));
+        assert_eq!(range("2024-12").end, Some(day(2025, 1, 1)));
+    }
+
+    #[test]
+    fn ranges_include_their_last_period() {
+        let r = range("2024-01..2024-06");
+        assert_eq!(
+            (r.start, r.end),
+            (Some(day(2024, 1, 1)), Some(day(2024, 7, 1)))
+        );
+        assert!(r.contains(day(2024, 6, 30)));
+        assert!(!r.contains(day(2024, 7, 1)));
+        assert_eq!(range("2020..").end, None);
+        assert_eq!(range("..2020").start, None);
+        assert_eq!(range("..2020").end, Some(day(2021, 1, 1)));
+    }
+
+    #[test]
+    fn comparisons_are_exclusive_of_later_dates() {
+        assert_eq!(range(">2024").start, Some(day(2024, 1, 1)));
+        assert_eq!(range(">2024").end, None);
+        assert_eq!(range("<2024").end, Some(day(2024, 1, 1)));
+        assert!(!range("<2024").contains(day(2024, 1, 1)));
+    }
+
+    #[test]
+    fn ages_count_back_from_now() {
+        let r = range("7d..");
+        assert_eq!(r.start, Some(now() - Duration::days(7)));
+        assert_eq!(range("2w..").start, Some(now() - Duration::days(14)));
+        assert_eq!(range("3m..").start, Some(now() - Duration::days(90)));
+        assert_eq!(range("..1y").end, Some(now() - Duration::days(365)));
+    }
+
+    #[test]
+    fn bad_dates_are_errors() {
+        for value in [
+            "2023-02-29",
+            "2024-13",
+            "2024-1-2-3",
+            "yesterday",
+            "7x",
+            "20a4",
+            "",
+        ] {
+            assert!(DateRange::parse(value, now()).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn out_of_range_dates_are_errors_not_panics() {
+        for value in [
+            "4000000000",
+            "262143",
+            "262142-12..",
+            "9223372036854775807d",
+            "9223372036854775807y",
+            "2000000000000d",
+            "1000000y",
+            "99999999999w..",
+        ] {
+            assert!(DateRange::parse(value, now()).is_err(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_not_filters() {
+        assert!(Filter::parse("https", "//example.com").unwrap().is_none());
+        assert!(Filter::parse("entitys", "x").unwrap().is_none());
+    }
+
+    #[test]
+    fn fields_parse_case_insensitively() {
+        assert_eq!(
+            Filter::parse("TAG", "To Read").unwrap(),
+            Some(Filter::Tag("to-read".into()))
+        );
+        assert_eq!(
+            Filter::parse("type", "md").unwrap(),
+            Some(Filter::Type(DocumentKind::Markdown))
+        );
+        assert_eq!(
+            Filter::parse("type", "PDF").unwrap(),
+            Some(Filter::FileType("pdf".into()))
+        );
+        assert!(Filter::parse("type", "tar.gz").is_err());
+        assert!(Filter::parse("added", "soon").is_err());
+    }
+
+    #[test]
+    fn entity_filters_take_an_optional_kind() {
+        let person = Filter::Entity(Some(EntityKind::Person), "ada lovelace".into());
+        assert_eq!(
+            Filter::parse("entity:person", "Ada Lovelace").unwrap(),
+            Some(person.clone())
+        );
+        assert_eq!(
+            Filter::parse("entity", "person:Ada Lovelace").unwrap(),
+            Some(person)
+        );
+        assert_eq!(
+            Filter::parse("entity", "Ada").unwrap(),
+            Some(Filter::Entity(None, "ada".into()))
+        );
+        assert!(Filter::parse("entity:planet", "Mars").is_err());
+    }
+
+    #[test]
+    fn tags_match_below_themselves() {
+        let mut doc = doc();
+        doc.tags.push("history/rome".into());
+        let matches = |tag: &str| Filter::Tag(tag.into()).matches(&doc);
+        assert!(matches("history"));
+        assert!(matches("history/rome"));
+        assert!(!matches("hist"));
+        assert!(!matches("history/rome/empire"));
+    }
+
+    #[test]
+    fn sources_match_web_file_or_part() {
+        let doc = doc();
+        let matches = |s: &str| Filter::parse("source", s).unwrap().unwrap().matches(&doc);
+        assert!(matches("web"));
+        assert!(!matches("file"));
+        assert!(matches("Example.COM"));
+        assert!(!matches("other.org"));
+    }
+
+    #[test]
+    fn meta_matches_keys_and_parts_of_values() {
+        let mut doc = doc();
+        doc.metadata.insert("camera".into(), "Fujifilm X100".into());
+        let matches = |s: &str| Filter::parse("meta", s).unwrap().unwrap().matches(&doc);
+        assert!(matches("camera"));
+        assert!(matches("camera=fuji"));
+        assert!(!matches("camera=canon"));
+        assert!(!matches("lens"));
+    }
+
+    #[test]
+    fn entities_match_by_kind_and_name() {
+        let mut doc = doc();
+        doc.entities.push(Entity {
+            kind: EntityKind::Place,
+            name: "Rome".into(),
+        });
+        assert!(Filter::Entity(None, "rome".into()).matches(&doc));
+        assert!(Filter::Entity(Some(EntityKind::Place), "rome".into()).matches(&doc));
+        assert!(!Filter::Entity(Some(EntityKind::Person), "rome".into()).matches(&doc));
+    }
+
+    #[test]
+    fn added_dates_are_filtered() {
+        let mut doc = doc();
+        doc.added = day(2024, 3, 5);
+        assert!(Filter::Added(range("2024-03")).matches(&doc));
+        assert!(!Filter::Added(range("2024-04..")).matches(&doc));
+    }
+
+    #[test]
+    fn file_types_match_the_source_or_first_attachment() {
+        let pdf = || Filter::parse("type", "pdf").unwrap().unwrap();
+        let mut paper = doc();
+        assert!(!pdf().matches(&paper));
+        paper.source = Some("/home/me/papers/Smith 2020.PDF".into());
+        assert!(pdf().matches(&paper));
+        paper.source = Some("https://example.com/paper.pdf?download=1".into());
+        assert!(pdf().matches(&paper));
+
+        let mut imported = doc();
+        imported.source = Some("zotero/ABCD1234".into());
+        imported.attachments.push(crate::types::Attachment {
+            name: "smith2020.pdf".into(),
+            mime: Some("application/pdf".into()),
+            blob: "0".repeat(64),
+            thumbnail: None,
+        });
+        assert!(pdf().matches(&imported));
+        assert!(!Filter::parse("type", "epub")
+            .unwrap()
+            .unwrap()
+            .matches(&imported));
+    }
+}
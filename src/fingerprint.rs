@@ -0,0 +1,260 @@
+//! Content fingerprints for finding exact and near-duplicate documents.
+//!
+//! Near duplicates are found with MinHash: each document becomes the set
+//! of its three-word shingles, and the share of equal minimum hashes
+//! between two signatures estimates the Jaccard similarity of the sets.
+//! Locality-sensitive hashing over bands of the signature keeps clustering
+//! from comparing every pair of documents.
+
+use std::collections::{BTreeMap, HashMap};
+
+use sha2::{Digest, Sha256};
+
+use crate::index::{analyze, language_of};
+use crate::types::{Document, DocumentId};
+
+/// Number of hash functions in a signature.
+const HASHES: usize = 64;
+/// Signature rows per LSH band. With 16 bands of 4 rows, pairs above about
+/// 0.5 similarity almost always share a band.
+const ROWS: usize = 4;
+const SHINGLE: usize = 3;
+
+/// SHA-256 of the content, equal for exact duplicates.
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// The MinHash signature of a document's content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature(Vec<u64>);
+
+impl Signature {
+    pub fn of(doc: &Document) -> Self {
+        let words = analyze(&doc.content, language_of(doc));
+        let shingles: Vec<u64> = if words.len() < SHINGLE {
+            vec![hash_words(&words)]
+        } else {
+            words.windows(SHINGLE).map(hash_words).collect()
+        };
+        Signature(
+            (0..HASHES as u64)
+                .map(|seed| {
+                    shingles
+                        .iter()
+                        .map(|s| mix(s ^ mix(seed)))
+                        .min()
+                        .unwrap_or(u64::MAX)
+                })
+                .collect(),
+        )
+    }
+
+    /// Estimated Jaccard similarity of the two shingle sets.
+    pub fn similarity(&self, other: &Signature) -> f32 {
+        let equal = self.0.iter().zip(&other.0).filter(|(a, b)| a == b).count();
+        equal as f32 / HASHES as f32
+    }
+
+    fn bands(&self) -> impl Iterator<Item = (usize, &[u64])> {
+        self.0.chunks(ROWS).enumerate()
+    }
+}
+
+/// Documents that are all duplicates of one another, directly or through
+/// other members.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// Oldest first, so the first member is the likely original.
+    pub members: Vec<DocumentId>,
+    /// The lowest similarity of the pairs that joined the cluster.
+    pub similarity: f32,
+}
+
+/// Groups documents whose estimated similarity reaches `threshold`,
+/// largest clusters first.
+pub fn clusters(docs: &[Document], threshold: f32) -> Vec<Cluster> {
+    let signatures: Vec<Signature> = docs.iter().map(Signature::of).collect();
+    let mut buckets: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+    for (i, signature) in signatures.iter().enumerate() {
+        for band in signature.bands() {
+            buckets.entry(band).or_default().push(i);
+        }
+    }
+
+    let mut parent: Vec<usize> = (0..docs.len()).collect();
+    let mut weakest: HashMap<usize, f32> = HashMap::new();
+    for bucket in buckets.values().filter(|b| b.len() > 1) {
+        for (n, &a) in bucket.iter().enumerate() {
+            for &b in &bucket[n + 1..] {
+                let similarity = signatures[a].similarity(&signatures[b]);
+                if similarity < threshold {
+                    continue;
+                }
+                let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+                let low = [weakest.get(&ra), weakest.get(&rb)]
+                    .into_iter()
+                    .flatten()
+                    .fold(similarity, |m, s| m.min(*s));
+                if ra != rb {
+                    parent[rb] = ra;
+                }
+                weakest.insert(ra, low);
+            }
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..docs.len() {
+        let root = find(&mut parent, i);
+        groups.entry(root).or_default().push(i);
+    }
+    let mut out: Vec<Cluster> = groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(root, mut members)| {
+            members.sort_by_key(|&i| (docs[i].added, docs[i].id.clone()));
+            Cluster {
+                members: members.into_iter().map(|i| docs[i].id.clone()).collect(),
+                similarity: weakest.get(&root).copied().unwrap_or(1.0),
+            }
+        })
+        .collect();
+    out.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then_with(|| a.members.cmp(&b.members))
+    });
+    out
+}
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+fn hash_words(words: &[String]) -> u64 {
+    words.iter().fold(0xcbf2_9ce4_8422_2325, |h, w| {
+        w.bytes()
+            .chain(std::iter::once(0))
+            .fold(h, |h, b| (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+    })
+}
+
+/// The SplitMix64 finalizer, used to derive independent hash functions.
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    use crate::transform::language;
+    use crate::types::DocumentKind;
+
+    /// A hundred distinct words, with those at `replaced` positions changed.
+    fn text(replaced: impl IntoIterator<Item = usize>) -> String {
+        let mut words: Vec<String> = (0..100).map(|i| format!("word{i}")).collect();
+        for i in replaced {
+            words[i] = format!("other{i}");
+        }
+        words.join(" ")
+    }
+
+    fn doc(key: &str, content: &str, age: i64) -> Document {
+        let mut doc = Document::new(
+            DocumentId::derive(key),
+            key.to_string(),
+            DocumentKind::Text,
+            content.to_string(),
+            None,
+        );
+        doc.added = Utc::now() - Duration::days(age);
+        doc.metadata
+            .insert(language::KEY.to_string(), "en".to_string());
+        doc
+    }
+
+    fn similarity(a: &str, b: &str) -> f32 {
+        Signature::of(&doc("a", a, 0)).similarity(&Signature::of(&doc("b", b, 0)))
+    }
+
+    #[test]
+    fn content_hash_is_sha256() {
+        assert_eq!(
+            content_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn similarity_estimates_shared_shingles() {
+        assert_eq!(similarity(&text([]), &text([])), 1.0);
+        assert!(similarity(&text([]), &text([50])) > 0.8);
+        let unrelated = (0..100).map(|i| format!("else{i}")).collect::<Vec<_>>();
+        assert!(similarity(&text([]), &unrelated.join(" ")) < 0.1);
+    }
+
+    #[test]
+    fn similarity_ignores_case_and_punctuation() {
+        let a = text([]);
+        let b = a.to_uppercase().replace(' ', ", ");
+        assert_eq!(similarity(&a, &b), 1.0);
+    }
+
+    #[test]
+    fn short_documents_have_signatures() {
+        assert_eq!(similarity("two words", "two words"), 1.0);
+        assert!(similarity("two words", "other words") < 0.5);
+    }
+
+    #[test]
+    fn clusters_group_near_duplicates_oldest_first() {
+        let docs = [
+            doc("copy", &text([3]), 1),
+            doc("unrelated", "nothing like the others at all", 5),
+            doc("original", &text([]), 10),
+        ];
+        let clusters = clusters(&docs, 0.8);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(
+            clusters[0].members,
+            vec![docs[2].id.clone(), docs[0].id.clone()]
+        );
+        assert!(clusters[0].similarity >= 0.8);
+    }
+
+    #[test]
+    fn clusters_join_through_common_members() {
+        // Each neighbour shares most shingles; the ends share fewer.
+        let docs = [
+            doc("first", &text([]), 3),
+            doc("middle", &text(0..15), 2),
+            doc("last", &text((0..15).chain(85..100)), 1),
+        ];
+        let direct = Signature::of(&docs[0]).similarity(&Signature::of(&docs[2]));
+        assert!(direct < 0.55, "{direct}");
+        let clusters = clusters(&docs, 0.55);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].members.len(), 3);
+        assert!(clusters[0].similarity < 0.9);
+    }
+
+    #[test]
+    fn nothing_clusters_below_the_threshold() {
+        let docs = [doc("a", &text([]), 2), doc("b", &text(0..40), 1)];
+        assert!(clusters(&docs, 0.9).is_empty());
+        assert!(clusters(&[], 0.5).is_empty());
+    }
+}
@@ -0,0 +1,72 @@
+//! Typo-tolerant matching of short strings such as titles, tags and aliases.
+
+/// Minimum similarity for a candidate to count as a match.
+pub const DEFAULT_THRESHOLD: f64 = 0.6;
+
+/// Scores how well `query` matches `candidate`, from 0.0 (unrelated) to 1.0.
+///
+/// Substring matches score highest, word prefixes next; otherwise the query is
+/// compared by edit distance against each word and against the whole string.
+pub fn score(query: &str, candidate: &str) -> f64 {
+    let query = query.trim().to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if query.is_empty() || candidate.is_empty() {
+        return 0.0;
+    }
+    if candidate == query {
+        return 1.0;
+    }
+    if let Some(pos) = candidate.find(&query) {
+        let at_word_start = candidate[..pos]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+        return if at_word_start { 0.95 } else { 0.85 };
+    }
+
+    let q: Vec<char> = query.chars().collect();
+    let mut best = similarity(&q, &candidate.chars().collect::<Vec<_>>());
+    for word in candidate
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let w: Vec<char> = word.chars().collect();
+        best = best.max(similarity(&q, &w));
+        // Compare against a prefix of the word too, so partial words still match.
+        if w.len() > q.len() {
+            best = best.max(0.9 * similarity(&q, &w[..q.len()]));
+        }
+    }
+    best
+}
+
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / longest as f64
+}
+
+/// Optimal string alignment distance: Levenshtein plus adjacent transpositions.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
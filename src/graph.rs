@@ -0,0 +1,679 @@
+//! The knowledge graph: documents, tags and named entities as nodes;
+//! links, tag assignments, relations, citations and mentions as edges.
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::citations;
+use crate::entities::EntityKind;
+use crate::links::LinkIndex;
+use crate::ontology::Ontology;
+use crate::relations::RelationKind;
+use crate::storage::{read_json_or_default, write_json};
+use crate::tags;
+use crate::types::{Document, DocumentId};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum NodeId {
+    Document(DocumentId),
+    Tag(String),
+    /// A named entity, as `kind:name`.
+    Entity(String),
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeId::Document(id) => write!(f, "{id}"),
+            NodeId::Tag(tag) => write!(f, "#{tag}"),
+            NodeId::Entity(entity) => write!(f, "@{entity}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Node {
+    pub id: NodeId,
+    pub label: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EdgeKind {
+    /// A document links to another document.
+    LinksTo,
+    /// A document carries a tag.
+    Tagged,
+    /// A tag is nested below a broader tag.
+    Narrower,
+    /// A typed relation between documents.
+    Related(RelationKind),
+    /// A document mentions a named entity.
+    Mentions,
+    /// Two entities are mentioned by the same document.
+    CoOccurs,
+}
+
+impl EdgeKind {
+    pub fn name(&self) -> &str {
+        match self {
+            EdgeKind::LinksTo => "links-to",
+            EdgeKind::Tagged => "tagged",
+            EdgeKind::Narrower => "narrower",
+            EdgeKind::Related(kind) => kind.name(),
+            EdgeKind::Mentions => "mentions",
+            EdgeKind::CoOccurs => "co-occurs",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edge {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub kind: EdgeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    /// Derived by inference rather than stated by a document.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub inferred: bool,
+}
+
+/// A neighbouring node and the edge that connects it.
+#[derive(Debug, Clone, Copy)]
+pub struct Neighbor<'a> {
+    pub node: &'a NodeId,
+    pub edge: &'a Edge,
+    /// Whether the edge points away from the node we started at.
+    pub outgoing: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Graph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+    #[serde(skip)]
+    lookup: HashMap<NodeId, usize>,
+    #[serde(skip)]
+    adjacency: HashMap<NodeId, Vec<usize>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Graph {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("graph.json");
+        let mut graph: Graph = read_json_or_default(&path)?;
+        graph.path = path;
+        graph.reindex();
+        Ok(graph)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    /// Materializes the graph from documents and their resolved links, then
+    /// infers relations the ontology implies.
+    pub fn rebuild(&mut self, docs: &[Document], links: &LinkIndex, ontology: Option<&Ontology>) {
+        self.nodes.clear();
+        self.edges.clear();
+        self.lookup.clear();
+        for doc in docs {
+            self.add_node(NodeId::Document(doc.id.clone()), &doc.title);
+        }
+        for doc in docs {
+            let from = NodeId::Document(doc.id.clone());
+            for target in links.links_from(&doc.id) {
+                self.edges.push(Edge {
+                    from: from.clone(),
+                    to: NodeId::Document(target.clone()),
+                    kind: EdgeKind::LinksTo,
+                    confidence: None,
+                    inferred: false,
+                });
+            }
+            for relation in &doc.relations {
+                let to = NodeId::Document(relation.target.clone());
+                // Relations may outlive their target.
+                if self.lookup.contains_key(&to) {
+                    self.edges.push(Edge {
+                        from: from.clone(),
+                        to,
+                        kind: EdgeKind::Related(relation.kind.clone()),
+                        confidence: Some(relation.confidence),
+                        inferred: false,
+                    });
+                }
+            }
+            for tag in &doc.tags {
+                self.add_tag(tag);
+                self.edges.push(Edge {
+                    from: from.clone(),
+                    to: NodeId::Tag(tag.clone()),
+                    kind: EdgeKind::Tagged,
+                    confidence: None,
+                    inferred: false,
+                });
+            }
+            for entity in &doc.entities {
+                let to = NodeId::Entity(entity.to_string());
+                self.add_node(to.clone(), &entity.name);
+                self.edges.push(Edge {
+                    from: from.clone(),
+                    to,
+                    kind: EdgeKind::Mentions,
+                    confidence: None,
+                    inferred: false,
+                });
+            }
+        }
+        let stated: HashSet<(&DocumentId, &DocumentId)> = docs
+            .iter()
+            .flat_map(|d| {
+                d.relations
+                    .iter()
+                    .filter(|r| r.kind == RelationKind::Cites)
+                    .map(move |r| (&d.id, &r.target))
+            })
+            .collect();
+        for citation in citations::find(docs) {
+            if !stated.contains(&(&citation.from, &citation.to)) {
+                self.edges.push(Edge {
+                    from: NodeId::Document(citation.from),
+                    to: NodeId::Document(citation.to),
+                    kind: EdgeKind::Related(RelationKind::Cites),
+                    confidence: Some(citation.confidence),
+                    inferred: false,
+                });
+            }
+        }
+        self.add_co_occurrences(docs);
+        self.infer(ontology);
+        self.reindex();
+    }
+
+    /// Adds the transitive closure of transitive relations, then the
+    /// inverse of every relation. A chain's confidence is the product of
+    /// its links.
+    fn infer(&mut self, ontology: Option<&Ontology>) {
+        let mut stated: HashMap<(NodeId, NodeId, RelationKind), f32> = HashMap::new();
+        for edge in &self.edges {
+            if let EdgeKind::Related(kind) = &edge.kind {
+                stated.insert(
+                    (edge.from.clone(), edge.to.clone(), kind.clone()),
+                    edge.confidence.unwrap_or(1.0),
+                );
+            }
+        }
+
+        let mut derived: HashMap<(NodeId, NodeId, RelationKind), f32> = HashMap::new();
+        let kinds: HashSet<&RelationKind> = stated.keys().map(|(_, _, k)| k).collect();
+        for kind in kinds {
+            if !kind.is_transitive(ontology) {
+                continue;
+            }
+            let mut next: HashMap<&NodeId, Vec<(&NodeId, f32)>> = HashMap::new();
+            for ((from, to, k), c) in &stated {
+                if k == kind {
+                    next.entry(from).or_default().push((to, *c));
+                }
+            }
+            for &start in next.keys() {
+                let mut best: HashMap<&NodeId, f32> = HashMap::new();
+                let mut queue = VecDeque::from([(start, 1.0f32)]);
+                while let Some((node, c)) = queue.pop_front() {
+                    for &(to, step) in next.get(node).into_iter().flatten() {
+                        let c = c * step;
+                        if to != start && best.get(to).is_none_or(|&b| c > b) {
+                            best.insert(to, c);
+                            queue.push_back((to, c));
+                        }
+                    }
+                }
+                for (to, c) in best {
+                    let key = (start.clone(), to.clone(), kind.clone());
+                    if !stated.contains_key(&key) {
+                        derived.insert(key, c);
+                    }
+                }
+            }
+        }
+
+        let forward: Vec<_> = stated.iter().chain(&derived).collect();
+        let mut inverses = Vec::new();
+        for ((from, to, kind), c) in forward {
+            if let Some(inverse) = kind.inverse(ontology) {
+                let key = (to.clone(), from.clone(), inverse);
+                if !stated.contains_key(&key) && !derived.contains_key(&key) {
+                    inverses.push((key, *c));
+                }
+            }
+        }
+        derived.extend(inverses);
+
+        let mut derived: Vec<_> = derived.into_iter().collect();
+        derived.sort_by(|a, b| a.0.cmp(&b.0));
+        for ((from, to, kind), c) in derived {
+            self.edges.push(Edge {
+                from,
+                to,
+                kind: EdgeKind::Related(kind),
+                confidence: Some(c),
+                inferred: true,
+            });
+        }
+    }
+
+    fn add_node(&mut self, id: NodeId, label: &str) -> bool {
+        if self.lookup.contains_key(&id) {
+            return false;
+        }
+        self.lookup.insert(id.clone(), self.nodes.len());
+        self.nodes.push(Node {
+            id,
+            label: label.to_string(),
+        });
+        true
+    }
+
+    /// Links every pair of entities mentioned by the same document, once
+    /// per pair however many documents share them. Dates are left out, as
+    /// sharing a date says little.
+    fn add_co_occurrences(&mut self, docs: &[Document]) {
+        let mut pairs: BTreeSet<(NodeId, NodeId)> = BTreeSet::new();
+        for doc in docs {
+            let mut named: Vec<NodeId> = doc
+                .entities
+                .iter()
+                .filter(|e| e.kind != EntityKind::Date)
+                .map(|e| NodeId::Entity(e.to_string()))
+                .collect();
+            named.sort();
+            for (i, a) in named.iter().enumerate() {
+                for b in &named[i + 1..] {
+                    pairs.insert((a.clone(), b.clone()));
+                }
+            }
+        }
+        for (from, to) in pairs {
+            self.edges.push(Edge {
+                from,
+                to,
+                kind: EdgeKind::CoOccurs,
+                confidence: None,
+                inferred: false,
+            });
+        }
+    }
+
+    /// Adds a tag node together with the chain of its broader tags.
+    fn add_tag(&mut self, tag: &str) {
+        for t in tags::ancestors(tag) {
+            if !self.add_node(NodeId::Tag(t.to_string()), t) {
+                break;
+            }
+            if let Some(parent) = tags::parent(t) {
+                self.edges.push(Edge {
+                    from: NodeId::Tag(t.to_string()),
+                    to: NodeId::Tag(parent.to_string()),
+                    kind: EdgeKind::Narrower,
+                    confidence: None,
+                    inferred: false,
+                });
+            }
+        }
+    }
+
+    fn reindex(&mut self) {
+        self.lookup = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (n.id.clone(), i))
+            .collect();
+        self.adjacency.clear();
+        for (i, edge) in self.edges.iter().enumerate() {
+            self.adjacency.entry(edge.from.clone()).or_default().push(i);
+            self.adjacency.entry(edge.to.clone()).or_default().push(i);
+        }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn node(&self, id: &NodeId) -> Option<&Node> {
+        self.lookup.get(id).map(|&i| &self.nodes[i])
+    }
+
+    /// Nodes sharing an edge with `id`, in either direction.
+    pub fn neighbors(&self, id: &NodeId) -> Vec<Neighbor<'_>> {
+        self.adjacency
+            .get(id)
+            .into_iter()
+            .flatten()
+            .map(|&i| {
+                let edge = &self.edges[i];
+                let outgoing = edge.from == *id;
+                Neighbor {
+                    node: if outgoing { &edge.to } else { &edge.from },
+                    edge,
+                    outgoing,
+                }
+            })
+            .collect()
+    }
+
+    /// A shortest path from `from` to `to`, ignoring edge direction.
+    pub fn path_between(&self, from: &NodeId, to: &NodeId) -> Option<Vec<NodeId>> {
+        if self.node(from).is_none() || self.node(to).is_none() {
+            return None;
+        }
+        let mut previous: HashMap<&NodeId, &NodeId> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        previous.insert(from, from);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![current.clone()];
+                let mut node = current;
+                while node != from {
+                    node = previous[node];
+                    path.push(node.clone());
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for neighbor in self.neighbors(current) {
+                if !previous.contains_key(neighbor.node) {
+                    previous.insert(neighbor.node, current);
+                    queue.push_back(neighbor.node);
+                }
+            }
+        }
+        None
+    }
+
+    /// Every node within `depth` edges of `id`, including `id` itself.
+    pub fn neighborhood(&self, id: &NodeId, depth: usize) -> HashSet<NodeId> {
+        let mut seen = HashSet::new();
+        if self.node(id).is_none() {
+            return seen;
+        }
+        seen.insert(id.clone());
+        let mut frontier = vec![id.clone()];
+        for _ in 0..depth {
+            let mut next = Vec::new();
+            for current in &frontier {
+                for neighbor in self.neighbors(current) {
+                    if seen.insert(neighbor.node.clone()) {
+                        next.push(neighbor.node.clone());
+                    }
+                }
+            }
+            frontier = next;
+        }
+        seen
+    }
+
+    /// Documents carrying `tag` or a tag nested below it, together with all
+    /// of their tags and the broader tags those sit under.
+    pub fn tagged(&self, tag: &str) -> HashSet<NodeId> {
+        let docs: HashSet<&NodeId> = self
+            .edges
+            .iter()
+            .filter(|e| e.kind == EdgeKind::Tagged)
+            .filter(|e| matches!(&e.to, NodeId::Tag(t) if tags::is_within(t, tag)))
+            .map(|e| &e.from)
+            .collect();
+        let mut keep: HashSet<NodeId> = docs.iter().map(|&d| d.clone()).collect();
+        for edge in &self.edges {
+            if let (EdgeKind::Tagged, NodeId::Tag(t)) = (&edge.kind, &edge.to) {
+                if docs.contains(&edge.from) {
+                    keep.extend(tags::ancestors(t).map(|a| NodeId::Tag(a.to_string())));
+                }
+            }
+        }
+        keep
+    }
+
+    /// The nodes in `keep` and the edges running between them.
+    pub fn subgraph(&self, keep: &HashSet<NodeId>) -> Graph {
+        let mut graph = Graph {
+            nodes: self
+                .nodes
+                .iter()
+                .filter(|n| keep.contains(&n.id))
+                .cloned()
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .filter(|e| keep.contains(&e.from) && keep.contains(&e.to))
+                .cloned()
+                .collect(),
+            ..Graph::default()
+        };
+        graph.reindex();
+        graph
+    }
+
+    /// Node indices adjacent to each node, ignoring edge direction.
+    fn undirected(&self) -> Vec<Vec<usize>> {
+        let mut adj = vec![Vec::new(); self.nodes.len()];
+        for edge in &self.edges {
+            let (a, b) = (self.lookup[&edge.from], self.lookup[&edge.to]);
+            adj[a].push(b);
+            adj[b].push(a);
+        }
+        adj
+    }
+
+    /// PageRank over the directed edges, with the rank of nodes that have
+    /// no outgoing edges spread evenly over the graph.
+    pub fn pagerank(&self) -> HashMap<&NodeId, f64> {
+        const DAMPING: f64 = 0.85;
+        const ITERATIONS: usize = 50;
+        let n = self.nodes.len();
+        if n == 0 {
+            return HashMap::new();
+        }
+        let mut out = vec![Vec::new(); n];
+        for edge in &self.edges {
+            out[self.lookup[&edge.from]].push(self.lookup[&edge.to]);
+        }
+        let mut rank = vec![1.0 / n as f64; n];
+        for _ in 0..ITERATIONS {
+            let dangling: f64 = (0..n).filter(|&i| out[i].is_empty()).map(|i| rank[i]).sum();
+            let base = (1.0 - DAMPING + DAMPING * dangling) / n as f64;
+            let mut next = vec![base; n];
+            for (i, targets) in out.iter().enumerate() {
+                for &j in targets {
+                    next[j] += DAMPING * rank[i] / targets.len() as f64;
+                }
+            }
+            rank = next;
+        }
+        self.nodes.iter().map(|n| &n.id).zip(rank).collect()
+    }
+
+    /// Betweenness centrality ignoring edge direction (Brandes' algorithm),
+    /// normalized to `0..=1`.
+    pub fn betweenness(&self) -> HashMap<&NodeId, f64> {
+        let n = self.nodes.len();
+        let adj = self.undirected();
+        let mut centrality = vec![0.0; n];
+        for s in 0..n {
+            let mut order = Vec::new();
+            let mut preds = vec![Vec::new(); n];
+            let mut paths = vec![0.0; n];
+            let mut dist = vec![usize::MAX; n];
+            paths[s] = 1.0;
+            dist[s] = 0;
+            let mut queue = VecDeque::from([s]);
+            while let Some(v) = queue.pop_front() {
+                order.push(v);
+                for &w in &adj[v] {
+                    if dist[w] == usize::MAX {
+                        dist[w] = dist[v] + 1;
+                        queue.push_back(w);
+                    }
+                    if dist[w] == dist[v] + 1 {
+                        paths[w] += paths[v];
+                        preds[w].push(v);
+                    }
+                }
+            }
+            let mut delta = vec![0.0; n];
+            for &w in order.iter().rev() {
+                for &v in &preds[w] {
+                    delta[v] += paths[v] / paths[w] * (1.0 + delta[w]);
+                }
+                if w != s {
+                    centrality[w] += delta[w];
+                }
+            }
+        }
+        // Each pair was counted from both ends.
+        let pairs = if n > 2 {
+            ((n - 1) * (n - 2)) as f64
+        } else {
+            1.0
+        };
+        self.nodes
+            .iter()
+            .map(|n| &n.id)
+            .zip(centrality.into_iter().map(|c| c / pairs))
+            .collect()
+    }
+
+    /// Connected components ignoring edge direction, smallest first.
+    pub fn components(&self) -> Vec<Vec<&NodeId>> {
+        let adj = self.undirected();
+        let mut seen = vec![false; self.nodes.len()];
+        let mut components = Vec::new();
+        for start in 0..self.nodes.len() {
+            if seen[start] {
+                continue;
+            }
+            seen[start] = true;
+            let mut members = Vec::new();
+            let mut stack = vec![start];
+            while let Some(v) = stack.pop() {
+                members.push(&self.nodes[v].id);
+                for &w in &adj[v] {
+                    if !seen[w] {
+                        seen[w] = true;
+                        stack.push(w);
+                    }
+                }
+            }
+            members.sort();
+            components.push(members);
+        }
+        components.sort_by_key(|c| c.len());
+        components
+    }
+
+    /// Number of edges touching `id`.
+    pub fn degree(&self, id: &NodeId) -> usize {
+        self.adjacency.get(id).map_or(0, Vec::len)
+    }
+
+    /// Renders the graph in Graphviz DOT syntax.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph ozymandias {\n");
+        for node in &self.nodes {
+            let shape = match node.id {
+                NodeId::Document(_) => "box",
+                NodeId::Tag(_) => "ellipse",
+                NodeId::Entity(_) => "diamond",
+            };
+            let _ = writeln!(
+                out,
+                "  {} [label={}, shape={shape}];",
+                dot_quote(&node.id.to_string()),
+                dot_quote(&node.label)
+            );
+        }
+        for edge in &self.edges {
+            let label = match edge.confidence {
+                Some(c) if c < 1.0 => format!("{} ({c:.2})", edge.kind.name()),
+                _ => edge.kind.name().to_string(),
+            };
+            let style = if edge.inferred { ", style=dashed" } else { "" };
+            let _ = writeln!(
+                out,
+                "  {} -> {} [label={}{style}];",
+                dot_quote(&edge.from.to_string()),
+                dot_quote(&edge.to.to_string()),
+                dot_quote(&label)
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as GraphML, as read by Gephi and yEd.
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n",
+            "  <key id=\"type\" for=\"node\" attr.name=\"type\" attr.type=\"string\"/>\n",
+            "  <key id=\"kind\" for=\"edge\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <key id=\"confidence\" for=\"edge\" attr.name=\"confidence\" attr.type=\"double\"/>\n",
+            "  <key id=\"inferred\" for=\"edge\" attr.name=\"inferred\" attr.type=\"boolean\"/>\n",
+            "  <graph id=\"ozymandias\" edgedefault=\"directed\">\n",
+        ));
+        for node in &self.nodes {
+            let kind = match node.id {
+                NodeId::Document(_) => "document",
+                NodeId::Tag(_) => "tag",
+                NodeId::Entity(_) => "entity",
+            };
+            let _ = writeln!(
+                out,
+                "    <node id=\"{}\"><data key=\"label\">{}</data><data key=\"type\">{kind}</data></node>",
+                xml_escape(&node.id.to_string()),
+                xml_escape(&node.label)
+            );
+        }
+        for edge in &self.edges {
+            let confidence = edge.confidence.map_or(String::new(), |c| {
+                format!("<data key=\"confidence\">{c}</data>")
+            });
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"kind\">{}</data>{confidence}<data key=\"inferred\">{}</data></edge>",
+                xml_escape(&edge.from.to_string()),
+                xml_escape(&edge.to.to_string()),
+                xml_escape(edge.kind.name()),
+                edge.inferred
+            );
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,275 @@
+//! The GraphQL schema served at `/graphql`: documents with their tags,
+//! links and relations, tag counts and search, so a client can fetch
+//! exactly the fields it needs in one request.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Object, Result, SimpleObject,
+};
+
+use crate::index::snippet;
+use crate::kb::KnowledgeBase;
+use crate::search::{self, SearchMode};
+use crate::tags;
+use crate::types::{Document, DocumentId};
+
+pub type Schema = async_graphql::Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+const SNIPPET_WIDTH: usize = 160;
+
+pub fn schema(kb: Arc<Mutex<KnowledgeBase>>) -> Schema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(kb)
+        .finish()
+}
+
+fn kb<'a>(ctx: &Context<'a>) -> MutexGuard<'a, KnowledgeBase> {
+    ctx.data_unchecked::<Arc<Mutex<KnowledgeBase>>>()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Documents with these ids, skipping ones that no longer exist.
+fn documents(kb: &KnowledgeBase, ids: impl IntoIterator<Item = DocumentId>) -> Result<Vec<Doc>> {
+    let mut docs = Vec::new();
+    for id in ids {
+        if let Some(doc) = kb.storage.get(&id)? {
+            docs.push(Doc(doc));
+        }
+    }
+    Ok(docs)
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A document by id or unambiguous id prefix.
+    async fn document(&self, ctx: &Context<'_>, id: String) -> Result<Option<Doc>> {
+        let kb = kb(ctx);
+        let Ok(id) = kb.resolve(&id) else {
+            return Ok(None);
+        };
+        Ok(kb.storage.get(&id)?.map(Doc))
+    }
+
+    /// Documents matching `query`, in the syntax of `ozy list`, newest
+    /// first.
+    async fn documents(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] query: String,
+        #[graphql(default)] offset: usize,
+        limit: Option<usize>,
+    ) -> Result<Vec<Doc>> {
+        let kb = kb(ctx);
+        let query = kb.parse_query(&query)?;
+        let mut docs: Vec<Document> = kb
+            .storage
+            .all()?
+            .into_iter()
+            .filter(|d| query.matches(d, true))
+            .collect();
+        docs.sort_by_key(|d| std::cmp::Reverse(d.added));
+        Ok(docs
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .map(Doc)
+            .collect())
+    }
+
+    /// Ranked matches for `query`, in the syntax of `ozy search`.
+    async fn search(
+        &self,
+        ctx: &Context<'_>,
+        query: String,
+        #[graphql(default)] mode: Mode,
+        #[graphql(default = 10)] limit: usize,
+        #[graphql(default)] offset: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let mut kb = kb(ctx);
+        let query = kb.parse_query(&query)?;
+        let matches = search::run(&mut kb, &query, mode.into())?;
+        Ok(matches
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|m| SearchHit {
+                score: m.score,
+                snippet: snippet(m.passage(), &query.text, SNIPPET_WIDTH),
+                document: Doc(m.doc),
+            })
+            .collect())
+    }
+
+    /// Every tag with the number of documents carrying it.
+    async fn tags(&self, ctx: &Context<'_>) -> Result<Vec<TagCount>> {
+        let kb = kb(ctx);
+        Ok(tags::counts(kb.storage.as_ref())?
+            .into_iter()
+            .map(|(name, count)| TagCount { name, count })
+            .collect())
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    Keyword,
+    Semantic,
+    #[default]
+    Hybrid,
+}
+
+impl From<Mode> for SearchMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Keyword => SearchMode::Keyword,
+            Mode::Semantic => SearchMode::Semantic,
+            Mode::Hybrid => SearchMode::Hybrid,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct SearchHit {
+    score: f64,
+    snippet: String,
+    document: Doc,
+}
+
+#[derive(SimpleObject)]
+pub struct TagCount {
+    name: String,
+    count: usize,
+}
+
+#[derive(SimpleObject)]
+pub struct Entry {
+    key: String,
+    value: String,
+}
+
+#[derive(SimpleObject)]
+pub struct EntityRef {
+    kind: String,
+    name: String,
+}
+
+pub struct RelationRef {
+    kind: String,
+    target: DocumentId,
+    confidence: f32,
+}
+
+#[Object]
+impl RelationRef {
+    async fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    async fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// The related document, or null if it was removed.
+    async fn target(&self, ctx: &Context<'_>) -> Result<Option<Doc>> {
+        Ok(kb(ctx).storage.get(&self.target)?.map(Doc))
+    }
+}
+
+pub struct Doc(Document);
+
+#[Object(name = "Document")]
+impl Doc {
+    async fn id(&self) -> &str {
+        &self.0.id.0
+    }
+
+    async fn title(&self) -> &str {
+        &self.0.title
+    }
+
+    /// `markdown`, `text` or `html`.
+    async fn kind(&self) -> &str {
+        self.0.kind.name()
+    }
+
+    async fn content(&self) -> &str {
+        &self.0.content
+    }
+
+    async fn source(&self) -> Option<&str> {
+        self.0.source.as_deref()
+    }
+
+    /// When the document was added, in RFC 3339.
+    async fn added(&self) -> String {
+        self.0.added.to_rfc3339()
+    }
+
+    async fn tags(&self) -> &[String] {
+        &self.0.tags
+    }
+
+    async fn aliases(&self) -> &[String] {
+        &self.0.aliases
+    }
+
+    async fn keywords(&self) -> &[String] {
+        &self.0.keywords
+    }
+
+    async fn language(&self) -> Option<&str> {
+        self.0.language()
+    }
+
+    async fn metadata(&self) -> Vec<Entry> {
+        self.0
+            .metadata
+            .iter()
+            .map(|(key, value)| Entry {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+
+    async fn entities(&self) -> Vec<EntityRef> {
+        self.0
+            .entities
+            .iter()
+            .map(|e| EntityRef {
+                kind: e.kind.name().to_string(),
+                name: e.name.clone(),
+            })
+            .collect()
+    }
+
+    async fn relations(&self) -> Vec<RelationRef> {
+        self.0
+            .relations
+            .iter()
+            .map(|r| RelationRef {
+                kind: r.kind.to_string(),
+                target: r.target.clone(),
+                confidence: r.confidence,
+            })
+            .collect()
+    }
+
+    /// Documents this one links to.
+    async fn links(&self, ctx: &Context<'_>) -> Result<Vec<Doc>> {
+        let kb = kb(ctx);
+        let ids: Vec<DocumentId> = kb.links.links_from(&self.0.id).cloned().collect();
+        documents(&kb, ids)
+    }
+
+    /// Documents linking to this one.
+    async fn backlinks(&self, ctx: &Context<'_>) -> Result<Vec<Doc>> {
+        let kb = kb(ctx);
+        let ids: Vec<DocumentId> = kb.links.links_to(&self.0.id).cloned().collect();
+        documents(&kb, ids)
+    }
+}
@@ -0,0 +1,297 @@
+//! Earlier revisions of documents, kept whenever a document is stored with
+//! a new title or content, for `ozy history` and `ozy diff`.
+//!
+//! Revisions are numbered from 1, the oldest; the document as stored now
+//! is the one after the last kept.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::kb;
+use crate::storage::write_json;
+use crate::types::DocumentId;
+
+/// Lines of unchanged text shown around each change.
+const CONTEXT: usize = 3;
+/// Beyond this many pairs of lines to compare, changed passages are shown
+/// as replaced as a whole rather than line by line.
+const MAX_COMPARISONS: usize = 4_000_000;
+
+/// A document's title and content as they were.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub title: String,
+    pub content: String,
+    /// When this revision was stored.
+    pub saved: DateTime<Utc>,
+}
+
+/// The revisions of every document, a JSON file each under `history/`.
+pub struct History {
+    dir: PathBuf,
+}
+
+impl History {
+    pub fn open(root: &Path) -> Self {
+        History {
+            dir: root.join("history"),
+        }
+    }
+
+    /// The file holding the revisions of a document.
+    pub fn path(&self, id: &DocumentId) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// The documents with earlier revisions kept.
+    pub fn ids(&self) -> Result<Vec<DocumentId>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.dir.display()))
+            }
+        };
+        let mut ids = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    ids.push(DocumentId(stem.to_string()));
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// The earlier revisions of a document, oldest first.
+    pub fn revisions(&self, id: &DocumentId) -> Result<Vec<Revision>> {
+        let path = self.path(id);
+        match fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw)
+                .with_context(|| format!("corrupt history {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Keeps `revision` as the latest earlier revision of a document.
+    pub fn record(&self, id: &DocumentId, revision: Revision) -> Result<()> {
+        if kb::dry_run() {
+            return Ok(());
+        }
+        let mut revisions = self.revisions(id)?;
+        revisions.push(revision);
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        write_json(&self.path(id), &revisions)
+    }
+
+    /// Forgets every earlier revision of a document.
+    pub fn remove(&self, id: &DocumentId) -> Result<()> {
+        if kb::dry_run() {
+            return Ok(());
+        }
+        match fs::remove_file(self.path(id)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Same,
+    Removed,
+    Added,
+}
+
+/// The lines of `old` and `new` in order, each kept, removed or added,
+/// with as few changes as a longest common subsequence allows.
+fn compare<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut ops: Vec<(Op, &str)> = old[..prefix].iter().map(|l| (Op::Same, *l)).collect();
+    if a.len().saturating_mul(b.len()) > MAX_COMPARISONS {
+        ops.extend(a.iter().map(|l| (Op::Removed, *l)));
+        ops.extend(b.iter().map(|l| (Op::Added, *l)));
+    } else {
+        // lengths[i][j]: the longest common subsequence of a[i..] and b[j..].
+        let width = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i * width + j] = match a[i] == b[j] {
+                    true => lengths[(i + 1) * width + j + 1] + 1,
+                    false => lengths[(i + 1) * width + j].max(lengths[i * width + j + 1]),
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                ops.push((Op::Same, a[i]));
+                (i, j) = (i + 1, j + 1);
+            } else if i < a.len()
+                && (j == b.len() || lengths[(i + 1) * width + j] >= lengths[i * width + j + 1])
+            {
+                ops.push((Op::Removed, a[i]));
+                i += 1;
+            } else {
+                ops.push((Op::Added, b[j]));
+                j += 1;
+            }
+        }
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|l| (Op::Same, *l)));
+    ops
+}
+
+/// How many lines going from `old` to `new` adds and removes.
+pub fn changes(old: &str, new: &str) -> (usize, usize) {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = compare(&old_lines, &new_lines);
+    let count = |kind| ops.iter().filter(|(op, _)| *op == kind).count();
+    (count(Op::Added), count(Op::Removed))
+}
+
+/// A unified diff from `old` to `new`, labelled with `from` and `to`;
+/// empty if they are the same.
+pub fn unified(old: &str, new: &str, from: &str, to: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = compare(&old_lines, &new_lines);
+    let changed: Vec<usize> = (0..ops.len()).filter(|&i| ops[i].0 != Op::Same).collect();
+    if changed.is_empty() {
+        return String::new();
+    }
+    // Changes close enough for their context to touch share a hunk.
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    let mut out = format!("--- {from}\n+++ {to}\n");
+    // Line numbers, from 1, at the start of each op.
+    let (mut old_at, mut new_at) = (Vec::with_capacity(ops.len()), Vec::with_capacity(ops.len()));
+    let (mut o, mut n) = (1, 1);
+    for (op, _) in &ops {
+        old_at.push(o);
+        new_at.push(n);
+        o += usize::from(*op != Op::Added);
+        n += usize::from(*op != Op::Removed);
+    }
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let old_len = hunk.iter().filter(|(op, _)| *op != Op::Added).count();
+        let new_len = hunk.iter().filter(|(op, _)| *op != Op::Removed).count();
+        // An empty side is numbered by the line before it, as diff does.
+        let old_start = old_at[start] - usize::from(old_len == 0);
+        let new_start = new_at[start] - usize::from(new_len == 0);
+        out.push_str(&format!(
+            "@@ -{old_start},{old_len} +{new_start},{new_len} @@\n"
+        ));
+        for (op, line) in hunk {
+            let mark = match op {
+                Op::Same => ' ',
+                Op::Removed => '-',
+                Op::Added => '+',
+            };
+            out.push(mark);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// `old` and `new` in two columns of `width` characters, as `sdiff` shows
+/// them: `|` marks a changed line, `<` one only on the left and `>` one
+/// only on the right. Long unchanged stretches are cut down to their
+/// context.
+pub fn side_by_side(old: &str, new: &str, width: usize) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = compare(&old_lines, &new_lines);
+    let mut rows: Vec<(&str, char, &str)> = Vec::new();
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i].0 == Op::Same {
+            rows.push((ops[i].1, ' ', ops[i].1));
+            i += 1;
+            continue;
+        }
+        // Pair a run of removed lines with the added lines after it.
+        let removed: Vec<&str> = ops[i..]
+            .iter()
+            .take_while(|(op, _)| *op == Op::Removed)
+            .map(|(_, l)| *l)
+            .collect();
+        i += removed.len();
+        let added: Vec<&str> = ops[i..]
+            .iter()
+            .take_while(|(op, _)| *op == Op::Added)
+            .map(|(_, l)| *l)
+            .collect();
+        i += added.len();
+        for n in 0..removed.len().max(added.len()) {
+            let (left, right) = (removed.get(n), added.get(n));
+            let mark = match (left, right) {
+                (Some(_), Some(_)) => '|',
+                (Some(_), None) => '<',
+                _ => '>',
+            };
+            rows.push((
+                left.copied().unwrap_or_default(),
+                mark,
+                right.copied().unwrap_or_default(),
+            ));
+        }
+    }
+    let fit = |line: &str| {
+        let mut cell: String = line.chars().take(width).collect();
+        let pad = width - cell.chars().count();
+        cell.extend(std::iter::repeat_n(' ', pad));
+        cell
+    };
+    let mut out = String::new();
+    let mut skipped = false;
+    for (n, (left, mark, right)) in rows.iter().enumerate() {
+        let near_change = rows[n.saturating_sub(CONTEXT)..(n + CONTEXT + 1).min(rows.len())]
+            .iter()
+            .any(|(_, m, _)| *m != ' ');
+        if !near_change {
+            if !skipped {
+                out.push_str(&format!("{:^width$}   {:^width$}\n", "...", "..."));
+                skipped = true;
+            }
+            continue;
+        }
+        skipped = false;
+        out.push_str(fit(left).as_str());
+        out.push_str(format!(" {mark} {}", fit(right)).trim_end());
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,244 @@
+//! User scripts in Rhai, run at points of a document's life. Each hook is
+//! a script in the knowledge base's `hooks/` directory:
+//!
+//! - `post-parse.rhai` runs on every document about to be added, before
+//!   the pipeline,
+//! - `pre-store.rhai` runs after the pipeline, before the document is
+//!   stored,
+//! - `post-search.rhai` runs on the matches of every search.
+//!
+//! The document hooks see the document as the map `doc`, with `id`,
+//! `kind`, `source`, `title`, `content`, `tags`, `aliases` and `metadata`;
+//! what they change of the title, content, tags, aliases and metadata is
+//! kept. Setting `skip` to a reason drops the document:
+//!
+//! ```rhai
+//! if doc.title.starts_with("Meeting") { doc.tags.push("meeting"); }
+//! if doc.content.len() < 20 { skip = "too short"; }
+//! ```
+//!
+//! `post-search` sees the `query` and `results`, an array of maps with
+//! `id`, `title`, `tags` and `score`. The results it leaves, in their order
+//! and with their scores, are the matches.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+
+use crate::error::OzymandiasError;
+use crate::search::Match;
+use crate::tags;
+use crate::transform::Outcome;
+use crate::types::Document;
+
+pub const HOOKS_DIR: &str = "hooks";
+/// Operations a script may run per call, so that one that loops is
+/// stopped.
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PostParse,
+    PreStore,
+    PostSearch,
+}
+
+impl Hook {
+    const ALL: [Hook; 3] = [Hook::PostParse, Hook::PreStore, Hook::PostSearch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PostParse => "post-parse",
+            Hook::PreStore => "pre-store",
+            Hook::PostSearch => "post-search",
+        }
+    }
+}
+
+/// The compiled hook scripts of a knowledge base.
+#[derive(Default)]
+pub struct Hooks {
+    engine: Engine,
+    scripts: Vec<(Hook, AST)>,
+}
+
+impl Hooks {
+    /// Compiles the scripts in the knowledge base's `hooks/`.
+    pub fn load(root: &Path) -> Result<Self> {
+        let dir = root.join(HOOKS_DIR);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Hooks::default()),
+            Err(e) => return Err(e).with_context(|| format!("cannot read {}", dir.display())),
+        };
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| tracing::info!("{text}"));
+        engine.on_debug(|text, _, _| tracing::debug!("{text}"));
+        let mut scripts = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "rhai") {
+                continue;
+            }
+            let stem = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default();
+            let Some(hook) = Hook::ALL.into_iter().find(|h| h.name() == stem) else {
+                bail!(OzymandiasError::ConfigInvalid(format!(
+                    "{} is not a hook (expected post-parse.rhai, pre-store.rhai or post-search.rhai)",
+                    path.display()
+                )));
+            };
+            let source = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            let ast = engine
+                .compile(&source)
+                .map_err(|e| OzymandiasError::ConfigInvalid(format!("{}: {e}", path.display())))?;
+            scripts.push((hook, ast));
+        }
+        Ok(Hooks { engine, scripts })
+    }
+
+    fn script(&self, hook: Hook) -> Option<&AST> {
+        self.scripts
+            .iter()
+            .find(|(h, _)| *h == hook)
+            .map(|(_, ast)| ast)
+    }
+
+    /// Runs the `post-parse` or `pre-store` hook on a document.
+    pub fn document(&self, hook: Hook, doc: &mut Document) -> Result<Outcome> {
+        let Some(ast) = self.script(hook) else {
+            return Ok(Outcome::Continue);
+        };
+        let mut scope = Scope::new();
+        scope.push("doc", document_map(doc));
+        scope.push("skip", Dynamic::UNIT);
+        self.engine
+            .run_ast_with_scope(&mut scope, ast)
+            .map_err(|e| anyhow!("{} hook failed on {}: {e}", hook.name(), doc.id))?;
+        if let Some(reason) = scope.get_value::<String>("skip") {
+            return Ok(Outcome::Skip(format!("{} hook: {reason}", hook.name())));
+        }
+        let map = scope
+            .get_value::<Map>("doc")
+            .with_context(|| format!("the {} hook replaced doc with a non-map", hook.name()))?;
+        update(doc, map).with_context(|| format!("{} hook on {}", hook.name(), doc.id))?;
+        Ok(Outcome::Continue)
+    }
+
+    /// Runs the `post-search` hook on the matches of `query`.
+    pub fn search(&self, query: &str, matches: &mut Vec<Match>) -> Result<()> {
+        let Some(ast) = self.script(Hook::PostSearch) else {
+            return Ok(());
+        };
+        let results: Array = matches
+            .iter()
+            .map(|m| {
+                let mut map = Map::new();
+                map.insert("id".into(), Dynamic::from(m.doc.id.to_string()));
+                map.insert("title".into(), Dynamic::from(m.doc.title.clone()));
+                map.insert("tags".into(), strings(&m.doc.tags));
+                map.insert("score".into(), Dynamic::from_float(m.score));
+                Dynamic::from_map(map)
+            })
+            .collect();
+        let mut scope = Scope::new();
+        scope.push("query", query.to_string());
+        scope.push("results", results);
+        self.engine
+            .run_ast_with_scope(&mut scope, ast)
+            .map_err(|e| anyhow!("post-search hook failed: {e}"))?;
+        let results = scope
+            .get_value::<Array>("results")
+            .context("the post-search hook replaced results with a non-array")?;
+        let mut remaining = std::mem::take(matches);
+        for result in results {
+            let map = result
+                .try_cast::<Map>()
+                .context("the post-search hook left a result that is not a map")?;
+            let id = string(&map, "id")?;
+            let Some(i) = remaining.iter().position(|m| m.doc.id.0 == id) else {
+                bail!("the post-search hook left a result for {id}, which did not match");
+            };
+            let mut found = remaining.swap_remove(i);
+            if let Some(score) = map.get("score") {
+                found.score = score
+                    .as_float()
+                    .or_else(|_| score.as_int().map(|i| i as f64))
+                    .map_err(|_| {
+                        anyhow!("the post-search hook set a score that is not a number")
+                    })?;
+            }
+            matches.push(found);
+        }
+        Ok(())
+    }
+}
+
+fn document_map(doc: &Document) -> Map {
+    let mut map = Map::new();
+    map.insert("id".into(), Dynamic::from(doc.id.to_string()));
+    map.insert("kind".into(), Dynamic::from(doc.kind.name().to_string()));
+    let source = doc.source.clone().map_or(Dynamic::UNIT, Dynamic::from);
+    map.insert("source".into(), source);
+    map.insert("title".into(), Dynamic::from(doc.title.clone()));
+    map.insert("content".into(), Dynamic::from(doc.content.clone()));
+    map.insert("tags".into(), strings(&doc.tags));
+    map.insert("aliases".into(), strings(&doc.aliases));
+    let metadata: Map = doc
+        .metadata
+        .iter()
+        .map(|(k, v)| (k.as_str().into(), Dynamic::from(v.clone())))
+        .collect();
+    map.insert("metadata".into(), Dynamic::from_map(metadata));
+    map
+}
+
+/// Takes what a hook may change from the map it left.
+fn update(doc: &mut Document, map: Map) -> Result<()> {
+    doc.title = string(&map, "title")?;
+    doc.content = string(&map, "content")?;
+    doc.tags = string_list(&map, "tags")?
+        .iter()
+        .map(|t| tags::normalize(t))
+        .collect::<Result<_>>()?;
+    doc.tags.sort();
+    doc.tags.dedup();
+    doc.aliases = string_list(&map, "aliases")?;
+    let metadata = map
+        .get("metadata")
+        .and_then(|m| m.clone().try_cast::<Map>())
+        .context("metadata must be a map")?;
+    doc.metadata = metadata
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    Ok(())
+}
+
+fn strings(values: &[String]) -> Dynamic {
+    Dynamic::from_array(values.iter().cloned().map(Dynamic::from).collect())
+}
+
+fn string(map: &Map, key: &str) -> Result<String> {
+    map.get(key)
+        .and_then(|v| v.clone().into_string().ok())
+        .with_context(|| format!("{key} must be a string"))
+}
+
+fn string_list(map: &Map, key: &str) -> Result<Vec<String>> {
+    let list = map
+        .get(key)
+        .and_then(|v| v.clone().into_array().ok())
+        .with_context(|| format!("{key} must be an array"))?;
+    list.into_iter()
+        .map(|v| v.into_string().ok())
+        .collect::<Option<_>>()
+        .with_context(|| format!("{key} must hold only strings"))
+}
@@ -0,0 +1,518 @@
+//! BibTeX `.bib` files: one reference document per entry, with the
+//! citation key as an alias and the fields as metadata, kept in their
+//! LaTeX form so that `ozy export bibtex` writes them back unchanged.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::error::OzymandiasError;
+use crate::fingerprint::content_hash;
+use crate::import::zotero::CITATION_KEY;
+use crate::tags;
+use crate::types::{Document, DocumentId, DocumentKind, SOURCE_HASH_KEY};
+
+/// Prefix of the sources of imported entries.
+const SCHEME: &str = "bibtex:";
+/// Metadata keys of an entry's type, such as `article`, and its authors
+/// and editors, separated by `; ` as for references from Zotero.
+pub const ENTRY_TYPE_KEY: &str = "entry_type";
+pub const AUTHORS_KEY: &str = "authors";
+pub const EDITORS_KEY: &str = "editors";
+
+/// An entry as written in the file.
+#[derive(Debug)]
+pub struct Entry {
+    pub kind: String,
+    pub key: String,
+    /// Field names in lowercase and their values with macros expanded.
+    pub fields: Vec<(String, String)>,
+}
+
+/// The entries of the `.bib` file at `path` as documents.
+pub fn read(path: &Path) -> Result<Vec<Document>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let entries = parse(&text).with_context(|| {
+        OzymandiasError::ParseFailed(format!("cannot read {} as BibTeX", path.display()))
+    })?;
+    let file = file_name(path);
+    Ok(entries.into_iter().map(|e| document(e, file)).collect())
+}
+
+/// Prefix of the sources of the entries read from `path`.
+pub fn scope(path: &Path) -> String {
+    format!("{SCHEME}/{}/", file_name(path))
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("references.bib")
+}
+
+fn document(entry: Entry, file: &str) -> Document {
+    let field = |name: &str| {
+        entry
+            .fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let title = field("title").map_or_else(|| entry.key.clone(), plain);
+    let authors: Vec<String> = field("author").map(names).unwrap_or_default();
+    let mut content = format!("# {title}\n\n");
+    if !authors.is_empty() {
+        let authors: Vec<String> = authors.iter().map(|a| plain(a)).collect();
+        content.push_str(&format!("{}\n\n", authors.join("; ")));
+    }
+    if let Some(abstract_text) = field("abstract") {
+        content.push_str(&format!("## Abstract\n\n{}\n", plain(abstract_text)));
+    }
+    let source = format!("{SCHEME}/{file}/{}", entry.key);
+    let mut doc = Document::new(
+        DocumentId::derive(&source),
+        title,
+        DocumentKind::Markdown,
+        content.trim_end().to_string() + "\n",
+        Some(source),
+    );
+    let mut hashed = format!("@{}{{{}", entry.kind, entry.key);
+    for (name, value) in &entry.fields {
+        hashed.push_str(&format!("\n{name}={value}"));
+        match name.as_str() {
+            // The title is kept as written only where it has markup.
+            "title" if plain(value) != *value => {
+                doc.metadata.insert(name.clone(), value.clone());
+            }
+            "title" | "abstract" => {}
+            "author" => {
+                doc.metadata
+                    .insert(AUTHORS_KEY.into(), names(value).join("; "));
+            }
+            "editor" => {
+                doc.metadata
+                    .insert(EDITORS_KEY.into(), names(value).join("; "));
+            }
+            "keywords" => {
+                for keyword in value.split([',', ';']).map(str::trim) {
+                    match tags::normalize(&plain(keyword)) {
+                        Ok(tag) if !doc.tags.contains(&tag) => doc.tags.push(tag),
+                        Ok(_) => {}
+                        Err(e) => {
+                            tracing::warn!("{}: skipped keyword {keyword:?}: {e:#}", entry.key)
+                        }
+                    }
+                }
+            }
+            _ => {
+                doc.metadata.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    doc.tags.sort();
+    doc.aliases.push(entry.key.clone());
+    doc.metadata.insert(CITATION_KEY.into(), entry.key);
+    doc.metadata.insert(ENTRY_TYPE_KEY.into(), entry.kind);
+    doc.metadata
+        .insert(SOURCE_HASH_KEY.into(), content_hash(&hashed));
+    doc
+}
+
+/// Splits an `author` field on the `and`s between names.
+fn names(value: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut depth = 0;
+    let mut current = String::new();
+    let words: Vec<&str> = value.split_whitespace().collect();
+    for word in words {
+        if depth == 0 && word.eq_ignore_ascii_case("and") && !current.is_empty() {
+            names.push(std::mem::take(&mut current));
+            continue;
+        }
+        depth += word.matches('{').count() as i32 - word.matches('}').count() as i32;
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    if !current.is_empty() {
+        names.push(current);
+    }
+    names
+}
+
+/// A LaTeX value as plain text: without the braces that protect case and
+/// with the common escapes undone.
+pub fn plain(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '\\' => match chars.peek() {
+                Some(&e @ ('&' | '%' | '$' | '#' | '_' | '{' | '}')) => {
+                    out.push(e);
+                    chars.next();
+                }
+                _ => out.push(c),
+            },
+            // A tie is a space that does not break.
+            c if c.is_whitespace() || c == '~' => {
+                if !out.ends_with(' ') {
+                    out.push(' ');
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out.trim().to_string()
+}
+
+/// The entries of a `.bib` file. `@string` macros are expanded and
+/// `@comment` and `@preamble` skipped; text between entries is ignored.
+pub fn parse(text: &str) -> Result<Vec<Entry>> {
+    let mut parser = BibParser {
+        text,
+        at: 0,
+        strings: month_macros(),
+    };
+    let mut entries = Vec::new();
+    while let Some(start) = parser.text[parser.at..].find('@') {
+        parser.at += start + 1;
+        let kind = parser.identifier().to_ascii_lowercase();
+        parser.skip_space();
+        // An `@` elsewhere, as in an e-mail address, starts no entry.
+        let close = match parser.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => continue,
+        };
+        parser.next();
+        match kind.as_str() {
+            "comment" => {
+                parser.at -= 1;
+                parser.braced()?;
+                continue;
+            }
+            "preamble" => {
+                parser.value()?;
+                parser.expect(close)?;
+                continue;
+            }
+            "string" => {
+                parser.skip_space();
+                let name = parser.identifier().to_ascii_lowercase();
+                parser.skip_space();
+                parser.expect('=')?;
+                let value = parser.value()?;
+                parser.strings.insert(name, value);
+                parser.skip_space();
+                parser.expect(close)?;
+                continue;
+            }
+            _ => {}
+        }
+        parser.skip_space();
+        let key_end = parser.text[parser.at..]
+            .find([',', close])
+            .context("entry without fields")?;
+        let key = parser.text[parser.at..parser.at + key_end]
+            .trim()
+            .to_string();
+        parser.at += key_end;
+        let mut fields = Vec::new();
+        loop {
+            parser.skip_space();
+            match parser.next() {
+                Some(',') => {}
+                Some(c) if c == close => break,
+                Some(c) => bail!("unexpected {c:?} in entry {key}"),
+                None => bail!("entry {key} is not closed"),
+            }
+            parser.skip_space();
+            if parser.peek() == Some(close) {
+                parser.next();
+                break;
+            }
+            let name = parser.identifier().to_ascii_lowercase();
+            if name.is_empty() {
+                bail!("expected a field name in entry {key}");
+            }
+            parser.skip_space();
+            parser.expect('=')?;
+            let value = parser.value()?;
+            fields.push((name, value));
+        }
+        entries.push(Entry { kind, key, fields });
+    }
+    Ok(entries)
+}
+
+fn month_macros() -> HashMap<String, String> {
+    let months = [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ];
+    months
+        .iter()
+        .map(|m| {
+            (
+                m[..3].to_string(),
+                format!("{}{}", m[..1].to_uppercase(), &m[1..]),
+            )
+        })
+        .collect()
+}
+
+struct BibParser<'a> {
+    text: &'a str,
+    at: usize,
+    strings: HashMap<String, String>,
+}
+
+impl BibParser<'_> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.at..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.at += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => bail!("expected {expected:?} but found {c:?}"),
+            None => bail!("expected {expected:?} but the file ended"),
+        }
+    }
+
+    fn identifier(&mut self) -> &str {
+        let start = self.at;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || "_-:.+/".contains(c))
+        {
+            self.next();
+        }
+        &self.text[start..self.at]
+    }
+
+    /// A field value: braced or quoted parts, numbers and macros joined
+    /// with `#`.
+    fn value(&mut self) -> Result<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some('{') => value.push_str(self.braced()?),
+                Some('"') => value.push_str(&self.quoted()?),
+                Some(c) if c.is_alphanumeric() => {
+                    let word = self.identifier().to_string();
+                    let expanded = match word.chars().all(|c| c.is_ascii_digit()) {
+                        true => word,
+                        false => self
+                            .strings
+                            .get(&word.to_ascii_lowercase())
+                            .cloned()
+                            .unwrap_or(word),
+                    };
+                    value.push_str(&expanded);
+                }
+                _ => bail!("expected a value"),
+            }
+            self.skip_space();
+            if self.peek() != Some('#') {
+                break;
+            }
+            self.next();
+        }
+        Ok(value)
+    }
+
+    /// The text inside balanced braces, which the parser is at.
+    fn braced(&mut self) -> Result<&str> {
+        self.expect('{')?;
+        let start = self.at;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some('\\') => {
+                    self.next();
+                }
+                Some(_) => {}
+                None => bail!("unbalanced braces"),
+            }
+        }
+        Ok(&self.text[start..self.at - 1])
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let start = self.at;
+        let mut depth = 0;
+        loop {
+            match self.next() {
+                Some('{') => depth += 1,
+                Some('}') => depth -= 1,
+                Some('\\') => {
+                    self.next();
+                }
+                Some('"') if depth == 0 => break,
+                Some(_) => {}
+                None => bail!("unterminated string"),
+            }
+        }
+        Ok(self.text[start..self.at - 1].to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export;
+
+    const BIB: &str = r#"
+Some notes before the entries, and a stray e-mail@example.com.
+@comment{ignored {entirely}}
+@preamble{"\newcommand{\noop}[1]{}"}
+@string{acm = "ACM Press"}
+@Article{knuth84,
+  author = {Donald E. Knuth and {Barnes and Noble}},
+  title = {Literate {P}rogramming},
+  journal = "The Computer Journal",
+  year = 1984,
+  month = may,
+  publisher = acm # ", New York",
+  keywords = {programming; Literate Programming},
+  abstract = {All about \& around.},
+}
+@book(lamport86, title = "{\LaTeX}: A Document Preparation System", year = "1986")
+"#;
+
+    fn entries() -> Vec<Entry> {
+        parse(BIB).unwrap()
+    }
+
+    fn field<'a>(entry: &'a Entry, name: &str) -> Option<&'a str> {
+        entry
+            .fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn parse_reads_entries_and_expands_macros() {
+        let entries = entries();
+        assert_eq!(entries.len(), 2);
+        let knuth = &entries[0];
+        assert_eq!(
+            (knuth.kind.as_str(), knuth.key.as_str()),
+            ("article", "knuth84")
+        );
+        assert_eq!(field(knuth, "title"), Some("Literate {P}rogramming"));
+        assert_eq!(field(knuth, "journal"), Some("The Computer Journal"));
+        assert_eq!(field(knuth, "year"), Some("1984"));
+        assert_eq!(field(knuth, "month"), Some("May"));
+        assert_eq!(field(knuth, "publisher"), Some("ACM Press, New York"));
+        let lamport = &entries[1];
+        assert_eq!(
+            (lamport.kind.as_str(), lamport.key.as_str()),
+            ("book", "lamport86")
+        );
+        assert_eq!(
+            field(lamport, "title"),
+            Some("{\\LaTeX}: A Document Preparation System")
+        );
+    }
+
+    #[test]
+    fn parse_rejects_broken_entries() {
+        assert!(parse("@article{key, title = {open").is_err());
+        assert!(parse("@article{key, title = {x} author = {y}}").is_err());
+        assert!(parse("@article{key, = {x}}").is_err());
+        assert!(parse("@article{key").is_err());
+    }
+
+    #[test]
+    fn names_split_on_unbraced_ands() {
+        assert_eq!(
+            names("Donald E. Knuth and {Barnes and Noble} AND Ada Lovelace"),
+            vec!["Donald E. Knuth", "{Barnes and Noble}", "Ada Lovelace"]
+        );
+    }
+
+    #[test]
+    fn plain_drops_braces_and_escapes() {
+        assert_eq!(plain("{T}he  \\& ~{\\%}x\n"), "The & %x");
+        assert_eq!(plain("\\LaTeX"), "\\LaTeX");
+    }
+
+    #[test]
+    fn entries_become_documents() {
+        let doc = document(entries().remove(0), "refs.bib");
+        assert_eq!(doc.title, "Literate Programming");
+        assert_eq!(doc.source.as_deref(), Some("bibtex:/refs.bib/knuth84"));
+        assert_eq!(doc.aliases, vec!["knuth84"]);
+        assert_eq!(doc.tags, vec!["literate-programming", "programming"]);
+        assert_eq!(
+            doc.metadata[AUTHORS_KEY],
+            "Donald E. Knuth; {Barnes and Noble}"
+        );
+        assert!(doc.content.contains("Donald E. Knuth; Barnes and Noble"));
+        assert!(doc.content.contains("## Abstract\n\nAll about & around."));
+        assert_eq!(scope(Path::new("/tmp/refs.bib")), "bibtex:/refs.bib/");
+    }
+
+    #[test]
+    fn entries_survive_a_round_trip() {
+        let docs: Vec<Document> = entries()
+            .into_iter()
+            .map(|e| document(e, "refs.bib"))
+            .collect();
+        let written = export::bibtex::write(&docs);
+        let again: Vec<Document> = parse(&written)
+            .unwrap()
+            .into_iter()
+            .map(|e| document(e, "refs.bib"))
+            .collect();
+        assert_eq!(again.len(), docs.len());
+        for (before, after) in docs.iter().zip(&again) {
+            let mut metadata = before.metadata.clone();
+            let mut metadata_after = after.metadata.clone();
+            // The hash is of the entry as written, which the export lays
+            // out in its own order.
+            metadata.remove(SOURCE_HASH_KEY);
+            metadata_after.remove(SOURCE_HASH_KEY);
+            assert_eq!(metadata_after, metadata);
+            assert_eq!(after.id, before.id);
+            assert_eq!(after.title, before.title);
+            assert_eq!(after.content, before.content);
+            assert_eq!(after.tags, before.tags);
+            assert_eq!(after.aliases, before.aliases);
+        }
+    }
+}
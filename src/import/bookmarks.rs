@@ -0,0 +1,215 @@
+//! Browser bookmarks: the HTML both Firefox and Chrome export, a Firefox
+//! JSON backup, or Chrome's `Bookmarks` file. Folders below the browser's
+//! own, such as the bookmarks bar, become nested tags.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use super::{tokens, Saved, Token};
+use crate::error::OzymandiasError;
+use crate::tags;
+
+/// Seconds between 1601, which Chrome counts time from, and 1970.
+const WEBKIT_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// The bookmarks of web pages in the export at `path`.
+pub fn read(path: &Path) -> Result<Vec<Saved>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let mut bookmarks = Vec::new();
+    let read = match text.trim_start().starts_with('{') {
+        true => serde_json::from_str(&text)
+            .map_err(Into::into)
+            .and_then(|json| json_bookmarks(&json, &mut bookmarks)),
+        false => {
+            html(&text, &mut bookmarks);
+            Ok(())
+        }
+    };
+    read.with_context(|| {
+        OzymandiasError::ParseFailed(format!("cannot read {} as bookmarks", path.display()))
+    })?;
+    // Only pages can be clipped; `place:` queries, `javascript:` and the
+    // like are left out.
+    bookmarks.retain(|b| {
+        let web = b.url.starts_with("http://") || b.url.starts_with("https://");
+        if !web {
+            tracing::debug!("skipped bookmark {}", b.url);
+        }
+        web
+    });
+    Ok(bookmarks)
+}
+
+/// The Netscape bookmark format: `<DT><H3>Folder</H3><DL><p>` opens a
+/// folder, `<DT><A HREF=… ADD_DATE=… TAGS=…>Title</A>` is a bookmark, and
+/// `</DL>` closes the folder.
+fn html(html: &str, bookmarks: &mut Vec<Saved>) {
+    // The open folders; `None` for the browser's own.
+    let mut folders: Vec<Option<String>> = Vec::new();
+    // A folder heading, until the list of its contents opens.
+    let mut heading: Option<Option<String>> = None;
+    let mut in_heading = false;
+    let mut anchor: Option<Saved> = None;
+    for token in tokens(html) {
+        match &token {
+            Token::Open { name, .. } if name == "h3" => {
+                let builtin = token.attribute("personal_toolbar_folder").is_some()
+                    || token.attribute("unfiled_bookmarks_folder").is_some();
+                heading = Some((!builtin).then(String::new));
+                in_heading = true;
+            }
+            Token::Close(name) if name == "h3" => in_heading = false,
+            Token::Open { name, .. } if name == "dl" => folders.push(heading.take().flatten()),
+            Token::Close(name) if name == "dl" => {
+                folders.pop();
+            }
+            Token::Open { name, .. } if name == "a" => {
+                anchor = token.attribute("href").map(|url| {
+                    bookmark(
+                        url,
+                        None,
+                        token
+                            .attribute("add_date")
+                            .and_then(|t| t.parse().ok())
+                            .and_then(|t| DateTime::from_timestamp(t, 0)),
+                        &folders,
+                        token.attribute("tags").unwrap_or_default(),
+                    )
+                });
+            }
+            Token::Close(name) if name == "a" => bookmarks.extend(anchor.take()),
+            Token::Text(text) if in_heading => {
+                if let Some(Some(name)) = heading.as_mut() {
+                    name.push_str(text);
+                }
+            }
+            Token::Text(text) => {
+                if let Some(bookmark) = anchor.as_mut() {
+                    bookmark.title = Some(text.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+    bookmarks.extend(anchor);
+}
+
+/// A Firefox backup, a tree of `text/x-moz-place-container` folders and
+/// `text/x-moz-place` bookmarks, or Chrome's file, whose `roots` hold
+/// trees of `folder` and `url` nodes.
+fn json_bookmarks(json: &Value, bookmarks: &mut Vec<Saved>) -> Result<()> {
+    if let Some(roots) = json["roots"].as_object() {
+        for root in roots.values() {
+            chrome(root, &mut Vec::new(), bookmarks);
+        }
+        return Ok(());
+    }
+    anyhow::ensure!(
+        json["type"].is_string(),
+        "expected a Firefox or Chrome bookmark file"
+    );
+    firefox(json, &mut Vec::new(), bookmarks);
+    Ok(())
+}
+
+fn firefox(node: &Value, folders: &mut Vec<Option<String>>, bookmarks: &mut Vec<Saved>) {
+    match node["type"].as_str() {
+        Some("text/x-moz-place") => {
+            if let Some(url) = node["uri"].as_str() {
+                let added = node["dateAdded"]
+                    .as_i64()
+                    .and_then(DateTime::from_timestamp_micros);
+                bookmarks.push(bookmark(
+                    url,
+                    node["title"].as_str(),
+                    added,
+                    folders,
+                    node["tags"].as_str().unwrap_or_default(),
+                ));
+            }
+        }
+        Some("text/x-moz-place-container") => {
+            // Containers with a `root` are Firefox's own.
+            let folder = match node["root"].is_string() {
+                true => None,
+                false => node["title"].as_str().map(String::from),
+            };
+            folders.push(folder);
+            for child in node["children"].as_array().into_iter().flatten() {
+                firefox(child, folders, bookmarks);
+            }
+            folders.pop();
+        }
+        _ => {}
+    }
+}
+
+fn chrome(node: &Value, folders: &mut Vec<Option<String>>, bookmarks: &mut Vec<Saved>) {
+    match node["type"].as_str() {
+        Some("url") => {
+            if let Some(url) = node["url"].as_str() {
+                // Microseconds since 1601, as a string.
+                let added = node["date_added"]
+                    .as_str()
+                    .and_then(|t| t.parse::<i64>().ok())
+                    .filter(|t| *t > 0)
+                    .and_then(|t| {
+                        DateTime::from_timestamp_micros(t - WEBKIT_EPOCH_OFFSET * 1_000_000)
+                    });
+                bookmarks.push(bookmark(url, node["name"].as_str(), added, folders, ""));
+            }
+        }
+        Some("folder") => {
+            // The roots are Chrome's own folders.
+            let folder = match folders.is_empty() {
+                true => None,
+                false => node["name"].as_str().map(String::from),
+            };
+            folders.push(folder);
+            for child in node["children"].as_array().into_iter().flatten() {
+                chrome(child, folders, bookmarks);
+            }
+            folders.pop();
+        }
+        _ => {}
+    }
+}
+
+fn bookmark(
+    url: &str,
+    title: Option<&str>,
+    added: Option<DateTime<Utc>>,
+    folders: &[Option<String>],
+    labels: &str,
+) -> Saved {
+    let path: Vec<&str> = folders
+        .iter()
+        .flatten()
+        .map(|f| f.trim())
+        .filter(|f| !f.is_empty())
+        .collect();
+    let mut tags: Vec<String> = labels
+        .split(',')
+        .filter_map(|t| tags::normalize(t).ok())
+        .collect();
+    let mut metadata = Vec::new();
+    if !path.is_empty() {
+        // Slashes within a folder's name would nest it.
+        let segments: Vec<String> = path.iter().map(|f| f.replace('/', "-")).collect();
+        tags.extend(tags::normalize(&segments.join("/")).ok());
+        metadata.push(("bookmark_folder".into(), path.join(" / ")));
+    }
+    tags.sort();
+    tags.dedup();
+    Saved {
+        url: url.to_string(),
+        title: title.filter(|t| !t.is_empty()).map(String::from),
+        saved: added,
+        tags,
+        metadata,
+    }
+}
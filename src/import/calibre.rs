@@ -0,0 +1,372 @@
+//! A Calibre library: a directory of `Author/Title (id)/` folders, each
+//! holding a book's `metadata.opf`, its cover and one file per format.
+//!
+//! Every format of a book is attached to one document, whose text is read
+//! from the first preferred format ozy can read: EPUB, PDF or text.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use super::{metadata_key, tokens, Token};
+use crate::error::OzymandiasError;
+use crate::fingerprint::content_hash;
+use crate::links;
+use crate::parser::{self, strip_tags};
+use crate::storage::BlobStore;
+use crate::tags;
+use crate::types::{Attachment, Document, DocumentId, DocumentKind, SOURCE_HASH_KEY};
+
+/// Prefix of the sources of imported books.
+const SCHEME: &str = "calibre:";
+const METADATA_FILE: &str = "metadata.opf";
+const COVER_FILE: &str = "cover.jpg";
+/// Formats whose text can be read, and how.
+const READABLE: &[&str] = &["epub", "pdf", "txt", "md", "markdown", "html", "htm"];
+/// Folders Calibre keeps beside the books.
+const SKIPPED_DIRS: &[&str] = &[".caltrash", ".calnotes"];
+
+/// A book as `metadata.opf` describes it.
+#[derive(Debug, Default)]
+struct Book {
+    title: String,
+    authors: Vec<String>,
+    description: Option<String>,
+    subjects: Vec<String>,
+    uuid: Option<String>,
+    added: Option<DateTime<Utc>>,
+    fields: Vec<(String, String)>,
+}
+
+/// The books in the library at `dir`. Their files are stored in `blobs`,
+/// and their text read from the first format in `prefer`, such as `epub`,
+/// that a book has.
+pub fn read(dir: &Path, prefer: &[String], blobs: &BlobStore) -> Result<Vec<Document>> {
+    if !dir.join("metadata.db").is_file() {
+        return Err(OzymandiasError::ParseFailed(format!(
+            "{} is not a Calibre library (it has no metadata.db)",
+            dir.display()
+        ))
+        .into());
+    }
+    let mut folders = Vec::new();
+    find_books(dir, &mut folders)?;
+    folders.sort();
+    let mut docs = Vec::new();
+    for folder in folders {
+        let relative = folder.strip_prefix(dir).unwrap_or(&folder).to_path_buf();
+        match book(&folder, &relative, prefer, blobs) {
+            Ok(doc) => docs.push(doc),
+            Err(e) => tracing::warn!("skipped {}: {e:#}", relative.display()),
+        }
+    }
+    Ok(docs)
+}
+
+/// Prefix of the sources of all imported books, as there is one library.
+pub fn scope() -> String {
+    format!("{SCHEME}/")
+}
+
+fn find_books(dir: &Path, folders: &mut Vec<PathBuf>) -> Result<()> {
+    let entries =
+        std::fs::read_dir(dir).with_context(|| format!("cannot read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if !path.is_dir() || SKIPPED_DIRS.contains(&name) {
+            continue;
+        }
+        if path.join(METADATA_FILE).is_file() {
+            folders.push(path);
+        } else {
+            find_books(&path, folders)?;
+        }
+    }
+    Ok(())
+}
+
+fn book(folder: &Path, relative: &Path, prefer: &[String], blobs: &BlobStore) -> Result<Document> {
+    let opf_path = folder.join(METADATA_FILE);
+    let opf = std::fs::read_to_string(&opf_path)
+        .with_context(|| format!("failed to read {}", opf_path.display()))?;
+    let book = metadata(&opf);
+
+    let mut formats: Vec<PathBuf> = std::fs::read_dir(folder)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.is_file())
+        .filter(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+            name != METADATA_FILE && name != COVER_FILE
+        })
+        .collect();
+    formats.sort();
+    let extension = |p: &Path| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    let preferred = prefer.iter().find_map(|format| {
+        formats
+            .iter()
+            .find(|p| extension(p) == format.to_ascii_lowercase())
+    });
+
+    let mut content = format!("# {}\n\n", book.title);
+    let cover = folder.join(COVER_FILE);
+    let mut attachments = Vec::new();
+    if cover.is_file() {
+        let blob = blobs.put(&std::fs::read(&cover)?)?;
+        content.push_str(&format!("![Cover](blob:{blob})\n\n"));
+        attachments.push(Attachment {
+            name: COVER_FILE.into(),
+            mime: Some("image/jpeg".into()),
+            blob,
+            thumbnail: None,
+        });
+    }
+    if !book.authors.is_empty() {
+        content.push_str(&format!("{}\n\n", book.authors.join("; ")));
+    }
+    if let Some(description) = &book.description {
+        content.push_str(&format!("## Description\n\n{description}\n\n"));
+    }
+    let mut text = None;
+    if !formats.is_empty() {
+        content.push_str("## Formats\n\n");
+    }
+    for path in &formats {
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let name = path
+            .file_name()
+            .map_or_else(|| "book".into(), |n| n.to_string_lossy().into_owned());
+        let blob = blobs.put(&bytes)?;
+        content.push_str(&format!(
+            "- [{}](blob:{blob})\n",
+            extension(path).to_uppercase()
+        ));
+        if Some(path) == preferred && READABLE.contains(&extension(path).as_str()) {
+            match book_text(&extension(path), &bytes) {
+                Ok(t) => text = Some(t),
+                Err(e) => tracing::warn!("{}: cannot read {name}: {e:#}", book.title),
+            }
+        }
+        attachments.push(Attachment {
+            name,
+            mime: mime(&extension(path)).map(String::from),
+            blob,
+            thumbnail: None,
+        });
+    }
+    if !formats.is_empty() {
+        content.push('\n');
+    }
+    if let Some(text) = text.filter(|t| !t.trim().is_empty()) {
+        content.push_str(&format!("## Full text\n\n{}\n", text.trim()));
+    }
+
+    let key = book
+        .uuid
+        .clone()
+        .unwrap_or_else(|| relative.display().to_string());
+    let source = format!("{SCHEME}/{key}");
+    let mut doc = Document::new(
+        DocumentId::derive(&source),
+        book.title.clone(),
+        DocumentKind::Markdown,
+        content.trim_end().to_string() + "\n",
+        Some(source),
+    );
+    doc.links = links::extract(&doc.content);
+    doc.metadata
+        .insert(SOURCE_HASH_KEY.into(), content_hash(&doc.content));
+    if !book.authors.is_empty() {
+        doc.metadata
+            .insert("authors".into(), book.authors.join("; "));
+    }
+    let names: Vec<String> = formats
+        .iter()
+        .map(|p| extension(p).to_uppercase())
+        .collect();
+    if !names.is_empty() {
+        doc.metadata.insert("formats".into(), names.join(", "));
+    }
+    if let Some(path) = preferred {
+        doc.metadata
+            .insert("format".into(), extension(path).to_uppercase());
+    }
+    doc.metadata.extend(book.fields);
+    if let Some(added) = book.added {
+        doc.added = added;
+    }
+    for subject in &book.subjects {
+        match tags::normalize(subject) {
+            Ok(tag) if !doc.tags.contains(&tag) => doc.tags.push(tag),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("{}: skipped tag {subject:?}: {e:#}", doc.title),
+        }
+    }
+    doc.tags.sort();
+    doc.attachments = attachments;
+    Ok(doc)
+}
+
+/// Reads the Dublin Core elements and Calibre's `<meta>` of an OPF file.
+fn metadata(opf: &str) -> Book {
+    let mut book = Book::default();
+    let mut open: Option<Token> = None;
+    for token in tokens(opf) {
+        match token {
+            Token::Open { ref name, .. } if name == "meta" => {
+                let (Some(key), Some(value)) =
+                    (token.attribute("name"), token.attribute("content"))
+                else {
+                    continue;
+                };
+                let Some(key) = key.strip_prefix("calibre:") else {
+                    continue;
+                };
+                match key {
+                    "timestamp" => {
+                        book.added = DateTime::parse_from_rfc3339(value)
+                            .ok()
+                            .map(|t| t.with_timezone(&Utc))
+                    }
+                    "series" | "series_index" | "rating" | "publication_type" => {
+                        book.fields.push((key.into(), value.into()))
+                    }
+                    _ => {}
+                }
+            }
+            Token::Open { ref name, .. } if name.starts_with("dc:") => open = Some(token),
+            Token::Text(text) => {
+                let Some(element) = open.take() else {
+                    continue;
+                };
+                let Token::Open { name, .. } = &element else {
+                    continue;
+                };
+                match name.trim_start_matches("dc:") {
+                    "title" => book.title = text,
+                    "creator" => match element.attribute("opf:role").unwrap_or("aut") {
+                        "aut" => book.authors.push(text),
+                        "edt" => book.fields.push(("editors".into(), text)),
+                        role => book
+                            .fields
+                            .push((metadata_key(&format!("{role} creator")), text)),
+                    },
+                    "description" => book.description = Some(strip_tags(&text)),
+                    "subject" => book.subjects.push(text),
+                    "date" => book.fields.push(("published".into(), text)),
+                    "publisher" | "language" => book
+                        .fields
+                        .push((name.trim_start_matches("dc:").into(), text)),
+                    "identifier" => {
+                        let scheme = element
+                            .attribute("opf:scheme")
+                            .unwrap_or_default()
+                            .to_ascii_lowercase();
+                        match scheme.as_str() {
+                            "uuid" => book.uuid = Some(text),
+                            "calibre" => book.fields.push(("calibre_id".into(), text)),
+                            "" => {}
+                            scheme => book.fields.push((metadata_key(scheme), text)),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Token::Close(_) => open = None,
+            _ => {}
+        }
+    }
+    if book.title.is_empty() {
+        book.title = "Untitled".into();
+    }
+    book
+}
+
+fn mime(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "epub" => "application/epub+zip",
+        "pdf" => "application/pdf",
+        "mobi" | "azw" | "azw3" => "application/x-mobipocket-ebook",
+        "txt" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "djvu" => "image/vnd.djvu",
+        "cbz" => "application/vnd.comicbook+zip",
+        _ => return None,
+    })
+}
+
+/// The text of a book file in a [`READABLE`] format.
+fn book_text(extension: &str, bytes: &[u8]) -> Result<String> {
+    match extension {
+        "epub" => epub_text(bytes),
+        "pdf" => parser::pdf_text(bytes),
+        "html" | "htm" => Ok(strip_tags(&String::from_utf8_lossy(bytes))),
+        _ => Ok(String::from_utf8_lossy(bytes).into_owned()),
+    }
+}
+
+/// The text of an EPUB's chapters in reading order, one paragraph each.
+fn epub_text(bytes: &[u8]) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+    let mut entry = |name: &str| -> Result<String> {
+        let mut file = archive
+            .by_name(name)
+            .with_context(|| format!("the EPUB has no {name}"))?;
+        let mut text = String::new();
+        file.read_to_string(&mut text)?;
+        Ok(text)
+    };
+    let container = entry("META-INF/container.xml")?;
+    let opf_path = tokens(&container)
+        .iter()
+        .find_map(|t| t.attribute("full-path").map(String::from))
+        .context("the EPUB names no package file")?;
+    let opf = entry(&opf_path)?;
+    let base = opf_path.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    let mut manifest = HashMap::new();
+    let mut spine = Vec::new();
+    for token in tokens(&opf) {
+        let Token::Open { name, .. } = &token else {
+            continue;
+        };
+        match name.as_str() {
+            "item" => {
+                if let (Some(id), Some(href)) = (token.attribute("id"), token.attribute("href")) {
+                    manifest.insert(id.to_string(), href.to_string());
+                }
+            }
+            "itemref" => spine.extend(token.attribute("idref").map(String::from)),
+            _ => {}
+        }
+    }
+    let mut chapters = Vec::new();
+    for id in spine {
+        let Some(href) = manifest.get(&id) else {
+            continue;
+        };
+        let href = links::percent_decode(href);
+        let path = match base {
+            "" => href,
+            base => format!("{base}/{href}"),
+        };
+        let chapter = strip_tags(&entry(&path)?);
+        if !chapter.is_empty() {
+            chapters.push(chapter);
+        }
+    }
+    Ok(chapters.join("\n\n"))
+}
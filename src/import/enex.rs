@@ -0,0 +1,499 @@
+//! Evernote's `.enex` export of a notebook: XML holding each note's title,
+//! tags, attributes and ENML content, with its images and other files
+//! embedded as base64.
+//!
+//! ENML, Evernote's XHTML dialect, becomes markdown. Embedded files go
+//! into the blob store as attachments of their note, and the places they
+//! appeared in link to them as `blob:<hash>`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use md5::{Digest, Md5};
+use quick_xml::events::{BytesRef, BytesStart, Event};
+use quick_xml::Reader;
+
+use super::metadata_key;
+use crate::error::OzymandiasError;
+use crate::fingerprint::content_hash;
+use crate::links;
+use crate::storage::BlobStore;
+use crate::tags;
+use crate::types::{Attachment, Document, DocumentId, DocumentKind, SOURCE_HASH_KEY};
+
+/// Prefix of the sources of imported notes.
+const SCHEME: &str = "enex:";
+/// Metadata key of the notebook a note was exported from.
+pub const NOTEBOOK_KEY: &str = "notebook";
+
+/// A note as read from the export, before its content is converted.
+#[derive(Debug, Default)]
+struct Note {
+    title: String,
+    content: String,
+    created: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    tags: Vec<String>,
+    attributes: Vec<(String, String)>,
+    /// Embedded files by the MD5 hash ENML refers to them with.
+    resources: HashMap<String, Attachment>,
+}
+
+#[derive(Debug, Default)]
+struct Resource {
+    data: Vec<u8>,
+    mime: Option<String>,
+    name: Option<String>,
+}
+
+/// The notes in the export at `path`, with their embedded files stored in
+/// `blobs`.
+pub fn read(path: &Path, blobs: &BlobStore) -> Result<Vec<Document>> {
+    let xml = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let file = file_name(path);
+    let notebook = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("notebook");
+    let notes = notes(&xml, blobs).with_context(|| {
+        OzymandiasError::ParseFailed(format!("cannot read {} as ENEX", path.display()))
+    })?;
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    Ok(notes
+        .into_iter()
+        .map(|note| {
+            // Notes have no ids in the export; their creation time stays
+            // the same when they are exported again.
+            let key = match note.created {
+                Some(created) => created.format("%Y%m%dT%H%M%SZ").to_string(),
+                None => note.title.clone(),
+            };
+            let n = seen.entry(key.clone()).or_default();
+            *n += 1;
+            let source = match n {
+                1 => format!("{SCHEME}/{file}/{key}"),
+                n => format!("{SCHEME}/{file}/{key} ({n})"),
+            };
+            document(note, source, notebook)
+        })
+        .collect())
+}
+
+/// Prefix of the sources of the notes read from `path`.
+pub fn scope(path: &Path) -> String {
+    format!("{SCHEME}/{}/", file_name(path))
+}
+
+fn file_name(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("notebook.enex")
+}
+
+fn document(note: Note, source: String, notebook: &str) -> Document {
+    let hash = content_hash(&format!(
+        "{}\n{}\n{}",
+        note.title,
+        note.tags.join(","),
+        note.content
+    ));
+    let body = markdown(&note.content, &note.resources);
+    let content = format!("# {}\n\n{body}", note.title);
+    let mut doc = Document::new(
+        DocumentId::derive(&source),
+        note.title,
+        DocumentKind::Markdown,
+        content,
+        Some(source),
+    );
+    doc.links = links::extract(&doc.content);
+    if let Some(created) = note.created {
+        doc.added = created;
+        doc.metadata.insert("created".into(), created.to_rfc3339());
+    }
+    if let Some(updated) = note.updated {
+        doc.metadata.insert("updated".into(), updated.to_rfc3339());
+    }
+    for tag in &note.tags {
+        match tags::normalize(tag) {
+            Ok(tag) if !doc.tags.contains(&tag) => doc.tags.push(tag),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("{}: skipped tag {tag:?}: {e:#}", doc.title),
+        }
+    }
+    doc.tags.sort();
+    doc.metadata.extend(note.attributes);
+    doc.metadata
+        .insert(NOTEBOOK_KEY.into(), notebook.to_string());
+    doc.metadata.insert(SOURCE_HASH_KEY.into(), hash);
+    let mut attachments: Vec<Attachment> = note.resources.into_values().collect();
+    attachments.sort_by(|a, b| a.name.cmp(&b.name));
+    doc.attachments = attachments;
+    doc
+}
+
+fn notes(xml: &str, blobs: &BlobStore) -> Result<Vec<Note>> {
+    let mut reader = Reader::from_str(xml);
+    let mut notes = Vec::new();
+    let mut note: Option<Note> = None;
+    let mut resource: Option<Resource> = None;
+    // Names of the open elements.
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = e.name().as_ref().to_string();
+                match name.as_str() {
+                    "note" => note = Some(Note::default()),
+                    "resource" => resource = Some(Resource::default()),
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Event::Text(t) => text.push_str(&t),
+            Event::CData(t) => text.push_str(&t),
+            Event::GeneralRef(r) => text.push_str(&entity(&r)),
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or_default();
+                let value = std::mem::take(&mut text);
+                let value = value.trim();
+                if let Some(r) = &mut resource {
+                    match (parent, name.as_str()) {
+                        ("resource", "data") => {
+                            let encoded: String =
+                                value.chars().filter(|c| !c.is_whitespace()).collect();
+                            r.data = base64::engine::general_purpose::STANDARD
+                                .decode(encoded)
+                                .context("invalid base64 in a resource")?;
+                        }
+                        ("resource", "mime") => r.mime = Some(value.to_string()),
+                        ("resource-attributes", "file-name") => r.name = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+                let Some(n) = &mut note else {
+                    continue;
+                };
+                match (parent, name.as_str()) {
+                    ("note", "title") => n.title = value.to_string(),
+                    ("note", "content") => n.content = value.to_string(),
+                    ("note", "created") => n.created = timestamp(value),
+                    ("note", "updated") => n.updated = timestamp(value),
+                    ("note", "tag") if !value.is_empty() => n.tags.push(value.to_string()),
+                    ("note-attributes", key) if !value.is_empty() => {
+                        n.attributes.push((metadata_key(key), value.to_string()))
+                    }
+                    ("note", "resource") => {
+                        let r = resource.take().unwrap_or_default();
+                        let md5: String = Md5::digest(&r.data)
+                            .iter()
+                            .map(|b| format!("{b:02x}"))
+                            .collect();
+                        let name = r.name.unwrap_or_else(|| {
+                            let extension = r
+                                .mime
+                                .as_deref()
+                                .and_then(|m| m.split('/').nth(1))
+                                .unwrap_or("bin");
+                            format!("{}.{extension}", &md5[..12])
+                        });
+                        let attachment = Attachment {
+                            name,
+                            mime: r.mime,
+                            blob: blobs.put(&r.data)?,
+                            thumbnail: None,
+                        };
+                        n.resources.insert(md5, attachment);
+                    }
+                    (_, "note") => notes.extend(note.take()),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(notes)
+}
+
+/// Evernote writes times like `20240105T123456Z`.
+fn timestamp(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// The text of `&name;`: the XML entities and the HTML ones common in
+/// ENML. Others are kept as they are.
+fn entity(reference: &BytesRef) -> String {
+    if let Ok(Some(c)) = reference.resolve_char_ref() {
+        return c.to_string();
+    }
+    let name: &str = reference;
+    match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        "nbsp" => " ",
+        "mdash" => "—",
+        "ndash" => "–",
+        "hellip" => "…",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "copy" => "©",
+        _ => return format!("&{name};"),
+    }
+    .to_string()
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .map(|a| {
+            a.normalized_value_with(Default::default(), 16, |_| None)
+                .map_or_else(|_| a.value.to_string(), |v| v.into_owned())
+        })
+}
+
+/// Converts ENML to markdown. Embedded files are looked up in
+/// `resources` by the hash `<en-media>` gives.
+fn markdown(enml: &str, resources: &HashMap<String, Attachment>) -> String {
+    let mut out = Markdown {
+        out: String::new(),
+        resources,
+        links: Vec::new(),
+        lists: Vec::new(),
+        pre: 0,
+    };
+    let mut reader = Reader::from_str(enml);
+    loop {
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            // Whatever could be read is better than nothing.
+            Err(e) => {
+                tracing::warn!("malformed note content: {e}");
+                break;
+            }
+        };
+        match event {
+            Event::Start(e) => out.start(&e),
+            Event::Empty(e) => {
+                out.start(&e);
+                out.end(e.name().as_ref());
+            }
+            Event::End(e) => out.end(e.name().as_ref()),
+            Event::Text(t) => out.text(&t),
+            Event::CData(t) => out.text(&t),
+            Event::GeneralRef(r) => out.text(&entity(&r)),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    out.finish()
+}
+
+/// Markdown written while walking through ENML.
+struct Markdown<'a> {
+    out: String,
+    resources: &'a HashMap<String, Attachment>,
+    /// Where the text of each open link starts, and its target.
+    links: Vec<(usize, String)>,
+    /// The next number of each open list, or none for a bulleted one.
+    lists: Vec<Option<usize>>,
+    /// Inside `<pre>`, where whitespace is kept.
+    pre: usize,
+}
+
+impl Markdown<'_> {
+    fn start(&mut self, e: &BytesStart) {
+        match e.name().as_ref() {
+            "div" | "tr" => self.line(),
+            "p" | "table" | "blockquote" => self.paragraph(),
+            "br" => self.out.push('\n'),
+            "hr" => {
+                self.paragraph();
+                self.out.push_str("---\n\n");
+            }
+            h @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                self.paragraph();
+                let level = h[1..].parse().unwrap_or(1);
+                self.out.push_str(&format!("{} ", "#".repeat(level)));
+            }
+            "b" | "strong" => self.out.push_str("**"),
+            "i" | "em" => self.out.push('*'),
+            "s" | "strike" | "del" => self.out.push_str("~~"),
+            "code" if self.pre == 0 => self.out.push('`'),
+            "pre" => {
+                self.paragraph();
+                self.out.push_str("```\n");
+                self.pre += 1;
+            }
+            name @ ("ul" | "ol") => {
+                if self.lists.is_empty() {
+                    self.paragraph();
+                }
+                self.lists.push((name == "ol").then_some(1));
+            }
+            "li" => {
+                self.line();
+                let depth = self.lists.len().saturating_sub(1);
+                self.out.push_str(&"  ".repeat(depth));
+                match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        self.out.push_str(&format!("{n}. "));
+                        *n += 1;
+                    }
+                    _ => self.out.push_str("- "),
+                }
+            }
+            "a" => {
+                let href = attribute(e, "href").unwrap_or_default();
+                self.out.push('[');
+                self.links.push((self.out.len(), href));
+            }
+            "img" => {
+                if let Some(src) = attribute(e, "src") {
+                    self.out.push_str(&format!("![]({src})"));
+                }
+            }
+            "en-todo" => {
+                let checked = attribute(e, "checked").is_some_and(|c| c == "true");
+                // A checkbox starting a line makes it a task list item.
+                if self
+                    .out
+                    .rsplit('\n')
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .is_empty()
+                {
+                    self.out.push_str("- ");
+                }
+                self.out.push_str(if checked { "[x] " } else { "[ ] " });
+            }
+            "en-media" => {
+                let hash = attribute(e, "hash").unwrap_or_default();
+                match self.resources.get(&hash) {
+                    Some(a) => {
+                        let image = a.mime.as_deref().is_some_and(|m| m.starts_with("image/"));
+                        let bang = if image { "!" } else { "" };
+                        self.out
+                            .push_str(&format!("{bang}[{}](blob:{})", a.name, a.blob));
+                    }
+                    None => self.out.push_str("[missing attachment]"),
+                }
+            }
+            "en-crypt" => self.out.push_str("[encrypted content]"),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, name: &str) {
+        match name {
+            "div" | "tr" => self.line(),
+            "p" | "table" | "blockquote" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.paragraph()
+            }
+            "td" | "th" => self.out.push_str(" | "),
+            "b" | "strong" => self.out.push_str("**"),
+            "i" | "em" => self.out.push('*'),
+            "s" | "strike" | "del" => self.out.push_str("~~"),
+            "code" if self.pre == 0 => self.out.push('`'),
+            "pre" => {
+                self.pre = self.pre.saturating_sub(1);
+                self.line();
+                self.out.push_str("```\n\n");
+            }
+            "ul" | "ol" => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.paragraph();
+                }
+            }
+            "a" => {
+                if let Some((start, href)) = self.links.pop() {
+                    if self.out.len() == start || href.is_empty() {
+                        self.out.push(']');
+                    } else {
+                        self.out.push_str(&format!("]({href})"));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Text with runs of whitespace as one space, except in `<pre>`.
+    fn text(&mut self, text: &str) {
+        if self.pre > 0 {
+            self.out.push_str(text);
+            return;
+        }
+        let at_start = self.out.is_empty() || self.out.ends_with([' ', '\n', '[']);
+        if text.starts_with(char::is_whitespace) && !at_start {
+            self.out.push(' ');
+        }
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                self.out.push(' ');
+            }
+            self.out.push_str(word);
+        }
+        if text.ends_with(char::is_whitespace) && !text.trim().is_empty() {
+            self.out.push(' ');
+        }
+    }
+
+    /// Starts a new line, unless the current one holds nothing yet but a
+    /// list marker or heading.
+    fn line(&mut self) {
+        let current = self.out.rsplit('\n').next().unwrap_or_default().trim();
+        let marker = current == "-"
+            || current.chars().all(|c| c == '#')
+            || current
+                .strip_suffix('.')
+                .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()));
+        if !marker {
+            self.out.push('\n');
+        }
+    }
+
+    fn paragraph(&mut self) {
+        self.line();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// The markdown with trailing spaces and extra blank lines removed.
+    fn finish(self) -> String {
+        let mut out = String::new();
+        let mut blank = true;
+        for line in self.out.lines().map(str::trim_end) {
+            if line.is_empty() {
+                if !blank {
+                    out.push('\n');
+                }
+                blank = true;
+            } else {
+                out.push_str(line);
+                out.push('\n');
+                blank = false;
+            }
+        }
+        out.trim_end().to_string() + "\n"
+    }
+}
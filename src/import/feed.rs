@@ -0,0 +1,339 @@
+//! RSS and Atom feeds, pulled through the shared web client, whose entries
+//! are clipped like saved pages. Categories become tags, and when an entry
+//! was published becomes when it was saved.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use quick_xml::events::{BytesRef, BytesStart, Event};
+use quick_xml::Reader;
+
+use super::Saved;
+use crate::error::OzymandiasError;
+use crate::tags;
+use crate::web::{self, Client};
+
+/// Metadata key of the feed an entry was pulled from.
+pub const FEED_KEY: &str = "feed";
+
+/// The entries of the feed at `url`.
+pub async fn read(client: &Client, url: &str) -> Result<Vec<Saved>> {
+    let response = client.get(url).await?;
+    let final_url = response.url().to_string();
+    let xml = response
+        .text()
+        .await
+        .with_context(|| format!("failed to read {url}"))?;
+    let entries = parse(&xml, &final_url);
+    anyhow::ensure!(
+        !entries.is_empty() || is_feed(&xml),
+        OzymandiasError::ParseFailed(format!("{url} is not an RSS or Atom feed"))
+    );
+    Ok(entries)
+}
+
+/// Whether `xml` looks like a feed, even an empty one.
+fn is_feed(xml: &str) -> bool {
+    ["<rss", "<feed", "<rdf:RDF"]
+        .iter()
+        .any(|t| xml.contains(t))
+}
+
+/// The entries of RSS 2.0 `<item>`s, RSS 1.0 `<item>`s or Atom `<entry>`s,
+/// with relative links resolved against `url`.
+pub fn parse(xml: &str, url: &str) -> Vec<Saved> {
+    let mut reader = Reader::from_str(xml);
+    let mut entries = Vec::new();
+    let mut feed_title: Option<String> = None;
+    // Names of the open elements, and how many were open around the entry
+    // being read.
+    let mut path: Vec<String> = Vec::new();
+    let mut entry: Option<(usize, Entry)> = None;
+    let mut text = String::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = e.local_name().as_ref().to_string();
+                match &mut entry {
+                    None if name == "item" || name == "entry" => {
+                        entry = Some((path.len(), Entry::default()));
+                    }
+                    Some((depth, found)) if path.len() == *depth + 1 => found.element(&e),
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            Ok(Event::Empty(e)) => {
+                if let Some((depth, found)) = &mut entry {
+                    if path.len() == *depth + 1 {
+                        found.element(&e);
+                    }
+                }
+            }
+            Ok(Event::Text(t)) => text.push_str(&t),
+            Ok(Event::CData(t)) => text.push_str(&t),
+            Ok(Event::GeneralRef(r)) => text.push_str(&entity(&r)),
+            Ok(Event::End(_)) => {
+                let name = path.pop().unwrap_or_default();
+                let value = std::mem::take(&mut text);
+                let value = value.trim();
+                match &mut entry {
+                    Some((depth, _)) if path.len() == *depth => {
+                        let (_, found) = entry.take().expect("an entry is open");
+                        entries.extend(found.saved(url, feed_title.as_deref()));
+                    }
+                    Some((depth, found)) if path.len() == *depth + 1 => found.text(&name, value),
+                    None if name == "title" && feed_title.is_none() && !value.is_empty() => {
+                        feed_title = Some(value.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                tracing::warn!("stopped reading the feed at {url}: {e}");
+                break;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// What an entry says of itself.
+#[derive(Default)]
+struct Entry {
+    title: Option<String>,
+    link: Option<String>,
+    /// An RSS `<guid>` that is the entry's permalink.
+    permalink: Option<String>,
+    published: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    categories: Vec<String>,
+}
+
+impl Entry {
+    /// Reads what an Atom element keeps in attributes.
+    fn element(&mut self, element: &BytesStart) {
+        match element.local_name().as_ref() {
+            // The page of an entry is its link without a `rel`, or with
+            // `rel="alternate"`; others lead to comments, enclosures and
+            // the like.
+            "link" => {
+                let rel = attribute(element, "rel");
+                if let (Some(href), None | Some("alternate")) =
+                    (attribute(element, "href"), rel.as_deref())
+                {
+                    self.link.get_or_insert(href);
+                }
+            }
+            "category" => self.categories.extend(attribute(element, "term")),
+            "guid" if attribute(element, "isPermaLink").as_deref() == Some("false") => {
+                // Marks the text that follows as no link.
+                self.permalink = Some(String::new());
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads the text of an element of the entry.
+    fn text(&mut self, name: &str, value: &str) {
+        if value.is_empty() {
+            return;
+        }
+        match name {
+            "title" => self.title = Some(value.to_string()),
+            "link" => {
+                self.link.get_or_insert_with(|| value.to_string());
+            }
+            "guid" if self.permalink.is_none() => self.permalink = Some(value.to_string()),
+            "category" | "subject" => self.categories.push(value.to_string()),
+            // RSS 2.0, Atom, and RSS 1.0 with Dublin Core.
+            "pubDate" | "published" | "date" => {
+                self.published = self.published.or_else(|| date(value));
+            }
+            "updated" => self.updated = date(value),
+            _ => {}
+        }
+    }
+
+    fn saved(self, feed_url: &str, feed_title: Option<&str>) -> Option<Saved> {
+        let link = self
+            .link
+            .or(self.permalink.filter(|p| !p.is_empty()))
+            .map(|l| web::resolve(feed_url, &l))?;
+        if !link.starts_with("http://") && !link.starts_with("https://") {
+            tracing::debug!("skipped feed entry {link}");
+            return None;
+        }
+        Some(Saved {
+            url: link,
+            title: self.title,
+            saved: self.published.or(self.updated),
+            tags: self
+                .categories
+                .iter()
+                .filter_map(|c| tags::normalize(c).ok())
+                .collect(),
+            metadata: vec![(FEED_KEY.into(), feed_title.unwrap_or(feed_url).to_string())],
+        })
+    }
+}
+
+/// A date as RSS (RFC 2822) or Atom (RFC 3339) writes it.
+fn date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.as_ref() == name)
+        .map(|a| {
+            a.normalized_value_with(Default::default(), 16, |_| None)
+                .map_or_else(|_| a.value.to_string(), |v| v.into_owned())
+        })
+}
+
+fn entity(reference: &BytesRef) -> String {
+    if let Ok(Some(c)) = reference.resolve_char_ref() {
+        return c.to_string();
+    }
+    let name: &str = reference;
+    match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        _ => return format!("&{name};"),
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Example &amp; Co</title>
+    <link>https://example.com/</link>
+    <item>
+      <title><![CDATA[First <post>]]></title>
+      <link>/posts/1</link>
+      <pubDate>Tue, 10 Jun 2025 04:00:00 GMT</pubDate>
+      <category>Rust Lang</category>
+      <category>news/weekly</category>
+    </item>
+    <item>
+      <title>Only a guid</title>
+      <guid>https://example.com/posts/2</guid>
+    </item>
+    <item>
+      <title>Not a link</title>
+      <guid isPermaLink="false">urn:uuid:1234</guid>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Example</title>
+  <entry>
+    <title>Atom post</title>
+    <link rel="replies" href="https://example.com/comments"/>
+    <link href="entries/1"/>
+    <author><name>Someone</name><title>Not the entry's</title></author>
+    <updated>2025-06-11T08:30:00+02:00</updated>
+    <category term="Tips"/>
+  </entry>
+  <entry>
+    <title>Mail</title>
+    <link href="mailto:me@example.com"/>
+  </entry>
+</feed>"#;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        date(text).unwrap()
+    }
+
+    #[test]
+    fn rss_items_become_saved_pages() {
+        let saved = parse(RSS, "https://example.com/feed.xml");
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].url, "https://example.com/posts/1");
+        assert_eq!(saved[0].title.as_deref(), Some("First <post>"));
+        assert_eq!(saved[0].saved, Some(at("2025-06-10T04:00:00Z")));
+        assert_eq!(saved[0].tags, ["rust-lang", "news/weekly"]);
+        assert_eq!(
+            saved[0].metadata,
+            [(FEED_KEY.to_string(), "Example & Co".to_string())]
+        );
+        assert_eq!(saved[1].url, "https://example.com/posts/2");
+        assert_eq!(saved[1].saved, None);
+    }
+
+    #[test]
+    fn atom_entries_link_to_their_page_not_to_replies() {
+        let saved = parse(ATOM, "https://example.com/blog/atom.xml");
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].url, "https://example.com/blog/entries/1");
+        assert_eq!(saved[0].title.as_deref(), Some("Atom post"));
+        assert_eq!(saved[0].saved, Some(at("2025-06-11T06:30:00Z")));
+        assert_eq!(saved[0].tags, ["tips"]);
+        assert_eq!(saved[0].metadata[0].1, "Example");
+    }
+
+    #[test]
+    fn rss_1_0_items_are_dated_by_dublin_core() {
+        let xml = r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+            xmlns="http://purl.org/rss/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+          <channel><title>Old</title></channel>
+          <item>
+            <title>Item</title>
+            <link>https://example.org/item</link>
+            <dc:date>2024-01-02T03:04:05Z</dc:date>
+            <dc:subject>History</dc:subject>
+          </item>
+        </rdf:RDF>"#;
+        let saved = parse(xml, "https://example.org/rss");
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].saved, Some(at("2024-01-02T03:04:05Z")));
+        assert_eq!(saved[0].tags, ["history"]);
+        assert_eq!(saved[0].metadata[0].1, "Old");
+    }
+
+    #[test]
+    fn a_feed_without_a_title_is_named_by_its_url() {
+        let xml = "<rss><channel><item><link>https://a.org/x</link></item></channel></rss>";
+        let saved = parse(xml, "https://a.org/feed");
+        assert_eq!(saved[0].metadata[0].1, "https://a.org/feed");
+        assert_eq!(saved[0].title, None);
+    }
+
+    #[test]
+    fn broken_xml_keeps_the_entries_read_before_it() {
+        let xml = "<rss><channel>\
+            <item><link>https://a.org/1</link></item>\
+            <item><link>https://a.org/2</link></oops>";
+        let saved = parse(xml, "https://a.org/feed");
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].url, "https://a.org/1");
+    }
+
+    #[test]
+    fn empty_feeds_are_told_from_pages() {
+        assert!(is_feed(
+            "<?xml version=\"1.0\"?><rss version=\"2.0\"></rss>"
+        ));
+        assert!(is_feed("<feed xmlns=\"http://www.w3.org/2005/Atom\"/>"));
+        assert!(!is_feed("<html><body>Not a feed</body></html>"));
+        assert!(parse("<html><body>Not a feed</body></html>", "https://a.org").is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Instapaper's CSV export: `URL,Title,Selection,Folder,Timestamp`, and
+//! in newer exports `Tags`, a JSON list.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use super::{Saved, TO_READ_TAG};
+use crate::error::OzymandiasError;
+use crate::tags;
+
+/// The saved pages in the export at `path`.
+pub fn read(path: &Path) -> Result<Vec<Saved>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    saved(file).with_context(|| {
+        OzymandiasError::ParseFailed(format!(
+            "cannot read {} as an Instapaper export",
+            path.display()
+        ))
+    })
+}
+
+fn saved(file: std::fs::File) -> Result<Vec<Saved>> {
+    let mut reader = csv::Reader::from_reader(file);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let url = column("url").context("the CSV has no URL column")?;
+    let (title, selection, folder, timestamp, labels) = (
+        column("title"),
+        column("selection"),
+        column("folder"),
+        column("timestamp"),
+        column("tags"),
+    );
+    let mut saved = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: Option<usize>| i.and_then(|i| record.get(i)).filter(|f| !f.is_empty());
+        let Some(url) = field(Some(url)) else {
+            continue;
+        };
+        let folder = field(folder).unwrap_or("Unread");
+        let mut tags: Vec<String> = field(labels)
+            .and_then(|l| serde_json::from_str::<Vec<String>>(l).ok())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|t| tags::normalize(t).ok())
+            .collect();
+        // Folders other than Instapaper's own are tags.
+        match folder.to_ascii_lowercase().as_str() {
+            "unread" => tags.push(TO_READ_TAG.into()),
+            "archive" => {}
+            "starred" => tags.push("starred".into()),
+            _ => tags.extend(tags::normalize(folder).ok()),
+        }
+        let mut metadata = vec![("instapaper_folder".into(), folder.to_string())];
+        if let Some(selection) = field(selection) {
+            metadata.push(("selection".into(), selection.to_string()));
+        }
+        saved.push(Saved {
+            url: url.to_string(),
+            title: field(title).map(String::from),
+            saved: field(timestamp)
+                .and_then(|t| t.trim().parse().ok())
+                .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+            tags,
+            metadata,
+        });
+    }
+    Ok(saved)
+}
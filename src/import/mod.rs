@@ -0,0 +1,205 @@
+//! Reading documents out of exports of other note-taking tools, for
+//! `ozy import`. Importers set each document's `source` to a pseudo-path
+//! such as `notion:/Page.md`, so that importing a newer export updates the
+//! documents rather than adding them again.
+//!
+//! Read-later lists, bookmarks and feeds are read as [`Saved`] pages
+//! instead, which are clipped from the web like `ozy add --url`.
+
+pub mod bibtex;
+pub mod bookmarks;
+pub mod calibre;
+pub mod enex;
+pub mod feed;
+pub mod instapaper;
+pub mod notion;
+pub mod pocket;
+pub mod roam;
+pub mod zotero;
+
+use std::io::{Cursor, Read, Seek};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::error::OzymandiasError;
+use crate::parser::decode_entities;
+
+/// Tag of pages saved to read later and not yet read.
+pub const TO_READ_TAG: &str = "toread";
+
+/// A web page saved in a read-later service or as a bookmark.
+#[derive(Debug, Clone)]
+pub struct Saved {
+    pub url: String,
+    /// The title it was saved under, for when the page cannot be fetched.
+    pub title: Option<String>,
+    pub saved: Option<DateTime<Utc>>,
+    /// Normalized tags.
+    pub tags: Vec<String>,
+    /// Other fields of the export, as metadata.
+    pub metadata: Vec<(String, String)>,
+}
+
+/// A piece of HTML as read by [`tokens`].
+#[derive(Debug)]
+pub enum Token {
+    /// A start tag, with its lowercase name and attributes, whose values
+    /// are decoded.
+    Open {
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    Close(String),
+    Text(String),
+}
+
+impl Token {
+    /// The value of attribute `name`, given in lowercase, of a start tag.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        match self {
+            Token::Open { attributes, .. } => attributes
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.as_str()),
+            _ => None,
+        }
+    }
+}
+
+/// The tags and text of loosely written HTML, such as exported bookmark
+/// lists, which often leave elements unclosed. Comments and doctypes are
+/// dropped.
+pub fn tokens(html: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let text = decode_entities(rest[..start].trim());
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        rest = &rest[start..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = tag_end(rest) else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(name.trim().to_ascii_lowercase()));
+        } else if !tag.starts_with('!') && !tag.starts_with('?') {
+            tokens.push(open_tag(tag.trim_end_matches('/')));
+        }
+    }
+    let text = decode_entities(rest.trim());
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    tokens
+}
+
+/// The index of the `>` closing the tag `s` starts with, skipping quoted
+/// attribute values.
+fn tag_end(s: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn open_tag(tag: &str) -> Token {
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let name = tag[..name_end].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    let mut rest = tag[name_end..].trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..key_end].to_ascii_lowercase();
+        rest = rest[key_end..].trim_start();
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, remaining) = match after.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let body = &after[1..];
+                    let end = body.find(q).unwrap_or(body.len());
+                    (&body[..end], body.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = decode_entities(raw);
+            rest = remaining.trim_start();
+        }
+        if !key.is_empty() {
+            attributes.push((key, value));
+        }
+    }
+    Token::Open { name, attributes }
+}
+
+/// The files in a zip archive, with their paths inside it. Zips inside the
+/// archive are unpacked too.
+pub fn read_zip(path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let mut entries = Vec::new();
+    unpack(file, &mut entries).with_context(|| {
+        OzymandiasError::ParseFailed(format!("cannot read {} as a zip archive", path.display()))
+    })?;
+    Ok(entries)
+}
+
+fn unpack(reader: impl Read + Seek, entries: &mut Vec<(String, Vec<u8>)>) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(reader)?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name()?.to_string();
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        if name.to_ascii_lowercase().ends_with(".zip") {
+            unpack(Cursor::new(bytes), entries)?;
+        } else {
+            entries.push((name, bytes));
+        }
+    }
+    Ok(())
+}
+
+/// A field name such as `Created time` or `publicationTitle` as a
+/// metadata key, `created_time` or `publication_title`.
+pub fn metadata_key(name: &str) -> String {
+    let mut spaced = String::with_capacity(name.len());
+    let mut previous = ' ';
+    for c in name.chars() {
+        if c.is_uppercase() && previous.is_lowercase() {
+            spaced.push(' ');
+        }
+        spaced.push(c);
+        previous = c;
+    }
+    spaced
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("_")
+}
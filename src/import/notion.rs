@@ -0,0 +1,194 @@
+//! Notion's "Markdown & CSV" export: a page is `Title <id>.md`, its
+//! subpages sit in the folder `Title <id>/`, and a database is
+//! `Name <id>.csv` with the pages of its rows in `Name <id>/`.
+//!
+//! The folders a page sits in become its namespace, so `Projects/Ozy/Plan`
+//! is an alias of the page `Plan`, and it is `part-of` its parent page.
+//! The columns of a database row become metadata of its page.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::metadata_key;
+use crate::fingerprint::content_hash;
+use crate::parser::{MarkdownParser, Parser};
+use crate::relations::{Relation, RelationKind};
+use crate::types::{Document, DocumentId, DocumentKind, SOURCE_HASH_KEY};
+
+/// Prefix of the sources of imported pages.
+const SCHEME: &str = "notion:";
+/// Metadata keys of the database a row belongs to and the path of the
+/// page's ancestors.
+pub const DATABASE_KEY: &str = "database";
+pub const NAMESPACE_KEY: &str = "namespace";
+
+/// The columns of a database row as metadata keys and values.
+type Properties = Vec<(String, String)>;
+
+/// The pages and database rows in the export at `zip`.
+pub fn read(zip: &Path) -> Result<Vec<Document>> {
+    let entries: BTreeMap<String, Vec<u8>> = super::read_zip(zip)?.into_iter().collect();
+    let pages: Vec<&str> = entries
+        .keys()
+        .map(String::as_str)
+        .filter(|p| p.ends_with(".md"))
+        .collect();
+
+    let mut rows: HashMap<&str, (String, Properties)> = HashMap::new();
+    let mut docs = Vec::new();
+    for (path, bytes) in &entries {
+        let Some(folder) = path.strip_suffix(".csv") else {
+            continue;
+        };
+        // Newer exports write both the current view and every row.
+        let folder = match folder.strip_suffix("_all") {
+            Some(folder) => folder,
+            None if entries.contains_key(&format!("{folder}_all.csv")) => continue,
+            None => folder,
+        };
+        let database = strip_id(file_name(folder));
+        let bytes = bytes.strip_prefix("\u{feff}".as_bytes()).unwrap_or(bytes);
+        let mut reader = csv::Reader::from_reader(bytes);
+        let columns = reader
+            .headers()
+            .with_context(|| format!("cannot read {path}"))?
+            .clone();
+        let mut taken = HashSet::new();
+        for record in reader.records() {
+            let record = record.with_context(|| format!("cannot read {path}"))?;
+            let Some(title) = record.get(0).filter(|t| !t.is_empty()) else {
+                continue;
+            };
+            let properties: Properties = columns
+                .iter()
+                .zip(record.iter())
+                .skip(1)
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(column, value)| (metadata_key(column), value.to_string()))
+                .collect();
+            // Notion changes characters it cannot put in file names, so
+            // pages are matched on the letters and digits of their title.
+            let page = pages.iter().find(|p| {
+                parent(p) == folder
+                    && loose(strip_id(stem(p))) == loose(title)
+                    && !taken.contains(*p)
+            });
+            match page {
+                Some(page) => {
+                    taken.insert(*page);
+                    rows.insert(page, (database.to_string(), properties));
+                }
+                None => docs.push(row(folder, database, title, &record, properties)),
+            }
+        }
+    }
+
+    for page in &pages {
+        let raw = String::from_utf8_lossy(&entries[*page]);
+        let parsed = MarkdownParser.parse(&raw, strip_id(stem(page)))?;
+        let source = format!("{SCHEME}/{page}");
+        let mut doc = Document::new(
+            DocumentId::derive(&source),
+            parsed.title,
+            DocumentKind::Markdown,
+            parsed.content,
+            Some(source),
+        );
+        doc.links = parsed.links;
+        doc.metadata
+            .insert(SOURCE_HASH_KEY.into(), content_hash(&raw));
+        let namespace: Vec<&str> = parent(page)
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(strip_id)
+            .collect();
+        if !namespace.is_empty() {
+            let namespace = namespace.join("/");
+            doc.aliases.push(format!("{namespace}/{}", doc.title));
+            doc.metadata.insert(NAMESPACE_KEY.into(), namespace);
+        }
+        let parent_page = format!("{}.md", parent(page));
+        if entries.contains_key(&parent_page) {
+            doc.relations.push(Relation {
+                kind: RelationKind::PartOf,
+                target: DocumentId::derive(&format!("{SCHEME}/{parent_page}")),
+                confidence: 1.0,
+            });
+        }
+        if let Some((database, properties)) = rows.remove(page) {
+            doc.metadata.extend(properties);
+            doc.metadata.insert(DATABASE_KEY.into(), database);
+        }
+        docs.push(doc);
+    }
+    let skipped = entries.len() - pages.len();
+    tracing::debug!("skipped {skipped} files of the export that are not pages");
+    Ok(docs)
+}
+
+/// Prefix of the sources of all imported pages, as an export holds every
+/// page of the workspace.
+pub fn scope() -> String {
+    format!("{SCHEME}/")
+}
+
+/// A database row that has no page of its own.
+fn row(
+    folder: &str,
+    database: &str,
+    title: &str,
+    record: &csv::StringRecord,
+    properties: Properties,
+) -> Document {
+    let source = format!("{SCHEME}/{folder}/{title}");
+    let mut content = format!("# {title}\n\n");
+    for (key, value) in &properties {
+        content.push_str(&format!("{key}: {value}\n"));
+    }
+    let mut doc = Document::new(
+        DocumentId::derive(&source),
+        title.to_string(),
+        DocumentKind::Markdown,
+        content,
+        Some(source),
+    );
+    let line: Vec<&str> = record.iter().collect();
+    doc.metadata
+        .insert(SOURCE_HASH_KEY.into(), content_hash(&line.join("\t")));
+    doc.metadata.extend(properties);
+    doc.metadata
+        .insert(DATABASE_KEY.into(), database.to_string());
+    doc
+}
+
+/// Drops the page id Notion appends to names, as in
+/// `Plan 0123456789abcdef0123456789abcdef`.
+fn strip_id(name: &str) -> &str {
+    match name.rsplit_once(' ') {
+        Some((name, id)) if id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()) => name,
+        _ => name,
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map_or(path, |(_, name)| name)
+}
+
+fn stem(path: &str) -> &str {
+    let name = file_name(path);
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem)
+}
+
+fn loose(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,130 @@
+//! Pocket's export: `ril_export.html`, with unread and archived lists, or
+//! the CSV of newer exports, possibly zipped in parts.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use super::{read_zip, tokens, Saved, Token, TO_READ_TAG};
+use crate::error::OzymandiasError;
+use crate::tags;
+
+/// The saved pages in the export at `path`: HTML, CSV, or a zip of CSVs.
+pub fn read(path: &Path) -> Result<Vec<Saved>> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let not_pocket = || {
+        OzymandiasError::ParseFailed(format!("cannot read {} as a Pocket export", path.display()))
+    };
+    if extension == "zip" {
+        let mut saved = Vec::new();
+        for (name, bytes) in read_zip(path)? {
+            if name.to_ascii_lowercase().ends_with(".csv") {
+                saved.extend(csv(&bytes).with_context(not_pocket)?);
+            }
+        }
+        return Ok(saved);
+    }
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    match extension.as_str() {
+        "csv" => csv(&bytes).with_context(not_pocket),
+        _ => Ok(html(&String::from_utf8_lossy(&bytes))),
+    }
+}
+
+/// `<h1>Unread</h1><ul><li><a href=… time_added=… tags=…>Title</a>`, then
+/// the same under `<h1>Read Archive</h1>`.
+fn html(html: &str) -> Vec<Saved> {
+    let mut saved = Vec::new();
+    let mut heading = false;
+    let mut archived = false;
+    let mut anchor: Option<Saved> = None;
+    for token in tokens(html) {
+        match &token {
+            Token::Open { name, .. } if name == "h1" => heading = true,
+            Token::Close(name) if name == "h1" => heading = false,
+            Token::Text(text) if heading => archived = text.to_lowercase().contains("archive"),
+            Token::Open { name, .. } if name == "a" => {
+                let Some(url) = token.attribute("href").filter(|u| !u.is_empty()) else {
+                    continue;
+                };
+                anchor = Some(item(
+                    url,
+                    None,
+                    token.attribute("time_added"),
+                    token.attribute("tags").unwrap_or_default().split(','),
+                    archived,
+                ));
+            }
+            Token::Text(text) => {
+                if let Some(item) = anchor.as_mut() {
+                    item.title = Some(text.clone());
+                }
+            }
+            Token::Close(name) if name == "a" => saved.extend(anchor.take()),
+            _ => {}
+        }
+    }
+    saved.extend(anchor);
+    saved
+}
+
+/// `title,url,time_added,tags,status`, with tags separated by `|` and a
+/// status of `unread` or `archive`.
+fn csv(bytes: &[u8]) -> Result<Vec<Saved>> {
+    let mut reader = csv::Reader::from_reader(bytes);
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let url = column("url").context("the CSV has no url column")?;
+    let (title, added, tags, status) = (
+        column("title"),
+        column("time_added"),
+        column("tags"),
+        column("status"),
+    );
+    let mut saved = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |i: Option<usize>| i.and_then(|i| record.get(i)).filter(|f| !f.is_empty());
+        let Some(url) = field(Some(url)) else {
+            continue;
+        };
+        saved.push(item(
+            url,
+            field(title),
+            field(added),
+            field(tags).unwrap_or_default().split('|'),
+            field(status) == Some("archive"),
+        ));
+    }
+    Ok(saved)
+}
+
+fn item<'a>(
+    url: &str,
+    title: Option<&str>,
+    added: Option<&str>,
+    labels: impl Iterator<Item = &'a str>,
+    archived: bool,
+) -> Saved {
+    let mut tags: Vec<String> = labels.filter_map(|t| tags::normalize(t).ok()).collect();
+    if !archived {
+        tags.push(TO_READ_TAG.into());
+    }
+    tags.dedup();
+    let status = if archived { "archive" } else { "unread" };
+    Saved {
+        url: url.to_string(),
+        title: title.map(String::from),
+        saved: added
+            .and_then(|t| t.trim().parse().ok())
+            .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+        tags,
+        metadata: vec![("pocket_status".into(), status.into())],
+    }
+}
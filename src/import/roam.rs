@@ -0,0 +1,482 @@
+//! A Roam Research graph, exported as JSON or as EDN. Each page becomes a
+//! markdown document in which blocks with children are headings over
+//! their children, down to four levels, and deeper blocks nested lists.
+//!
+//! `((block refs))` become links to the page holding the block, labelled
+//! with its text, and daily notes, titled like `January 5th, 2024`,
+//! become journal entries titled `2024-01-05`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Deserialize;
+
+use super::metadata_key;
+use crate::error::OzymandiasError;
+use crate::fingerprint::content_hash;
+use crate::links;
+use crate::types::{Document, DocumentId, DocumentKind, SOURCE_HASH_KEY};
+
+/// Prefix of the sources of imported pages.
+const SCHEME: &str = "roam:";
+/// Tag of daily notes.
+pub const JOURNAL_TAG: &str = "journal";
+/// Block levels rendered as headings; deeper blocks become lists.
+const HEADING_LEVELS: usize = 4;
+/// Longest label, in characters, of a link replacing a block ref.
+const MAX_LABEL: usize = 80;
+
+#[derive(Debug, Default, Deserialize)]
+struct Page {
+    title: String,
+    #[serde(default)]
+    children: Vec<Block>,
+    #[serde(rename = "create-time")]
+    created: Option<i64>,
+    #[serde(rename = "edit-time")]
+    edited: Option<i64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Block {
+    #[serde(default)]
+    string: String,
+    uid: Option<String>,
+    #[serde(default)]
+    children: Vec<Block>,
+}
+
+/// The pages of the graph exported to `path`, as JSON or, if it ends in
+/// `.edn`, as EDN.
+pub fn read(path: &Path) -> Result<Vec<Document>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let edn = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("edn"));
+    let pages = match edn {
+        true => edn::pages(&text),
+        false => serde_json::from_str::<Vec<Page>>(&text).map_err(Into::into),
+    }
+    .with_context(|| {
+        OzymandiasError::ParseFailed(format!("cannot read {} as a Roam export", path.display()))
+    })?;
+    let graph = graph_name(path);
+
+    // Block refs name blocks on any page.
+    let mut blocks = HashMap::new();
+    for page in &pages {
+        index(&page.title, &page.children, &mut blocks);
+    }
+    Ok(pages
+        .iter()
+        .map(|page| document(page, graph, &blocks))
+        .collect())
+}
+
+/// Prefix of the sources of the pages read from `path`.
+pub fn scope(path: &Path) -> String {
+    format!("{SCHEME}/{}/", graph_name(path))
+}
+
+fn graph_name(path: &Path) -> &str {
+    path.file_stem().and_then(|s| s.to_str()).unwrap_or("roam")
+}
+
+fn index<'a>(
+    page: &'a str,
+    children: &'a [Block],
+    blocks: &mut HashMap<&'a str, (&'a str, &'a str)>,
+) {
+    for block in children {
+        if let Some(uid) = &block.uid {
+            blocks.insert(uid, (page, &block.string));
+        }
+        index(page, &block.children, blocks);
+    }
+}
+
+fn document(page: &Page, graph: &str, blocks: &HashMap<&str, (&str, &str)>) -> Document {
+    let day = daily_note(&page.title);
+    let title = day.map_or_else(|| page.title.clone(), |d| d.to_string());
+    let mut body = String::new();
+    render(&page.children, 0, blocks, &mut body);
+    let content = format!("# {title}\n\n{}", body.trim_end())
+        .trim_end()
+        .to_string()
+        + "\n";
+    let source = format!("{SCHEME}/{graph}/{}", page.title);
+    let mut doc = Document::new(
+        DocumentId::derive(&source),
+        title,
+        DocumentKind::Markdown,
+        content,
+        Some(source),
+    );
+    doc.links = links::extract(&doc.content);
+    doc.metadata
+        .insert(SOURCE_HASH_KEY.into(), content_hash(&doc.content));
+    if let Some(created) = page
+        .created
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+    {
+        doc.added = created;
+        doc.metadata.insert("created".into(), created.to_rfc3339());
+    }
+    if let Some(edited) = page.edited.and_then(DateTime::<Utc>::from_timestamp_millis) {
+        doc.metadata.insert("edited".into(), edited.to_rfc3339());
+    }
+    if let Some(day) = day {
+        // Links to the page use Roam's title.
+        doc.aliases.push(page.title.clone());
+        doc.tags.push(JOURNAL_TAG.into());
+        doc.added = day.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc();
+    }
+    // `Key:: value` blocks at the top of a page are its attributes.
+    for block in &page.children {
+        if let Some((key, value)) = block.string.split_once("::") {
+            let key = metadata_key(key);
+            if !key.is_empty() && !value.trim().is_empty() && !key.contains('\n') {
+                doc.metadata.insert(key, value.trim().to_string());
+            }
+        }
+    }
+    doc
+}
+
+/// The day of a daily note, titled like `January 5th, 2024`.
+fn daily_note(title: &str) -> Option<NaiveDate> {
+    let (month, rest) = title.split_once(' ')?;
+    let (day, year) = rest.split_once(", ")?;
+    let day = day.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    NaiveDate::parse_from_str(&format!("{month} {day} {year}"), "%B %d %Y").ok()
+}
+
+fn render(blocks: &[Block], depth: usize, refs: &HashMap<&str, (&str, &str)>, out: &mut String) {
+    for block in blocks {
+        let text = markdown(&block.string, refs);
+        if depth >= HEADING_LEVELS {
+            let indent = "  ".repeat(depth - HEADING_LEVELS);
+            let text = text.replace('\n', &format!("\n{indent}  "));
+            out.push_str(&format!("{indent}- {text}\n"));
+            render(&block.children, depth + 1, refs, out);
+            if depth == HEADING_LEVELS {
+                out.push('\n');
+            }
+        } else if block.children.is_empty() {
+            out.push_str(&format!("{text}\n\n"));
+        } else {
+            let heading = text.replace('\n', " ");
+            out.push_str(&format!("{} {heading}\n\n", "#".repeat(depth + 2)));
+            render(&block.children, depth + 1, refs, out);
+        }
+    }
+}
+
+/// Roam's markup as markdown: block refs and embeds as links to their
+/// page, `#tags` as page links, `__italics__` and TODO boxes.
+fn markdown(text: &str, refs: &HashMap<&str, (&str, &str)>) -> String {
+    let mut out = text
+        .replace("{{[[TODO]]}}", "[ ]")
+        .replace("{{TODO}}", "[ ]")
+        .replace("{{[[DONE]]}}", "[x]")
+        .replace("{{DONE}}", "[x]")
+        .replace("__", "*")
+        .replace("#[[", "[[");
+    for embed in ["{{embed: ", "{{[[embed]]: "] {
+        while let Some(start) = out.find(embed) {
+            let Some(end) = out[start..].find("}}") else {
+                break;
+            };
+            let inner = out[start + embed.len()..start + end].to_string();
+            out.replace_range(start..start + end + 2, &inner);
+        }
+    }
+    let mut result = String::with_capacity(out.len());
+    let mut rest = out.as_str();
+    while let Some(start) = rest.find("((") {
+        let Some(end) = rest[start..].find("))") else {
+            break;
+        };
+        let uid = &rest[start + 2..start + end];
+        result.push_str(&rest[..start]);
+        match refs.get(uid) {
+            Some((page, text)) => {
+                let page = daily_note(page).map_or_else(|| page.to_string(), |d| d.to_string());
+                result.push_str(&format!("[[{page}|{}]]", label(text)));
+            }
+            None => result.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    result.push_str(rest);
+    hashtags(&result)
+}
+
+/// The text of a referenced block without its markup, to label links.
+fn label(text: &str) -> String {
+    let plain = text
+        .replace(['[', ']', '(', ')', '|', '#', '*', '_', '^'], "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    match plain.char_indices().nth(MAX_LABEL) {
+        Some((end, _)) => format!("{}…", plain[..end].trim_end()),
+        None => plain,
+    }
+}
+
+/// `#tag` as `[[tag]]`, which is what it means in Roam.
+fn hashtags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    let mut previous = ' ';
+    while let Some((i, c)) = chars.next() {
+        let starts_tag = c == '#'
+            && previous.is_whitespace()
+            && chars.peek().is_some_and(|(_, n)| n.is_alphanumeric());
+        if starts_tag {
+            let tag: String = text[i + 1..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '/'))
+                .collect();
+            for _ in 0..tag.chars().count() {
+                chars.next();
+            }
+            out.push_str(&format!("[[{tag}]]"));
+            previous = ']';
+            continue;
+        }
+        out.push(c);
+        previous = c;
+    }
+    out
+}
+
+/// Roam's EDN export: a datascript database whose datoms, `[entity
+/// attribute value transaction]`, hold the pages and blocks.
+mod edn {
+    use super::*;
+
+    /// The values EDN has, as far as the export uses them.
+    #[derive(Debug, Clone)]
+    enum Value {
+        Int(i64),
+        Str(String),
+        Keyword(String),
+        Vector(Vec<Value>),
+        Map(Vec<(Value, Value)>),
+        /// `nil`, booleans, floats and symbols.
+        Other,
+    }
+
+    pub(super) fn pages(text: &str) -> Result<Vec<Page>> {
+        let mut reader = Reader { text, at: 0 };
+        let db = reader.value()?;
+        let Value::Map(db) = db else {
+            bail!("expected a datascript database");
+        };
+        let datoms = db
+            .into_iter()
+            .find(|(k, _)| matches!(k, Value::Keyword(k) if k == "datoms"))
+            .map(|(_, v)| v);
+        let Some(Value::Vector(datoms)) = datoms else {
+            bail!("the database has no datoms");
+        };
+
+        let mut entities: BTreeMap<i64, Vec<(String, Value)>> = BTreeMap::new();
+        for datom in datoms {
+            let Value::Vector(datom) = datom else {
+                continue;
+            };
+            if let [Value::Int(e), Value::Keyword(a), v, ..] = datom.as_slice() {
+                entities.entry(*e).or_default().push((a.clone(), v.clone()));
+            }
+        }
+        let attribute = |e: i64, name: &str| {
+            entities
+                .get(&e)
+                .and_then(|attrs| attrs.iter().find(|(a, _)| a == name))
+                .map(|(_, v)| v.clone())
+        };
+        let string = |e: i64, name: &str| match attribute(e, name) {
+            Some(Value::Str(s)) => Some(s),
+            _ => None,
+        };
+        let int = |e: i64, name: &str| match attribute(e, name) {
+            Some(Value::Int(n)) => Some(n),
+            _ => None,
+        };
+        fn children(
+            e: i64,
+            entities: &BTreeMap<i64, Vec<(String, Value)>>,
+            string: &dyn Fn(i64, &str) -> Option<String>,
+            int: &dyn Fn(i64, &str) -> Option<i64>,
+        ) -> Vec<Block> {
+            let mut ids: Vec<i64> = entities
+                .get(&e)
+                .into_iter()
+                .flatten()
+                .filter(|(a, _)| a == "block/children")
+                .filter_map(|(_, v)| match v {
+                    Value::Int(child) => Some(*child),
+                    _ => None,
+                })
+                .collect();
+            ids.sort_by_key(|child| int(*child, "block/order").unwrap_or(0));
+            ids.into_iter()
+                .map(|child| Block {
+                    string: string(child, "block/string").unwrap_or_default(),
+                    uid: string(child, "block/uid"),
+                    children: children(child, entities, string, int),
+                })
+                .collect()
+        }
+        Ok(entities
+            .keys()
+            .filter_map(|&e| {
+                let title = string(e, "node/title")?;
+                Some(Page {
+                    title,
+                    children: children(e, &entities, &string, &int),
+                    created: int(e, "create/time"),
+                    edited: int(e, "edit/time"),
+                })
+            })
+            .collect())
+    }
+
+    struct Reader<'a> {
+        text: &'a str,
+        at: usize,
+    }
+
+    impl Reader<'_> {
+        fn peek(&self) -> Option<char> {
+            self.text[self.at..].chars().next()
+        }
+
+        fn bump(&mut self) -> Option<char> {
+            let c = self.peek()?;
+            self.at += c.len_utf8();
+            Some(c)
+        }
+
+        /// Skips whitespace, commas and `;` comments.
+        fn skip(&mut self) {
+            while let Some(c) = self.peek() {
+                if c.is_whitespace() || c == ',' {
+                    self.bump();
+                } else if c == ';' {
+                    while self.bump().is_some_and(|c| c != '\n') {}
+                } else {
+                    break;
+                }
+            }
+        }
+
+        fn token(&mut self) -> &str {
+            let start = self.at;
+            while self
+                .peek()
+                .is_some_and(|c| !c.is_whitespace() && !"()[]{},;\"".contains(c))
+            {
+                self.bump();
+            }
+            &self.text[start..self.at]
+        }
+
+        fn value(&mut self) -> Result<Value> {
+            self.skip();
+            match self.peek() {
+                None => bail!("the file ended early"),
+                Some('"') => {
+                    self.bump();
+                    let mut s = String::new();
+                    loop {
+                        match self.bump() {
+                            Some('"') => break,
+                            Some('\\') => match self.bump() {
+                                Some('n') => s.push('\n'),
+                                Some('t') => s.push('\t'),
+                                Some('r') => s.push('\r'),
+                                Some(c) => s.push(c),
+                                None => bail!("unterminated string"),
+                            },
+                            Some(c) => s.push(c),
+                            None => bail!("unterminated string"),
+                        }
+                    }
+                    Ok(Value::Str(s))
+                }
+                Some('[') | Some('(') => {
+                    let close = if self.bump() == Some('[') { ']' } else { ')' };
+                    Ok(Value::Vector(self.items(close)?))
+                }
+                Some('{') => {
+                    self.bump();
+                    let items = self.items('}')?;
+                    let mut pairs = Vec::new();
+                    let mut items = items.into_iter();
+                    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                        pairs.push((k, v));
+                    }
+                    Ok(Value::Map(pairs))
+                }
+                Some('#') => {
+                    self.bump();
+                    match self.peek() {
+                        Some('{') => {
+                            self.bump();
+                            Ok(Value::Vector(self.items('}')?))
+                        }
+                        Some('_') => {
+                            self.bump();
+                            self.value()?;
+                            self.value()
+                        }
+                        // A tagged value such as `#datascript/DB {...}`
+                        // is read as the value.
+                        _ => {
+                            self.token();
+                            self.value()
+                        }
+                    }
+                }
+                Some('\\') => {
+                    self.bump();
+                    let token = self.token().to_string();
+                    Ok(Value::Str(token))
+                }
+                Some(':') => {
+                    self.bump();
+                    Ok(Value::Keyword(self.token().to_string()))
+                }
+                Some(_) => {
+                    let token = self.token();
+                    if token.is_empty() {
+                        bail!("unexpected {:?}", self.peek().unwrap_or_default());
+                    }
+                    Ok(match token.trim_end_matches('N').parse::<i64>() {
+                        Ok(n) => Value::Int(n),
+                        Err(_) => Value::Other,
+                    })
+                }
+            }
+        }
+
+        fn items(&mut self, close: char) -> Result<Vec<Value>> {
+            let mut items = Vec::new();
+            loop {
+                self.skip();
+                if self.peek() == Some(close) {
+                    self.bump();
+                    return Ok(items);
+                }
+                items.push(self.value()?);
+            }
+        }
+    }
+}
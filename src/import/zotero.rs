@@ -0,0 +1,399 @@
+//! A Zotero library, read from its `zotero.sqlite` database or from a
+//! Better BibTeX JSON export. Each reference becomes a document holding
+//! its abstract, notes and the text of its PDFs, as Zotero indexed it or
+//! else read from the files. The files themselves are stored as
+//! attachments.
+//!
+//! Citation keys become aliases, so `ozy show @smith2020` finds the
+//! reference. They are read from Zotero's own `citationKey` field, a
+//! `Citation Key:` line in `extra`, or Better BibTeX's database.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde_json::Value;
+
+use super::metadata_key;
+use crate::error::OzymandiasError;
+use crate::fingerprint::content_hash;
+use crate::parser::{self, strip_tags};
+use crate::storage::BlobStore;
+use crate::tags;
+use crate::types::{Attachment, Document, DocumentId, DocumentKind, SOURCE_HASH_KEY};
+
+/// Prefix of the sources of imported references.
+const SCHEME: &str = "zotero:";
+/// Metadata key of a reference's citation key.
+pub const CITATION_KEY: &str = "citation_key";
+/// Fields that go into the content or are read for something else.
+const SKIPPED_FIELDS: &[&str] = &["title", "abstractNote", "extra", "citationKey"];
+/// Zotero's item types that are not references.
+const NOT_REFERENCES: &[&str] = &["attachment", "note", "annotation"];
+
+/// A reference as read from either kind of library.
+#[derive(Debug, Default)]
+struct Reference {
+    /// Zotero's key for the item, unique in its library.
+    key: String,
+    kind: String,
+    title: String,
+    abstract_note: Option<String>,
+    creators: Vec<String>,
+    fields: Vec<(String, String)>,
+    citation_key: Option<String>,
+    tags: Vec<String>,
+    /// Child notes, as HTML.
+    notes: Vec<String>,
+    files: Vec<File>,
+}
+
+#[derive(Debug)]
+struct File {
+    path: PathBuf,
+    mime: Option<String>,
+    /// Text Zotero extracted from the file for its full-text search.
+    text: Option<String>,
+}
+
+fn is_pdf(file: &File) -> bool {
+    file.mime.as_deref() == Some("application/pdf")
+        || file
+            .path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("pdf"))
+}
+
+/// The references in `path`: a `.json` Better BibTeX export or Zotero's
+/// `zotero.sqlite`. Attached files that can be found are stored in
+/// `blobs`.
+pub fn read(path: &Path, blobs: &BlobStore) -> Result<Vec<Document>> {
+    let json = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    let references = match json {
+        true => read_json(path),
+        false => read_sqlite(path),
+    }
+    .with_context(|| {
+        OzymandiasError::ParseFailed(format!(
+            "cannot read {} as a Zotero library",
+            path.display()
+        ))
+    })?;
+    references.into_iter().map(|r| document(r, blobs)).collect()
+}
+
+/// Prefix of the sources of all imported references, as there is one
+/// library.
+pub fn scope() -> String {
+    format!("{SCHEME}/")
+}
+
+fn document(reference: Reference, blobs: &BlobStore) -> Result<Document> {
+    let mut content = format!("# {}\n\n", reference.title);
+    if !reference.creators.is_empty() {
+        content.push_str(&format!("{}\n\n", reference.creators.join("; ")));
+    }
+    if let Some(abstract_note) = &reference.abstract_note {
+        content.push_str(&format!("## Abstract\n\n{}\n\n", abstract_note.trim()));
+    }
+    if !reference.notes.is_empty() {
+        content.push_str("## Notes\n\n");
+        for note in &reference.notes {
+            content.push_str(&format!("{}\n\n", strip_tags(note)));
+        }
+    }
+    let mut attachments = Vec::new();
+    let mut texts = Vec::new();
+    for file in &reference.files {
+        let name = file
+            .path
+            .file_name()
+            .map_or_else(|| "attachment".into(), |n| n.to_string_lossy().into_owned());
+        match std::fs::read(&file.path) {
+            Ok(bytes) => {
+                if file.text.is_none() && is_pdf(file) {
+                    match parser::pdf_text(&bytes) {
+                        Ok(text) => texts.push(text),
+                        Err(e) => tracing::warn!("{}: cannot read {name}: {e:#}", reference.title),
+                    }
+                }
+                attachments.push(Attachment {
+                    name,
+                    mime: file.mime.clone(),
+                    blob: blobs.put(&bytes)?,
+                    thumbnail: None,
+                });
+            }
+            Err(e) => tracing::warn!(
+                "{}: cannot read attachment {}: {e}",
+                reference.title,
+                file.path.display()
+            ),
+        }
+        texts.extend(file.text.clone());
+    }
+    if !attachments.is_empty() {
+        content.push_str("## Attachments\n\n");
+        for a in &attachments {
+            content.push_str(&format!("- [{}](blob:{})\n", a.name, a.blob));
+        }
+        content.push('\n');
+    }
+    for text in texts.iter().filter(|t| !t.trim().is_empty()) {
+        content.push_str(&format!("## Full text\n\n{}\n\n", text.trim()));
+    }
+
+    let source = format!("{SCHEME}/{}", reference.key);
+    let mut doc = Document::new(
+        DocumentId::derive(&source),
+        reference.title,
+        DocumentKind::Markdown,
+        content.trim_end().to_string() + "\n",
+        Some(source),
+    );
+    doc.metadata
+        .insert(SOURCE_HASH_KEY.into(), content_hash(&doc.content));
+    doc.metadata.insert("item_type".into(), reference.kind);
+    if !reference.creators.is_empty() {
+        doc.metadata
+            .insert("authors".into(), reference.creators.join("; "));
+    }
+    for (field, value) in reference.fields {
+        if !SKIPPED_FIELDS.contains(&field.as_str()) && !value.is_empty() {
+            doc.metadata.insert(metadata_key(&field), value);
+        }
+    }
+    if let Some(key) = reference.citation_key {
+        doc.aliases.push(key.clone());
+        doc.metadata.insert(CITATION_KEY.into(), key);
+    }
+    for tag in &reference.tags {
+        match tags::normalize(tag) {
+            Ok(tag) if !doc.tags.contains(&tag) => doc.tags.push(tag),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("{}: skipped tag {tag:?}: {e:#}", doc.title),
+        }
+    }
+    doc.tags.sort();
+    doc.attachments = attachments;
+    Ok(doc)
+}
+
+/// The citation key in a `Citation Key: smith2020` line of `extra`.
+fn key_in_extra(extra: &str) -> Option<String> {
+    extra.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        let name = name.trim().to_ascii_lowercase();
+        (name == "citation key" && !value.trim().is_empty()).then(|| value.trim().to_string())
+    })
+}
+
+fn read_sqlite(path: &Path) -> Result<Vec<Reference>> {
+    let db = open_read_only(path)?;
+    let data_dir = path.parent().unwrap_or(Path::new("."));
+    // Better BibTeX keeps its keys in a database of its own.
+    let bbt_path = data_dir.join("better-bibtex.sqlite");
+    let bbt = match bbt_path.exists() {
+        true => Some(open_read_only(&bbt_path)?),
+        false => None,
+    };
+    let not_deleted = "itemID NOT IN (SELECT itemID FROM deletedItems)";
+    let mut items = db.prepare(&format!(
+        "SELECT itemID, key, typeName FROM items JOIN itemTypes USING (itemTypeID)
+         WHERE {not_deleted} ORDER BY itemID"
+    ))?;
+    let mut fields = db.prepare(
+        "SELECT fieldName, value FROM itemData JOIN fieldsCombined USING (fieldID)
+         JOIN itemDataValues USING (valueID) WHERE itemID = ?1",
+    )?;
+    let mut creators = db.prepare(
+        "SELECT firstName, lastName FROM itemCreators JOIN creators USING (creatorID)
+         WHERE itemID = ?1 ORDER BY orderIndex",
+    )?;
+    let mut item_tags =
+        db.prepare("SELECT name FROM itemTags JOIN tags USING (tagID) WHERE itemID = ?1")?;
+    let mut notes = db.prepare(&format!(
+        "SELECT note FROM itemNotes WHERE parentItemID = ?1 AND {not_deleted}"
+    ))?;
+    let mut files = db.prepare(&format!(
+        "SELECT key, path, contentType FROM itemAttachments JOIN items USING (itemID)
+         WHERE parentItemID = ?1 AND path IS NOT NULL AND {not_deleted}"
+    ))?;
+
+    let rows: Vec<(i64, String, String)> = items
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))?
+        .collect::<rusqlite::Result<_>>()?;
+    let mut references = Vec::new();
+    for (id, key, kind) in rows {
+        if NOT_REFERENCES.contains(&kind.as_str()) {
+            continue;
+        }
+        let mut reference = Reference {
+            key: key.clone(),
+            kind,
+            ..Reference::default()
+        };
+        for field in fields.query_map([id], |r| Ok((r.get(0)?, r.get(1)?)))? {
+            let (name, value): (String, String) = field?;
+            match name.as_str() {
+                "title" => reference.title = value,
+                "abstractNote" => reference.abstract_note = Some(value),
+                "citationKey" => reference.citation_key = Some(value),
+                "extra" if reference.citation_key.is_none() => {
+                    reference.citation_key = key_in_extra(&value)
+                }
+                _ => reference.fields.push((name, value)),
+            }
+        }
+        for creator in creators.query_map([id], |r| {
+            Ok((r.get::<_, Option<String>>(0)?, r.get::<_, String>(1)?))
+        })? {
+            reference.creators.push(match creator? {
+                (Some(first), last) if !first.is_empty() => format!("{last}, {first}"),
+                (_, last) => last,
+            });
+        }
+        reference.tags = item_tags
+            .query_map([id], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        reference.notes = notes
+            .query_map([id], |r| r.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for file in files.query_map([id], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, Option<String>>(2)?,
+            ))
+        })? {
+            let (attachment_key, stored, mime) = file?;
+            // Imported files live in `storage/<key>/`; linked files may be
+            // anywhere, but only absolute paths can be found.
+            let storage = data_dir.join("storage").join(&attachment_key);
+            let path = match stored.strip_prefix("storage:") {
+                Some(name) => storage.join(name),
+                None if Path::new(&stored).is_absolute() => PathBuf::from(&stored),
+                None => continue,
+            };
+            let text = std::fs::read_to_string(storage.join(".zotero-ft-cache")).ok();
+            reference.files.push(File { path, mime, text });
+        }
+        if reference.citation_key.is_none() {
+            if let Some(bbt) = &bbt {
+                reference.citation_key = bbt
+                    .query_row(
+                        "SELECT citationKey FROM citationkey WHERE itemKey = ?1",
+                        [&key],
+                        |r| r.get(0),
+                    )
+                    .optional()
+                    .context("cannot read Better BibTeX's citation keys")?;
+            }
+        }
+        references.push(reference);
+    }
+    Ok(references)
+}
+
+/// Opens a database without locking it, so Zotero may keep running.
+fn open_read_only(path: &Path) -> Result<Connection> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("cannot access {}", path.display()))?;
+    let escaped = path
+        .display()
+        .to_string()
+        .replace('%', "%25")
+        .replace('?', "%3f")
+        .replace('#', "%23");
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI;
+    Connection::open_with_flags(format!("file:{escaped}?immutable=1"), flags)
+        .with_context(|| format!("cannot open {}", path.display()))
+}
+
+/// A Better BibTeX JSON export: `{"items": [...]}` with Zotero's fields
+/// on each item.
+fn read_json(path: &Path) -> Result<Vec<Reference>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let export: Value = serde_json::from_str(&text)?;
+    let items = export["items"]
+        .as_array()
+        .context("the export has no `items` list")?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut references = Vec::new();
+    for item in items {
+        let kind = item["itemType"].as_str().unwrap_or_default().to_string();
+        if NOT_REFERENCES.contains(&kind.as_str()) {
+            continue;
+        }
+        let Some(key) = item["itemKey"].as_str().or(item["key"].as_str()) else {
+            continue;
+        };
+        let mut reference = Reference {
+            key: key.to_string(),
+            kind,
+            title: item["title"].as_str().unwrap_or_default().to_string(),
+            abstract_note: item["abstractNote"].as_str().map(str::to_string),
+            citation_key: item["citationKey"].as_str().map(str::to_string),
+            ..Reference::default()
+        };
+        if reference.citation_key.is_none() {
+            reference.citation_key = item["extra"].as_str().and_then(key_in_extra);
+        }
+        let object = item.as_object().into_iter().flatten();
+        for (name, value) in object {
+            if let Some(value) = value.as_str().filter(|_| is_field(name)) {
+                reference.fields.push((name.clone(), value.to_string()));
+            }
+        }
+        for creator in item["creators"].as_array().into_iter().flatten() {
+            let name = match (creator["lastName"].as_str(), creator["firstName"].as_str()) {
+                (Some(last), Some(first)) if !first.is_empty() => format!("{last}, {first}"),
+                (Some(last), _) => last.to_string(),
+                _ => creator["name"].as_str().unwrap_or_default().to_string(),
+            };
+            reference.creators.push(name);
+        }
+        for tag in item["tags"].as_array().into_iter().flatten() {
+            let tag = tag["tag"].as_str().or(tag.as_str());
+            reference.tags.extend(tag.map(str::to_string));
+        }
+        for note in item["notes"].as_array().into_iter().flatten() {
+            let note = note["note"].as_str().or(note.as_str());
+            reference.notes.extend(note.map(str::to_string));
+        }
+        for file in item["attachments"].as_array().into_iter().flatten() {
+            let Some(stored) = file["path"].as_str().or(file["localPath"].as_str()) else {
+                continue;
+            };
+            reference.files.push(File {
+                path: base.join(stored),
+                mime: file["contentType"].as_str().map(str::to_string),
+                text: None,
+            });
+        }
+        references.push(reference);
+    }
+    Ok(references)
+}
+
+/// Whether a key of an exported item is a bibliographic field rather than
+/// bookkeeping of the export.
+fn is_field(name: &str) -> bool {
+    const BOOKKEEPING: &[&str] = &[
+        "itemType",
+        "itemKey",
+        "key",
+        "itemID",
+        "libraryID",
+        "uri",
+        "relations",
+        "version",
+        "select",
+        "collections",
+    ];
+    !BOOKKEEPING.contains(&name) && !SKIPPED_FIELDS.contains(&name)
+}
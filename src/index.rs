@@ -0,0 +1,541 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use rust_stemmers::{Algorithm, Stemmer};
+
+use crate::storage::{read_json_or_default, write_json};
+use crate::transform::language;
+use crate::types::{Document, DocumentId};
+
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+/// Title terms count this many times towards a document's term frequencies.
+const TITLE_BOOST: u32 = 3;
+/// Keywords of a document count this many extra times, so that documents
+/// about a word outrank ones that merely mention it.
+const KEYWORD_BOOST: u32 = 2;
+
+/// Splits text into lowercase alphanumeric terms, composed to NFC so that
+/// accented letters match however they were encoded.
+pub fn tokenize(text: &str) -> Vec<String> {
+    let text: Cow<str> = if is_nfc_quick(text.chars()) == IsNormalized::Yes {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.nfc().collect())
+    };
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Splits text into index terms the way suits its language. Chinese and
+/// Japanese do not separate words with spaces, so runs of Han and kana
+/// become overlapping character bigrams; everything else is tokenized.
+pub fn analyze(text: &str, language: Option<&str>) -> Vec<String> {
+    let terms = tokenize(text);
+    if !language.is_some_and(language::is_unsegmented) {
+        return terms;
+    }
+    let mut out = Vec::with_capacity(terms.len());
+    for term in terms {
+        let mut run: Vec<char> = Vec::new();
+        let mut rest = String::new();
+        for c in term.chars().chain(std::iter::once(' ')) {
+            if language::is_han(c) || language::is_kana(c) {
+                if !rest.is_empty() {
+                    out.push(std::mem::take(&mut rest));
+                }
+                run.push(c);
+                continue;
+            }
+            match run.len() {
+                0 => {}
+                1 => out.push(run[0].to_string()),
+                _ => out.extend(run.windows(2).map(|w| w.iter().collect())),
+            }
+            run.clear();
+            if c != ' ' {
+                rest.push(c);
+            }
+        }
+        if !rest.is_empty() {
+            out.push(rest);
+        }
+    }
+    out
+}
+
+/// The language a document is analyzed in: the detected one if the
+/// pipeline recorded it, else a guess from its content.
+pub fn language_of(doc: &Document) -> Option<&str> {
+    doc.language().or_else(|| language::detect(&doc.content))
+}
+
+/// Settings of the full-text analyzer, the `[analysis]` config section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    /// Reduce words to their Snowball stems, so that "organizing" matches
+    /// "organization". Knowledge bases of source code may prefer exact
+    /// words.
+    pub stemming: bool,
+    /// Language assumed for documents whose language was not detected.
+    pub language: String,
+    /// Leave out stopwords such as "the" and "of". Turning this off keeps
+    /// every word, for phrase searches that must match exactly.
+    pub remove_stopwords: bool,
+    /// Stopwords per language, replacing the built-in list of that
+    /// language.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub stopwords: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig {
+            stemming: true,
+            language: "en".to_string(),
+            remove_stopwords: true,
+            stopwords: BTreeMap::new(),
+        }
+    }
+}
+
+/// Turns text into index terms: [`analyze`], then stemming in the
+/// language of the text.
+#[derive(Debug, Clone, Default)]
+pub struct Analyzer {
+    config: AnalysisConfig,
+}
+
+impl Analyzer {
+    pub fn new(config: AnalysisConfig) -> Self {
+        Analyzer { config }
+    }
+
+    pub fn config(&self) -> &AnalysisConfig {
+        &self.config
+    }
+
+    pub fn terms(&self, text: &str, language: Option<&str>) -> Vec<String> {
+        let stemmer = self.stemmer(language);
+        analyze(text, language)
+            .into_iter()
+            .filter(|t| !self.is_stopword(t, language))
+            .map(|t| match &stemmer {
+                Some(s) => s.stem(&t).into_owned(),
+                None => t,
+            })
+            .collect()
+    }
+
+    /// The index form of a single analyzed term, or `None` for a stopword.
+    pub fn normalize(&self, term: &str, language: Option<&str>) -> Option<String> {
+        if self.is_stopword(term, language) {
+            return None;
+        }
+        Some(match self.stemmer(language) {
+            Some(s) => s.stem(term).into_owned(),
+            None => term.to_string(),
+        })
+    }
+
+    /// Whether `term`, as produced by [`analyze`], is left out of the index.
+    pub fn is_stopword(&self, term: &str, language: Option<&str>) -> bool {
+        if !self.config.remove_stopwords {
+            return false;
+        }
+        let language = language.unwrap_or(&self.config.language);
+        match self.config.stopwords.get(language) {
+            Some(custom) => custom.iter().any(|w| w == term),
+            None => language::stopwords(language).contains(&term),
+        }
+    }
+
+    fn stemmer(&self, language: Option<&str>) -> Option<Stemmer> {
+        if !self.config.stemming {
+            return None;
+        }
+        stemming_algorithm(language.unwrap_or(&self.config.language)).map(Stemmer::create)
+    }
+}
+
+/// The Snowball stemmer for an ISO 639-1 language code.
+fn stemming_algorithm(language: &str) -> Option<Algorithm> {
+    Some(match language {
+        "ar" => Algorithm::Arabic,
+        "da" => Algorithm::Danish,
+        "de" => Algorithm::German,
+        "el" => Algorithm::Greek,
+        "en" => Algorithm::English,
+        "es" => Algorithm::Spanish,
+        "fi" => Algorithm::Finnish,
+        "fr" => Algorithm::French,
+        "hu" => Algorithm::Hungarian,
+        "it" => Algorithm::Italian,
+        "nl" => Algorithm::Dutch,
+        "no" | "nb" | "nn" => Algorithm::Norwegian,
+        "pt" => Algorithm::Portuguese,
+        "ro" => Algorithm::Romanian,
+        "ru" => Algorithm::Russian,
+        "sv" => Algorithm::Swedish,
+        "ta" => Algorithm::Tamil,
+        "tr" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct IndexedDoc {
+    len: u32,
+    terms: HashMap<String, u32>,
+    /// Language the terms were analyzed in, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    language: Option<String>,
+}
+
+/// A persisted full-text index ranked with BM25.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Index {
+    /// Analyzer settings the terms were produced with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    analysis: Option<AnalysisConfig>,
+    docs: BTreeMap<DocumentId, IndexedDoc>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    analyzer: Analyzer,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub id: DocumentId,
+    pub score: f64,
+}
+
+impl Index {
+    pub fn open(root: &Path, analysis: &AnalysisConfig) -> Result<Self> {
+        let path = root.join("index.json");
+        let mut index: Index = read_json_or_default(&path)?;
+        index.path = path;
+        index.analyzer = Analyzer::new(analysis.clone());
+        Ok(index)
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    /// Whether the terms were produced with other analyzer settings than
+    /// the configured ones, so the index must be rebuilt.
+    pub fn is_stale(&self) -> bool {
+        !self.docs.is_empty() && self.analysis.as_ref() != Some(self.analyzer.config())
+    }
+
+    /// Re-indexes `docs` from scratch with the configured analyzer.
+    pub fn rebuild(&mut self, docs: &[Document]) {
+        self.docs.clear();
+        for doc in docs {
+            self.insert(doc);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    pub fn insert(&mut self, doc: &Document) {
+        if self.docs.is_empty() {
+            self.analysis = Some(self.analyzer.config().clone());
+        }
+        let language = language_of(doc);
+        let mut indexed = IndexedDoc {
+            language: language.map(str::to_string),
+            ..IndexedDoc::default()
+        };
+        // Aliases weigh as much as the title, so that a renamed document
+        // is found by its old name.
+        for name in std::iter::once(&doc.title).chain(&doc.aliases) {
+            for term in self.analyzer.terms(name, language) {
+                *indexed.terms.entry(term).or_default() += TITLE_BOOST;
+                indexed.len += TITLE_BOOST;
+            }
+        }
+        for term in self.analyzer.terms(&doc.content, language) {
+            *indexed.terms.entry(term).or_default() += 1;
+            indexed.len += 1;
+        }
+        for keyword in &doc.keywords {
+            if let Some(term) = self.analyzer.normalize(keyword, language) {
+                *indexed.terms.entry(term).or_default() += KEYWORD_BOOST;
+                indexed.len += KEYWORD_BOOST;
+            }
+        }
+        self.docs.insert(doc.id.clone(), indexed);
+    }
+
+    pub fn remove(&mut self, id: &DocumentId) {
+        self.docs.remove(id);
+    }
+
+    pub fn contains(&self, id: &DocumentId) -> bool {
+        self.docs.contains_key(id)
+    }
+
+    /// Number of distinct terms.
+    pub fn term_count(&self) -> usize {
+        let terms: HashSet<&str> = self
+            .docs
+            .values()
+            .flat_map(|d| d.terms.keys().map(String::as_str))
+            .collect();
+        terms.len()
+    }
+
+    /// Ids of the indexed documents.
+    pub fn ids(&self) -> impl Iterator<Item = &DocumentId> {
+        self.docs.keys()
+    }
+
+    /// Number of indexed documents.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Number of indexed documents containing `term`, an index term as
+    /// produced by the analyzer.
+    pub fn document_frequency(&self, term: &str) -> usize {
+        self.docs
+            .values()
+            .filter(|d| d.terms.contains_key(term))
+            .count()
+    }
+
+    /// Scores every document containing at least one query term, best first.
+    ///
+    /// Each query word is stemmed in the language of each document it is
+    /// compared with, since queries are usually too short to tell their
+    /// language.
+    pub fn search(&self, query: &str) -> Vec<Hit> {
+        let words = analyze(query, language::detect(query));
+        if words.is_empty() || self.docs.is_empty() {
+            return Vec::new();
+        }
+        let n = self.docs.len() as f64;
+        let avg_len = self.docs.values().map(|d| d.len as f64).sum::<f64>() / n;
+
+        let mut scores: HashMap<&DocumentId, f64> = HashMap::new();
+        let mut df: HashMap<String, f64> = HashMap::new();
+        for word in &words {
+            for (id, doc) in &self.docs {
+                let Some(term) = self.analyzer.normalize(word, doc.language.as_deref()) else {
+                    continue;
+                };
+                let Some(&tf) = doc.terms.get(&term) else {
+                    continue;
+                };
+                let df = *df
+                    .entry(term)
+                    .or_insert_with_key(|term| self.document_frequency(term) as f64);
+                let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+                let tf = tf as f64;
+                let norm = K1 * (1.0 - B + B * doc.len as f64 / avg_len.max(1.0));
+                *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let mut hits: Vec<Hit> = scores
+            .into_iter()
+            .map(|(id, score)| Hit {
+                id: id.clone(),
+                score,
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits
+    }
+}
+
+/// Picks a short excerpt of `content` around the first query term it contains.
+pub fn snippet(content: &str, query: &str, width: usize) -> String {
+    let terms = analyze(query, language::detect(query));
+    let lower = content.to_lowercase();
+    let start = if lower.len() == content.len() {
+        terms
+            .iter()
+            .filter_map(|t| lower.find(t.as_str()))
+            .min()
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    let mut start = start.saturating_sub(width / 4);
+    while !content.is_char_boundary(start) {
+        start -= 1;
+    }
+    let text: String = content[start..]
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let mut out: String = text.chars().take(width).collect();
+    if start > 0 {
+        out.insert(0, '…');
+    }
+    if text.chars().count() > width {
+        out.push('…');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DocumentKind;
+
+    fn doc(key: &str, title: &str, content: &str) -> Document {
+        let mut doc = Document::new(
+            DocumentId::derive(key),
+            title.to_string(),
+            DocumentKind::Markdown,
+            content.to_string(),
+            None,
+        );
+        doc.metadata
+            .insert(language::KEY.to_string(), "en".to_string());
+        doc
+    }
+
+    fn index(docs: &[Document]) -> Index {
+        let mut index = Index::default();
+        index.rebuild(docs);
+        index
+    }
+
+    fn ranked(index: &Index, query: &str) -> Vec<DocumentId> {
+        index.search(query).into_iter().map(|h| h.id).collect()
+    }
+
+    #[test]
+    fn search_ranks_rarer_terms_higher() {
+        let docs = [
+            doc("a", "One", "apple banana"),
+            doc("b", "Two", "apple cherry"),
+            doc("c", "Three", "apple date"),
+        ];
+        let index = index(&docs);
+        assert_eq!(ranked(&index, "apple cherry")[0], docs[1].id);
+        assert_eq!(ranked(&index, "apple").len(), 3);
+        assert!(ranked(&index, "elderberry").is_empty());
+    }
+
+    #[test]
+    fn search_ranks_repeated_terms_higher() {
+        let docs = [
+            doc("a", "One", "tea and biscuits and more"),
+            doc("b", "Two", "tea tea tea with biscuits"),
+        ];
+        assert_eq!(
+            ranked(&index(&docs), "tea"),
+            vec![docs[1].id.clone(), docs[0].id.clone()]
+        );
+    }
+
+    #[test]
+    fn titles_and_aliases_outweigh_content() {
+        let mut aliased = doc("c", "Three", "nothing relevant here at all");
+        aliased.aliases.push("Volcanoes".to_string());
+        let docs = [
+            doc("a", "Notes", "volcanoes erupt sometimes, or so we hear"),
+            doc("b", "Volcanoes", "they erupt sometimes, or so we hear"),
+            aliased,
+        ];
+        let ranked = ranked(&index(&docs), "volcanoes");
+        assert_eq!(ranked.len(), 3);
+        assert_eq!(ranked[2], docs[0].id);
+    }
+
+    #[test]
+    fn search_matches_other_inflections() {
+        let docs = [doc("a", "One", "She was running late.")];
+        assert_eq!(ranked(&index(&docs), "runs"), vec![docs[0].id.clone()]);
+    }
+
+    #[test]
+    fn stopwords_match_nothing() {
+        let docs = [doc("a", "One", "the cat and the hat")];
+        let index = index(&docs);
+        assert!(index.search("the and").is_empty());
+        assert_eq!(index.document_frequency("the"), 0);
+        assert_eq!(index.document_frequency("cat"), 1);
+    }
+
+    #[test]
+    fn scores_are_stable_across_ties() {
+        let docs = [
+            doc("a", "Same", "same words"),
+            doc("b", "Same", "same words"),
+        ];
+        let hits = index(&docs).search("words");
+        assert_eq!(hits[0].score, hits[1].score);
+        assert!(hits[0].id < hits[1].id);
+    }
+
+    #[test]
+    fn stale_after_analysis_changes() {
+        let docs = [doc("a", "One", "words")];
+        let mut index = index(&docs);
+        assert!(!index.is_stale());
+        index.analyzer = Analyzer::new(AnalysisConfig {
+            stemming: false,
+            ..AnalysisConfig::default()
+        });
+        assert!(index.is_stale());
+        index.rebuild(&docs);
+        assert!(!index.is_stale());
+    }
+
+    #[test]
+    fn unsegmented_text_becomes_bigrams() {
+        assert_eq!(analyze("東京都", Some("ja")), vec!["東京", "京都"]);
+        assert_eq!(analyze("東京 abc", Some("ja")), vec!["東京", "abc"]);
+        assert_eq!(analyze("東京都", Some("en")), vec!["東京都"]);
+    }
+
+    #[test]
+    fn tokenize_composes_accents() {
+        assert_eq!(tokenize("Cafe\u{301}, CAFÉ!"), vec!["café", "café"]);
+    }
+
+    #[test]
+    fn snippet_centers_on_the_first_match() {
+        let content = format!("{} needle {}", "hay ".repeat(50), "straw ".repeat(50));
+        let snippet = snippet(&content, "needle", 40);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("needle"));
+        assert_eq!(snippet.chars().count(), 42);
+    }
+
+    #[test]
+    fn snippet_of_short_content_is_whole() {
+        assert_eq!(snippet("just  a\nline", "line", 40), "just a line");
+        assert_eq!(snippet("no match here", "absent", 40), "no match here");
+    }
+
+    #[test]
+    fn snippet_starts_on_a_char_boundary() {
+        let content = format!("{} needle", "é".repeat(30));
+        assert!(snippet(&content, "needle", 40).contains("needle"));
+        // Lowercasing changes the length, so the match cannot be located.
+        assert!(snippet("İİİ needle", "needle", 4).starts_with("İİİ"));
+    }
+}
@@ -0,0 +1,871 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::attachments;
+use crate::config::{Config, Layers};
+use crate::error::OzymandiasError;
+use crate::graph::Graph;
+use crate::history::{History, Revision};
+use crate::hooks::Hooks;
+use crate::index::Index;
+use crate::links::LinkIndex;
+use crate::ml::classifier::TagClassifier;
+use crate::ml::prompts::Prompts;
+use crate::ml::{provider_from_config, EmbeddingProvider};
+use crate::ontology::Ontology;
+use crate::parallel;
+use crate::plugins::Plugins;
+use crate::progress::Progress;
+use crate::query::Query;
+use crate::relations::{Relation, RelationKind};
+use crate::runtime;
+use crate::storage::{read_json_or_default, write_json, BlobStore, DryRun, FsStorage, Storage};
+use crate::sync::CONFLICT_KEY;
+use crate::tags;
+use crate::tasks::TaskIndex;
+use crate::thumbnails;
+use crate::tombstones::{Tombstone, Tombstones};
+use crate::transform::dedupe::NEAR_DUPLICATE_KEY;
+use crate::trash::Trash;
+use crate::types::{
+    Attachment, Document, DocumentId, ARCHIVE_KEY, FETCH_ERROR_KEY, MODIFIED_KEY, SNAPSHOT_KEY,
+    SOURCE_HASH_KEY,
+};
+use crate::undo::{self, Change, Operation, UndoLog};
+use crate::vectors::VectorIndex;
+
+pub const KB_DIR: &str = ".ozymandias";
+/// File in the knowledge base recording the format of its files.
+pub const FORMAT_FILE: &str = "format.json";
+/// The format this program writes; `ozy migrate` brings knowledge bases
+/// in an earlier one up to it.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Metadata describing where a document's content came from, which a
+/// merged duplicate does not pass on.
+const PROVENANCE_KEYS: [&str; 7] = [
+    SOURCE_HASH_KEY,
+    MODIFIED_KEY,
+    SNAPSHOT_KEY,
+    ARCHIVE_KEY,
+    FETCH_ERROR_KEY,
+    NEAR_DUPLICATE_KEY,
+    CONFLICT_KEY,
+];
+
+static SELECTED: OnceLock<String> = OnceLock::new();
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Selects, for this run, a knowledge base registered by name under
+/// `[knowledge_bases]` in the user's config.
+pub fn select(name: String) {
+    SELECTED.set(name).ok();
+}
+
+/// Makes knowledge bases opened from now on report what they would change
+/// instead of changing it.
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.store(dry_run, Ordering::Relaxed);
+}
+
+pub fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// The directory holding the knowledge base commands work on: the
+/// selected one, or else the nearest directory, from the current one
+/// upwards, with a `.ozymandias/` in it. Without any, it is the current
+/// directory, where `ozy add` creates one.
+pub fn dir() -> Result<PathBuf> {
+    let Some(name) = SELECTED.get() else {
+        let cwd = std::env::current_dir()?;
+        let found = cwd.ancestors().find(|dir| dir.join(KB_DIR).is_dir());
+        return Ok(found.unwrap_or(&cwd).to_path_buf());
+    };
+    let registered = Layers::load(None)?.config()?.knowledge_bases;
+    let Some(path) = registered.get(name) else {
+        let names: Vec<&str> = registered.keys().map(String::as_str).collect();
+        bail!(OzymandiasError::NotFound(format!(
+            "no knowledge base named {name:?} in the config (registered: {})",
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        )));
+    };
+    Ok(expand_home(path))
+}
+
+/// `path` with a leading `~` standing for the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// What [`FORMAT_FILE`] holds.
+#[derive(Default, Serialize, Deserialize)]
+struct FormatMarker {
+    version: u32,
+}
+
+/// An opened knowledge base: document storage plus its search indexes.
+pub struct KnowledgeBase {
+    pub root: PathBuf,
+    pub storage: Box<dyn Storage>,
+    pub blobs: BlobStore,
+    pub index: Index,
+    pub vectors: VectorIndex,
+    pub config: Config,
+    pub tombstones: Tombstones,
+    /// Earlier revisions of documents.
+    pub history: History,
+    /// Removed documents that can still be restored.
+    pub trash: Trash,
+    /// Recent commands that `ozy undo` can reverse.
+    pub undo: UndoLog,
+    pub links: LinkIndex,
+    pub graph: Graph,
+    pub tasks: TaskIndex,
+    pub ontology: Option<Ontology>,
+    embedder: Option<Box<dyn EmbeddingProvider>>,
+    /// The plugins, compiled on first use.
+    plugins: OnceLock<Plugins>,
+    /// The hook scripts, compiled on first use.
+    hooks: OnceLock<Hooks>,
+    /// Set when documents changed, so links, tasks and the graph must be
+    /// rebuilt.
+    derived_dirty: bool,
+    /// The command being recorded by `record_as`, and the documents it
+    /// changed as they were before.
+    recording: Option<(String, BTreeMap<DocumentId, Change>)>,
+}
+
+impl KnowledgeBase {
+    /// Opens the knowledge base in `dir`, creating it on first use.
+    pub fn open_or_init(dir: &Path) -> Result<Self> {
+        let root = dir.join(KB_DIR);
+        let new = !root.is_dir();
+        std::fs::create_dir_all(&root)
+            .with_context(|| format!("failed to create {}", root.display()))?;
+        let kb = Self::open_root(root)?;
+        if new {
+            kb.mark_format()?;
+        }
+        Ok(kb)
+    }
+
+    /// Opens an existing knowledge base in `dir`.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let root = dir.join(KB_DIR);
+        if !root.is_dir() {
+            bail!(OzymandiasError::NotFound(format!(
+                "no knowledge base found in {} or above (run `ozy add` to create one)",
+                dir.display()
+            )));
+        }
+        Self::open_root(root)
+    }
+
+    fn open_root(root: PathBuf) -> Result<Self> {
+        tracing::debug!("opening the knowledge base in {}", root.display());
+        let config = Config::load(&root)?;
+        let storage: Box<dyn Storage> = match dry_run() {
+            true => Box::new(DryRun::new(FsStorage::open(&root)?)),
+            false => Box::new(FsStorage::open(&root)?),
+        };
+        let mut index = Index::open(&root, &config.analysis)?;
+        if index.is_stale() {
+            tracing::info!("rebuilding the full-text index for changed [analysis] settings");
+            index.rebuild(&storage.all()?);
+            if !dry_run() {
+                index.save()?;
+            }
+        }
+        let mut blobs = BlobStore::open(&root)?;
+        blobs.dry_run = dry_run();
+        Ok(KnowledgeBase {
+            storage,
+            blobs,
+            index,
+            vectors: VectorIndex::open(&root)?,
+            config,
+            tombstones: Tombstones::open(&root)?,
+            history: History::open(&root),
+            trash: Trash::open(&root),
+            undo: UndoLog::open(&root)?,
+            links: LinkIndex::open(&root)?,
+            graph: Graph::open(&root)?,
+            tasks: TaskIndex::open(&root)?,
+            ontology: Ontology::load(&root)?,
+            embedder: None,
+            plugins: OnceLock::new(),
+            hooks: OnceLock::new(),
+            derived_dirty: false,
+            recording: None,
+            root,
+        })
+    }
+
+    /// The format the knowledge base's files are in; 0 for knowledge bases
+    /// made before it was recorded.
+    pub fn format_version(&self) -> Result<u32> {
+        let marker: FormatMarker = read_json_or_default(&self.root.join(FORMAT_FILE))?;
+        Ok(marker.version)
+    }
+
+    /// Records that the knowledge base's files are in the current format.
+    pub fn mark_format(&self) -> Result<()> {
+        if dry_run() {
+            return Ok(());
+        }
+        let marker = FormatMarker {
+            version: FORMAT_VERSION,
+        };
+        write_json(&self.root.join(FORMAT_FILE), &marker)
+    }
+
+    /// A directory from the config, such as `journal.dir`, taken relative
+    /// to the directory holding the knowledge base.
+    pub fn workspace_path(&self, dir: &Path) -> PathBuf {
+        self.root.parent().unwrap_or(Path::new(".")).join(dir)
+    }
+
+    /// Records the changes made until `commit` as an operation that
+    /// `ozy undo` can reverse, described by `command`.
+    pub fn record_as(&mut self, command: String) {
+        self.recording = Some((command, BTreeMap::new()));
+    }
+
+    /// Notes a document as it is before a change, if one is recorded.
+    /// Every write to storage goes through here first.
+    pub fn touch(&mut self, id: &DocumentId) -> Result<()> {
+        let Some((_, changes)) = &mut self.recording else {
+            return Ok(());
+        };
+        if !changes.contains_key(id) {
+            let change = Change {
+                id: id.clone(),
+                before: self.storage.get(id)?,
+                tombstone: self.tombstones.get(id).cloned(),
+                after: None,
+            };
+            changes.insert(id.clone(), change);
+        }
+        Ok(())
+    }
+
+    /// Expands a unique id prefix, as typed by users, to a full id. An
+    /// argument such as `@smith2020` names a document by its title or an
+    /// alias, such as a citation key.
+    pub fn resolve(&self, prefix: &str) -> Result<DocumentId> {
+        if let Some(name) = prefix.strip_prefix('@') {
+            let candidates: Vec<DocumentId> = self
+                .storage
+                .all()?
+                .into_iter()
+                .filter(|d| {
+                    std::iter::once(&d.title)
+                        .chain(&d.aliases)
+                        .any(|n| n.eq_ignore_ascii_case(name))
+                })
+                .map(|d| d.id)
+                .collect();
+            return match candidates.as_slice() {
+                [id] => Ok(id.clone()),
+                [] => bail!(OzymandiasError::NotFound(format!(
+                    "no document is called {name}"
+                ))),
+                _ => bail!(
+                    "{name} is ambiguous ({} documents are called that)",
+                    candidates.len()
+                ),
+            };
+        }
+        let candidates: Vec<DocumentId> = self
+            .storage
+            .ids()?
+            .into_iter()
+            .filter(|id| id.0.starts_with(prefix))
+            .collect();
+        if candidates.is_empty() {
+            // Duplicates merged away resolve to the document they went into.
+            let merged: Vec<DocumentId> = self
+                .storage
+                .all()?
+                .into_iter()
+                .filter(|d| d.former_ids.iter().any(|f| f.0.starts_with(prefix)))
+                .map(|d| d.id)
+                .collect();
+            if let [id] = merged.as_slice() {
+                return Ok(id.clone());
+            }
+        }
+        match candidates.as_slice() {
+            [id] => Ok(id.clone()),
+            [] => bail!(OzymandiasError::NotFound(format!(
+                "no document with id {prefix}"
+            ))),
+            _ => bail!(
+                "id prefix {prefix} is ambiguous ({} documents match)",
+                candidates.len()
+            ),
+        }
+    }
+
+    /// Parses a query and widens its tag filters to narrower concepts of
+    /// the ontology.
+    pub fn parse_query(&self, input: &str) -> Result<Query> {
+        let mut query = Query::parse(input)?;
+        query.analyzer = self.index.analyzer().clone();
+        if let Some(ontology) = &self.ontology {
+            query.infer(ontology);
+        }
+        Ok(query)
+    }
+
+    /// The prompt templates sent to language models.
+    pub fn prompts(&self) -> Prompts {
+        Prompts::open(&self.root)
+    }
+
+    pub fn get(&self, id: &DocumentId) -> Result<Document> {
+        self.storage
+            .get(id)?
+            .with_context(|| format!("no document with id {id}"))
+    }
+
+    /// The configured embedding provider, loaded on first use.
+    pub fn embedder(&mut self) -> Result<&dyn EmbeddingProvider> {
+        if self.embedder.is_none() {
+            self.embedder = Some(provider_from_config(
+                &self.config.embedding,
+                &self.config.llm,
+            )?);
+        }
+        Ok(self.embedder.as_deref().unwrap())
+    }
+
+    /// The plugins in `plugins/` and the parser commands of the config,
+    /// loaded on first use.
+    pub fn plugins(&self) -> Result<&Plugins> {
+        if let Some(plugins) = self.plugins.get() {
+            return Ok(plugins);
+        }
+        let plugins = Plugins::load(&self.root, &self.config.parsers)?;
+        Ok(self.plugins.get_or_init(|| plugins))
+    }
+
+    /// The hook scripts in `hooks/`, loaded on first use.
+    pub fn hooks(&self) -> Result<&Hooks> {
+        if let Some(hooks) = self.hooks.get() {
+            return Ok(hooks);
+        }
+        let hooks = Hooks::load(&self.root)?;
+        Ok(self.hooks.get_or_init(|| hooks))
+    }
+
+    /// Stores a document, indexes it and records its embeddings.
+    pub fn insert(&mut self, doc: &Document) -> Result<()> {
+        let vectors = runtime::block_on(embeddings(self.embedder()?, doc))?;
+        self.insert_embedded(doc, vectors)
+    }
+
+    /// Embeddings of documents for `insert_embedded`, computed for up to
+    /// `jobs` documents at once.
+    pub async fn embed_all(
+        &mut self,
+        docs: &[&Document],
+        jobs: usize,
+    ) -> Result<Vec<Result<Vec<Vec<f32>>>>> {
+        let embedder = self.embedder()?;
+        Ok(parallel::map_async(docs, jobs, |doc| embeddings(embedder, doc)).await)
+    }
+
+    /// Stores a document like `insert`, with embeddings from `embed_all`.
+    /// The title and content it replaces are kept as a revision, and a
+    /// copy in the trash is dropped.
+    pub fn insert_embedded(&mut self, doc: &Document, vectors: Vec<Vec<f32>>) -> Result<()> {
+        self.touch(&doc.id)?;
+        self.trash.remove(&doc.id)?;
+        if let Some(old) = self.storage.get(&doc.id)? {
+            if old.title != doc.title || old.content != doc.content {
+                let saved = self.storage.modified(&doc.id)?.unwrap_or(old.added);
+                self.history.record(
+                    &doc.id,
+                    Revision {
+                        title: old.title,
+                        content: old.content,
+                        saved,
+                    },
+                )?;
+            }
+        }
+        let name = self.embedder()?.name();
+        self.vectors.remove(&doc.id);
+        store_embeddings(&mut self.vectors, &name, doc, vectors)?;
+        self.storage.put(doc)?;
+        self.index.insert(doc);
+        self.derived_dirty = true;
+        Ok(())
+    }
+
+    /// A tag classifier trained on every tagged document with an embedding.
+    pub fn tag_classifier(&self) -> Result<TagClassifier> {
+        let docs = self.storage.all()?;
+        Ok(TagClassifier::train(docs.iter().filter_map(|doc| {
+            let vector = self.vectors.get(&doc.id)?;
+            (!doc.tags.is_empty()).then_some((doc.tags.as_slice(), vector))
+        })))
+    }
+
+    /// Adds and removes tags of a document, returning its new tag list.
+    pub fn retag(
+        &mut self,
+        id: &DocumentId,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<Vec<String>> {
+        let mut doc = self.get(id)?;
+        if tags::apply(&mut doc.tags, add, remove) {
+            self.touch(id)?;
+            self.storage.put(&doc)?;
+            self.derived_dirty = true;
+        }
+        Ok(doc.tags)
+    }
+
+    /// Adds and removes alias titles of a document, returning its new
+    /// aliases. Its own title is never an alias.
+    pub fn realias(
+        &mut self,
+        id: &DocumentId,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<Vec<String>> {
+        let mut doc = self.get(id)?;
+        let before = doc.aliases.clone();
+        doc.aliases
+            .retain(|a| !remove.iter().any(|r| r.eq_ignore_ascii_case(a)));
+        for alias in add {
+            if *alias != doc.title && !doc.aliases.contains(alias) {
+                doc.aliases.push(alias.clone());
+            }
+        }
+        if doc.aliases != before {
+            self.touch(id)?;
+            self.storage.put(&doc)?;
+            self.index.insert(&doc);
+            self.derived_dirty = true;
+        }
+        Ok(doc.aliases)
+    }
+
+    /// Attaches files already in the blob store to a document, returning
+    /// its attachments afterwards.
+    pub fn attach(&mut self, id: &DocumentId, files: Vec<Attachment>) -> Result<Vec<Attachment>> {
+        let mut doc = self.get(id)?;
+        let mut changed = false;
+        for file in files {
+            changed |= attachments::add(&mut doc, file);
+        }
+        if changed {
+            thumbnails::fill(
+                &self.blobs,
+                &mut doc.attachments,
+                self.config.thumbnails.max_dimension,
+            );
+            self.touch(id)?;
+            self.storage.put(&doc)?;
+        }
+        Ok(doc.attachments)
+    }
+
+    /// Records a relation from `id`, replacing an earlier one of the same
+    /// kind to the same target.
+    pub fn relate(&mut self, id: &DocumentId, relation: Relation) -> Result<()> {
+        let mut doc = self.get(id)?;
+        doc.relations
+            .retain(|r| !(r.kind == relation.kind && r.target == relation.target));
+        doc.relations.push(relation);
+        self.touch(id)?;
+        self.storage.put(&doc)?;
+        self.derived_dirty = true;
+        Ok(())
+    }
+
+    /// Removes a relation, returning whether it existed.
+    pub fn unrelate(
+        &mut self,
+        id: &DocumentId,
+        kind: &RelationKind,
+        target: &DocumentId,
+    ) -> Result<bool> {
+        let mut doc = self.get(id)?;
+        let before = doc.relations.len();
+        doc.relations
+            .retain(|r| !(r.kind == *kind && r.target == *target));
+        if doc.relations.len() == before {
+            return Ok(false);
+        }
+        self.touch(id)?;
+        self.storage.put(&doc)?;
+        self.derived_dirty = true;
+        Ok(true)
+    }
+
+    /// Folds `duplicate` into `keep`: its tags, relations, attachments and
+    /// metadata move over where `keep` has none of its own, its title
+    /// becomes an alias and its id a former id, relations pointing at it
+    /// are redirected, and it is removed with a tombstone.
+    pub fn merge(&mut self, keep: &DocumentId, duplicate: &DocumentId) -> Result<()> {
+        if keep == duplicate {
+            bail!("cannot merge {keep} into itself");
+        }
+        let mut doc = self.get(keep)?;
+        let dup = self.get(duplicate)?;
+        tags::apply(&mut doc.tags, &dup.tags, &[]);
+        for alias in std::iter::once(&dup.title).chain(&dup.aliases) {
+            if *alias != doc.title && !doc.aliases.contains(alias) {
+                doc.aliases.push(alias.clone());
+            }
+        }
+        for id in std::iter::once(duplicate).chain(&dup.former_ids) {
+            if !doc.former_ids.contains(id) {
+                doc.former_ids.push(id.clone());
+            }
+        }
+        for attachment in dup.attachments {
+            attachments::add(&mut doc, attachment);
+        }
+        for (key, value) in &dup.metadata {
+            if !PROVENANCE_KEYS.contains(&key.as_str()) && !doc.metadata.contains_key(key) {
+                doc.metadata.insert(key.clone(), value.clone());
+            }
+        }
+        for relation in dup.relations {
+            let exists = doc
+                .relations
+                .iter()
+                .any(|r| r.kind == relation.kind && r.target == relation.target);
+            if relation.target != *keep && !exists {
+                doc.relations.push(relation);
+            }
+        }
+        if doc.metadata.get(NEAR_DUPLICATE_KEY) == Some(&duplicate.0) {
+            doc.metadata.remove(NEAR_DUPLICATE_KEY);
+        }
+        self.touch(keep)?;
+        self.storage.put(&doc)?;
+
+        for mut other in self.storage.all()? {
+            if other.id == *keep || other.id == *duplicate {
+                continue;
+            }
+            let mut changed = false;
+            for relation in &mut other.relations {
+                if relation.target == *duplicate {
+                    relation.target = keep.clone();
+                    changed = true;
+                }
+            }
+            if other.metadata.get(NEAR_DUPLICATE_KEY) == Some(&duplicate.0) {
+                other
+                    .metadata
+                    .insert(NEAR_DUPLICATE_KEY.into(), keep.to_string());
+                changed = true;
+            }
+            if changed {
+                let mut seen = Vec::new();
+                other.relations.retain(|r| {
+                    let key = (r.kind.clone(), r.target.clone());
+                    let first = !seen.contains(&key);
+                    seen.push(key);
+                    first
+                });
+                self.touch(&other.id)?;
+                self.storage.put(&other)?;
+            }
+        }
+        self.remove(duplicate, false)?;
+        Ok(())
+    }
+
+    /// Removes a document from storage and every index. Unless `purge` is
+    /// set, the document goes to the trash and a tombstone keeps later
+    /// imports of the same source from resurrecting it; purging also
+    /// clears an existing tombstone, the document's earlier revisions and
+    /// any copy in the trash.
+    pub fn remove(&mut self, id: &DocumentId, purge: bool) -> Result<Option<Document>> {
+        self.touch(id)?;
+        let doc = self.storage.get(id)?;
+        if doc.is_some() {
+            self.storage.delete(id)?;
+            self.index.remove(id);
+            self.vectors.remove(id);
+            self.derived_dirty = true;
+        }
+        match (&doc, purge) {
+            (_, true) => {
+                self.tombstones.remove(id);
+                self.history.remove(id)?;
+                self.trash.remove(id)?;
+            }
+            (Some(doc), false) => {
+                let deleted = Utc::now();
+                self.trash.put(doc, deleted)?;
+                self.tombstones.insert(
+                    id.clone(),
+                    Tombstone {
+                        title: doc.title.clone(),
+                        source: doc.source.clone(),
+                        deleted,
+                    },
+                );
+            }
+            (None, false) => {}
+        }
+        Ok(doc)
+    }
+
+    /// Re-embeds every document with the configured provider.
+    pub fn reembed(&mut self) -> Result<usize> {
+        self.embedder = None;
+        let docs = self.storage.all()?;
+        let embedder = self.embedder()?;
+        let name = embedder.name();
+        let progress = Progress::new("documents re-embedded", docs.len());
+        let vectors = runtime::block_on(parallel::map_async(
+            &docs,
+            parallel::default_jobs(),
+            |doc| async {
+                let vectors = embeddings(embedder, doc).await;
+                progress.inc(1);
+                vectors
+            },
+        ));
+        let vectors = vectors.into_iter().collect::<Result<Vec<_>>>()?;
+        drop(progress);
+        self.vectors.reset(&name);
+        for (doc, vectors) in docs.iter().zip(vectors) {
+            store_embeddings(&mut self.vectors, &name, doc, vectors)?;
+        }
+        Ok(docs.len())
+    }
+
+    /// Rebuilds the full-text index from the stored documents, and the
+    /// link, task and graph indexes on the next commit. Returns how many
+    /// documents were indexed.
+    pub fn reindex(&mut self) -> Result<usize> {
+        let docs = self.storage.all()?;
+        let progress = Progress::new("documents reindexed", docs.len());
+        self.index.rebuild(&[]);
+        for doc in &docs {
+            self.index.insert(doc);
+            progress.inc(1);
+        }
+        self.derived_dirty = true;
+        Ok(docs.len())
+    }
+
+    /// Hashes of the blobs still needed: those of `docs`, of the documents
+    /// in the trash and of the documents as they were before the
+    /// operations `ozy undo` can still reverse, since those may be brought
+    /// back with their files.
+    pub fn referenced_blobs(&self, docs: &[Document]) -> Result<BTreeSet<String>> {
+        let trashed = self.trash.all()?;
+        let undoable = self
+            .undo
+            .operations()
+            .iter()
+            .flat_map(|o| &o.changes)
+            .filter_map(|c| c.before.as_ref());
+        Ok(docs
+            .iter()
+            .chain(trashed.iter().map(|t| &t.document))
+            .chain(undoable)
+            .flat_map(blobs_of)
+            .cloned()
+            .collect())
+    }
+
+    /// Flushes in-memory state such as the indexes to disk. In a dry run,
+    /// it reports the documents that would have changed instead.
+    pub fn commit(&mut self) -> Result<()> {
+        if dry_run() {
+            for (id, doc) in self.storage.held_back() {
+                match doc {
+                    Some(doc) => tracing::info!("dry run: would write {id}  {}", doc.title),
+                    None => tracing::info!("dry run: would delete {id}"),
+                }
+            }
+            tracing::info!("dry run: nothing was changed");
+            return Ok(());
+        }
+        if let Some((command, changes)) = self.recording.take() {
+            let mut recorded = Vec::new();
+            for (id, mut change) in changes {
+                change.after = undo::state(self.storage.get(&id)?.as_ref())?;
+                let before = undo::state(change.before.as_ref())?;
+                let unchanged = before == change.after
+                    && change.tombstone.as_ref().map(|t| t.deleted)
+                        == self.tombstones.get(&id).map(|t| t.deleted);
+                if !unchanged {
+                    recorded.push(change);
+                }
+            }
+            if !recorded.is_empty() {
+                self.undo.push(Operation {
+                    command,
+                    at: Utc::now(),
+                    changes: recorded,
+                });
+                self.undo.save()?;
+            }
+        }
+        if let Some(days) = self.config.trash.expire_after_days {
+            for id in self.trash.expire(days)? {
+                self.history.remove(&id)?;
+                tracing::info!("emptied {id} from the trash after {days} days");
+            }
+        }
+        if self.derived_dirty {
+            let docs = self.storage.all()?;
+            self.links.rebuild(&docs);
+            self.links.save()?;
+            self.tasks.rebuild(&docs);
+            self.tasks.save()?;
+            self.graph
+                .rebuild(&docs, &self.links, self.ontology.as_ref());
+            self.graph.save()?;
+            self.derived_dirty = false;
+        }
+        self.storage.flush()?;
+        self.index.save()?;
+        self.vectors.save()?;
+        self.tombstones.save()
+    }
+}
+
+/// Blobs a document needs: its attachments, their thumbnails and its page
+/// snapshot.
+pub fn blobs_of(doc: &Document) -> impl Iterator<Item = &String> {
+    let attachments = doc
+        .attachments
+        .iter()
+        .flat_map(|a| std::iter::once(&a.blob).chain(&a.thumbnail));
+    [SNAPSHOT_KEY, ARCHIVE_KEY]
+        .into_iter()
+        .filter_map(|key| doc.metadata.get(key))
+        .chain(attachments)
+}
+
+fn embedding_text(doc: &Document) -> String {
+    format!("{}\n{}", doc.title, doc.content)
+}
+
+/// The embedding of the whole document, followed by one per chunk when
+/// the document was split into more than one.
+async fn embeddings(embedder: &dyn EmbeddingProvider, doc: &Document) -> Result<Vec<Vec<f32>>> {
+    let mut out = vec![embedder.embed(&embedding_text(doc)).await?];
+    if doc.chunks.len() > 1 {
+        for chunk in &doc.chunks {
+            let text = format!("{}\n{}", doc.title, chunk.text(&doc.content));
+            out.push(embedder.embed(&text).await?);
+        }
+    }
+    Ok(out)
+}
+
+fn store_embeddings(
+    index: &mut VectorIndex,
+    model: &str,
+    doc: &Document,
+    vectors: Vec<Vec<f32>>,
+) -> Result<()> {
+    let mut vectors = vectors.into_iter();
+    if let Some(vector) = vectors.next() {
+        index.insert(model, doc.id.clone(), vector)?;
+    }
+    for (chunk, vector) in doc.chunks.iter().zip(vectors) {
+        index.insert_chunk(model, chunk.id.clone(), doc.id.clone(), vector)?;
+    }
+    Ok(())
+}
+
+/// Knowledge bases for tests, each in a directory of its own.
+#[cfg(test)]
+pub(crate) mod scratch {
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::*;
+
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Held by tests reading or switching the dry run, which is set for
+    /// the whole process.
+    pub(crate) fn lock() -> MutexGuard<'static, ()> {
+        LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A new knowledge base, removed again when dropped.
+    pub(crate) struct Scratch {
+        pub kb: KnowledgeBase,
+        pub dir: PathBuf,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl Scratch {
+        pub(crate) fn new(test: &str) -> Self {
+            let lock = lock();
+            set_dry_run(false);
+            let dir = std::env::temp_dir().join(format!("ozy-{}-{test}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            Scratch {
+                kb: KnowledgeBase::open_or_init(&dir).expect("a new knowledge base"),
+                dir,
+                _lock: lock,
+            }
+        }
+
+        /// Opens the knowledge base again, as the next command would.
+        pub(crate) fn reopen(&mut self) {
+            self.kb = KnowledgeBase::open(&self.dir).expect("the knowledge base");
+        }
+
+        /// Every file of the knowledge base with its contents, to tell
+        /// whether anything was written.
+        pub(crate) fn files(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+            fn walk(dir: &Path, files: &mut BTreeMap<PathBuf, Vec<u8>>) {
+                for entry in std::fs::read_dir(dir).expect("a readable directory") {
+                    let path = entry.expect("a directory entry").path();
+                    match path.is_dir() {
+                        true => walk(&path, files),
+                        false => {
+                            let bytes = std::fs::read(&path).expect("a readable file");
+                            files.insert(path, bytes);
+                        }
+                    }
+                }
+            }
+            let mut files = BTreeMap::new();
+            walk(&self.dir, &mut files);
+            files
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            set_dry_run(false);
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
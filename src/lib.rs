@@ -0,0 +1,58 @@
+pub mod archive;
+pub mod attachments;
+pub mod citations;
+pub mod cli;
+pub mod clip;
+pub mod commands;
+pub mod completion;
+pub mod config;
+pub mod dates;
+pub mod dirs;
+pub mod entities;
+pub mod error;
+pub mod export;
+pub mod filter;
+pub mod fingerprint;
+pub mod fuzzy;
+pub mod graph;
+pub mod graphql;
+pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod index;
+pub mod kb;
+pub mod linkcheck;
+pub mod links;
+pub mod logging;
+pub mod mcp;
+pub mod media;
+pub mod ml;
+pub mod ontology;
+pub mod output;
+pub mod parallel;
+pub mod parser;
+pub mod plugins;
+pub mod progress;
+pub mod query;
+pub mod relations;
+pub mod relay;
+pub mod review;
+pub mod rules;
+pub mod runtime;
+pub mod search;
+pub mod server;
+pub mod skos;
+pub mod storage;
+pub mod sync;
+pub mod tags;
+pub mod tasks;
+pub mod templates;
+pub mod thumbnails;
+pub mod tombstones;
+pub mod transform;
+pub mod trash;
+pub mod tui;
+pub mod types;
+pub mod undo;
+pub mod vectors;
+pub mod web;
@@ -0,0 +1,58 @@
+//! Probing external URLs for link rot.
+
+use reqwest::Method;
+
+use crate::parallel;
+use crate::progress::Progress;
+use crate::web::{self, Client};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// The server answered with a redirect to another location.
+    Redirected {
+        status: u16,
+        location: String,
+    },
+    /// An error status, or no answer at all.
+    Dead(String),
+}
+
+/// Probes every URL through `client`, `concurrency` at once, and returns
+/// its status, in the order given.
+pub async fn check(urls: &[String], client: &Client, concurrency: usize) -> Vec<Status> {
+    let progress = Progress::new("links checked", urls.len());
+    parallel::map_async(urls, concurrency, |url| async {
+        let status = probe(client, url).await;
+        progress.inc(1);
+        status
+    })
+    .await
+}
+
+/// The client retries timeouts and 429 and 5xx answers itself.
+async fn probe(client: &Client, url: &str) -> Status {
+    // Some servers refuse HEAD; ask again with GET before judging.
+    let result = match client.request(Method::HEAD, url).await {
+        Ok(response) if matches!(response.status().as_u16(), 403 | 405 | 501) => {
+            client.request(Method::GET, url).await
+        }
+        result => result,
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(e) => return Status::Dead(format!("{e:#}")),
+    };
+    let status = response.status();
+    if status.is_redirection() {
+        let location = web::location(&response).unwrap_or_default();
+        Status::Redirected {
+            status: status.as_u16(),
+            location: web::resolve(url, location),
+        }
+    } else if status.is_success() {
+        Status::Ok
+    } else {
+        Status::Dead(status.to_string())
+    }
+}
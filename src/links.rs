@@ -0,0 +1,214 @@
+//! Links between documents: extraction at parse time and the backlink index.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{read_json_or_default, write_json};
+use crate::types::{Document, DocumentId};
+
+/// A reference found in a document, as written by its author.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Link {
+    /// `[[Note Title]]`, resolved by title or alias.
+    Wiki(String),
+    /// A markdown or HTML link target: a relative path or a URL.
+    Href(String),
+}
+
+impl Link {
+    pub fn is_external(&self) -> bool {
+        matches!(self, Link::Href(h) if h.contains("://") || h.starts_with("mailto:"))
+    }
+}
+
+/// Finds `[[wiki links]]`, `[text](target)` and `href="target"` links.
+pub fn extract(raw: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    let mut rest = raw;
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("]]") else { break };
+        let inner = &after[..end];
+        // [[Title#Heading|Label]] links to Title.
+        let target = inner.split(['|', '#']).next().unwrap_or_default().trim();
+        if !target.is_empty() && !inner.contains('\n') {
+            links.push(Link::Wiki(target.to_string()));
+        }
+        rest = &after[end + 2..];
+    }
+
+    let mut rest = raw;
+    while let Some(start) = rest.find("](") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find(')') else { break };
+        let target = after[..end].split_whitespace().next().unwrap_or_default();
+        let target = target.trim_matches(|c| c == '<' || c == '>');
+        if !target.is_empty() && !target.starts_with('#') {
+            links.push(Link::Href(target.to_string()));
+        }
+        rest = &after[end + 1..];
+    }
+
+    let lower = raw.to_ascii_lowercase();
+    let mut offset = 0;
+    while let Some(start) = lower[offset..].find("href=") {
+        let at = offset + start + 5;
+        let quote = raw[at..].chars().next();
+        offset = at;
+        if let Some(q @ ('"' | '\'')) = quote {
+            if let Some(end) = raw[at + 1..].find(q) {
+                let target = &raw[at + 1..at + 1 + end];
+                if !target.is_empty() && !target.starts_with('#') {
+                    links.push(Link::Href(target.to_string()));
+                }
+                offset = at + 1 + end;
+            }
+        }
+    }
+
+    links.sort();
+    links.dedup();
+    links
+}
+
+/// Resolved links between documents, rebuilt whenever documents change so
+/// that links to notes added later resolve too.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LinkIndex {
+    outgoing: BTreeMap<DocumentId, BTreeSet<DocumentId>>,
+    incoming: BTreeMap<DocumentId, BTreeSet<DocumentId>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl LinkIndex {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("links.json");
+        let mut index: LinkIndex = read_json_or_default(&path)?;
+        index.path = path;
+        Ok(index)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    /// Resolves the links of every document against all titles, aliases
+    /// and source paths.
+    pub fn rebuild(&mut self, docs: &[Document]) {
+        let resolver = Resolver::new(docs);
+        self.outgoing.clear();
+        self.incoming.clear();
+        for doc in docs {
+            for link in &doc.links {
+                if let Some(target) = resolver.resolve(doc, link) {
+                    if target != doc.id {
+                        self.outgoing
+                            .entry(doc.id.clone())
+                            .or_default()
+                            .insert(target.clone());
+                        self.incoming
+                            .entry(target)
+                            .or_default()
+                            .insert(doc.id.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn links_from(&self, id: &DocumentId) -> impl Iterator<Item = &DocumentId> {
+        self.outgoing.get(id).into_iter().flatten()
+    }
+
+    pub fn links_to(&self, id: &DocumentId) -> impl Iterator<Item = &DocumentId> {
+        self.incoming.get(id).into_iter().flatten()
+    }
+}
+
+/// Finds the documents links point to, by title, alias, file name or path.
+pub struct Resolver {
+    by_title: HashMap<String, DocumentId>,
+    by_path: HashMap<PathBuf, DocumentId>,
+}
+
+impl Resolver {
+    pub fn new(docs: &[Document]) -> Self {
+        let mut by_title = HashMap::new();
+        let mut by_path = HashMap::new();
+        for doc in docs {
+            for name in std::iter::once(&doc.title).chain(&doc.aliases) {
+                by_title
+                    .entry(name.to_lowercase())
+                    .or_insert_with(|| doc.id.clone());
+            }
+            if let Some(source) = &doc.source {
+                by_path.insert(PathBuf::from(source), doc.id.clone());
+                if let Some(stem) = Path::new(source).file_stem().and_then(|s| s.to_str()) {
+                    by_title
+                        .entry(stem.to_lowercase())
+                        .or_insert_with(|| doc.id.clone());
+                }
+            }
+        }
+        Resolver { by_title, by_path }
+    }
+
+    pub fn resolve(&self, from: &Document, link: &Link) -> Option<DocumentId> {
+        if link.is_external() {
+            return None;
+        }
+        match link {
+            Link::Wiki(title) => self.by_title.get(&title.to_lowercase()).cloned(),
+            Link::Href(href) => {
+                let href = percent_decode(href.split('#').next().unwrap_or_default());
+                let base = Path::new(from.source.as_deref()?).parent()?;
+                self.by_path.get(&normalize(&base.join(href))).cloned()
+            }
+        }
+    }
+}
+
+/// Decodes `%XX` escapes, as in `My%20Note.md`, leaving malformed ones.
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Lexically resolves `.` and `..` so paths compare without touching disk.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,82 @@
+//! Diagnostics of what ozy is doing, as opposed to the output of commands:
+//! human-readable on stderr by default, or JSON lines, optionally appended
+//! to a file so that long `watch` and `serve` sessions can be monitored.
+//! `-v` and `-q` set the level of ozy's own messages, while `RUST_LOG`, when
+//! set, filters by level and module instead, e.g.
+//! `RUST_LOG=ozymandias::server=debug`. The level comes from the command
+//! line, so it is parsed before the subscriber is installed.
+
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+
+/// Messages at or above this level are logged without `-v` or `-q`.
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+
+/// Each `-v` raises [`DEFAULT_LEVEL`] one step towards TRACE, each `-q`
+/// lowers it towards OFF.
+pub fn level(verbose: u8, quiet: u8) -> LevelFilter {
+    const LEVELS: [LevelFilter; 6] = [
+        LevelFilter::OFF,
+        LevelFilter::ERROR,
+        LevelFilter::WARN,
+        LevelFilter::INFO,
+        LevelFilter::DEBUG,
+        LevelFilter::TRACE,
+    ];
+    let default = LEVELS.iter().position(|l| *l == DEFAULT_LEVEL).unwrap() as i32;
+    let index = (default + i32::from(verbose) - i32::from(quiet)).clamp(0, 5);
+    LEVELS[index as usize]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// One readable line per event
+    Text,
+    /// One JSON object per event, with a timestamp and the module
+    Json,
+}
+
+/// Installs the global subscriber, logging ozy's messages at `level` and
+/// only problems from the libraries it uses, unless `RUST_LOG` says
+/// otherwise. Logs go to `file` when given, appended to what is already
+/// there, and to stderr otherwise.
+pub fn init(level: LevelFilter, format: LogFormat, file: Option<&Path>) -> Result<()> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::new(format!(
+            "{},ozymandias={level}",
+            level.min(LevelFilter::WARN)
+        ))
+    });
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = match (format, file) {
+        (LogFormat::Json, Some(path)) => builder.json().with_writer(open(path)?).try_init(),
+        (LogFormat::Json, None) => builder.json().with_writer(std::io::stderr).try_init(),
+        (LogFormat::Text, Some(path)) => {
+            builder.with_ansi(false).with_writer(open(path)?).try_init()
+        }
+        // Timestamps and module paths are noise in an interactive session.
+        (LogFormat::Text, None) => builder
+            .without_time()
+            .with_target(false)
+            .with_ansi(std::io::stderr().is_terminal())
+            .with_writer(std::io::stderr)
+            .try_init(),
+    };
+    result.map_err(|e| anyhow::anyhow!(e))
+}
+
+fn open(path: &Path) -> Result<Mutex<std::fs::File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("cannot open log file {}", path.display()))?;
+    Ok(Mutex::new(file))
+}
@@ -1,3 +1,15 @@
-fn main() {
-    println!("Hello, world!");
+use std::process::ExitCode;
+
+use clap::Parser;
+use ozymandias::{cli::Cli, commands, completion, error};
+
+fn main() -> ExitCode {
+    completion::complete();
+    match commands::run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(error::exit_code(&e))
+        }
+    }
 }
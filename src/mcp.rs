@@ -0,0 +1,256 @@
+//! A Model Context Protocol server over stdin and stdout, run by `ozy
+//! serve --mcp`, so that LLM clients can search, read and add documents.
+//!
+//! Messages are JSON-RPC 2.0, one per line. Nothing else may be written to
+//! stdout while serving; diagnostics go to stderr.
+
+use std::io::{BufRead, Write};
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::index::snippet;
+use crate::kb::KnowledgeBase;
+use crate::search::{self, SearchMode};
+use crate::tags;
+use crate::transform::{Outcome, Pipelines};
+use crate::types::{Document, DocumentId, DocumentKind};
+
+/// Protocol revisions understood, newest first.
+const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+const SNIPPET_WIDTH: usize = 200;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Answers requests from stdin until it is closed.
+pub fn serve(mut kb: KnowledgeBase) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut stdout = std::io::stdout().lock();
+    for line in stdin.lock().lines() {
+        let line = line.context("cannot read from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(message) => handle(&mut kb, message),
+            Err(e) => Some(error(Value::Null, PARSE_ERROR, &e.to_string())),
+        };
+        if let Some(response) = response {
+            writeln!(stdout, "{response}")?;
+            stdout.flush()?;
+        }
+    }
+    kb.commit()
+}
+
+/// The response to a message; notifications get none.
+fn handle(kb: &mut KnowledgeBase, message: Value) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        return id.map(|id| error(id, INVALID_REQUEST, "missing method"));
+    };
+    // Notifications such as `notifications/initialized` need no answer.
+    let id = id?;
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    let result = match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => call(kb, params),
+        _ => {
+            return Some(error(
+                id,
+                METHOD_NOT_FOUND,
+                &format!("unknown method {method}"),
+            ))
+        }
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => error(id, INVALID_PARAMS, &format!("{e:#}")),
+    })
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = requested
+        .filter(|v| PROTOCOL_VERSIONS.contains(v))
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "ozymandias", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn tools() -> Value {
+    json!([
+        {
+            "name": "search",
+            "description": "Search the knowledge base. Returns document ids, titles and \
+                matching excerpts, best first. The query accepts words, \"quoted \
+                phrases\", AND/OR/NOT and filters such as tag:history or type:markdown.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "limit": { "type": "integer", "minimum": 1, "default": 10 },
+                    "mode": { "type": "string", "enum": ["keyword", "semantic", "hybrid"] },
+                },
+                "required": ["query"],
+            },
+        },
+        {
+            "name": "get",
+            "description": "Read a document of the knowledge base by id, with its tags \
+                and metadata.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "id": { "type": "string" } },
+                "required": ["id"],
+            },
+        },
+        {
+            "name": "add",
+            "description": "Add a note to the knowledge base.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "title": { "type": "string" },
+                    "content": { "type": "string", "description": "Markdown text" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "source": { "type": "string", "description": "URL or citation" },
+                },
+                "required": ["title", "content"],
+            },
+        },
+    ])
+}
+
+/// Runs a tool. Failures of the tool itself are reported in the result,
+/// so that the model sees them; only malformed calls are errors.
+fn call(kb: &mut KnowledgeBase, params: Value) -> Result<Value> {
+    #[derive(Deserialize)]
+    struct Call {
+        name: String,
+        #[serde(default)]
+        arguments: Value,
+    }
+    let call: Call = serde_json::from_value(params).context("invalid tools/call parameters")?;
+    let output = match call.name.as_str() {
+        "search" => search_tool(kb, parse(call.arguments)?),
+        "get" => get_tool(kb, parse(call.arguments)?),
+        "add" => add_tool(kb, parse(call.arguments)?),
+        other => bail!("unknown tool {other}"),
+    };
+    let (text, is_error) = match output {
+        Ok(text) => (text, false),
+        Err(e) => (format!("{e:#}"), true),
+    };
+    Ok(json!({ "content": [{ "type": "text", "text": text }], "isError": is_error }))
+}
+
+fn parse<T: serde::de::DeserializeOwned>(arguments: Value) -> Result<T> {
+    serde_json::from_value(arguments).context("invalid tool arguments")
+}
+
+#[derive(Deserialize)]
+struct SearchArguments {
+    query: String,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(default)]
+    mode: SearchMode,
+}
+
+fn default_limit() -> usize {
+    10
+}
+
+fn search_tool(kb: &mut KnowledgeBase, args: SearchArguments) -> Result<String> {
+    let query = kb.parse_query(&args.query)?;
+    let matches = search::run(kb, &query, args.mode)?;
+    if matches.is_empty() {
+        return Ok(format!("no results for {:?}", args.query));
+    }
+    let mut out = String::new();
+    for m in matches.iter().take(args.limit) {
+        out.push_str(&format!(
+            "{}  {}\n  {}\n",
+            m.doc.id,
+            m.doc.title,
+            snippet(m.passage(), &query.text, SNIPPET_WIDTH)
+        ));
+    }
+    Ok(out)
+}
+
+#[derive(Deserialize)]
+struct GetArguments {
+    id: String,
+}
+
+fn get_tool(kb: &mut KnowledgeBase, args: GetArguments) -> Result<String> {
+    let doc = kb.get(&kb.resolve(&args.id)?)?;
+    let mut out = format!("# {}\n\nid: {}\n", doc.title, doc.id);
+    if let Some(source) = &doc.source {
+        out.push_str(&format!("source: {source}\n"));
+    }
+    if !doc.tags.is_empty() {
+        out.push_str(&format!("tags: {}\n", doc.tags.join(", ")));
+    }
+    for (key, value) in &doc.metadata {
+        out.push_str(&format!("{key}: {value}\n"));
+    }
+    out.push('\n');
+    out.push_str(&doc.content);
+    Ok(out)
+}
+
+#[derive(Deserialize)]
+struct AddArguments {
+    title: String,
+    content: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    source: Option<String>,
+}
+
+fn add_tool(kb: &mut KnowledgeBase, args: AddArguments) -> Result<String> {
+    let key = args
+        .source
+        .clone()
+        .unwrap_or_else(|| format!("mcp:{}:{}", args.title, Utc::now().to_rfc3339()));
+    let id = DocumentId::derive(&key);
+    if kb.tombstones.get(&id).is_some() {
+        bail!("{id} was removed from the knowledge base and cannot be added again");
+    }
+    let mut doc = Document::new(
+        id,
+        args.title,
+        DocumentKind::Markdown,
+        args.content,
+        args.source,
+    );
+    doc.tags = args
+        .tags
+        .iter()
+        .map(|t| tags::normalize(t))
+        .collect::<Result<_>>()?;
+    let pipelines = Pipelines::from_config(&kb.config.pipeline, kb.plugins()?)?;
+    if let Outcome::Skip(reason) = pipelines.run(&mut doc, kb)? {
+        return Ok(format!("not added: {reason}"));
+    }
+    kb.insert(&doc)?;
+    kb.commit()?;
+    Ok(format!("added {}  {}", doc.id, doc.title))
+}
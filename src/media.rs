@@ -0,0 +1,291 @@
+//! Metadata that media files carry about themselves, copied into the
+//! metadata of the documents they are attached to so that queries such as
+//! `meta:camera=fuji` or `created:2019` find them:
+//!
+//! - photos: when they were `taken`, where (`gps`, as `lat, lon`) and the
+//!   `camera`, from EXIF,
+//! - PDFs: their `authors` and `keywords`, from XMP or the document
+//!   information dictionary,
+//! - audio: the `artist`, `album`, `track`, `year` and `genre` of ID3 tags.
+//!
+//! A document keeps what its own metadata says; the first attachment
+//! fills in what it lacks.
+
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+use exif::{In, Tag, Value};
+use id3::TagLike;
+use quick_xml::events::{BytesRef, BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::import::bibtex::AUTHORS_KEY;
+use crate::storage::BlobStore;
+use crate::types::Document;
+
+/// Metadata key of the moment a photo was taken.
+pub const TAKEN_KEY: &str = "taken";
+
+/// Whether metadata can be read from files of this media type.
+pub fn supported(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/jpeg" | "image/png" | "image/webp" | "application/pdf" | "audio/mpeg"
+    )
+}
+
+/// The metadata a file of type `mime` carries.
+pub fn extract(mime: &str, bytes: &[u8]) -> BTreeMap<String, String> {
+    let found = match mime {
+        "image/jpeg" | "image/png" | "image/webp" => exif(bytes),
+        "application/pdf" => pdf(bytes),
+        "audio/mpeg" => id3(bytes),
+        _ => Vec::new(),
+    };
+    found
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.trim().to_string()))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+/// Adds to `doc` the metadata of its attachments that it lacks.
+pub fn fill(blobs: &BlobStore, doc: &mut Document) {
+    for attachment in &doc.attachments {
+        let Some(mime) = attachment.mime.as_deref().filter(|m| supported(m)) else {
+            continue;
+        };
+        let bytes = match blobs.get(&attachment.blob) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => continue,
+            Err(e) => {
+                tracing::warn!("cannot read {}: {e:#}", attachment.name);
+                continue;
+            }
+        };
+        for (key, value) in extract(mime, &bytes) {
+            doc.metadata.entry(key).or_insert(value);
+        }
+    }
+}
+
+fn exif(bytes: &[u8]) -> Vec<(&'static str, String)> {
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut Cursor::new(bytes)) else {
+        return Vec::new();
+    };
+    let ascii = |tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(parts) => parts
+            .first()
+            .map(|p| String::from_utf8_lossy(p).trim().to_string()),
+        _ => None,
+    };
+    let degrees = |tag, reference, negative: &str| {
+        let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+            return None;
+        };
+        let value = parts
+            .iter()
+            .zip([1.0, 60.0, 3600.0])
+            .map(|(p, scale)| p.to_f64() / scale)
+            .sum::<f64>();
+        Some(if ascii(reference)? == negative {
+            -value
+        } else {
+            value
+        })
+    };
+
+    let mut out = Vec::new();
+    // Written `2024:03:01 12:30:00`.
+    if let Some(taken) = ascii(Tag::DateTimeOriginal).or_else(|| ascii(Tag::DateTime)) {
+        out.push((TAKEN_KEY, taken.replacen(':', "-", 2)));
+    }
+    if let (Some(lat), Some(lon)) = (
+        degrees(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        degrees(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+    ) {
+        out.push(("gps", format!("{lat:.6}, {lon:.6}")));
+    }
+    let camera: Vec<String> = [ascii(Tag::Make), ascii(Tag::Model)]
+        .into_iter()
+        .flatten()
+        .collect();
+    if !camera.is_empty() {
+        out.push(("camera", camera.join(" ")));
+    }
+    out
+}
+
+fn id3(bytes: &[u8]) -> Vec<(&'static str, String)> {
+    let Ok(tag) = id3::Tag::read_from2(Cursor::new(bytes)) else {
+        return Vec::new();
+    };
+    [
+        ("artist", tag.artist().map(str::to_string)),
+        ("album", tag.album().map(str::to_string)),
+        ("track", tag.title().map(str::to_string)),
+        ("year", tag.year().map(|y| y.to_string())),
+        ("genre", tag.genre_parsed().map(|g| g.into_owned())),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| Some((key, value?)))
+    .collect()
+}
+
+/// The authors and keywords of a PDF, from its XMP packet, else from its
+/// document information dictionary.
+fn pdf(bytes: &[u8]) -> Vec<(&'static str, String)> {
+    let xmp = xmp(bytes);
+    let authors = xmp
+        .get("dc:creator")
+        .map(|names| names.join("; "))
+        .or_else(|| info(bytes, "Author"));
+    let keywords = xmp
+        .get("pdf:Keywords")
+        .or_else(|| xmp.get("dc:subject"))
+        .map(|words| words.join(", "))
+        .or_else(|| info(bytes, "Keywords"));
+    [(AUTHORS_KEY, authors), ("keywords", keywords)]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+}
+
+/// The properties of the first XMP packet in `bytes`, by qualified name;
+/// lists such as `dc:creator` have a value per item. PDFs usually keep
+/// the packet uncompressed so that tools can find it this way.
+fn xmp(bytes: &[u8]) -> BTreeMap<String, Vec<String>> {
+    let mut properties: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let Some(start) = find(bytes, b"<x:xmpmeta") else {
+        return properties;
+    };
+    let Some(end) = find(&bytes[start..], b"</x:xmpmeta>") else {
+        return properties;
+    };
+    let packet = String::from_utf8_lossy(&bytes[start..start + end]);
+    let mut reader = Reader::from_str(&packet);
+    // Names of the open elements.
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                attributes(&e, &mut properties);
+                path.push(e.name().as_ref().to_string());
+                text.clear();
+            }
+            Ok(Event::Empty(e)) => attributes(&e, &mut properties),
+            Ok(Event::Text(t)) => text.push_str(&t),
+            Ok(Event::CData(t)) => text.push_str(&t),
+            Ok(Event::GeneralRef(r)) => text.push_str(&entity(&r)),
+            Ok(Event::End(_)) => {
+                let name = path.pop().unwrap_or_default();
+                let value = std::mem::take(&mut text);
+                let value = value.trim();
+                // The property is the innermost element outside RDF's own.
+                let property = std::iter::once(&name)
+                    .chain(path.iter().rev())
+                    .find(|n| !n.starts_with("rdf:") && !n.starts_with("x:"));
+                if let (Some(property), false) = (property, value.is_empty()) {
+                    properties
+                        .entry(property.clone())
+                        .or_default()
+                        .push(value.to_string());
+                }
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    properties
+}
+
+/// Simple properties written as attributes of an `rdf:Description`.
+fn attributes(element: &BytesStart, properties: &mut BTreeMap<String, Vec<String>>) {
+    if element.name().as_ref() != "rdf:Description" {
+        return;
+    }
+    for a in element.attributes().flatten() {
+        let key = a.key.as_ref().to_string();
+        if !key.starts_with("rdf:") && !key.starts_with("xmlns") {
+            let value = a
+                .normalized_value_with(Default::default(), 16, |_| None)
+                .map_or_else(|_| a.value.to_string(), |v| v.into_owned());
+            properties.entry(key).or_default().push(value);
+        }
+    }
+}
+
+fn entity(reference: &BytesRef) -> String {
+    if let Ok(Some(c)) = reference.resolve_char_ref() {
+        return c.to_string();
+    }
+    let name: &str = reference;
+    match name {
+        "amp" => "&",
+        "lt" => "<",
+        "gt" => ">",
+        "quot" => "\"",
+        "apos" => "'",
+        _ => return format!("&{name};"),
+    }
+    .to_string()
+}
+
+/// A string entry of a PDF's document information dictionary, such as
+/// `/Author (Ada Lovelace)`.
+fn info(bytes: &[u8], key: &str) -> Option<String> {
+    let marker = format!("/{key}");
+    let mut from = 0;
+    while let Some(at) = find(&bytes[from..], marker.as_bytes()) {
+        let rest = &bytes[from + at + marker.len()..];
+        from += at + marker.len();
+        let rest = rest.trim_ascii_start();
+        if rest.first() == Some(&b'(') {
+            return Some(literal(&rest[1..]));
+        }
+    }
+    None
+}
+
+/// A PDF literal string up to its closing parenthesis, in UTF-16 when it
+/// starts with a byte order mark and in Latin-1 otherwise.
+fn literal(bytes: &[u8]) -> String {
+    let mut raw = Vec::new();
+    let mut depth = 0;
+    let mut chars = bytes.iter().copied();
+    while let Some(b) = chars.next() {
+        match b {
+            b'\\' => match chars.next() {
+                Some(b'n') => raw.push(b'\n'),
+                Some(b'r') => raw.push(b'\r'),
+                Some(b't') => raw.push(b'\t'),
+                Some(escaped) => raw.push(escaped),
+                None => break,
+            },
+            b'(' => {
+                depth += 1;
+                raw.push(b);
+            }
+            b')' if depth == 0 => break,
+            b')' => {
+                depth -= 1;
+                raw.push(b);
+            }
+            _ => raw.push(b),
+        }
+    }
+    match raw.strip_prefix(&[0xfe, 0xff]) {
+        Some(utf16) => String::from_utf16_lossy(
+            &utf16
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>(),
+        ),
+        None => raw.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
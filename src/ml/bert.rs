@@ -0,0 +1,104 @@
+//! The BERT encoder of sentence-embedding models such as
+//! all-MiniLM-L6-v2, run on the CPU with candle's implementation of the
+//! Hugging Face model.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config};
+
+pub struct Bert {
+    model: BertModel,
+    max_len: usize,
+}
+
+impl Bert {
+    pub fn load(config: &Path, weights: &Path) -> Result<Self> {
+        let config: Config = serde_json::from_str(
+            &std::fs::read_to_string(config)
+                .with_context(|| format!("failed to read {}", config.display()))?,
+        )
+        .with_context(|| format!("invalid model config {}", config.display()))?;
+        let bytes = std::fs::read(weights)
+            .with_context(|| format!("failed to read {}", weights.display()))?;
+        let model = VarBuilder::from_buffered_safetensors(bytes, DType::F32, &Device::Cpu)
+            .and_then(|vb| BertModel::load(vb, &config))
+            .with_context(|| format!("failed to load {}", weights.display()))?;
+        Ok(Bert {
+            model,
+            max_len: config.max_position_embeddings,
+        })
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Encodes token ids and mean-pools the last hidden state.
+    pub fn embed(&self, ids: &[u32]) -> Result<Vec<f32>> {
+        let input = Tensor::new(ids, &Device::Cpu)?.unsqueeze(0)?;
+        let token_types = input.zeros_like()?;
+        let hidden = self.model.forward(&input, &token_types, None)?;
+        Ok(hidden.mean(1)?.squeeze(0)?.to_vec1()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use candle_nn::VarMap;
+
+    use super::*;
+    use crate::ml::normalize;
+
+    const HIDDEN: usize = 16;
+
+    /// A BERT of two small layers with random weights, written to `dir`
+    /// as a downloaded model is.
+    fn tiny(dir: &Path) -> Bert {
+        let config = serde_json::json!({
+            "vocab_size": 64,
+            "hidden_size": HIDDEN,
+            "num_hidden_layers": 2,
+            "num_attention_heads": 4,
+            "intermediate_size": 32,
+            "hidden_act": "gelu",
+            "hidden_dropout_prob": 0.1,
+            "max_position_embeddings": 32,
+            "type_vocab_size": 2,
+            "initializer_range": 0.02,
+            "layer_norm_eps": 1e-12,
+            "pad_token_id": 0,
+            "classifier_dropout": null,
+            "model_type": "bert",
+        });
+        std::fs::create_dir_all(dir).unwrap();
+        let varmap = VarMap::new();
+        let vb = VarBuilder::from_varmap(&varmap, DType::F32, &Device::Cpu);
+        BertModel::load(vb, &serde_json::from_value(config.clone()).unwrap()).unwrap();
+        varmap.save(dir.join("model.safetensors")).unwrap();
+        std::fs::write(dir.join("config.json"), config.to_string()).unwrap();
+        Bert::load(&dir.join("config.json"), &dir.join("model.safetensors")).unwrap()
+    }
+
+    #[test]
+    fn embeddings_have_the_hidden_size_and_normalize_to_unit_length() {
+        let dir = std::env::temp_dir().join(format!("ozy-{}-tiny-bert", std::process::id()));
+        let bert = tiny(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(bert.max_len(), 32);
+
+        let mut a = bert.embed(&[2, 7, 9, 3]).unwrap();
+        let mut b = bert.embed(&[2, 11, 3]).unwrap();
+        assert_eq!((a.len(), b.len()), (HIDDEN, HIDDEN));
+        assert!(a.iter().chain(&b).all(|x| x.is_finite()));
+        assert_eq!(a, bert.embed(&[2, 7, 9, 3]).unwrap());
+        assert_ne!(a, b);
+        for v in [&mut a, &mut b] {
+            normalize(v);
+            let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((norm - 1.0).abs() < 1e-5, "{norm}");
+        }
+    }
+}
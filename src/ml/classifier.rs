@@ -0,0 +1,51 @@
+//! Predicts tags for a document from the tags of similar documents.
+
+use std::collections::BTreeMap;
+
+use super::{cosine, normalize};
+
+/// A nearest-centroid classifier whose labels are the existing tag
+/// vocabulary: each tag is represented by the mean embedding of the
+/// documents carrying it.
+#[derive(Debug, Default)]
+pub struct TagClassifier {
+    centroids: BTreeMap<String, Vec<f32>>,
+}
+
+impl TagClassifier {
+    /// Trains on `(tags, embedding)` pairs of already tagged documents.
+    pub fn train<'a>(examples: impl IntoIterator<Item = (&'a [String], &'a [f32])>) -> Self {
+        let mut sums: BTreeMap<String, Vec<f32>> = BTreeMap::new();
+        for (tags, vector) in examples {
+            for tag in tags {
+                let sum = sums
+                    .entry(tag.clone())
+                    .or_insert_with(|| vec![0.0; vector.len()]);
+                if sum.len() == vector.len() {
+                    sum.iter_mut().zip(vector).for_each(|(s, v)| *s += v);
+                }
+            }
+        }
+        for centroid in sums.values_mut() {
+            normalize(centroid);
+        }
+        TagClassifier { centroids: sums }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.centroids.is_empty()
+    }
+
+    /// Tags ranked by confidence (cosine similarity to the tag centroid),
+    /// best first, skipping anything at or below zero.
+    pub fn predict(&self, vector: &[f32]) -> Vec<(String, f32)> {
+        let mut scored: Vec<(String, f32)> = self
+            .centroids
+            .iter()
+            .map(|(tag, centroid)| (tag.clone(), cosine(vector, centroid)))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored
+    }
+}
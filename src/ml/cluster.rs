@@ -0,0 +1,111 @@
+//! Grouping documents into topics by embedding similarity.
+
+use std::collections::HashMap;
+
+use super::keywords::{candidates, top};
+use super::{cosine, normalize};
+use crate::index::{language_of, Analyzer};
+use crate::types::Document;
+
+/// Rounds of reassignment after which k-means stops even if it has not
+/// converged.
+const MAX_ITERATIONS: usize = 50;
+
+/// Spherical k-means: assigns each vector to one of `k` clusters by cosine
+/// similarity to the cluster centroids. Returns the cluster of each vector.
+///
+/// Centroids start with k-means++ seeding made deterministic by always
+/// taking the vector farthest from the centroids chosen so far, so that
+/// repeated runs agree.
+pub fn kmeans(vectors: &[&[f32]], k: usize) -> Vec<usize> {
+    let k = k.clamp(1, vectors.len().max(1));
+    if vectors.is_empty() {
+        return Vec::new();
+    }
+    let mut centroids: Vec<Vec<f32>> = vec![vectors[0].to_vec()];
+    while centroids.len() < k {
+        let farthest = vectors
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let nearest = centroids
+                    .iter()
+                    .map(|c| cosine(v, c))
+                    .fold(f32::MIN, f32::max);
+                (i, nearest)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map_or(0, |(i, _)| i);
+        centroids.push(vectors[farthest].to_vec());
+    }
+
+    let mut assignment = vec![usize::MAX; vectors.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (i, v) in vectors.iter().enumerate() {
+            let best = centroids
+                .iter()
+                .enumerate()
+                .max_by(|a, b| cosine(v, a.1).total_cmp(&cosine(v, b.1)))
+                .map_or(0, |(c, _)| c);
+            if assignment[i] != best {
+                assignment[i] = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+        for (c, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<&&[f32]> = vectors
+                .iter()
+                .zip(&assignment)
+                .filter(|(_, a)| **a == c)
+                .map(|(v, _)| v)
+                .collect();
+            // An emptied cluster keeps its old centroid.
+            if members.is_empty() {
+                continue;
+            }
+            centroid.iter_mut().for_each(|x| *x = 0.0);
+            for v in members {
+                centroid.iter_mut().zip(v.iter()).for_each(|(x, y)| *x += y);
+            }
+            normalize(centroid);
+        }
+    }
+    assignment
+}
+
+/// The words most characteristic of `members` compared with `corpus`: high
+/// frequency within the group, weighted by inverse document frequency
+/// over the corpus.
+pub fn label(
+    members: &[&Document],
+    corpus: &[Document],
+    analyzer: &Analyzer,
+    n: usize,
+) -> Vec<String> {
+    let words = |doc: &Document| {
+        candidates(
+            &format!("{} {}", doc.title, doc.content),
+            language_of(doc),
+            analyzer,
+        )
+    };
+    let mut df: HashMap<String, usize> = HashMap::new();
+    for doc in corpus {
+        let mut seen = words(doc);
+        seen.sort();
+        seen.dedup();
+        for w in seen {
+            *df.entry(w).or_default() += 1;
+        }
+    }
+    top(
+        members.iter().flat_map(|d| words(d)),
+        |w| df.get(w).copied().unwrap_or(0),
+        corpus.len(),
+        n,
+    )
+}
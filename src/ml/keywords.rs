@@ -0,0 +1,47 @@
+//! Picking the words that characterize a text, by TF-IDF.
+
+use std::collections::HashMap;
+
+use crate::index::{analyze, Analyzer};
+use crate::transform::language;
+
+/// The words of `text` that can serve as keywords: analyzed but not
+/// stemmed, so they read well, without stopwords, numbers and words of
+/// one or two letters. Chinese and Japanese bigrams are kept, except
+/// hiragana ones, which are mostly inflections and particles.
+pub fn candidates(text: &str, language: Option<&str>, analyzer: &Analyzer) -> Vec<String> {
+    let short_ok = language.is_some_and(language::is_unsegmented);
+    analyze(text, language)
+        .into_iter()
+        .filter(|w| {
+            (short_ok || w.chars().count() > 2)
+                && !w.chars().all(|c| c.is_ascii_digit())
+                && !analyzer.is_stopword(w, language)
+                && !w.chars().all(|c| ('\u{3040}'..='\u{309f}').contains(&c))
+        })
+        .collect()
+}
+
+/// The `n` words with the highest term frequency times inverse document
+/// frequency, best first. `df` gives the number of the `total` documents
+/// of the corpus containing a word.
+pub fn top(
+    words: impl IntoIterator<Item = String>,
+    df: impl Fn(&str) -> usize,
+    total: usize,
+    n: usize,
+) -> Vec<String> {
+    let mut tf: HashMap<String, usize> = HashMap::new();
+    for w in words {
+        *tf.entry(w).or_default() += 1;
+    }
+    let mut scored: Vec<(String, f64)> = tf
+        .into_iter()
+        .map(|(w, count)| {
+            let idf = ((total as f64 + 1.0) / (1.0 + df(&w) as f64)).ln() + 1.0;
+            (w, count as f64 * idf)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    scored.into_iter().take(n).map(|(w, _)| w).collect()
+}
@@ -0,0 +1,389 @@
+//! Text generation providers, configured in the `[llm]` config section.
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::providers::{self, http, post_json, send, OllamaProvider, OpenAiProvider};
+use super::EmbeddingProvider;
+use crate::config::{LlmConfig, LlmKind};
+
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+const OPENAI_MODEL: &str = "gpt-4o-mini";
+const OPENAI_KEY_ENV: &str = "OPENAI_API_KEY";
+const ANTHROPIC_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_MODEL: &str = "claude-3-5-haiku-latest";
+const ANTHROPIC_KEY_ENV: &str = "ANTHROPIC_API_KEY";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Longest completion requested from the Anthropic API, which requires a
+/// limit.
+const ANTHROPIC_MAX_TOKENS: u32 = 2048;
+const OLLAMA_URL: &str = "http://localhost:11434";
+const OLLAMA_MODEL: &str = "llama3.2";
+
+/// Completes a prompt with generated text.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Identifies provider and model, for messages and metadata.
+    fn name(&self) -> String;
+
+    async fn complete(&self, prompt: &str) -> Result<String>;
+
+    /// Like [`complete`](Self::complete), but calls `on_token` with each
+    /// piece of the text as it is generated. Returns the whole text.
+    async fn stream(
+        &self,
+        prompt: &str,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String> {
+        let text = self.complete(prompt).await?;
+        on_token(&text);
+        Ok(text)
+    }
+
+    /// The embeddings endpoint of the same service, if it has one.
+    fn embedder(&self) -> Option<Box<dyn EmbeddingProvider>> {
+        None
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        match self.embedder() {
+            Some(embedder) => embedder.embed(text).await,
+            None => bail!("{} cannot compute embeddings", self.name()),
+        }
+    }
+}
+
+/// Builds the provider selected by the `[llm]` config section, or `None`
+/// when no language model is configured.
+pub fn llm_from_config(config: &LlmConfig) -> Option<Box<dyn LlmProvider>> {
+    let model = config.model.clone();
+    Some(match config.provider {
+        LlmKind::None => return None,
+        LlmKind::OpenAi => {
+            let key_env = config.api_key_env.as_deref().unwrap_or(OPENAI_KEY_ENV);
+            Box::new(OpenAiChat {
+                url: config.url.clone().unwrap_or_else(|| OPENAI_URL.to_string()),
+                model: model.unwrap_or_else(|| OPENAI_MODEL.to_string()),
+                api_key: std::env::var(key_env).ok(),
+                embedding_model: config
+                    .embedding_model
+                    .clone()
+                    .unwrap_or_else(|| providers::OPENAI_MODEL.to_string()),
+            })
+        }
+        LlmKind::Anthropic => {
+            let key_env = config.api_key_env.as_deref().unwrap_or(ANTHROPIC_KEY_ENV);
+            Box::new(AnthropicMessages {
+                url: config
+                    .url
+                    .clone()
+                    .unwrap_or_else(|| ANTHROPIC_URL.to_string()),
+                model: model.unwrap_or_else(|| ANTHROPIC_MODEL.to_string()),
+                api_key: std::env::var(key_env).ok(),
+            })
+        }
+        LlmKind::Ollama => Box::new(OllamaGenerate {
+            url: config.url.clone().unwrap_or_else(|| OLLAMA_URL.to_string()),
+            model: model.unwrap_or_else(|| OLLAMA_MODEL.to_string()),
+            embedding_model: config
+                .embedding_model
+                .clone()
+                .unwrap_or_else(|| providers::OLLAMA_MODEL.to_string()),
+        }),
+    })
+}
+
+/// Any service implementing the OpenAI `/chat/completions` endpoint.
+pub struct OpenAiChat {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+    embedding_model: String,
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiChat {
+    fn name(&self) -> String {
+        format!("openai:{}", self.model)
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Response {
+            choices: Vec<Choice>,
+        }
+        #[derive(Deserialize)]
+        struct Choice {
+            message: Message,
+        }
+        #[derive(Deserialize)]
+        struct Message {
+            content: String,
+        }
+        let (url, request, body) = self.request(prompt, false);
+        let response: Response = post_json(request, &url, body).await?;
+        match response.choices.into_iter().next() {
+            Some(choice) => Ok(choice.message.content.trim().to_string()),
+            None => bail!("{url} returned no completion"),
+        }
+    }
+
+    async fn stream(
+        &self,
+        prompt: &str,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Chunk {
+            choices: Vec<Choice>,
+        }
+        #[derive(Deserialize)]
+        struct Choice {
+            delta: Delta,
+        }
+        #[derive(Deserialize)]
+        struct Delta {
+            #[serde(default)]
+            content: Option<String>,
+        }
+        let (url, request, body) = self.request(prompt, true);
+        let mut text = String::new();
+        // Server-sent events: one `data:` line per chunk, then `[DONE]`.
+        post_lines(request, &url, body, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(true);
+            };
+            if data == "[DONE]" {
+                return Ok(false);
+            }
+            let chunk: Chunk = serde_json::from_str(data)
+                .with_context(|| format!("unexpected response from {url}"))?;
+            for token in chunk.choices.into_iter().filter_map(|c| c.delta.content) {
+                on_token(&token);
+                text.push_str(&token);
+            }
+            Ok(true)
+        })
+        .await?;
+        Ok(text.trim().to_string())
+    }
+
+    fn embedder(&self) -> Option<Box<dyn EmbeddingProvider>> {
+        Some(Box::new(OpenAiProvider {
+            url: self.url.clone(),
+            model: self.embedding_model.clone(),
+            api_key: self.api_key.clone(),
+        }))
+    }
+}
+
+impl OpenAiChat {
+    fn request(
+        &self,
+        prompt: &str,
+        stream: bool,
+    ) -> (String, reqwest::RequestBuilder, serde_json::Value) {
+        let url = format!("{}/chat/completions", self.url.trim_end_matches('/'));
+        let mut request = http().post(&url);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let body = json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt }],
+            "stream": stream,
+        });
+        (url, request, body)
+    }
+}
+
+/// A local Ollama server's `/api/generate` endpoint.
+pub struct OllamaGenerate {
+    url: String,
+    model: String,
+    embedding_model: String,
+}
+
+#[async_trait]
+impl LlmProvider for OllamaGenerate {
+    fn name(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Response {
+            response: String,
+        }
+        let url = format!("{}/api/generate", self.url.trim_end_matches('/'));
+        let body = json!({ "model": self.model, "prompt": prompt, "stream": false });
+        let response: Response = post_json(http().post(&url), &url, body).await?;
+        Ok(response.response.trim().to_string())
+    }
+
+    async fn stream(
+        &self,
+        prompt: &str,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Chunk {
+            response: String,
+            #[serde(default)]
+            done: bool,
+        }
+        let url = format!("{}/api/generate", self.url.trim_end_matches('/'));
+        let body = json!({ "model": self.model, "prompt": prompt, "stream": true });
+        let mut text = String::new();
+        // One JSON object per line, the last one marked `done`.
+        post_lines(http().post(&url), &url, body, |line| {
+            if line.trim().is_empty() {
+                return Ok(true);
+            }
+            let chunk: Chunk = serde_json::from_str(line)
+                .with_context(|| format!("unexpected response from {url}"))?;
+            on_token(&chunk.response);
+            text.push_str(&chunk.response);
+            Ok(!chunk.done)
+        })
+        .await?;
+        Ok(text.trim().to_string())
+    }
+
+    fn embedder(&self) -> Option<Box<dyn EmbeddingProvider>> {
+        Some(Box::new(OllamaProvider {
+            url: self.url.clone(),
+            model: self.embedding_model.clone(),
+        }))
+    }
+}
+
+/// The Anthropic `/messages` endpoint. Anthropic offers no embeddings.
+pub struct AnthropicMessages {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicMessages {
+    fn name(&self) -> String {
+        format!("anthropic:{}", self.model)
+    }
+
+    async fn complete(&self, prompt: &str) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Response {
+            content: Vec<Block>,
+        }
+        #[derive(Deserialize)]
+        struct Block {
+            #[serde(default)]
+            text: String,
+        }
+        let (url, request, body) = self.request(prompt, false);
+        let response: Response = post_json(request, &url, body).await?;
+        let text: String = response.content.into_iter().map(|b| b.text).collect();
+        if text.is_empty() {
+            bail!("{url} returned no completion");
+        }
+        Ok(text.trim().to_string())
+    }
+
+    async fn stream(
+        &self,
+        prompt: &str,
+        on_token: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<String> {
+        #[derive(Deserialize)]
+        struct Event {
+            #[serde(rename = "type")]
+            kind: String,
+            #[serde(default)]
+            delta: Option<Delta>,
+        }
+        #[derive(Deserialize)]
+        struct Delta {
+            #[serde(default)]
+            text: Option<String>,
+        }
+        let (url, request, body) = self.request(prompt, true);
+        let mut text = String::new();
+        // Server-sent events; text arrives in `content_block_delta` events
+        // and `message_stop` ends the message.
+        post_lines(request, &url, body, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                return Ok(true);
+            };
+            let event: Event = serde_json::from_str(data)
+                .with_context(|| format!("unexpected response from {url}"))?;
+            match event.kind.as_str() {
+                "message_stop" => return Ok(false),
+                "error" => bail!("{url} reported an error: {data}"),
+                _ => {}
+            }
+            if let Some(token) = event.delta.and_then(|d| d.text) {
+                on_token(&token);
+                text.push_str(&token);
+            }
+            Ok(true)
+        })
+        .await?;
+        Ok(text.trim().to_string())
+    }
+}
+
+impl AnthropicMessages {
+    fn request(
+        &self,
+        prompt: &str,
+        stream: bool,
+    ) -> (String, reqwest::RequestBuilder, serde_json::Value) {
+        let url = format!("{}/messages", self.url.trim_end_matches('/'));
+        let mut request = http()
+            .post(&url)
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        if let Some(key) = &self.api_key {
+            request = request.header("x-api-key", key);
+        }
+        let body = json!({
+            "model": self.model,
+            "max_tokens": ANTHROPIC_MAX_TOKENS,
+            "messages": [{ "role": "user", "content": prompt }],
+            "stream": stream,
+        });
+        (url, request, body)
+    }
+}
+
+/// Sends `body` and calls `on_line` with each line of the response as it
+/// arrives, until the response ends or `on_line` returns false.
+async fn post_lines(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    body: serde_json::Value,
+    mut on_line: impl FnMut(&str) -> Result<bool>,
+) -> Result<()> {
+    let mut response = send(request, url, body).await?;
+    let mut pending = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .with_context(|| format!("failed to read response from {url}"))?
+    {
+        pending.extend_from_slice(&chunk);
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !on_line(line.trim_end_matches(['\r', '\n']))? {
+                return Ok(());
+            }
+        }
+    }
+    if !pending.is_empty() {
+        on_line(&String::from_utf8_lossy(&pending))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Machine-learning helpers: text embeddings and the models behind them.
+
+pub mod bert;
+pub mod classifier;
+pub mod cluster;
+pub mod keywords;
+pub mod llm;
+pub mod models;
+pub mod prompts;
+pub mod providers;
+pub mod rag;
+pub mod summarize;
+pub mod tokenizer;
+
+use crate::index::tokenize;
+
+pub use llm::{llm_from_config, LlmProvider};
+pub use providers::{provider_from_config, EmbeddingProvider};
+
+/// Dimension of the built-in hashed embeddings.
+pub const HASHED_DIM: usize = 256;
+pub const HASHED_MODEL: &str = "hashed-trigram-256";
+/// Embeds text with feature hashing over words and character trigrams.
+///
+/// This needs no model download and captures spelling-level similarity only;
+/// it is the fallback used when no real embedding model is configured.
+pub fn embed_hashed(text: &str) -> Vec<f32> {
+    let mut v = vec![0f32; HASHED_DIM];
+    for token in tokenize(text) {
+        add_feature(&mut v, token.as_bytes(), 1.0);
+        let padded: Vec<char> = format!("#{token}#").chars().collect();
+        for gram in padded.windows(3) {
+            let gram: String = gram.iter().collect();
+            add_feature(&mut v, gram.as_bytes(), 0.5);
+        }
+    }
+    normalize(&mut v);
+    v
+}
+
+fn add_feature(v: &mut [f32], bytes: &[u8], weight: f32) {
+    let h = fnv1a(bytes);
+    let sign = if h & (1 << 63) == 0 { 1.0 } else { -1.0 };
+    v[(h % v.len() as u64) as usize] += sign * weight;
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, &b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let na = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let nb = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if na == 0.0 || nb == 0.0 {
+        0.0
+    } else {
+        dot / (na * nb)
+    }
+}
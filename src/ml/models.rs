@@ -0,0 +1,289 @@
+//! Downloading and caching of local models.
+//!
+//! A model is a Hugging Face repository such as
+//! `sentence-transformers/all-MiniLM-L6-v2`, optionally pinned to a branch,
+//! tag or commit as `repo@revision`. The first download resolves the
+//! revision to a commit and records it, with the SHA-256 of every file, in
+//! the model's `manifest.json`; files are checked against the hashes the
+//! hub publishes before they are kept, and later downloads fetch that same
+//! commit, so a cached model never changes under the knowledge base.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::dirs;
+use crate::error::OzymandiasError;
+use crate::ml::providers::http;
+use crate::runtime;
+use crate::storage::{read_json_or_default, write_json};
+
+pub const DEFAULT_EMBEDDING_MODEL: &str = "sentence-transformers/all-MiniLM-L6-v2";
+/// Well-known embedding models trained on English text only.
+const ENGLISH_ONLY: &[&str] = &[
+    DEFAULT_EMBEDDING_MODEL,
+    "sentence-transformers/all-MiniLM-L12-v2",
+    "sentence-transformers/all-mpnet-base-v2",
+    "BAAI/bge-small-en-v1.5",
+    "BAAI/bge-base-en-v1.5",
+];
+const EMBEDDING_FILES: &[&str] = &["config.json", "tokenizer.json", "model.safetensors"];
+const HUB_URL: &str = "https://huggingface.co";
+/// File in a model's directory recording the commit and hashes of its
+/// files.
+const MANIFEST: &str = "manifest.json";
+
+/// `~/.cache/ozymandias/models`, or below `$XDG_CACHE_HOME`, where
+/// downloaded models live.
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()?.join("models"))
+}
+
+/// The repository and revision of a model written as `repo@revision`,
+/// after checking that neither can lead outside the model cache.
+fn split(model: &str) -> Result<(&str, &str)> {
+    let (repo, revision) = model.split_once('@').unwrap_or((model, "main"));
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part != "."
+            && part != ".."
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    let parts: Vec<&str> = repo.split('/').collect();
+    if parts.len() > 2 || !parts.iter().all(|p| valid(p)) || !valid(revision) {
+        bail!(OzymandiasError::ConfigInvalid(format!(
+            "{model:?} is not a Hugging Face repository such as {DEFAULT_EMBEDDING_MODEL}"
+        )));
+    }
+    Ok((repo, revision))
+}
+
+/// Directory holding the files of a model such as
+/// `sentence-transformers/all-MiniLM-L6-v2`.
+pub fn model_dir(model: &str) -> Result<PathBuf> {
+    split(model)?;
+    Ok(cache_dir()?.join(model.replace('/', "--")))
+}
+
+/// Whether `repo` is known to embed text in `language` meaningfully.
+/// Models this module knows nothing about are assumed to.
+pub fn supports_language(repo: &str, language: &str) -> bool {
+    let repo = repo.split_once('@').map_or(repo, |(repo, _)| repo);
+    language == "en" || !ENGLISH_ONLY.contains(&repo)
+}
+
+pub fn is_downloaded(model: &str) -> Result<bool> {
+    let dir = model_dir(model)?;
+    Ok(dir.join(MANIFEST).is_file() && EMBEDDING_FILES.iter().all(|f| dir.join(f).is_file()))
+}
+
+/// The commit a model was downloaded at, and the SHA-256 of its files.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    commit: String,
+    files: BTreeMap<String, String>,
+}
+
+/// A revision of a repository, as `/api/models/{repo}/revision/{revision}`
+/// of the hub describes it.
+#[derive(Deserialize)]
+struct RevisionInfo {
+    sha: String,
+    siblings: Vec<Sibling>,
+}
+
+#[derive(Deserialize)]
+struct Sibling {
+    rfilename: String,
+    /// The git object id of the file, a SHA-1.
+    #[serde(rename = "blobId")]
+    blob_id: Option<String>,
+    lfs: Option<Lfs>,
+}
+
+#[derive(Deserialize)]
+struct Lfs {
+    sha256: String,
+}
+
+/// What a file's contents must hash to.
+enum Expected {
+    Sha256(String),
+    /// The SHA-1 git gives the file as a blob.
+    GitBlob(String),
+}
+
+impl Expected {
+    fn matches(&self, bytes: &[u8]) -> bool {
+        match self {
+            Expected::Sha256(hash) => hex(&Sha256::digest(bytes)) == *hash,
+            Expected::GitBlob(hash) => {
+                let mut hasher = Sha1::new();
+                hasher.update(format!("blob {}\0", bytes.len()).as_bytes());
+                hasher.update(bytes);
+                hex(&hasher.finalize()) == *hash
+            }
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Fetches the embedding model files unless they are already cached.
+/// This is the only time a network connection is needed; inference is local.
+pub fn download(model: &str) -> Result<PathBuf> {
+    let (repo, revision) = split(model)?;
+    let dir = model_dir(model)?;
+    if is_downloaded(model)? {
+        return Ok(dir);
+    }
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    // A download cut short goes on at the commit it started with.
+    let manifest_path = dir.join(MANIFEST);
+    let pinned: Manifest = read_json_or_default(&manifest_path)?;
+    let revision = match pinned.commit.is_empty() {
+        true => revision,
+        false => pinned.commit.as_str(),
+    };
+    let url = format!("{HUB_URL}/api/models/{repo}/revision/{revision}?blobs=true");
+    let raw = runtime::block_on(async { get(&url).await?.text().await })
+        .with_context(|| format!("failed to look up {model} at {url}"))?;
+    let info: RevisionInfo =
+        serde_json::from_str(&raw).with_context(|| format!("unexpected answer from {url}"))?;
+    let mut manifest = Manifest {
+        commit: info.sha,
+        files: BTreeMap::new(),
+    };
+    for file in EMBEDDING_FILES {
+        let expected = info
+            .siblings
+            .iter()
+            .find(|s| s.rfilename == *file)
+            .and_then(|s| match (&s.lfs, &s.blob_id) {
+                (Some(lfs), _) => Some(Expected::Sha256(lfs.sha256.clone())),
+                (None, Some(blob)) => Some(Expected::GitBlob(blob.clone())),
+                (None, None) => None,
+            })
+            .with_context(|| {
+                OzymandiasError::NotFound(format!("{model} has no {file} with a known hash"))
+            })?;
+        let dest = dir.join(file);
+        let bytes = match read_if(&dest, &expected)? {
+            Some(bytes) => bytes,
+            None => fetch(repo, &manifest.commit, file, &dest, &expected)?,
+        };
+        manifest
+            .files
+            .insert(file.to_string(), hex(&Sha256::digest(&bytes)));
+    }
+    write_json(&manifest_path, &manifest)?;
+    Ok(dir)
+}
+
+/// The contents of `path` if it exists and hashes as expected.
+fn read_if(path: &Path, expected: &Expected) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) if expected.matches(&bytes) => Ok(Some(bytes)),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Downloads a file of a repository at `commit` to `dest`, keeping it only
+/// if it hashes as expected.
+fn fetch(
+    repo: &str,
+    commit: &str,
+    file: &str,
+    dest: &Path,
+    expected: &Expected,
+) -> Result<Vec<u8>> {
+    let url = format!("{HUB_URL}/{repo}/resolve/{commit}/{file}");
+    tracing::info!("downloading {url}");
+    let bytes = runtime::block_on(async { get(&url).await?.bytes().await })
+        .with_context(|| format!("failed to download {url}"))?
+        .to_vec();
+    if !expected.matches(&bytes) {
+        bail!(OzymandiasError::NetworkError(format!(
+            "{url} does not match the hash the hub publishes for it"
+        )));
+    }
+    let partial = dest.with_extension("part");
+    File::create(&partial)
+        .and_then(|mut out| out.write_all(&bytes))
+        .with_context(|| format!("failed to write {}", partial.display()))?;
+    fs::rename(&partial, dest)?;
+    Ok(bytes)
+}
+
+/// GETs `url` through the shared client, failing unless the answer is a
+/// success.
+async fn get(url: &str) -> reqwest::Result<reqwest::Response> {
+    http().get(url).send().await?.error_for_status()
+}
+
+/// Lists the repositories present in the model cache.
+pub fn list_downloaded() -> Result<Vec<String>> {
+    let dir = cache_dir()?;
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut repos = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().replace("--", "/");
+        if split(&name).is_ok() && is_downloaded(&name)? {
+            repos.push(name);
+        }
+    }
+    repos.sort();
+    Ok(repos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_takes_a_revision() {
+        assert_eq!(
+            split(DEFAULT_EMBEDDING_MODEL).unwrap(),
+            (DEFAULT_EMBEDDING_MODEL, "main")
+        );
+        assert_eq!(split("BAAI/bge-small-en-v1.5@v1.0").unwrap().1, "v1.0");
+    }
+
+    #[test]
+    fn split_rejects_paths_out_of_the_cache() {
+        for model in [
+            "..",
+            "../x",
+            "a/../../b",
+            "a/b/c",
+            "/etc",
+            "a/b@..",
+            "a/b@",
+            "",
+        ] {
+            assert!(split(model).is_err(), "{model:?}");
+        }
+    }
+
+    #[test]
+    fn git_blob_hashes_like_git() {
+        // `printf hello | git hash-object --stdin`
+        let expected = Expected::GitBlob("b6fc4c620b67d95f953a5c1c1230aaab5db5a1b0".into());
+        assert!(expected.matches(b"hello"));
+        assert!(!expected.matches(b"hello\n"));
+    }
+}
@@ -0,0 +1,117 @@
+//! Prompts sent to language models, overridable by files in the
+//! knowledge base's `prompts` directory.
+//!
+//! A template is plain text in which `{{name}}` is replaced with the value
+//! of variable `name`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+
+/// A prompt with its built-in text and the variables it can use.
+#[derive(Debug, Clone, Copy)]
+pub struct Template {
+    pub name: &'static str,
+    pub default: &'static str,
+    pub variables: &'static [&'static str],
+}
+
+/// Answers to `ozy ask` and `ozy chat`. `sources` holds the numbered
+/// passages and `history` the earlier exchanges of a chat, or nothing.
+pub const ASK: Template = Template {
+    name: "ask",
+    default: "Answer the question using only the numbered sources below. Cite the \
+sources you use by their number in square brackets, like [1]. If the sources do not \
+contain the answer, say so.
+{{sources}}{{history}}
+Question: {{question}}",
+    variables: &["question", "sources", "history"],
+};
+
+/// Summaries written by `ozy summarize` and the `summarize` stage.
+pub const SUMMARIZE: Template = Template {
+    name: "summarize",
+    default: "Summarize the following document in at most three sentences. Reply \
+with the summary only.
+
+Title: {{title}}
+
+{{content}}",
+    variables: &["title", "content"],
+};
+
+/// Cloze deletions drafted by `ozy export anki --cloze`. The reply is read
+/// one card per line, with the hidden words in square brackets.
+pub const CLOZE: Template = Template {
+    name: "cloze",
+    default: "Write up to five flashcards that test the key facts of the following \
+document. Write each card as one self-contained sentence on its own line, and put the \
+words to hide in square brackets, like: The capital of France is [Paris]. Reply with \
+the cards only.
+
+Title: {{title}}
+
+{{content}}",
+    variables: &["title", "content"],
+};
+
+pub const TEMPLATES: [Template; 3] = [ASK, SUMMARIZE, CLOZE];
+
+/// The prompt templates of one knowledge base.
+#[derive(Debug, Clone)]
+pub struct Prompts {
+    dir: PathBuf,
+}
+
+impl Prompts {
+    pub fn open(root: &Path) -> Self {
+        Prompts {
+            dir: root.join("prompts"),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file overriding `template`, whether or not it exists.
+    pub fn path(&self, template: &Template) -> PathBuf {
+        self.dir.join(format!("{}.txt", template.name))
+    }
+
+    /// The text of `template`: the file in the prompts directory if there
+    /// is one, else the built-in text.
+    pub fn text(&self, template: &Template) -> Result<String> {
+        let path = self.path(template);
+        match std::fs::read_to_string(&path) {
+            Ok(text) => Ok(text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(template.default.to_string()),
+            Err(e) => Err(e).with_context(|| format!("cannot read {}", path.display())),
+        }
+    }
+
+    /// `template` with its variables replaced by `values`.
+    pub fn render(&self, template: &Template, values: &[(&str, &str)]) -> Result<String> {
+        let text = self.text(template)?;
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + len].trim();
+            let Some((_, value)) = values.iter().find(|(n, _)| *n == name) else {
+                bail!(
+                    "prompt template {} uses unknown variable {{{{{name}}}}} (available: {})",
+                    self.path(template).display(),
+                    template.variables.join(", ")
+                );
+            };
+            out.push_str(&rest[..start]);
+            out.push_str(value);
+            rest = &rest[start + len + 2..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
@@ -0,0 +1,224 @@
+//! Embedding providers: where vectors come from, chosen in the config file.
+
+use std::sync::{Arc, OnceLock};
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::bert::Bert;
+use super::llm_from_config;
+use super::tokenizer::ModelTokenizer;
+use super::{embed_hashed, models, normalize, HASHED_MODEL};
+use crate::config::{EmbeddingConfig, LlmConfig, ProviderKind};
+
+/// Longest input, in tokens, fed to local models.
+const LOCAL_MAX_TOKENS: usize = 256;
+const OPENAI_URL: &str = "https://api.openai.com/v1";
+pub(crate) const OPENAI_MODEL: &str = "text-embedding-3-small";
+const OPENAI_KEY_ENV: &str = "OPENAI_API_KEY";
+const OLLAMA_URL: &str = "http://localhost:11434";
+pub(crate) const OLLAMA_MODEL: &str = "nomic-embed-text";
+
+/// Turns text into a fixed-size vector.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Identifies provider and model. It is recorded in the vector index so
+    /// vectors from different models are never compared with each other.
+    fn name(&self) -> String;
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>>;
+}
+
+/// Builds the provider selected by the `[embedding]` config section; `llm`
+/// is the `[llm]` section, for the `llm` provider.
+pub fn provider_from_config(
+    config: &EmbeddingConfig,
+    llm: &LlmConfig,
+) -> Result<Box<dyn EmbeddingProvider>> {
+    let model = config.model.clone();
+    Ok(match config.provider {
+        ProviderKind::Hashed => Box::new(HashedProvider),
+        ProviderKind::Local => Box::new(LocalProvider::load(
+            model.as_deref().unwrap_or(models::DEFAULT_EMBEDDING_MODEL),
+        )?),
+        ProviderKind::OpenAi => {
+            let key_env = config.api_key_env.as_deref().unwrap_or(OPENAI_KEY_ENV);
+            Box::new(OpenAiProvider {
+                url: config.url.clone().unwrap_or_else(|| OPENAI_URL.to_string()),
+                model: model.unwrap_or_else(|| OPENAI_MODEL.to_string()),
+                api_key: std::env::var(key_env).ok(),
+            })
+        }
+        ProviderKind::Ollama => Box::new(OllamaProvider {
+            url: config.url.clone().unwrap_or_else(|| OLLAMA_URL.to_string()),
+            model: model.unwrap_or_else(|| OLLAMA_MODEL.to_string()),
+        }),
+        ProviderKind::Llm => {
+            let llm = llm_from_config(llm)
+                .context("the `llm` embedding provider needs a `provider` in the [llm] section")?;
+            match llm.embedder() {
+                Some(embedder) => embedder,
+                None => bail!("{} cannot compute embeddings", llm.name()),
+            }
+        }
+    })
+}
+
+pub struct HashedProvider;
+
+#[async_trait]
+impl EmbeddingProvider for HashedProvider {
+    fn name(&self) -> String {
+        HASHED_MODEL.to_string()
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        Ok(embed_hashed(text))
+    }
+}
+
+/// Runs a downloaded BERT sentence-embedding model in-process.
+pub struct LocalProvider {
+    repo: String,
+    model: Arc<(ModelTokenizer, Bert)>,
+}
+
+impl LocalProvider {
+    pub fn load(repo: &str) -> Result<Self> {
+        let dir = models::download(repo)?;
+        let model = Bert::load(&dir.join("config.json"), &dir.join("model.safetensors"))?;
+        let tokenizer = ModelTokenizer::load(
+            &dir.join("tokenizer.json"),
+            LOCAL_MAX_TOKENS.min(model.max_len()),
+        )?;
+        Ok(LocalProvider {
+            repo: repo.to_string(),
+            model: Arc::new((tokenizer, model)),
+        })
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for LocalProvider {
+    fn name(&self) -> String {
+        format!("local:{}", self.repo)
+    }
+
+    /// Inference keeps a core busy, so it runs on a blocking thread, where
+    /// texts embedded at once run side by side.
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        let model = Arc::clone(&self.model);
+        let text = text.to_string();
+        tokio::task::spawn_blocking(move || {
+            let (tokenizer, bert) = &*model;
+            let mut v = bert.embed(&tokenizer.encode(&text)?)?;
+            normalize(&mut v);
+            Ok(v)
+        })
+        .await
+        .context("embedding failed")?
+    }
+}
+
+/// Any service implementing the OpenAI `/embeddings` endpoint.
+pub struct OpenAiProvider {
+    pub(crate) url: String,
+    pub(crate) model: String,
+    pub(crate) api_key: Option<String>,
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    fn name(&self) -> String {
+        format!("openai:{}", self.model)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        #[derive(Deserialize)]
+        struct Response {
+            data: Vec<Item>,
+        }
+        #[derive(Deserialize)]
+        struct Item {
+            embedding: Vec<f32>,
+        }
+        let url = format!("{}/embeddings", self.url.trim_end_matches('/'));
+        let mut request = http().post(&url);
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let body = json!({ "model": self.model, "input": text });
+        let response: Response = post_json(request, &url, body).await?;
+        match response.data.into_iter().next() {
+            Some(item) => Ok(item.embedding),
+            None => bail!("{url} returned no embedding"),
+        }
+    }
+}
+
+/// A local Ollama server's `/api/embed` endpoint.
+pub struct OllamaProvider {
+    pub(crate) url: String,
+    pub(crate) model: String,
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaProvider {
+    fn name(&self) -> String {
+        format!("ollama:{}", self.model)
+    }
+
+    async fn embed(&self, text: &str) -> Result<Vec<f32>> {
+        #[derive(Deserialize)]
+        struct Response {
+            embeddings: Vec<Vec<f32>>,
+        }
+        let url = format!("{}/api/embed", self.url.trim_end_matches('/'));
+        let body = json!({ "model": self.model, "input": text });
+        let response: Response = post_json(http().post(&url), &url, body).await?;
+        match response.embeddings.into_iter().next() {
+            Some(v) => Ok(v),
+            None => bail!("{url} returned no embedding"),
+        }
+    }
+}
+
+/// The client requests to embedding and language model services, model
+/// downloads and sync remotes share, so that connections to them are kept
+/// open between requests.
+pub(crate) fn http() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Sends `body` as JSON and reads the JSON answer, failing unless the
+/// answer is a success.
+pub(crate) async fn post_json<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    body: serde_json::Value,
+) -> Result<T> {
+    let raw = send(request, url, body)
+        .await?
+        .text()
+        .await
+        .with_context(|| format!("failed to read response from {url}"))?;
+    serde_json::from_str(&raw).with_context(|| format!("unexpected response from {url}"))
+}
+
+/// Sends `body` as JSON and returns the answer if it is a success.
+pub(crate) async fn send(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    body: serde_json::Value,
+) -> Result<reqwest::Response> {
+    request
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("request to {url} failed"))
+}
@@ -0,0 +1,150 @@
+//! Answering questions from the knowledge base's own text: the passages
+//! most relevant to a question are retrieved with hybrid search and handed
+//! to a language model, which is asked to cite them.
+
+use std::fmt::Write;
+
+use anyhow::Result;
+
+use super::prompts::{Prompts, ASK};
+use super::LlmProvider;
+use crate::index::language_of;
+use crate::kb::KnowledgeBase;
+use crate::query::Query;
+use crate::search::{self, SearchMode};
+use crate::types::DocumentId;
+
+/// Longest passage handed to the model, in bytes.
+const PASSAGE_LEN: usize = 2_000;
+/// Passages shorter than this, in bytes, are extended with the chunks that
+/// follow them, since a heading or a single sentence is little context.
+const MIN_PASSAGE_LEN: usize = 400;
+
+/// A span of a document retrieved for a question.
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub doc: DocumentId,
+    pub title: String,
+    /// Byte offsets of the passage in the document's content.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl Passage {
+    /// Where the passage comes from, as `id:start-end`.
+    pub fn citation(&self) -> String {
+        format!("{}:{}-{}", self.doc, self.start, self.end)
+    }
+}
+
+/// The passages best answering `question`, at most one per document.
+///
+/// A document embedded in chunks contributes the chunk closest to the
+/// question; otherwise the chunk sharing the most words with it, or the
+/// beginning of the document when it was not chunked. Short chunks are
+/// extended with the ones following them.
+pub fn retrieve(kb: &mut KnowledgeBase, question: &str, limit: usize) -> Result<Vec<Passage>> {
+    let mut query = Query::plain(question);
+    query.analyzer = kb.index.analyzer().clone();
+    let matches = search::run(kb, &query, SearchMode::Hybrid)?;
+    let mut passages = Vec::new();
+    for m in matches.into_iter().take(limit) {
+        let doc = &m.doc;
+        let (start, end) = match &m.chunk {
+            Some(chunk) => (chunk.start, chunk.end),
+            None => {
+                let language = language_of(doc);
+                let words = query.analyzer.terms(question, language);
+                doc.chunks
+                    .iter()
+                    .max_by_key(|c| {
+                        let terms = query.analyzer.terms(c.text(&doc.content), language);
+                        (
+                            words.iter().filter(|w| terms.contains(w)).count(),
+                            -(c.start as i64),
+                        )
+                    })
+                    .map_or((0, doc.content.len()), |c| (c.start, c.end))
+            }
+        };
+        let mut end = end;
+        for chunk in doc.chunks.iter().filter(|c| c.start >= start) {
+            if end - start >= MIN_PASSAGE_LEN || chunk.end - start > PASSAGE_LEN {
+                break;
+            }
+            end = end.max(chunk.end);
+        }
+        end = end.min(start + PASSAGE_LEN);
+        while !doc.content.is_char_boundary(end) {
+            end -= 1;
+        }
+        passages.push(Passage {
+            doc: doc.id.clone(),
+            title: doc.title.clone(),
+            start,
+            end,
+            text: doc.content[start..end].trim().to_string(),
+        });
+    }
+    Ok(passages)
+}
+
+/// An exchange earlier in a conversation.
+#[derive(Debug, Clone)]
+pub struct Turn {
+    pub question: String,
+    pub answer: String,
+}
+
+/// Asks `llm` to answer `question` from `passages`, citing them by their
+/// number in brackets.
+pub async fn answer(
+    llm: &dyn LlmProvider,
+    prompts: &Prompts,
+    question: &str,
+    passages: &[Passage],
+) -> Result<String> {
+    llm.complete(&prompt(prompts, question, passages, &[])?)
+        .await
+}
+
+/// The prompt asking for an answer to `question` from `passages`, after
+/// the earlier exchanges of a conversation in `history`.
+pub fn prompt(
+    prompts: &Prompts,
+    question: &str,
+    passages: &[Passage],
+    history: &[Turn],
+) -> Result<String> {
+    let mut sources = String::new();
+    for (n, passage) in passages.iter().enumerate() {
+        let _ = write!(
+            sources,
+            "\n[{}] {} ({})\n{}\n",
+            n + 1,
+            passage.title,
+            passage.citation(),
+            passage.text
+        );
+    }
+    let mut conversation = String::new();
+    if !history.is_empty() {
+        conversation.push_str("\nThe conversation so far:\n");
+        for turn in history {
+            let _ = write!(
+                conversation,
+                "\nUser: {}\nAssistant: {}\n",
+                turn.question, turn.answer
+            );
+        }
+    }
+    prompts.render(
+        &ASK,
+        &[
+            ("question", question),
+            ("sources", &sources),
+            ("history", &conversation),
+        ],
+    )
+}
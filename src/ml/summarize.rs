@@ -0,0 +1,68 @@
+//! Short abstracts of documents, written by the configured language model
+//! or, without one, picked from the document's own sentences.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use super::prompts::{Prompts, SUMMARIZE};
+use super::LlmProvider;
+use crate::index::{language_of, Analyzer};
+use crate::transform::chunk::sentences;
+use crate::types::Document;
+
+/// Metadata key holding a document's summary.
+pub const SUMMARY_KEY: &str = "summary";
+/// Longest excerpt of the content sent to a language model.
+const PROMPT_CHARS: usize = 12_000;
+/// Sentences in an extractive summary.
+const SENTENCES: usize = 3;
+
+pub async fn summarize(
+    doc: &Document,
+    llm: Option<&dyn LlmProvider>,
+    prompts: &Prompts,
+) -> Result<String> {
+    let summary = match llm {
+        Some(llm) => {
+            let excerpt: String = doc.content.chars().take(PROMPT_CHARS).collect();
+            let prompt =
+                prompts.render(&SUMMARIZE, &[("title", &doc.title), ("content", &excerpt)])?;
+            llm.complete(&prompt).await?
+        }
+        None => extractive(doc),
+    };
+    Ok(summary.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// The sentences whose words are most frequent in the whole document, in
+/// their original order.
+fn extractive(doc: &Document) -> String {
+    let analyzer = Analyzer::default();
+    let language = language_of(doc);
+    let mut frequency: HashMap<String, usize> = HashMap::new();
+    for term in analyzer.terms(&doc.content, language) {
+        *frequency.entry(term).or_default() += 1;
+    }
+    let mut seen = HashSet::new();
+    let mut scored: Vec<(usize, f64, &str)> = sentences(&doc.content)
+        .into_iter()
+        .map(|(start, end)| &doc.content[start..end])
+        .filter(|s| !s.starts_with('#') && s.split_whitespace().count() >= 4)
+        .filter(|s| seen.insert(*s))
+        .enumerate()
+        .map(|(i, s)| {
+            let terms = analyzer.terms(s, language);
+            let total: usize = terms.iter().filter_map(|t| frequency.get(t)).sum();
+            (i, total as f64 / terms.len().max(1) as f64, s)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scored.truncate(SENTENCES);
+    scored.sort_by_key(|(i, _, _)| *i);
+    scored
+        .into_iter()
+        .map(|(_, _, s)| s)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,35 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use tokenizers::{Tokenizer, TruncationParams};
+
+/// The tokenizer a model was trained with, read from its
+/// `tokenizer.json` and run by the Hugging Face implementation.
+pub struct ModelTokenizer(Tokenizer);
+
+impl ModelTokenizer {
+    /// Loads the tokenizer, cutting what it encodes to `max_len` ids.
+    pub fn load(path: &Path, max_len: usize) -> Result<Self> {
+        let mut tokenizer = Tokenizer::from_file(path)
+            .map_err(|e| anyhow!("failed to read {}: {e}", path.display()))?;
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: max_len,
+                ..Default::default()
+            }))
+            .map_err(|e| anyhow!("invalid tokenizer {}: {e}", path.display()))?;
+        // One text at a time: padding would only add masked positions.
+        tokenizer.with_padding(None);
+        Ok(ModelTokenizer(tokenizer))
+    }
+
+    /// Encodes text with the model's special tokens, such as `[CLS] pieces…
+    /// [SEP]` for BERT.
+    pub fn encode(&self, text: &str) -> Result<Vec<u32>> {
+        let encoding = self
+            .0
+            .encode(text, true)
+            .map_err(|e| anyhow!("failed to tokenize: {e}"))?;
+        Ok(encoding.get_ids().to_vec())
+    }
+}
@@ -0,0 +1,487 @@
+//! A user-defined ontology of concepts, read from `ontology.yaml`:
+//!
+//! ```yaml
+//! concepts:
+//!   computer-science:
+//!     synonyms: [computing]
+//!   machine-learning:
+//!     parents: [computer-science]
+//!     synonyms: [ml, statistical learning]
+//! relations:
+//!   supports:
+//!     description: Provides evidence for
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::index::tokenize;
+use crate::relations;
+use crate::rules::Rule;
+use crate::tags;
+use crate::types::Document;
+
+pub const ONTOLOGY_FILE: &str = "ontology.yaml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Ontology {
+    #[serde(default)]
+    pub concepts: BTreeMap<String, Concept>,
+    /// Relation types beyond the built-in ones, for `ozy relate`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub relations: BTreeMap<String, RelationType>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Concept {
+    /// Broader concepts this one is a kind of.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub parents: Vec<String>,
+    /// Other names the concept goes by in documents.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub synonyms: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RelationType {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Name of the relation read in the opposite direction, inferred for
+    /// every relation of this type. It may be the type itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inverse: Option<String>,
+    /// Whether `a → b → c` implies `a → c`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub transitive: bool,
+}
+
+/// A concept or category assigned to a document.
+#[derive(Debug, Clone)]
+pub struct Classification {
+    pub concept: String,
+    pub evidence: Evidence,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evidence {
+    /// The classification rule at this position in the config matched.
+    Rule(usize),
+    /// How often the concept or one of its synonyms occurs.
+    Mentions(usize),
+}
+
+/// A problem in an ontology file, with the line it was found on.
+#[derive(Debug, Clone, Serialize)]
+pub struct OntologyError {
+    pub path: PathBuf,
+    pub line: usize,
+    /// Short name of the check that failed, such as `cycle`.
+    pub check: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub concept: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for OntologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+    }
+}
+
+impl std::error::Error for OntologyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The ontology cannot be loaded.
+    Error,
+    /// The ontology loads but probably does not say what was meant.
+    Warning,
+}
+
+/// A finding of `ozy ontology lint`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LintIssue {
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub problem: OntologyError,
+}
+
+impl Ontology {
+    /// Loads the ontology of a knowledge base, if it defines one.
+    pub fn load(kb_root: &Path) -> Result<Option<Self>> {
+        let path = kb_root.join(ONTOLOGY_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => Ok(Some(Self::parse(&raw, &path)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Parses and validates an ontology, failing on its first problem.
+    pub fn parse(raw: &str, path: &Path) -> Result<Self, OntologyError> {
+        let ontology = Self::parse_unchecked(raw, path)?;
+        match ontology.problems(raw, path).into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(ontology),
+        }
+    }
+
+    fn parse_unchecked(raw: &str, path: &Path) -> Result<Self, OntologyError> {
+        if raw.trim().is_empty() {
+            return Ok(Ontology::default());
+        }
+        serde_yaml::from_str(raw).map_err(|e| {
+            let message = e.to_string();
+            // The location is reported separately.
+            let message = message.rsplit_once(" at line ").map_or(&*message, |m| m.0);
+            OntologyError {
+                path: path.to_path_buf(),
+                line: e.location().map_or(1, |l| l.line()),
+                check: "syntax",
+                concept: None,
+                message: message.to_string(),
+            }
+        })
+    }
+
+    /// Every problem that keeps the ontology from loading.
+    fn problems(&self, raw: &str, path: &Path) -> Vec<OntologyError> {
+        let mut out = Vec::new();
+        let mut error = |line: usize, check: &'static str, concept: &str, message: String| {
+            out.push(OntologyError {
+                path: path.to_path_buf(),
+                line,
+                check,
+                concept: Some(concept.to_string()),
+                message,
+            })
+        };
+
+        let mut names: HashMap<Vec<String>, &str> = HashMap::new();
+        for (name, concept) in &self.concepts {
+            let line = line_of(raw, name, 0);
+            for parent in &concept.parents {
+                if !self.concepts.contains_key(parent) {
+                    error(
+                        line_of(raw, parent, line),
+                        "unknown-parent",
+                        name,
+                        format!("concept {name:?} has unknown parent {parent:?}"),
+                    );
+                }
+            }
+            for term in std::iter::once(name).chain(&concept.synonyms) {
+                let words = tokenize(term);
+                if words.is_empty() {
+                    error(
+                        line_of(raw, term, line),
+                        "empty-label",
+                        name,
+                        format!("{term:?} contains no searchable words"),
+                    );
+                    continue;
+                }
+                if let Some(other) = names.insert(words, name) {
+                    if other != name {
+                        error(
+                            line_of(raw, term, line),
+                            "duplicate-label",
+                            name,
+                            format!("{term:?} names both {other:?} and {name:?}"),
+                        );
+                    }
+                }
+            }
+        }
+        for (name, relation) in &self.relations {
+            let line = line_of(raw, name, line_of(raw, "relations:", 0));
+            if relations::BUILTIN.contains(&name.as_str()) {
+                error(
+                    line,
+                    "relation-name",
+                    name,
+                    format!("relation {name:?} is built in"),
+                );
+            } else if name.is_empty()
+                || name.contains(char::is_whitespace)
+                || name != &name.to_lowercase()
+            {
+                error(
+                    line,
+                    "relation-name",
+                    name,
+                    format!("relation {name:?} must be a lowercase word such as \"supports\""),
+                );
+            }
+            if let Some(inverse) = &relation.inverse {
+                if relations::BUILTIN.contains(&inverse.as_str()) && inverse != name {
+                    error(
+                        line_of(raw, inverse, line),
+                        "relation-inverse",
+                        name,
+                        format!(
+                            "relation {name:?} cannot have the built-in {inverse:?} as inverse"
+                        ),
+                    );
+                }
+            }
+        }
+        for name in self.cycles() {
+            error(
+                line_of(raw, name, 0),
+                "cycle",
+                name,
+                format!("concept {name:?} is its own ancestor"),
+            );
+        }
+        out.sort_by_key(|e| e.line);
+        out
+    }
+
+    /// Checks an ontology file: everything that keeps it from loading, plus
+    /// orphan concepts outside any hierarchy and concepts no document uses.
+    pub fn lint(raw: &str, path: &Path, docs: &[Document]) -> Vec<LintIssue> {
+        let ontology = match Self::parse_unchecked(raw, path) {
+            Ok(ontology) => ontology,
+            Err(problem) => {
+                return vec![LintIssue {
+                    severity: Severity::Error,
+                    problem,
+                }]
+            }
+        };
+        let mut issues: Vec<LintIssue> = ontology
+            .problems(raw, path)
+            .into_iter()
+            .map(|problem| LintIssue {
+                severity: Severity::Error,
+                problem,
+            })
+            .collect();
+
+        let mut used: Vec<&str> = Vec::new();
+        for doc in docs {
+            let tagged = doc.tags.iter().filter_map(|t| ontology.concept_for_tag(t));
+            let mentioned: Vec<String> = ontology
+                .classify(doc, &[])
+                .into_iter()
+                .map(|c| c.concept)
+                .collect();
+            for name in tagged.chain(mentioned.iter().map(String::as_str)) {
+                if let Some((name, _)) = ontology.concepts.get_key_value(name) {
+                    used.push(name);
+                    used.extend(ontology.ancestors(name));
+                }
+            }
+        }
+        let is_parent =
+            |name: &String| ontology.concepts.values().any(|c| c.parents.contains(name));
+        for (name, concept) in &ontology.concepts {
+            let mut warn = |check: &'static str, message: String| {
+                issues.push(LintIssue {
+                    severity: Severity::Warning,
+                    problem: OntologyError {
+                        path: path.to_path_buf(),
+                        line: line_of(raw, name, 0),
+                        check,
+                        concept: Some(name.clone()),
+                        message,
+                    },
+                })
+            };
+            if ontology.concepts.len() > 1 && concept.parents.is_empty() && !is_parent(name) {
+                warn(
+                    "orphan",
+                    format!("concept {name:?} has neither parents nor children"),
+                );
+            }
+            if !used.contains(&name.as_str()) {
+                warn(
+                    "unused",
+                    format!("no document is tagged with or mentions {name:?}"),
+                );
+            }
+        }
+        issues.sort_by_key(|i| i.problem.line);
+        issues
+    }
+
+    /// Validates the ontology and writes it to the knowledge base.
+    pub fn save(&self, kb_root: &Path) -> Result<()> {
+        let path = kb_root.join(ONTOLOGY_FILE);
+        let raw = serde_yaml::to_string(self)?;
+        Self::parse(&raw, &path)?;
+        std::fs::write(&path, raw).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Adds a concept unless its name is already taken, dropping synonyms
+    /// that would be ambiguous with existing names.
+    pub fn insert(&mut self, name: String, mut concept: Concept) -> bool {
+        let claimed: Vec<Vec<String>> = self
+            .concepts
+            .iter()
+            .flat_map(|(n, c)| std::iter::once(n).chain(&c.synonyms))
+            .map(|term| tokenize(term))
+            .collect();
+        let words = tokenize(&name);
+        if words.is_empty() || claimed.contains(&words) {
+            return false;
+        }
+        let mut taken = claimed;
+        taken.push(words);
+        concept.synonyms.retain(|term| {
+            let words = tokenize(term);
+            if words.is_empty() || taken.contains(&words) {
+                return false;
+            }
+            taken.push(words);
+            true
+        });
+        self.concepts.insert(name, concept);
+        true
+    }
+
+    /// Drops parent relations to concepts that do not exist, such as ones
+    /// [`insert`](Self::insert) refused.
+    pub fn prune_parents(&mut self) {
+        let known: Vec<String> = self.concepts.keys().cloned().collect();
+        for concept in self.concepts.values_mut() {
+            concept.parents.retain(|p| known.contains(p));
+        }
+    }
+
+    /// Concepts that are their own ancestors.
+    fn cycles(&self) -> Vec<&str> {
+        self.concepts
+            .keys()
+            .filter(|name| {
+                let mut stack: Vec<&str> = self.concepts[*name]
+                    .parents
+                    .iter()
+                    .map(String::as_str)
+                    .collect();
+                let mut seen = Vec::new();
+                while let Some(current) = stack.pop() {
+                    if current == name.as_str() {
+                        return true;
+                    }
+                    if !seen.contains(&current) {
+                        seen.push(current);
+                        if let Some(concept) = self.concepts.get(current) {
+                            stack.extend(concept.parents.iter().map(String::as_str));
+                        }
+                    }
+                }
+                false
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Every broader concept of `name`, nearest first.
+    pub fn ancestors(&self, name: &str) -> Vec<&str> {
+        let mut out: Vec<&str> = Vec::new();
+        let mut queue: Vec<&str> = vec![name];
+        let mut i = 0;
+        while i < queue.len() {
+            if let Some(concept) = self.concepts.get(queue[i]) {
+                for parent in &concept.parents {
+                    if !out.contains(&parent.as_str()) {
+                        out.push(parent);
+                        queue.push(parent);
+                    }
+                }
+            }
+            i += 1;
+        }
+        out
+    }
+
+    /// Every narrower concept of `name`, at any depth.
+    pub fn narrower(&self, name: &str) -> Vec<&str> {
+        self.concepts
+            .keys()
+            .filter(|c| self.ancestors(c).contains(&name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The concept a tag stands for, comparing normalized names.
+    pub fn concept_for_tag(&self, tag: &str) -> Option<&str> {
+        self.concepts
+            .keys()
+            .find(|name| tags::normalize(name).is_ok_and(|n| n == tag))
+            .map(String::as_str)
+    }
+
+    /// Classifies a document: first by the configured rules, in order,
+    /// then by the concepts it mentions by name or synonym, most mentioned
+    /// first.
+    pub fn classify(&self, doc: &Document, rules: &[Rule]) -> Vec<Classification> {
+        let words = tokenize(&format!("{} {}", doc.title, doc.content));
+        let mut out: Vec<Classification> = Vec::new();
+        for (i, rule) in rules.iter().enumerate() {
+            if rule.matches(doc, &words) && !out.iter().any(|c| c.concept == rule.category) {
+                out.push(Classification {
+                    concept: rule.category.clone(),
+                    evidence: Evidence::Rule(i),
+                });
+            }
+        }
+        let mut mentioned: Vec<(usize, &String)> = self
+            .concepts
+            .iter()
+            .filter(|(name, _)| !out.iter().any(|c| &c.concept == *name))
+            .filter_map(|(name, concept)| {
+                let mentions: usize = std::iter::once(name)
+                    .chain(&concept.synonyms)
+                    .map(|term| {
+                        let phrase = tokenize(term);
+                        words.windows(phrase.len()).filter(|w| *w == phrase).count()
+                    })
+                    .sum();
+                (mentions > 0).then_some((mentions, name))
+            })
+            .collect();
+        mentioned.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+        out.extend(
+            mentioned
+                .into_iter()
+                .map(|(mentions, name)| Classification {
+                    concept: name.clone(),
+                    evidence: Evidence::Mentions(mentions),
+                }),
+        );
+        out
+    }
+}
+
+/// The 1-based line at or after `after` on which `needle` appears, as a
+/// key if possible, else as a whole word.
+fn line_of(raw: &str, needle: &str, after: usize) -> usize {
+    let lines = || raw.lines().enumerate().skip(after.saturating_sub(1));
+    let key = format!("{needle}:");
+    lines()
+        .find(|(_, line)| line.trim_start().starts_with(&key))
+        .or_else(|| lines().find(|(_, line)| contains_word(line, needle)))
+        .map_or(1, |(i, _)| i + 1)
+}
+
+fn contains_word(line: &str, word: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    line.match_indices(word).any(|(i, _)| {
+        let before = line[..i].chars().next_back();
+        let after = line[i + word.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    })
+}
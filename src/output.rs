@@ -0,0 +1,887 @@
+//! Machine-readable output, selected with the global `--format` flag.
+//!
+//! With `--format json` or `--format yaml` a command prints a single value
+//! to stdout instead of text for people: an object, or an array of
+//! objects for commands that act on several inputs. The records below are
+//! the shapes commands print, and form a stable interface for scripts:
+//!
+//! - field names are snake_case and are never renamed or removed; new
+//!   fields may appear,
+//! - every field is always present, with `null` or an empty array when
+//!   there is nothing to report,
+//! - document ids are the full ids, times are RFC 3339 in UTC and graph
+//!   nodes are written as in `ozy graph`: a document id, `#tag` or
+//!   `@kind:name`,
+//! - progress and warnings go to stderr, so stdout holds only the value.
+//!
+//! Interactive and long-running commands (`tui`, `chat`, `serve`,
+//! `watch`) ignore the flag. `graph export` writes the graph itself in the
+//! format asked for, and is the only command that takes `--format dot` or
+//! `--format graphml`.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::config::Origin;
+use crate::search::SearchMode;
+use crate::sync::Conflict;
+use crate::types::{Document, DocumentId, DocumentKind};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Text for people to read
+    #[default]
+    Plain,
+    /// Pretty-printed JSON
+    Json,
+    Yaml,
+    /// Graphviz DOT, for `graph export` only
+    Dot,
+    /// GraphML for Gephi and yEd, for `graph export` only
+    Graphml,
+}
+
+impl Format {
+    /// Prints `value`, calling `plain` to write it as text.
+    pub fn print<T: Serialize + ?Sized>(self, value: &T, plain: impl FnOnce(&T)) -> Result<()> {
+        match self {
+            Format::Plain => plain(value),
+            Format::Json => println!("{}", serde_json::to_string_pretty(value)?),
+            Format::Yaml => print!("{}", serde_yaml::to_string(value)?),
+            Format::Dot | Format::Graphml => self.only_for_graphs()?,
+        }
+        Ok(())
+    }
+
+    /// Fails for the formats only `graph export` can write.
+    pub fn only_for_graphs(self) -> Result<()> {
+        if matches!(self, Format::Dot | Format::Graphml) {
+            bail!(
+                "--format {} is only for `ozy graph export`",
+                self.to_possible_value()
+                    .expect("no skipped variants")
+                    .get_name()
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_plain(self) -> bool {
+        self == Format::Plain
+    }
+}
+
+/// A document named in the output of another command.
+#[derive(Debug, Clone, Serialize)]
+pub struct DocumentRef {
+    pub id: DocumentId,
+    pub title: String,
+}
+
+impl From<&Document> for DocumentRef {
+    fn from(doc: &Document) -> Self {
+        DocumentRef {
+            id: doc.id.clone(),
+            title: doc.title.clone(),
+        }
+    }
+}
+
+/// A document in `ozy list` and `ozy dedupe report`.
+#[derive(Debug, Serialize)]
+pub struct DocumentSummary {
+    pub id: DocumentId,
+    pub title: String,
+    pub kind: DocumentKind,
+    pub added: DateTime<Utc>,
+    /// Length of the content in bytes.
+    pub size: usize,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl From<&Document> for DocumentSummary {
+    fn from(doc: &Document) -> Self {
+        DocumentSummary {
+            id: doc.id.clone(),
+            title: doc.title.clone(),
+            kind: doc.kind,
+            added: doc.added,
+            size: doc.content.len(),
+            source: doc.source.clone(),
+            tags: doc.tags.clone(),
+        }
+    }
+}
+
+/// A name with the number of documents it applies to.
+#[derive(Debug, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub count: usize,
+}
+
+impl Count {
+    pub fn all(counts: impl IntoIterator<Item = (String, usize)>) -> Vec<Count> {
+        counts
+            .into_iter()
+            .map(|(name, count)| Count { name, count })
+            .collect()
+    }
+}
+
+/// `ozy add`: one per path or URL.
+#[derive(Debug, Serialize)]
+pub struct Added {
+    /// The path or URL as given.
+    pub input: String,
+    pub status: AddStatus,
+    pub id: DocumentId,
+    /// Null when the input was skipped before being read.
+    pub title: Option<String>,
+    /// Why the document was skipped.
+    pub reason: Option<String>,
+    /// The document this one nearly duplicates.
+    pub near_duplicate_of: Option<String>,
+    /// Tags applied automatically by the classifier.
+    pub tagged: Vec<String>,
+    /// Tags suggested with `--suggest-tags`, best first.
+    pub suggested: Vec<Suggestion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddStatus {
+    Added,
+    Skipped,
+    /// The file has not changed since it was last added.
+    Unchanged,
+    /// Gone from the export, and moved to the trash by `--prune`.
+    Pruned,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Suggestion {
+    pub tag: String,
+    /// Classifier confidence between 0 and 1.
+    pub score: f32,
+}
+
+/// `ozy search`.
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub query: String,
+    pub mode: SearchMode,
+    /// Number of matches, including those outside `--offset` and `--limit`.
+    pub total: usize,
+    pub results: Vec<SearchResult>,
+    /// Present with `--facets`.
+    pub facets: Option<Facets>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// Position among all matches, from 1.
+    pub rank: usize,
+    pub id: DocumentId,
+    pub title: String,
+    pub score: f64,
+    /// The stored summary, or an excerpt around the matching terms.
+    pub snippet: String,
+}
+
+/// Tag and type counts over all matches of a search.
+#[derive(Debug, Serialize)]
+pub struct Facets {
+    pub types: Vec<Count>,
+    pub tags: Vec<Count>,
+}
+
+/// `ozy search --list-saved`: one per saved search.
+#[derive(Debug, Serialize)]
+pub struct SavedSearchEntry {
+    pub name: String,
+    pub query: String,
+    pub mode: SearchMode,
+}
+
+/// `ozy find`: one per match, best first.
+#[derive(Debug, Serialize)]
+pub struct FindMatch {
+    pub id: DocumentId,
+    pub title: String,
+    /// Fuzzy similarity between 0 and 1.
+    pub score: f64,
+    /// What matched: `title`, `tag` or `alias`.
+    pub field: &'static str,
+    pub value: String,
+}
+
+/// `ozy list`.
+#[derive(Debug, Serialize)]
+pub struct Listing {
+    /// Number of matching documents, including those outside `--offset`
+    /// and `--limit`.
+    pub total: usize,
+    pub documents: Vec<DocumentSummary>,
+}
+
+/// `ozy show`.
+#[derive(Debug, Serialize)]
+pub struct Shown {
+    pub id: DocumentId,
+    pub title: String,
+    pub kind: DocumentKind,
+    pub added: DateTime<Utc>,
+    pub size: usize,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
+    pub aliases: Vec<String>,
+    pub keywords: Vec<String>,
+    pub metadata: BTreeMap<String, String>,
+    pub entities: Vec<EntityEntry>,
+    pub links_to: Vec<DocumentRef>,
+    pub linked_from: Vec<DocumentRef>,
+    pub relations: Vec<RelationEntry>,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EntityEntry {
+    /// `person`, `organization`, `place` or `date`.
+    pub kind: &'static str,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RelationEntry {
+    pub kind: String,
+    /// The related graph node.
+    pub target: String,
+    /// Null if the target was removed.
+    pub title: Option<String>,
+    /// Null for certain relations.
+    pub confidence: Option<f32>,
+    /// Derived from other relations by the ontology rather than stated.
+    pub inferred: bool,
+}
+
+/// `ozy rm`: one per id.
+#[derive(Debug, Serialize)]
+pub struct Removed {
+    pub id: DocumentId,
+    /// Null when only a tombstone was cleared.
+    pub title: Option<String>,
+    pub action: RemoveAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoveAction {
+    Removed,
+    Purged,
+    ClearedTombstone,
+}
+
+/// `ozy trash list`, `restore` and `empty`: one per document.
+#[derive(Debug, Serialize)]
+pub struct TrashEntry {
+    pub id: DocumentId,
+    pub title: String,
+    pub deleted: DateTime<Utc>,
+    /// When it will be emptied from the trash; null unless
+    /// `trash.expire_after_days` is set.
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// `ozy tag add` and `ozy tag rm`.
+#[derive(Debug, Serialize)]
+pub struct Tagged {
+    pub id: DocumentId,
+    /// All tags of the document afterwards.
+    pub tags: Vec<String>,
+}
+
+/// `ozy alias add`, `rm` and `list`: one per document.
+#[derive(Debug, Serialize)]
+pub struct Aliases {
+    pub id: DocumentId,
+    pub title: String,
+    pub aliases: Vec<String>,
+    /// Ids of duplicates merged into the document, which resolve to it.
+    pub former_ids: Vec<DocumentId>,
+}
+
+/// `ozy tag list --tree`: one per top-level tag.
+#[derive(Debug, Serialize)]
+pub struct TagTree {
+    /// The last segment of the tag path.
+    pub name: String,
+    /// Documents tagged with exactly this tag.
+    pub direct: usize,
+    /// Documents tagged with this tag or any nested below it.
+    pub total: usize,
+    pub children: Vec<TagTree>,
+}
+
+/// `ozy relate`.
+#[derive(Debug, Serialize)]
+pub struct Related {
+    pub from: DocumentId,
+    pub kind: String,
+    pub to: DocumentId,
+    /// Whether the relation was removed rather than added.
+    pub removed: bool,
+}
+
+/// `ozy cites` and `ozy cited-by`: one per citing or cited document.
+#[derive(Debug, Serialize)]
+pub struct CitationRef {
+    pub id: DocumentId,
+    pub title: String,
+    /// 1 for a DOI, less for a title, as given for a `cites` relation.
+    pub confidence: Option<f32>,
+}
+
+/// `ozy new`.
+#[derive(Debug, Serialize)]
+pub struct NewNote {
+    pub id: DocumentId,
+    pub title: String,
+    pub path: PathBuf,
+    /// The template the note was created from.
+    pub template: Option<String>,
+}
+
+/// `ozy open`.
+#[derive(Debug, Serialize)]
+pub struct Opened {
+    pub id: DocumentId,
+    /// The file or URL opened.
+    pub target: String,
+    /// `editor`, `browser`, `viewer` for the system's application for
+    /// the file, or `file-manager` with `--reveal`.
+    pub with: &'static str,
+}
+
+/// `ozy attach` and `ozy attachments`: one per attached file.
+#[derive(Debug, Serialize)]
+pub struct AttachmentEntry {
+    pub name: String,
+    pub mime: Option<String>,
+    /// Hash of the blob holding the file.
+    pub blob: String,
+    /// Hash of the blob holding a PNG preview of it.
+    pub thumbnail: Option<String>,
+    /// Size in bytes, or null if the blob is missing.
+    pub size: Option<u64>,
+    /// Where the file is: in the blob store, or where `--save` copied it.
+    pub path: PathBuf,
+}
+
+/// `ozy journal`.
+#[derive(Debug, Serialize)]
+pub struct JournalEntry {
+    pub id: DocumentId,
+    pub title: String,
+    pub path: PathBuf,
+    /// Whether the entry's file was created from the template.
+    pub created: bool,
+    /// The entry of the closest earlier day, which this one follows.
+    pub previous: Option<DocumentId>,
+    /// The entry of the closest later day, which follows this one.
+    pub next: Option<DocumentId>,
+}
+
+/// `ozy timeline`: one per date of a document.
+#[derive(Debug, Serialize)]
+pub struct TimelineEntry {
+    /// As precise as the document gives it: `1815`, `1815-12` or
+    /// `1815-12-10`.
+    pub date: String,
+    pub id: DocumentId,
+    pub title: String,
+}
+
+/// `ozy todo list` and `done`: one per task.
+#[derive(Debug, Serialize)]
+pub struct TodoEntry {
+    /// The task as named on the command line: `<document>:<line>`.
+    pub id: String,
+    pub document: DocumentId,
+    pub title: String,
+    pub line: usize,
+    pub text: String,
+    pub done: bool,
+    pub due: Option<NaiveDate>,
+}
+
+/// `ozy review add`, `remove` and `list`: one per card.
+#[derive(Debug, Serialize)]
+pub struct ReviewCard {
+    pub document: DocumentId,
+    pub title: String,
+    /// The flashcard's question, or null for the document as a whole.
+    pub question: Option<String>,
+    pub due: NaiveDate,
+    /// Days between the last review and the next.
+    pub interval: u32,
+    pub reviews: usize,
+}
+
+/// `ozy review start`.
+#[derive(Debug, Serialize)]
+pub struct ReviewSession {
+    pub reviewed: usize,
+    /// Reviews graded below 3.
+    pub forgotten: usize,
+    /// Cards still due today.
+    pub remaining: usize,
+}
+
+/// `ozy classify`: one per document.
+#[derive(Debug, Serialize)]
+pub struct Classified {
+    pub id: DocumentId,
+    pub title: String,
+    pub concepts: Vec<ConceptMatch>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConceptMatch {
+    pub concept: String,
+    /// The matching classification rule, counting from 1.
+    pub rule: Option<usize>,
+    /// How often the concept's labels appear, when no rule matched.
+    pub mentions: Option<usize>,
+    /// Broader concepts, nearest first.
+    pub broader: Vec<String>,
+}
+
+/// `ozy summarize`: one per document.
+#[derive(Debug, Serialize)]
+pub struct Summarized {
+    pub id: DocumentId,
+    pub title: String,
+    pub summary: String,
+}
+
+/// `ozy cluster`.
+#[derive(Debug, Serialize)]
+pub struct Clusters {
+    /// Largest first.
+    pub clusters: Vec<Topic>,
+    /// Number of documents tagged with `--tag`.
+    pub tagged: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Topic {
+    pub keywords: Vec<String>,
+    pub documents: Vec<DocumentRef>,
+}
+
+/// `ozy ask`.
+#[derive(Debug, Serialize)]
+pub struct Answer {
+    pub question: String,
+    /// Null when no language model is configured.
+    pub answer: Option<String>,
+    /// Passages given to the model, numbered from 1 in the answer.
+    pub sources: Vec<Source>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Source {
+    /// `id:start-end`.
+    pub citation: String,
+    pub id: DocumentId,
+    pub title: String,
+    /// Byte offsets of the passage in the document's content.
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// `ozy graph neighbors`: one per edge.
+#[derive(Debug, Serialize)]
+pub struct GraphNeighbor {
+    /// `outgoing` or `incoming`.
+    pub direction: &'static str,
+    pub kind: String,
+    pub node: String,
+    pub label: String,
+}
+
+/// A node of the graph; `ozy graph path` prints an array of these, empty
+/// when there is no path.
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+    pub node: String,
+    pub label: String,
+}
+
+/// `ozy graph stats`.
+#[derive(Debug, Serialize)]
+pub struct GraphStats {
+    pub nodes: usize,
+    pub edges: usize,
+    pub documents: usize,
+    /// The most central documents, up to `--limit`.
+    pub ranking: Vec<Centrality>,
+    /// Connected components, smallest first, up to `--limit`.
+    pub components: Vec<Vec<GraphNode>>,
+    /// Number of connected components.
+    pub component_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Centrality {
+    pub rank: usize,
+    pub id: String,
+    pub title: String,
+    pub pagerank: f64,
+    pub betweenness: f64,
+    pub degree: usize,
+}
+
+/// `ozy links check`: one per reported URL.
+#[derive(Debug, Serialize)]
+pub struct LinkStatus {
+    pub url: String,
+    /// `ok`, `redirected` or `dead`.
+    pub status: &'static str,
+    /// The HTTP status of a redirect.
+    pub http_status: Option<u16>,
+    /// Where a redirect points.
+    pub location: Option<String>,
+    /// Why a link is dead.
+    pub reason: Option<String>,
+    /// Documents containing the link.
+    pub documents: Vec<DocumentRef>,
+}
+
+/// `ozy dedupe report`: one per cluster of near duplicates.
+#[derive(Debug, Serialize)]
+pub struct DuplicateCluster {
+    /// The lowest similarity between two members.
+    pub similarity: f32,
+    /// Oldest first.
+    pub documents: Vec<DocumentSummary>,
+}
+
+/// `ozy dedupe merge`: one per duplicate.
+#[derive(Debug, Serialize)]
+pub struct Merged {
+    pub duplicate: DocumentId,
+    pub into: DocumentId,
+}
+
+/// `ozy undo --list`: the most recent first.
+#[derive(Debug, Serialize)]
+pub struct UndoEntry {
+    pub command: String,
+    pub at: DateTime<Utc>,
+    /// Documents it changed.
+    pub documents: usize,
+}
+
+/// `ozy undo`.
+#[derive(Debug, Serialize)]
+pub struct Undone {
+    pub command: String,
+    pub at: DateTime<Utc>,
+    /// Documents put back as they were.
+    pub restored: Vec<DocumentId>,
+    /// Documents the command had added, now removed.
+    pub removed: Vec<DocumentId>,
+}
+
+/// `ozy history`.
+#[derive(Debug, Serialize)]
+pub struct DocumentHistory {
+    pub id: DocumentId,
+    pub title: String,
+    /// Oldest first; the last is the document as it is now.
+    pub revisions: Vec<RevisionEntry>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevisionEntry {
+    pub number: usize,
+    pub saved: DateTime<Utc>,
+    pub title: String,
+    pub size: usize,
+    /// Lines added since the revision before.
+    pub added: usize,
+    /// Lines removed since the revision before.
+    pub removed: usize,
+}
+
+/// `ozy diff`.
+#[derive(Debug, Serialize)]
+pub struct DocumentDiff {
+    pub id: DocumentId,
+    pub from: usize,
+    pub to: usize,
+    /// A unified diff of the content; empty if it did not change.
+    pub diff: String,
+}
+
+/// `ozy export`.
+#[derive(Debug, Serialize)]
+pub struct Exported {
+    pub dir: PathBuf,
+    pub documents: usize,
+    /// Files written next to the documents, such as images.
+    pub attachments: usize,
+}
+
+/// `ozy export anki`.
+#[derive(Debug, Serialize)]
+pub struct ExportedDeck {
+    pub file: PathBuf,
+    pub documents: usize,
+    pub cards: usize,
+}
+
+/// `ozy export book`.
+#[derive(Debug, Serialize)]
+pub struct ExportedBook {
+    pub file: PathBuf,
+    /// One per document, in order.
+    pub chapters: Vec<DocumentSummary>,
+    /// Pages, for a PDF.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<usize>,
+    /// Images and files inside an EPUB.
+    pub attachments: usize,
+}
+
+/// `ozy export bibtex`.
+#[derive(Debug, Serialize)]
+pub struct ExportedBibliography {
+    pub file: PathBuf,
+    pub references: usize,
+}
+
+/// `ozy sync`.
+#[derive(Debug, Serialize)]
+pub struct Synced {
+    pub remote: String,
+    /// Documents added, changed or removed here.
+    pub pulled: usize,
+    /// Documents added, changed or removed on the remote.
+    pub pushed: usize,
+    /// Documents changed on both sides, and which version was kept.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// `ozy stats`.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub documents: usize,
+    /// Documents per type, most common first.
+    pub types: Vec<Count>,
+    /// Documents per tag, most common first.
+    pub tags: Vec<Count>,
+    pub storage: StorageSize,
+    pub index: IndexSize,
+    pub links: LinkCounts,
+    /// Documents added per month, oldest first.
+    pub growth: Vec<Growth>,
+}
+
+/// Bytes on disk, by what they hold.
+#[derive(Debug, Serialize)]
+pub struct StorageSize {
+    pub documents: u64,
+    /// Snapshots of web pages.
+    pub blobs: u64,
+    /// The full-text index, embeddings, links and graph.
+    pub indexes: u64,
+    /// Config, tombstones and anything else.
+    pub other: u64,
+    pub total: u64,
+    /// What the total comes to compressed with DEFLATE.
+    pub compressed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IndexSize {
+    pub documents: usize,
+    /// Distinct terms in the full-text index.
+    pub terms: usize,
+    /// Documents and chunks with an embedding.
+    pub vectors: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LinkCounts {
+    /// Links between documents of the knowledge base.
+    pub internal: usize,
+    /// Links to the web and other URLs.
+    pub external: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Growth {
+    /// As `YYYY-MM`.
+    pub month: String,
+    pub added: usize,
+    /// Documents added up to the end of the month.
+    pub total: usize,
+}
+
+/// `ozy doctor`: one per problem found.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    /// Short name of the check that failed, such as `orphaned-blob`.
+    pub check: &'static str,
+    /// The document concerned, if any.
+    pub id: Option<DocumentId>,
+    pub message: String,
+    /// Whether `--fix` can repair it.
+    pub fixable: bool,
+    /// Whether it was repaired.
+    pub fixed: bool,
+}
+
+/// `ozy verify`.
+#[derive(Debug, Serialize)]
+pub struct Verified {
+    /// Document files hashed.
+    pub documents: usize,
+    /// Blobs hashed.
+    pub blobs: usize,
+    /// Checksums recorded with `--record`.
+    pub recorded: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// `ozy gc`.
+#[derive(Debug, Serialize)]
+pub struct Collected {
+    /// Blobs no document refers to, removed.
+    pub blobs: usize,
+    /// Documents gone for good whose revisions were removed.
+    pub history: usize,
+    /// Entries of the full-text index for documents no longer stored.
+    pub index_entries: usize,
+    /// Embeddings of documents no longer stored.
+    pub embeddings: usize,
+    /// Checksums of documents no longer stored.
+    pub checksums: usize,
+    /// Tombstones of documents that are stored again.
+    pub tombstones: usize,
+    /// Operations of the undo log that can no longer be undone.
+    pub undo_operations: usize,
+    /// Bytes freed on disk.
+    pub reclaimed: u64,
+}
+
+/// `ozy reindex`.
+#[derive(Debug, Serialize)]
+pub struct Reindexed {
+    pub documents: usize,
+}
+
+/// `ozy migrate`.
+#[derive(Debug, Serialize)]
+pub struct Migrated {
+    pub documents: usize,
+    /// Documents rewritten in the current format.
+    pub rewritten: Vec<DocumentId>,
+    /// The format of the knowledge base before, and now.
+    pub from_format: u32,
+    pub format: u32,
+}
+
+/// `ozy backup`.
+#[derive(Debug, Serialize)]
+pub struct BackedUp {
+    pub file: PathBuf,
+    pub files: usize,
+    /// Size of the files before compression.
+    pub bytes: u64,
+}
+
+/// `ozy models list`.
+#[derive(Debug, Serialize)]
+pub struct Models {
+    /// The model the knowledge base's vectors were made with.
+    pub vectors: Option<String>,
+    /// Number of documents per detected language.
+    pub languages: BTreeMap<String, usize>,
+    /// Repositories downloaded with `ozy models pull`.
+    pub downloaded: Vec<String>,
+}
+
+/// `ozy models pull`.
+#[derive(Debug, Serialize)]
+pub struct Pulled {
+    pub repo: String,
+    pub path: PathBuf,
+}
+
+/// `ozy models use` and `ozy models reembed`.
+#[derive(Debug, Serialize)]
+pub struct Reembedded {
+    pub documents: usize,
+    pub model: String,
+}
+
+/// `ozy ontology import`.
+#[derive(Debug, Serialize)]
+pub struct Imported {
+    pub file: PathBuf,
+    pub added: usize,
+    /// Concepts whose names were already taken.
+    pub skipped: usize,
+}
+
+/// `ozy prompts list`: one per template.
+#[derive(Debug, Serialize)]
+pub struct PromptTemplate {
+    pub name: &'static str,
+    /// The file overriding the built-in text, if any.
+    pub path: Option<PathBuf>,
+    pub variables: Vec<&'static str>,
+}
+
+/// `ozy prompts show`.
+#[derive(Debug, Serialize)]
+pub struct PromptText {
+    pub name: &'static str,
+    pub text: String,
+}
+
+/// `ozy prompts init`: one per template.
+#[derive(Debug, Serialize)]
+pub struct PromptFile {
+    pub path: PathBuf,
+    /// False when an existing file was kept.
+    pub written: bool,
+}
+
+/// `ozy config get` and `ozy config list`: one per setting.
+#[derive(Debug, Serialize)]
+pub struct Setting {
+    /// Dotted key, such as `llm.model`.
+    pub key: String,
+    pub value: toml::Value,
+    pub origin: Origin,
+}
+
+/// `ozy config set`.
+#[derive(Debug, Serialize)]
+pub struct SettingChanged {
+    pub file: PathBuf,
+    pub key: String,
+    pub value: toml::Value,
+}
@@ -0,0 +1,69 @@
+//! Spreading independent work over threads, or, for work that waits on
+//! the network, over futures.
+
+use std::future::Future;
+use std::num::NonZero;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use futures::stream::{self, StreamExt};
+
+/// Jobs run at least by default, since much of the work spread over them
+/// waits on the network: fetching pages, remote embeddings and language
+/// models.
+const MIN_JOBS: usize = 8;
+
+/// How many jobs to run at once when not told otherwise: one per core,
+/// but no fewer than [`MIN_JOBS`].
+pub fn default_jobs() -> usize {
+    thread::available_parallelism()
+        .map_or(1, NonZero::get)
+        .max(MIN_JOBS)
+}
+
+/// Applies `f` to every item on up to `jobs` threads, and returns the
+/// results in the order of the items.
+pub fn map<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(i) else {
+                    break;
+                };
+                let result = f(item);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.expect("every item was mapped"))
+        .collect()
+}
+
+/// Awaits `f` of every item with up to `jobs` of them in flight at once,
+/// and returns the results in the order of the items. No thread is taken
+/// while a future waits, so this suits requests over the network.
+pub async fn map_async<'a, T, R, F>(
+    items: &'a [T],
+    jobs: usize,
+    f: impl FnMut(&'a T) -> F,
+) -> Vec<R>
+where
+    F: Future<Output = R>,
+{
+    stream::iter(items)
+        .map(f)
+        .buffered(jobs.max(1))
+        .collect()
+        .await
+}
@@ -0,0 +1,356 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::error::OzymandiasError;
+use crate::links::{self, Link};
+use crate::plugins::Plugins;
+use crate::types::DocumentKind;
+
+/// The result of turning a raw input into indexable text.
+#[derive(Debug, Clone)]
+pub struct ParsedData {
+    pub title: String,
+    pub kind: DocumentKind,
+    pub content: String,
+    pub links: Vec<Link>,
+}
+
+pub trait Parser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData>;
+
+    /// Parses the bytes of a file. Parsers of text decode them as UTF-8;
+    /// those that can read other formats take them as they are.
+    fn parse_bytes(&self, raw: &[u8], fallback_title: &str) -> Result<ParsedData> {
+        let text = std::str::from_utf8(raw).context("the file is not UTF-8 text")?;
+        self.parse(text, fallback_title)
+    }
+}
+
+pub struct MarkdownParser;
+pub struct TextParser;
+pub struct HtmlParser;
+/// Like [`HtmlParser`], but keeps only the main article of a web page and
+/// drops navigation, headers, footers and sidebars.
+pub struct ArticleParser;
+
+impl Parser for MarkdownParser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData> {
+        let title = raw
+            .lines()
+            .find_map(|l| l.strip_prefix("# "))
+            .map(|t| t.trim().to_string())
+            .unwrap_or_else(|| fallback_title.to_string());
+        Ok(ParsedData {
+            title,
+            kind: DocumentKind::Markdown,
+            content: raw.to_string(),
+            links: links::extract(raw),
+        })
+    }
+}
+
+impl Parser for TextParser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData> {
+        Ok(ParsedData {
+            title: fallback_title.to_string(),
+            kind: DocumentKind::Text,
+            content: raw.to_string(),
+            links: links::extract(raw),
+        })
+    }
+}
+
+impl Parser for HtmlParser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData> {
+        let title = between(raw, "<title>", "</title>")
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .unwrap_or_else(|| fallback_title.to_string());
+        Ok(ParsedData {
+            title,
+            kind: DocumentKind::Html,
+            content: strip_tags(raw),
+            links: links::extract(raw),
+        })
+    }
+}
+
+impl Parser for ArticleParser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData> {
+        let mut parsed = HtmlParser.parse(raw, fallback_title)?;
+        let article = readable(raw);
+        parsed.content = strip_tags(&article);
+        parsed.links = links::extract(&article);
+        Ok(parsed)
+    }
+}
+
+/// The markup of a page's main content: its `<article>`, else `<main>`,
+/// else `<body>`, with page chrome removed.
+fn readable(html: &str) -> String {
+    let region = ["article", "main", "body"]
+        .iter()
+        .find_map(|tag| element(html, tag))
+        .unwrap_or(html);
+    let mut out = region.to_string();
+    for tag in ["nav", "header", "footer", "aside", "form"] {
+        out = remove_elements(&out, tag);
+    }
+    out
+}
+
+/// The first `<tag …>…</tag>` element, including the tags themselves.
+fn element<'a>(html: &'a str, tag: &str) -> Option<&'a str> {
+    let lower = html.to_ascii_lowercase();
+    let start = find_open(&lower, tag, 0)?;
+    let close = format!("</{tag}>");
+    let end = lower[start..].find(&close)? + start + close.len();
+    Some(&html[start..end])
+}
+
+pub fn remove_elements(html: &str, tag: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let close = format!("</{tag}>");
+    let mut out = String::with_capacity(html.len());
+    let mut i = 0;
+    while let Some(start) = find_open(&lower, tag, i) {
+        out.push_str(&html[i..start]);
+        i = lower[start..]
+            .find(&close)
+            .map_or(html.len(), |p| start + p + close.len());
+    }
+    out.push_str(&html[i.min(html.len())..]);
+    out
+}
+
+/// Finds `<tag>` or `<tag attr…>` but not `<tagline>`.
+fn find_open(lower: &str, tag: &str, from: usize) -> Option<usize> {
+    let open = format!("<{tag}");
+    let mut at = from;
+    while let Some(p) = lower[at..].find(&open) {
+        let start = at + p;
+        let next = lower[start + open.len()..].chars().next();
+        if matches!(next, Some('>' | '/') | Some(' ' | '\t' | '\n' | '\r')) {
+            return Some(start);
+        }
+        at = start + open.len();
+    }
+    None
+}
+
+fn between<'a>(s: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let lower = s.to_ascii_lowercase();
+    let start = lower.find(open)? + open.len();
+    let end = start + lower[start..].find(close)?;
+    Some(&s[start..end])
+}
+
+/// The text of a PDF, page after page. Text that is only in images is
+/// not read.
+pub fn pdf_text(bytes: &[u8]) -> Result<String> {
+    // Some damaged files make the extractor panic rather than fail.
+    std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+        .map_err(|_| OzymandiasError::ParseFailed("the PDF is damaged".into()))?
+        .map_err(|e| OzymandiasError::ParseFailed(format!("cannot read the PDF: {e}")).into())
+}
+
+/// Removes markup, scripts and styles, leaving whitespace-separated text.
+pub fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let lower = html.to_ascii_lowercase();
+    let mut i = 0;
+    while i < html.len() {
+        let rest = &lower[i..];
+        if rest.starts_with("<script") || rest.starts_with("<style") {
+            let close = if rest.starts_with("<script") {
+                "</script>"
+            } else {
+                "</style>"
+            };
+            i = rest.find(close).map_or(html.len(), |p| i + p + close.len());
+            out.push(' ');
+        } else if rest.starts_with('<') {
+            i = rest.find('>').map_or(html.len(), |p| i + p + 1);
+            out.push(' ');
+        } else {
+            let c = html[i..].chars().next().unwrap();
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    decode_entities(&out.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// `s` with the common named entities replaced by their characters.
+pub fn decode_entities(s: &str) -> String {
+    s.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A parser run as an external command, configured under `[parsers]` by
+/// file extension. It gets the file on stdin, its name without the
+/// extension in `OZY_TITLE`, and prints JSON on stdout:
+///
+/// ```json
+/// {"title": "…", "kind": "markdown", "content": "…", "links": [{"wiki": "…"}]}
+/// ```
+///
+/// Only `content` is required. Without a title the file name is used,
+/// without a kind the content is plain text, and without links they are
+/// extracted from the content.
+pub struct CommandParser {
+    pub command: String,
+}
+
+#[derive(Deserialize)]
+struct CommandOutput {
+    title: Option<String>,
+    kind: Option<DocumentKind>,
+    content: String,
+    links: Option<Vec<Link>>,
+}
+
+impl Parser for CommandParser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData> {
+        self.parse_bytes(raw.as_bytes(), fallback_title)
+    }
+
+    /// The command gets the file as it is, so that it may read binary
+    /// formats such as PDF.
+    fn parse_bytes(&self, raw: &[u8], fallback_title: &str) -> Result<ParsedData> {
+        let mut words = self.command.split_whitespace();
+        let program = words.next().context("the parser command is empty")?;
+        let mut child = Command::new(program)
+            .args(words)
+            .env("OZY_TITLE", fallback_title)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {}", self.command))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        // Written on another thread, so that a parser printing as it reads
+        // cannot fill its stdout while waiting for more input.
+        let input = raw.to_vec();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        // A parser that exits without reading all of its input is fine.
+        writer.join().expect("the writer does not panic").ok();
+        if !output.status.success() {
+            bail!("{} exited with {}", self.command, output.status);
+        }
+        let parsed: CommandOutput = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("{} printed invalid JSON", self.command))?;
+        let links = parsed
+            .links
+            .unwrap_or_else(|| links::extract(&parsed.content));
+        Ok(ParsedData {
+            title: parsed.title.unwrap_or_else(|| fallback_title.to_string()),
+            kind: parsed.kind.unwrap_or(DocumentKind::Text),
+            content: parsed.content,
+            links,
+        })
+    }
+}
+
+pub fn parser_for(kind: DocumentKind) -> Box<dyn Parser> {
+    match kind {
+        DocumentKind::Markdown => Box::new(MarkdownParser),
+        DocumentKind::Html => Box::new(HtmlParser),
+        DocumentKind::Text => Box::new(TextParser),
+    }
+}
+
+/// Reads and parses a file, picking the parser from its extension.
+pub fn parse_file(path: &Path, plugins: &Plugins) -> Result<ParsedData> {
+    let raw = std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    parse_raw(path, &raw, plugins)
+}
+
+/// Parses the already read bytes of the file at `path`, with a parser
+/// command or plugin if one reads its extension. Only parsers of text
+/// decode them.
+pub fn parse_raw(path: &Path, raw: &[u8], plugins: &Plugins) -> Result<ParsedData> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("untitled");
+    let builtin;
+    let parser: &dyn Parser = match plugins.parser(extension) {
+        Some(plugin) => plugin,
+        None => {
+            builtin = parser_for(DocumentKind::from_extension(extension));
+            builtin.as_ref()
+        }
+    };
+    parser
+        .parse_bytes(raw, stem)
+        .with_context(|| OzymandiasError::ParseFailed(format!("cannot parse {}", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A one-page PDF showing `text` in Helvetica.
+    fn pdf(text: &str) -> Vec<u8> {
+        let stream = format!("BT /F1 24 Tf 72 700 Td ({text}) Tj ET");
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_string(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] /Contents 4 0 R \
+             /Resources << /Font << /F1 5 0 R >> >> >>"
+                .to_string(),
+            format!(
+                "<< /Length {} >>\nstream\n{stream}\nendstream",
+                stream.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+        ];
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend(format!("{} 0 obj\n{object}\nendobj\n", i + 1).bytes());
+        }
+        let xref = out.len();
+        out.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).bytes());
+        for offset in offsets {
+            out.extend(format!("{offset:010} 00000 n \n").bytes());
+        }
+        out.extend(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+                objects.len() + 1
+            )
+            .bytes(),
+        );
+        out
+    }
+
+    #[test]
+    fn the_text_of_a_pdf_is_read() {
+        let text = pdf_text(&pdf("Hello from a PDF")).unwrap();
+        assert_eq!(text.trim(), "Hello from a PDF");
+    }
+
+    #[test]
+    fn what_is_not_a_pdf_fails_to_parse() {
+        let e = pdf_text(b"%PDF-1.4\nnot really").unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<OzymandiasError>(),
+            Some(OzymandiasError::ParseFailed(_))
+        ));
+    }
+}
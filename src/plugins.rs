@@ -0,0 +1,276 @@
+//! WebAssembly plugins: components in `plugins/` implementing the contract
+//! in `wit/plugin.wit`. A parser plugin reads files with the extensions it
+//! names; a transformer plugin is a pipeline stage named after its file,
+//! so `plugins/redact.wasm` runs wherever `"redact"` is listed in
+//! `[pipeline]`.
+//!
+//! Each call runs in a fresh instance with a budget of fuel, so plugins
+//! keep no state between documents and one that loops is stopped.
+//!
+//! Parser commands configured under `[parsers]`, lighter to write than a
+//! component, are kept here too and take precedence over parser plugins:
+//!
+//! ```toml
+//! [parsers]
+//! org = "org2json --links"
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Engine, Store};
+
+use crate::kb::KnowledgeBase;
+use crate::links::Link;
+use crate::parser::{CommandParser, ParsedData, Parser};
+use crate::transform::{Outcome, Stage};
+use crate::types::{Document, DocumentKind};
+
+mod parser_bindings {
+    wasmtime::component::bindgen!({ path: "wit", world: "parser-plugin" });
+}
+
+mod transformer_bindings {
+    wasmtime::component::bindgen!({ path: "wit", world: "transformer-plugin" });
+}
+
+use parser_bindings::exports::ozymandias::plugin::parser as wit_parser;
+use parser_bindings::{ParserPlugin, ParserPluginPre};
+use transformer_bindings::exports::ozymandias::plugin::transformer as wit_transformer;
+use transformer_bindings::{TransformerPlugin, TransformerPluginPre};
+
+pub const PLUGINS_DIR: &str = "plugins";
+const PARSER_EXPORT: &str = "ozymandias:plugin/parser@0.1.0";
+const TRANSFORMER_EXPORT: &str = "ozymandias:plugin/transformer@0.1.0";
+/// Instructions, roughly, a plugin may run per call.
+const FUEL: u64 = 10_000_000_000;
+
+/// The plugins of a knowledge base.
+#[derive(Default)]
+pub struct Plugins {
+    /// Parser commands by lowercase extension.
+    commands: BTreeMap<String, CommandParser>,
+    parsers: Vec<WasmParser>,
+    transformers: BTreeMap<String, WasmTransformer>,
+}
+
+impl Plugins {
+    /// Compiles every `.wasm` file in the knowledge base's `plugins/`,
+    /// and takes the parser `commands` by extension.
+    pub fn load(root: &Path, commands: &BTreeMap<String, String>) -> Result<Self> {
+        let mut plugins = Plugins {
+            commands: commands
+                .iter()
+                .map(|(extension, command)| {
+                    let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+                    let parser = CommandParser {
+                        command: command.clone(),
+                    };
+                    (extension, parser)
+                })
+                .collect(),
+            ..Plugins::default()
+        };
+        let dir = root.join(PLUGINS_DIR);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(plugins),
+            Err(e) => return Err(e).with_context(|| format!("cannot read {}", dir.display())),
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?;
+        paths.retain(|p| p.extension().is_some_and(|e| e == "wasm"));
+        paths.sort();
+        if paths.is_empty() {
+            return Ok(plugins);
+        }
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        for path in paths {
+            plugins
+                .add(&engine, &path)
+                .with_context(|| format!("cannot load plugin {}", path.display()))?;
+        }
+        Ok(plugins)
+    }
+
+    fn add(&mut self, engine: &Engine, path: &Path) -> Result<()> {
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .context("plugin file names must be UTF-8")?
+            .to_string();
+        let component = Component::from_file(engine, path)?;
+        let mut linker = Linker::new(engine);
+        linker.define_unknown_imports_as_traps(&component)?;
+        let pre = linker.instantiate_pre(&component)?;
+        let mut found = false;
+        if component.get_export_index(None, PARSER_EXPORT).is_some() {
+            let parser = WasmParser {
+                name: name.clone(),
+                engine: engine.clone(),
+                pre: ParserPluginPre::new(pre.clone())?,
+                extensions: Vec::new(),
+            };
+            let (mut store, plugin) = parser.instantiate()?;
+            let extensions = plugin
+                .ozymandias_plugin_parser()
+                .call_extensions(&mut store)?;
+            self.parsers.push(WasmParser {
+                extensions: extensions.iter().map(|e| e.to_ascii_lowercase()).collect(),
+                ..parser
+            });
+            found = true;
+        }
+        if component
+            .get_export_index(None, TRANSFORMER_EXPORT)
+            .is_some()
+        {
+            let transformer = WasmTransformer {
+                name: name.clone(),
+                engine: engine.clone(),
+                pre: TransformerPluginPre::new(pre)?,
+            };
+            self.transformers.insert(name, transformer);
+            found = true;
+        }
+        if !found {
+            bail!("it exports neither {PARSER_EXPORT} nor {TRANSFORMER_EXPORT}");
+        }
+        Ok(())
+    }
+
+    /// The parser command or plugin for files with `extension`, if any.
+    pub fn parser(&self, extension: &str) -> Option<&dyn Parser> {
+        let extension = extension.to_ascii_lowercase();
+        if let Some(command) = self.commands.get(&extension) {
+            return Some(command);
+        }
+        self.parsers
+            .iter()
+            .find(|p| p.extensions.contains(&extension))
+            .map(|p| p as &dyn Parser)
+    }
+
+    /// Whether a parser command or plugin reads files with `extension`.
+    pub fn handles(&self, extension: &str) -> bool {
+        self.parser(extension).is_some()
+    }
+
+    /// The transformer plugin named `name`, as a pipeline stage.
+    pub fn stage(&self, name: &str) -> Option<Box<dyn Stage>> {
+        self.transformers
+            .get(name)
+            .map(|t| Box::new(t.clone()) as Box<dyn Stage>)
+    }
+
+    /// Names of the transformer plugins.
+    pub fn stages(&self) -> impl Iterator<Item = &str> {
+        self.transformers.keys().map(String::as_str)
+    }
+}
+
+/// A parser plugin.
+pub struct WasmParser {
+    name: String,
+    engine: Engine,
+    pre: ParserPluginPre<()>,
+    extensions: Vec<String>,
+}
+
+impl WasmParser {
+    fn instantiate(&self) -> Result<(Store<()>, ParserPlugin)> {
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(FUEL)?;
+        let plugin = self.pre.instantiate(&mut store)?;
+        Ok((store, plugin))
+    }
+}
+
+impl Parser for WasmParser {
+    fn parse(&self, raw: &str, fallback_title: &str) -> Result<ParsedData> {
+        let (mut store, plugin) = self.instantiate()?;
+        let parsed = plugin
+            .ozymandias_plugin_parser()
+            .call_parse(&mut store, raw, fallback_title)
+            .with_context(|| format!("plugin {} failed", self.name))?
+            .map_err(|e| anyhow!("plugin {}: {e}", self.name))?;
+        Ok(ParsedData {
+            title: parsed.title,
+            kind: match parsed.kind {
+                wit_parser::Kind::Markdown => DocumentKind::Markdown,
+                wit_parser::Kind::Text => DocumentKind::Text,
+                wit_parser::Kind::Html => DocumentKind::Html,
+            },
+            content: parsed.content,
+            links: parsed
+                .links
+                .into_iter()
+                .map(|link| match link {
+                    wit_parser::Link::Wiki(title) => Link::Wiki(title),
+                    wit_parser::Link::Href(target) => Link::Href(target),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// A transformer plugin.
+#[derive(Clone)]
+pub struct WasmTransformer {
+    name: String,
+    engine: Engine,
+    pre: TransformerPluginPre<()>,
+}
+
+impl Stage for WasmTransformer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(&self, doc: &mut Document, _kb: &KnowledgeBase) -> Result<Outcome> {
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(FUEL)?;
+        let plugin: TransformerPlugin = self.pre.instantiate(&mut store)?;
+        let input = wit_transformer::Document {
+            id: doc.id.to_string(),
+            kind: match doc.kind {
+                DocumentKind::Markdown => wit_transformer::Kind::Markdown,
+                DocumentKind::Text => wit_transformer::Kind::Text,
+                DocumentKind::Html => wit_transformer::Kind::Html,
+            },
+            source: doc.source.clone(),
+            title: doc.title.clone(),
+            content: doc.content.clone(),
+            tags: doc.tags.clone(),
+            aliases: doc.aliases.clone(),
+            metadata: doc
+                .metadata
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        };
+        let outcome = plugin
+            .ozymandias_plugin_transformer()
+            .call_apply(&mut store, &input)
+            .with_context(|| format!("plugin {} failed on {}", self.name, doc.id))?
+            .map_err(|e| anyhow!("plugin {} on {}: {e}", self.name, doc.id))?;
+        match outcome {
+            wit_transformer::Outcome::Keep(output) => {
+                doc.title = output.title;
+                doc.content = output.content;
+                doc.tags = output.tags;
+                doc.aliases = output.aliases;
+                doc.metadata = output.metadata.into_iter().collect();
+                Ok(Outcome::Continue)
+            }
+            wit_transformer::Outcome::Skip(reason) => Ok(Outcome::Skip(reason)),
+        }
+    }
+}
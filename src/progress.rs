@@ -0,0 +1,107 @@
+//! Progress of long-running commands, on stderr: a bar with an estimate of
+//! the time left on a terminal, otherwise a log line every few seconds, and
+//! nothing at all with `--quiet`.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// How often progress is logged when stderr is not a terminal.
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Turns progress reporting off for this run.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Counts work done towards a known total. It can be shared between
+/// threads.
+pub struct Progress {
+    what: &'static str,
+    total: u64,
+    bar: Option<ProgressBar>,
+    done: AtomicU64,
+    started: Instant,
+    /// When a line was last logged, without a bar.
+    logged: Mutex<Instant>,
+}
+
+impl Progress {
+    /// Starts reporting on `total` items, such as files or documents.
+    pub fn new(what: &'static str, total: usize) -> Self {
+        let total = total as u64;
+        let quiet = QUIET.load(Ordering::Relaxed);
+        let bar = (!quiet && std::io::stderr().is_terminal()).then(|| {
+            let bar = ProgressBar::new(total).with_message(what);
+            bar.set_style(
+                ProgressStyle::with_template("{bar:30} {pos}/{len} {msg}, {eta} left")
+                    .expect("the template is valid"),
+            );
+            bar
+        });
+        let now = Instant::now();
+        Progress {
+            what,
+            total: if quiet { 0 } else { total },
+            bar,
+            done: AtomicU64::new(0),
+            started: now,
+            logged: Mutex::new(now),
+        }
+    }
+
+    /// Records `n` more items as done.
+    pub fn inc(&self, n: u64) {
+        if let Some(bar) = &self.bar {
+            bar.inc(n);
+            return;
+        }
+        let done = self.done.fetch_add(n, Ordering::Relaxed) + n;
+        if self.total == 0 || done >= self.total {
+            return;
+        }
+        let mut logged = self.logged.lock().unwrap();
+        if logged.elapsed() < LOG_INTERVAL {
+            return;
+        }
+        *logged = Instant::now();
+        let elapsed = self.started.elapsed();
+        let left = elapsed.mul_f64((self.total - done) as f64 / done as f64);
+        tracing::info!(
+            "{done}/{} {} ({}%), about {} left",
+            self.total,
+            self.what,
+            done * 100 / self.total,
+            human_duration(left)
+        );
+    }
+
+    /// Runs `f`, which prints, without it being drawn over by the bar.
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        match &self.bar {
+            Some(bar) => bar.suspend(f),
+            None => f(),
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn human_duration(d: Duration) -> String {
+    match d.as_secs() {
+        s if s < 60 => format!("{s}s"),
+        s if s < 3600 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}h {}m", s / 3600, s % 3600 / 60),
+    }
+}
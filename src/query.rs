@@ -0,0 +1,634 @@
+//! The query language shared by every command that selects documents.
+//!
+//! ```text
+//! query   := or
+//! or      := and ("OR" and)*
+//! and     := unary (["AND"] unary)*
+//! unary   := ("NOT" | "-") unary | primary
+//! primary := "(" or ")" | "field:value" | "\"phrase\"" | word
+//! ```
+//!
+//! Operators are case-sensitive so that the words "and", "or" and "not"
+//! can still be searched for. Field values may be quoted: `tag:"to read"`,
+//! `entity:person:"Ada Lovelace"`.
+
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::filter::Filter;
+use crate::index::{analyze, language_of, Analyzer};
+use crate::ontology::Ontology;
+use crate::tags;
+use crate::transform::language;
+use crate::types::Document;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    Term(String),
+    Phrase(Vec<String>),
+    Filter(Filter),
+}
+
+/// A parsed query: the boolean expression plus the words to rank by.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub expr: Option<Expr>,
+    /// Positive terms and phrases, fed to the keyword and vector indexes.
+    pub text: String,
+    /// Whether terms are boolean constraints. Plain word lists are only
+    /// ranking signals; any operator, phrase or parenthesis makes them strict.
+    pub strict: bool,
+    /// How terms are compared with document words; the full-text index's
+    /// analyzer, so filtering agrees with ranking.
+    pub analyzer: Analyzer,
+}
+
+/// A syntax error with the character column it occurred at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub input: String,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "query syntax error at column {}: {}",
+            self.column + 1,
+            self.message
+        )?;
+        writeln!(f, "  {}", self.input)?;
+        write!(f, "  {}^", " ".repeat(self.column))
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = lex(input)?;
+        let strict = tokens
+            .iter()
+            .any(|t| !matches!(t.kind, TokenKind::Word(_) | TokenKind::Field(..)));
+        let mut parser = ExprParser {
+            input,
+            tokens,
+            pos: 0,
+        };
+        let expr = if parser.tokens.is_empty() {
+            None
+        } else {
+            let expr = parser.or()?;
+            if let Some(tok) = parser.peek() {
+                return Err(parser.error_at(tok.column, "unexpected input").into());
+            }
+            Some(expr)
+        };
+        let mut text = Vec::new();
+        if let Some(expr) = &expr {
+            collect_text(expr, &mut text);
+        }
+        Ok(Query {
+            expr,
+            text: text.join(" "),
+            strict,
+            analyzer: Analyzer::default(),
+        })
+    }
+
+    /// A query that only ranks by the words of `text`, for natural
+    /// language that should not be read as query syntax.
+    pub fn plain(text: &str) -> Self {
+        Query {
+            expr: None,
+            text: text.to_string(),
+            strict: false,
+            analyzer: Analyzer::default(),
+        }
+    }
+
+    /// Lets `tag:` filters also match tags of narrower ontology concepts,
+    /// so `tag:technology` finds notes tagged `rust` when Rust is a
+    /// programming language and programming languages are technology.
+    pub fn infer(&mut self, ontology: &Ontology) {
+        if let Some(expr) = &mut self.expr {
+            widen_tags(expr, ontology);
+        }
+    }
+
+    /// Applies the boolean structure. When `terms_required` is false, as in
+    /// semantic ranking, positive terms always pass and only filters and
+    /// negated terms constrain the result.
+    pub fn matches(&self, doc: &Document, terms_required: bool) -> bool {
+        let Some(expr) = &self.expr else {
+            return true;
+        };
+        let text = format!("{} {}", doc.title, doc.content);
+        let language = language_of(doc);
+        let words = self.analyzer.terms(&text, language);
+        let ctx = EvalContext {
+            doc,
+            words: &words,
+            analyzer: &self.analyzer,
+            language,
+            strict: terms_required && self.strict,
+        };
+        ctx.eval(expr, false)
+    }
+}
+
+fn collect_text(expr: &Expr, out: &mut Vec<String>) {
+    match expr {
+        Expr::And(items) | Expr::Or(items) => items.iter().for_each(|e| collect_text(e, out)),
+        Expr::Term(t) => out.push(t.clone()),
+        Expr::Phrase(words) => out.extend(words.iter().cloned()),
+        Expr::Not(_) | Expr::Filter(_) => {}
+    }
+}
+
+fn widen_tags(expr: &mut Expr, ontology: &Ontology) {
+    match expr {
+        Expr::And(items) | Expr::Or(items) => {
+            items.iter_mut().for_each(|e| widen_tags(e, ontology))
+        }
+        Expr::Not(inner) => widen_tags(inner, ontology),
+        Expr::Filter(Filter::Tag(tag)) => {
+            let Some(concept) = ontology.concept_for_tag(tag) else {
+                return;
+            };
+            let mut alternatives = vec![Expr::Filter(Filter::Tag(tag.clone()))];
+            for narrower in ontology.narrower(concept) {
+                if let Ok(t) = tags::normalize(narrower) {
+                    alternatives.push(Expr::Filter(Filter::Tag(t)));
+                }
+            }
+            *expr = flatten(alternatives, Expr::Or);
+        }
+        Expr::Term(_) | Expr::Phrase(_) | Expr::Filter(_) => {}
+    }
+}
+
+struct EvalContext<'a> {
+    doc: &'a Document,
+    words: &'a [String],
+    analyzer: &'a Analyzer,
+    language: Option<&'a str>,
+    strict: bool,
+}
+
+impl EvalContext<'_> {
+    fn eval(&self, expr: &Expr, negated: bool) -> bool {
+        match expr {
+            Expr::And(items) => items.iter().all(|e| self.eval(e, negated)),
+            Expr::Or(items) => items.iter().any(|e| self.eval(e, negated)),
+            Expr::Not(inner) => !self.eval(inner, !negated),
+            Expr::Term(term) => {
+                if !self.strict && !negated {
+                    return true;
+                }
+                // Stopwords are not indexed, so they constrain nothing.
+                match self.analyzer.normalize(term, self.language) {
+                    Some(term) => self.words.contains(&term),
+                    None => true,
+                }
+            }
+            Expr::Phrase(phrase) => {
+                if !self.strict && !negated {
+                    return true;
+                }
+                let phrase: Vec<String> = phrase
+                    .iter()
+                    .filter_map(|w| self.analyzer.normalize(w, self.language))
+                    .collect();
+                phrase.is_empty() || self.words.windows(phrase.len()).any(|w| w == phrase)
+            }
+            Expr::Filter(filter) => filter.matches(self.doc),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Word(String),
+    Phrase(String),
+    Field(String, String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    column: usize,
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, SyntaxError> {
+    let chars: Vec<char> = input.chars().collect();
+    let error = |column: usize, message: &str| SyntaxError {
+        input: input.to_string(),
+        column,
+        message: message.to_string(),
+    };
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i;
+        let kind = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => {
+                i += 1;
+                TokenKind::LParen
+            }
+            ')' => {
+                i += 1;
+                TokenKind::RParen
+            }
+            '-' if chars.get(i + 1).is_some_and(|n| !n.is_whitespace()) => {
+                i += 1;
+                TokenKind::Not
+            }
+            '"' => {
+                let (text, next) =
+                    quoted(&chars, i).ok_or_else(|| error(i, "unterminated quote"))?;
+                i = next;
+                TokenKind::Phrase(text)
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && !"()\"".contains(chars[i]) {
+                    if chars[i] == ':' && chars.get(i + 1) == Some(&'"') {
+                        i += 1;
+                        break;
+                    }
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if let Some(field) = word
+                    .strip_suffix(':')
+                    .filter(|_| chars.get(i) == Some(&'"'))
+                {
+                    let (value, next) =
+                        quoted(&chars, i).ok_or_else(|| error(i, "unterminated quote"))?;
+                    i = next;
+                    TokenKind::Field(field.to_string(), value)
+                } else {
+                    match word.as_str() {
+                        "AND" => TokenKind::And,
+                        "OR" => TokenKind::Or,
+                        "NOT" => TokenKind::Not,
+                        _ => match word.split_once(':') {
+                            Some((field, value)) if !field.is_empty() && !value.is_empty() => {
+                                TokenKind::Field(field.to_string(), value.to_string())
+                            }
+                            _ => TokenKind::Word(word),
+                        },
+                    }
+                }
+            }
+        };
+        tokens.push(Token { kind, column });
+    }
+    Ok(tokens)
+}
+
+/// Reads a `"…"` string starting at `start`, honouring `\"` escapes.
+fn quoted(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut out = String::new();
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                out.push(chars[i + 1]);
+                i += 2;
+            }
+            '"' => return Some((out, i + 1)),
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    None
+}
+
+struct ExprParser<'a> {
+    input: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn end_column(&self) -> usize {
+        self.input.chars().count()
+    }
+
+    fn error_at(&self, column: usize, message: &str) -> SyntaxError {
+        SyntaxError {
+            input: self.input.to_string(),
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, SyntaxError> {
+        let mut items = vec![self.and()?];
+        while self.peek().is_some_and(|t| t.kind == TokenKind::Or) {
+            self.pos += 1;
+            items.push(self.and()?);
+        }
+        Ok(flatten(items, Expr::Or))
+    }
+
+    fn and(&mut self) -> Result<Expr, SyntaxError> {
+        let mut items = vec![self.unary()?];
+        loop {
+            match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::And) => {
+                    self.pos += 1;
+                    items.push(self.unary()?);
+                }
+                Some(TokenKind::Or | TokenKind::RParen) | None => break,
+                Some(_) => items.push(self.unary()?),
+            }
+        }
+        Ok(flatten(items, Expr::And))
+    }
+
+    fn unary(&mut self) -> Result<Expr, SyntaxError> {
+        if self.peek().is_some_and(|t| t.kind == TokenKind::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, SyntaxError> {
+        let Some(token) = self.tokens.get(self.pos).cloned() else {
+            return Err(self.error_at(self.end_column(), "expected a term"));
+        };
+        self.pos += 1;
+        match token.kind {
+            TokenKind::LParen => {
+                let inner = self.or()?;
+                match self.peek() {
+                    Some(t) if t.kind == TokenKind::RParen => {
+                        self.pos += 1;
+                        Ok(inner)
+                    }
+                    Some(t) => Err(self.error_at(t.column, "expected ')'")),
+                    None => Err(self.error_at(token.column, "unclosed '('")),
+                }
+            }
+            TokenKind::Word(word) => Ok(word_expr(&word)),
+            TokenKind::Phrase(text) => {
+                let words = analyze(&text, language::detect(&text));
+                if words.is_empty() {
+                    return Err(self.error_at(token.column, "empty phrase"));
+                }
+                Ok(Expr::Phrase(words))
+            }
+            TokenKind::Field(field, value) => match Filter::parse(&field, &value) {
+                Ok(Some(filter)) => Ok(Expr::Filter(filter)),
+                // Not a known field, e.g. a URL: search for its words instead.
+                Ok(None) => Ok(word_expr(&format!("{field}:{value}"))),
+                Err(e) => {
+                    Err(self.error_at(token.column + field.chars().count() + 1, &format!("{e:#}")))
+                }
+            },
+            TokenKind::RParen => Err(self.error_at(token.column, "unmatched ')'")),
+            TokenKind::And | TokenKind::Or => {
+                Err(self.error_at(token.column, "expected a term before this operator"))
+            }
+            TokenKind::Not => unreachable!("handled in unary"),
+        }
+    }
+}
+
+/// A bare word; punctuation inside it (`trade-offs`) makes it a phrase.
+fn word_expr(word: &str) -> Expr {
+    let mut terms = analyze(word, language::detect(word));
+    match terms.len() {
+        0 => Expr::And(Vec::new()),
+        1 => Expr::Term(terms.remove(0)),
+        _ => Expr::Phrase(terms),
+    }
+}
+
+fn flatten(mut items: Vec<Expr>, wrap: fn(Vec<Expr>) -> Expr) -> Expr {
+    if items.len() == 1 {
+        items.remove(0)
+    } else {
+        wrap(items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{DocumentId, DocumentKind};
+
+    fn term(t: &str) -> Expr {
+        Expr::Term(t.to_string())
+    }
+
+    fn expr(input: &str) -> Expr {
+        Query::parse(input).unwrap().expr.unwrap()
+    }
+
+    fn syntax_error(input: &str) -> SyntaxError {
+        Query::parse(input)
+            .unwrap_err()
+            .downcast::<SyntaxError>()
+            .unwrap()
+    }
+
+    fn doc(content: &str) -> Document {
+        let mut doc = Document::new(
+            DocumentId::derive(content),
+            "Note".to_string(),
+            DocumentKind::Markdown,
+            content.to_string(),
+            None,
+        );
+        doc.metadata
+            .insert(language::KEY.to_string(), "en".to_string());
+        doc
+    }
+
+    fn matches(query: &str, content: &str) -> bool {
+        Query::parse(query).unwrap().matches(&doc(content), true)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            expr("apple pear OR plum"),
+            Expr::Or(vec![
+                Expr::And(vec![term("apple"), term("pear")]),
+                term("plum")
+            ])
+        );
+        assert_eq!(
+            expr("apple AND (pear OR plum)"),
+            Expr::And(vec![
+                term("apple"),
+                Expr::Or(vec![term("pear"), term("plum")])
+            ])
+        );
+    }
+
+    #[test]
+    fn not_and_minus_negate() {
+        let negated = Expr::And(vec![term("apple"), Expr::Not(Box::new(term("pear")))]);
+        assert_eq!(expr("apple NOT pear"), negated);
+        assert_eq!(expr("apple -pear"), negated);
+        assert_eq!(
+            expr("NOT NOT apple"),
+            Expr::Not(Box::new(Expr::Not(Box::new(term("apple")))))
+        );
+    }
+
+    #[test]
+    fn operators_are_case_sensitive() {
+        assert_eq!(
+            expr("cats and dogs"),
+            Expr::And(vec![term("cats"), term("and"), term("dogs")])
+        );
+    }
+
+    #[test]
+    fn quotes_make_phrases_and_field_values() {
+        assert_eq!(
+            expr("\"New York\""),
+            Expr::Phrase(vec!["new".into(), "york".into()])
+        );
+        assert_eq!(
+            expr("tag:\"to read\""),
+            Expr::Filter(Filter::Tag("to-read".into()))
+        );
+        assert_eq!(
+            expr("\"say \\\"hi\\\"\""),
+            Expr::Phrase(vec!["say".into(), "hi".into()])
+        );
+    }
+
+    #[test]
+    fn unknown_fields_and_punctuation_stay_words() {
+        assert_eq!(
+            expr("https://example.org"),
+            Expr::Phrase(vec!["https".into(), "example".into(), "org".into()])
+        );
+        assert_eq!(
+            expr("trade-offs"),
+            Expr::Phrase(vec!["trade".into(), "offs".into()])
+        );
+    }
+
+    #[test]
+    fn only_operators_and_phrases_make_terms_strict() {
+        assert!(!Query::parse("apple pear").unwrap().strict);
+        assert!(!Query::parse("apple tag:fruit").unwrap().strict);
+        assert!(Query::parse("apple AND pear").unwrap().strict);
+        assert!(Query::parse("-pear").unwrap().strict);
+        assert!(Query::parse("\"apple pie\"").unwrap().strict);
+        assert!(Query::parse("(apple)").unwrap().strict);
+    }
+
+    #[test]
+    fn text_holds_the_positive_words() {
+        let query = Query::parse("apple -pear tag:fruit \"big tree\" OR plum").unwrap();
+        assert_eq!(query.text, "apple big tree plum");
+        assert!(Query::parse("").unwrap().expr.is_none());
+        assert!(Query::parse("   ").unwrap().expr.is_none());
+    }
+
+    #[test]
+    fn errors_point_at_their_column() {
+        let cases = [
+            ("(apple", 0, "unclosed '('"),
+            ("apple)", 5, "unexpected input"),
+            ("OR apple", 0, "expected a term before this operator"),
+            ("apple AND", 9, "expected a term"),
+            ("\"open", 0, "unterminated quote"),
+            ("tag:\"open", 4, "unterminated quote"),
+            ("\"...\"", 0, "empty phrase"),
+        ];
+        for (input, column, message) in cases {
+            let error = syntax_error(input);
+            assert_eq!(
+                (error.column, error.message.as_str()),
+                (column, message),
+                "{input:?}"
+            );
+        }
+        assert_eq!(syntax_error("x added:someday").column, 8);
+    }
+
+    #[test]
+    fn errors_show_a_caret() {
+        assert_eq!(
+            syntax_error("apple)").to_string(),
+            "query syntax error at column 6: unexpected input\n  apple)\n       ^"
+        );
+    }
+
+    #[test]
+    fn strict_terms_must_appear() {
+        assert!(matches("apple AND pie", "An apple pie."));
+        assert!(!matches("apple AND pear", "An apple pie."));
+        assert!(matches("pear OR pie", "An apple pie."));
+        assert!(matches("apples", "An apple pie."));
+    }
+
+    #[test]
+    fn loose_terms_only_rank() {
+        assert!(matches("banana", "An apple pie."));
+        // As in semantic ranking, where only negations and filters count.
+        let loose = |query: &str| {
+            Query::parse(query)
+                .unwrap()
+                .matches(&doc("An apple pie."), false)
+        };
+        assert!(loose("banana AND cherry"));
+        assert!(!loose("banana -pie"));
+        assert!(loose("banana -pear"));
+    }
+
+    #[test]
+    fn phrases_match_words_in_order() {
+        assert!(matches("\"apple pie\"", "I like apple pie."));
+        assert!(!matches("\"apple pie\"", "A pie, then an apple."));
+        assert!(matches("\"pie of the apple\"", "The pie of the apple."));
+    }
+
+    #[test]
+    fn stopwords_constrain_nothing() {
+        assert!(matches("the AND apple", "An apple."));
+    }
+
+    #[test]
+    fn filters_apply_to_any_query() {
+        let mut tagged = doc("An apple.");
+        tagged.tags.push("fruit".into());
+        let query = Query::parse("apple tag:fruit").unwrap();
+        assert!(query.matches(&tagged, true));
+        assert!(!query.matches(&doc("An apple."), true));
+    }
+}
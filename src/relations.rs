@@ -0,0 +1,121 @@
+//! Typed relations between documents, such as "cites" or "contradicts".
+
+use std::fmt;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ontology::Ontology;
+use crate::types::DocumentId;
+
+/// The kind of a relation. Besides the built-in kinds, an ontology may
+/// declare its own under `relations:`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum RelationKind {
+    IsA,
+    PartOf,
+    Cites,
+    Contradicts,
+    /// Comes after, as a journal entry after the previous one.
+    Follows,
+    Custom(String),
+}
+
+pub const BUILTIN: [&str; 5] = ["is-a", "part-of", "cites", "contradicts", "follows"];
+
+impl RelationKind {
+    pub fn name(&self) -> &str {
+        match self {
+            RelationKind::IsA => "is-a",
+            RelationKind::PartOf => "part-of",
+            RelationKind::Cites => "cites",
+            RelationKind::Contradicts => "contradicts",
+            RelationKind::Follows => "follows",
+            RelationKind::Custom(name) => name,
+        }
+    }
+
+    /// The relation read backwards, if it has a name: `part-of` ⇄
+    /// `has-part`, `cites` ⇄ `cited-by`, `follows` ⇄ `precedes`, and
+    /// `contradicts` is symmetric.
+    pub fn inverse(&self, ontology: Option<&Ontology>) -> Option<RelationKind> {
+        let name = match self {
+            RelationKind::IsA => "has-kind",
+            RelationKind::PartOf => "has-part",
+            RelationKind::Cites => "cited-by",
+            RelationKind::Contradicts => "contradicts",
+            RelationKind::Follows => "precedes",
+            RelationKind::Custom(name) => {
+                let declared = ontology?.relations.get(name)?;
+                declared.inverse.as_deref()?
+            }
+        };
+        Some(RelationKind::from(name.to_string()))
+    }
+
+    pub fn is_transitive(&self, ontology: Option<&Ontology>) -> bool {
+        match self {
+            RelationKind::IsA | RelationKind::PartOf => true,
+            RelationKind::Cites | RelationKind::Contradicts | RelationKind::Follows => false,
+            RelationKind::Custom(name) => {
+                ontology.is_some_and(|o| o.relations.get(name).is_some_and(|r| r.transitive))
+            }
+        }
+    }
+
+    /// Parses a relation name typed by the user, accepting custom kinds
+    /// only if the ontology declares them.
+    pub fn parse(name: &str, ontology: Option<&Ontology>) -> Result<Self> {
+        let name = name.trim().to_lowercase();
+        let kind = RelationKind::from(name.clone());
+        if let RelationKind::Custom(_) = kind {
+            if !ontology.is_some_and(|o| o.relations.contains_key(&name)) {
+                bail!(
+                    "unknown relation {name:?} (expected {} or one declared in the ontology)",
+                    BUILTIN.join(", ")
+                );
+            }
+        }
+        Ok(kind)
+    }
+}
+
+impl From<String> for RelationKind {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "is-a" => RelationKind::IsA,
+            "part-of" => RelationKind::PartOf,
+            "cites" => RelationKind::Cites,
+            "contradicts" => RelationKind::Contradicts,
+            "follows" => RelationKind::Follows,
+            _ => RelationKind::Custom(name),
+        }
+    }
+}
+
+impl From<RelationKind> for String {
+    fn from(kind: RelationKind) -> Self {
+        kind.name().to_string()
+    }
+}
+
+impl fmt::Display for RelationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A typed edge from the document holding it to `target`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Relation {
+    pub kind: RelationKind,
+    pub target: DocumentId,
+    /// How sure the author is, from 0 to 1.
+    #[serde(default = "certain")]
+    pub confidence: f32,
+}
+
+fn certain() -> f32 {
+    1.0
+}
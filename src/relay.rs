@@ -0,0 +1,667 @@
+//! End-to-end encrypted sync through a relay that is not trusted with the
+//! documents: `ozy serve --relay <dir>` on a server, or a directory kept
+//! in sync by some other service.
+//!
+//! A relay stores opaque objects by name and nothing else. Documents,
+//! blobs and the index of their versions are sealed with
+//! ChaCha20-Poly1305 under a key derived from a passphrase that only the
+//! replicas know, and are named by keyed hashes of their ids, so the relay
+//! sees neither content nor ids. The replicas do all the comparing; the
+//! relay only holds the latest version each has sent.
+//!
+//! Objects that change are only replaced if they are still what the
+//! replica last read, so that replicas syncing at once do not undo each
+//! other. The index counts its writes, and each replica remembers the
+//! latest count it has seen, so a relay serving an older index than that
+//! is caught rolling it back.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::OpenOptions;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use reqwest::{Method, RequestBuilder, StatusCode};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{hmac, pbkdf2};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::OzymandiasError;
+use crate::kb;
+use crate::ml::providers::http;
+use crate::runtime;
+use crate::storage::{read_json_or_default, sha256, write_json};
+use crate::sync::{self, Blob, Entry, Order, Remote, Request, Response, Version};
+use crate::types::DocumentId;
+
+/// Environment variable holding the passphrase, unless a remote names
+/// another.
+pub const PASSPHRASE_ENV: &str = "OZY_SYNC_PASSPHRASE";
+/// PBKDF2 rounds making guessing the passphrase from stolen objects slow.
+const ROUNDS: u32 = 200_000;
+const SALT: &str = "salt";
+const CHECK: &str = "check";
+const INDEX: &str = "index";
+/// File in a relay's directory locked while an object is replaced.
+const LOCK_FILE: &str = ".lock";
+/// File in the knowledge base holding the latest index version seen of
+/// each relay.
+pub const SEEN_FILE: &str = "relays.json";
+/// What the check object holds, to tell a wrong passphrase from damage.
+const CHECK_TEXT: &[u8] = b"ozymandias relay";
+
+/// Where a relay keeps its objects.
+pub trait Store {
+    /// Names of all objects.
+    fn list(&self) -> Result<Vec<String>>;
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()>;
+    /// Puts `bytes` only if the object is still the one whose SHA-256 is
+    /// `current`, or still missing for `None`, returning whether it did.
+    fn replace(&self, name: &str, current: Option<&str>, bytes: &[u8]) -> Result<bool>;
+}
+
+/// Whether `name` can name an object: what [`Relay`] makes up is
+/// lowercase hex with a prefix.
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 80
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// Objects as files in a directory.
+pub struct DirStore(pub PathBuf);
+
+impl Store for DirStore {
+    fn list(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&self.0)
+            .with_context(|| format!("failed to read {}", self.0.display()))?
+        {
+            if let Some(name) = entry?.file_name().to_str().filter(|n| valid_name(n)) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.0.join(name);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        std::fs::create_dir_all(&self.0)
+            .with_context(|| format!("failed to create {}", self.0.display()))?;
+        let path = self.0.join(name);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn replace(&self, name: &str, current: Option<&str>, bytes: &[u8]) -> Result<bool> {
+        std::fs::create_dir_all(&self.0)
+            .with_context(|| format!("failed to create {}", self.0.display()))?;
+        let path = self.0.join(LOCK_FILE);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        lock.lock()
+            .with_context(|| format!("failed to lock {}", path.display()))?;
+        if self.get(name)?.map(|stored| sha256(&stored)).as_deref() != current {
+            return Ok(false);
+        }
+        self.put(name, bytes)?;
+        Ok(true)
+    }
+}
+
+/// The objects of `ozy serve --relay`, under `/relay`.
+pub struct HttpStore {
+    pub url: String,
+    pub token: String,
+}
+
+impl HttpStore {
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        http().request(method, url).bearer_auth(&self.token)
+    }
+}
+
+impl Store for HttpStore {
+    fn list(&self) -> Result<Vec<String>> {
+        let url = format!("{}/relay", self.url);
+        let raw = runtime::block_on(async {
+            let response = self
+                .request(Method::GET, &url)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .with_context(|| format!("request to {url} failed"))?;
+            response
+                .text()
+                .await
+                .with_context(|| format!("failed to read response from {url}"))
+        })?;
+        serde_json::from_str(&raw).with_context(|| format!("unexpected response from {url}"))
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let url = format!("{}/relay/{name}", self.url);
+        runtime::block_on(async {
+            let response = self
+                .request(Method::GET, &url)
+                .send()
+                .await
+                .with_context(|| format!("request to {url} failed"))?;
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let bytes = response
+                .error_for_status()
+                .with_context(|| format!("request to {url} failed"))?
+                .bytes()
+                .await
+                .with_context(|| format!("failed to read response from {url}"))?;
+            Ok(Some(bytes.to_vec()))
+        })
+    }
+
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        let url = format!("{}/relay/{name}", self.url);
+        runtime::block_on(
+            self.request(Method::PUT, &url)
+                .header("Content-Type", "application/octet-stream")
+                .body(bytes.to_vec())
+                .send(),
+        )
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(|| format!("request to {url} failed"))?;
+        Ok(())
+    }
+
+    fn replace(&self, name: &str, current: Option<&str>, bytes: &[u8]) -> Result<bool> {
+        let url = format!("{}/relay/{name}", self.url);
+        let request = self
+            .request(Method::PUT, &url)
+            .header("Content-Type", "application/octet-stream");
+        let request = match current {
+            Some(hash) => request.header("If-Match", format!("\"{hash}\"")),
+            None => request.header("If-None-Match", "*"),
+        };
+        let response = runtime::block_on(request.body(bytes.to_vec()).send())
+            .with_context(|| format!("request to {url} failed"))?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Ok(false);
+        }
+        response
+            .error_for_status()
+            .with_context(|| format!("request to {url} failed"))?;
+        Ok(true)
+    }
+}
+
+/// The versions of the documents a relay holds, and how many times they
+/// were written.
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    version: u64,
+    documents: BTreeMap<DocumentId, Version>,
+}
+
+/// A relay opened with the passphrase, acting as the remote of a sync.
+pub struct Relay {
+    store: Box<dyn Store>,
+    key: LessSafeKey,
+    names: hmac::Key,
+    random: SystemRandom,
+    /// The [`SEEN_FILE`] of the replica syncing.
+    seen: PathBuf,
+}
+
+impl Relay {
+    /// Opens the relay in `store`, setting it up with `passphrase` on
+    /// first use. `seen` is the [`SEEN_FILE`] of the replica syncing.
+    pub fn open(store: Box<dyn Store>, passphrase: &str, seen: PathBuf) -> Result<Self> {
+        let random = SystemRandom::new();
+        let salt = loop {
+            if let Some(salt) = store.get(SALT)? {
+                break salt;
+            }
+            let mut salt = vec![0; 16];
+            random
+                .fill(&mut salt)
+                .map_err(|_| anyhow::anyhow!("no random numbers"))?;
+            // A replica setting the relay up at the same time may win.
+            if store.replace(SALT, None, &salt)? {
+                break salt;
+            }
+        };
+        let mut keys = [0; 64];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(ROUNDS).expect("nonzero"),
+            &salt,
+            passphrase.as_bytes(),
+            &mut keys,
+        );
+        let key = UnboundKey::new(&aead::CHACHA20_POLY1305, &keys[..32])
+            .map_err(|_| anyhow::anyhow!("invalid key"))?;
+        let relay = Relay {
+            store,
+            key: LessSafeKey::new(key),
+            names: hmac::Key::new(hmac::HMAC_SHA256, &keys[32..]),
+            random,
+            seen,
+        };
+        loop {
+            match relay.store.get(CHECK)? {
+                Some(sealed) => {
+                    if relay.open_object(CHECK, sealed).ok().as_deref() != Some(CHECK_TEXT) {
+                        bail!(OzymandiasError::ConfigInvalid(
+                            "wrong passphrase for the relay".into()
+                        ));
+                    }
+                    break;
+                }
+                None if relay.replace(CHECK, None, CHECK_TEXT)? => break,
+                None => {}
+            }
+        }
+        Ok(relay)
+    }
+
+    /// The object name of a document or blob, which only holders of the
+    /// passphrase can tell apart from any other.
+    fn name(&self, kind: &str, id: &str) -> String {
+        let tag = hmac::sign(&self.names, format!("{kind}\0{id}").as_bytes());
+        let hex: String = tag.as_ref()[..16]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        format!("{kind}-{hex}")
+    }
+
+    /// Encrypts `bytes` under the object's name, so that the relay cannot
+    /// pass one object off as another.
+    fn put(&self, name: &str, bytes: &[u8]) -> Result<()> {
+        self.store.put(name, &self.seal(name, bytes)?)
+    }
+
+    /// Like [`Relay::put`], but only over the sealed object `current`; see
+    /// [`Store::replace`].
+    fn replace(&self, name: &str, current: Option<&[u8]>, bytes: &[u8]) -> Result<bool> {
+        let current = current.map(sha256);
+        self.store
+            .replace(name, current.as_deref(), &self.seal(name, bytes)?)
+    }
+
+    fn seal(&self, name: &str, bytes: &[u8]) -> Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("no random numbers"))?;
+        let mut sealed = bytes.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(name.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| anyhow::anyhow!("failed to encrypt {name}"))?;
+        let mut object = nonce.to_vec();
+        object.append(&mut sealed);
+        Ok(object)
+    }
+
+    fn open_object(&self, name: &str, mut object: Vec<u8>) -> Result<Vec<u8>> {
+        if object.len() < NONCE_LEN {
+            bail!("relay object {name} is damaged");
+        }
+        let mut sealed = object.split_off(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(&object).expect("nonce length");
+        let len = self
+            .key
+            .open_in_place(nonce, Aad::from(name.as_bytes()), &mut sealed)
+            .map_err(|_| {
+                OzymandiasError::StorageCorrupt(format!("relay object {name} is damaged"))
+            })?
+            .len();
+        sealed.truncate(len);
+        Ok(sealed)
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        match self.store.get(name)? {
+            Some(object) => self.open_object(name, object).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The index, sealed as stored and opened, after checking that it is
+    /// not older than one seen before.
+    fn index(&self) -> Result<(Option<Vec<u8>>, Index)> {
+        let sealed = self.store.get(INDEX)?;
+        let index = match &sealed {
+            Some(object) => {
+                let raw = self.open_object(INDEX, object.clone())?;
+                // Relays written before the index counted its writes.
+                serde_json::from_slice(&raw)
+                    .or_else(|_| {
+                        serde_json::from_slice(&raw).map(|documents| Index {
+                            version: 0,
+                            documents,
+                        })
+                    })
+                    .context("the relay's index is damaged")?
+            }
+            None => Index::default(),
+        };
+        let seen: BTreeMap<String, u64> = read_json_or_default(&self.seen)?;
+        if index.version < seen.get(&self.id()).copied().unwrap_or(0) {
+            bail!(OzymandiasError::StorageCorrupt(
+                "the relay's index is older than one synced before; it was rolled back".into()
+            ));
+        }
+        self.remember(index.version)?;
+        Ok((sealed, index))
+    }
+
+    /// Names the relay in [`SEEN_FILE`], as only holders of the passphrase
+    /// can.
+    fn id(&self) -> String {
+        self.name("relay", "")
+    }
+
+    /// Records that the relay's index was seen at `version`.
+    fn remember(&self, version: u64) -> Result<()> {
+        if kb::dry_run() {
+            return Ok(());
+        }
+        let mut seen: BTreeMap<String, u64> = read_json_or_default(&self.seen)?;
+        let latest = seen.entry(self.id()).or_default();
+        if *latest < version {
+            *latest = version;
+            write_json(&self.seen, &seen)?;
+        }
+        Ok(())
+    }
+
+    /// Stores `entry` unless the relay has a version of its document that
+    /// is not older, returning whether the relay now holds `entry`'s.
+    fn put_entry(&self, entry: &Entry) -> Result<bool> {
+        let name = self.name("doc", &entry.id.0);
+        loop {
+            let current = self.store.get(&name)?;
+            if let Some(object) = &current {
+                let stored: Entry =
+                    serde_json::from_slice(&self.open_object(&name, object.clone())?)
+                        .context("a relay document is damaged")?;
+                match sync::compare(&entry.version.vector, &stored.version.vector) {
+                    Order::Newer => {}
+                    Order::Same => return Ok(true),
+                    Order::Older | Order::Concurrent => return Ok(false),
+                }
+            }
+            if self.replace(&name, current.as_deref(), &serde_json::to_vec(entry)?)? {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl Remote for Relay {
+    fn call(&mut self, request: Request) -> Result<Response> {
+        let base64 = base64::engine::general_purpose::STANDARD;
+        match request {
+            // A relay is not a replica: it only has what replicas sent.
+            // Which blobs it has is not asked; `Apply` skips those.
+            Request::Manifest => Ok(Response::Manifest {
+                replica: "relay".into(),
+                versions: self.index()?.1.documents,
+                blobs: Vec::new(),
+            }),
+            Request::Fetch { ids } => {
+                let mut entries = Vec::new();
+                for id in ids {
+                    if let Some(raw) = self.get(&self.name("doc", &id.0))? {
+                        let entry: Entry =
+                            serde_json::from_slice(&raw).context("a relay document is damaged")?;
+                        if entry.id != id {
+                            bail!("the relay returned {} for {id}", entry.id);
+                        }
+                        entries.push(entry);
+                    }
+                }
+                Ok(Response::Entries { entries })
+            }
+            Request::Blobs { hashes } => {
+                let mut blobs = Vec::new();
+                for hash in hashes {
+                    if let Some(bytes) = self.get(&self.name("blob", &hash))? {
+                        blobs.push(Blob {
+                            hash,
+                            data: base64.encode(bytes),
+                        });
+                    }
+                }
+                Ok(Response::Blobs { blobs })
+            }
+            Request::Apply { entries, blobs } => {
+                let stored: HashSet<String> = self.store.list()?.into_iter().collect();
+                for blob in blobs {
+                    let name = self.name("blob", &blob.hash);
+                    if !stored.contains(&name) {
+                        let bytes = base64
+                            .decode(&blob.data)
+                            .context("invalid base64 in a blob")?;
+                        self.put(&name, &bytes)?;
+                    }
+                }
+                let mut held = Vec::new();
+                for entry in entries {
+                    if self.put_entry(&entry)? {
+                        held.push(entry);
+                    }
+                }
+                // Read again until no other replica wrote meanwhile.
+                loop {
+                    let (sealed, mut index) = self.index()?;
+                    let mut documents = 0;
+                    for entry in &held {
+                        let newer = index.documents.get(&entry.id).is_none_or(|current| {
+                            sync::compare(&entry.version.vector, &current.vector) == Order::Newer
+                        });
+                        if newer {
+                            index
+                                .documents
+                                .insert(entry.id.clone(), entry.version.clone());
+                            documents += 1;
+                        }
+                    }
+                    if documents == 0 {
+                        return Ok(Response::Applied { documents });
+                    }
+                    index.version += 1;
+                    if self.replace(INDEX, sealed.as_deref(), &serde_json::to_vec(&index)?)? {
+                        self.remember(index.version)?;
+                        return Ok(Response::Applied { documents });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    use crate::types::{Document, DocumentKind};
+
+    /// An empty directory of its own for each test.
+    fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ozy-relay-{}-{test}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn open(dir: &std::path::Path, passphrase: &str) -> Result<Relay> {
+        Relay::open(
+            Box::new(DirStore(dir.join("relay"))),
+            passphrase,
+            dir.join(SEEN_FILE),
+        )
+    }
+
+    fn entry(key: &str, count: u64) -> Entry {
+        let doc = Document::new(
+            DocumentId::derive(key),
+            "Notes".into(),
+            DocumentKind::Markdown,
+            format!("Version {count}."),
+            None,
+        );
+        Entry {
+            id: doc.id.clone(),
+            version: Version {
+                vector: BTreeMap::from([("laptop".to_string(), count)]),
+                hash: Some(sha256(doc.content.as_bytes())),
+                modified: Utc::now(),
+            },
+            doc: Some(doc),
+        }
+    }
+
+    fn apply(relay: &mut Relay, entries: Vec<Entry>) -> usize {
+        match relay
+            .call(Request::Apply {
+                entries,
+                blobs: Vec::new(),
+            })
+            .unwrap()
+        {
+            Response::Applied { documents } => documents,
+            _ => panic!("expected Applied"),
+        }
+    }
+
+    #[test]
+    fn object_names_are_lowercase_hex_with_a_prefix() {
+        assert!(valid_name("doc-0123abcd"));
+        assert!(valid_name(INDEX));
+        assert!(!valid_name(""));
+        assert!(!valid_name("../index"));
+        assert!(!valid_name("Doc"));
+        assert!(!valid_name(LOCK_FILE));
+        assert!(!valid_name(&"a".repeat(81)));
+    }
+
+    #[test]
+    fn objects_open_only_under_the_name_they_were_sealed_with() {
+        let dir = dir("seal");
+        let relay = open(&dir, "secret").unwrap();
+        let sealed = relay.seal("doc-1", b"some notes").unwrap();
+        assert!(!sealed.windows(5).any(|w| w == b"notes"));
+        assert_eq!(
+            relay.open_object("doc-1", sealed.clone()).unwrap(),
+            b"some notes"
+        );
+        assert!(relay.open_object("doc-2", sealed.clone()).is_err());
+
+        let mut damaged = sealed;
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(relay.open_object("doc-1", damaged).is_err());
+        assert!(relay.open_object("doc-1", vec![0; 3]).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_relay_opens_again_only_with_its_passphrase() {
+        let dir = dir("passphrase");
+        let first = open(&dir, "secret").unwrap();
+        let again = open(&dir, "secret").unwrap();
+        assert_eq!(first.name("doc", "x"), again.name("doc", "x"));
+
+        let e = open(&dir, "guess").err().unwrap();
+        assert!(matches!(
+            e.downcast_ref::<OzymandiasError>(),
+            Some(OzymandiasError::ConfigInvalid(_))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn the_relay_sees_neither_ids_nor_content() {
+        let dir = dir("opaque");
+        let mut relay = open(&dir, "secret").unwrap();
+        let notes = entry("notes", 1);
+        apply(&mut relay, vec![notes.clone()]);
+        for name in DirStore(dir.join("relay")).list().unwrap() {
+            assert!(!name.contains(&notes.id.0));
+            let bytes = std::fs::read(dir.join("relay").join(name)).unwrap();
+            assert!(!bytes.windows(7).any(|w| w == b"Version"));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn documents_sent_come_back_until_an_older_version_is_sent() {
+        let dir = dir("apply");
+        let mut relay = open(&dir, "secret").unwrap();
+        assert_eq!(apply(&mut relay, vec![entry("notes", 2)]), 1);
+        assert_eq!(apply(&mut relay, vec![entry("notes", 2)]), 0);
+        assert_eq!(apply(&mut relay, vec![entry("notes", 1)]), 0);
+
+        let id = DocumentId::derive("notes");
+        let Response::Manifest { versions, .. } = relay.call(Request::Manifest).unwrap() else {
+            panic!("expected Manifest");
+        };
+        assert_eq!(versions[&id].vector["laptop"], 2);
+        let Response::Entries { entries } = relay
+            .call(Request::Fetch {
+                ids: vec![id, DocumentId::derive("missing")],
+            })
+            .unwrap()
+        else {
+            panic!("expected Entries");
+        };
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].doc.as_ref().unwrap().content, "Version 2.");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn an_index_rolled_back_is_caught() {
+        let dir = dir("rollback");
+        let mut relay = open(&dir, "secret").unwrap();
+        apply(&mut relay, vec![entry("notes", 1)]);
+        let store = DirStore(dir.join("relay"));
+        let old = store.get(INDEX).unwrap().unwrap();
+        apply(&mut relay, vec![entry("notes", 2)]);
+
+        store.put(INDEX, &old).unwrap();
+        let e = relay.call(Request::Manifest).err().unwrap();
+        assert!(matches!(
+            e.downcast_ref::<OzymandiasError>(),
+            Some(OzymandiasError::StorageCorrupt(_))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,293 @@
+//! Spaced repetition for `ozy review`, scheduled with SM-2.
+//!
+//! A card is a flashcard written into a document, a `Q:` / `A:` pair or a
+//! heading phrased as a question, or the document as a whole, asking for
+//! its title. Cards keep only the question they ask, so that an edited
+//! answer is reviewed as it now reads. Their schedules and every grade
+//! given are kept in `reviews.json`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::kb;
+use crate::storage::{read_json_or_default, write_json};
+use crate::types::DocumentId;
+
+/// The lowest grade that counts as recalled.
+pub const PASS: u8 = 3;
+pub const MAX_GRADE: u8 = 5;
+const INITIAL_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+
+/// When a card is next due, by SM-2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    /// How much the interval grows with each successful review.
+    pub ease: f64,
+    /// Days between the last review and the next.
+    pub interval: u32,
+    /// Successful reviews in a row.
+    pub repetitions: u32,
+    pub due: NaiveDate,
+}
+
+impl Schedule {
+    /// The schedule of a new card, due on `today`.
+    pub fn new(today: NaiveDate) -> Self {
+        Schedule {
+            ease: INITIAL_EASE,
+            interval: 0,
+            repetitions: 0,
+            due: today,
+        }
+    }
+
+    /// The schedule after a review graded `grade`, from 0 (forgotten) to
+    /// 5 (recalled at once).
+    pub fn next(&self, grade: u8, today: NaiveDate) -> Schedule {
+        let grade = grade.min(MAX_GRADE);
+        let (interval, repetitions) = if grade < PASS {
+            (1, 0)
+        } else {
+            let interval = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (f64::from(self.interval) * self.ease).round() as u32,
+            };
+            (interval, self.repetitions + 1)
+        };
+        let miss = f64::from(MAX_GRADE - grade);
+        let ease = (self.ease + 0.1 - miss * (0.08 + miss * 0.02)).max(MIN_EASE);
+        Schedule {
+            ease,
+            interval,
+            repetitions,
+            due: today + Duration::days(i64::from(interval)),
+        }
+    }
+}
+
+/// One grade given to a card.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Review {
+    pub at: DateTime<Utc>,
+    pub grade: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Card {
+    pub document: DocumentId,
+    /// The flashcard's question, or none for the document as a whole.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub question: Option<String>,
+    pub schedule: Schedule,
+    /// Every review of the card, oldest first.
+    #[serde(default)]
+    pub history: Vec<Review>,
+}
+
+/// The cards under review, in `reviews.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Reviews {
+    cards: Vec<Card>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Reviews {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("reviews.json");
+        let mut reviews: Reviews = read_json_or_default(&path)?;
+        reviews.path = path;
+        Ok(reviews)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if kb::dry_run() {
+            return Ok(());
+        }
+        write_json(&self.path, self)
+    }
+
+    pub fn cards(&self) -> &[Card] {
+        &self.cards
+    }
+
+    /// Puts a card up for review, due on `today`, unless it already is.
+    /// Returns whether it was added.
+    pub fn add(
+        &mut self,
+        document: &DocumentId,
+        question: Option<String>,
+        today: NaiveDate,
+    ) -> bool {
+        if self
+            .cards
+            .iter()
+            .any(|c| c.document == *document && c.question == question)
+        {
+            return false;
+        }
+        self.cards.push(Card {
+            document: document.clone(),
+            question,
+            schedule: Schedule::new(today),
+            history: Vec::new(),
+        });
+        true
+    }
+
+    /// Takes every card of a document out of review, returning them.
+    pub fn remove(&mut self, document: &DocumentId) -> Vec<Card> {
+        let (removed, kept) = std::mem::take(&mut self.cards)
+            .into_iter()
+            .partition(|c| c.document == *document);
+        self.cards = kept;
+        removed
+    }
+
+    /// Takes one card out of review.
+    pub fn drop_card(&mut self, document: &DocumentId, question: Option<&str>) {
+        self.cards
+            .retain(|c| !(c.document == *document && c.question.as_deref() == question));
+    }
+
+    /// The cards due on or before `today`, the most overdue first.
+    pub fn due(&self, today: NaiveDate) -> Vec<Card> {
+        let mut due: Vec<Card> = self
+            .cards
+            .iter()
+            .filter(|c| c.schedule.due <= today)
+            .cloned()
+            .collect();
+        due.sort_by_key(|c| c.schedule.due);
+        due
+    }
+
+    /// Records a review of a card and schedules its next one.
+    pub fn grade(
+        &mut self,
+        document: &DocumentId,
+        question: Option<&str>,
+        grade: u8,
+        today: NaiveDate,
+    ) -> Option<&Card> {
+        let card = self
+            .cards
+            .iter_mut()
+            .find(|c| c.document == *document && c.question.as_deref() == question)?;
+        card.schedule = card.schedule.next(grade, today);
+        card.history.push(Review {
+            at: Utc::now(),
+            grade,
+        });
+        Some(card)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap()
+    }
+
+    fn reviewed(grades: &[u8]) -> Schedule {
+        grades
+            .iter()
+            .fold(Schedule::new(today()), |s, &g| s.next(g, today()))
+    }
+
+    #[test]
+    fn new_cards_are_due_at_once() {
+        let schedule = Schedule::new(today());
+        assert_eq!((schedule.interval, schedule.repetitions), (0, 0));
+        assert_eq!(schedule.ease, INITIAL_EASE);
+        assert_eq!(schedule.due, today());
+    }
+
+    #[test]
+    fn intervals_grow_one_six_then_by_ease() {
+        assert_eq!(reviewed(&[4]).interval, 1);
+        assert_eq!(reviewed(&[4, 4]).interval, 6);
+        // A grade of 4 leaves the ease as it was.
+        assert_eq!(reviewed(&[4, 4, 4]).interval, 15);
+        assert_eq!(reviewed(&[4, 4, 4, 4]).interval, 38);
+        assert_eq!(reviewed(&[4, 4, 4]).repetitions, 3);
+        assert_eq!(reviewed(&[4, 4]).due, today() + Duration::days(6));
+    }
+
+    #[test]
+    fn ease_follows_the_grade() {
+        assert!((reviewed(&[5]).ease - 2.6).abs() < 1e-9);
+        assert!((reviewed(&[4]).ease - 2.5).abs() < 1e-9);
+        assert!((reviewed(&[3]).ease - 2.36).abs() < 1e-9);
+        assert!((reviewed(&[0]).ease - 1.7).abs() < 1e-9);
+        assert_eq!(reviewed(&[5, 5, 5]).interval, 16);
+    }
+
+    #[test]
+    fn lapses_start_over() {
+        let schedule = reviewed(&[5, 5, 5, 2]);
+        assert_eq!((schedule.interval, schedule.repetitions), (1, 0));
+        assert_eq!(reviewed(&[5, 5, 5, 2, 4]).interval, 1);
+        assert_eq!(reviewed(&[5, 5, 5, 2, 4, 4]).interval, 6);
+    }
+
+    #[test]
+    fn ease_has_a_floor() {
+        assert_eq!(reviewed(&[0, 0, 0, 0, 0]).ease, MIN_EASE);
+    }
+
+    #[test]
+    fn grades_above_the_maximum_count_as_the_maximum() {
+        assert_eq!(reviewed(&[9]).ease, reviewed(&[MAX_GRADE]).ease);
+    }
+
+    #[test]
+    fn cards_are_added_once_and_graded() {
+        let mut reviews = Reviews::default();
+        let doc = DocumentId::derive("doc");
+        assert!(reviews.add(&doc, None, today()));
+        assert!(!reviews.add(&doc, None, today()));
+        assert!(reviews.add(&doc, Some("Why?".into()), today()));
+
+        let card = reviews.grade(&doc, Some("Why?"), 5, today()).unwrap();
+        assert_eq!(card.history.len(), 1);
+        assert_eq!(card.schedule.due, today() + Duration::days(1));
+        assert!(reviews.grade(&doc, Some("How?"), 5, today()).is_none());
+
+        let due = reviews.due(today());
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].question, None);
+        assert_eq!(reviews.due(today() + Duration::days(1)).len(), 2);
+    }
+
+    #[test]
+    fn due_cards_come_most_overdue_first() {
+        let mut reviews = Reviews::default();
+        let (a, b) = (DocumentId::derive("a"), DocumentId::derive("b"));
+        reviews.add(&a, None, today());
+        reviews.add(&b, None, today() - Duration::days(3));
+        let due = reviews.due(today());
+        assert_eq!(due[0].document, b);
+        assert_eq!(due[1].document, a);
+    }
+
+    #[test]
+    fn cards_are_removed_by_document_or_one_at_a_time() {
+        let mut reviews = Reviews::default();
+        let doc = DocumentId::derive("doc");
+        reviews.add(&doc, None, today());
+        reviews.add(&doc, Some("Why?".into()), today());
+        reviews.drop_card(&doc, Some("Why?"));
+        assert_eq!(reviews.cards().len(), 1);
+        assert_eq!(reviews.remove(&doc).len(), 1);
+        assert!(reviews.cards().is_empty());
+    }
+}
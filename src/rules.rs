@@ -0,0 +1,134 @@
+//! Deterministic classification rules, configured in `config.toml`:
+//!
+//! ```toml
+//! [[classification.rules]]
+//! category = "machine-learning"
+//! keywords = ["neural network*", "backprop*"]
+//!
+//! [[classification.rules]]
+//! category = "work"
+//! path = "~/work/**"
+//! domain = "intranet.example.com"
+//! ```
+//!
+//! All conditions a rule sets must hold; any one of its keywords suffices.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::types::Document;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub category: String,
+    /// Words or phrases; `*` matches any run of characters within a word.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// A glob over the source path: `*` stays within a directory, `**`
+    /// crosses them and a leading `~/` is the home directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Matches web sources on this domain or any subdomain of it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+}
+
+impl Rule {
+    pub fn validate(&self) -> Result<()> {
+        if self.keywords.is_empty() && self.path.is_none() && self.domain.is_none() {
+            bail!(
+                "rule for {:?} needs keywords, a path or a domain",
+                self.category
+            );
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, doc: &Document, words: &[String]) -> bool {
+        if self.keywords.is_empty() && self.path.is_none() && self.domain.is_none() {
+            return false;
+        }
+        let source = doc.source.as_deref().unwrap_or_default();
+        let keywords = self.keywords.is_empty()
+            || self.keywords.iter().any(|k| {
+                let phrase = tokenize_pattern(k);
+                !phrase.is_empty()
+                    && words.windows(phrase.len()).any(|w| {
+                        w.iter()
+                            .zip(&phrase)
+                            .all(|(word, p)| glob(p, &chars(word), true))
+                    })
+            });
+        let path = self.path.as_ref().is_none_or(|pattern| {
+            !is_web(source) && glob(&chars(&expand_home(pattern)), &chars(source), false)
+        });
+        let domain = self.domain.as_ref().is_none_or(|domain| {
+            let domain = domain.to_ascii_lowercase();
+            host(source).is_some_and(|h| {
+                h == domain || h.strip_suffix(&domain).is_some_and(|s| s.ends_with('.'))
+            })
+        });
+        keywords && path && domain
+    }
+}
+
+/// Splits a keyword pattern like the text it is matched against, keeping
+/// `*` as part of words.
+fn tokenize_pattern(pattern: &str) -> Vec<Vec<char>> {
+    pattern
+        .split(|c: char| !c.is_alphanumeric() && c != '*')
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase().chars().collect())
+        .collect()
+}
+
+/// Matches `*` (any run, not crossing `/` unless `any_char`), `**` (any
+/// run) and `?` (one character other than `/`).
+fn glob(pattern: &[char], text: &[char], any_char: bool) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', rest @ ..] => {
+            let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+            (0..=text.len()).any(|i| glob(rest, &text[i..], any_char))
+        }
+        ['*', rest @ ..] => {
+            for i in 0..=text.len() {
+                if glob(rest, &text[i..], any_char) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' && !any_char {
+                    break;
+                }
+            }
+            false
+        }
+        ['?', rest @ ..] => {
+            matches!(text, [c, ..] if *c != '/' || any_char) && glob(rest, &text[1..], any_char)
+        }
+        [p, rest @ ..] => matches!(text, [c, ..] if c == p) && glob(rest, &text[1..], any_char),
+    }
+}
+
+fn chars(s: &str) -> Vec<char> {
+    s.chars().collect()
+}
+
+fn expand_home(pattern: &str) -> String {
+    match (pattern.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{rest}", home.trim_end_matches('/')),
+        _ => pattern.to_string(),
+    }
+}
+
+fn is_web(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+fn host(source: &str) -> Option<String> {
+    let rest = source.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit_once('@').map_or(authority, |(_, h)| h);
+    let host = host.split(':').next()?;
+    Some(host.to_ascii_lowercase())
+}
@@ -0,0 +1,44 @@
+//! The async runtime under the synchronous commands. Fetching pages,
+//! remote embeddings and language models are async, so that many requests
+//! wait on the network at once; commands wait for them with [`block_on`].
+
+use std::future::Future;
+use std::sync::OnceLock;
+
+use tokio::runtime::{Builder, Handle, Runtime};
+
+/// The runtime futures run on when no other is running, started on first
+/// use.
+fn shared() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the async runtime")
+    })
+}
+
+/// Runs `future` to completion and returns its output. It runs on the
+/// runtime of the caller, as in the server's handlers, or else on a shared
+/// one.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    match Handle::try_current() {
+        Ok(handle) => tokio::task::block_in_place(|| handle.block_on(future)),
+        Err(_) => shared().block_on(future),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_on_nests() {
+        let inner = block_on(async { block_on(async { 1 }) + 1 });
+        assert_eq!(inner, 2);
+        let blocking =
+            block_on(async { tokio::task::spawn_blocking(|| block_on(async { 3 })).await });
+        assert_eq!(blocking.unwrap(), 3);
+    }
+}
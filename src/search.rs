@@ -0,0 +1,148 @@
+//! Query execution shared by every command that ranks documents.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::index::Hit;
+use crate::kb::KnowledgeBase;
+use crate::query::Query;
+use crate::runtime;
+use crate::transform::chunk::Chunk;
+use crate::types::Document;
+
+/// Damping constant of reciprocal rank fusion; 60 is the value from the
+/// original paper and keeps a single list from dominating the fused ranking.
+const RRF_K: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// BM25 over the full-text index
+    Keyword,
+    /// Cosine similarity over document embeddings
+    Semantic,
+    /// Both, fused with reciprocal rank fusion
+    #[default]
+    Hybrid,
+}
+
+/// A document that satisfied a query.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub doc: Document,
+    pub score: f64,
+    /// The chunk of the document closest to the query, when ranked
+    /// semantically and the document was embedded in chunks.
+    pub chunk: Option<Chunk>,
+}
+
+impl Match {
+    /// The text best representing the match: its chunk, or the whole
+    /// document.
+    pub fn passage(&self) -> &str {
+        self.chunk
+            .as_ref()
+            .map_or(&self.doc.content, |c| c.text(&self.doc.content))
+    }
+}
+
+/// Ranks the documents of `kb` against `query`, best first, then passes
+/// them through the `post-search` hook. A query without positive terms
+/// returns every matching document, newest first.
+///
+/// Semantic ranking finds documents that share no words with the query, so
+/// in that mode terms only rank and never exclude documents.
+pub fn run(kb: &mut KnowledgeBase, query: &Query, mode: SearchMode) -> Result<Vec<Match>> {
+    let mut matches = rank(kb, query, mode)?;
+    kb.hooks()?.search(&query.text, &mut matches)?;
+    Ok(matches)
+}
+
+fn rank(kb: &mut KnowledgeBase, query: &Query, mode: SearchMode) -> Result<Vec<Match>> {
+    let terms_required = mode != SearchMode::Semantic;
+    if query.text.trim().is_empty() {
+        let mut matches: Vec<Match> = kb
+            .storage
+            .all()?
+            .into_iter()
+            .filter(|doc| query.matches(doc, true))
+            .map(|doc| Match {
+                doc,
+                score: 0.0,
+                chunk: None,
+            })
+            .collect();
+        matches.sort_by_key(|m| std::cmp::Reverse(m.doc.added));
+        return Ok(matches);
+    }
+
+    let vector = match mode {
+        SearchMode::Keyword => None,
+        _ => Some(runtime::block_on(kb.embedder()?.embed(&query.text))?),
+    };
+    let hits = match (mode, &vector) {
+        (SearchMode::Semantic, Some(v)) => kb.vectors.search(v),
+        (SearchMode::Hybrid, Some(v)) => {
+            reciprocal_rank_fusion(&[kb.index.search(&query.text), kb.vectors.search(v)])
+        }
+        _ => kb.index.search(&query.text),
+    };
+    let mut matches = Vec::with_capacity(hits.len());
+    for hit in hits {
+        let doc = kb.get(&hit.id)?;
+        if query.matches(&doc, terms_required) {
+            let chunk = vector
+                .as_ref()
+                .and_then(|v| kb.vectors.best_chunk(&doc.id, v))
+                .and_then(|id| doc.chunks.iter().find(|c| c.id == id))
+                .cloned();
+            matches.push(Match {
+                doc,
+                score: hit.score,
+                chunk,
+            });
+        }
+    }
+    Ok(matches)
+}
+
+/// Merges several rankings by summing `1 / (k + rank)` per document.
+pub fn reciprocal_rank_fusion(rankings: &[Vec<Hit>]) -> Vec<Hit> {
+    let mut scores = HashMap::new();
+    for ranking in rankings {
+        for (rank, hit) in ranking.iter().enumerate() {
+            *scores.entry(hit.id.clone()).or_insert(0.0) += 1.0 / (RRF_K + rank as f64 + 1.0);
+        }
+    }
+    let mut hits: Vec<Hit> = scores
+        .into_iter()
+        .map(|(id, score)| Hit { id, score })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    hits
+}
+
+/// Facet values with the number of matches carrying them, most common first.
+pub type Counts = Vec<(String, usize)>;
+
+/// Counts how many matches carry each tag and each document type.
+pub fn facets(matches: &[Match]) -> (Counts, Counts) {
+    let mut tags: HashMap<String, usize> = HashMap::new();
+    let mut types: HashMap<String, usize> = HashMap::new();
+    for m in matches {
+        for tag in &m.doc.tags {
+            *tags.entry(tag.clone()).or_default() += 1;
+        }
+        *types.entry(m.doc.kind.name().to_string()).or_default() += 1;
+    }
+    (sorted_counts(tags), sorted_counts(types))
+}
+
+fn sorted_counts(counts: HashMap<String, usize>) -> Counts {
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
@@ -0,0 +1,728 @@
+//! The HTTP API served by `ozy serve`.
+//!
+//! Every request must carry `Authorization: Bearer <token>`. Responses and
+//! request bodies are JSON; errors are `{"error": "..."}` with a matching
+//! status code.
+//!
+//! `GET /documents/{id}/attachments/{n}/thumbnail` serves a PNG preview
+//! of an image or PDF attachment.
+//!
+//! `POST /graphql` answers GraphQL queries over the same data; `GET
+//! /graphql` returns the schema.
+//!
+//! `POST /sync` is the remote end of `ozy sync`, answering the requests of
+//! [`crate::sync`]. `ozy serve --relay` serves only `/relay`, where
+//! replicas keep encrypted objects; see [`crate::relay`].
+//!
+//! The knowledge base is opened once when the server starts, so changes
+//! made meanwhile by other `ozy` commands are not seen until it restarts.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use axum::extract::{DefaultBodyLimit, Path as UrlPath, Query as UrlQuery, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::commands::graph::parse_node;
+use crate::graph::NodeId;
+use crate::graphql;
+use crate::index::snippet;
+use crate::kb::KnowledgeBase;
+use crate::relay::{self, DirStore, Store};
+use crate::search::{self, SearchMode};
+use crate::tags;
+use crate::transform::{Outcome, Pipelines};
+use crate::types::{Document, DocumentKind};
+
+/// File in the knowledge base holding the API token.
+pub const TOKEN_FILE: &str = "api-token";
+const SNIPPET_WIDTH: usize = 160;
+
+#[derive(Clone)]
+struct AppState {
+    kb: Arc<Mutex<KnowledgeBase>>,
+    schema: graphql::Schema,
+    token: Arc<str>,
+}
+
+impl AppState {
+    /// Runs `f` with the knowledge base on a thread that may block, so
+    /// that a slow search or ingest does not stall the server's other
+    /// requests.
+    async fn with_kb<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut KnowledgeBase) -> ApiResult<T> + Send + 'static,
+    ) -> ApiResult<T> {
+        let kb = self.kb.clone();
+        blocking(move || {
+            // A handler that panicked left the knowledge base as it was on
+            // disk; carry on with it.
+            let mut kb = kb.lock().unwrap_or_else(|e| e.into_inner());
+            f(&mut kb)
+        })
+        .await
+    }
+}
+
+/// Runs `f` on a thread that may block.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> ApiResult<T> + Send + 'static,
+) -> ApiResult<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| anyhow::anyhow!("request handler failed: {e}"))?
+}
+
+/// The API token of the knowledge base at `root`, created on first use.
+pub fn token(root: &Path) -> Result<String> {
+    let path = root.join(TOKEN_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        // Left empty by an interrupted first run.
+        Ok(_) => std::fs::remove_file(&path)
+            .with_context(|| format!("cannot remove {}", path.display()))?,
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("cannot read {}", path.display())),
+    }
+    let token = random_token()?;
+    // Readable only by its owner from the start, not once written.
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("cannot create {}", path.display()))?;
+    writeln!(file, "{token}").with_context(|| format!("cannot write {}", path.display()))?;
+    Ok(token)
+}
+
+/// 32 random bytes from the operating system, hex-encoded.
+pub fn random_token() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("no random numbers"))?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Serves the API on `addr` until interrupted.
+pub fn serve(kb: KnowledgeBase, addr: SocketAddr, token: String) -> Result<()> {
+    run(api(kb, token), addr)
+}
+
+fn api(kb: KnowledgeBase, token: String) -> Router {
+    let kb = Arc::new(Mutex::new(kb));
+    let state = AppState {
+        schema: graphql::schema(kb.clone()),
+        kb,
+        token: token.into(),
+    };
+    Router::new()
+        .route("/search", get(search))
+        .route("/documents", get(list_documents).post(create_document))
+        .route(
+            "/documents/{id}",
+            get(get_document)
+                .put(update_document)
+                .delete(delete_document),
+        )
+        .route("/documents/{id}/tags", get(get_tags).post(change_tags))
+        .route(
+            "/documents/{id}/attachments/{n}/thumbnail",
+            get(attachment_thumbnail),
+        )
+        .route("/tags", get(all_tags))
+        .route("/graph/neighbors/{node}", get(neighbors))
+        .route("/graph/path", get(path))
+        .route("/graphql", get(graphql_schema).post(graphql))
+        // Synced documents come with their attachments.
+        .route("/sync", post(sync).layer(DefaultBodyLimit::disable()))
+        .layer(middleware::from_fn_with_state(
+            state.token.clone(),
+            authorize,
+        ))
+        .layer(middleware::from_fn(log))
+        .with_state(state)
+}
+
+/// Serves a relay for encrypted `ozy sync`, keeping what replicas send in
+/// `dir/objects`, on `addr` until interrupted. It needs no knowledge base
+/// and cannot read what it keeps.
+pub fn serve_relay(dir: &Path, addr: SocketAddr, token: String) -> Result<()> {
+    let objects = dir.join("objects");
+    std::fs::create_dir_all(&objects)
+        .with_context(|| format!("failed to create {}", objects.display()))?;
+    let app = Router::new()
+        .route("/relay", get(relay_list))
+        .route(
+            "/relay/{name}",
+            get(relay_get)
+                .put(relay_put)
+                .layer(DefaultBodyLimit::disable()),
+        )
+        .layer(middleware::from_fn_with_state(Arc::from(token), authorize))
+        .layer(middleware::from_fn(log))
+        .with_state(Arc::new(DirStore(objects)));
+    run(app, addr)
+}
+
+fn run(app: Router, addr: SocketAddr) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("cannot listen on {addr}"))?;
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await
+            .context("server failed")
+    })
+}
+
+async fn authorize(State(token): State<Arc<str>>, request: Request, next: Next) -> Response {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match given {
+        Some(given) if same(given.trim(), &token) => next.run(request).await,
+        _ => ApiError::new(StatusCode::UNAUTHORIZED, "missing or wrong API token").into_response(),
+    }
+}
+
+async fn log(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    tracing::info!(
+        %method,
+        path,
+        status = response.status().as_u16(),
+        ms = started.elapsed().as_millis() as u64,
+        "request"
+    );
+    response
+}
+
+/// Compares in time independent of where the strings differ.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_found(e: anyhow::Error) -> Self {
+        ApiError::new(StatusCode::NOT_FOUND, format!("{e:#}"))
+    }
+
+    fn bad_request(e: anyhow::Error) -> Self {
+        ApiError::new(StatusCode::BAD_REQUEST, format!("{e:#}"))
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}"))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+#[derive(Deserialize)]
+struct SearchParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    mode: SearchMode,
+    limit: Option<usize>,
+    #[serde(default)]
+    offset: usize,
+}
+
+#[derive(Serialize)]
+struct Hit {
+    id: String,
+    title: String,
+    score: f64,
+    tags: Vec<String>,
+    snippet: String,
+}
+
+async fn search(
+    State(state): State<AppState>,
+    UrlQuery(params): UrlQuery<SearchParams>,
+) -> ApiResult<Json<Vec<Hit>>> {
+    state
+        .with_kb(move |kb| {
+            let query = kb.parse_query(&params.q).map_err(ApiError::bad_request)?;
+            let matches = search::run(kb, &query, params.mode)?;
+            let hits = matches
+                .iter()
+                .skip(params.offset)
+                .take(params.limit.unwrap_or(20))
+                .map(|m| Hit {
+                    id: m.doc.id.to_string(),
+                    title: m.doc.title.clone(),
+                    score: m.score,
+                    tags: m.doc.tags.clone(),
+                    snippet: snippet(m.passage(), &query.text, SNIPPET_WIDTH),
+                })
+                .collect();
+            Ok(Json(hits))
+        })
+        .await
+}
+
+#[derive(Serialize)]
+struct Summary {
+    id: String,
+    title: String,
+    kind: DocumentKind,
+    added: chrono::DateTime<Utc>,
+    tags: Vec<String>,
+}
+
+impl From<&Document> for Summary {
+    fn from(doc: &Document) -> Self {
+        Summary {
+            id: doc.id.to_string(),
+            title: doc.title.clone(),
+            kind: doc.kind,
+            added: doc.added,
+            tags: doc.tags.clone(),
+        }
+    }
+}
+
+/// Documents matching the optional `q` query, newest first.
+async fn list_documents(
+    State(state): State<AppState>,
+    UrlQuery(params): UrlQuery<SearchParams>,
+) -> ApiResult<Json<Vec<Summary>>> {
+    state
+        .with_kb(move |kb| {
+            let query = kb.parse_query(&params.q).map_err(ApiError::bad_request)?;
+            let mut docs: Vec<Document> = kb
+                .storage
+                .all()?
+                .into_iter()
+                .filter(|d| query.matches(d, true))
+                .collect();
+            docs.sort_by_key(|d| std::cmp::Reverse(d.added));
+            let docs = docs
+                .iter()
+                .skip(params.offset)
+                .take(params.limit.unwrap_or(usize::MAX))
+                .map(Summary::from)
+                .collect();
+            Ok(Json(docs))
+        })
+        .await
+}
+
+async fn get_document(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+) -> ApiResult<Json<Document>> {
+    state
+        .with_kb(move |kb| {
+            let id = kb.resolve(&id).map_err(ApiError::not_found)?;
+            Ok(Json(kb.get(&id)?))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct NewDocument {
+    title: String,
+    content: String,
+    #[serde(default = "default_kind")]
+    kind: DocumentKind,
+    source: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn default_kind() -> DocumentKind {
+    DocumentKind::Markdown
+}
+
+/// Adds a document through the ingest pipeline, as `ozy add` would.
+async fn create_document(
+    State(state): State<AppState>,
+    Json(new): Json<NewDocument>,
+) -> ApiResult<(StatusCode, Json<Document>)> {
+    state
+        .with_kb(move |kb| {
+            let key = new
+                .source
+                .clone()
+                .unwrap_or_else(|| format!("api:{}:{}", new.title, Utc::now().to_rfc3339()));
+            let id = crate::types::DocumentId::derive(&key);
+            if kb.tombstones.get(&id).is_some() {
+                return Err(ApiError::new(
+                    StatusCode::CONFLICT,
+                    format!("{id} was removed; purge its tombstone to add it again"),
+                ));
+            }
+            let tags = new
+                .tags
+                .iter()
+                .map(|t| tags::normalize(t))
+                .collect::<Result<Vec<_>>>()
+                .map_err(ApiError::bad_request)?;
+            let mut doc = Document::new(id, new.title, new.kind, new.content, new.source);
+            doc.tags = tags;
+            let pipelines = Pipelines::from_config(&kb.config.pipeline, kb.plugins()?)?;
+            if let Outcome::Skip(reason) = pipelines.run(&mut doc, kb)? {
+                return Err(ApiError::new(StatusCode::CONFLICT, reason));
+            }
+            kb.insert(&doc)?;
+            kb.commit()?;
+            Ok((StatusCode::CREATED, Json(doc)))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct DocumentUpdate {
+    title: Option<String>,
+    content: Option<String>,
+}
+
+/// Replaces the title or content of a document and runs it through the
+/// pipeline again.
+async fn update_document(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+    Json(update): Json<DocumentUpdate>,
+) -> ApiResult<Json<Document>> {
+    state
+        .with_kb(move |kb| {
+            let id = kb.resolve(&id).map_err(ApiError::not_found)?;
+            let mut doc = kb.get(&id)?;
+            if let Some(title) = update.title {
+                doc.title = title;
+            }
+            if let Some(content) = update.content {
+                doc.content = content;
+            }
+            let pipelines = Pipelines::from_config(&kb.config.pipeline, kb.plugins()?)?;
+            if let Outcome::Skip(reason) = pipelines.run(&mut doc, kb)? {
+                return Err(ApiError::new(StatusCode::CONFLICT, reason));
+            }
+            kb.insert(&doc)?;
+            kb.commit()?;
+            Ok(Json(doc))
+        })
+        .await
+}
+
+/// Removes a document, leaving a tombstone as `ozy rm` does.
+async fn delete_document(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+) -> ApiResult<StatusCode> {
+    state
+        .with_kb(move |kb| {
+            let id = kb.resolve(&id).map_err(ApiError::not_found)?;
+            kb.remove(&id, false)?;
+            kb.commit()?;
+            Ok(StatusCode::NO_CONTENT)
+        })
+        .await
+}
+
+async fn get_tags(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+) -> ApiResult<Json<Vec<String>>> {
+    state
+        .with_kb(move |kb| {
+            let id = kb.resolve(&id).map_err(ApiError::not_found)?;
+            Ok(Json(kb.get(&id)?.tags))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct TagChange {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+async fn change_tags(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+    Json(change): Json<TagChange>,
+) -> ApiResult<Json<Vec<String>>> {
+    let normalize_all = |tags: &[String]| {
+        tags.iter()
+            .map(|t| tags::normalize(t))
+            .collect::<Result<Vec<_>>>()
+            .map_err(ApiError::bad_request)
+    };
+    let (add, remove) = (normalize_all(&change.add)?, normalize_all(&change.remove)?);
+    state
+        .with_kb(move |kb| {
+            let id = kb.resolve(&id).map_err(ApiError::not_found)?;
+            let now = kb.retag(&id, &add, &remove)?;
+            kb.commit()?;
+            Ok(Json(now))
+        })
+        .await
+}
+
+/// The PNG preview of a document's `n`th attachment, counted from 0.
+async fn attachment_thumbnail(
+    State(state): State<AppState>,
+    UrlPath((id, n)): UrlPath<(String, usize)>,
+) -> ApiResult<Response> {
+    state
+        .with_kb(move |kb| {
+            let id = kb.resolve(&id).map_err(ApiError::not_found)?;
+            let doc = kb.get(&id)?;
+            let attachment = doc.attachments.get(n).ok_or_else(|| {
+                ApiError::new(StatusCode::NOT_FOUND, format!("{id} has no attachment {n}"))
+            })?;
+            let no_thumbnail = || {
+                ApiError::new(
+                    StatusCode::NOT_FOUND,
+                    format!("{} has no thumbnail", attachment.name),
+                )
+            };
+            let hash = attachment.thumbnail.as_deref().ok_or_else(no_thumbnail)?;
+            let png = kb.blobs.get(hash)?.ok_or_else(no_thumbnail)?;
+            Ok(([(header::CONTENT_TYPE, "image/png")], png).into_response())
+        })
+        .await
+}
+
+async fn all_tags(
+    State(state): State<AppState>,
+) -> ApiResult<Json<std::collections::BTreeMap<String, usize>>> {
+    state
+        .with_kb(|kb| Ok(Json(tags::counts(kb.storage.as_ref())?)))
+        .await
+}
+
+#[derive(Serialize)]
+struct GraphNeighbor {
+    node: NodeId,
+    label: String,
+    edge: String,
+    outgoing: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    inferred: bool,
+}
+
+/// Neighbours of a node written as on the command line: a document id,
+/// `#tag` or `@kind:name`.
+async fn neighbors(
+    State(state): State<AppState>,
+    UrlPath(node): UrlPath<String>,
+) -> ApiResult<Json<Vec<GraphNeighbor>>> {
+    state
+        .with_kb(move |kb| {
+            let node = parse_node(kb, &node).map_err(ApiError::not_found)?;
+            let neighbors = kb
+                .graph
+                .neighbors(&node)
+                .into_iter()
+                .map(|n| GraphNeighbor {
+                    node: n.node.clone(),
+                    label: kb
+                        .graph
+                        .node(n.node)
+                        .map_or_else(String::new, |n| n.label.clone()),
+                    edge: n.edge.kind.name().to_string(),
+                    outgoing: n.outgoing,
+                    confidence: n.edge.confidence,
+                    inferred: n.edge.inferred,
+                })
+                .collect();
+            Ok(Json(neighbors))
+        })
+        .await
+}
+
+#[derive(Deserialize)]
+struct PathParams {
+    from: String,
+    to: String,
+}
+
+/// A shortest path between two nodes, or `null` when they are not
+/// connected.
+async fn path(
+    State(state): State<AppState>,
+    UrlQuery(params): UrlQuery<PathParams>,
+) -> ApiResult<Json<Option<Vec<NodeId>>>> {
+    state
+        .with_kb(move |kb| {
+            let from = parse_node(kb, &params.from).map_err(ApiError::not_found)?;
+            let to = parse_node(kb, &params.to).map_err(ApiError::not_found)?;
+            Ok(Json(kb.graph.path_between(&from, &to)))
+        })
+        .await
+}
+
+async fn graphql(
+    State(state): State<AppState>,
+    Json(request): Json<async_graphql::Request>,
+) -> ApiResult<Json<async_graphql::Response>> {
+    // Resolvers lock the knowledge base as they go, so the whole query
+    // runs where blocking is allowed.
+    let runtime = tokio::runtime::Handle::current();
+    blocking(move || Ok(Json(runtime.block_on(state.schema.execute(request))))).await
+}
+
+/// Answers a request of `ozy sync` on another machine.
+async fn sync(
+    State(state): State<AppState>,
+    Json(request): Json<crate::sync::Request>,
+) -> ApiResult<Json<crate::sync::Response>> {
+    state
+        .with_kb(move |kb| Ok(Json(crate::sync::handle(kb, request)?)))
+        .await
+}
+
+async fn relay_list(State(store): State<Arc<DirStore>>) -> ApiResult<Json<Vec<String>>> {
+    blocking(move || Ok(Json(store.list()?))).await
+}
+
+async fn relay_get(
+    State(store): State<Arc<DirStore>>,
+    UrlPath(name): UrlPath<String>,
+) -> ApiResult<Vec<u8>> {
+    if !relay::valid_name(&name) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid object name",
+        ));
+    }
+    blocking(move || {
+        store
+            .get(&name)?
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("no object {name}")))
+    })
+    .await
+}
+
+/// Stores an object; with `If-Match: "<sha256>"` only over that object,
+/// and with `If-None-Match: *` only if there is none.
+async fn relay_put(
+    State(store): State<Arc<DirStore>>,
+    UrlPath(name): UrlPath<String>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> ApiResult<StatusCode> {
+    if !relay::valid_name(&name) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "invalid object name",
+        ));
+    }
+    let value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let current = match (value(header::IF_MATCH), value(header::IF_NONE_MATCH)) {
+        (Some(hash), _) => Some(Some(hash.trim().trim_matches('"').to_string())),
+        (None, Some("*")) => Some(None),
+        _ => None,
+    };
+    blocking(move || {
+        let Some(current) = current else {
+            store.put(&name, &body)?;
+            return Ok(StatusCode::NO_CONTENT);
+        };
+        match store.replace(&name, current.as_deref(), &body)? {
+            true => Ok(StatusCode::NO_CONTENT),
+            false => Err(ApiError::new(
+                StatusCode::PRECONDITION_FAILED,
+                format!("{name} has changed"),
+            )),
+        }
+    })
+    .await
+}
+
+async fn graphql_schema(State(state): State<AppState>) -> String {
+    state.schema.sdl()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kb::scratch::Scratch;
+    use crate::runtime;
+
+    /// The status of `GET path` on `app`, with `token` if there is one.
+    fn status(app: Router, path: &str, token: Option<&str>) -> u16 {
+        runtime::block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            let mut request = reqwest::Client::new().get(format!("http://{addr}{path}"));
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            request.send().await.unwrap().status().as_u16()
+        })
+    }
+
+    #[test]
+    fn the_api_answers_only_with_its_token() {
+        let scratch = Scratch::new("server-token");
+        let api = || api(KnowledgeBase::open(&scratch.dir).unwrap(), "secret".into());
+        assert_eq!(status(api(), "/tags", None), 401);
+        assert_eq!(status(api(), "/tags", Some("secreT")), 401);
+        assert_eq!(status(api(), "/tags", Some("secret2")), 401);
+        assert_eq!(status(api(), "/documents/abc", Some("")), 401);
+        assert_eq!(status(api(), "/tags", Some("secret")), 200);
+    }
+}
@@ -0,0 +1,193 @@
+//! Importing SKOS vocabularies, in Turtle or RDF/XML, as an [`Ontology`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use rio_api::model::{Literal, Subject, Term, Triple};
+use rio_api::parser::TriplesParser;
+use rio_turtle::{TurtleError, TurtleParser};
+use rio_xml::{RdfXmlError, RdfXmlParser};
+
+use crate::ontology::{Concept, Ontology};
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const SKOS: &str = "http://www.w3.org/2004/02/skos/core#";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Turtle,
+    RdfXml,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ttl" | "turtle" => Some(Format::Turtle),
+            "rdf" | "xml" | "owl" => Some(Format::RdfXml),
+            _ => None,
+        }
+    }
+}
+
+/// What the vocabulary says about one resource.
+#[derive(Default)]
+struct Resource {
+    is_concept: bool,
+    pref_labels: Vec<(String, String)>,
+    alt_labels: Vec<(String, String)>,
+    definitions: Vec<(String, String)>,
+    broader: BTreeSet<String>,
+}
+
+/// The outcome of an import: the concepts read and how many were dropped
+/// because their labels clashed.
+pub struct Import {
+    pub ontology: Ontology,
+    pub skipped: usize,
+}
+
+/// Reads the `skos:Concept`s of a vocabulary, preferring labels in `lang`.
+/// `skos:broader` and `skos:narrower` become parent relations, alternative
+/// and hidden labels become synonyms.
+pub fn import(path: &Path, format: Format, lang: &str) -> Result<Import> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let reader = BufReader::new(file);
+    let mut resources: BTreeMap<String, Resource> = BTreeMap::new();
+    let mut on_triple = |t: Triple| {
+        collect(&mut resources, t);
+    };
+    let parsed = match format {
+        Format::Turtle => TurtleParser::new(reader, None)
+            .parse_all(&mut |t| {
+                on_triple(t);
+                Ok::<_, TurtleError>(())
+            })
+            .map_err(|e| e.to_string()),
+        Format::RdfXml => RdfXmlParser::new(reader, None)
+            .parse_all(&mut |t| {
+                on_triple(t);
+                Ok::<_, RdfXmlError>(())
+            })
+            .map_err(|e| e.to_string()),
+    };
+    if let Err(e) = parsed {
+        bail!("{}: {e}", path.display());
+    }
+
+    let names: BTreeMap<&str, String> = resources
+        .iter()
+        .filter(|(_, r)| r.is_concept || !r.pref_labels.is_empty())
+        .map(|(key, r)| {
+            let name = pick(&r.pref_labels, lang).unwrap_or_else(|| local_name(key).to_string());
+            (key.as_str(), name)
+        })
+        .collect();
+
+    let mut import = Import {
+        ontology: Ontology::default(),
+        skipped: 0,
+    };
+    for (key, name) in &names {
+        let resource = &resources[*key];
+        let concept = Concept {
+            parents: resource
+                .broader
+                .iter()
+                .filter_map(|b| names.get(b.as_str()).cloned())
+                .collect(),
+            synonyms: resource
+                .alt_labels
+                .iter()
+                .filter(|(l, _)| l.is_empty() || l == lang)
+                .map(|(_, v)| v.clone())
+                .collect(),
+            description: pick(&resource.definitions, lang),
+        };
+        if !import.ontology.insert(name.clone(), concept) {
+            import.skipped += 1;
+        }
+    }
+    import.ontology.prune_parents();
+    Ok(import)
+}
+
+fn collect(resources: &mut BTreeMap<String, Resource>, t: Triple) {
+    let subject = match t.subject {
+        Subject::NamedNode(n) => n.iri.to_string(),
+        Subject::BlankNode(b) => format!("_:{}", b.id),
+        Subject::Triple(_) => return,
+    };
+    let object = match t.object {
+        Term::NamedNode(n) => Object::Node(n.iri.to_string()),
+        Term::BlankNode(b) => Object::Node(format!("_:{}", b.id)),
+        Term::Literal(Literal::Simple { value }) | Term::Literal(Literal::Typed { value, .. }) => {
+            Object::Text(String::new(), value.to_string())
+        }
+        Term::Literal(Literal::LanguageTaggedString { value, language }) => {
+            Object::Text(language.to_ascii_lowercase(), value.to_string())
+        }
+        Term::Triple(_) => return,
+    };
+    let predicate = t.predicate.iri;
+    match (predicate.strip_prefix(SKOS), object) {
+        (None, Object::Node(class))
+            if predicate == RDF_TYPE && class == format!("{SKOS}Concept") =>
+        {
+            resources.entry(subject).or_default().is_concept = true;
+        }
+        (Some("prefLabel"), Object::Text(l, v)) => {
+            resources
+                .entry(subject)
+                .or_default()
+                .pref_labels
+                .push((l, v));
+        }
+        (Some("altLabel" | "hiddenLabel"), Object::Text(l, v)) => {
+            resources
+                .entry(subject)
+                .or_default()
+                .alt_labels
+                .push((l, v));
+        }
+        (Some("definition"), Object::Text(l, v)) => {
+            resources
+                .entry(subject)
+                .or_default()
+                .definitions
+                .push((l, v));
+        }
+        (Some("broader"), Object::Node(parent)) => {
+            resources.entry(subject).or_default().broader.insert(parent);
+        }
+        (Some("narrower"), Object::Node(child)) => {
+            resources.entry(child).or_default().broader.insert(subject);
+        }
+        _ => {}
+    }
+}
+
+enum Object {
+    Node(String),
+    /// A literal and its language tag, empty when it has none.
+    Text(String, String),
+}
+
+/// The value in `lang`, else one without a language, else any.
+fn pick(values: &[(String, String)], lang: &str) -> Option<String> {
+    values
+        .iter()
+        .find(|(l, _)| l == lang)
+        .or_else(|| values.iter().find(|(l, _)| l.is_empty()))
+        .or_else(|| values.first())
+        .map(|(_, v)| v.trim().to_string())
+}
+
+/// The part of an IRI after its last `#` or `/`.
+fn local_name(iri: &str) -> &str {
+    iri.rsplit(['#', '/'])
+        .find(|s| !s.is_empty())
+        .unwrap_or(iri)
+}
@@ -0,0 +1,363 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+
+use crate::error::OzymandiasError;
+use crate::types::{Document, DocumentId};
+
+/// Persistence backend for documents.
+pub trait Storage: Send + Sync {
+    fn get(&self, id: &DocumentId) -> Result<Option<Document>>;
+    fn put(&mut self, doc: &Document) -> Result<()>;
+    fn delete(&mut self, id: &DocumentId) -> Result<bool>;
+    fn ids(&self) -> Result<Vec<DocumentId>>;
+
+    fn all(&self) -> Result<Vec<Document>> {
+        let mut docs = Vec::new();
+        for id in self.ids()? {
+            if let Some(doc) = self.get(&id)? {
+                docs.push(doc);
+            }
+        }
+        Ok(docs)
+    }
+
+    /// When a document was last written, if the backend knows.
+    fn modified(&self, _id: &DocumentId) -> Result<Option<DateTime<Utc>>> {
+        Ok(None)
+    }
+
+    /// Saves anything the backend holds in memory, such as checksums.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes held back instead of made, as by [`DryRun`]: each document
+    /// that would be stored, or `None` for one that would be deleted.
+    fn held_back(&self) -> Vec<(&DocumentId, Option<&Document>)> {
+        Vec::new()
+    }
+}
+
+/// Keeps writes to another storage in memory instead of passing them on,
+/// for `--dry-run`. Reads see the held back writes, so commands behave as
+/// they would for real.
+pub struct DryRun<S> {
+    inner: S,
+    held: BTreeMap<DocumentId, Option<Document>>,
+}
+
+impl<S: Storage> DryRun<S> {
+    pub fn new(inner: S) -> Self {
+        DryRun {
+            inner,
+            held: BTreeMap::new(),
+        }
+    }
+}
+
+impl<S: Storage> Storage for DryRun<S> {
+    fn get(&self, id: &DocumentId) -> Result<Option<Document>> {
+        match self.held.get(id) {
+            Some(held) => Ok(held.clone()),
+            None => self.inner.get(id),
+        }
+    }
+
+    fn put(&mut self, doc: &Document) -> Result<()> {
+        self.held.insert(doc.id.clone(), Some(doc.clone()));
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &DocumentId) -> Result<bool> {
+        let existed = self.get(id)?.is_some();
+        self.held.insert(id.clone(), None);
+        Ok(existed)
+    }
+
+    fn ids(&self) -> Result<Vec<DocumentId>> {
+        let mut ids: BTreeSet<DocumentId> = self.inner.ids()?.into_iter().collect();
+        for (id, held) in &self.held {
+            match held {
+                Some(_) => ids.insert(id.clone()),
+                None => ids.remove(id),
+            };
+        }
+        Ok(ids.into_iter().collect())
+    }
+
+    fn modified(&self, id: &DocumentId) -> Result<Option<DateTime<Utc>>> {
+        match self.held.contains_key(id) {
+            true => Ok(Some(Utc::now())),
+            false => self.inner.modified(id),
+        }
+    }
+
+    fn held_back(&self) -> Vec<(&DocumentId, Option<&Document>)> {
+        self.held
+            .iter()
+            .map(|(id, doc)| (id, doc.as_ref()))
+            .collect()
+    }
+}
+
+/// File in the knowledge base holding the checksums of document files.
+pub const CHECKSUMS_FILE: &str = "checksums.json";
+
+/// Stores each document as a JSON file under `documents/`, and the
+/// SHA-256 digest of each file in [`CHECKSUMS_FILE`] so that `ozy verify`
+/// can tell when one was damaged.
+pub struct FsStorage {
+    dir: PathBuf,
+    /// Checksums changed since they were last saved; `None` for deleted
+    /// documents.
+    changed: BTreeMap<DocumentId, Option<String>>,
+}
+
+impl FsStorage {
+    pub fn open(root: &Path) -> Result<Self> {
+        let dir = root.join("documents");
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(FsStorage {
+            dir,
+            changed: BTreeMap::new(),
+        })
+    }
+
+    fn path(&self, id: &DocumentId) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// Saves the checksums of the documents written since the last save.
+    /// They are merged into the file as it is now, so that other `ozy`
+    /// processes writing other documents meanwhile do not lose theirs.
+    fn save_checksums(&mut self) -> Result<()> {
+        if self.changed.is_empty() {
+            return Ok(());
+        }
+        let path = self.dir.with_file_name(CHECKSUMS_FILE);
+        let mut checksums: BTreeMap<DocumentId, String> = read_json_or_default(&path)?;
+        for (id, checksum) in std::mem::take(&mut self.changed) {
+            match checksum {
+                Some(checksum) => checksums.insert(id, checksum),
+                None => checksums.remove(&id),
+            };
+        }
+        write_json(&path, &checksums)
+    }
+}
+
+impl Drop for FsStorage {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_checksums() {
+            tracing::warn!("failed to save document checksums: {e:#}");
+        }
+    }
+}
+
+/// The checksums of the document files of the knowledge base in `root`.
+pub fn checksums(root: &Path) -> Result<BTreeMap<DocumentId, String>> {
+    read_json_or_default(&root.join(CHECKSUMS_FILE))
+}
+
+/// The SHA-256 digest of `bytes`, in hex.
+pub fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Whether `s` is a hash [`sha256`] could have made, and so safe to name a
+/// blob after.
+pub fn is_hash(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+impl Storage for FsStorage {
+    fn get(&self, id: &DocumentId) -> Result<Option<Document>> {
+        let path = self.path(id);
+        match fs::read_to_string(&path) {
+            Ok(raw) => Ok(Some(serde_json::from_str(&raw).with_context(|| {
+                OzymandiasError::StorageCorrupt(format!("corrupt document {}", path.display()))
+            })?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    fn put(&mut self, doc: &Document) -> Result<()> {
+        let bytes = serde_json::to_vec_pretty(doc)?;
+        write_atomic(&self.path(&doc.id), &bytes)?;
+        self.changed.insert(doc.id.clone(), Some(sha256(&bytes)));
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &DocumentId) -> Result<bool> {
+        self.changed.insert(id.clone(), None);
+        match fs::remove_file(self.path(id)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.save_checksums()
+    }
+
+    fn modified(&self, id: &DocumentId) -> Result<Option<DateTime<Utc>>> {
+        match fs::metadata(self.path(id)).and_then(|m| m.modified()) {
+            Ok(time) => Ok(Some(time.into())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn ids(&self) -> Result<Vec<DocumentId>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    ids.push(DocumentId(stem.to_string()));
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+}
+
+/// Drops the checksums of documents `keep` does not accept, returning how
+/// many.
+pub fn compact_checksums(root: &Path, mut keep: impl FnMut(&DocumentId) -> bool) -> Result<usize> {
+    let mut checksums = checksums(root)?;
+    let before = checksums.len();
+    checksums.retain(|id, _| keep(id));
+    let dropped = before - checksums.len();
+    if dropped > 0 && !crate::kb::dry_run() {
+        write_json(&root.join(CHECKSUMS_FILE), &checksums)?;
+    }
+    Ok(dropped)
+}
+
+/// Moves the file of a document that cannot be read into `corrupt/`, out
+/// of the way of everything that reads all documents, and returns where
+/// it went.
+pub fn quarantine(root: &Path, id: &DocumentId) -> Result<PathBuf> {
+    let from = root.join("documents").join(format!("{id}.json"));
+    let dir = root.join("corrupt");
+    let to = dir.join(format!("{id}.json"));
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    fs::rename(&from, &to).with_context(|| format!("failed to move {}", from.display()))?;
+    let mut checksums = checksums(root)?;
+    if checksums.remove(id).is_some() {
+        write_json(&root.join(CHECKSUMS_FILE), &checksums)?;
+    }
+    Ok(to)
+}
+
+/// Content-addressed storage for raw bytes, such as page snapshots, kept
+/// under `blobs/` and named by their SHA-256 digest.
+pub struct BlobStore {
+    dir: PathBuf,
+    /// Set for `--dry-run`, where blobs are hashed but not written.
+    pub dry_run: bool,
+}
+
+impl BlobStore {
+    pub fn open(root: &Path) -> Result<Self> {
+        let dir = root.join("blobs");
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(BlobStore {
+            dir,
+            dry_run: false,
+        })
+    }
+
+    pub fn path(&self, hash: &str) -> PathBuf {
+        self.dir.join(hash)
+    }
+
+    /// Stores `bytes` unless an identical blob exists and returns its hash.
+    pub fn put(&self, bytes: &[u8]) -> Result<String> {
+        let hash = sha256(bytes);
+        let path = self.path(&hash);
+        if !self.dry_run && !path.exists() {
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, bytes).with_context(|| format!("failed to write {}", tmp.display()))?;
+            fs::rename(&tmp, &path)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        Ok(hash)
+    }
+
+    /// Hashes of all stored blobs.
+    pub fn hashes(&self) -> Result<Vec<String>> {
+        Ok(self.names()?.into_iter().filter(|n| is_hash(n)).collect())
+    }
+
+    /// Names of the files under `blobs/` that are not blobs, such as the
+    /// leftovers of interrupted writes.
+    pub fn leftovers(&self) -> Result<Vec<String>> {
+        Ok(self.names()?.into_iter().filter(|n| !is_hash(n)).collect())
+    }
+
+    fn names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn remove(&self, hash: &str) -> Result<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        let path = self.path(hash);
+        fs::remove_file(&path).with_context(|| format!("failed to remove {}", path.display()))
+    }
+
+    pub fn get(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(hash);
+        match fs::read(&path) {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+}
+
+/// Writes `value` as pretty JSON through a temporary file so readers never
+/// observe a half-written file.
+pub fn write_json<T: serde::Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(value)?)
+}
+
+/// Writes `bytes` through a temporary file, as [`write_json`] does.
+fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes).with_context(|| format!("failed to write {}", tmp.display()))?;
+    fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Reads a JSON file, returning `T::default()` when it does not exist yet.
+pub fn read_json_or_default<T: serde::de::DeserializeOwned + Default>(path: &Path) -> Result<T> {
+    match fs::read_to_string(path) {
+        Ok(raw) => serde_json::from_str(&raw).with_context(|| {
+            OzymandiasError::StorageCorrupt(format!("corrupt {}", path.display()))
+        }),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(T::default()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
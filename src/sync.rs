@@ -0,0 +1,972 @@
+//! Two-way sync between knowledge bases, such as those on a laptop and a
+//! desktop, for `ozy sync`.
+//!
+//! Each knowledge base is a replica with a random name, and counts its own
+//! changes. A document's version vector holds, per replica, the latest of
+//! its changes the document has seen. When one vector has seen everything
+//! the other has, its version replaces the other; when each has seen
+//! changes the other has not, the document was changed on both sides and
+//! the [`MergePolicy`] decides. Changes are noticed when a sync starts, by
+//! comparing each document with the hash recorded the last time.
+//!
+//! The remote end answers [`Request`]s with [`handle`]: in-process for a
+//! directory, through `ozy sync --serve` over SSH, or at `POST /sync` of
+//! `ozy serve`.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::error::OzymandiasError;
+use crate::fingerprint::content_hash;
+use crate::kb::{self, KnowledgeBase};
+use crate::ml::providers::http;
+use crate::runtime;
+use crate::storage::{is_hash, read_json_or_default, sha256, write_json};
+use crate::types::{Document, DocumentId};
+
+/// File in the knowledge base holding the replica's name and the version
+/// of every document.
+pub const STATE_FILE: &str = "sync.json";
+/// File in the knowledge base locked while a sync reads and writes the
+/// state, so that two at once cannot lose each other's versions.
+const LOCK_FILE: &str = "sync.lock";
+/// How long a sync waits for another one of the same knowledge base.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Metadata key of a conflicting version kept as a copy, holding the id
+/// of the document it is a version of.
+pub const CONFLICT_KEY: &str = "conflict_of";
+
+/// What to keep of a document changed on both sides since they last
+/// synced.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergePolicy {
+    /// The version changed last
+    #[default]
+    Newest,
+    /// This knowledge base's version
+    Local,
+    /// The remote's version
+    Remote,
+    /// This knowledge base's version, with the remote's as a copy beside it
+    Both,
+}
+
+/// The `[sync]` config section.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub policy: MergePolicy,
+    /// Remotes by name, for `ozy sync <name>`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub remotes: BTreeMap<String, RemoteConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// `ssh://host/path`, `host:path`, the URL of `ozy serve` or a
+    /// directory.
+    pub url: String,
+    /// Environment variable holding the API token, for `ozy serve`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+    /// Environment variable holding the passphrase of a relay (default:
+    /// `OZY_SYNC_PASSPHRASE`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passphrase_env: Option<String>,
+}
+
+/// A document as one replica has it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Version {
+    /// Changes seen, by replica.
+    pub vector: BTreeMap<String, u64>,
+    /// Hash of the stored document; `None` once it is removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// When the latest change was made.
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Order {
+    Same,
+    Newer,
+    Older,
+    Concurrent,
+}
+
+/// How version vector `a` relates to `b`.
+pub(crate) fn compare(a: &BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> Order {
+    let count = |v: &BTreeMap<String, u64>, r: &String| v.get(r).copied().unwrap_or(0);
+    let (mut ahead, mut behind) = (false, false);
+    for replica in a.keys().chain(b.keys()) {
+        ahead |= count(a, replica) > count(b, replica);
+        behind |= count(a, replica) < count(b, replica);
+    }
+    match (ahead, behind) {
+        (false, false) => Order::Same,
+        (true, false) => Order::Newer,
+        (false, true) => Order::Older,
+        (true, true) => Order::Concurrent,
+    }
+}
+
+/// A vector that has seen everything both `a` and `b` have.
+fn merged(a: &BTreeMap<String, u64>, b: &BTreeMap<String, u64>) -> BTreeMap<String, u64> {
+    let mut out = a.clone();
+    for (replica, count) in b {
+        let entry = out.entry(replica.clone()).or_default();
+        *entry = (*entry).max(*count);
+    }
+    out
+}
+
+fn hash(doc: &Document) -> Result<String> {
+    Ok(content_hash(&serde_json::to_string(doc)?))
+}
+
+/// The replica's name and the versions of its documents, kept in
+/// `sync.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    pub replica: String,
+    pub versions: BTreeMap<DocumentId, Version>,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Held until the state is dropped; `None` in a dry run, which writes
+    /// nothing.
+    #[serde(skip)]
+    lock: Option<File>,
+}
+
+impl SyncState {
+    /// Reads the state of the knowledge base at `root`, naming the
+    /// replica on first use. Another sync of the same knowledge base waits
+    /// until this state is dropped.
+    pub fn open(root: &Path) -> Result<Self> {
+        let lock = match kb::dry_run() {
+            true => None,
+            false => Some(lock(root)?),
+        };
+        let path = root.join(STATE_FILE);
+        let mut state: SyncState = read_json_or_default(&path)?;
+        state.lock = lock;
+        if state.replica.is_empty() {
+            state.replica = crate::server::random_token()?[..12].to_string();
+        }
+        state.path = path;
+        Ok(state)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if kb::dry_run() {
+            return Ok(());
+        }
+        write_json(&self.path, self)
+    }
+
+    /// Counts a change of this replica to a document, `None` for its
+    /// removal, made at `modified`.
+    fn bump(&mut self, id: &DocumentId, hash: Option<String>, modified: DateTime<Utc>) {
+        let version = self.versions.entry(id.clone()).or_insert_with(|| Version {
+            vector: BTreeMap::new(),
+            hash: None,
+            modified,
+        });
+        *version.vector.entry(self.replica.clone()).or_default() += 1;
+        version.hash = hash;
+        version.modified = modified;
+    }
+
+    /// Records the documents added, changed and removed since the last
+    /// sync as changes of this replica, returning how many there were.
+    pub fn refresh(&mut self, kb: &KnowledgeBase) -> Result<usize> {
+        let mut changed = 0;
+        let mut present = HashSet::new();
+        for doc in kb.storage.all()? {
+            let hash = hash(&doc)?;
+            if self.versions.get(&doc.id).and_then(|v| v.hash.as_ref()) != Some(&hash) {
+                let modified = kb.storage.modified(&doc.id)?.unwrap_or_else(Utc::now);
+                self.bump(&doc.id, Some(hash), modified);
+                changed += 1;
+            }
+            present.insert(doc.id);
+        }
+        let removed: Vec<DocumentId> = self
+            .versions
+            .iter()
+            .filter(|(id, v)| v.hash.is_some() && !present.contains(*id))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in removed {
+            let removed = kb.tombstones.get(&id).map_or_else(Utc::now, |t| t.deleted);
+            self.bump(&id, None, removed);
+            changed += 1;
+        }
+        Ok(changed)
+    }
+}
+
+/// Locks the sync state of the knowledge base at `root`, waiting up to
+/// [`LOCK_TIMEOUT`] for another sync to finish.
+fn lock(root: &Path) -> Result<File> {
+    let path = root.join(LOCK_FILE);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    let started = Instant::now();
+    loop {
+        match file.try_lock() {
+            Ok(()) => return Ok(file),
+            Err(TryLockError::WouldBlock) if started.elapsed() < LOCK_TIMEOUT => {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(TryLockError::WouldBlock) => {
+                bail!("another sync of {} is still running", root.display())
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("failed to lock {}", path.display()))
+            }
+        }
+    }
+}
+
+/// A version of a document sent between replicas, with the document
+/// unless it was removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: DocumentId,
+    pub version: Version,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<Document>,
+}
+
+/// A blob, base64-encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Blob {
+    pub hash: String,
+    pub data: String,
+}
+
+/// What one replica asks of another.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// The version of every document, after noticing changes.
+    Manifest,
+    /// The current versions of these documents.
+    Fetch {
+        ids: Vec<DocumentId>,
+    },
+    Blobs {
+        hashes: Vec<String>,
+    },
+    /// Store these versions where they are newer than the replica's own.
+    Apply {
+        entries: Vec<Entry>,
+        blobs: Vec<Blob>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Manifest {
+        replica: String,
+        versions: BTreeMap<DocumentId, Version>,
+        /// Hashes of the blobs the replica has.
+        blobs: Vec<String>,
+    },
+    Entries {
+        entries: Vec<Entry>,
+    },
+    Blobs {
+        blobs: Vec<Blob>,
+    },
+    /// How many documents changed.
+    Applied {
+        documents: usize,
+    },
+}
+
+/// Fails unless every id is a [`DocumentId`] this program could have
+/// made, since ids from another replica name files here.
+fn check_ids<'a>(ids: impl IntoIterator<Item = &'a DocumentId>) -> Result<()> {
+    for id in ids {
+        if !id.is_valid() {
+            bail!(OzymandiasError::NetworkError(format!(
+                "the remote sent an invalid document id {id:?}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless every hash is a SHA-256 digest, for the same reason.
+fn check_hashes<'a>(hashes: impl IntoIterator<Item = &'a String>) -> Result<()> {
+    for hash in hashes {
+        if !is_hash(hash) {
+            bail!(OzymandiasError::NetworkError(format!(
+                "the remote sent an invalid blob hash {hash:?}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless an entry from the remote names only valid ids and blobs.
+fn check_entries(entries: &[Entry]) -> Result<()> {
+    for entry in entries {
+        check_ids([&entry.id])?;
+        if let Some(doc) = &entry.doc {
+            if doc.id != entry.id {
+                bail!("synced document {} arrived as {}", entry.id, doc.id);
+            }
+            check_hashes(kb::blobs_of(doc))?;
+        }
+    }
+    Ok(())
+}
+
+/// Answers a request of another replica, as the remote end of a sync.
+pub fn handle(kb: &mut KnowledgeBase, request: Request) -> Result<Response> {
+    match &request {
+        Request::Manifest => {}
+        Request::Fetch { ids } => check_ids(ids)?,
+        Request::Blobs { hashes } => check_hashes(hashes)?,
+        Request::Apply { entries, blobs } => {
+            check_entries(entries)?;
+            check_hashes(blobs.iter().map(|b| &b.hash))?;
+        }
+    }
+    let mut state = SyncState::open(&kb.root)?;
+    match request {
+        Request::Manifest => {
+            state.refresh(kb)?;
+            state.save()?;
+            Ok(Response::Manifest {
+                replica: state.replica,
+                versions: state.versions,
+                blobs: kb.blobs.hashes()?,
+            })
+        }
+        Request::Fetch { ids } => Ok(Response::Entries {
+            entries: entries(kb, &state, &ids)?,
+        }),
+        Request::Blobs { hashes } => Ok(Response::Blobs {
+            blobs: read_blobs(kb, hashes.iter())?,
+        }),
+        Request::Apply { entries, blobs } => {
+            // Changes made here since the manifest are not overwritten.
+            state.refresh(kb)?;
+            let documents = apply(kb, &mut state, entries, &blobs)?;
+            kb.commit()?;
+            state.save()?;
+            Ok(Response::Applied { documents })
+        }
+    }
+}
+
+fn entries(kb: &KnowledgeBase, state: &SyncState, ids: &[DocumentId]) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for id in ids {
+        let Some(version) = state.versions.get(id) else {
+            continue;
+        };
+        let doc = match version.hash {
+            Some(_) => kb.storage.get(id)?,
+            None => None,
+        };
+        entries.push(Entry {
+            id: id.clone(),
+            version: version.clone(),
+            doc,
+        });
+    }
+    Ok(entries)
+}
+
+fn read_blobs<'a>(
+    kb: &KnowledgeBase,
+    hashes: impl Iterator<Item = &'a String>,
+) -> Result<Vec<Blob>> {
+    let mut blobs = Vec::new();
+    for hash in hashes {
+        if let Some(bytes) = kb.blobs.get(hash)? {
+            blobs.push(Blob {
+                hash: hash.clone(),
+                data: base64::engine::general_purpose::STANDARD.encode(bytes),
+            });
+        }
+    }
+    Ok(blobs)
+}
+
+/// Stores the entries newer than the replica's own versions, returning
+/// how many documents changed.
+fn apply(
+    kb: &mut KnowledgeBase,
+    state: &mut SyncState,
+    entries: Vec<Entry>,
+    blobs: &[Blob],
+) -> Result<usize> {
+    for blob in blobs {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(&blob.data)
+            .context("invalid base64 in a synced blob")?;
+        // Checked before storing, so a damaged blob is not kept either.
+        if sha256(&bytes) != blob.hash {
+            bail!("blob {} arrived damaged", blob.hash);
+        }
+        kb.blobs.put(&bytes)?;
+    }
+    let mut changed = 0;
+    for mut entry in entries {
+        if let Some(current) = state.versions.get(&entry.id) {
+            if compare(&entry.version.vector, &current.vector) != Order::Newer {
+                continue;
+            }
+        }
+        let stored = state.versions.get(&entry.id).and_then(|v| v.hash.clone());
+        match &entry.doc {
+            Some(doc) => {
+                let hash = hash(doc)?;
+                if stored.as_ref() != Some(&hash) {
+                    kb.tombstones.remove(&doc.id);
+                    kb.insert(doc)?;
+                    changed += 1;
+                }
+                entry.version.hash = Some(hash);
+            }
+            None => {
+                if kb.remove(&entry.id, false)?.is_some() {
+                    changed += 1;
+                }
+                entry.version.hash = None;
+            }
+        }
+        state.versions.insert(entry.id, entry.version);
+    }
+    Ok(changed)
+}
+
+/// Which version of a conflicting document was kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kept {
+    Local,
+    Remote,
+    Both,
+}
+
+/// A document changed on both sides.
+#[derive(Debug, Clone, Serialize)]
+pub struct Conflict {
+    pub id: DocumentId,
+    pub title: String,
+    pub kept: Kept,
+    /// The copy of the remote's version, when both were kept.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub copy: Option<DocumentId>,
+}
+
+/// What a sync changed.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Documents added, changed or removed here.
+    pub pulled: usize,
+    /// Documents added, changed or removed on the remote.
+    pub pushed: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Exchanges changes with `remote` until both have the same documents,
+/// settling documents changed on both sides by `policy`.
+pub fn sync(
+    kb: &mut KnowledgeBase,
+    remote: &mut dyn Remote,
+    policy: MergePolicy,
+) -> Result<Report> {
+    let mut state = SyncState::open(&kb.root)?;
+    state.refresh(kb)?;
+    let Response::Manifest {
+        replica,
+        versions: theirs,
+        blobs: their_blobs,
+    } = remote.call(Request::Manifest)?
+    else {
+        bail!(unexpected());
+    };
+    if replica == state.replica {
+        bail!("the remote is this knowledge base");
+    }
+    check_ids(theirs.keys())?;
+
+    let ids: BTreeSet<DocumentId> = state
+        .versions
+        .keys()
+        .chain(theirs.keys())
+        .cloned()
+        .collect();
+    let (mut pull, mut push, mut conflicting) = (Vec::new(), Vec::new(), Vec::new());
+    for id in ids {
+        match (state.versions.get(&id), theirs.get(&id)) {
+            (Some(_), None) => push.push(id),
+            (None, Some(_)) => pull.push(id),
+            (Some(ours), Some(theirs)) => match compare(&ours.vector, &theirs.vector) {
+                Order::Same => {}
+                Order::Newer => push.push(id),
+                Order::Older => pull.push(id),
+                // The same change on both sides.
+                Order::Concurrent if ours.hash == theirs.hash => {
+                    let vector = merged(&ours.vector, &theirs.vector);
+                    state.versions.get_mut(&id).expect("compared").vector = vector;
+                    push.push(id);
+                }
+                Order::Concurrent => conflicting.push(id),
+            },
+            (None, None) => unreachable!("every id comes from one side"),
+        }
+    }
+
+    let fetch: Vec<DocumentId> = pull.iter().chain(&conflicting).cloned().collect();
+    let fetched = match fetch.is_empty() {
+        true => Vec::new(),
+        false => match remote.call(Request::Fetch { ids: fetch })? {
+            Response::Entries { entries } => entries,
+            _ => bail!(unexpected()),
+        },
+    };
+    check_entries(&fetched)?;
+    let missing: BTreeSet<String> = fetched
+        .iter()
+        .filter_map(|e| e.doc.as_ref())
+        .flat_map(kb::blobs_of)
+        .filter(|h| !kb.blobs.path(h).exists())
+        .cloned()
+        .collect();
+    let blobs = match missing.is_empty() {
+        true => Vec::new(),
+        false => match remote.call(Request::Blobs {
+            hashes: missing.into_iter().collect(),
+        })? {
+            Response::Blobs { blobs } => blobs,
+            _ => bail!(unexpected()),
+        },
+    };
+    check_hashes(blobs.iter().map(|b| &b.hash))?;
+
+    let conflicting: HashSet<DocumentId> = conflicting.into_iter().collect();
+    let mut report = Report::default();
+    let mut incoming = Vec::new();
+    for mut entry in fetched {
+        if !conflicting.contains(&entry.id) {
+            incoming.push(entry);
+            continue;
+        }
+        let ours = state.versions[&entry.id].clone();
+        let local = kb.storage.get(&entry.id)?;
+        let mut vector = merged(&ours.vector, &entry.version.vector);
+        *vector.entry(state.replica.clone()).or_default() += 1;
+        let kept = match (policy, &local, &entry.doc) {
+            (MergePolicy::Local, ..) => Kept::Local,
+            (MergePolicy::Remote, ..) => Kept::Remote,
+            // A removal on one side gives way to a change on the other.
+            (MergePolicy::Both, None, _) => Kept::Remote,
+            (MergePolicy::Both, _, None) => Kept::Local,
+            (MergePolicy::Both, Some(_), Some(_)) => Kept::Both,
+            (MergePolicy::Newest, ..) if entry.version.modified > ours.modified => Kept::Remote,
+            (MergePolicy::Newest, ..) => Kept::Local,
+        };
+        let mut conflict = Conflict {
+            id: entry.id.clone(),
+            title: local
+                .as_ref()
+                .or(entry.doc.as_ref())
+                .map_or_else(String::new, |d| d.title.clone()),
+            kept,
+            copy: None,
+        };
+        push.push(entry.id.clone());
+        if kept == Kept::Remote {
+            entry.version.vector = vector;
+            incoming.push(entry);
+        } else {
+            state
+                .versions
+                .get_mut(&entry.id)
+                .expect("conflicting")
+                .vector = vector;
+            if let (Kept::Both, Some(theirs)) = (kept, entry.doc) {
+                let copy = conflict_copy(theirs, &replica)?;
+                conflict.copy = Some(copy.id.clone());
+                push.push(copy.id.clone());
+                incoming.push(Entry {
+                    id: copy.id.clone(),
+                    version: Version {
+                        vector: BTreeMap::from([(state.replica.clone(), 1)]),
+                        hash: None,
+                        modified: Utc::now(),
+                    },
+                    doc: Some(copy),
+                });
+            }
+        }
+        report.conflicts.push(conflict);
+    }
+    report.pulled = apply(kb, &mut state, incoming, &blobs)?;
+    kb.commit()?;
+    state.save()?;
+
+    let outgoing = entries(kb, &state, &push)?;
+    if outgoing.is_empty() {
+        return Ok(report);
+    }
+    let their_blobs: HashSet<&String> = their_blobs.iter().collect();
+    let wanted: BTreeSet<&String> = outgoing
+        .iter()
+        .filter_map(|e| e.doc.as_ref())
+        .flat_map(kb::blobs_of)
+        .filter(|h| !their_blobs.contains(h))
+        .collect();
+    let blobs = read_blobs(kb, wanted.into_iter())?;
+    if kb::dry_run() {
+        tracing::info!("dry run: would send {} documents", outgoing.len());
+        report.pushed = outgoing.len();
+        return Ok(report);
+    }
+    report.pushed = match remote.call(Request::Apply {
+        entries: outgoing,
+        blobs,
+    })? {
+        Response::Applied { documents } => documents,
+        _ => bail!(unexpected()),
+    };
+    Ok(report)
+}
+
+/// The remote's version of a conflicting document as a document of its
+/// own, named after the replica it came from.
+fn conflict_copy(mut doc: Document, replica: &str) -> Result<Document> {
+    let original = doc.id.clone();
+    doc.id = DocumentId::derive(&format!("{original} conflict {}", hash(&doc)?));
+    doc.title = format!("{} (conflict from {replica})", doc.title);
+    for chunk in &mut doc.chunks {
+        chunk.id = chunk.id.replacen(&original.0, &doc.id.0, 1);
+    }
+    doc.metadata.insert(CONFLICT_KEY.into(), original.0);
+    Ok(doc)
+}
+
+/// Talks to the other replica of a sync.
+pub trait Remote {
+    fn call(&mut self, request: Request) -> Result<Response>;
+}
+
+/// The remote named `name` under `[sync.remotes]`, or else the one at
+/// `name`: `ssh://host/path` or `host:path` over SSH, an `http(s)://` URL
+/// of `ozy serve`, or a directory. With `relay+` before the URL of `ozy
+/// serve --relay`, or `relay:` before a directory, everything is
+/// encrypted first; see [`crate::relay`]. `token` is the API token for
+/// `ozy serve`, if not set for the remote. `root` is the knowledge base
+/// syncing.
+pub fn connect(
+    root: &Path,
+    name: &str,
+    config: &SyncConfig,
+    token: Option<String>,
+) -> Result<Box<dyn Remote>> {
+    let remote = config.remotes.get(name);
+    let url = remote.map_or(name, |r| r.url.as_str());
+    let token = || match (token, remote.and_then(|r| r.token_env.as_deref())) {
+        (Some(token), _) => Ok(token),
+        (None, Some(var)) => std::env::var(var).with_context(|| {
+            OzymandiasError::ConfigInvalid(format!("{var} holds no API token for {name}"))
+        }),
+        (None, None) => bail!(OzymandiasError::ConfigInvalid(format!(
+            "{name} needs the API token of its server: pass --token or set token_env for it"
+        ))),
+    };
+    let relay = url
+        .strip_prefix("relay+")
+        .or_else(|| url.strip_prefix("relay:"));
+    if let Some(relay) = relay {
+        let store: Box<dyn crate::relay::Store> =
+            if relay.starts_with("http://") || relay.starts_with("https://") {
+                Box::new(crate::relay::HttpStore {
+                    url: relay.trim_end_matches('/').to_string(),
+                    token: token()?,
+                })
+            } else {
+                Box::new(crate::relay::DirStore(kb::expand_home(Path::new(relay))))
+            };
+        let var = remote
+            .and_then(|r| r.passphrase_env.as_deref())
+            .unwrap_or(crate::relay::PASSPHRASE_ENV);
+        let passphrase = std::env::var(var)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .with_context(|| {
+                OzymandiasError::ConfigInvalid(format!("set {var} to the passphrase of {name}"))
+            })?;
+        return Ok(Box::new(crate::relay::Relay::open(
+            store,
+            &passphrase,
+            root.join(crate::relay::SEEN_FILE),
+        )?));
+    }
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Ok(Box::new(Http {
+            url: format!("{}/sync", url.trim_end_matches('/')),
+            token: token()?,
+        }));
+    }
+    let ssh = match url.strip_prefix("ssh://") {
+        Some(rest) => {
+            let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+            // `ssh://host/~/notes` is relative to the home directory.
+            let path = match path.strip_prefix("~/") {
+                Some(path) => path.to_string(),
+                None => format!("/{path}"),
+            };
+            Some((host.to_string(), path))
+        }
+        // `host:path` as scp takes it, unless the colon belongs to a path.
+        None => url
+            .split_once(':')
+            .filter(|(host, _)| host.len() > 1 && !host.contains('/') && !Path::new(url).exists())
+            .map(|(host, path)| (host.to_string(), path.to_string())),
+    };
+    match ssh {
+        // Would be taken as an option of ssh.
+        Some((host, _)) if host.starts_with('-') => bail!(OzymandiasError::ConfigInvalid(format!(
+            "{host:?} is not a host name"
+        ))),
+        Some((host, path)) => Ok(Box::new(Ssh { host, path })),
+        None => Ok(Box::new(Local(KnowledgeBase::open(&kb::expand_home(
+            Path::new(url),
+        ))?))),
+    }
+}
+
+/// A knowledge base in a directory, such as a mounted drive.
+struct Local(KnowledgeBase);
+
+impl Remote for Local {
+    fn call(&mut self, request: Request) -> Result<Response> {
+        handle(&mut self.0, request)
+    }
+}
+
+/// `ozy sync --serve` run through `ssh` in the knowledge base's
+/// directory, once per request.
+struct Ssh {
+    host: String,
+    path: String,
+}
+
+impl Remote for Ssh {
+    fn call(&mut self, request: Request) -> Result<Response> {
+        let dir = match self.path.as_str() {
+            "" => String::from("."),
+            path => format!("'{}'", path.replace('\'', "'\\''")),
+        };
+        let mut child = Command::new("ssh")
+            .arg("--")
+            .arg(&self.host)
+            .arg(format!("cd {dir} && ozy sync --serve"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("failed to run ssh")?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        serde_json::to_writer(&mut stdin, &request)?;
+        stdin.flush()?;
+        drop(stdin);
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!(OzymandiasError::NetworkError(format!(
+                "ozy sync --serve on {} failed ({})",
+                self.host, output.status
+            )));
+        }
+        serde_json::from_slice(&output.stdout).map_err(|_| unexpected().into())
+    }
+}
+
+/// `POST /sync` of `ozy serve`.
+struct Http {
+    url: String,
+    token: String,
+}
+
+impl Remote for Http {
+    fn call(&mut self, request: Request) -> Result<Response> {
+        let body = serde_json::to_string(&request)?;
+        let raw = runtime::block_on(async {
+            let response = http()
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .bearer_auth(&self.token)
+                .body(body)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .with_context(|| format!("request to {} failed", self.url))?;
+            response
+                .text()
+                .await
+                .with_context(|| format!("failed to read response from {}", self.url))
+        })?;
+        serde_json::from_str(&raw).map_err(|_| unexpected().into())
+    }
+}
+
+fn unexpected() -> OzymandiasError {
+    OzymandiasError::NetworkError("unexpected answer from the remote".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::chunk::Chunk;
+
+    fn vector(counts: &[(&str, u64)]) -> BTreeMap<String, u64> {
+        counts.iter().map(|(r, n)| (r.to_string(), *n)).collect()
+    }
+
+    fn entry(id: &DocumentId, doc: Option<Document>) -> Entry {
+        Entry {
+            id: id.clone(),
+            version: Version {
+                vector: vector(&[("a", 1)]),
+                hash: None,
+                modified: Utc::now(),
+            },
+            doc,
+        }
+    }
+
+    fn doc(key: &str) -> Document {
+        Document::new(
+            DocumentId::derive(key),
+            "Notes".into(),
+            crate::types::DocumentKind::Markdown,
+            "Some notes.".into(),
+            None,
+        )
+    }
+
+    #[test]
+    fn vectors_compare_by_what_each_has_seen() {
+        let a = vector(&[("a", 2), ("b", 1)]);
+        assert_eq!(compare(&a, &a), Order::Same);
+        assert_eq!(compare(&a, &vector(&[("a", 1), ("b", 1)])), Order::Newer);
+        assert_eq!(compare(&a, &vector(&[("a", 2), ("b", 3)])), Order::Older);
+        assert_eq!(
+            compare(&a, &vector(&[("a", 1), ("b", 2)])),
+            Order::Concurrent
+        );
+    }
+
+    #[test]
+    fn a_missing_replica_counts_as_no_changes() {
+        assert_eq!(
+            compare(&vector(&[("a", 1), ("b", 0)]), &vector(&[("a", 1)])),
+            Order::Same
+        );
+        assert_eq!(
+            compare(&vector(&[("b", 1)]), &BTreeMap::new()),
+            Order::Newer
+        );
+        assert_eq!(
+            compare(&vector(&[("a", 1)]), &vector(&[("b", 1)])),
+            Order::Concurrent
+        );
+    }
+
+    #[test]
+    fn a_merged_vector_is_newer_than_or_the_same_as_both() {
+        let a = vector(&[("a", 3), ("b", 1)]);
+        let b = vector(&[("b", 2), ("c", 1)]);
+        let m = merged(&a, &b);
+        assert_eq!(m, vector(&[("a", 3), ("b", 2), ("c", 1)]));
+        assert_eq!(compare(&m, &a), Order::Newer);
+        assert_eq!(compare(&m, &b), Order::Newer);
+        assert_eq!(compare(&merged(&a, &a), &a), Order::Same);
+    }
+
+    #[test]
+    fn bumping_counts_a_change_of_this_replica() {
+        let mut state = SyncState {
+            replica: "here".into(),
+            ..SyncState::default()
+        };
+        let id = DocumentId::derive("doc");
+        let earlier = Utc::now() - chrono::Duration::days(1);
+        state.bump(&id, Some("1".into()), earlier);
+        state.bump(&id, None, Utc::now());
+        let version = &state.versions[&id];
+        assert_eq!(version.vector, vector(&[("here", 2)]));
+        assert_eq!(version.hash, None);
+        assert!(version.modified > earlier);
+    }
+
+    #[test]
+    fn ids_and_hashes_from_the_remote_must_be_ones_made_here() {
+        assert!(check_ids([&DocumentId::derive("doc")]).is_ok());
+        assert!(check_ids([&DocumentId("../../etc/passwd".into())]).is_err());
+        assert!(check_hashes([&sha256(b"blob")]).is_ok());
+        assert!(check_hashes([&"../blob".to_string()]).is_err());
+    }
+
+    #[test]
+    fn an_entry_must_hold_the_document_it_names() {
+        let notes = doc("notes");
+        assert!(check_entries(&[entry(&notes.id, Some(notes.clone()))]).is_ok());
+        assert!(check_entries(&[entry(&notes.id, None)]).is_ok());
+        assert!(
+            check_entries(&[entry(&DocumentId::derive("other"), Some(notes.clone()))]).is_err()
+        );
+
+        let mut snapshot = notes.clone();
+        snapshot
+            .metadata
+            .insert(crate::types::SNAPSHOT_KEY.into(), "/etc/passwd".into());
+        assert!(check_entries(&[entry(&notes.id, Some(snapshot))]).is_err());
+    }
+
+    #[test]
+    fn a_conflict_copy_is_a_document_of_its_own() {
+        let mut original = doc("notes");
+        original.chunks.push(Chunk {
+            id: format!("{}-0123abcd", original.id),
+            start: 0,
+            end: 5,
+        });
+        let copy = conflict_copy(original.clone(), "laptop").unwrap();
+        assert_ne!(copy.id, original.id);
+        assert!(copy.id.is_valid());
+        assert_eq!(copy.title, "Notes (conflict from laptop)");
+        assert_eq!(copy.chunks[0].id, format!("{}-0123abcd", copy.id));
+        assert_eq!(copy.metadata[CONFLICT_KEY], original.id.0);
+        assert_eq!(copy.content, original.content);
+
+        // The same version makes the same copy on every replica.
+        assert_eq!(conflict_copy(original, "laptop").unwrap().id, copy.id);
+    }
+}
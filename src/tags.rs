@@ -0,0 +1,105 @@
+//! Tag normalization and bookkeeping.
+//!
+//! Tags may be paths such as `project/ozymandias/design`; each segment names
+//! a narrower tag than the one before it.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+
+use crate::storage::Storage;
+
+/// Canonical form of a tag: trimmed, lowercase, inner whitespace as `-`,
+/// path segments separated by single `/`.
+pub fn normalize(tag: &str) -> Result<String> {
+    let segments: Vec<String> = tag
+        .split('/')
+        .map(|s| {
+            s.split_whitespace()
+                .collect::<Vec<_>>()
+                .join("-")
+                .to_lowercase()
+        })
+        .filter(|s| !s.is_empty())
+        .collect();
+    if segments.is_empty() {
+        bail!("tags cannot be empty");
+    }
+    let tag = segments.join("/");
+    if let Some(c) = tag
+        .chars()
+        .find(|c| matches!(c, ',' | ':' | '"' | '(' | ')'))
+    {
+        bail!("tag {tag:?} contains {c:?}, which the query language reserves");
+    }
+    Ok(tag)
+}
+
+/// Whether `tag` is `ancestor` itself or lies below it in the hierarchy.
+pub fn is_within(tag: &str, ancestor: &str) -> bool {
+    tag.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// The next broader tag: `a/b` for `a/b/c`, nothing for a top-level tag.
+pub fn parent(tag: &str) -> Option<&str> {
+    tag.rsplit_once('/').map(|(parent, _)| parent)
+}
+
+/// `tag` followed by all of its ancestors, narrowest first.
+pub fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(tag), |t| parent(t))
+}
+
+/// Adds and removes tags on a sorted, duplicate-free list. Returns whether
+/// anything changed.
+pub fn apply(tags: &mut Vec<String>, add: &[String], remove: &[String]) -> bool {
+    let before = tags.clone();
+    tags.retain(|t| !remove.contains(t));
+    for tag in add {
+        if !tags.contains(tag) {
+            tags.push(tag.clone());
+        }
+    }
+    tags.sort();
+    *tags != before
+}
+
+/// Number of documents carrying each tag.
+pub fn counts(storage: &dyn Storage) -> Result<BTreeMap<String, usize>> {
+    let mut counts = BTreeMap::new();
+    for doc in storage.all()? {
+        for tag in doc.tags {
+            *counts.entry(tag).or_default() += 1;
+        }
+    }
+    Ok(counts)
+}
+
+/// A node of the tag hierarchy, for tree-style listings.
+#[derive(Debug, Default)]
+pub struct TagNode {
+    /// Documents tagged with exactly this tag.
+    pub direct: usize,
+    /// Documents tagged with this tag or any descendant.
+    pub total: usize,
+    pub children: BTreeMap<String, TagNode>,
+}
+
+/// Arranges per-tag document counts into a tree keyed by path segment.
+///
+/// `total` counts tag assignments, so a document carrying both `a/b` and
+/// `a/c` is counted twice under `a`.
+pub fn tree(counts: &BTreeMap<String, usize>) -> TagNode {
+    let mut root = TagNode::default();
+    for (tag, &count) in counts {
+        let mut node = &mut root;
+        node.total += count;
+        for segment in tag.split('/') {
+            node = node.children.entry(segment.to_string()).or_default();
+            node.total += count;
+        }
+        node.direct += count;
+    }
+    root
+}
@@ -0,0 +1,224 @@
+//! Tasks written into documents: Markdown checkboxes (`- [ ]`, `- [x]`)
+//! and lines marked `TODO:` or `DONE:`. They are indexed along with links
+//! whenever documents change.
+//!
+//! A task may say when it is due, after `due`, `by` or `@`: a date such
+//! as `2024-03-01`, or `today`, `tomorrow`, a weekday, `next week` or
+//! `in 3 days`, counted from the day the document was added.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{read_json_or_default, write_json};
+use crate::types::{Document, DocumentId};
+
+const CHECKBOXES: [(&str, bool); 6] = [
+    ("- [ ] ", false),
+    ("* [ ] ", false),
+    ("- [x] ", true),
+    ("* [x] ", true),
+    ("- [X] ", true),
+    ("* [X] ", true),
+];
+const MARKERS: [(&str, bool); 2] = [("TODO:", false), ("DONE:", true)];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub document: DocumentId,
+    /// Line of the task in the document's content, from 1.
+    pub line: usize,
+    pub text: String,
+    pub done: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due: Option<NaiveDate>,
+}
+
+impl Task {
+    /// How the task is named on the command line: its document and line.
+    pub fn id(&self) -> String {
+        format!("{}:{}", self.document, self.line)
+    }
+}
+
+/// The tasks written into a document.
+pub fn extract(doc: &Document) -> Vec<Task> {
+    let added = doc.added.with_timezone(&Local).date_naive();
+    let mut tasks = Vec::new();
+    let mut fenced = false;
+    for (i, line) in doc.content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            fenced = !fenced;
+        }
+        if fenced {
+            continue;
+        }
+        let found = CHECKBOXES
+            .iter()
+            .find_map(|(prefix, done)| Some((trimmed.strip_prefix(prefix)?, *done)))
+            .or_else(|| {
+                MARKERS.iter().find_map(|(marker, done)| {
+                    let at = line.find(marker)?;
+                    Some((&line[at + marker.len()..], *done))
+                })
+            });
+        let Some((text, done)) = found else {
+            continue;
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        tasks.push(Task {
+            document: doc.id.clone(),
+            line: i + 1,
+            text: text.to_string(),
+            done,
+            due: due(text, added),
+        });
+    }
+    tasks
+}
+
+/// The line of a task marked as done, if `line` holds an open task.
+pub fn complete(line: &str) -> Option<String> {
+    for (open, done) in [("- [ ] ", "- [x] "), ("* [ ] ", "* [x] ")] {
+        let indent = line.len() - line.trim_start().len();
+        if line[indent..].starts_with(open) {
+            return Some(format!(
+                "{}{done}{}",
+                &line[..indent],
+                &line[indent + open.len()..]
+            ));
+        }
+    }
+    line.contains("TODO:")
+        .then(|| line.replacen("TODO:", "DONE:", 1))
+}
+
+/// When a task is due, by the words after `due`, `by` or `@` in its text.
+pub fn due(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | '(' | ')'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    for (i, word) in words.iter().enumerate() {
+        let rest = match *word {
+            "due" | "due:" | "by" => &words[i + 1..],
+            w if w.starts_with('@') && w.len() > 1 => {
+                if let Some(date) = parse_date(&[&w[1..]], today) {
+                    return Some(date);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let rest: Vec<&str> = rest
+            .iter()
+            .map(|w| w.trim_end_matches(['.', '!']))
+            .collect();
+        if let Some(date) = parse_date(&rest, today) {
+            return Some(date);
+        }
+    }
+    None
+}
+
+/// A day named by the first words of `words`, such as `tomorrow`,
+/// `next friday`, `in 2 weeks` or `2024-03-01`.
+pub fn parse_date(words: &[&str], today: NaiveDate) -> Option<NaiveDate> {
+    let first = *words.first()?;
+    if let Ok(date) = NaiveDate::parse_from_str(first, "%Y-%m-%d") {
+        return Some(date);
+    }
+    match first {
+        "today" | "tonight" => return Some(today),
+        "tomorrow" => return Some(today + Duration::days(1)),
+        "next" => {
+            let second = *words.get(1)?;
+            return match second {
+                "week" => Some(today + Duration::days(7)),
+                "month" => today.checked_add_months(chrono::Months::new(1)),
+                day => Some(following(today, weekday(day)?) + Duration::days(7)),
+            };
+        }
+        "in" => {
+            let count: i64 = words.get(1)?.parse().ok()?;
+            let unit = words.get(2)?.trim_end_matches('s');
+            return match unit {
+                "day" => today.checked_add_signed(Duration::try_days(count)?),
+                "week" => today.checked_add_signed(Duration::try_days(count.checked_mul(7)?)?),
+                "month" => {
+                    today.checked_add_months(chrono::Months::new(u32::try_from(count).ok()?))
+                }
+                _ => None,
+            };
+        }
+        _ => {}
+    }
+    weekday(first).map(|day| following(today, day))
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    match word.get(..3)? {
+        "mon" => Some(Weekday::Mon),
+        "tue" => Some(Weekday::Tue),
+        "wed" => Some(Weekday::Wed),
+        "thu" => Some(Weekday::Thu),
+        "fri" => Some(Weekday::Fri),
+        "sat" => Some(Weekday::Sat),
+        "sun" => Some(Weekday::Sun),
+        _ => None,
+    }
+    .filter(|_| word.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+/// The first `day` after `today`.
+fn following(today: NaiveDate, day: Weekday) -> NaiveDate {
+    let ahead = (day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+    today + Duration::days(if ahead == 0 { 7 } else { i64::from(ahead) })
+}
+
+/// The tasks of every document, rebuilt whenever documents change.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TaskIndex {
+    tasks: Vec<Task>,
+    #[serde(skip)]
+    path: PathBuf,
+    /// Set when the knowledge base predates the index.
+    #[serde(skip)]
+    missing: bool,
+}
+
+impl TaskIndex {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("tasks.json");
+        let mut index: TaskIndex = read_json_or_default(&path)?;
+        index.missing = !path.exists();
+        index.path = path;
+        Ok(index)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    pub fn rebuild(&mut self, docs: &[Document]) {
+        self.tasks = docs.iter().flat_map(extract).collect();
+        self.missing = false;
+    }
+
+    /// Whether the index was never built, so must be before it is read.
+    pub fn is_missing(&self) -> bool {
+        self.missing
+    }
+
+    pub fn all(&self) -> &[Task] {
+        &self.tasks
+    }
+}
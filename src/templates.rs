@@ -0,0 +1,87 @@
+//! Note templates: Markdown files in the knowledge base's `templates/`,
+//! such as `templates/meeting.md`, in which `{{name}}` is replaced by the
+//! value of a variable. `{{title}}`, `{{date}}` and `{{weekday}}` are
+//! always known; `ozy new` asks for the others:
+//!
+//! ```markdown
+//! # {{title}}
+//!
+//! {{date}}, with {{attendees}}
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
+
+pub const TEMPLATES_DIR: &str = "templates";
+/// What a note starts from without a template.
+pub const DEFAULT: &str = "# {{title}}\n\n";
+
+pub fn path(root: &Path, name: &str) -> PathBuf {
+    root.join(TEMPLATES_DIR).join(format!("{name}.md"))
+}
+
+/// The text of the template `name`, if the knowledge base has one.
+pub fn load(root: &Path, name: &str) -> Result<Option<String>> {
+    let path = path(root, name);
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// The variables every template may use, for a note titled `title`
+/// about the day `date`.
+pub fn builtins<'a>(title: &str, date: NaiveDate) -> BTreeMap<&'a str, String> {
+    BTreeMap::from([
+        ("title", title.to_string()),
+        ("date", date.format("%Y-%m-%d").to_string()),
+        ("weekday", date.format("%A").to_string()),
+    ])
+}
+
+/// Names of the variables `template` uses, in the order they first
+/// appear.
+pub fn variables(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        let name = after[..end].trim();
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+        rest = &after[end + 2..];
+    }
+    names
+}
+
+/// Replaces each `{{name}}`, with or without spaces inside the braces, by
+/// the value of the variable. Unknown variables are left as they are.
+pub fn render(template: &str, vars: &BTreeMap<&str, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        match vars.get(after[..end].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,103 @@
+//! Small PNG previews of image and PDF attachments, made when they are
+//! attached so that the TUI and the API can show them without loading the
+//! originals. They are kept in the blob store like the files themselves.
+//!
+//! Images are scaled down here; the first page of a PDF is rendered by
+//! `pdftoppm` from Poppler, and PDFs get no thumbnail without it.
+
+use std::io::Cursor;
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use image::{DynamicImage, ImageFormat};
+
+use crate::storage::BlobStore;
+use crate::types::Attachment;
+
+/// Whether a thumbnail can be made of files of this media type.
+pub fn supported(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/png" | "image/jpeg" | "image/gif" | "image/webp" | "application/pdf"
+    )
+}
+
+/// Gives each attachment that lacks one a thumbnail at most `max` pixels
+/// wide and high; `max` of 0 makes none. Attachments whose thumbnail
+/// cannot be made are left as they are, with a warning.
+pub fn fill(blobs: &BlobStore, attachments: &mut [Attachment], max: u32) {
+    if max == 0 {
+        return;
+    }
+    for attachment in attachments.iter_mut().filter(|a| a.thumbnail.is_none()) {
+        match make(blobs, attachment, max) {
+            Ok(thumbnail) => attachment.thumbnail = thumbnail,
+            Err(e) => tracing::warn!("no thumbnail for {}: {e:#}", attachment.name),
+        }
+    }
+}
+
+/// Stores a thumbnail of an attachment, returning its blob, or `None` if
+/// none can be made of its type.
+pub fn make(blobs: &BlobStore, attachment: &Attachment, max: u32) -> Result<Option<String>> {
+    let Some(mime) = attachment.mime.as_deref().filter(|m| supported(m)) else {
+        return Ok(None);
+    };
+    let image = if mime == "application/pdf" {
+        match first_page(blobs, &attachment.blob, max)? {
+            Some(page) => page,
+            None => return Ok(None),
+        }
+    } else {
+        let Some(bytes) = blobs.get(&attachment.blob)? else {
+            bail!("its blob is missing");
+        };
+        image::load_from_memory(&bytes).context("failed to decode the image")?
+    };
+    let mut png = Vec::new();
+    image
+        .thumbnail(max, max)
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .context("failed to encode the thumbnail")?;
+    Ok(Some(blobs.put(&png)?))
+}
+
+/// Decodes a stored thumbnail.
+pub fn load(blobs: &BlobStore, hash: &str) -> Result<Option<DynamicImage>> {
+    let Some(bytes) = blobs.get(hash)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        image::load_from_memory_with_format(&bytes, ImageFormat::Png)
+            .context("failed to decode the thumbnail")?,
+    ))
+}
+
+/// The first page of a stored PDF, rendered to fit `max` pixels, or
+/// `None` if `pdftoppm` is not installed.
+fn first_page(blobs: &BlobStore, hash: &str, max: u32) -> Result<Option<DynamicImage>> {
+    let pdf = blobs.path(hash);
+    if !pdf.exists() {
+        bail!("its blob is missing");
+    }
+    let output = match Command::new("pdftoppm")
+        .args(["-png", "-singlefile", "-f", "1", "-l", "1", "-scale-to"])
+        .arg(max.to_string())
+        .arg(&pdf)
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            tracing::debug!("pdftoppm is not installed; skipping PDF thumbnails");
+            return Ok(None);
+        }
+        Err(e) => return Err(e).context("failed to run pdftoppm"),
+    };
+    if !output.status.success() {
+        bail!("pdftoppm exited with {}", output.status);
+    }
+    Ok(Some(
+        image::load_from_memory_with_format(&output.stdout, ImageFormat::Png)
+            .context("pdftoppm printed an invalid image")?,
+    ))
+}
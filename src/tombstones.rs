@@ -0,0 +1,60 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::{read_json_or_default, write_json};
+use crate::types::DocumentId;
+
+/// What remains of a removed document, so that importing its source again
+/// does not quietly bring it back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub deleted: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Tombstones {
+    entries: BTreeMap<DocumentId, Tombstone>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Tombstones {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("tombstones.json");
+        let mut tombstones: Tombstones = read_json_or_default(&path)?;
+        tombstones.path = path;
+        Ok(tombstones)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    pub fn get(&self, id: &DocumentId) -> Option<&Tombstone> {
+        self.entries.get(id)
+    }
+
+    pub fn insert(&mut self, id: DocumentId, tombstone: Tombstone) {
+        self.entries.insert(id, tombstone);
+    }
+
+    pub fn remove(&mut self, id: &DocumentId) -> Option<Tombstone> {
+        self.entries.remove(id)
+    }
+
+    /// Keeps the tombstones of the documents `keep` accepts, returning how
+    /// many were dropped.
+    pub fn retain(&mut self, mut keep: impl FnMut(&DocumentId) -> bool) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|id, _| keep(id));
+        before - self.entries.len()
+    }
+}
@@ -0,0 +1,229 @@
+//! Splitting documents into passages that are embedded and ranked on
+//! their own, so a long document can match on the one section that
+//! matters.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{Outcome, PipelineConfig, Stage};
+use crate::kb::KnowledgeBase;
+use crate::types::{Document, DocumentId};
+
+/// A passage of a document's content.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chunk {
+    /// The document id and a hash of the passage, so unchanged passages
+    /// keep their id when a document is re-imported.
+    pub id: String,
+    /// Byte offsets into the document's content.
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Chunk {
+    pub fn text<'a>(&self, content: &'a str) -> &'a str {
+        content.get(self.start..self.end).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// Consecutive paragraphs up to `chunk_size` characters.
+    #[default]
+    Paragraphs,
+    /// Consecutive sentences up to `chunk_size` characters.
+    Sentences,
+    /// Windows of `chunk_tokens` words overlapping by `chunk_overlap`.
+    Tokens,
+    /// One chunk per markdown section; long sections split by paragraph.
+    Headings,
+}
+
+pub struct Chunking {
+    pub strategy: Strategy,
+    pub size: usize,
+    pub tokens: usize,
+    pub overlap: usize,
+}
+
+impl Chunking {
+    pub fn new(strategy: Strategy, config: &PipelineConfig) -> Self {
+        Chunking {
+            strategy,
+            size: config.chunk_size.max(1),
+            tokens: config.chunk_tokens.max(1),
+            overlap: config.chunk_overlap,
+        }
+    }
+
+    pub fn spans(&self, text: &str) -> Vec<(usize, usize)> {
+        match self.strategy {
+            Strategy::Paragraphs => group(text, paragraphs(text, 0..text.len()), self.size),
+            Strategy::Sentences => group(text, sentences(text), self.size),
+            Strategy::Tokens => token_windows(text, self.tokens, self.overlap),
+            Strategy::Headings => sections(text)
+                .into_iter()
+                .flat_map(|(start, end)| {
+                    if text[start..end].chars().count() <= self.size {
+                        vec![(start, end)]
+                    } else {
+                        group(text, paragraphs(text, start..end), self.size)
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Stage for Chunking {
+    fn name(&self) -> &'static str {
+        "chunk"
+    }
+
+    fn apply(&self, doc: &mut Document, _kb: &KnowledgeBase) -> Result<Outcome> {
+        doc.chunks = chunks(&doc.id, &doc.content, self.spans(&doc.content));
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Turns spans into chunks with ids unique within the document.
+pub fn chunks(doc: &DocumentId, content: &str, spans: Vec<(usize, usize)>) -> Vec<Chunk> {
+    let mut out: Vec<Chunk> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        let hex: String = Sha256::digest(&content.as_bytes()[start..end])
+            .iter()
+            .take(4)
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        let mut id = format!("{doc}-{hex}");
+        // Repeated passages, such as a boilerplate line, need distinct ids.
+        let mut n = 1;
+        while out.iter().any(|c| c.id == id) {
+            n += 1;
+            id = format!("{doc}-{hex}-{n}");
+        }
+        out.push(Chunk { id, start, end });
+    }
+    out
+}
+
+/// Merges consecutive units while the result stays within `size`
+/// characters. A unit longer than that becomes a chunk of its own.
+fn group(text: &str, units: Vec<(usize, usize)>, size: usize) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    for (start, end) in units {
+        current = match current {
+            Some((s, _)) if text[s..end].chars().count() <= size => Some((s, end)),
+            Some(c) => {
+                out.push(c);
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    out.extend(current);
+    out
+}
+
+/// Non-blank paragraphs within `range`, trimmed of surrounding newlines.
+fn paragraphs(text: &str, range: std::ops::Range<usize>) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    let mut offset = range.start;
+    for para in text[range].split("\n\n") {
+        let start = offset + (para.len() - para.trim_start().len());
+        let end = offset + para.trim_end().len();
+        offset += para.len() + 2;
+        if start < end {
+            out.push((start, end));
+        }
+    }
+    out
+}
+
+/// Sentences, ending at `.`, `!` or `?` followed by whitespace, or at a
+/// paragraph break.
+pub fn sentences(text: &str) -> Vec<(usize, usize)> {
+    let mut out = Vec::new();
+    for (start, end) in paragraphs(text, 0..text.len()) {
+        let para = &text[start..end];
+        let mut from = 0;
+        let mut chars = para.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let boundary = matches!(c, '.' | '!' | '?')
+                && chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+            if boundary {
+                let end = i + c.len_utf8();
+                push_trimmed(&mut out, para, start, from, end);
+                from = end;
+            }
+        }
+        push_trimmed(&mut out, para, start, from, para.len());
+    }
+    out
+}
+
+fn push_trimmed(out: &mut Vec<(usize, usize)>, s: &str, base: usize, from: usize, to: usize) {
+    let piece = &s[from..to];
+    let start = from + (piece.len() - piece.trim_start().len());
+    let end = from + piece.trim_end().len();
+    if start < end {
+        out.push((base + start, base + end));
+    }
+}
+
+/// Windows of `size` whitespace-separated words, each starting `size -
+/// overlap` words after the previous one.
+fn token_windows(text: &str, size: usize, overlap: usize) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some(i),
+            (true, Some(s)) => {
+                words.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        words.push((s, text.len()));
+    }
+    let step = size.saturating_sub(overlap).max(1);
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < words.len() {
+        let last = (i + size).min(words.len()) - 1;
+        out.push((words[i].0, words[last].1));
+        if last + 1 == words.len() {
+            break;
+        }
+        i += step;
+    }
+    out
+}
+
+/// Markdown sections, each starting at an ATX heading. Text before the
+/// first heading is a section of its own.
+fn sections(text: &str) -> Vec<(usize, usize)> {
+    let mut starts = vec![0];
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        if offset > 0 && line.starts_with('#') {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    starts.push(text.len());
+    starts
+        .windows(2)
+        .filter_map(|w| {
+            let piece = &text[w[0]..w[1]];
+            let end = w[0] + piece.trim_end().len();
+            (w[0] < end).then_some((w[0], end))
+        })
+        .collect()
+}
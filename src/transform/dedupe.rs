@@ -0,0 +1,83 @@
+//! Skipping documents whose content is already in the knowledge base, and
+//! flagging ones that nearly match a stored document.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use super::{Outcome, Stage};
+use crate::fingerprint::{content_hash, Signature};
+use crate::kb::KnowledgeBase;
+use crate::types::{Document, DocumentId};
+
+/// Metadata key naming the stored document a new one nearly duplicates.
+pub const NEAR_DUPLICATE_KEY: &str = "near_duplicate_of";
+
+/// Remembers the fingerprints of stored documents, loading those it has
+/// not seen yet whenever a document passes.
+pub struct Dedupe {
+    threshold: f32,
+    seen: RefCell<HashMap<DocumentId, (String, Signature)>>,
+    /// Documents let through but not stored yet, as when several are
+    /// embedded together before being stored.
+    passed: RefCell<HashSet<DocumentId>>,
+}
+
+impl Dedupe {
+    pub fn new(threshold: f32) -> Self {
+        Dedupe {
+            threshold,
+            seen: RefCell::default(),
+            passed: RefCell::default(),
+        }
+    }
+}
+
+impl Stage for Dedupe {
+    fn name(&self) -> &'static str {
+        "dedupe"
+    }
+
+    fn apply(&self, doc: &mut Document, kb: &KnowledgeBase) -> Result<Outcome> {
+        let mut seen = self.seen.borrow_mut();
+        let mut passed = self.passed.borrow_mut();
+        let ids = kb.storage.ids()?;
+        passed.retain(|id| !ids.contains(id));
+        seen.retain(|id, _| ids.contains(id) || passed.contains(id));
+        let unseen: Vec<DocumentId> = ids
+            .into_iter()
+            .filter(|id| !seen.contains_key(id))
+            .collect();
+        for id in unseen {
+            if let Some(stored) = kb.storage.get(&id)? {
+                seen.insert(id, fingerprint(&stored));
+            }
+        }
+
+        // Re-adding a stored document updates it, whatever else matches.
+        if seen.contains_key(&doc.id) {
+            return Ok(Outcome::Continue);
+        }
+        let (hash, signature) = fingerprint(doc);
+        if let Some((id, _)) = seen.iter().find(|(_, (h, _))| *h == hash) {
+            return Ok(Outcome::Skip(format!("same content as {id}")));
+        }
+        let nearest = seen
+            .iter()
+            .map(|(id, (_, s))| (id, s.similarity(&signature)))
+            .filter(|(_, similarity)| *similarity >= self.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(a.0)));
+        if let Some((id, _)) = nearest {
+            doc.metadata
+                .insert(NEAR_DUPLICATE_KEY.into(), id.to_string());
+        }
+        seen.insert(doc.id.clone(), (hash, signature));
+        passed.insert(doc.id.clone());
+        Ok(Outcome::Continue)
+    }
+}
+
+fn fingerprint(doc: &Document) -> (String, Signature) {
+    (content_hash(&doc.content), Signature::of(doc))
+}
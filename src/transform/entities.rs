@@ -0,0 +1,21 @@
+//! Recording the named entities a document mentions.
+
+use anyhow::Result;
+
+use super::{Outcome, Stage};
+use crate::entities::extract;
+use crate::kb::KnowledgeBase;
+use crate::types::Document;
+
+pub struct Entities;
+
+impl Stage for Entities {
+    fn name(&self) -> &'static str {
+        "entities"
+    }
+
+    fn apply(&self, doc: &mut Document, _kb: &KnowledgeBase) -> Result<Outcome> {
+        doc.entities = extract(&doc.content);
+        Ok(Outcome::Continue)
+    }
+}
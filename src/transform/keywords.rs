@@ -0,0 +1,44 @@
+//! Recording the words that best characterize each document.
+
+use anyhow::Result;
+
+use super::{Outcome, Stage};
+use crate::index::language_of;
+use crate::kb::KnowledgeBase;
+use crate::ml::keywords::{candidates, top};
+use crate::types::Document;
+
+/// Picks the `count` words of a document that are frequent in it and rare
+/// in the rest of the knowledge base, by the full-text index.
+pub struct Keywords {
+    count: usize,
+}
+
+impl Keywords {
+    pub fn new(count: usize) -> Self {
+        Keywords { count }
+    }
+}
+
+impl Stage for Keywords {
+    fn name(&self) -> &'static str {
+        "keywords"
+    }
+
+    fn apply(&self, doc: &mut Document, kb: &KnowledgeBase) -> Result<Outcome> {
+        let language = language_of(doc);
+        let analyzer = kb.index.analyzer();
+        let words = candidates(
+            &format!("{} {}", doc.title, doc.content),
+            language,
+            analyzer,
+        );
+        let df = |w: &str| {
+            analyzer
+                .normalize(w, language)
+                .map_or(0, |term| kb.index.document_frequency(&term))
+        };
+        doc.keywords = top(words, df, kb.index.len(), self.count);
+        Ok(Outcome::Continue)
+    }
+}
@@ -0,0 +1,150 @@
+//! Guessing the language of a document from its script and, for Latin
+//! text, its most common words. The result is stored in the `language`
+//! metadata key, where the search analyzer and model selection read it.
+
+use anyhow::Result;
+
+use super::{Outcome, Stage};
+use crate::index::tokenize;
+use crate::kb::KnowledgeBase;
+use crate::types::Document;
+
+pub struct DetectLanguage;
+
+/// Metadata key holding the ISO 639-1 code of a document's language.
+pub const KEY: &str = "language";
+
+const STOPWORDS: [(&str, &[&str]); 8] = [
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "is", "in", "that", "it", "with", "for",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "und", "das", "ist", "nicht", "mit", "ein", "zu", "den",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "des", "est", "une", "que", "pour", "dans",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "la", "los", "y", "que", "es", "una", "por", "con", "para",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "di", "che", "la", "e", "un", "per", "non", "sono", "della",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "de", "het", "een", "en", "van", "is", "niet", "dat", "op", "met",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "do", "da", "em", "um", "uma", "não", "com", "são",
+        ],
+    ),
+    (
+        "sv",
+        &[
+            "och", "att", "det", "som", "en", "är", "av", "för", "med", "inte",
+        ],
+    ),
+];
+
+/// The built-in stopwords of `language`, used by the full-text analyzer
+/// unless the config supplies its own.
+pub fn stopwords(language: &str) -> &'static [&'static str] {
+    STOPWORDS
+        .iter()
+        .find(|(l, _)| *l == language)
+        .map_or(&[], |(_, words)| words)
+}
+
+/// A language and a test for the letters of its script.
+type Script = (&'static str, fn(char) -> bool);
+
+/// Languages recognized by script alone, for letters outside Latin.
+const SCRIPTS: [Script; 8] = [
+    ("ru", |c| matches!(c, '\u{0400}'..='\u{04FF}')),
+    ("el", |c| matches!(c, '\u{0370}'..='\u{03FF}')),
+    ("he", |c| matches!(c, '\u{0590}'..='\u{05FF}')),
+    ("ar", |c| matches!(c, '\u{0600}'..='\u{06FF}')),
+    ("hi", |c| matches!(c, '\u{0900}'..='\u{097F}')),
+    ("th", |c| matches!(c, '\u{0E00}'..='\u{0E7F}')),
+    (
+        "ko",
+        |c| matches!(c, '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}'),
+    ),
+    ("zh", is_han),
+];
+
+pub fn is_han(c: char) -> bool {
+    matches!(c, '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+pub fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}')
+}
+
+/// Whether `language` is written in Han characters without spaces
+/// between words.
+pub fn is_unsegmented(language: &str) -> bool {
+    matches!(language, "zh" | "ja")
+}
+
+impl Stage for DetectLanguage {
+    fn name(&self) -> &'static str {
+        "detect-language"
+    }
+
+    fn apply(&self, doc: &mut Document, _kb: &KnowledgeBase) -> Result<Outcome> {
+        if let Some(language) = detect(&doc.content) {
+            doc.metadata.insert(KEY.into(), language.into());
+        }
+        Ok(Outcome::Continue)
+    }
+}
+
+/// The ISO 639-1 code of the language of `text`, if one stands out.
+///
+/// Text mostly in a non-Latin script is identified by that script, with
+/// Han text containing kana taken as Japanese. Latin text is identified by
+/// the language whose stopwords are most frequent.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    if letters.is_empty() {
+        return None;
+    }
+    let kana = letters.iter().filter(|c| is_kana(**c)).count();
+    let (script, count) = SCRIPTS
+        .iter()
+        .map(|(lang, test)| (*lang, letters.iter().filter(|c| test(**c)).count()))
+        .max_by_key(|(_, count)| *count)?;
+    if (count + kana) * 2 >= letters.len() {
+        return Some(if kana > 0 { "ja" } else { script });
+    }
+
+    let words = tokenize(text);
+    let (language, hits) = STOPWORDS
+        .iter()
+        .map(|(lang, stop)| {
+            let hits = words.iter().filter(|w| stop.contains(&w.as_str())).count();
+            (*lang, hits)
+        })
+        .max_by_key(|(_, hits)| *hits)?;
+    (hits >= 3 && hits * 20 >= words.len()).then_some(language)
+}
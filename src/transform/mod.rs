@@ -0,0 +1,219 @@
+//! The ingest pipeline: named stages that transform a parsed document
+//! before it is stored. Which stages run, and in what order, is configured
+//! per document type:
+//!
+//! ```toml
+//! [pipeline]
+//! default = ["normalize", "detect-language", "entities", "chunk", "dedupe"]
+//!
+//! [pipeline.types]
+//! html = ["normalize", "chunk", "summarize"]
+//!
+//! [pipeline.chunkers]
+//! markdown = "headings"
+//! ```
+//!
+//! A transformer plugin is listed like any other stage, by its name. The
+//! `post-parse` and `pre-store` hooks run before and after the stages.
+
+pub mod chunk;
+pub mod dedupe;
+pub mod entities;
+pub mod keywords;
+pub mod language;
+pub mod normalize;
+pub mod summarize;
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::hooks::Hook;
+use crate::kb::KnowledgeBase;
+use crate::plugins::Plugins;
+use crate::types::{Document, DocumentKind};
+use chunk::{Chunking, Strategy};
+
+/// What a stage decided about a document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Continue,
+    /// Stop here and do not store the document.
+    Skip(String),
+}
+
+pub trait Stage {
+    fn name(&self) -> &str;
+    fn apply(&self, doc: &mut Document, kb: &KnowledgeBase) -> Result<Outcome>;
+}
+
+/// The stages run by default, in order.
+pub const STAGES: [&str; 6] = [
+    "normalize",
+    "detect-language",
+    "entities",
+    "keywords",
+    "chunk",
+    "dedupe",
+];
+/// Stages that only run when configured, since they are slow or call
+/// external services.
+pub const OPTIONAL_STAGES: [&str; 1] = ["summarize"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    /// Stages for document types without their own entry in `types`.
+    pub default: Vec<String>,
+    /// Stages per document type (`markdown`, `text` or `html`).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub types: BTreeMap<String, Vec<String>>,
+    /// How the `chunk` stage splits documents without an entry in
+    /// `chunkers`.
+    pub chunker: Strategy,
+    /// Chunking strategy per document type.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub chunkers: BTreeMap<String, Strategy>,
+    /// Target chunk length in characters.
+    pub chunk_size: usize,
+    /// Window length of the `tokens` chunker, in words.
+    pub chunk_tokens: usize,
+    /// Words shared by consecutive windows of the `tokens` chunker.
+    pub chunk_overlap: usize,
+    /// Estimated share of common phrases above which the `dedupe` stage
+    /// flags a document as a near duplicate of a stored one.
+    pub near_duplicate_threshold: f32,
+    /// Number of keywords the `keywords` stage records per document.
+    pub keywords: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            default: STAGES.iter().map(|s| s.to_string()).collect(),
+            types: BTreeMap::new(),
+            chunker: Strategy::default(),
+            chunkers: BTreeMap::new(),
+            chunk_size: 1000,
+            chunk_tokens: 200,
+            chunk_overlap: 20,
+            near_duplicate_threshold: 0.8,
+            keywords: 8,
+        }
+    }
+}
+
+fn stage(
+    name: &str,
+    kind: Option<DocumentKind>,
+    config: &PipelineConfig,
+    plugins: &Plugins,
+) -> Result<Box<dyn Stage>> {
+    Ok(match name {
+        "normalize" => Box::new(normalize::Normalize),
+        "detect-language" => Box::new(language::DetectLanguage),
+        "entities" => Box::new(entities::Entities),
+        "keywords" => Box::new(keywords::Keywords::new(config.keywords)),
+        "chunk" => {
+            let strategy = kind
+                .and_then(|k| config.chunkers.get(k.name()))
+                .copied()
+                .unwrap_or(config.chunker);
+            Box::new(Chunking::new(strategy, config))
+        }
+        "dedupe" => Box::new(dedupe::Dedupe::new(config.near_duplicate_threshold)),
+        "summarize" => Box::new(summarize::Summarize::default()),
+        other => match plugins.stage(other) {
+            Some(stage) => stage,
+            None => bail!(
+                "unknown pipeline stage {other:?} (expected one of {}, {}{})",
+                STAGES.join(", "),
+                OPTIONAL_STAGES.join(", "),
+                plugins
+                    .stages()
+                    .map(|s| format!(", {s}"))
+                    .collect::<String>()
+            ),
+        },
+    })
+}
+
+/// The stages of one document type, in order.
+pub struct Pipeline {
+    stages: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    /// Builds the stages for documents of `kind`, or for any kind.
+    pub fn new(
+        names: &[String],
+        kind: Option<DocumentKind>,
+        config: &PipelineConfig,
+        plugins: &Plugins,
+    ) -> Result<Self> {
+        Ok(Pipeline {
+            stages: names
+                .iter()
+                .map(|n| stage(n, kind, config, plugins))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    pub fn run(&self, doc: &mut Document, kb: &KnowledgeBase) -> Result<Outcome> {
+        for stage in &self.stages {
+            if let Outcome::Skip(reason) = stage.apply(doc, kb)? {
+                return Ok(Outcome::Skip(format!("{}: {reason}", stage.name())));
+            }
+        }
+        Ok(Outcome::Continue)
+    }
+}
+
+/// A pipeline for every document type, built from the config.
+pub struct Pipelines {
+    default: Pipeline,
+    types: HashMap<DocumentKind, Pipeline>,
+}
+
+impl Pipelines {
+    pub fn from_config(config: &PipelineConfig, plugins: &Plugins) -> Result<Self> {
+        for name in config.types.keys().chain(config.chunkers.keys()) {
+            parse_kind(name)?;
+        }
+        let mut types = HashMap::new();
+        for kind in [
+            DocumentKind::Markdown,
+            DocumentKind::Text,
+            DocumentKind::Html,
+        ] {
+            let stages = config.types.get(kind.name()).unwrap_or(&config.default);
+            types.insert(kind, Pipeline::new(stages, Some(kind), config, plugins)?);
+        }
+        Ok(Pipelines {
+            default: Pipeline::new(&config.default, None, config, plugins)?,
+            types,
+        })
+    }
+
+    pub fn run(&self, doc: &mut Document, kb: &KnowledgeBase) -> Result<Outcome> {
+        let hooks = kb.hooks()?;
+        if let Outcome::Skip(reason) = hooks.document(Hook::PostParse, doc)? {
+            return Ok(Outcome::Skip(reason));
+        }
+        let pipeline = self.types.get(&doc.kind).unwrap_or(&self.default);
+        if let Outcome::Skip(reason) = pipeline.run(doc, kb)? {
+            return Ok(Outcome::Skip(reason));
+        }
+        hooks.document(Hook::PreStore, doc)
+    }
+}
+
+fn parse_kind(name: &str) -> Result<DocumentKind> {
+    Ok(match name {
+        "markdown" => DocumentKind::Markdown,
+        "text" => DocumentKind::Text,
+        "html" => DocumentKind::Html,
+        other => bail!("unknown document type {other:?} in [pipeline]"),
+    })
+}
@@ -0,0 +1,92 @@
+//! Canonical text, so that texts that read the same compare and index
+//! equally whatever editor or web page they came from.
+
+use anyhow::Result;
+use unicode_normalization::UnicodeNormalization;
+
+use super::{Outcome, Stage};
+use crate::kb::KnowledgeBase;
+use crate::types::Document;
+
+pub struct Normalize;
+
+impl Stage for Normalize {
+    fn name(&self) -> &'static str {
+        "normalize"
+    }
+
+    fn apply(&self, doc: &mut Document, _kb: &KnowledgeBase) -> Result<Outcome> {
+        doc.content = normalize(&doc.content);
+        doc.title = normalize_line(&doc.title).trim().to_string();
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Composes unicode to NFC, converts line endings to `\n`, strips trailing
+/// whitespace and collapses runs of blank lines into one. Outside fenced
+/// code blocks, lines also get [`normalize_line`].
+pub fn normalize(text: &str) -> String {
+    let text: String = text.nfc().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut blank = 0;
+    let mut in_code = false;
+    for line in text.lines() {
+        let fence = line.trim_start().starts_with("```");
+        let line = if in_code || fence {
+            line.trim_end().to_string()
+        } else {
+            normalize_line(line)
+        };
+        if fence {
+            in_code = !in_code;
+        }
+        if line.is_empty() && !in_code {
+            blank += 1;
+            continue;
+        }
+        if !out.is_empty() {
+            out.push_str(if blank > 0 { "\n\n" } else { "\n" });
+        }
+        blank = 0;
+        out.push_str(&line);
+    }
+    out
+}
+
+/// Replaces typographic quotes, dashes and ellipses with their ASCII
+/// forms, drops invisible characters and collapses runs of spaces after
+/// the indentation.
+pub fn normalize_line(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let indent = line.len() - line.trim_start().len();
+    out.push_str(&line[..indent]);
+    let mut space = false;
+    for c in line[indent..].nfc() {
+        let replacement = match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => "'",
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => "\"",
+            '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2212}' => "-",
+            '\u{2014}' | '\u{2015}' => "--",
+            '\u{2026}' => "...",
+            '\u{00AD}' | '\u{200B}' | '\u{200C}' | '\u{200D}' | '\u{2060}' | '\u{FEFF}' => "",
+            c if c.is_whitespace() => {
+                space = true;
+                continue;
+            }
+            _ => {
+                if space {
+                    out.push(' ');
+                    space = false;
+                }
+                out.push(c);
+                continue;
+            }
+        };
+        if space && !replacement.is_empty() {
+            out.push(' ');
+            space = false;
+        }
+        out.push_str(replacement);
+    }
+    out
+}
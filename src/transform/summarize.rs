@@ -0,0 +1,35 @@
+//! Writing a summary of each document as it is added.
+
+use std::cell::OnceCell;
+
+use anyhow::{Context, Result};
+
+use super::{Outcome, Stage};
+use crate::kb::KnowledgeBase;
+use crate::ml::summarize::{summarize, SUMMARY_KEY};
+use crate::ml::{llm_from_config, LlmProvider};
+use crate::runtime;
+use crate::types::Document;
+
+/// Summarizes with the language model of the `[llm]` config section,
+/// created on first use.
+#[derive(Default)]
+pub struct Summarize {
+    llm: OnceCell<Option<Box<dyn LlmProvider>>>,
+}
+
+impl Stage for Summarize {
+    fn name(&self) -> &'static str {
+        "summarize"
+    }
+
+    fn apply(&self, doc: &mut Document, kb: &KnowledgeBase) -> Result<Outcome> {
+        let llm = self.llm.get_or_init(|| llm_from_config(&kb.config.llm));
+        let summary = runtime::block_on(summarize(doc, llm.as_deref(), &kb.prompts()))
+            .with_context(|| format!("failed to summarize {}", doc.title))?;
+        if !summary.is_empty() {
+            doc.metadata.insert(SUMMARY_KEY.into(), summary);
+        }
+        Ok(Outcome::Continue)
+    }
+}
@@ -0,0 +1,142 @@
+//! Documents removed with `ozy rm`, kept until `ozy trash empty` or until
+//! they expire, so that `ozy trash restore` can bring them back.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::OzymandiasError;
+use crate::kb;
+use crate::storage::write_json;
+use crate::types::{Document, DocumentId};
+
+/// A removed document as it was stored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trashed {
+    pub document: Document,
+    pub deleted: DateTime<Utc>,
+}
+
+/// The removed documents, a JSON file each under `trash/`.
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn open(root: &Path) -> Self {
+        Trash {
+            dir: root.join("trash"),
+        }
+    }
+
+    fn path(&self, id: &DocumentId) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    pub fn get(&self, id: &DocumentId) -> Result<Option<Trashed>> {
+        let path = self.path(id);
+        match fs::read_to_string(&path) {
+            Ok(raw) => serde_json::from_str(&raw)
+                .map(Some)
+                .with_context(|| format!("corrupt trash entry {}", path.display())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Every document in the trash, the most recently removed first.
+    pub fn all(&self) -> Result<Vec<Trashed>> {
+        let mut trashed = Vec::new();
+        for id in self.ids()? {
+            if let Some(entry) = self.get(&id)? {
+                trashed.push(entry);
+            }
+        }
+        trashed.sort_by_key(|t| std::cmp::Reverse(t.deleted));
+        Ok(trashed)
+    }
+
+    fn ids(&self) -> Result<Vec<DocumentId>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.dir.display()))
+            }
+        };
+        let mut ids = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    ids.push(DocumentId(stem.to_string()));
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Expands a unique prefix to the id of a document in the trash.
+    pub fn resolve(&self, prefix: &str) -> Result<DocumentId> {
+        let candidates: Vec<DocumentId> = self
+            .ids()?
+            .into_iter()
+            .filter(|id| id.0.starts_with(prefix))
+            .collect();
+        match candidates.as_slice() {
+            [id] => Ok(id.clone()),
+            [] => bail!(OzymandiasError::NotFound(format!(
+                "no document with id {prefix} in the trash"
+            ))),
+            _ => bail!(
+                "id prefix {prefix} is ambiguous ({} documents in the trash match)",
+                candidates.len()
+            ),
+        }
+    }
+
+    /// Keeps a removed document.
+    pub fn put(&self, document: &Document, deleted: DateTime<Utc>) -> Result<()> {
+        if kb::dry_run() {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let trashed = Trashed {
+            document: document.clone(),
+            deleted,
+        };
+        write_json(&self.path(&document.id), &trashed)
+    }
+
+    /// Drops a document from the trash; true if it was there.
+    pub fn remove(&self, id: &DocumentId) -> Result<bool> {
+        if kb::dry_run() {
+            return Ok(self.path(id).exists());
+        }
+        match fs::remove_file(self.path(id)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Drops the documents removed more than `days` days ago, returning
+    /// their ids.
+    pub fn expire(&self, days: u32) -> Result<Vec<DocumentId>> {
+        let cutoff = Utc::now() - Duration::days(i64::from(days));
+        let mut expired = Vec::new();
+        for trashed in self.all()? {
+            if trashed.deleted < cutoff {
+                self.remove(&trashed.document.id)?;
+                expired.push(trashed.document.id);
+            }
+        }
+        Ok(expired)
+    }
+}
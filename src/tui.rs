@@ -0,0 +1,353 @@
+//! Full-screen terminal interface: a query line, the matching documents and
+//! a preview of the selected one with its backlinks and the thumbnail of
+//! its first image or PDF.
+
+use anyhow::Result;
+use image::DynamicImage;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::kb::KnowledgeBase;
+use crate::search::{self, Match, SearchMode};
+use crate::tags;
+use crate::thumbnails;
+
+/// Lines the thumbnail of an attachment takes in the preview.
+const THUMBNAIL_ROWS: u16 = 12;
+const HELP: &str = "/ search  ↑↓ select  PgUp/PgDn scroll  t tags  m mode  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Query,
+    Results,
+    /// Editing the tags of the selected document.
+    Tags,
+}
+
+struct App {
+    kb: KnowledgeBase,
+    query: String,
+    mode: SearchMode,
+    matches: Vec<Match>,
+    list: ListState,
+    focus: Focus,
+    /// Tags being edited, space-separated.
+    tags: String,
+    scroll: u16,
+    status: String,
+}
+
+/// Runs the interface until the user quits, restoring the terminal even
+/// when an error ends it.
+pub fn run(kb: KnowledgeBase) -> Result<()> {
+    let mut app = App {
+        kb,
+        query: String::new(),
+        mode: SearchMode::Hybrid,
+        matches: Vec::new(),
+        list: ListState::default(),
+        focus: Focus::Results,
+        tags: String::new(),
+        scroll: 0,
+        status: HELP.to_string(),
+    };
+    app.search();
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result?;
+    app.kb.commit()
+}
+
+impl App {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+                return Ok(());
+            }
+            let quit = match self.focus {
+                Focus::Query => self.query_key(key),
+                Focus::Results => self.results_key(key),
+                Focus::Tags => self.tags_key(key),
+            };
+            if quit {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handles a key while typing the query; returns whether to quit.
+    fn query_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Enter => {
+                self.search();
+                self.focus = Focus::Results;
+            }
+            KeyCode::Esc | KeyCode::Tab | KeyCode::Down => self.focus = Focus::Results,
+            KeyCode::Backspace => {
+                self.query.pop();
+            }
+            KeyCode::Char(c) => self.query.push(c),
+            _ => {}
+        }
+        false
+    }
+
+    fn results_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char('/') | KeyCode::Tab => self.focus = Focus::Query,
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Home | KeyCode::Char('g') => self.select(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => self.select(isize::MAX),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('m') => {
+                self.mode = match self.mode {
+                    SearchMode::Hybrid => SearchMode::Keyword,
+                    SearchMode::Keyword => SearchMode::Semantic,
+                    SearchMode::Semantic => SearchMode::Hybrid,
+                };
+                self.search();
+            }
+            KeyCode::Char('t') => {
+                if let Some(m) = self.selected() {
+                    self.tags = m.doc.tags.join(" ");
+                    self.focus = Focus::Tags;
+                    self.status = "tags, separated by spaces; Enter saves, Esc cancels".into();
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn tags_key(&mut self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Enter => {
+                self.status = match self.save_tags() {
+                    Ok(()) => HELP.to_string(),
+                    Err(e) => format!("{e:#}"),
+                };
+                self.focus = Focus::Results;
+            }
+            KeyCode::Esc => {
+                self.status = HELP.to_string();
+                self.focus = Focus::Results;
+            }
+            KeyCode::Backspace => {
+                self.tags.pop();
+            }
+            KeyCode::Char(c) => self.tags.push(c),
+            _ => {}
+        }
+        false
+    }
+
+    fn search(&mut self) {
+        let result = self
+            .kb
+            .parse_query(&self.query)
+            .and_then(|query| search::run(&mut self.kb, &query, self.mode));
+        match result {
+            Ok(matches) => {
+                self.status = HELP.to_string();
+                self.matches = matches;
+            }
+            Err(e) => {
+                self.status = format!("{e:#}");
+                self.matches.clear();
+            }
+        }
+        self.list.select((!self.matches.is_empty()).then_some(0));
+        self.scroll = 0;
+    }
+
+    fn select(&mut self, delta: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        let last = self.matches.len() - 1;
+        let current = self.list.selected().unwrap_or(0);
+        let next = current.saturating_add_signed(delta).min(last);
+        self.list.select(Some(next));
+        self.scroll = 0;
+    }
+
+    fn selected(&self) -> Option<&Match> {
+        self.matches.get(self.list.selected()?)
+    }
+
+    fn save_tags(&mut self) -> Result<()> {
+        let Some(index) = self.list.selected() else {
+            return Ok(());
+        };
+        let wanted = self
+            .tags
+            .split_whitespace()
+            .map(tags::normalize)
+            .collect::<Result<Vec<_>>>()?;
+        let doc = &self.matches[index].doc;
+        let removed: Vec<String> = doc
+            .tags
+            .iter()
+            .filter(|t| !wanted.contains(t))
+            .cloned()
+            .collect();
+        let now = self.kb.retag(&doc.id, &wanted, &removed)?;
+        self.matches[index].doc.tags = now;
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [query, body, status] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [results, preview] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(body);
+
+        let mode = format!(" search ({}) ", mode_name(self.mode));
+        frame.render_widget(
+            Paragraph::new(self.query.as_str()).block(bordered(&mode, self.focus == Focus::Query)),
+            query,
+        );
+        if self.focus == Focus::Query {
+            let x = query.x + 1 + self.query.chars().count() as u16;
+            frame.set_cursor_position((x.min(query.right().saturating_sub(2)), query.y + 1));
+        }
+
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|m| {
+                ListItem::new(Line::from(vec![
+                    Span::raw(m.doc.title.clone()),
+                    Span::raw(format!("  {}", m.doc.id)).dim(),
+                ]))
+            })
+            .collect();
+        let title = format!(" {} documents ", self.matches.len());
+        let list = List::new(items)
+            .block(bordered(&title, self.focus == Focus::Results))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, results, &mut self.list);
+
+        self.draw_preview(frame, preview);
+
+        let status_line = match self.focus {
+            Focus::Tags => format!("tags: {}", self.tags),
+            _ => self.status.clone(),
+        };
+        frame.render_widget(Paragraph::new(status_line).dim(), status);
+        if self.focus == Focus::Tags {
+            let x = status.x + 6 + self.tags.chars().count() as u16;
+            frame.set_cursor_position((x.min(status.right().saturating_sub(1)), status.y));
+        }
+    }
+
+    fn draw_preview(&self, frame: &mut Frame, area: Rect) {
+        let Some(m) = self.selected() else {
+            frame.render_widget(Block::bordered().title(" preview "), area);
+            return;
+        };
+        let doc = &m.doc;
+        let mut lines = vec![
+            Line::from(doc.title.clone()).bold(),
+            Line::from(format!("{}  {}", doc.id, doc.kind.name())).dim(),
+        ];
+        if !doc.tags.is_empty() {
+            lines.push(Line::from(format!("tags: {}", doc.tags.join(", "))));
+        }
+        if !doc.keywords.is_empty() {
+            lines.push(Line::from(format!("keywords: {}", doc.keywords.join(", "))).dim());
+        }
+        let backlinks: Vec<String> = self
+            .kb
+            .links
+            .links_to(&doc.id)
+            .map(|id| match self.kb.storage.get(id) {
+                Ok(Some(from)) => format!("  {id}  {}", from.title),
+                _ => format!("  {id}"),
+            })
+            .collect();
+        if !backlinks.is_empty() {
+            lines.push(Line::from("linked from:"));
+            lines.extend(backlinks.into_iter().map(Line::from));
+        }
+        if let Some(hash) = doc.attachments.iter().find_map(|a| a.thumbnail.as_deref()) {
+            if let Ok(Some(image)) = thumbnails::load(&self.kb.blobs, hash) {
+                lines.push(Line::default());
+                let width = area.width.saturating_sub(2);
+                lines.extend(picture(&image, width, THUMBNAIL_ROWS));
+            }
+        }
+        lines.push(Line::default());
+        lines.extend(doc.content.lines().map(|l| Line::from(l.to_string())));
+        let preview = Paragraph::new(lines)
+            .block(Block::bordered().title(" preview "))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(preview, area);
+    }
+}
+
+/// An image drawn with half blocks, two pixels to a cell, scaled to fit
+/// `width` cells by `rows` lines.
+fn picture(image: &DynamicImage, width: u16, rows: u16) -> Vec<Line<'static>> {
+    let image = image
+        .thumbnail(u32::from(width), u32::from(rows) * 2)
+        .to_rgb8();
+    let color = |x, y| {
+        let [r, g, b] = image.get_pixel(x, y).0;
+        Color::Rgb(r, g, b)
+    };
+    (0..image.height())
+        .step_by(2)
+        .map(|y| {
+            Line::from(
+                (0..image.width())
+                    .map(|x| {
+                        let mut style = Style::new().fg(color(x, y));
+                        if y + 1 < image.height() {
+                            style = style.bg(color(x, y + 1));
+                        }
+                        Span::styled("▀", style)
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect()
+}
+
+fn bordered(title: &str, focused: bool) -> Block<'static> {
+    let block = Block::bordered().title(title.to_string());
+    if focused {
+        block.border_style(Style::new().bold())
+    } else {
+        block
+    }
+}
+
+fn mode_name(mode: SearchMode) -> &'static str {
+    match mode {
+        SearchMode::Keyword => "keyword",
+        SearchMode::Semantic => "semantic",
+        SearchMode::Hybrid => "hybrid",
+    }
+}
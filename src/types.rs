@@ -1,3 +1,192 @@
-struct URL {
-    base_link: &str,
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::entities::Entity;
+use crate::links::Link;
+use crate::relations::Relation;
+use crate::transform::chunk::Chunk;
+use crate::transform::language;
+
+/// Short, stable identifier of a document inside a knowledge base.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DocumentId(pub String);
+
+impl DocumentId {
+    const LEN: usize = 12;
+
+    /// Derives an id from a stable key such as the canonical source path.
+    pub fn derive(key: &str) -> Self {
+        let digest = Sha256::digest(key.as_bytes());
+        let hex: String = digest.iter().map(|b| format!("{b:02x}")).collect();
+        DocumentId(hex[..Self::LEN].to_string())
+    }
+
+    /// Whether the id is one [`DocumentId::derive`] could have made, and so
+    /// safe to name files after.
+    pub fn is_valid(&self) -> bool {
+        self.0.len() == Self::LEN
+            && self
+                .0
+                .bytes()
+                .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    }
+}
+
+impl fmt::Display for DocumentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentKind {
+    Markdown,
+    Text,
+    Html,
+}
+
+impl Document {
+    /// A document as first seen, before the pipeline has run, added now.
+    pub fn new(
+        id: DocumentId,
+        title: String,
+        kind: DocumentKind,
+        content: String,
+        source: Option<String>,
+    ) -> Self {
+        Document {
+            id,
+            title,
+            kind,
+            content,
+            source,
+            added: Utc::now(),
+            tags: Vec::new(),
+            aliases: Vec::new(),
+            former_ids: Vec::new(),
+            links: Vec::new(),
+            relations: Vec::new(),
+            entities: Vec::new(),
+            keywords: Vec::new(),
+            chunks: Vec::new(),
+            attachments: Vec::new(),
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// The language found by the `detect-language` stage, as an ISO 639-1
+    /// code.
+    pub fn language(&self) -> Option<&str> {
+        self.metadata.get(language::KEY).map(String::as_str)
+    }
+
+    /// The extension, in lowercase, of the file the document was added
+    /// from, or else of its first attachment, such as the PDF of a paper
+    /// imported from Zotero.
+    pub fn file_type(&self) -> Option<String> {
+        let extension = |name: &str| {
+            let path = name.split(['?', '#']).next().unwrap_or(name);
+            std::path::Path::new(path)
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(str::to_ascii_lowercase)
+        };
+        self.source
+            .as_deref()
+            .and_then(extension)
+            .or_else(|| self.attachments.first().and_then(|a| extension(&a.name)))
+    }
+}
+
+impl DocumentKind {
+    pub fn from_extension(ext: &str) -> Self {
+        match ext.to_ascii_lowercase().as_str() {
+            "md" | "markdown" => DocumentKind::Markdown,
+            "html" | "htm" => DocumentKind::Html,
+            _ => DocumentKind::Text,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DocumentKind::Markdown => "markdown",
+            DocumentKind::Text => "text",
+            DocumentKind::Html => "html",
+        }
+    }
+}
+
+/// Metadata keys recording the state of a source file when it was last
+/// ingested.
+pub const MODIFIED_KEY: &str = "modified";
+pub const SOURCE_HASH_KEY: &str = "source_hash";
+/// Metadata key of the blob holding the HTML a web page was clipped from.
+pub const SNAPSHOT_KEY: &str = "snapshot";
+/// Metadata key of the blob holding a self-contained copy of a web page,
+/// kept with `ozy add --url --archive`.
+pub const ARCHIVE_KEY: &str = "archive";
+/// Metadata key recording why a saved page could not be clipped.
+pub const FETCH_ERROR_KEY: &str = "fetch_error";
+
+/// A single entry of the knowledge base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Document {
+    pub id: DocumentId,
+    pub title: String,
+    pub kind: DocumentKind,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub added: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Ids of duplicates merged into the document, which resolve to it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub former_ids: Vec<DocumentId>,
+    /// Outgoing links as written in the content, resolved by [`LinkIndex`].
+    ///
+    /// [`LinkIndex`]: crate::links::LinkIndex
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+    /// Typed relations to other documents, set with `ozy relate`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relations: Vec<Relation>,
+    /// People, organizations, places and dates found by the entities stage.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<Entity>,
+    /// Words characterizing the document, best first, found by the
+    /// keywords stage.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// Passages of `content` found by the chunking stage.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<Chunk>,
+    /// Files in the blob store that belong to the document, referred to
+    /// from the content as `blob:<hash>`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A file kept in the blob store, such as an image of an imported note.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// The file name it had, or one made up for it.
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Hash of the blob holding its bytes.
+    pub blob: String,
+    /// Hash of the blob holding a PNG preview of it, for images and PDFs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
 }
@@ -0,0 +1,136 @@
+//! The log of recent commands that `ozy undo` can reverse: `add`, `rm`,
+//! `tag`, `dedupe merge` and `trash restore`.
+//!
+//! Each operation keeps every document it touched as it was before, along
+//! with its tombstone, and a hash of what it left behind, so that undoing
+//! never overwrites a later change made by some other command. Blobs that
+//! an undone `add` stored are left for `ozy doctor` to report, and `ozy
+//! gc` forgets the operations such a change left stuck.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::fingerprint::content_hash;
+use crate::storage::{read_json_or_default, write_json};
+use crate::tombstones::Tombstone;
+use crate::types::{Document, DocumentId};
+
+/// How many operations are kept; older ones can no longer be undone.
+const KEEP: usize = 50;
+
+/// A command as it changed the knowledge base.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    /// The command, as it would be typed.
+    pub command: String,
+    pub at: DateTime<Utc>,
+    pub changes: Vec<Change>,
+}
+
+/// One document as the operation found and left it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub id: DocumentId,
+    /// The document before, if it existed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Document>,
+    /// Its tombstone before, if it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tombstone: Option<Tombstone>,
+    /// The [`state`] of the document after.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+}
+
+/// Describes a command for the log, quoting arguments with spaces.
+pub fn command(name: &str, args: impl IntoIterator<Item = String>) -> String {
+    let mut command = name.to_string();
+    for arg in args {
+        command.push(' ');
+        match arg.contains(char::is_whitespace) {
+            true => command.push_str(&format!("{arg:?}")),
+            false => command.push_str(&arg),
+        }
+    }
+    command
+}
+
+/// A hash of a document's stored form, to tell whether it changed since.
+pub fn state(doc: Option<&Document>) -> Result<Option<String>> {
+    Ok(match doc {
+        Some(doc) => Some(content_hash(&serde_json::to_string(doc)?)),
+        None => None,
+    })
+}
+
+/// The operations, oldest first, in `undo.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UndoLog {
+    operations: Vec<Operation>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl UndoLog {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("undo.json");
+        let mut log: UndoLog = read_json_or_default(&path)?;
+        log.path = path;
+        Ok(log)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    /// The operations that can be undone, oldest first.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn push(&mut self, operation: Operation) {
+        self.operations.push(operation);
+        let excess = self.operations.len().saturating_sub(KEEP);
+        self.operations.drain(..excess);
+    }
+
+    pub fn pop(&mut self) -> Option<Operation> {
+        self.operations.pop()
+    }
+
+    /// Drops the operations that can no longer be undone, returning how
+    /// many. Undoing goes newest first and stops at an operation whose
+    /// documents changed since, so that one and all before it are dead.
+    /// `current` gives the [`state`] of a document now.
+    pub fn compact(
+        &mut self,
+        mut current: impl FnMut(&DocumentId) -> Result<Option<String>>,
+    ) -> Result<usize> {
+        // The states documents would be in once the later operations are
+        // undone.
+        let mut states: HashMap<DocumentId, Option<String>> = HashMap::new();
+        let mut dead = 0;
+        'operations: for (i, operation) in self.operations.iter().enumerate().rev() {
+            for change in &operation.changes {
+                let now = match states.get(&change.id) {
+                    Some(state) => state.clone(),
+                    None => current(&change.id)?,
+                };
+                if now != change.after {
+                    dead = i + 1;
+                    break 'operations;
+                }
+            }
+            for change in &operation.changes {
+                states.insert(change.id.clone(), state(change.before.as_ref())?);
+            }
+        }
+        self.operations.drain(..dead);
+        Ok(dead)
+    }
+}
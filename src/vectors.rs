@@ -0,0 +1,142 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::index::Hit;
+use crate::ml::cosine;
+use crate::storage::{read_json_or_default, write_json};
+use crate::types::DocumentId;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChunkVector {
+    doc: DocumentId,
+    vector: Vec<f32>,
+}
+
+/// Persisted document embeddings, all produced by the same model.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VectorIndex {
+    /// Name of the model that produced the vectors, to catch mixing models.
+    model: Option<String>,
+    vectors: BTreeMap<DocumentId, Vec<f32>>,
+    /// Embeddings of the chunks of documents long enough to be split.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    chunks: BTreeMap<String, ChunkVector>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl VectorIndex {
+    pub fn open(root: &Path) -> Result<Self> {
+        let path = root.join("vectors.json");
+        let mut index: VectorIndex = read_json_or_default(&path)?;
+        index.path = path;
+        Ok(index)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write_json(&self.path, self)
+    }
+
+    pub fn model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    /// Drops all vectors so the index can be rebuilt with `model`.
+    pub fn reset(&mut self, model: &str) {
+        self.vectors.clear();
+        self.chunks.clear();
+        self.model = Some(model.to_string());
+    }
+
+    pub fn insert(&mut self, model: &str, id: DocumentId, vector: Vec<f32>) -> Result<()> {
+        self.check_model(model)?;
+        self.vectors.insert(id, vector);
+        Ok(())
+    }
+
+    /// Records the embedding of one chunk of `doc`.
+    pub fn insert_chunk(
+        &mut self,
+        model: &str,
+        chunk: String,
+        doc: DocumentId,
+        vector: Vec<f32>,
+    ) -> Result<()> {
+        self.check_model(model)?;
+        self.chunks.insert(chunk, ChunkVector { doc, vector });
+        Ok(())
+    }
+
+    fn check_model(&mut self, model: &str) -> Result<()> {
+        match &self.model {
+            Some(m) if m != model && !self.vectors.is_empty() => {
+                bail!("embedding index was built with {m}, not {model}; run `ozy models reembed`")
+            }
+            _ => self.model = Some(model.to_string()),
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: &DocumentId) -> Option<&[f32]> {
+        self.vectors.get(id).map(Vec::as_slice)
+    }
+
+    pub fn remove(&mut self, id: &DocumentId) {
+        self.vectors.remove(id);
+        self.chunks.retain(|_, c| c.doc != *id);
+    }
+
+    /// Number of vectors, of documents and of chunks.
+    pub fn len(&self) -> usize {
+        self.vectors.len() + self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ids of the documents with a vector for themselves or a chunk.
+    pub fn ids(&self) -> BTreeSet<&DocumentId> {
+        self.vectors
+            .keys()
+            .chain(self.chunks.values().map(|c| &c.doc))
+            .collect()
+    }
+
+    /// Ranks documents by cosine similarity to `query`, best first. A
+    /// document scores as its best match among itself and its chunks.
+    pub fn search(&self, query: &[f32]) -> Vec<Hit> {
+        let mut scores: BTreeMap<&DocumentId, f32> = BTreeMap::new();
+        let whole = self.vectors.iter();
+        let parts = self.chunks.values().map(|c| (&c.doc, &c.vector));
+        for (id, v) in whole.chain(parts) {
+            let score = cosine(query, v);
+            let best = scores.entry(id).or_insert(score);
+            *best = best.max(score);
+        }
+        let mut hits: Vec<Hit> = scores
+            .into_iter()
+            .map(|(id, score)| Hit {
+                id: id.clone(),
+                score: f64::from(score),
+            })
+            .filter(|h| h.score > 0.0)
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        hits
+    }
+
+    /// The id of the chunk of `doc` most similar to `query`, if the
+    /// document was embedded in chunks.
+    pub fn best_chunk(&self, doc: &DocumentId, query: &[f32]) -> Option<&str> {
+        self.chunks
+            .iter()
+            .filter(|(_, c)| c.doc == *doc)
+            .map(|(id, c)| (id, cosine(query, &c.vector)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(id, _)| id.as_str())
+    }
+}
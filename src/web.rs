@@ -0,0 +1,548 @@
+//! The HTTP client for fetching pages from the web, shared by `ozy add
+//! --url` and clipping imported bookmarks, so that bulk imports stay
+//! polite: requests to the same host are spaced out, failures that may
+//! pass are retried with backoff, the client names itself with a
+//! configurable user agent and, when configured, it leaves alone what a
+//! site's `robots.txt` disallows and waits as long as it asks. Link
+//! checking and pulling feeds go through it too. Requests are async, so
+//! that waiting on one host holds up none of the others.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use reqwest::redirect::Policy;
+use reqwest::{Method, Response, Url};
+use serde::{Deserialize, Serialize};
+
+/// The longest `Retry-After` that is waited for; longer ones count as
+/// failures.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+/// The longest `Crawl-delay` honored; sites asking for more are fetched
+/// from this slowly.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60);
+/// Redirects followed before giving up.
+const MAX_REDIRECTS: usize = 10;
+
+/// The `[web]` config section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebConfig {
+    /// How the client names itself to servers.
+    pub user_agent: String,
+    /// Least time between two requests to the same host, in milliseconds.
+    pub host_delay_ms: u64,
+    /// Extra attempts after a timeout or a 429 or 5xx answer.
+    pub retries: u32,
+    /// Seconds to wait for a whole answer.
+    pub timeout_secs: u64,
+    /// Skip pages a site's `robots.txt` disallows, and honor its
+    /// `Crawl-delay`.
+    pub respect_robots: bool,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            user_agent: concat!("ozymandias/", env!("CARGO_PKG_VERSION")).to_string(),
+            host_delay_ms: 1000,
+            retries: 2,
+            timeout_secs: 30,
+            respect_robots: false,
+        }
+    }
+}
+
+/// Fetches pages as configured. Its requests are async, so that many can
+/// wait on the network at once; it can be shared between them.
+pub struct Client {
+    http: reqwest::Client,
+    /// The product token of the user agent, which `robots.txt` groups
+    /// are matched against.
+    product: String,
+    retries: u32,
+    delay: Duration,
+    hosts: HostLimiter,
+    /// The rules of each host, once fetched, when they are respected.
+    robots: Option<Mutex<HashMap<String, Robots>>>,
+}
+
+impl Client {
+    pub fn new(config: &WebConfig) -> Self {
+        // Redirects are followed here, so that robots rules apply to
+        // where they lead.
+        let http = reqwest::Client::builder()
+            .redirect(Policy::none())
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .user_agent(config.user_agent.as_str())
+            .build()
+            .expect("the HTTP client is configured correctly");
+        let product = config
+            .user_agent
+            .split(['/', ' '])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        Client {
+            http,
+            product,
+            retries: config.retries,
+            delay: Duration::from_millis(config.host_delay_ms),
+            hosts: HostLimiter::new(),
+            robots: config.respect_robots.then(|| Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// GETs `url`, following redirects, and fails unless the answer is a
+    /// success.
+    pub async fn get(&self, url: &str) -> Result<Response> {
+        let response = self.follow(url).await?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("failed to fetch {url}: the server answered {status}");
+        }
+        Ok(response)
+    }
+
+    /// GETs `url`, following redirects, and returns the last answer
+    /// whatever its status.
+    async fn follow(&self, url: &str) -> Result<Response> {
+        let mut current = url.to_string();
+        for _ in 0..=MAX_REDIRECTS {
+            let response = self.request(Method::GET, &current).await?;
+            match location(&response) {
+                Some(next) if response.status().is_redirection() => {
+                    // Fragments are not sent.
+                    let next = resolve(&current, next);
+                    current = next.split('#').next().unwrap_or_default().to_string();
+                }
+                _ => return Ok(response),
+            }
+        }
+        bail!("failed to fetch {url}: more than {MAX_REDIRECTS} redirects")
+    }
+
+    /// Makes one GET or HEAD request, without following redirects, and
+    /// returns the answer whatever its status.
+    pub async fn request(&self, method: Method, url: &str) -> Result<Response> {
+        let parsed = Url::parse(url).with_context(|| format!("invalid URL {url}"))?;
+        let host = parsed.host_str().unwrap_or_default().to_string();
+        let mut delay = self.delay;
+        // `robots.txt` itself may always be fetched.
+        if self.robots.is_some() && parsed.path() != "/robots.txt" {
+            let robots = self.robots(&parsed).await;
+            let path = match parsed.query() {
+                Some(query) => format!("{}?{query}", parsed.path()),
+                None => parsed.path().to_string(),
+            };
+            if !robots.allows(&path) {
+                bail!("robots.txt of {host} disallows fetching {url}");
+            }
+            delay = delay.max(robots.crawl_delay.unwrap_or_default());
+        }
+        self.call(method, url, &host, delay).await
+    }
+
+    /// Makes a request, retrying when it may succeed later.
+    async fn call(
+        &self,
+        method: Method,
+        url: &str,
+        host: &str,
+        delay: Duration,
+    ) -> Result<Response> {
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 0;
+        loop {
+            self.hosts.wait(host, delay).await;
+            let result = self.http.request(method.clone(), url).send().await;
+            let wait = match &result {
+                Ok(response) => {
+                    let status = response.status();
+                    (status.as_u16() == 429 || status.is_server_error())
+                        .then(|| retry_after(response).unwrap_or(backoff))
+                }
+                Err(e) if e.is_timeout() || e.is_connect() => Some(backoff),
+                Err(_) => None,
+            };
+            match wait {
+                Some(wait) if attempt < self.retries && wait <= MAX_RETRY_AFTER => {
+                    attempt += 1;
+                    tracing::debug!("retrying {url} in {}s", wait.as_secs_f32());
+                    tokio::time::sleep(wait).await;
+                    backoff *= 2;
+                }
+                _ => return result.with_context(|| format!("failed to fetch {url}")),
+            }
+        }
+    }
+
+    /// The `robots.txt` rules of the site `url` is on, fetched the first
+    /// time they are needed. A site without one allows everything; one
+    /// whose file cannot be fetched, because it fails or cannot be
+    /// reached, is left alone.
+    async fn robots(&self, url: &Url) -> Robots {
+        let robots = self.robots.as_ref().expect("robots.txt is respected");
+        let site = url.origin().ascii_serialization();
+        if let Some(known) = robots.lock().unwrap().get(&site) {
+            return known.clone();
+        }
+        let url = format!("{site}/robots.txt");
+        // Boxed, since fetching the rules goes through `request` again.
+        let rules = match Box::pin(self.follow(&url)).await {
+            Ok(response) if response.status().is_success() => match response.text().await {
+                Ok(text) => Robots::parse(&text, &self.product),
+                Err(e) => {
+                    tracing::debug!("cannot read {url}: {e}; fetching nothing from {site}");
+                    Robots::disallow_all()
+                }
+            },
+            Ok(response) if response.status().is_client_error() => Robots::default(),
+            Ok(response) => {
+                let status = response.status();
+                tracing::debug!("{url} answered {status}; fetching nothing from {site}");
+                Robots::disallow_all()
+            }
+            Err(e) => {
+                tracing::debug!("{e:#}; fetching nothing from {site}");
+                Robots::disallow_all()
+            }
+        };
+        robots.lock().unwrap().insert(site, rules.clone());
+        rules
+    }
+}
+
+/// Where a redirect leads, as its `Location` header says, which may be
+/// relative to the URL redirected from.
+pub fn location(response: &Response) -> Option<&str> {
+    response.headers().get("location")?.to_str().ok()
+}
+
+/// How long a 429 or 503 answer asks to wait, if it says so in seconds.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get("retry-after")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// Spaces out requests to the same host, across the requests in flight.
+pub struct HostLimiter {
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl HostLimiter {
+    pub fn new() -> Self {
+        HostLimiter {
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a request to `host` may be made, `delay` after the one
+    /// before.
+    pub async fn wait(&self, host: &str, delay: Duration) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = next.get(host).copied().unwrap_or(now).max(now);
+            next.insert(host.to_string(), slot + delay);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+impl Default for HostLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The rules of a `robots.txt` that apply to this client.
+#[derive(Debug, Clone, Default)]
+struct Robots {
+    /// Path patterns, each allowed or disallowed.
+    rules: Vec<(String, bool)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Rules that allow nothing, for sites whose rules cannot be read.
+    fn disallow_all() -> Self {
+        Robots {
+            rules: vec![("/".into(), false)],
+            crawl_delay: None,
+        }
+    }
+
+    /// The rules of the group naming `product`, else of the group for
+    /// every agent, `*`.
+    fn parse(text: &str, product: &str) -> Self {
+        let mut specific = Robots::default();
+        let mut general = Robots::default();
+        let mut found_specific = false;
+        // The agents of the group being read, and whether its rules have
+        // started, which ends its list of agents.
+        let mut agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_rules = true;
+            let is_specific = !product.is_empty() && agents.iter().any(|a| a == product);
+            found_specific |= is_specific;
+            let target = if is_specific {
+                &mut specific
+            } else if agents.iter().any(|a| a == "*") {
+                &mut general
+            } else {
+                continue;
+            };
+            match key.as_str() {
+                "allow" if !value.is_empty() => target.rules.push((value.to_string(), true)),
+                "disallow" if !value.is_empty() => target.rules.push((value.to_string(), false)),
+                // Whatever the file asks, waits are bounded.
+                "crawl-delay" => {
+                    target.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                        .map(|d| d.min(MAX_CRAWL_DELAY))
+                }
+                _ => {}
+            }
+        }
+        if found_specific {
+            specific
+        } else {
+            general
+        }
+    }
+
+    /// Whether `path` may be fetched: the longest matching rule decides,
+    /// and allowing wins a tie.
+    fn allows(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|(pattern, _)| matches(pattern, path))
+            .max_by_key(|(pattern, allow)| (pattern.len(), *allow))
+            .is_none_or(|(_, allow)| *allow)
+    }
+}
+
+/// Whether a `robots.txt` path pattern matches the start of `path`; `*`
+/// stands for any characters and a final `$` for the end of the path.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        if last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Whether `reference` starts with a scheme, such as `https:` or
+/// `mailto:`, and so is absolute already.
+fn has_scheme(reference: &str) -> bool {
+    let Some((scheme, _)) = reference.split_once(':') else {
+        return false;
+    };
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+}
+
+/// `reference` made absolute against the URL `base`.
+pub fn resolve(base: &str, reference: &str) -> String {
+    let reference = reference.trim();
+    if has_scheme(reference) {
+        return reference.to_string();
+    }
+    let (scheme, rest) = base.split_once("://").unwrap_or(("https", base));
+    if let Some(authority_and_path) = reference.strip_prefix("//") {
+        return format!("{scheme}://{authority_and_path}");
+    }
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let path = rest[authority_end..]
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default();
+    if reference.starts_with('#') || reference.starts_with('?') {
+        // Replaces the fragment, or the query and the fragment.
+        let cut: &[char] = if reference.starts_with('?') {
+            &['?', '#']
+        } else {
+            &['#']
+        };
+        let page = rest.split(cut).next().unwrap_or(rest);
+        return format!("{scheme}://{page}{reference}");
+    }
+    let joined = if reference.starts_with('/') {
+        reference.to_string()
+    } else {
+        let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{dir}/{reference}")
+    };
+    // Splits off the query so that dots in it are left alone.
+    let (joined_path, suffix) = match joined.find(['?', '#']) {
+        Some(at) => joined.split_at(at),
+        None => (joined.as_str(), ""),
+    };
+    let mut segments: Vec<&str> = Vec::new();
+    let parts: Vec<&str> = joined_path.split('/').skip(1).collect();
+    for (i, segment) in parts.iter().enumerate() {
+        match *segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            s => segments.push(s),
+        }
+        // A path ending in `.` or `..` names a directory.
+        if i + 1 == parts.len() && matches!(*segment, "." | "..") {
+            segments.push("");
+        }
+    }
+    format!("{scheme}://{authority}/{}{suffix}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "\
+# Rules for everyone
+User-agent: *
+Disallow: /private/
+Allow: /private/public.html
+Crawl-delay: 2
+
+User-agent: ozymandias
+User-agent: other
+Disallow: /drafts  # not yet
+Crawl-delay: 3600
+";
+
+    #[test]
+    fn the_group_naming_the_product_is_used_over_the_general_one() {
+        let robots = Robots::parse(ROBOTS, "ozymandias");
+        assert!(!robots.allows("/drafts/1"));
+        assert!(robots.allows("/private/"));
+        assert_eq!(robots.crawl_delay, Some(MAX_CRAWL_DELAY));
+
+        let robots = Robots::parse(ROBOTS, "someone");
+        assert!(robots.allows("/drafts/1"));
+        assert!(!robots.allows("/private/notes.html"));
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn the_longest_matching_rule_decides() {
+        let robots = Robots::parse(ROBOTS, "");
+        assert!(robots.allows("/private/public.html"));
+        assert!(!robots.allows("/private/other.html"));
+        assert!(robots.allows("/"));
+    }
+
+    #[test]
+    fn an_empty_disallow_allows_everything() {
+        let robots = Robots::parse("User-agent: *\nDisallow:\n", "ozymandias");
+        assert!(robots.allows("/anything"));
+        assert!(Robots::default().allows("/anything"));
+        assert!(!Robots::disallow_all().allows("/anything"));
+    }
+
+    #[test]
+    fn rules_of_groups_for_other_agents_are_ignored() {
+        let robots = Robots::parse("User-agent: other\nDisallow: /\n", "ozymandias");
+        assert!(robots.allows("/"));
+        assert_eq!(robots.crawl_delay, None);
+    }
+
+    #[test]
+    fn patterns_match_path_prefixes_with_wildcards_and_anchors() {
+        assert!(matches("/a", "/a/b"));
+        assert!(!matches("/a", "/b/a"));
+        assert!(matches("/*.pdf", "/files/report.pdf"));
+        assert!(matches("/*.pdf", "/report.pdf?download"));
+        assert!(matches("/*.pdf$", "/files/report.pdf"));
+        assert!(!matches("/*.pdf$", "/report.pdf?download"));
+        assert!(matches("/a$", "/a"));
+        assert!(!matches("/a$", "/ab"));
+        assert!(matches("/a*b*c", "/a-b-c-d"));
+        assert!(!matches("/a*c*b", "/a-b-c"));
+    }
+
+    #[test]
+    fn references_resolve_against_a_base() {
+        let base = "http://a/b/c/d;p?q";
+        for (reference, resolved) in [
+            ("g", "http://a/b/c/g"),
+            ("./g", "http://a/b/c/g"),
+            ("g/", "http://a/b/c/g/"),
+            ("/g", "http://a/g"),
+            ("//g", "http://g"),
+            ("?y", "http://a/b/c/d;p?y"),
+            ("g?y", "http://a/b/c/g?y"),
+            ("#s", "http://a/b/c/d;p?q#s"),
+            ("g?y/./x", "http://a/b/c/g?y/./x"),
+            (".", "http://a/b/c/"),
+            ("..", "http://a/b/"),
+            ("../g", "http://a/b/g"),
+            ("../..", "http://a/"),
+            ("../../../g", "http://a/g"),
+            (" g ", "http://a/b/c/g"),
+        ] {
+            assert_eq!(resolve(base, reference), resolved, "{reference}");
+        }
+    }
+
+    #[test]
+    fn absolute_references_are_kept() {
+        let base = "https://example.com/notes/";
+        assert_eq!(resolve(base, "http://other.org/x"), "http://other.org/x");
+        assert_eq!(
+            resolve(base, "mailto:me@example.com"),
+            "mailto:me@example.com"
+        );
+        assert_eq!(
+            resolve("https://example.com", "page"),
+            "https://example.com/page"
+        );
+    }
+}